@@ -0,0 +1,69 @@
+// Benchmarks of the commands that are parsed and formatted the most: the infos
+// an engine sends during a search, the position and go commands a GUI sends
+// before every move, with the position growing a move each time. Run them with
+// cargo bench
+// and compare with a run on the base branch to find regressions.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::str::FromStr;
+use uzi::{EngCmd, GuiCmd};
+
+const INFO: &str = "info depth 24 seldepth 33 multipv 1 score cp 31 nodes 3015876 nps 1508685 \
+                    hashfull 966 tbhits 0 time 1999 pv e2e4 e7e5 g1f3 b8c6 f1b5 g8f6 e1g1 f6e4 \
+                    f1e1 e4d6 f3e5 f8e7 b5f1 c6e5 e1e5 e8g8";
+
+// The numbers of an info, as sent by an engine between pvs, e.g. with nps.
+const NUMBERS: &str = "info depth 24 seldepth 33 multipv 1 score cp -31 nodes 3015876 \
+                       nps 1508685 hashfull 966 tbhits 0 time 1999";
+
+const GO: &str = "go wtime 300000 btime 298731 winc 2000 binc 2000 movestogo 34";
+
+// A position after 200 moves, which a GUI sends before every move of a long
+// game.
+fn long_position() -> String {
+    let mut line = "position startpos moves".to_string();
+    for _ in 0..50 {
+        line.push_str(" g1f3 g8f6 f3g1 f6g8");
+    }
+    line
+}
+
+fn info(c: &mut Criterion) {
+    c.bench_function("parse info", |b| {
+        b.iter(|| EngCmd::from_str(black_box(INFO)).unwrap())
+    });
+    c.bench_function("parse info numbers", |b| {
+        b.iter(|| EngCmd::from_str(black_box(NUMBERS)).unwrap())
+    });
+    let info = EngCmd::from_str(INFO).unwrap();
+    let mut buf = String::new();
+    c.bench_function("format info", |b| {
+        b.iter(|| {
+            use std::fmt::Write;
+            buf.clear();
+            write!(buf, "{}", black_box(&info)).unwrap();
+        })
+    });
+}
+
+fn position(c: &mut Criterion) {
+    let line = long_position();
+    c.bench_function("parse position with 200 moves", |b| {
+        b.iter(|| GuiCmd::from_str(black_box(&line)).unwrap())
+    });
+    let pos = GuiCmd::from_str(&line).unwrap();
+    c.bench_function("format position with 200 moves", |b| {
+        b.iter(|| black_box(&pos).to_string())
+    });
+}
+
+fn go(c: &mut Criterion) {
+    c.bench_function("parse go", |b| {
+        b.iter(|| GuiCmd::from_str(black_box(GO)).unwrap())
+    });
+    let go = GuiCmd::from_str(GO).unwrap();
+    c.bench_function("format go", |b| b.iter(|| black_box(&go).to_string()));
+}
+
+criterion_group!(benches, info, position, go);
+criterion_main!(benches);
@@ -0,0 +1,141 @@
+// An engine that plays a random legal move, or the null move if there is none.
+// It is built only on the public API of the library, so it shows how to write
+// an engine with the runner. It knows the rules from Board, but nothing about
+// playing well. Run it with
+// cargo run --example random_mover
+// and point a GUI at the binary.
+
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uzi::{run, Board, EngineMeta, GamePos, Go, Info, InfoSender, Pm, Pos, StopFlag, UciEngine};
+
+#[derive(Clone, Debug)]
+struct RandomMover {
+    // The state of a xorshift generator, which is never 0.
+    seed: u64,
+}
+
+impl RandomMover {
+    fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos());
+        Self::with_seed(u64::from(nanos))
+    }
+
+    // Creates an engine that plays the same moves every time.
+    fn with_seed(seed: u64) -> Self {
+        Self { seed: seed | 1 }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+}
+
+impl UciEngine for RandomMover {
+    fn go(
+        &mut self,
+        pos: &GamePos,
+        go: &Go,
+        stop: &StopFlag,
+        info: &InfoSender,
+    ) -> (Pm, Option<Pm>) {
+        let moves = Board::from_pos(pos.pos()).map_or_else(Vec::new, |board| board.legal_moves());
+        info.send(Info::from_string(&format!("{} legal moves", moves.len())));
+
+        // An infinite search, or a search while pondering, must not end before
+        // the GUI says so.
+        while !stop.is_stopped() && (go.is_infinite() || stop.is_pondering()) {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        if moves.is_empty() {
+            return (Pm::Null, None);
+        }
+        let i = self.next_random() % moves.len() as u64;
+        (moves[i as usize], None)
+    }
+
+    fn on_perft(&mut self, pos: &Pos, depth: u16) -> Option<Vec<(Pm, u64)>> {
+        let board = Board::from_pos(pos)?;
+        let counts = board
+            .legal_moves()
+            .into_iter()
+            .map(|pm| {
+                let mut next = board.clone();
+                next.play(pm);
+                (pm, next.perft(depth.saturating_sub(1)))
+            })
+            .collect();
+        Some(counts)
+    }
+}
+
+fn main() {
+    let meta = EngineMeta::new("RandomMover", env!("CARGO_PKG_VERSION"), &["uzi"]);
+    if let Err(err) = run(meta, RandomMover::new()) {
+        eprintln!("random_mover: {:?}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{self, AsyncWriteExt};
+    use tokio::sync::mpsc;
+    use uzi::{Engine, Runner, StreamTransport, UciOut};
+
+    #[tokio::test]
+    async fn client_plays_random_mover() {
+        let (client, server) = io::duplex(4096);
+        let (server_read, server_write) = io::split(server);
+        let runner = Runner::new(
+            EngineMeta::new("RandomMover", "", &["uzi"]),
+            RandomMover::with_seed(7),
+        );
+        let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
+        tokio::spawn(async move {
+            let mut server_write = server_write;
+            while let Some(bytes) = rx.recv().await {
+                server_write.write_all(&bytes).await.unwrap();
+            }
+        });
+        tokio::spawn(runner.run_with_async(server_read, UciOut::new(ChannelWriter(tx))));
+
+        let (client_read, client_write) = io::split(client);
+        let mut eng = Engine::new(StreamTransport::new(client_read, client_write));
+        let timeout = Duration::from_secs(5);
+        assert_eq!(eng.uci(timeout).await, Ok(()));
+        assert_eq!(eng.name(), Some("RandomMover"));
+
+        let mut pos = Pos::new();
+        for _ in 0..4 {
+            assert_eq!(eng.position(&pos).await, Ok(()));
+            let mut go = Go::new();
+            go.set_depth(1);
+            let best = eng.go(&go).await.unwrap().wait().await.unwrap();
+            let board = Board::from_pos(&pos).unwrap();
+            assert!(board.legal_moves().contains(&best.0));
+            pos.add_move(best.0);
+        }
+    }
+
+    // Passes the output of the runner's threads on to the async pipe.
+    struct ChannelWriter(mpsc::UnboundedSender<Vec<u8>>);
+
+    impl std::io::Write for ChannelWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let _ = self.0.send(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
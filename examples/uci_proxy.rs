@@ -0,0 +1,34 @@
+// A proxy that logs the lines a GUI and an engine exchange to stderr. Run it
+// with
+// cargo run --example uci_proxy -- <engine>
+// and point a GUI at a script that runs it, e.g. to find out why the GUI and
+// the engine don't get along.
+
+use uzi::{LineAction, UciProxy};
+
+#[tokio::main]
+async fn main() {
+    let Some(engine) = std::env::args().nth(1) else {
+        eprintln!("usage: uci_proxy <engine>");
+        std::process::exit(2);
+    };
+    let result = match UciProxy::spawn(engine) {
+        Ok(mut proxy) => {
+            proxy
+                .add_gui_hook(|line| {
+                    eprintln!("> {}", line);
+                    LineAction::Forward
+                })
+                .add_engine_hook(|line| {
+                    eprintln!("< {}", line);
+                    LineAction::Forward
+                });
+            proxy.run().await
+        }
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        eprintln!("uci_proxy: {:?}", err);
+        std::process::exit(1);
+    }
+}
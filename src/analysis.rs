@@ -0,0 +1,840 @@
+// This module contains AnalysisSession, which analyzes positions on an engine
+// and reports whole lines, i.e. the principal variations with their scores,
+// rather than the raw info updates of the search.
+
+use crate::cache::AnalysisCache;
+use crate::client::Engine;
+use crate::engcmd::{Info, Score};
+use crate::err::UziErr;
+use crate::guicmd::{Go, Pos};
+use crate::opt::{HasOpt, SetOpt};
+use crate::pm::Pm;
+use crate::search::SearchHandle;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+// How often infinite analysis delivers the updates of each line, unless the
+// engine has its own info throttle.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+// Analyzes a position on an engine. The position is set once, and each call to
+// analyze searches it with the given limits.
+#[derive(Debug)]
+pub struct AnalysisSession<'a> {
+    eng: &'a mut Engine,
+    pos: Pos,
+    cache: Option<AnalysisCache>,
+}
+
+// A line found by the engine, as of its latest update.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PvLine {
+    // The rank of the line, starting at 1, which is the best line.
+    pub rank: u64,
+    pub depth: Option<u16>,
+    pub sel_depth: Option<u16>,
+    pub score: Option<Score>,
+    pub moves: Vec<Pm>,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+// The result of an analysis.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AnalysisResult {
+    pub best: Pm,
+    pub ponder: Option<Pm>,
+    // The latest update of every line, best first.
+    pub lines: Vec<PvLine>,
+}
+
+// An analysis that runs until it is stopped, like on an analysis board. The
+// position can be changed while it runs.
+#[derive(Debug)]
+pub struct InfiniteAnalysis<'a> {
+    analysis: Analysis<'a>,
+    pos: Pos,
+}
+
+// The conditions that end an analysis started with analyze_until. The analysis
+// stops at the first one that fires.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StopWhen {
+    depth: Option<u16>,
+    // The number of iterations, and the margin in centipawns.
+    stable: Option<(usize, i32)>,
+    mate: bool,
+    time: Option<Duration>,
+    nodes: Option<u64>,
+}
+
+// Why an analysis started with analyze_until ended.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum StopReason {
+    // The engine finished the search on its own, within the limits of go.
+    Finished,
+    Depth,
+    Stable,
+    Mate,
+    Time,
+    Nodes,
+}
+
+// The result of analyze_until: the last complete info of the best line, if the
+// engine sent any, and why the analysis ended.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EarlyStop {
+    pub info: Option<Info>,
+    pub reason: StopReason,
+}
+
+// A running analysis. The line updates are read with next_update, and the
+// result with wait, which can be called at any time.
+#[derive(Debug)]
+pub struct Analysis<'a> {
+    search: SearchHandle<'a>,
+    // The latest update of every line, best first.
+    lines: Vec<PvLine>,
+}
+
+impl<'a> AnalysisSession<'a> {
+    // Creates a session that analyzes the start position until set_position is
+    // called.
+    pub fn new(eng: &'a mut Engine) -> Self {
+        Self {
+            eng,
+            pos: Pos::new(),
+            cache: None,
+        }
+    }
+
+    pub fn set_position(&mut self, pos: Pos) -> &mut Self {
+        self.pos = pos;
+        self
+    }
+
+    pub fn position(&self) -> &Pos {
+        &self.pos
+    }
+
+    // Sets the cache used by analyze_cached.
+    pub fn set_cache(&mut self, cache: AnalysisCache) -> &mut Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    // Analyzes the position with the given limits until the engine is done,
+    // unless the cache already has the result of the same analysis on an
+    // engine with the same name. New results are added to the cache. Without
+    // a cache, this is the same as analyze followed by wait.
+    pub async fn analyze_cached(&mut self, limits: &Go) -> Result<AnalysisResult, UziErr> {
+        let name = self.eng.name().map(String::from);
+        if let Some(result) = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.get(name.as_deref(), &self.pos, limits))
+        {
+            return Ok(result);
+        }
+        let result = self.analyze(limits).await?.wait().await?;
+        if let Some(ref cache) = self.cache {
+            cache.insert(name.as_deref(), &self.pos, limits, result.clone())?;
+        }
+        Ok(result)
+    }
+
+    // Sends the position and starts searching it with the given limits.
+    pub async fn analyze(&mut self, limits: &Go) -> Result<Analysis<'_>, UziErr> {
+        self.eng.position(&self.pos).await?;
+        let search = self.eng.go(limits).await?;
+        Ok(Analysis {
+            search,
+            lines: Vec::new(),
+        })
+    }
+
+    // Starts analyzing the position with go infinite. The line updates are
+    // throttled to one per UPDATE_INTERVAL for each line, unless the engine
+    // has an info throttle of its own.
+    pub async fn start_infinite_analysis(&mut self) -> Result<InfiniteAnalysis<'_>, UziErr> {
+        let has_throttle = self.eng.info_interval().is_some();
+        let pos = self.pos.clone();
+        let mut analysis = self.analyze(&infinite()).await?;
+        if !has_throttle {
+            analysis.search.set_throttle(UPDATE_INTERVAL);
+        }
+        Ok(InfiniteAnalysis { analysis, pos })
+    }
+
+    // Searches the position for the best k lines, and returns the lines of the
+    // deepest iteration the engine completed, best first. This sets MultiPV to
+    // k, which must be within the range declared by the engine. An engine that
+    // does not declare MultiPV can only search for one line.
+    pub async fn analyze_multipv(&mut self, k: u64, limits: &Go) -> Result<Vec<PvLine>, UziErr> {
+        let multi_pv = self.eng.options().iter().find_map(|opt| match opt {
+            HasOpt::MultiPv(spin) => Some(*spin),
+            _ => None,
+        });
+        match multi_pv {
+            Some(spin) if k < spin.min || k > spin.max => return Err(UziErr::BadOptValue),
+            Some(_) => self.eng.set_opt(SetOpt::MultiPv(k)).await?,
+            None if k != 1 => return Err(UziErr::UnknownOpt),
+            None => (),
+        }
+
+        // The lines of every depth. The engine may be stopped in the middle of
+        // an iteration, which then has fewer lines than the previous one.
+        let mut by_depth: BTreeMap<u16, Vec<PvLine>> = BTreeMap::new();
+        let mut analysis = self.analyze(limits).await?;
+        while let Some(line) = analysis.next_update().await? {
+            let lines = by_depth.entry(line.depth.unwrap_or(0)).or_default();
+            lines.retain(|old| old.rank != line.rank);
+            lines.push(line);
+        }
+        analysis.wait().await?;
+
+        let most = by_depth.values().map(Vec::len).max().unwrap_or(0);
+        let mut lines = by_depth
+            .into_values()
+            .rev()
+            .find(|lines| lines.len() == most)
+            .unwrap_or_default();
+        lines.sort_by_key(|line| line.rank);
+        lines.truncate(k as usize);
+        Ok(lines)
+    }
+
+    // Searches the position until one of the conditions fires, then stops the
+    // engine. The search runs until stop unless the limits end it. Returns the
+    // last complete info of the best line, i.e. one with a pv and a score that
+    // is not a bound, which the engine sends when it finishes an iteration.
+    pub async fn analyze_until(
+        &mut self,
+        limits: &Go,
+        stop_when: &StopWhen,
+    ) -> Result<EarlyStop, UziErr> {
+        let mut go = limits.clone();
+        if !go.has_limit() {
+            go.set_infinite();
+        }
+        self.eng.position(&self.pos).await?;
+        let deadline = stop_when.time.map(|time| Instant::now() + time);
+        let mut search = self.eng.go(&go).await?;
+
+        let mut last: Option<Info> = None;
+        // The score of the best line after each iteration, latest last.
+        let mut scores: Vec<(u16, Score)> = Vec::new();
+        let reason = loop {
+            let next = match deadline {
+                Some(deadline) => {
+                    match time::timeout_at(deadline.into(), search.next_info()).await {
+                        Ok(next) => next?,
+                        Err(_) => break StopReason::Time,
+                    }
+                }
+                None => search.next_info().await?,
+            };
+            let Some(info) = next else {
+                break StopReason::Finished;
+            };
+            let budget = stop_when.nodes.zip(info.nodes());
+            if budget.is_some_and(|(budget, nodes)| nodes >= budget) {
+                break StopReason::Nodes;
+            }
+            let Some(score) = complete_score(&info) else {
+                continue;
+            };
+            let depth = info.depth().unwrap_or(0);
+            match scores.last_mut() {
+                Some(last) if last.0 == depth => last.1 = score,
+                _ => scores.push((depth, score)),
+            }
+            last = Some(info);
+            if let Some(reason) = stop_when.check(depth, &scores) {
+                break reason;
+            }
+        };
+        search.stop().await?;
+        search.wait().await?;
+        Ok(EarlyStop { info: last, reason })
+    }
+
+    // Searches the position for the given number of nodes, which makes the
+    // result independent of the hardware. The engine is sent go nodes, and is
+    // stopped once it reports that many nodes, for engines that ignore it.
+    pub async fn analyze_nodes(&mut self, nodes: u64) -> Result<EarlyStop, UziErr> {
+        let mut go = Go::new();
+        go.set_nodes(nodes);
+        let mut stop_when = StopWhen::new();
+        stop_when.set_nodes(nodes);
+        self.analyze_until(&go, &stop_when).await
+    }
+
+    // Analyzes the position again, e.g. deeper, starting from the result of an
+    // earlier analysis of it. UCI has no way to hint the order of the moves,
+    // so when the earlier result has at least two lines, the search is
+    // restricted to their first moves with searchmoves, unless the limits
+    // already restrict it. The new lines are merged with the earlier ones, see
+    // AnalysisResult::merge.
+    pub async fn analyze_from(
+        &mut self,
+        prior: &AnalysisResult,
+        limits: &Go,
+    ) -> Result<AnalysisResult, UziErr> {
+        let candidates = prior.candidates();
+        let mut go = limits.clone();
+        if go.search_moves().is_none() && candidates.len() > 1 {
+            for pm in candidates {
+                go.add_search_move(pm);
+            }
+        }
+        let result = self.analyze(&go).await?.wait().await?;
+        Ok(result.merge(prior))
+    }
+}
+
+impl AnalysisResult {
+    // Returns the first move of every line, best first.
+    pub fn candidates(&self) -> Vec<Pm> {
+        self.lines
+            .iter()
+            .filter_map(|line| line.moves.first().copied())
+            .collect()
+    }
+
+    // Merges the lines of an earlier analysis of the same position into this
+    // one. Lines are matched by their first move, and the deeper one is kept,
+    // or this one if they are as deep. The lines of this analysis come first,
+    // followed by the earlier lines for the moves it didn't search, and all of
+    // them are ranked again in that order.
+    pub fn merge(mut self, prior: &AnalysisResult) -> AnalysisResult {
+        for old in &prior.lines {
+            let first = old.moves.first();
+            match self
+                .lines
+                .iter_mut()
+                .find(|line| line.moves.first() == first)
+            {
+                Some(line) if old.depth > line.depth => *line = old.clone(),
+                Some(_) => (),
+                None => self.lines.push(old.clone()),
+            }
+        }
+        for (i, line) in self.lines.iter_mut().enumerate() {
+            line.rank = i as u64 + 1;
+        }
+        self
+    }
+}
+
+impl InfiniteAnalysis<'_> {
+    // Returns the next update of a line. This only returns None if the engine
+    // ends the search on its own, e.g. in a position without legal moves.
+    pub async fn next_update(&mut self) -> Result<Option<PvLine>, UziErr> {
+        self.analysis.next_update().await
+    }
+
+    // Returns the latest update of every line of the current position, best
+    // first.
+    pub fn lines(&self) -> &[PvLine] {
+        self.analysis.lines()
+    }
+
+    pub fn position(&self) -> &Pos {
+        &self.pos
+    }
+
+    // Switches the analysis to another position: the engine is stopped, and
+    // starts analyzing the new position once it sent its bestmove. The lines
+    // of the previous position are dropped.
+    pub async fn set_position(&mut self, pos: Pos) -> Result<(), UziErr> {
+        self.analysis.search.restart(&pos, &infinite()).await?;
+        self.analysis.lines.clear();
+        self.pos = pos;
+        Ok(())
+    }
+
+    // Stops the analysis and returns its result.
+    pub async fn stop(mut self) -> Result<AnalysisResult, UziErr> {
+        self.analysis.stop().await?;
+        self.analysis.wait().await
+    }
+}
+
+fn infinite() -> Go {
+    let mut go = Go::new();
+    go.set_infinite();
+    go
+}
+
+// Returns the score of an info that completes an iteration of the best line.
+fn complete_score(info: &Info) -> Option<Score> {
+    let score = info.score()?;
+    let is_best = info.multi_pv().unwrap_or(1) == 1;
+    (is_best && info.pv().is_some() && score.bound().is_none()).then_some(score)
+}
+
+impl StopWhen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Stops once the engine completes an iteration at this depth.
+    pub fn set_depth(&mut self, depth: u16) -> &mut Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    // Stops once the score of the best line stayed within margin centipawns
+    // over the last iterations.
+    pub fn set_stable(&mut self, iterations: usize, margin: i32) -> &mut Self {
+        self.stable = Some((iterations, margin));
+        self
+    }
+
+    // Stops once the engine finds a mate, for either side.
+    pub fn set_mate(&mut self) -> &mut Self {
+        self.mate = true;
+        self
+    }
+
+    // Stops once the time passes.
+    pub fn set_time(&mut self, time: Duration) -> &mut Self {
+        self.time = Some(time);
+        self
+    }
+
+    // Stops once the engine reports that it searched this many nodes.
+    pub fn set_nodes(&mut self, nodes: u64) -> &mut Self {
+        self.nodes = Some(nodes);
+        self
+    }
+
+    // Returns the condition that fired after an iteration, given the scores of
+    // the iterations so far.
+    fn check(&self, depth: u16, scores: &[(u16, Score)]) -> Option<StopReason> {
+        let score = scores.last()?.1;
+        if self.mate && score.mate().is_some() {
+            return Some(StopReason::Mate);
+        }
+        if self.depth.is_some_and(|target| depth >= target) {
+            return Some(StopReason::Depth);
+        }
+        let (iterations, margin) = self.stable?;
+        let recent = scores.get(scores.len().checked_sub(iterations.max(1))?..)?;
+        let cps: Option<Vec<i32>> = recent.iter().map(|(_, score)| score.cp()).collect();
+        let cps = cps?;
+        let (min, max) = (cps.iter().min()?, cps.iter().max()?);
+        (max - min <= margin).then_some(StopReason::Stable)
+    }
+}
+
+impl Analysis<'_> {
+    // Returns the next update of a line, or None once the search is done.
+    // Updates without a pv, e.g. the current move, are skipped.
+    pub async fn next_update(&mut self) -> Result<Option<PvLine>, UziErr> {
+        while let Some(info) = self.search.next_info().await? {
+            if let Some(line) = PvLine::from_info(&info) {
+                self.update(line.clone());
+                return Ok(Some(line));
+            }
+        }
+        Ok(None)
+    }
+
+    // Returns the latest update of every line, best first.
+    pub fn lines(&self) -> &[PvLine] {
+        &self.lines
+    }
+
+    // Tells the engine to stop searching. The result is still read with wait.
+    pub async fn stop(&mut self) -> Result<(), UziErr> {
+        self.search.stop().await
+    }
+
+    // Waits for the search to finish, keeping the remaining line updates, and
+    // returns the result.
+    pub async fn wait(mut self) -> Result<AnalysisResult, UziErr> {
+        while self.next_update().await?.is_some() {}
+        let (best, ponder) = self.search.wait().await?;
+        Ok(AnalysisResult {
+            best,
+            ponder,
+            lines: self.lines,
+        })
+    }
+
+    fn update(&mut self, line: PvLine) {
+        match self.lines.binary_search_by_key(&line.rank, |old| old.rank) {
+            Ok(i) => self.lines[i] = line,
+            Err(i) => self.lines.insert(i, line),
+        }
+    }
+}
+
+impl PvLine {
+    // Returns the line in the info, if it has a pv. Engines that do not use
+    // multipv send only the best line.
+    pub fn from_info(info: &Info) -> Option<Self> {
+        let pv = info.pv()?;
+        Some(Self {
+            rank: info.multi_pv().unwrap_or(1),
+            depth: info.depth(),
+            sel_depth: info.sel_depth(),
+            score: info.score(),
+            moves: pv.to_vec(),
+            nodes: info.nodes(),
+            time: info.time(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::fake_engine;
+    use std::str::FromStr;
+
+    // An engine that searches with two lines, and sends an update without a pv
+    // in between.
+    const MULTI_PV_ENGINE: &str = r#"
+while read -r line; do
+    case "$line" in
+        uci) echo "uciok";;
+        isready) echo "readyok";;
+        go*)
+            echo "info depth 1 multipv 1 score cp 10 pv e2e4"
+            echo "info depth 1 multipv 2 score cp 5 pv d2d4"
+            echo "info depth 2 currmove g1f3 currmovenumber 3"
+            echo "info depth 2 multipv 2 score cp 15 pv g1f3 g8f6"
+            echo "bestmove g1f3 ponder g8f6";;
+        quit) exit 0;;
+    esac
+done
+"#;
+
+    // An engine with MultiPV that is stopped during its second iteration.
+    const STOPPED_ENGINE: &str = r#"
+while read -r line; do
+    case "$line" in
+        uci)
+            echo "option name MultiPV type spin default 1 min 1 max 3"
+            echo "uciok";;
+        isready) echo "readyok";;
+        "setoption name MultiPV value "*) multipv=${line##* };;
+        go*)
+            echo "info depth 1 multipv 1 score cp 10 pv e2e4"
+            if [ "$multipv" = 2 ]; then
+                echo "info depth 1 multipv 2 score cp 5 pv d2d4"
+            fi
+            echo "info depth 2 multipv 1 score cp 12 pv e2e4 e7e5"
+            echo "bestmove e2e4 ponder e7e5";;
+        quit) exit 0;;
+    esac
+done
+"#;
+
+    // An engine that runs five iterations, one of them with a fail high, and
+    // finds a mate in the last one. It waits for stop unless it searches to a
+    // depth.
+    const ITERATING_ENGINE: &str = r#"
+iterate() {
+    echo "info depth 1 score cp 10 pv e2e4"
+    echo "info depth 2 score cp 60 lowerbound pv d2d4"
+    echo "info depth 2 score cp 40 pv d2d4"
+    echo "info depth 3 score cp 12 pv e2e4"
+    echo "info depth 4 score cp 13 pv e2e4 e7e5"
+    echo "info depth 5 score mate 3 pv e2e4 e7e5"
+}
+while read -r line; do
+    case "$line" in
+        isready) echo "readyok";;
+        "go depth"*) iterate; echo "bestmove e2e4";;
+        go*) iterate;;
+        stop) echo "bestmove e2e4";;
+    esac
+done
+"#;
+
+    fn pm(s: &str) -> Pm {
+        Pm::from_str(s).unwrap()
+    }
+
+    #[tokio::test]
+    async fn analysis_reports_lines() {
+        let mut eng = Engine::new(fake_engine(MULTI_PV_ENGINE));
+        eng.uci(Duration::from_secs(5)).await.unwrap();
+        let mut session = AnalysisSession::new(&mut eng);
+        let mut pos = Pos::new();
+        pos.add_move(pm("e2e4"));
+        session.set_position(pos);
+
+        let mut go = Go::new();
+        go.set_depth(2);
+        let mut analysis = session.analyze(&go).await.unwrap();
+        let first = analysis.next_update().await.unwrap().unwrap();
+        assert_eq!(first.rank, 1);
+        assert_eq!(first.depth, Some(1));
+        assert_eq!(first.score.and_then(|score| score.cp()), Some(10));
+        assert_eq!(first.moves, vec![pm("e2e4")]);
+        assert_eq!(analysis.lines(), std::slice::from_ref(&first));
+
+        let result = analysis.wait().await.unwrap();
+        assert_eq!(result.best, pm("g1f3"));
+        assert_eq!(result.ponder, Some(pm("g8f6")));
+        let pvs: Vec<(u64, Option<u16>, Vec<Pm>)> = result
+            .lines
+            .into_iter()
+            .map(|line| (line.rank, line.depth, line.moves))
+            .collect();
+        assert_eq!(
+            pvs,
+            vec![
+                (1, Some(1), vec![pm("e2e4")]),
+                (2, Some(2), vec![pm("g1f3"), pm("g8f6")]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn analysis_multipv_returns_complete_iteration() {
+        let mut eng = Engine::new(fake_engine(STOPPED_ENGINE));
+        eng.uci(Duration::from_secs(5)).await.unwrap();
+        let mut session = AnalysisSession::new(&mut eng);
+        let mut go = Go::new();
+        go.set_depth(2);
+
+        let lines = session.analyze_multipv(2, &go).await.unwrap();
+        let ranks: Vec<(u64, Option<u16>, Vec<Pm>)> = lines
+            .into_iter()
+            .map(|line| (line.rank, line.depth, line.moves))
+            .collect();
+        assert_eq!(
+            ranks,
+            vec![
+                (1, Some(1), vec![pm("e2e4")]),
+                (2, Some(1), vec![pm("d2d4")])
+            ]
+        );
+
+        assert_eq!(
+            session.analyze_multipv(4, &go).await,
+            Err(UziErr::BadOptValue)
+        );
+        let lines = session.analyze_multipv(1, &go).await.unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].depth, Some(2));
+    }
+
+    #[tokio::test]
+    async fn analysis_multipv_needs_declared_option() {
+        let mut eng = Engine::new(fake_engine(crate::testutil::FAKE_ENGINE));
+        eng.uci(Duration::from_secs(5)).await.unwrap();
+        let mut session = AnalysisSession::new(&mut eng);
+        let mut go = Go::new();
+        go.set_depth(2);
+        assert_eq!(
+            session.analyze_multipv(2, &go).await,
+            Err(UziErr::UnknownOpt)
+        );
+        let lines = session.analyze_multipv(1, &go).await.unwrap();
+        assert_eq!(lines[0].moves, vec![pm("e2e4"), pm("e7e5")]);
+    }
+
+    // Returns why the analysis ended, and the depth of its last info.
+    async fn until(
+        session: &mut AnalysisSession<'_>,
+        stop_when: StopWhen,
+        go: Go,
+    ) -> (StopReason, u16) {
+        let stop = session.analyze_until(&go, &stop_when).await.unwrap();
+        (stop.reason, stop.info.unwrap().depth().unwrap())
+    }
+
+    #[tokio::test]
+    async fn analysis_until_stops_early() {
+        let mut eng = Engine::new(fake_engine(ITERATING_ENGINE));
+        let mut session = AnalysisSession::new(&mut eng);
+
+        let mut stop_when = StopWhen::new();
+        stop_when.set_depth(3);
+        assert_eq!(
+            until(&mut session, stop_when, Go::new()).await,
+            (StopReason::Depth, 3)
+        );
+
+        let mut stop_when = StopWhen::new();
+        stop_when.set_stable(2, 1);
+        assert_eq!(
+            until(&mut session, stop_when, Go::new()).await,
+            (StopReason::Stable, 4)
+        );
+
+        let mut stop_when = StopWhen::new();
+        stop_when.set_mate().set_depth(9);
+        assert_eq!(
+            until(&mut session, stop_when, Go::new()).await,
+            (StopReason::Mate, 5)
+        );
+
+        let mut stop_when = StopWhen::new();
+        stop_when.set_time(Duration::from_millis(50)).set_depth(9);
+        assert_eq!(
+            until(&mut session, stop_when, Go::new()).await,
+            (StopReason::Time, 5)
+        );
+
+        let mut go = Go::new();
+        go.set_depth(5);
+        assert_eq!(
+            until(&mut session, StopWhen::new(), go).await,
+            (StopReason::Finished, 5)
+        );
+    }
+
+    #[tokio::test]
+    async fn analysis_nodes_stops_engine_over_budget() {
+        // Honors go nodes only up to 1000 nodes.
+        let script = r#"
+            while read -r line; do
+                case "$line" in
+                    isready) echo "readyok";;
+                    "go nodes 1000")
+                        echo "info depth 1 score cp 10 nodes 900 pv e2e4"
+                        echo "bestmove e2e4";;
+                    go*)
+                        echo "info depth 1 score cp 10 nodes 900 pv e2e4"
+                        echo "info depth 2 score cp 20 nodes 2500 pv d2d4"
+                        echo "info depth 3 score cp 30 nodes 9000 pv g1f3";;
+                    stop) echo "bestmove d2d4";;
+                esac
+            done
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let mut session = AnalysisSession::new(&mut eng);
+
+        let stop = session.analyze_nodes(1000).await.unwrap();
+        assert_eq!(stop.reason, StopReason::Finished);
+        assert_eq!(stop.info.unwrap().nodes(), Some(900));
+
+        let stop = session.analyze_nodes(2000).await.unwrap();
+        assert_eq!(stop.reason, StopReason::Nodes);
+        assert_eq!(stop.info.unwrap().nodes(), Some(900));
+    }
+
+    #[tokio::test]
+    async fn analysis_infinite_switches_position() {
+        // Sends a line for the last move of the position it analyzes.
+        let script = r#"
+            while read -r line; do
+                case "$line" in
+                    isready) echo "readyok";;
+                    position*) last=${line##* };;
+                    "go infinite") echo "info depth 1 score cp 10 pv $last";;
+                    stop) echo "bestmove $last";;
+                esac
+            done
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let mut session = AnalysisSession::new(&mut eng);
+        let mut pos = Pos::new();
+        pos.add_move(pm("e2e4"));
+        session.set_position(pos.clone());
+
+        let mut analysis = session.start_infinite_analysis().await.unwrap();
+        let line = analysis.next_update().await.unwrap().unwrap();
+        assert_eq!(line.moves, vec![pm("e2e4")]);
+
+        pos.add_move(pm("e7e5"));
+        analysis.set_position(pos.clone()).await.unwrap();
+        assert!(analysis.lines().is_empty());
+        assert_eq!(analysis.position(), &pos);
+        let line = analysis.next_update().await.unwrap().unwrap();
+        assert_eq!(line.moves, vec![pm("e7e5")]);
+
+        let result = analysis.stop().await.unwrap();
+        assert_eq!(result.best, pm("e7e5"));
+        assert_eq!(result.lines, vec![line]);
+    }
+
+    #[tokio::test]
+    async fn analysis_cached_searches_once() {
+        // Scores each search by how many searches it did before.
+        let script = r#"
+            n=0
+            while read -r line; do
+                case "$line" in
+                    isready) echo "readyok";;
+                    go*)
+                        n=$((n + 1))
+                        echo "info depth 1 score cp $n pv e2e4"
+                        echo "bestmove e2e4";;
+                esac
+            done
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let mut session = AnalysisSession::new(&mut eng);
+        let mut go = Go::new();
+        go.set_depth(1);
+        let cache = AnalysisCache::new();
+        session.set_cache(cache.clone());
+
+        let first = session.analyze_cached(&go).await.unwrap();
+        let again = session.analyze_cached(&go).await.unwrap();
+        assert_eq!(again, first);
+        assert_eq!(cache.len(), 1);
+
+        // Other limits are a different entry.
+        go.set_depth(2);
+        let deeper = session.analyze_cached(&go).await.unwrap();
+        assert_eq!(deeper.lines[0].score.and_then(|score| score.cp()), Some(2));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn analysis_from_prior_lines() {
+        // Only answers a search restricted to the first moves of the earlier
+        // lines, and finds that only d2d4 is good.
+        let script = r#"
+            while read -r line; do
+                case "$line" in
+                    isready) echo "readyok";;
+                    "go depth 2 searchmoves e2e4 d2d4")
+                        echo "info depth 2 multipv 1 score cp 40 pv d2d4 d7d5"
+                        echo "bestmove d2d4";;
+                esac
+            done
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let mut session = AnalysisSession::new(&mut eng);
+        let line = |rank, depth, cp: &str, moves: &[&str]| PvLine {
+            rank,
+            depth: Some(depth),
+            sel_depth: None,
+            score: Info::try_from(&["info", "score", "cp", cp][..])
+                .unwrap()
+                .score(),
+            moves: moves.iter().map(|m| pm(m)).collect(),
+            nodes: None,
+            time: None,
+        };
+        let prior = AnalysisResult {
+            best: pm("e2e4"),
+            ponder: None,
+            lines: vec![line(1, 1, "30", &["e2e4"]), line(2, 1, "20", &["d2d4"])],
+        };
+        let mut go = Go::new();
+        go.set_depth(2);
+
+        let result = session.analyze_from(&prior, &go).await.unwrap();
+        assert_eq!(result.best, pm("d2d4"));
+        assert_eq!(
+            result.lines,
+            vec![
+                line(1, 2, "40", &["d2d4", "d7d5"]),
+                line(2, 1, "30", &["e2e4"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,315 @@
+// This module contains BatchEval, which evaluates a list of positions given as
+// FENs, on one engine or on an EnginePool, e.g. to generate datasets or to run
+// test suites.
+
+use crate::analysis::{AnalysisResult, AnalysisSession};
+use crate::cache::AnalysisCache;
+use crate::client::Engine;
+use crate::err::UziErr;
+use crate::guicmd::{Go, Pos};
+use crate::pool::EnginePool;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+
+// Called with every result of a batch.
+type Progress = Box<dyn FnMut(&FenEval, BatchProgress) + Send>;
+
+// Evaluates positions with the same limits. Every result is passed to the
+// progress callback as soon as it is known, and the batch can be cancelled
+// between positions.
+pub struct BatchEval {
+    limits: Go,
+    progress: Option<Progress>,
+    cancel: BatchCancel,
+    cache: Option<AnalysisCache>,
+}
+
+// The evaluation of one position of the batch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FenEval {
+    // The index of the position in the batch.
+    pub index: usize,
+    pub fen: String,
+    pub result: Result<AnalysisResult, UziErr>,
+}
+
+// How far a batch is, passed to the progress callback.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BatchProgress {
+    pub done: usize,
+    // The number of positions, if the iterator knows it.
+    pub total: Option<usize>,
+}
+
+// Cancels a batch from another task. Positions that are being evaluated are
+// finished, and no new ones are started.
+#[derive(Clone, Debug, Default)]
+pub struct BatchCancel(Arc<AtomicBool>);
+
+impl BatchCancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+// The state shared by the workers of a batch.
+struct Shared<I> {
+    fens: Mutex<std::iter::Enumerate<I>>,
+    total: Option<usize>,
+    results: Mutex<Vec<FenEval>>,
+    progress: Mutex<Option<Progress>>,
+}
+
+impl BatchEval {
+    pub fn new(limits: Go) -> Self {
+        Self {
+            limits,
+            progress: None,
+            cancel: BatchCancel::default(),
+            cache: None,
+        }
+    }
+
+    // Sets the callback that gets every result, with the progress of the
+    // batch. Results come in the order they finish, which is not the order of
+    // the positions when the batch runs on a pool.
+    pub fn set_progress<F>(&mut self, progress: F) -> &mut Self
+    where
+        F: FnMut(&FenEval, BatchProgress) + Send + 'static,
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    // Sets a cache for the results, so that positions evaluated by an earlier
+    // batch with the same limits are not searched again.
+    pub fn set_cache(&mut self, cache: AnalysisCache) -> &mut Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    // Returns a handle that cancels the batch.
+    pub fn cancel_handle(&self) -> BatchCancel {
+        self.cancel.clone()
+    }
+
+    // Evaluates the positions one after the other on the engine. Returns the
+    // results in the order of the positions, without the positions skipped
+    // after the batch was cancelled.
+    pub async fn run<I>(self, eng: &mut Engine, fens: I) -> Vec<FenEval>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let limits = self.limits.clone();
+        let cancel = self.cancel.clone();
+        let cache = self.cache.clone();
+        let shared = self.into_shared(fens);
+        while let Some((index, fen)) = next_fen(&shared, &cancel) {
+            let result = evaluate(eng, &fen, &limits, &cache).await;
+            on_result(&shared, FenEval { index, fen, result });
+        }
+        into_results(shared)
+    }
+
+    // Like run, but evaluates as many positions at once as the pool has
+    // engines.
+    pub async fn run_pool<I>(self, pool: &EnginePool, fens: I) -> Vec<FenEval>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let limits = self.limits.clone();
+        let cancel = self.cancel.clone();
+        let cache = self.cache.clone();
+        let shared = self.into_shared(fens);
+        let worker = async || {
+            while let Some((index, fen)) = next_fen(&shared, &cancel) {
+                let result = pool
+                    .run(async |eng: &mut Engine| evaluate(eng, &fen, &limits, &cache).await)
+                    .await;
+                on_result(&shared, FenEval { index, fen, result });
+            }
+        };
+        let workers =
+            (0..pool.size()).map(|_| Box::pin(worker()) as Pin<Box<dyn Future<Output = ()>>>);
+        join_all(workers.collect()).await;
+        into_results(shared)
+    }
+
+    fn into_shared<I: IntoIterator<Item = String>>(self, fens: I) -> Shared<I::IntoIter> {
+        let fens = fens.into_iter();
+        let (lower, upper) = fens.size_hint();
+        Shared {
+            fens: Mutex::new(fens.enumerate()),
+            total: upper.filter(|upper| *upper == lower),
+            results: Mutex::new(Vec::new()),
+            progress: Mutex::new(self.progress),
+        }
+    }
+}
+
+impl std::fmt::Debug for BatchEval {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter
+            .debug_struct("BatchEval")
+            .field("limits", &self.limits)
+            .field("cancel", &self.cancel)
+            .field("cache", &self.cache)
+            .finish_non_exhaustive()
+    }
+}
+
+// Returns the next position to evaluate, unless the batch was cancelled.
+fn next_fen<I: Iterator<Item = String>>(
+    shared: &Shared<I>,
+    cancel: &BatchCancel,
+) -> Option<(usize, String)> {
+    if cancel.is_cancelled() {
+        return None;
+    }
+    shared.fens.lock().unwrap().next()
+}
+
+fn on_result<I>(shared: &Shared<I>, eval: FenEval) {
+    let mut results = shared.results.lock().unwrap();
+    if let Some(ref mut progress) = *shared.progress.lock().unwrap() {
+        let done = results.len() + 1;
+        progress(
+            &eval,
+            BatchProgress {
+                done,
+                total: shared.total,
+            },
+        );
+    }
+    results.push(eval);
+}
+
+fn into_results<I>(shared: Shared<I>) -> Vec<FenEval> {
+    let mut results = shared.results.into_inner().unwrap();
+    results.sort_by_key(|eval| eval.index);
+    results
+}
+
+async fn evaluate(
+    eng: &mut Engine,
+    fen: &str,
+    limits: &Go,
+    cache: &Option<AnalysisCache>,
+) -> Result<AnalysisResult, UziErr> {
+    let mut session = AnalysisSession::new(eng);
+    session.set_position(Pos::with_fen(fen));
+    if let Some(cache) = cache {
+        session.set_cache(cache.clone());
+    }
+    session.analyze_cached(limits).await
+}
+
+// Runs the futures concurrently on the current task until all of them are
+// done.
+pub(crate) async fn join_all(mut futures: Vec<Pin<Box<dyn Future<Output = ()> + '_>>>) {
+    poll_fn(|cx| {
+        futures.retain_mut(|future| future.as_mut().poll(cx).is_pending());
+        match futures.is_empty() {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engproc::Spawner;
+    use crate::pm::Pm;
+    use crate::testutil::fake_engine;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    // Plays the move that follows the FEN, which the tests put after the
+    // moves counters.
+    const FEN_ENGINE: &str = r#"
+while read -r line; do
+    case "$line" in
+        uci) echo "uciok";;
+        isready) echo "readyok";;
+        position*) best=${line##* };;
+        go*)
+            echo "info depth 1 score cp 10 pv $best"
+            echo "bestmove $best";;
+        quit) exit 0;;
+    esac
+done
+"#;
+
+    fn fens() -> Vec<String> {
+        ["e2e4", "d2d4", "g1f3", "c2c4"]
+            .iter()
+            .map(|pm| format!("8/8/8/8/8/8/8/K6k w - - 0 1 {}", pm))
+            .collect()
+    }
+
+    fn limits() -> Go {
+        let mut go = Go::new();
+        go.set_depth(1);
+        go
+    }
+
+    fn best(eval: &FenEval) -> Pm {
+        eval.result.as_ref().unwrap().best
+    }
+
+    #[tokio::test]
+    async fn batch_runs_on_engine_with_progress() {
+        let mut eng = Engine::new(fake_engine(FEN_ENGINE));
+        let mut batch = BatchEval::new(limits());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let progress_seen = seen.clone();
+        batch.set_progress(move |eval, progress| {
+            progress_seen.lock().unwrap().push((eval.index, progress));
+        });
+
+        let results = batch.run(&mut eng, fens()).await;
+        let moves: Vec<Pm> = results.iter().map(best).collect();
+        let expected: Vec<Pm> = ["e2e4", "d2d4", "g1f3", "c2c4"]
+            .iter()
+            .map(|pm| Pm::from_str(pm).unwrap())
+            .collect();
+        assert_eq!(moves, expected);
+        assert_eq!(
+            seen.lock().unwrap()[3],
+            (
+                3,
+                BatchProgress {
+                    done: 4,
+                    total: Some(4)
+                }
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn batch_runs_on_pool_and_cancels() {
+        let pool = EnginePool::new(Spawner::new(|| Ok(fake_engine(FEN_ENGINE))), 2);
+        let results = BatchEval::new(limits()).run_pool(&pool, fens()).await;
+        let indexes: Vec<usize> = results.iter().map(|eval| eval.index).collect();
+        assert_eq!(indexes, vec![0, 1, 2, 3]);
+        assert_eq!(best(&results[2]), Pm::from_str("g1f3").unwrap());
+
+        // Cancel after the first result.
+        let mut batch = BatchEval::new(limits());
+        let cancel = batch.cancel_handle();
+        batch.set_progress(move |_, _| cancel.cancel());
+        let mut eng = Engine::new(fake_engine(FEN_ENGINE));
+        eng.uci(Duration::from_secs(5)).await.unwrap();
+        let results = batch.run(&mut eng, fens()).await;
+        assert_eq!(results.len(), 1);
+    }
+}
@@ -0,0 +1,534 @@
+// This module contains a blocking version of the client, enabled with the
+// sync-client feature. It has the same API as client::Engine without async, and
+// reads the engine output on threads, so no async runtime is needed. It is meant
+// for small tools; the async client also has a watchdog, restarts, transports
+// other than processes and info throttling.
+
+use crate::client::{best_move_limits, Shutdown};
+use crate::engcmd::{EngCmd, Info};
+use crate::engproc::{exit_signal, CrashReport, Launcher};
+use crate::err::UziErr;
+use crate::framing::LineReader;
+use crate::guicmd::{Go, GuiCmd, Pos};
+use crate::opt::{HasOpt, SetOpt};
+use crate::pm::Pm;
+use crate::search::SearchState;
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::fmt::Display;
+use std::io::{Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc::RecvTimeoutError;
+#[cfg(not(feature = "spsc"))]
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// The number of stderr lines kept for crash reports.
+const STDERR_TAIL_LEN: usize = 32;
+
+// How often the process is checked while waiting for it to exit.
+const EXIT_POLL: Duration = Duration::from_millis(10);
+
+// How many stdout lines the reader thread reads ahead of the client with the
+// spsc feature, after which it waits for the client.
+#[cfg(feature = "spsc")]
+const STDOUT_CAPACITY: usize = 1024;
+
+// The queue of the lines the engine writes to stdout. With the spsc feature,
+// this is a bounded lock-free queue rather than a channel.
+#[cfg(feature = "spsc")]
+type LineReceiver = crate::spsc::Receiver<String>;
+#[cfg(not(feature = "spsc"))]
+type LineReceiver = Receiver<String>;
+
+// A blocking client connected to a chess engine process. The process is killed
+// when this is dropped.
+#[derive(Debug)]
+pub struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+
+    // The lines the engine writes to stdout, read by a separate thread. The
+    // channel is closed when the engine closes its output.
+    stdout: LineReceiver,
+
+    // The last lines the engine wrote to stderr, read by a separate thread.
+    stderr_thread: JoinHandle<()>,
+    stderr_tail: Arc<Mutex<VecDeque<String>>>,
+
+    // Text sent to the engine before the first line, see Launcher.
+    startup: Option<String>,
+    last_sent: Option<String>,
+
+    name: Option<String>,
+    author: Option<String>,
+    options: Vec<HasOpt>,
+
+    // Commands received while waiting for a specific reply, returned by recv
+    // before reading anything new from the engine.
+    pending: VecDeque<EngCmd>,
+
+    search: SearchState,
+}
+
+impl Engine {
+    // Spawns the engine binary at the given path.
+    pub fn spawn<S: AsRef<OsStr>>(program: S) -> Result<Self, UziErr> {
+        Self::from_cmd(Command::new(program))
+    }
+
+    // Launches the engine with the given parameters.
+    pub fn launch(launcher: Launcher) -> Result<Self, UziErr> {
+        let mut eng = Self::from_cmd(launcher.command()?)?;
+        eng.startup = launcher.startup().map(String::from);
+        Ok(eng)
+    }
+
+    // Spawns the engine from a command that has been set up by the caller,
+    // e.g. with arguments. The standard streams are overridden.
+    pub fn from_cmd(mut cmd: Command) -> Result<Self, UziErr> {
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or(UziErr::EngineExited)?;
+        let stdout = child.stdout.take().ok_or(UziErr::EngineExited)?;
+        let stderr = child.stderr.take().ok_or(UziErr::EngineExited)?;
+
+        #[cfg(feature = "spsc")]
+        let (stdout_tx, stdout_rx) = crate::spsc::channel(STDOUT_CAPACITY);
+        #[cfg(not(feature = "spsc"))]
+        let (stdout_tx, stdout_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut lines = LineReader::new(stdout);
+            loop {
+                let line = match lines.next_line_blocking() {
+                    Ok(Some(line)) => line,
+                    // The rest of a line that is too long is skipped.
+                    Err(UziErr::LineTooLong(_)) => continue,
+                    _ => break,
+                };
+                if stdout_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let stderr_tail = Arc::new(Mutex::new(VecDeque::with_capacity(STDERR_TAIL_LEN)));
+        let tail = stderr_tail.clone();
+        let stderr_thread = thread::spawn(move || read_stderr(stderr, tail));
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout: stdout_rx,
+            stderr_thread,
+            stderr_tail,
+            startup: None,
+            last_sent: None,
+            name: None,
+            author: None,
+            options: Vec::new(),
+            pending: VecDeque::new(),
+            search: SearchState::Idle,
+        })
+    }
+
+    // The name of the engine, as sent during the handshake.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    // The author of the engine, as sent during the handshake.
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    // The options declared by the engine during the handshake.
+    pub fn options(&self) -> &[HasOpt] {
+        &self.options
+    }
+
+    // Returns true from go until the bestmove of the search has been received.
+    pub fn is_searching(&self) -> bool {
+        !self.search.is_idle()
+    }
+
+    // Performs the handshake: sends uci and collects the engine identity and
+    // options until uciok is received. Lines that are not UCI commands are
+    // skipped.
+    pub fn uci(&mut self, timeout: Duration) -> Result<(), UziErr> {
+        self.send(&GuiCmd::Uci)?;
+        self.options.clear();
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.read_cmd(Some(deadline))? {
+                EngCmd::UciOk => return Ok(()),
+                EngCmd::IdName(name) => self.name = Some(name),
+                EngCmd::IdAuthor(author) => self.author = Some(author),
+                EngCmd::HasOpt(opt) => self.options.push(opt),
+                // TODO: log unexpected commands.
+                _ => continue,
+            }
+        }
+    }
+
+    // Sends a command to the engine. Returns UziErr::BadSearchState if the
+    // command is not valid while the engine is searching.
+    pub fn send(&mut self, cmd: &GuiCmd) -> Result<(), UziErr> {
+        let search = self.search.on_send(cmd)?;
+        if let Some(startup) = self.startup.take() {
+            writeln!(self.stdin, "{}", startup)?;
+        }
+        let line = cmd.to_string();
+        writeln!(self.stdin, "{}", line)?;
+        self.stdin.flush()?;
+        self.last_sent = Some(line);
+        self.search = search;
+        Ok(())
+    }
+
+    // Returns the next command from the engine, starting with the commands that
+    // were buffered while waiting for other replies. A bestmove that does not
+    // belong to any search is dropped.
+    pub fn recv(&mut self) -> Result<EngCmd, UziErr> {
+        loop {
+            let cmd = match self.pending.pop_front() {
+                Some(cmd) => cmd,
+                None => self.read_cmd(None)?,
+            };
+            let (search, is_expected) = self.search.on_recv(&cmd);
+            self.search = search;
+            if is_expected {
+                return Ok(cmd);
+            }
+            // TODO: log the stray command.
+        }
+    }
+
+    // Sets an option by name, e.g. set_option("Threads", 4).
+    pub fn set_option<V: Display>(&mut self, name: &str, value: V) -> Result<(), UziErr> {
+        self.set_opt(SetOpt::Custom {
+            name: name.into(),
+            value: Some(value.to_string()),
+        })
+    }
+
+    // Presses a button option, e.g. "Clear Hash".
+    pub fn press_button(&mut self, name: &str) -> Result<(), UziErr> {
+        self.set_opt(SetOpt::Custom {
+            name: name.into(),
+            value: None,
+        })
+    }
+
+    // Sets one of the options defined by the UCI standard.
+    pub fn set_opt(&mut self, opt: SetOpt) -> Result<(), UziErr> {
+        self.send(&GuiCmd::SetOpt(opt))
+    }
+
+    // Tells the engine to switch debug mode on or off.
+    pub fn debug(&mut self, is_enabled: bool) -> Result<(), UziErr> {
+        self.send(&GuiCmd::Debug(is_enabled))
+    }
+
+    // Tells the engine that the next position is from a different game.
+    pub fn new_game(&mut self) -> Result<(), UziErr> {
+        self.send(&GuiCmd::NewGame)
+    }
+
+    // Sets up the position to search.
+    pub fn position(&mut self, pos: &Pos) -> Result<(), UziErr> {
+        self.send(&GuiCmd::Pos(pos.clone()))
+    }
+
+    // Starts searching the current position. The returned handle delivers the
+    // info updates of the search and its result.
+    pub fn go(&mut self, go: &Go) -> Result<SearchHandle<'_>, UziErr> {
+        if self.search.is_stopping() {
+            self.wait_best_move()?;
+        }
+        self.send(&GuiCmd::Go(go.clone()))?;
+        Ok(SearchHandle {
+            eng: self,
+            best: None,
+        })
+    }
+
+    // Tells the engine to stop searching as soon as possible. The engine still
+    // replies with bestmove.
+    pub fn stop(&mut self) -> Result<(), UziErr> {
+        self.send(&GuiCmd::Stop)
+    }
+
+    // Tells the engine that the opponent played the expected move.
+    pub fn ponderhit(&mut self) -> Result<(), UziErr> {
+        self.send(&GuiCmd::Ponderhit)
+    }
+
+    // Tells the engine to quit.
+    pub fn quit(&mut self) -> Result<(), UziErr> {
+        self.send(&GuiCmd::Quit)
+    }
+
+    // Sends quit and waits for the engine to exit. If it does not exit within
+    // the grace period, it is killed.
+    pub fn shutdown(&mut self, grace_period: Duration) -> Result<Shutdown, UziErr> {
+        // The engine may already be gone, in which case it has exited.
+        let _ = self.quit();
+        if self.wait_exit(grace_period).is_some() {
+            return Ok(Shutdown::Quit);
+        }
+        self.child.kill()?;
+        self.child.wait()?;
+        Ok(Shutdown::Killed)
+    }
+
+    // Sends isready and waits for readyok. Any other command received in the
+    // meantime is buffered and later returned by recv. Returns UziErr::Timeout
+    // if the engine does not answer within the timeout.
+    pub fn sync(&mut self, timeout: Duration) -> Result<(), UziErr> {
+        self.send(&GuiCmd::IsReady)?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.read_cmd(Some(deadline))? {
+                EngCmd::ReadyOk => return Ok(()),
+                cmd => self.pending.push_back(cmd),
+            }
+        }
+    }
+
+    // Searches the position and returns the best move, like the async
+    // Engine::best_move.
+    pub fn best_move(&mut self, pos: &Pos, limits: &Go) -> Result<Pm, UziErr> {
+        self.position(pos)?;
+        let (best, _) = self.go(&best_move_limits(limits))?.wait()?;
+        Ok(best)
+    }
+
+    // Waits for the bestmove of a search that has been started, returning the
+    // best move and the optional ponder move. Info lines are skipped.
+    pub fn wait_best_move(&mut self) -> Result<(Pm, Option<Pm>), UziErr> {
+        if self.search.is_idle() {
+            return Err(UziErr::BadSearchState);
+        }
+        loop {
+            if let EngCmd::BestMove { best, ponder } = self.recv()? {
+                return Ok((best, ponder));
+            }
+        }
+    }
+
+    // Reads the next command from the engine, skipping lines that cannot be
+    // parsed. Fails with UziErr::Timeout if a deadline is given and passes.
+    fn read_cmd(&mut self, deadline: Option<Instant>) -> Result<EngCmd, UziErr> {
+        loop {
+            let line = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    match self.stdout.recv_timeout(timeout) {
+                        Ok(line) => line,
+                        Err(RecvTimeoutError::Timeout) => return Err(UziErr::Timeout),
+                        Err(RecvTimeoutError::Disconnected) => {
+                            return Err(UziErr::Crashed(self.crash_report()))
+                        }
+                    }
+                }
+                None => match self.stdout.recv() {
+                    Ok(line) => line,
+                    Err(_) => return Err(UziErr::Crashed(self.crash_report())),
+                },
+            };
+            match EngCmd::from_str(&line) {
+                Ok(cmd) => return Ok(cmd),
+                // TODO: log the line.
+                Err(_) => continue,
+            }
+        }
+    }
+
+    // Waits for the process to exit, returning None if it does not exit in
+    // time.
+    fn wait_exit(&mut self, timeout: Duration) -> Option<std::process::ExitStatus> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Ok(Some(status)) = self.child.try_wait() {
+                return Some(status);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            thread::sleep(EXIT_POLL);
+        }
+    }
+
+    // Creates the report for an engine that closed its output. This waits
+    // briefly for the process to exit and for the rest of stderr to be read.
+    fn crash_report(&mut self) -> CrashReport {
+        let status = self.wait_exit(Duration::from_millis(100));
+        let deadline = Instant::now() + Duration::from_millis(100);
+        while !self.stderr_thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(EXIT_POLL);
+        }
+        CrashReport {
+            exit_code: status.and_then(|status| status.code()),
+            signal: status.and_then(exit_signal),
+            in_flight: self.last_sent.clone(),
+            stdout: Vec::new(),
+            stderr: self.stderr_tail.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        // These fail if the process is already gone, which is fine.
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Reads the stderr of the engine until it is closed, keeping the last lines.
+fn read_stderr<R: Read>(stderr: R, tail: Arc<Mutex<VecDeque<String>>>) {
+    let mut lines = LineReader::new(stderr);
+    loop {
+        let line = match lines.next_line_blocking() {
+            Ok(Some(line)) => line,
+            Err(UziErr::LineTooLong(_)) => continue,
+            _ => break,
+        };
+        let mut tail = tail.lock().unwrap();
+        if tail.len() == STDERR_TAIL_LEN {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+}
+
+// A handle to a running search, see search::SearchHandle.
+#[derive(Debug)]
+pub struct SearchHandle<'a> {
+    eng: &'a mut Engine,
+
+    // The best move and the ponder move, once bestmove is received.
+    best: Option<(Pm, Option<Pm>)>,
+}
+
+impl SearchHandle<'_> {
+    // Returns true once the engine has sent bestmove.
+    pub fn is_done(&self) -> bool {
+        self.best.is_some()
+    }
+
+    // Returns the next info update of the search, or None once the engine has
+    // sent bestmove.
+    pub fn next_info(&mut self) -> Result<Option<Info>, UziErr> {
+        while self.best.is_none() {
+            match self.eng.recv()? {
+                EngCmd::Info(info) => return Ok(Some(info)),
+                EngCmd::BestMove { best, ponder } => self.best = Some((best, ponder)),
+                // TODO: log unexpected commands.
+                _ => continue,
+            }
+        }
+        Ok(None)
+    }
+
+    // Tells the engine to stop searching. The result is still read with wait.
+    pub fn stop(&mut self) -> Result<(), UziErr> {
+        if self.is_done() {
+            return Ok(());
+        }
+        self.eng.stop()
+    }
+
+    // Tells the engine that the opponent played the move it was pondering on.
+    pub fn ponderhit(&mut self) -> Result<(), UziErr> {
+        self.eng.ponderhit()
+    }
+
+    // Waits for the search to finish, skipping the remaining info updates, and
+    // returns the best move and the optional ponder move.
+    pub fn wait(mut self) -> Result<(Pm, Option<Pm>), UziErr> {
+        while self.next_info()?.is_some() {}
+        Ok(self.best.expect("search is done"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::FAKE_ENGINE;
+    use crate::types::SpinType;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn fake_engine(script: &str) -> Engine {
+        let mut launcher = Launcher::new("sh");
+        launcher.add_arg("-c").add_arg(script);
+        Engine::launch(launcher).unwrap()
+    }
+
+    #[test]
+    fn blocking_engine_uci_and_search() {
+        let mut eng = fake_engine(FAKE_ENGINE);
+        assert_eq!(eng.uci(TIMEOUT), Ok(()));
+        assert_eq!(eng.name(), Some("Fake"));
+        assert_eq!(
+            eng.options(),
+            &[HasOpt::Hash(SpinType {
+                default: 16,
+                min: 1,
+                max: 1024
+            })]
+        );
+        assert_eq!(eng.sync(TIMEOUT), Ok(()));
+
+        let mut go = Go::new();
+        go.set_depth(2);
+        let mut search = eng.go(&go).unwrap();
+        assert_eq!(search.next_info().unwrap().unwrap().depth(), Some(1));
+        assert_eq!(
+            search.wait(),
+            Ok((
+                Pm::from_str("e2e4").unwrap(),
+                Some(Pm::from_str("e7e5").unwrap())
+            ))
+        );
+        assert_eq!(
+            eng.best_move(&Pos::new(), &Go::new()),
+            Ok(Pm::from_str("e2e4").unwrap())
+        );
+        assert_eq!(eng.shutdown(TIMEOUT), Ok(Shutdown::Quit));
+    }
+
+    #[test]
+    fn blocking_engine_with_odd_output() {
+        // A name in Latin-1, and lines that end with a bare CR.
+        let script = r"read -r line; printf 'id name A\351\rid author B\ruciok\r'; cat > /dev/null";
+        let mut eng = fake_engine(script);
+        assert_eq!(eng.uci(TIMEOUT), Ok(()));
+        assert_eq!(eng.name(), Some("A\u{fffd}"));
+        assert_eq!(eng.author(), Some("B"));
+    }
+
+    #[test]
+    fn blocking_engine_timeout_and_crash() {
+        let mut eng = fake_engine("cat > /dev/null");
+        assert_eq!(eng.sync(Duration::from_millis(50)), Err(UziErr::Timeout));
+
+        let mut eng = fake_engine("read -r line; echo 'NNUE not found' >&2; exit 3");
+        match eng.sync(TIMEOUT) {
+            Err(UziErr::Crashed(report)) => {
+                assert_eq!(report.exit_code, Some(3));
+                assert_eq!(report.in_flight, Some("isready".into()));
+                assert_eq!(report.stderr, vec!["NNUE not found".to_string()]);
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+}
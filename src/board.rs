@@ -0,0 +1,750 @@
+// This module contains Board, a minimal board that knows the rules of chess,
+// for the example engine and for checking the moves of engines in a match. It
+// is meant to be simple rather than fast.
+
+use crate::guicmd::Pos;
+use crate::piece::Piece;
+use crate::pm::Pm;
+use crate::sq::Sq;
+
+pub(crate) const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    fn other(self) -> Self {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+
+    // The direction pawns of the color move in, in rows.
+    fn forward(self) -> i8 {
+        match self {
+            Color::White => 1,
+            Color::Black => -1,
+        }
+    }
+
+    // The row the pieces of the color start on.
+    fn home_row(self) -> i8 {
+        match self {
+            Color::White => 0,
+            Color::Black => 7,
+        }
+    }
+}
+
+const KNIGHT_STEPS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [(i8, i8); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+const ROOK_DIRS: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_DIRS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const PROMOS: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+
+// A board with the pieces, the side to move, the castling rights, the en
+// passant square and the move counters. Squares are (row, col) pairs, with a1
+// at (0, 0).
+#[derive(Clone, Debug)]
+pub struct Board {
+    squares: [[Option<(Color, Piece)>; 8]; 8],
+    side: Color,
+    // The king side and queen side rights, for white and then for black.
+    castling: [bool; 4],
+    en_passant: Option<(i8, i8)>,
+    // The plies since the last capture or pawn move.
+    halfmove_clock: u16,
+    // The number of the move, which starts at 1 and grows after black moves.
+    fullmove: u16,
+}
+
+impl Board {
+    // Sets up the position, and returns None if the FEN or a move is not
+    // valid.
+    pub fn from_pos(pos: &Pos) -> Option<Self> {
+        let mut board = Self::from_fen(pos.fen().unwrap_or(START_FEN))?;
+        for pm in pos.moves() {
+            if !board.legal_moves().contains(pm) {
+                return None;
+            }
+            board.play(*pm);
+        }
+        Some(board)
+    }
+
+    pub fn from_fen(fen: &str) -> Option<Self> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 {
+            return None;
+        }
+        let mut squares = [[None; 8]; 8];
+        let rows: Vec<&str> = fields[0].split('/').collect();
+        if rows.len() != 8 {
+            return None;
+        }
+        for (i, row) in rows.iter().enumerate() {
+            let mut col = 0;
+            for c in row.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    col += empty as usize;
+                    continue;
+                }
+                let color = if c.is_ascii_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                let piece = Piece::try_from(c.to_ascii_lowercase()).ok()?;
+                *squares[7 - i].get_mut(col)? = Some((color, piece));
+                col += 1;
+            }
+        }
+        let side = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => return None,
+        };
+        let castling = ['K', 'Q', 'k', 'q'].map(|right| fields[2].contains(right));
+        let en_passant = match fields[3] {
+            "-" => None,
+            sq => Some(rc(sq.parse::<Sq>().ok()?)),
+        };
+        // The counters are optional, like in the EPD positions of test suites.
+        let halfmove_clock = match fields.get(4) {
+            Some(clock) => clock.parse().ok()?,
+            None => 0,
+        };
+        let fullmove = match fields.get(5) {
+            Some(fullmove) => fullmove.parse().ok()?,
+            None => 1,
+        };
+        Some(Self {
+            squares,
+            side,
+            castling,
+            en_passant,
+            halfmove_clock,
+            fullmove,
+        })
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut rows = Vec::with_capacity(8);
+        for row in (0..8).rev() {
+            let mut fen_row = String::new();
+            let mut empty = 0;
+            for col in 0..8 {
+                let Some((color, piece)) = self.at((row, col)) else {
+                    empty += 1;
+                    continue;
+                };
+                if empty > 0 {
+                    fen_row.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                fen_row.push(piece_char(color, piece));
+            }
+            if empty > 0 {
+                fen_row.push_str(&empty.to_string());
+            }
+            rows.push(fen_row);
+        }
+        let side = match self.side {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+        let castling: String = ['K', 'Q', 'k', 'q']
+            .iter()
+            .zip(self.castling)
+            .filter_map(|(right, has_right)| has_right.then_some(*right))
+            .collect();
+        let en_passant = self
+            .en_passant
+            .map_or("-".to_string(), |at| sq(at).to_string());
+        format!(
+            "{} {} {} {} {} {}",
+            rows.join("/"),
+            side,
+            if castling.is_empty() { "-" } else { &castling },
+            en_passant,
+            self.halfmove_clock,
+            self.fullmove
+        )
+    }
+
+    // Returns the board mirrored top to bottom with the colors swapped, which
+    // is the same position for the other side.
+    pub fn flip(&self) -> Self {
+        let mut flipped = self.clone();
+        for row in 0..8 {
+            for col in 0..8 {
+                let piece = self.at((7 - row, col));
+                flipped.set(
+                    (row, col),
+                    piece.map(|(color, piece)| (color.other(), piece)),
+                );
+            }
+        }
+        flipped.side = self.side.other();
+        flipped.castling = [
+            self.castling[2],
+            self.castling[3],
+            self.castling[0],
+            self.castling[1],
+        ];
+        flipped.en_passant = self.en_passant.map(|(row, col)| (7 - row, col));
+        flipped
+    }
+
+    fn at(&self, (row, col): (i8, i8)) -> Option<(Color, Piece)> {
+        self.squares[row as usize][col as usize]
+    }
+
+    fn set(&mut self, (row, col): (i8, i8), piece: Option<(Color, Piece)>) {
+        self.squares[row as usize][col as usize] = piece;
+    }
+
+    pub fn legal_moves(&self) -> Vec<Pm> {
+        self.pseudo_moves()
+            .into_iter()
+            .filter(|pm| {
+                let mut next = self.clone();
+                next.play(*pm);
+                !next.is_in_check(self.side)
+            })
+            .collect()
+    }
+
+    // Returns the moves of the side to move, some of which may leave its king
+    // in check.
+    fn pseudo_moves(&self) -> Vec<Pm> {
+        let mut moves = Vec::new();
+        for row in 0..8 {
+            for col in 0..8 {
+                match self.at((row, col)) {
+                    Some((color, piece)) if color == self.side => {
+                        self.piece_moves((row, col), piece, &mut moves)
+                    }
+                    _ => (),
+                }
+            }
+        }
+        moves
+    }
+
+    fn piece_moves(&self, from: (i8, i8), piece: Piece, moves: &mut Vec<Pm>) {
+        match piece {
+            Piece::Pawn => self.pawn_moves(from, moves),
+            Piece::Knight => self.step_moves(from, &KNIGHT_STEPS, moves),
+            Piece::King => {
+                self.step_moves(from, &KING_STEPS, moves);
+                self.castling_moves(moves);
+            }
+            Piece::Bishop => self.slide_moves(from, &BISHOP_DIRS, moves),
+            Piece::Rook => self.slide_moves(from, &ROOK_DIRS, moves),
+            Piece::Queen => {
+                self.slide_moves(from, &BISHOP_DIRS, moves);
+                self.slide_moves(from, &ROOK_DIRS, moves);
+            }
+        }
+    }
+
+    fn pawn_moves(&self, from: (i8, i8), moves: &mut Vec<Pm>) {
+        let forward = self.side.forward();
+        let mut add = |to: (i8, i8)| {
+            if to.0 == 0 || to.0 == 7 {
+                for promo in PROMOS {
+                    moves.push(Pm::Promo {
+                        from: sq(from),
+                        to: sq(to),
+                        promo,
+                    });
+                }
+            } else {
+                moves.push(Pm::Normal {
+                    from: sq(from),
+                    to: sq(to),
+                });
+            }
+        };
+
+        let one = (from.0 + forward, from.1);
+        if self.at(one).is_none() {
+            add(one);
+            let two = (from.0 + 2 * forward, from.1);
+            if from.0 == self.side.home_row() + forward && self.at(two).is_none() {
+                add(two);
+            }
+        }
+        for dc in [-1, 1] {
+            let to = (from.0 + forward, from.1 + dc);
+            if !on_board(to) {
+                continue;
+            }
+            let is_capture = matches!(self.at(to), Some((color, _)) if color != self.side);
+            if is_capture || self.en_passant == Some(to) {
+                add(to);
+            }
+        }
+    }
+
+    fn step_moves(&self, from: (i8, i8), steps: &[(i8, i8)], moves: &mut Vec<Pm>) {
+        for (dr, dc) in steps {
+            let to = (from.0 + dr, from.1 + dc);
+            if on_board(to) && !matches!(self.at(to), Some((color, _)) if color == self.side) {
+                moves.push(Pm::Normal {
+                    from: sq(from),
+                    to: sq(to),
+                });
+            }
+        }
+    }
+
+    fn slide_moves(&self, from: (i8, i8), dirs: &[(i8, i8)], moves: &mut Vec<Pm>) {
+        for (dr, dc) in dirs {
+            let mut to = (from.0 + dr, from.1 + dc);
+            while on_board(to) {
+                let target = self.at(to);
+                if !matches!(target, Some((color, _)) if color == self.side) {
+                    moves.push(Pm::Normal {
+                        from: sq(from),
+                        to: sq(to),
+                    });
+                }
+                if target.is_some() {
+                    break;
+                }
+                to = (to.0 + dr, to.1 + dc);
+            }
+        }
+    }
+
+    fn castling_moves(&self, moves: &mut Vec<Pm>) {
+        let row = self.side.home_row();
+        let rights = match self.side {
+            Color::White => [self.castling[0], self.castling[1]],
+            Color::Black => [self.castling[2], self.castling[3]],
+        };
+        let enemy = self.side.other();
+        if self.at((row, 4)) != Some((self.side, Piece::King)) || self.is_attacked((row, 4), enemy)
+        {
+            return;
+        }
+        // The squares between king and rook, and the squares the king crosses.
+        let sides: [(bool, i8, &[i8], &[i8]); 2] = [
+            (rights[0], 7, &[5, 6], &[5, 6]),
+            (rights[1], 0, &[1, 2, 3], &[3, 2]),
+        ];
+        for (has_right, rook_col, empty, crossed) in sides {
+            let is_possible = has_right
+                && self.at((row, rook_col)) == Some((self.side, Piece::Rook))
+                && empty.iter().all(|col| self.at((row, *col)).is_none())
+                && crossed
+                    .iter()
+                    .all(|col| !self.is_attacked((row, *col), enemy));
+            if is_possible {
+                moves.push(Pm::Normal {
+                    from: sq((row, 4)),
+                    to: sq((row, crossed[1])),
+                });
+            }
+        }
+    }
+
+    fn is_in_check(&self, color: Color) -> bool {
+        (0..8)
+            .flat_map(|row| (0..8).map(move |col| (row, col)))
+            .find(|at| self.at(*at) == Some((color, Piece::King)))
+            .is_some_and(|king| self.is_attacked(king, color.other()))
+    }
+
+    // Returns true if a piece of color attacks the square.
+    fn is_attacked(&self, at: (i8, i8), color: Color) -> bool {
+        let is = |(dr, dc): &(i8, i8), pieces: &[Piece]| {
+            let from = (at.0 + dr, at.1 + dc);
+            on_board(from)
+                && matches!(self.at(from), Some((c, p)) if c == color && pieces.contains(&p))
+        };
+        let slides_to = |(dr, dc): &(i8, i8), pieces: &[Piece]| {
+            let mut from = (at.0 + dr, at.1 + dc);
+            while on_board(from) {
+                if let Some((c, p)) = self.at(from) {
+                    return c == color && pieces.contains(&p);
+                }
+                from = (from.0 + dr, from.1 + dc);
+            }
+            false
+        };
+        let pawn_row = -color.forward();
+        [(pawn_row, -1), (pawn_row, 1)]
+            .iter()
+            .any(|step| is(step, &[Piece::Pawn]))
+            || KNIGHT_STEPS.iter().any(|step| is(step, &[Piece::Knight]))
+            || KING_STEPS.iter().any(|step| is(step, &[Piece::King]))
+            || BISHOP_DIRS
+                .iter()
+                .any(|dir| slides_to(dir, &[Piece::Bishop, Piece::Queen]))
+            || ROOK_DIRS
+                .iter()
+                .any(|dir| slides_to(dir, &[Piece::Rook, Piece::Queen]))
+    }
+
+    // Plays a move from pseudo_moves.
+    pub fn play(&mut self, pm: Pm) {
+        if self.side == Color::Black {
+            self.fullmove += 1;
+        }
+        let (from, to, promo) = match pm {
+            Pm::Normal { from, to } => (rc(from), rc(to), None),
+            Pm::Promo { from, to, promo } => (rc(from), rc(to), Some(promo)),
+            Pm::Null => {
+                self.halfmove_clock += 1;
+                self.side = self.side.other();
+                self.en_passant = None;
+                return;
+            }
+            // Board only knows chess, which has no drops.
+            Pm::Drop { .. } => return,
+        };
+        let Some((color, piece)) = self.at(from) else {
+            return;
+        };
+
+        if piece == Piece::Pawn || self.at(to).is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if piece == Piece::Pawn && self.en_passant == Some(to) {
+            self.set((from.0, to.1), None);
+        }
+        self.en_passant = None;
+        if piece == Piece::Pawn && (to.0 - from.0).abs() == 2 {
+            self.en_passant = Some(((from.0 + to.0) / 2, from.1));
+        }
+        if piece == Piece::King && (to.1 - from.1).abs() == 2 {
+            let (rook_from, rook_to) = if to.1 == 6 { (7, 5) } else { (0, 3) };
+            let rook = self.at((from.0, rook_from));
+            self.set((from.0, rook_from), None);
+            self.set((from.0, rook_to), rook);
+        }
+
+        // Moving the king or a rook, or capturing a rook, loses the rights.
+        for (i, corner) in [(0, 7), (0, 0), (7, 7), (7, 0)].into_iter().enumerate() {
+            let king = (corner.0, 4);
+            if [from, to].contains(&corner) || from == king {
+                self.castling[i] = false;
+            }
+        }
+
+        self.set(from, None);
+        self.set(to, Some((color, promo.unwrap_or(piece))));
+        self.side = self.side.other();
+    }
+
+    pub fn is_white_to_move(&self) -> bool {
+        self.side == Color::White
+    }
+
+    // Returns true if the side to move is in check.
+    pub fn is_check(&self) -> bool {
+        self.is_in_check(self.side)
+    }
+
+    pub fn halfmove_clock(&self) -> u16 {
+        self.halfmove_clock
+    }
+
+    pub fn fullmove(&self) -> u16 {
+        self.fullmove
+    }
+
+    // Returns the move in standard algebraic notation, e.g. Nbd2, exd6, O-O or
+    // e8=Q+. The move has to be legal.
+    pub fn san(&self, pm: Pm) -> String {
+        let (from, to, promo) = match pm {
+            Pm::Normal { from, to } => (rc(from), rc(to), None),
+            Pm::Promo { from, to, promo } => (rc(from), rc(to), Some(promo)),
+            Pm::Null => return "--".to_string(),
+            // The SAN of a drop is the move itself, e.g. N@d5.
+            Pm::Drop { .. } => return pm.to_string(),
+        };
+        let Some((_, piece)) = self.at(from) else {
+            return pm.to_string();
+        };
+
+        let mut san = String::new();
+        if piece == Piece::King && (to.1 - from.1).abs() == 2 {
+            san.push_str(if to.1 == 6 { "O-O" } else { "O-O-O" });
+        } else {
+            let is_capture =
+                self.at(to).is_some() || piece == Piece::Pawn && self.en_passant == Some(to);
+            let file = |at: (i8, i8)| char::from(b'a' + at.1 as u8);
+            if piece == Piece::Pawn {
+                if is_capture {
+                    san.push(file(from));
+                }
+            } else {
+                san.push(piece.to_char().to_ascii_uppercase());
+                // Other pieces of the same kind that can move to the square.
+                let others: Vec<(i8, i8)> = self
+                    .legal_moves()
+                    .into_iter()
+                    .filter_map(|other| match other {
+                        Pm::Normal {
+                            from: other,
+                            to: other_to,
+                        } if rc(other_to) == to => Some(rc(other)),
+                        _ => None,
+                    })
+                    .filter(|other| *other != from && self.at(*other).map(|at| at.1) == Some(piece))
+                    .collect();
+                if !others.is_empty() {
+                    if others.iter().all(|other| other.1 != from.1) {
+                        san.push(file(from));
+                    } else if others.iter().all(|other| other.0 != from.0) {
+                        san.push(char::from(b'1' + from.0 as u8));
+                    } else {
+                        san.push_str(sq(from).as_str());
+                    }
+                }
+            }
+            if is_capture {
+                san.push('x');
+            }
+            san.push_str(sq(to).as_str());
+            if let Some(promo) = promo {
+                san.push('=');
+                san.push(promo.to_char().to_ascii_uppercase());
+            }
+        }
+
+        let mut next = self.clone();
+        next.play(pm);
+        if next.is_check() {
+            san.push(if next.legal_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+        san
+    }
+
+    // Returns the legal move written in standard algebraic notation, or None if
+    // there is none. Check marks and annotations like ! or ? are ignored, and
+    // castling can be written with zeros too.
+    pub fn parse_san(&self, san: &str) -> Option<Pm> {
+        let normalize = |san: &str| {
+            san.trim_end_matches(['+', '#', '!', '?'])
+                .replace('0', "O")
+                .replace('=', "")
+        };
+        let san = normalize(san);
+        self.legal_moves()
+            .into_iter()
+            .find(|pm| normalize(&self.san(*pm)) == san)
+    }
+
+    // Returns a key that is the same for positions that count as repeated,
+    // i.e. with the same pieces, side to move, castling rights and en passant
+    // square. The en passant square is kept even if no capture is possible,
+    // which can miss a repetition but never finds a wrong one.
+    pub fn key(&self) -> Vec<u8> {
+        let mut key: Vec<u8> = self
+            .squares
+            .iter()
+            .flatten()
+            .map(|square| match square {
+                Some((color, piece)) => piece_char(*color, *piece) as u8,
+                None => b'.',
+            })
+            .collect();
+        key.push(self.side.forward() as u8);
+        key.extend(self.castling.map(u8::from));
+        key.extend(
+            self.en_passant
+                .map_or([8, 8], |(row, col)| [row as u8, col as u8]),
+        );
+        key
+    }
+
+    // Returns true if neither side can mate, i.e. there are only the kings and
+    // at most one minor piece, or only the kings and bishops on squares of one
+    // color.
+    pub fn is_insufficient_material(&self) -> bool {
+        let (mut knights, mut bishops) = (0, 0);
+        let mut bishop_colors = [false; 2];
+        for row in 0..8 {
+            for col in 0..8 {
+                match self.at((row, col)) {
+                    None | Some((_, Piece::King)) => (),
+                    Some((_, Piece::Knight)) => knights += 1,
+                    Some((_, Piece::Bishop)) => {
+                        bishops += 1;
+                        bishop_colors[((row + col) % 2) as usize] = true;
+                    }
+                    Some(_) => return false,
+                }
+            }
+        }
+        knights + bishops <= 1 || knights == 0 && !(bishop_colors[0] && bishop_colors[1])
+    }
+
+    pub fn perft(&self, depth: u16) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .into_iter()
+            .map(|pm| {
+                let mut next = self.clone();
+                next.play(pm);
+                next.perft(depth - 1)
+            })
+            .sum()
+    }
+}
+
+fn piece_char(color: Color, piece: Piece) -> char {
+    match color {
+        Color::White => piece.to_char().to_ascii_uppercase(),
+        Color::Black => piece.to_char(),
+    }
+}
+
+fn on_board((row, col): (i8, i8)) -> bool {
+    (0..8).contains(&row) && (0..8).contains(&col)
+}
+
+fn sq((row, col): (i8, i8)) -> Sq {
+    Sq::from((row as u8, col as u8))
+}
+
+fn rc(sq: Sq) -> (i8, i8) {
+    let (row, col) = sq.rc();
+    (row as i8, col as i8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    pub fn perft(fen: &str, depth: u16) -> u64 {
+        Board::from_fen(fen).unwrap().perft(depth)
+    }
+
+    #[test]
+    fn board_perft() {
+        assert_eq!(perft(START_FEN, 3), 8902);
+        // Positions with castling, en passant and promotions.
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(perft(kiwipete, 2), 2039);
+        assert_eq!(perft("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 3), 2812);
+        let promos = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        assert_eq!(perft(promos, 2), 264);
+    }
+
+    #[test]
+    fn board_flip() {
+        let fen = "r3k2r/8/8/3pP3/8/8/8/4K2R w Kkq d6 0 1";
+        let flipped = Board::from_fen(fen).unwrap().flip();
+        assert_eq!(flipped.to_fen(), "4k2r/8/8/8/3Pp3/8/8/R3K2R b KQk d3 0 1");
+        assert_eq!(flipped.flip().to_fen(), fen);
+    }
+
+    #[test]
+    fn board_draw_rules() {
+        let board = |fen: &str| Board::from_fen(fen).unwrap();
+        assert!(board("8/8/8/8/8/8/8/KN5k w - - 0 1").is_insufficient_material());
+        assert!(board("8/8/8/8/8/8/8/KB3b1k w - - 0 1").is_insufficient_material());
+        assert!(!board("8/8/8/8/8/8/8/KB4bk w - - 0 1").is_insufficient_material());
+        assert!(!board("8/8/8/8/8/8/8/KNN4k w - - 0 1").is_insufficient_material());
+        assert!(!board("8/8/8/8/8/8/P7/K6k w - - 0 1").is_insufficient_material());
+
+        // Captures and pawn moves reset the clock.
+        let mut clock = board("8/8/8/8/8/8/P6r/K1R4k w - - 40 1");
+        clock.play(Pm::from_str("c1d1").unwrap());
+        assert_eq!(clock.halfmove_clock(), 41);
+        clock.play(Pm::from_str("h2a2").unwrap());
+        assert_eq!(clock.halfmove_clock(), 0);
+
+        // Moving the knights out and back repeats the position.
+        let start = board(START_FEN);
+        let mut next = start.clone();
+        for pm in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            next.play(Pm::from_str(pm).unwrap());
+        }
+        assert_eq!(next.key(), start.key());
+        next.play(Pm::from_str("e2e4").unwrap());
+        assert!(!next.is_white_to_move());
+        assert_ne!(next.key(), start.key());
+    }
+
+    #[test]
+    fn board_san_and_fen() {
+        let san =
+            |fen: &str, pm: &str| Board::from_fen(fen).unwrap().san(Pm::from_str(pm).unwrap());
+        assert_eq!(san(START_FEN, "g1f3"), "Nf3");
+        assert_eq!(san(START_FEN, "e2e4"), "e4");
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(san(kiwipete, "e1g1"), "O-O");
+        assert_eq!(san(kiwipete, "e1c1"), "O-O-O");
+        assert_eq!(san(kiwipete, "d5e6"), "dxe6");
+        assert_eq!(san(kiwipete, "e5f7"), "Nxf7");
+        // Moves that another piece of the same kind could also make.
+        assert_eq!(san("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", "a1d1"), "Rad1");
+        assert_eq!(san("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "b1d2"), "Nbd2");
+        assert_eq!(san("4k3/8/8/8/R7/8/8/R3K3 w - - 0 1", "a1a2"), "R1a2");
+        assert_eq!(san("4k3/8/8/8/8/8/8/R3K2R w - - 0 1", "h1h8"), "Rh8+");
+        assert_eq!(san("k7/8/K7/8/8/8/8/7R w - - 0 1", "h1h8"), "Rh8#");
+        assert_eq!(san("8/4P3/8/8/8/8/8/K6k w - - 0 1", "e7e8q"), "e8=Q");
+        assert_eq!(san("8/8/8/3pP3/8/8/8/K6k w - d6 0 1", "e5d6"), "exd6");
+
+        let parse = |fen: &str, san: &str| Board::from_fen(fen).unwrap().parse_san(san);
+        assert_eq!(parse(kiwipete, "0-0"), Pm::from_str("e1g1").ok());
+        assert_eq!(parse(kiwipete, "Nxf7!?"), Pm::from_str("e5f7").ok());
+        assert_eq!(
+            parse("8/4P3/8/8/8/8/8/K6k w - - 0 1", "e8Q"),
+            Pm::from_str("e7e8q").ok()
+        );
+        assert_eq!(parse(START_FEN, "e5"), None);
+
+        let mut board = Board::from_fen(START_FEN).unwrap();
+        assert_eq!(board.to_fen(), START_FEN);
+        for pm in ["e2e4", "c7c5", "g1f3"] {
+            board.play(Pm::from_str(pm).unwrap());
+        }
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+    }
+}
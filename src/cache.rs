@@ -0,0 +1,324 @@
+// This module contains AnalysisCache, which keeps the results of finished
+// analyses so that analyzing the same position with the same limits again
+// doesn't need the engine.
+
+use crate::analysis::{AnalysisResult, PvLine};
+use crate::engcmd::EngCmd;
+use crate::err::UziErr;
+use crate::guicmd::{Go, GuiCmd, Pos};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write as _};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+// A cache of analysis results, keyed by the engine, the position and the
+// limits of the search. Clones share the same entries, so one cache can be
+// used by several sessions, e.g. the workers of a batch.
+//
+// A cache opened on a file loads the entries saved in it, and appends every new
+// entry to it. The file holds one entry per paragraph, written as UCI commands:
+// the name of the engine, if it has one, the position, the go command, the
+// best move and an info with every line, e.g.
+//
+//   id name Stockfish 16
+//   position startpos moves e2e4
+//   go depth 20
+//   bestmove e7e5 ponder g1f3
+//   info depth 20 multipv 1 score cp 30 pv e7e5 g1f3
+#[derive(Clone, Debug, Default)]
+pub struct AnalysisCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<Key, AnalysisResult>,
+    path: Option<PathBuf>,
+}
+
+// Positions are keyed by their position command, i.e. the FEN and the moves
+// played from it, so the same position reached by other moves is a different
+// entry.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct Key {
+    engine: Option<String>,
+    pos: String,
+    limits: String,
+}
+
+impl Key {
+    fn new(engine: Option<&str>, pos: &Pos, limits: &Go) -> Self {
+        Self {
+            engine: engine.map(String::from),
+            pos: pos.to_string(),
+            limits: limits.to_string(),
+        }
+    }
+}
+
+impl AnalysisCache {
+    // Creates a cache that is only kept in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Opens the cache saved in the file, which is created by the first insert
+    // if it doesn't exist. Entries that can't be parsed are skipped, and one
+    // cut short by a crash while it was written is removed from the file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, UziErr> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match fs::read_to_string(&path) {
+            Ok(text) => drop_partial_paragraph(&path, &text)?
+                .split("\n\n")
+                .filter_map(|entry| parse_entry(entry).ok())
+                .collect(),
+            Err(err) if err.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            inner: Arc::new(Mutex::new(Inner {
+                entries,
+                path: Some(path),
+            })),
+        })
+    }
+
+    // Returns the result of analyzing the position with the limits on the
+    // engine with the given name, if it is in the cache.
+    pub fn get(&self, engine: Option<&str>, pos: &Pos, limits: &Go) -> Option<AnalysisResult> {
+        let key = Key::new(engine, pos, limits);
+        self.inner.lock().unwrap().entries.get(&key).cloned()
+    }
+
+    // Adds a result to the cache, and appends it to the file of the cache if
+    // it has one. A result that is already in the cache is replaced.
+    pub fn insert(
+        &self,
+        engine: Option<&str>,
+        pos: &Pos,
+        limits: &Go,
+        result: AnalysisResult,
+    ) -> Result<(), UziErr> {
+        let key = Key::new(engine, pos, limits);
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(ref path) = inner.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            file.write_all(format_entry(&key, &result).as_bytes())?;
+        }
+        inner.entries.insert(key, result);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Cuts the text of a file made of paragraphs after the blank line that ends
+// the last whole one, and the file with it, so that the next paragraph
+// appended to the file doesn't run into one that was cut short. Returns the
+// whole paragraphs.
+pub(crate) fn drop_partial_paragraph<'a>(path: &Path, text: &'a str) -> Result<&'a str, UziErr> {
+    let end = text.rfind("\n\n").map_or(0, |i| i + 2);
+    if end < text.len() {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(end as u64)?;
+    }
+    Ok(&text[..end])
+}
+
+// Formats an entry as a paragraph of the cache file, including the blank line
+// that ends it.
+fn format_entry(key: &Key, result: &AnalysisResult) -> String {
+    let mut entry = String::new();
+    if let Some(ref engine) = key.engine {
+        let _ = writeln!(entry, "{}", EngCmd::IdName(engine.clone()));
+    }
+    let best = EngCmd::BestMove {
+        best: result.best,
+        ponder: result.ponder,
+    };
+    let _ = writeln!(entry, "{}\n{}\n{}", key.pos, key.limits, best);
+    for line in &result.lines {
+        let _ = writeln!(entry, "{}", format_line(line));
+    }
+    entry.push('\n');
+    entry
+}
+
+// Formats a line as an info, which is parsed back by PvLine::from_info. The pv
+// goes last since it takes every move that follows it.
+fn format_line(line: &PvLine) -> String {
+    let mut info = String::from("info");
+    if let Some(depth) = line.depth {
+        let _ = write!(info, " depth {}", depth);
+    }
+    if let Some(sel_depth) = line.sel_depth {
+        let _ = write!(info, " seldepth {}", sel_depth);
+    }
+    let _ = write!(info, " multipv {}", line.rank);
+    if let Some(score) = line.score {
+        let _ = write!(info, " {}", score);
+    }
+    if let Some(nodes) = line.nodes {
+        let _ = write!(info, " nodes {}", nodes);
+    }
+    if let Some(time) = line.time {
+        let _ = write!(info, " time {}", time.as_millis());
+    }
+    info.push_str(" pv");
+    for pm in &line.moves {
+        let _ = write!(info, " {}", pm);
+    }
+    info
+}
+
+// Parses a paragraph of the cache file. The position and the go command are
+// formatted again, so that the key doesn't depend on the spacing in the file.
+fn parse_entry(entry: &str) -> Result<(Key, AnalysisResult), UziErr> {
+    let mut lines = entry
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+    let engine = match lines.peek().map(|line| EngCmd::from_str(line)) {
+        Some(Ok(EngCmd::IdName(name))) => {
+            lines.next();
+            Some(name)
+        }
+        _ => None,
+    };
+    let pos = match GuiCmd::from_str(lines.next().ok_or(UziErr::MissingCmd)?)? {
+        GuiCmd::Pos(pos) => pos,
+        _ => return Err(UziErr::What),
+    };
+    let limits = match GuiCmd::from_str(lines.next().ok_or(UziErr::MissingCmd)?)? {
+        GuiCmd::Go(limits) => limits,
+        _ => return Err(UziErr::What),
+    };
+    let (best, ponder) = match EngCmd::from_str(lines.next().ok_or(UziErr::MissingCmd)?)? {
+        EngCmd::BestMove { best, ponder } => (best, ponder),
+        _ => return Err(UziErr::What),
+    };
+    let mut pv_lines = Vec::new();
+    for line in lines {
+        match EngCmd::from_str(line)? {
+            EngCmd::Info(info) => pv_lines.extend(PvLine::from_info(&info)),
+            _ => return Err(UziErr::What),
+        }
+    }
+    let key = Key::new(engine.as_deref(), &pos, &limits);
+    let result = AnalysisResult {
+        best,
+        ponder,
+        lines: pv_lines,
+    };
+    Ok((key, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engcmd::Score;
+    use crate::pm::Pm;
+    use std::time::Duration;
+
+    fn pm(s: &str) -> Pm {
+        Pm::from_str(s).unwrap()
+    }
+
+    fn score(score: &str) -> Option<Score> {
+        match EngCmd::from_str(&format!("info {}", score)) {
+            Ok(EngCmd::Info(info)) => info.score(),
+            _ => None,
+        }
+    }
+
+    fn result() -> AnalysisResult {
+        AnalysisResult {
+            best: pm("e7e5"),
+            ponder: Some(pm("g1f3")),
+            lines: vec![
+                PvLine {
+                    rank: 1,
+                    depth: Some(20),
+                    sel_depth: Some(28),
+                    score: score("score cp 30"),
+                    moves: vec![pm("e7e5"), pm("g1f3")],
+                    nodes: Some(123456),
+                    time: Some(Duration::from_millis(250)),
+                },
+                PvLine {
+                    rank: 2,
+                    depth: Some(20),
+                    sel_depth: None,
+                    score: score("score mate -3 upperbound"),
+                    moves: vec![pm("g7g5")],
+                    nodes: None,
+                    time: None,
+                },
+            ],
+        }
+    }
+
+    fn limits(depth: u16) -> Go {
+        let mut go = Go::new();
+        go.set_depth(depth);
+        go
+    }
+
+    #[test]
+    fn cache_keys_by_engine_position_and_limits() {
+        let cache = AnalysisCache::new();
+        let mut pos = Pos::new();
+        pos.add_move(pm("e2e4"));
+        cache
+            .insert(Some("Engine"), &pos, &limits(20), result())
+            .unwrap();
+
+        assert_eq!(cache.get(Some("Engine"), &pos, &limits(20)), Some(result()));
+        assert_eq!(cache.get(None, &pos, &limits(20)), None);
+        assert_eq!(cache.get(Some("Engine"), &pos, &limits(19)), None);
+        assert_eq!(cache.get(Some("Engine"), &Pos::new(), &limits(20)), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn cache_persists_to_file() {
+        let path = std::env::temp_dir().join(format!("uzi-cache-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut pos = Pos::new();
+        pos.add_move(pm("e2e4"));
+
+        let cache = AnalysisCache::open(&path).unwrap();
+        assert!(cache.is_empty());
+        cache
+            .insert(Some("Engine"), &pos, &limits(20), result())
+            .unwrap();
+        cache
+            .insert(None, &Pos::new(), &limits(1), result())
+            .unwrap();
+
+        // A truncated entry at the end of the file is skipped.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"position startpos\ngo dep").unwrap();
+
+        let cache = AnalysisCache::open(&path).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(Some("Engine"), &pos, &limits(20)), Some(result()));
+        assert_eq!(cache.get(None, &Pos::new(), &limits(1)), Some(result()));
+
+        // An entry inserted after it survives.
+        cache.insert(None, &pos, &limits(1), result()).unwrap();
+        let cache = AnalysisCache::open(&path).unwrap();
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(None, &pos, &limits(1)), Some(result()));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,388 @@
+// This module contains Checkpoint, the file a Tournament writes its finished
+// games to, so that an interrupted tournament can be resumed without playing
+// them again. The file starts with a line that describes the tournament, e.g.
+//
+// tournament roundrobin players 3 rounds 2 openings 0
+//
+// followed by a paragraph per game, in the order the games finished:
+//
+// game 3 round 1 white 0 black 2 opening -
+// position startpos
+// timecontrol 300000 2000
+// move e2e4 time 2100 clock 299900 depth 18 score cp 35
+// move e7e5 time 1000 clock 301000
+// result 1-0 timeout
+// clocks 299900 0
+//
+// With time odds, the timecontrol line has the base and the increment of black
+// after the ones of white. Times are in milliseconds. Only the kind of error of
+// an engine that failed is kept, i.e. whether it crashed, stopped responding or
+// failed otherwise.
+
+use crate::cache::drop_partial_paragraph;
+use crate::engcmd::Info;
+use crate::engmatch::{
+    DrawReason, GameRecord, GameResult, MoveStats, Side, TimeControl, WinReason,
+};
+use crate::err::UziErr;
+use crate::guicmd::GuiCmd;
+use crate::pm::Pm;
+use crate::tournament::{Pairing, TournamentGame};
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    // The first line of the file.
+    header: String,
+}
+
+impl Checkpoint {
+    pub fn new(path: PathBuf, header: String) -> Self {
+        Self { path, header }
+    }
+
+    // Returns the games in the file with their index in the schedule, and
+    // creates the file if it doesn't exist. This fails with BadCheckpoint if
+    // the file is from another tournament. A paragraph that can't be parsed is
+    // skipped, and one the process was killed while writing is removed from
+    // the file, so that the games appended next aren't lost with it.
+    pub fn load(&self) -> Result<Vec<(usize, TournamentGame)>, UziErr> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                fs::write(&self.path, format!("{}\n\n", self.header))?;
+                return Ok(Vec::new());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let mut paragraphs = text.split("\n\n").filter(|p| !p.trim().is_empty());
+        if paragraphs.next().map(str::trim) != Some(self.header.as_str()) {
+            return Err(UziErr::BadCheckpoint);
+        }
+        // The header itself may be what was cut short.
+        if drop_partial_paragraph(&self.path, &text)?.is_empty() {
+            fs::write(&self.path, format!("{}\n\n", self.header))?;
+        }
+        Ok(paragraphs.filter_map(|p| parse_game(p).ok()).collect())
+    }
+
+    // Appends a finished game.
+    pub fn append(&self, index: usize, game: &TournamentGame) -> Result<(), UziErr> {
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        file.write_all(format_game(index, game).as_bytes())?;
+        Ok(())
+    }
+}
+
+// Formats a game as a paragraph, including the blank line that ends it.
+fn format_game(index: usize, game: &TournamentGame) -> String {
+    let (pairing, record) = (&game.pairing, &game.record);
+    let mut text = format!(
+        "game {} round {} white {} black {} opening ",
+        index, pairing.round, pairing.white, pairing.black
+    );
+    match pairing.opening {
+        Some(opening) => text.push_str(&opening.to_string()),
+        None => text.push('-'),
+    }
+    let (white_tc, black_tc) = (record.white_time_control, record.black_time_control);
+    let _ = write!(
+        text,
+        "\n{}\ntimecontrol {} {}",
+        record.start,
+        white_tc.base.as_millis(),
+        white_tc.inc.as_millis()
+    );
+    if black_tc != white_tc {
+        let _ = write!(
+            text,
+            " {} {}",
+            black_tc.base.as_millis(),
+            black_tc.inc.as_millis()
+        );
+    }
+    text.push('\n');
+    for (pm, stats) in record.moves.iter().zip(&record.stats) {
+        let _ = write!(
+            text,
+            "move {} time {} clock {}",
+            pm,
+            stats.time.as_millis(),
+            stats.clock.as_millis()
+        );
+        if let Some(depth) = stats.depth {
+            let _ = write!(text, " depth {}", depth);
+        }
+        // The score goes last since it has a variable number of words.
+        if let Some(score) = stats.score {
+            let _ = write!(text, " {}", score);
+        }
+        text.push('\n');
+    }
+    let _ = write!(
+        text,
+        "result {} {}\nclocks {} {}\n\n",
+        record.result.as_str(),
+        format_reason(&record.result),
+        record.white_time.as_millis(),
+        record.black_time.as_millis()
+    );
+    text
+}
+
+fn format_reason(result: &GameResult) -> String {
+    let reason = match result {
+        GameResult::Win(_, reason) => match reason {
+            WinReason::Checkmate => "checkmate",
+            WinReason::Timeout => "timeout",
+            WinReason::IllegalMove(pm) => return format!("illegal {}", pm),
+            WinReason::EngineFailed(err) => match err.root() {
+                UziErr::Crashed(_) | UziErr::EngineExited => "crashed",
+                UziErr::EngineHung | UziErr::Timeout => "hung",
+                _ => "failed",
+            },
+            WinReason::Resignation => "resignation",
+            WinReason::Adjudicator => "adjudicator",
+        },
+        GameResult::Draw(reason) => match reason {
+            DrawReason::Stalemate => "stalemate",
+            DrawReason::FiftyMoves => "fiftymoves",
+            DrawReason::Repetition => "repetition",
+            DrawReason::InsufficientMaterial => "material",
+            DrawReason::MaxPlies => "maxplies",
+            DrawReason::Adjudication => "adjudication",
+            DrawReason::Adjudicator => "adjudicator",
+        },
+    };
+    reason.to_string()
+}
+
+fn parse_game(paragraph: &str) -> Result<(usize, TournamentGame), UziErr> {
+    let mut lines = paragraph.lines().filter(|line| !line.trim().is_empty());
+    let mut next = || lines.next().ok_or(UziErr::BadCheckpoint);
+
+    let game = words(next()?, "game")?;
+    let opening: String = field(&game, "opening")?;
+    let pairing = Pairing {
+        round: field(&game, "round")?,
+        white: field(&game, "white")?,
+        black: field(&game, "black")?,
+        opening: match opening.as_str() {
+            "-" => None,
+            opening => Some(parse(Some(&opening))?),
+        },
+    };
+    let start = match GuiCmd::from_str(next()?)? {
+        GuiCmd::Pos(pos) => pos,
+        _ => return Err(UziErr::BadCheckpoint),
+    };
+    let tc = words(next()?, "timecontrol")?;
+    let white_time_control = TimeControl::new(millis(tc.first())?, millis(tc.get(1))?);
+    let black_time_control = match tc.len() {
+        2 => white_time_control,
+        _ => TimeControl::new(millis(tc.get(2))?, millis(tc.get(3))?),
+    };
+
+    let (mut moves, mut stats) = (Vec::new(), Vec::new());
+    let result = loop {
+        let line = next()?;
+        if let Ok(words) = words(line, "move") {
+            let (pm, move_stats) = parse_move(&words)?;
+            moves.push(pm);
+            stats.push(move_stats);
+        } else {
+            break parse_result(&words(line, "result")?)?;
+        }
+    };
+    let clocks = words(next()?, "clocks")?;
+    let record = GameRecord {
+        start,
+        white_time_control,
+        black_time_control,
+        moves,
+        stats,
+        result,
+        white_time: millis(clocks.first())?,
+        black_time: millis(clocks.get(1))?,
+    };
+    Ok((parse(game.first())?, TournamentGame { pairing, record }))
+}
+
+// Parses the words after "move".
+fn parse_move(words: &[&str]) -> Result<(Pm, MoveStats), UziErr> {
+    let pm = parse(words.first())?;
+    let (fields, score) = match words.iter().position(|word| *word == "score") {
+        Some(at) => words.split_at(at),
+        None => (words, &[][..]),
+    };
+    let mut info = vec!["info"];
+    info.extend(score);
+    let stats = MoveStats {
+        score: Info::try_from(info.as_slice())?.score(),
+        depth: field(fields, "depth").ok(),
+        time: millis(after(fields, "time"))?,
+        clock: millis(after(fields, "clock"))?,
+    };
+    Ok((pm, stats))
+}
+
+// Parses the words after "result".
+fn parse_result(words: &[&str]) -> Result<GameResult, UziErr> {
+    let winner = match words.first() {
+        Some(&"1-0") => Some(Side::White),
+        Some(&"0-1") => Some(Side::Black),
+        Some(&"1/2-1/2") => None,
+        _ => return Err(UziErr::BadCheckpoint),
+    };
+    let reason = words.get(1).copied().ok_or(UziErr::BadCheckpoint)?;
+    let result = match winner {
+        Some(winner) => GameResult::Win(
+            winner,
+            match reason {
+                "checkmate" => WinReason::Checkmate,
+                "timeout" => WinReason::Timeout,
+                "illegal" => WinReason::IllegalMove(parse(words.get(2))?),
+                "crashed" => WinReason::EngineFailed(UziErr::EngineExited),
+                "hung" => WinReason::EngineFailed(UziErr::EngineHung),
+                "failed" => WinReason::EngineFailed(UziErr::What),
+                "resignation" => WinReason::Resignation,
+                "adjudicator" => WinReason::Adjudicator,
+                _ => return Err(UziErr::BadCheckpoint),
+            },
+        ),
+        None => GameResult::Draw(match reason {
+            "stalemate" => DrawReason::Stalemate,
+            "fiftymoves" => DrawReason::FiftyMoves,
+            "repetition" => DrawReason::Repetition,
+            "material" => DrawReason::InsufficientMaterial,
+            "maxplies" => DrawReason::MaxPlies,
+            "adjudication" => DrawReason::Adjudication,
+            "adjudicator" => DrawReason::Adjudicator,
+            _ => return Err(UziErr::BadCheckpoint),
+        }),
+    };
+    Ok(result)
+}
+
+// Returns the words of the line after the first one, which has to be the key.
+fn words<'a>(line: &'a str, key: &str) -> Result<Vec<&'a str>, UziErr> {
+    let mut words = line.split_whitespace();
+    match words.next() {
+        Some(first) if first == key => Ok(words.collect()),
+        _ => Err(UziErr::BadCheckpoint),
+    }
+}
+
+// Returns the word that follows the key.
+fn after<'a, 'b>(words: &'b [&'a str], key: &str) -> Option<&'b &'a str> {
+    let at = words.iter().position(|word| *word == key)?;
+    words.get(at + 1)
+}
+
+fn field<T: FromStr>(words: &[&str], key: &str) -> Result<T, UziErr> {
+    parse(after(words, key))
+}
+
+fn parse<T: FromStr>(word: Option<&&str>) -> Result<T, UziErr> {
+    word.and_then(|word| word.parse().ok())
+        .ok_or(UziErr::BadCheckpoint)
+}
+
+fn millis(word: Option<&&str>) -> Result<Duration, UziErr> {
+    parse(word).map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engcmd::Score;
+    use crate::guicmd::Pos;
+
+    fn score(words: &[&str]) -> Option<Score> {
+        let mut info = vec!["info", "score"];
+        info.extend(words);
+        Info::try_from(info.as_slice()).unwrap().score()
+    }
+
+    fn game(result: GameResult) -> TournamentGame {
+        let stats = |score, depth, time| MoveStats {
+            score,
+            depth,
+            time: Duration::from_millis(time),
+            clock: Duration::from_millis(60_000 - time),
+        };
+        let mut start = Pos::with_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1");
+        start.add_move(Pm::from_str("e1c1").unwrap());
+        TournamentGame {
+            pairing: Pairing {
+                round: 1,
+                white: 2,
+                black: 0,
+                opening: Some(4),
+            },
+            record: GameRecord {
+                start,
+                white_time_control: TimeControl::new(
+                    Duration::from_secs(60),
+                    Duration::from_millis(500),
+                ),
+                // Black has time odds.
+                black_time_control: TimeControl::new(
+                    Duration::from_secs(30),
+                    Duration::from_millis(250),
+                ),
+                moves: vec![Pm::from_str("e8e7").unwrap(), Pm::from_str("d1d7").unwrap()],
+                stats: vec![
+                    stats(score(&["mate", "-3", "upperbound"]), Some(12), 1500),
+                    stats(None, None, 0),
+                ],
+                result,
+                white_time: Duration::from_millis(59_500),
+                black_time: Duration::ZERO,
+            },
+        }
+    }
+
+    #[test]
+    fn checkpoint_game_round_trip() {
+        let illegal = GameResult::Win(Side::White, WinReason::IllegalMove(Pm::Null));
+        let illegal_game = game(illegal);
+        let text = format_game(7, &illegal_game);
+        assert_eq!(parse_game(&text), Ok((7, illegal_game)));
+
+        // The error of a crash is replaced by one that tells the same.
+        let crash = UziErr::Crashed(Default::default());
+        let crashed = GameResult::Win(Side::Black, WinReason::EngineFailed(crash));
+        let (_, parsed) = parse_game(&format_game(0, &game(crashed.clone()))).unwrap();
+        assert_eq!(parsed.record.result.reason(), crashed.reason());
+    }
+
+    #[test]
+    fn checkpoint_load() {
+        let path = std::env::temp_dir().join(format!("uzi-checkpoint-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let checkpoint = Checkpoint::new(path.clone(), "tournament a".to_string());
+        assert_eq!(checkpoint.load(), Ok(Vec::new()));
+        let draw = game(GameResult::Draw(DrawReason::Repetition));
+        checkpoint.append(3, &draw).unwrap();
+        // A game that was being written when the process was killed.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"game 4 round 1 white 2").unwrap();
+
+        assert_eq!(checkpoint.load(), Ok(vec![(3, draw.clone())]));
+
+        // The game resumed after it survives a reload.
+        let win = game(GameResult::Win(Side::White, WinReason::Checkmate));
+        checkpoint.append(4, &win).unwrap();
+        assert_eq!(checkpoint.load(), Ok(vec![(3, draw), (4, win)]));
+        let other = Checkpoint::new(path.clone(), "tournament b".to_string());
+        assert_eq!(other.load(), Err(UziErr::BadCheckpoint));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,1311 @@
+// This module contains Engine, the client used by a GUI (or any other program)
+// to drive a UCI chess engine.
+
+use crate::diag::{CompilerInfo, DiagCmd, EvalReport};
+use crate::engcmd::{EngCmd, Info};
+use crate::engproc::{Launcher, Spawner};
+use crate::err::UziErr;
+use crate::guicmd::{Go, GuiCmd, Pos};
+use crate::infopool::InfoPool;
+use crate::latency::{Latency, LatencyTracker};
+use crate::mux::{SessionMux, SessionObserver, OBSERVER_CAPACITY};
+use crate::opt::{HasOpt, SetOpt, Variant};
+use crate::pm::Pm;
+use crate::search::{SearchHandle, SearchState};
+use crate::trace::{self, Direction};
+use crate::traffic::TrafficStats;
+use crate::transcript::Transcript;
+use crate::transport::Transport;
+use crate::watchdog::{Awaited, RespawnPolicy, Watchdog, WatchdogEvent};
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::time;
+
+// How long to wait for readyok when the client syncs on its own, e.g. after an
+// automatic ucinewgame.
+pub(crate) const SYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How long best_move lets the engine search when the caller sets no limit.
+pub(crate) const BEST_MOVE_TIME: Duration = Duration::from_secs(1);
+
+// The default number of lines kept in the transcript.
+const TRANSCRIPT_LEN: usize = 64;
+
+// A client connected to a chess engine.
+#[derive(Debug)]
+pub struct Engine {
+    transport: Box<dyn Transport>,
+
+    // Used to replace the engine when it hangs. Engines created from an existing
+    // transport cannot be respawned.
+    spawner: Option<Spawner>,
+
+    watchdog: Option<Watchdog>,
+
+    // The engine identity and options, known after the uci handshake.
+    name: Option<String>,
+    author: Option<String>,
+    options: Vec<HasOpt>,
+
+    // Commands received while waiting for a specific reply, e.g. info lines that
+    // arrive while synchronizing with isready. These are returned by recv
+    // before reading anything new from the engine.
+    pending: VecDeque<EngCmd>,
+
+    // Tracks go, stop and ponderhit so that invalid sequences are rejected and
+    // every search consumes exactly one bestmove.
+    search: SearchState,
+
+    // Used to send ucinewgame automatically when a position from a different
+    // game is set. game_started is true after ucinewgame until the first
+    // position of the game.
+    auto_new_game: bool,
+    last_pos: Option<Pos>,
+    game_started: bool,
+
+    // What is needed to set up a restarted engine like the original one: the
+    // handshake timeout, if the handshake was done, and the option values that
+    // were set.
+    uci_timeout: Option<Duration>,
+    applied: Vec<SetOpt>,
+    is_restarting: bool,
+
+    // If set, engines that crash are respawned on the next command, and
+    // respawns after crashes or hangs are limited, see RespawnPolicy.
+    respawn_policy: Option<RespawnPolicy>,
+    respawns: u32,
+    last_respawn: Option<Instant>,
+    crashed: bool,
+
+    // If set, searches deliver at most one info update per interval for each
+    // multipv rank, see InfoThrottle.
+    info_interval: Option<Duration>,
+
+    // The infos handed back by the consumer, which the next infos are parsed
+    // into.
+    info_pool: InfoPool,
+
+    // The last lines exchanged with the engine, which are attached to the
+    // errors returned by the client.
+    transcript: Transcript,
+
+    // Measures how long the engine takes to answer, see latency.
+    latency: LatencyTracker,
+
+    // Hands the commands received from the engine to its observers.
+    mux: SessionMux,
+
+    // Counts the lines exchanged with the engine.
+    traffic: TrafficStats,
+
+    // Forwards the lines that are not UCI commands, e.g. banners.
+    raw_tx: broadcast::Sender<String>,
+
+    // The lines of the reply to a diagnostic command, while one is pending.
+    diag_reply: Option<Vec<String>>,
+}
+
+impl Engine {
+    // Creates the client for an engine reached through the transport, e.g. an
+    // EngineProcess.
+    pub fn new<T: Transport + 'static>(transport: T) -> Self {
+        Self::from_transport(Box::new(transport))
+    }
+
+    fn from_transport(transport: Box<dyn Transport>) -> Self {
+        Self {
+            transport,
+            spawner: None,
+            watchdog: None,
+            name: None,
+            author: None,
+            options: Vec::new(),
+            pending: VecDeque::new(),
+            search: SearchState::Idle,
+            auto_new_game: true,
+            last_pos: None,
+            game_started: false,
+            uci_timeout: None,
+            applied: Vec::new(),
+            is_restarting: false,
+            respawn_policy: None,
+            respawns: 0,
+            last_respawn: None,
+            crashed: false,
+            info_interval: None,
+            info_pool: InfoPool::default(),
+            transcript: Transcript::new(TRANSCRIPT_LEN),
+            latency: LatencyTracker::default(),
+            mux: SessionMux::new(OBSERVER_CAPACITY),
+            traffic: TrafficStats::new(),
+            raw_tx: broadcast::channel(64).0,
+            diag_reply: None,
+        }
+    }
+
+    // Spawns the engine binary at the given path.
+    pub fn spawn<S: AsRef<OsStr>>(program: S) -> Result<Self, UziErr> {
+        Self::from_spawner(Spawner::from_program(program))
+    }
+
+    // Launches the engine with the given parameters, e.g. arguments and a
+    // working directory.
+    pub fn launch(launcher: Launcher) -> Result<Self, UziErr> {
+        Self::from_spawner(Spawner::from_launcher(launcher))
+    }
+
+    // Creates the engine with a process from the spawner, which is also used to
+    // replace the process if it has to be restarted.
+    pub fn from_spawner(spawner: Spawner) -> Result<Self, UziErr> {
+        let mut eng = Self::from_transport(spawner.spawn()?);
+        eng.spawner = Some(spawner);
+        Ok(eng)
+    }
+
+    // Sets the watchdog that kills (and possibly respawns) the engine when it
+    // misses a deadline.
+    pub fn set_watchdog(&mut self, watchdog: Watchdog) -> &mut Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    // Sets the policy for respawning the engine. With a policy, an engine that
+    // crashed is respawned before the next command is sent, and respawns after
+    // a crash or a hang are delayed and limited by the policy. This needs an
+    // engine created from a spawner.
+    pub fn set_respawn_policy(&mut self, policy: RespawnPolicy) -> &mut Self {
+        self.respawn_policy = Some(policy);
+        self
+    }
+
+    // Enables or disables sending ucinewgame automatically when a position from
+    // a different game is set. Enabled by default.
+    pub fn set_auto_new_game(&mut self, is_enabled: bool) -> &mut Self {
+        self.auto_new_game = is_enabled;
+        self
+    }
+
+    // Limits the info updates of searches to at most one per interval for each
+    // multipv rank. Updates that complete a depth and the last update of a
+    // search are always delivered.
+    pub fn set_info_throttle(&mut self, interval: Duration) -> &mut Self {
+        self.info_interval = Some(interval);
+        self
+    }
+
+    pub(crate) fn info_interval(&self) -> Option<Duration> {
+        self.info_interval
+    }
+
+    // Keeps up to capacity of the infos handed back with recycle_info, and
+    // parses the next infos into them, so that a consumer of thousands of
+    // updates per search doesn't allocate a pv for each of them. 0, the
+    // default, disables recycling.
+    pub fn set_info_pool(&mut self, capacity: usize) -> &mut Self {
+        self.info_pool.set_capacity(capacity);
+        self
+    }
+
+    // Hands back an info the consumer is done with, see set_info_pool.
+    pub fn recycle_info(&mut self, info: Info) {
+        self.info_pool.recycle(info);
+    }
+
+    // Sets the number of lines kept in the transcript, 64 by default. With 0 no
+    // transcript is kept and errors are returned without one.
+    pub fn set_transcript_len(&mut self, len: usize) -> &mut Self {
+        self.transcript.set_len(len);
+        self
+    }
+
+    // Returns the last lines exchanged with the engine, one per line. Lines
+    // sent to the engine start with "> " and lines received with "< ".
+    pub fn dump_transcript(&self) -> String {
+        self.transcript.lines().join("\n")
+    }
+
+    // Returns the latencies of the searches and isready round trips of the
+    // engine, e.g. the time from go to the first info, or from stop to
+    // bestmove. The latencies of a restarted engine add up with those of the
+    // previous one.
+    pub fn latency(&self) -> &Latency {
+        self.latency.latency()
+    }
+
+    pub fn reset_latency(&mut self) {
+        self.latency.reset();
+    }
+
+    // The name of the engine, as sent during the handshake.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    // The author of the engine, as sent during the handshake.
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    // The options declared by the engine during the handshake.
+    pub fn options(&self) -> &[HasOpt] {
+        &self.options
+    }
+
+    // The variants declared by the engine through UCI_Variant. The list is
+    // empty when the engine only plays chess.
+    pub fn variants(&self) -> Vec<Variant> {
+        self.options
+            .iter()
+            .find_map(HasOpt::variants)
+            .unwrap_or_default()
+    }
+
+    // Returns true from go until the bestmove of the search has been received.
+    pub fn is_searching(&self) -> bool {
+        !self.search.is_idle()
+    }
+
+    // Returns a receiver for the lines the engine writes to stderr. A restarted
+    // engine is a new process, so subscribers have to subscribe again.
+    pub fn subscribe_stderr(&self) -> broadcast::Receiver<String> {
+        self.transport.subscribe_stderr()
+    }
+
+    // Returns the number of commands of each kind and the bytes exchanged
+    // with the engine, including the ones of previous engines if it was
+    // restarted.
+    pub fn traffic(&self) -> &TrafficStats {
+        &self.traffic
+    }
+
+    pub fn reset_traffic(&mut self) {
+        self.traffic = TrafficStats::new();
+    }
+
+    pub(crate) fn on_throttled(&mut self, updates: u64) {
+        self.traffic.on_throttled(updates);
+    }
+
+    // Returns an observer that gets every command the engine sends from now
+    // on, including the ones the caller reads itself, e.g. for a panel or a
+    // logger. Observers don't affect the caller, and one that falls behind by
+    // more than the observer capacity misses the oldest commands.
+    pub fn observe(&self) -> SessionObserver {
+        self.mux.observe()
+    }
+
+    // Sets how many commands are kept for an observer that falls behind, 256
+    // by default. The observers created before are closed.
+    pub fn set_observer_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.mux = SessionMux::new(capacity);
+        self
+    }
+
+    // Returns a receiver for the lines the engine writes to stdout that are not
+    // UCI commands, e.g. the version banner many engines print on startup.
+    // These lines are otherwise skipped.
+    pub fn subscribe_raw(&self) -> broadcast::Receiver<String> {
+        self.raw_tx.subscribe()
+    }
+
+    // Performs the handshake: sends uci and collects the engine identity and
+    // options until uciok is received. Lines that are not UCI commands, e.g.
+    // banners or license text, are skipped and forwarded to subscribe_raw.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "handshake", level = "debug", skip_all)
+    )]
+    pub async fn uci(&mut self, timeout: Duration) -> Result<(), UziErr> {
+        self.send(&GuiCmd::Uci).await?;
+        self.uci_timeout = Some(timeout);
+        self.options.clear();
+        let wait_uciok = async {
+            loop {
+                match self.read_cmd().await? {
+                    EngCmd::UciOk => return Ok(()),
+                    EngCmd::IdName(name) => self.name = Some(name),
+                    EngCmd::IdAuthor(author) => self.author = Some(author),
+                    EngCmd::HasOpt(opt) => self.options.push(opt),
+                    cmd => trace::client_ignored(self.name(), &cmd, "unexpected in the handshake"),
+                }
+            }
+        };
+        match time::timeout(timeout, wait_uciok).await {
+            Ok(result) => result,
+            Err(_) => Err(self.attach(UziErr::Timeout)),
+        }
+    }
+
+    // Sends a command to the engine. Returns UziErr::BadSearchState if the
+    // command is not valid while the engine is searching, e.g. a second go
+    // before the bestmove of the first one has been received.
+    pub async fn send(&mut self, cmd: &GuiCmd) -> Result<(), UziErr> {
+        if self.crashed && !self.is_restarting {
+            self.crashed = false;
+            let result = Box::pin(self.respawn()).await;
+            if let Some(ref watchdog) = self.watchdog {
+                watchdog.report(match result {
+                    Ok(()) => WatchdogEvent::Respawned,
+                    Err(ref err) => WatchdogEvent::RespawnFailed(err.clone()),
+                });
+            }
+            result?;
+        }
+        let search = self.search.on_send(cmd).map_err(|err| self.attach(err))?;
+        self.write_line(&cmd.to_string()).await?;
+        self.latency.sent(cmd, Instant::now());
+        self.search = search;
+        Ok(())
+    }
+
+    // Sends eval, one of the diagnostic commands of Stockfish, and returns the
+    // static evaluation of the current position. The engine must finish the
+    // reply and answer the isready that follows it within the timeout.
+    pub async fn eval(&mut self, timeout: Duration) -> Result<EvalReport, UziErr> {
+        let reply = self.diag(DiagCmd::Eval, timeout).await?;
+        EvalReport::from_str(&reply).map_err(|err| self.attach(err))
+    }
+
+    // Sends compiler, one of the diagnostic commands of Stockfish, and returns
+    // how the engine was built.
+    pub async fn compiler(&mut self, timeout: Duration) -> Result<CompilerInfo, UziErr> {
+        let reply = self.diag(DiagCmd::Compiler, timeout).await?;
+        CompilerInfo::from_str(&reply).map_err(|err| self.attach(err))
+    }
+
+    // Sends flip, one of the diagnostic commands of Stockfish, which mirrors
+    // the current position and swaps the colors.
+    pub async fn flip(&mut self) -> Result<(), UziErr> {
+        if !self.search.is_idle() {
+            return Err(self.attach(UziErr::BadSearchState));
+        }
+        self.write_line(DiagCmd::Flip.as_str()).await
+    }
+
+    // Returns the next command from the engine, starting with the commands that
+    // were buffered while waiting for other replies. A bestmove that does not
+    // belong to any search is dropped.
+    pub async fn recv(&mut self) -> Result<EngCmd, UziErr> {
+        loop {
+            let cmd = match self.pending.pop_front() {
+                Some(cmd) => cmd,
+                None => self.read_cmd().await?,
+            };
+            let (search, is_expected) = self.search.on_recv(&cmd);
+            self.search = search;
+            if is_expected {
+                return Ok(cmd);
+            }
+            trace::client_ignored(self.name(), &cmd, "not part of a search");
+        }
+    }
+
+    // Sets an option by name, e.g. set_option("Threads", 4).
+    pub async fn set_option<V: Display>(&mut self, name: &str, value: V) -> Result<(), UziErr> {
+        self.set_opt(SetOpt::Custom {
+            name: name.into(),
+            value: Some(value.to_string()),
+        })
+        .await
+    }
+
+    // Presses a button option, e.g. "Clear Hash".
+    pub async fn press_button(&mut self, name: &str) -> Result<(), UziErr> {
+        self.set_opt(SetOpt::Custom {
+            name: name.into(),
+            value: None,
+        })
+        .await
+    }
+
+    // Sets one of the options defined by the UCI standard.
+    pub async fn set_opt(&mut self, opt: SetOpt) -> Result<(), UziErr> {
+        self.send(&GuiCmd::SetOpt(opt.clone())).await?;
+        match opt {
+            // Buttons are actions rather than values, so they are not applied
+            // again after a restart.
+            SetOpt::Custom { value: None, .. } => (),
+            // Each position value is for a different position.
+            SetOpt::SetPosVal(_) => self.applied.push(opt),
+            _ => {
+                self.applied
+                    .retain(|applied| !applied.name().eq_ignore_ascii_case(opt.name()));
+                self.applied.push(opt);
+            }
+        }
+        Ok(())
+    }
+
+    // Tells the engine to play a variant through UCI_Variant. Fails with
+    // UnknownOpt if the engine declared no variants, and with BadOptValue if
+    // the variant is not one of them.
+    pub async fn set_variant(&mut self, variant: Variant) -> Result<(), UziErr> {
+        let variants = self.variants();
+        if variants.is_empty() {
+            return Err(UziErr::UnknownOpt);
+        }
+        if !variants.contains(&variant) {
+            return Err(UziErr::BadOptValue);
+        }
+        self.set_opt(SetOpt::Variant(variant)).await
+    }
+
+    // Tells the engine to switch debug mode on or off.
+    pub async fn debug(&mut self, is_enabled: bool) -> Result<(), UziErr> {
+        self.send(&GuiCmd::Debug(is_enabled)).await
+    }
+
+    // Tells the engine that the next position is from a different game.
+    pub async fn new_game(&mut self) -> Result<(), UziErr> {
+        self.send(&GuiCmd::NewGame).await?;
+        self.last_pos = None;
+        self.game_started = true;
+        Ok(())
+    }
+
+    // Sets up the position to search. Unless disabled with set_auto_new_game,
+    // ucinewgame is sent first, followed by isready, if the position is not
+    // from the same game as the previous one.
+    pub async fn position(&mut self, pos: &Pos) -> Result<(), UziErr> {
+        let is_new_game = match self.last_pos {
+            Some(ref last_pos) => !pos.is_same_game(last_pos),
+            None => !self.game_started,
+        };
+        if self.auto_new_game && is_new_game {
+            self.new_game().await?;
+            self.sync(SYNC_TIMEOUT).await?;
+        }
+        self.send(&GuiCmd::Pos(pos.clone())).await?;
+        self.last_pos = Some(pos.clone());
+        self.game_started = false;
+        Ok(())
+    }
+
+    // Starts searching the current position. The returned handle delivers the
+    // info updates of the search and its result. If the previous search was
+    // stopped but its bestmove has not been read yet, it is read and dropped
+    // first.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "go", level = "debug", skip_all, fields(engine = self.name(), %go))
+    )]
+    pub async fn go(&mut self, go: &Go) -> Result<SearchHandle<'_>, UziErr> {
+        if self.search.is_stopping() {
+            self.wait_best_move().await?;
+        }
+        self.send(&GuiCmd::Go(go.clone())).await?;
+        Ok(SearchHandle::new(self))
+    }
+
+    // Like go, but stops the search when the deadline passes, for engines that
+    // do not respect the time limits in go. If the engine does not send
+    // bestmove shortly after it is stopped, the search fails like it does when
+    // the watchdog bestmove deadline passes.
+    pub async fn go_with_deadline(
+        &mut self,
+        go: &Go,
+        deadline: Duration,
+    ) -> Result<SearchHandle<'_>, UziErr> {
+        let mut search = self.go(go).await?;
+        search.set_deadline(deadline);
+        Ok(search)
+    }
+
+    // Tells the engine to stop searching as soon as possible. The engine still
+    // replies with bestmove.
+    pub async fn stop(&mut self) -> Result<(), UziErr> {
+        self.send(&GuiCmd::Stop).await
+    }
+
+    // Tells the engine that the opponent played the expected move, so the
+    // ponder search becomes a normal search.
+    pub async fn ponderhit(&mut self) -> Result<(), UziErr> {
+        self.send(&GuiCmd::Ponderhit).await
+    }
+
+    // Tells the engine to quit.
+    pub async fn quit(&mut self) -> Result<(), UziErr> {
+        self.send(&GuiCmd::Quit).await
+    }
+
+    // Sends quit and waits for the engine to exit. If it does not exit within
+    // the grace period, it is killed. Returns how the engine was shut down.
+    pub async fn shutdown(&mut self, grace_period: Duration) -> Result<Shutdown, UziErr> {
+        // The engine may already be gone, in which case waiting returns right
+        // away.
+        let _ = self.quit().await;
+        if let Ok(Ok(())) = time::timeout(grace_period, self.transport.wait_exit()).await {
+            return Ok(Shutdown::Quit);
+        }
+        match self.transport.kill().await {
+            Ok(()) => Ok(Shutdown::Killed),
+            Err(err) => Err(self.attach(err)),
+        }
+    }
+
+    // Sends isready and waits for readyok. Any other command received in the
+    // meantime, e.g. info lines from a running search, is buffered and later
+    // returned by recv. Returns UziErr::Timeout if the engine does not answer
+    // within the timeout. If a watchdog is set, its deadline also applies and the
+    // engine is killed if it does not answer in time.
+    pub async fn sync(&mut self, timeout: Duration) -> Result<(), UziErr> {
+        self.send(&GuiCmd::IsReady).await?;
+        let timeout = match self.watchdog {
+            Some(ref watchdog) => timeout.min(watchdog.ready_timeout),
+            None => timeout,
+        };
+        let wait_ready = async {
+            loop {
+                match self.read_cmd().await? {
+                    EngCmd::ReadyOk => return Ok(()),
+                    cmd => self.pending.push_back(cmd),
+                }
+            }
+        };
+        match time::timeout(timeout, wait_ready).await {
+            Ok(result) => result,
+            Err(_) => Err(self.on_hang(Awaited::ReadyOk, timeout).await),
+        }
+    }
+
+    // Searches the position and returns the best move, for callers that only
+    // need a move. The engine searches for BEST_MOVE_TIME unless the limits say
+    // otherwise. The engine may reply with the null move if there are no legal
+    // moves.
+    pub async fn best_move(&mut self, pos: &Pos, limits: &Go) -> Result<Pm, UziErr> {
+        self.position(pos).await?;
+        let (best, _) = self.go(&best_move_limits(limits)).await?.wait().await?;
+        Ok(best)
+    }
+
+    // Waits for the bestmove of a search that has been started, returning the
+    // best move and the optional ponder move. Info lines are skipped. If a
+    // watchdog with a bestmove deadline is set, the engine is killed if it does
+    // not reply in time.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "search", level = "debug", skip_all, fields(engine = self.name()))
+    )]
+    pub async fn wait_best_move(&mut self) -> Result<(Pm, Option<Pm>), UziErr> {
+        if self.search.is_idle() {
+            return Err(self.attach(UziErr::BadSearchState));
+        }
+        let started = Instant::now();
+        loop {
+            if let EngCmd::BestMove { best, ponder } = self.recv_search(started).await? {
+                return Ok((best, ponder));
+            }
+        }
+    }
+
+    // Receives the next command of a search started at the given time. If a
+    // watchdog with a bestmove deadline is set, the engine is killed when the
+    // deadline passes.
+    pub(crate) async fn recv_search(&mut self, started: Instant) -> Result<EngCmd, UziErr> {
+        let Some(deadline) = self.watchdog.as_ref().and_then(|w| w.best_move_timeout) else {
+            return self.recv().await;
+        };
+        let remaining = deadline.saturating_sub(started.elapsed());
+        match time::timeout(remaining, self.recv()).await {
+            Ok(result) => result,
+            Err(_) => Err(self.on_hang(Awaited::BestMove, deadline).await),
+        }
+    }
+
+    // Handles an engine that missed a deadline, returning the error to report
+    // to the caller. Without a watchdog this is just a timeout, otherwise the
+    // engine is killed and respawned if configured.
+    pub(crate) async fn on_hang(&mut self, awaited: Awaited, deadline: Duration) -> UziErr {
+        let Some(watchdog) = self.watchdog.clone() else {
+            return self.attach(UziErr::Timeout);
+        };
+
+        // This fails if the process is already gone, which is what we want.
+        let _ = self.transport.kill().await;
+        watchdog.report(WatchdogEvent::Killed { awaited, deadline });
+
+        // An engine that hangs while it is being restarted is not respawned
+        // again, otherwise a broken engine would be restarted forever.
+        if watchdog.respawn && self.spawner.is_some() && !self.is_restarting {
+            match Box::pin(self.respawn()).await {
+                Ok(()) => watchdog.report(WatchdogEvent::Respawned),
+                Err(err) => watchdog.report(WatchdogEvent::RespawnFailed(err)),
+            }
+        }
+
+        self.attach(UziErr::EngineHung)
+    }
+
+    // Restarts the engine after it crashed or hung, as allowed by the respawn
+    // policy.
+    async fn respawn(&mut self) -> Result<(), UziErr> {
+        if let Some(policy) = self.respawn_policy {
+            let now = Instant::now();
+            if let Some(last) = self.last_respawn {
+                if now.duration_since(last) >= policy.reset_window {
+                    self.respawns = 0;
+                }
+            }
+            if self.respawns >= policy.max_restarts {
+                return Err(self.attach(UziErr::RespawnLimit));
+            }
+            time::sleep(policy.delay(self.respawns)).await;
+            self.respawns += 1;
+            self.last_respawn = Some(Instant::now());
+        }
+        let result = self.restart().await;
+        if result.is_err() {
+            // Try again on the next command.
+            self.on_crash();
+        }
+        result
+    }
+
+    // Marks the engine to be respawned before the next command, if there is a
+    // respawn policy.
+    fn on_crash(&mut self) {
+        if self.respawn_policy.is_some() && self.spawner.is_some() && !self.is_restarting {
+            self.crashed = true;
+        }
+    }
+
+    // Replaces the engine process with a new one from the spawner. The new
+    // engine goes through the handshake if the old one did, and the option
+    // values that were set are applied again. Returns UziErr::CannotRestart if
+    // the engine was not created from a spawner.
+    pub async fn restart(&mut self) -> Result<(), UziErr> {
+        let Some(ref spawner) = self.spawner else {
+            return Err(self.attach(UziErr::CannotRestart));
+        };
+        let transport = spawner.spawn().map_err(|err| self.attach(err))?;
+
+        // This fails if the process is already gone, which is what we want.
+        let _ = self.transport.kill().await;
+        self.transport = transport;
+        self.pending.clear();
+        self.search = SearchState::Idle;
+        self.latency.abort();
+        self.last_pos = None;
+        self.game_started = false;
+        self.crashed = false;
+
+        self.is_restarting = true;
+        let result = self.set_up_again().await;
+        self.is_restarting = false;
+        result
+    }
+
+    // Sets up a restarted engine like the previous one.
+    async fn set_up_again(&mut self) -> Result<(), UziErr> {
+        if let Some(timeout) = self.uci_timeout {
+            self.uci(timeout).await?;
+        }
+        for opt in self.applied.clone() {
+            self.send(&GuiCmd::SetOpt(opt)).await?;
+        }
+        self.sync(SYNC_TIMEOUT).await
+    }
+
+    // Attaches the transcript to an error returned by the client, unless it
+    // already has one.
+    fn attach(&self, err: UziErr) -> UziErr {
+        match err {
+            UziErr::WithTranscript(..) => err,
+            err if self.transcript.is_enabled() => {
+                UziErr::WithTranscript(Box::new(err), self.transcript.lines())
+            }
+            err => err,
+        }
+    }
+
+    // Sends a diagnostic command and returns its reply. The reply is made of
+    // lines that are not UCI commands, with no end marker, so the command is
+    // followed by isready and the reply is what arrives before readyok.
+    async fn diag(&mut self, cmd: DiagCmd, timeout: Duration) -> Result<String, UziErr> {
+        if !self.search.is_idle() {
+            return Err(self.attach(UziErr::BadSearchState));
+        }
+        // read_cmd collects the reply, which can be longer than the raw
+        // channel holds, e.g. the eval of an NNUE engine.
+        self.diag_reply = Some(Vec::new());
+        let result = match self.write_line(cmd.as_str()).await {
+            Ok(()) => self.sync(timeout).await,
+            Err(err) => Err(err),
+        };
+        let reply = self.diag_reply.take().unwrap_or_default();
+        result.map(|()| reply.join("\n"))
+    }
+
+    async fn write_line(&mut self, line: &str) -> Result<(), UziErr> {
+        self.transcript.sent(line);
+        trace::client_line(Direction::Sent, self.name(), line);
+        if let Err(err) = self.transport.send_line(line).await {
+            self.on_crash();
+            return Err(self.attach(err));
+        }
+        self.traffic.on_sent(line);
+        Ok(())
+    }
+
+    // Reads the next command from the engine, skipping lines that cannot be
+    // parsed.
+    async fn read_cmd(&mut self) -> Result<EngCmd, UziErr> {
+        loop {
+            let line = match self.transport.recv_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => {
+                    let report = self.transport.crash_report().await;
+                    self.on_crash();
+                    return Err(self.attach(UziErr::Crashed(report)));
+                }
+                Err(err) => return Err(self.attach(err)),
+            };
+            self.transcript.received(&line);
+            trace::client_line(Direction::Received, self.name(), &line);
+            let cmd = self.info_pool.parse(&line);
+            self.traffic.on_received(&line, cmd.is_ok());
+            if let Ok(cmd) = cmd {
+                self.latency.received(&cmd, Instant::now());
+                self.mux.publish(&cmd);
+                return Ok(cmd);
+            }
+            if let Some(ref mut reply) = self.diag_reply {
+                reply.push(line.clone());
+            }
+            // This only fails if there are no subscribers.
+            let _ = self.raw_tx.send(line);
+        }
+    }
+}
+
+// How an engine was shut down.
+// - Quit - the engine exited after quit.
+// - Killed - the engine did not exit in time and was killed.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Shutdown {
+    Quit,
+    Killed,
+}
+
+// Returns the limits for best_move, which searches for BEST_MOVE_TIME if the
+// limits do not end the search.
+pub(crate) fn best_move_limits(limits: &Go) -> Go {
+    let mut go = limits.clone();
+    if !go.has_limit() {
+        go.set_move_time(BEST_MOVE_TIME);
+    }
+    go
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engproc::CrashReport;
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+    use crate::types::SpinType;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn engine_uci() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        assert_eq!(eng.uci(TIMEOUT).await, Ok(()));
+        assert_eq!(eng.name(), Some("Fake"));
+        assert_eq!(eng.author(), Some("uzi"));
+        assert_eq!(
+            eng.options(),
+            &[HasOpt::Hash(SpinType {
+                default: 16,
+                min: 1,
+                max: 1024
+            })]
+        );
+    }
+
+    #[tokio::test]
+    async fn engine_uci_skips_banner() {
+        let script = r#"
+            echo "Stockfish 16 by the Stockfish developers (see AUTHORS file)"
+            read -r line
+            echo "info string NNUE evaluation using nn.nnue enabled"
+            echo "id name Fake"
+            echo "option name Broken"
+            echo "This program comes with ABSOLUTELY NO WARRANTY."
+            echo "uciok"
+            read -r line
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let mut raw = eng.subscribe_raw();
+        assert_eq!(eng.uci(TIMEOUT).await, Ok(()));
+        assert_eq!(eng.name(), Some("Fake"));
+        assert!(eng.options().is_empty());
+
+        let expected = [
+            "Stockfish 16 by the Stockfish developers (see AUTHORS file)",
+            "option name Broken",
+            "This program comes with ABSOLUTELY NO WARRANTY.",
+        ];
+        for line in expected {
+            assert_eq!(raw.recv().await, Ok(line.to_string()));
+        }
+    }
+
+    // An engine that echoes every command it receives as an info string.
+    const ECHO_ENGINE: &str = r#"
+        while read -r line; do
+            echo "info string $line"
+        done
+    "#;
+
+    async fn recv_string(eng: &mut Engine) -> String {
+        match eng.recv().await {
+            Ok(EngCmd::Info(info)) => info.to_string(),
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+    }
+
+    #[tokio::test]
+    async fn engine_diagnostics() {
+        let script = r#"
+            while read -r line; do
+                case "$line" in
+                    eval) echo "Classical evaluation   +0.12 (white side)"
+                          echo "Final evaluation       +0.30 (white side)";;
+                    compiler) echo "Unknown command: 'compiler'";;
+                    flip) echo "flipped";;
+                    isready) echo "readyok";;
+                esac
+            done
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let report = eng.eval(TIMEOUT).await.unwrap();
+        assert_eq!(report.final_eval, Some(30));
+        assert_eq!(
+            report.details,
+            ["Classical evaluation   +0.12 (white side)"]
+        );
+        let mut raw = eng.subscribe_raw();
+        eng.flip().await.unwrap();
+        eng.sync(TIMEOUT).await.unwrap();
+        assert_eq!(raw.recv().await.unwrap(), "flipped");
+        assert_eq!(
+            eng.compiler(TIMEOUT).await.map_err(UziErr::into_root),
+            Err(UziErr::BadDiagnostic)
+        );
+
+        // A reply longer than the raw channel holds comes back whole.
+        let script = r#"
+            while read -r line; do
+                case "$line" in
+                    eval) i=0
+                          while [ $i -lt 100 ]; do echo "Term $i"; i=$((i+1)); done
+                          echo "Final evaluation       +0.30 (white side)";;
+                    isready) echo "readyok";;
+                esac
+            done
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let _raw = eng.subscribe_raw();
+        let report = eng.eval(TIMEOUT).await.unwrap();
+        assert_eq!(report.final_eval, Some(30));
+        assert_eq!(report.details.len(), 100);
+        assert_eq!(report.details[99], "Term 99");
+    }
+
+    #[tokio::test]
+    async fn engine_typed_commands() {
+        let mut eng = Engine::new(fake_engine(ECHO_ENGINE));
+        let mut go = Go::new();
+        go.set_depth(10).set_ponder();
+        let mut pos = Pos::new();
+        pos.add_move(Pm::from_str("e2e4").unwrap());
+
+        eng.set_option("Threads", 4).await.unwrap();
+        eng.press_button("Clear Hash").await.unwrap();
+        eng.set_opt(SetOpt::Hash(64)).await.unwrap();
+        eng.debug(true).await.unwrap();
+        eng.new_game().await.unwrap();
+        eng.position(&pos).await.unwrap();
+        eng.go(&go).await.unwrap();
+        eng.ponderhit().await.unwrap();
+        eng.stop().await.unwrap();
+        eng.quit().await.unwrap();
+
+        let expected = [
+            "setoption name Threads value 4",
+            "setoption name Clear Hash",
+            "setoption name Hash value 64",
+            "debug on",
+            "ucinewgame",
+            "position startpos moves e2e4",
+            "go ponder depth 10",
+            "ponderhit",
+            "stop",
+            "quit",
+        ];
+        for line in expected {
+            assert_eq!(recv_string(&mut eng).await, format!("info string {}", line));
+        }
+    }
+
+    #[tokio::test]
+    async fn engine_auto_new_game() {
+        // Like ECHO_ENGINE, but answers isready.
+        let script = r#"
+            while read -r line; do
+                case "$line" in
+                    isready) echo "readyok";;
+                    *) echo "info string $line";;
+                esac
+            done
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let mut pos = Pos::new();
+        eng.position(&pos).await.unwrap();
+        pos.add_move(Pm::from_str("e2e4").unwrap());
+        eng.position(&pos).await.unwrap();
+        eng.position(&Pos::with_fen("8/8/8/8/8/8/8/k1K5 w - - 0 1"))
+            .await
+            .unwrap();
+
+        let expected = [
+            "ucinewgame",
+            "position startpos",
+            "position startpos moves e2e4",
+            "ucinewgame",
+            "position fen 8/8/8/8/8/8/8/k1K5 w - - 0 1",
+        ];
+        for line in expected {
+            assert_eq!(recv_string(&mut eng).await, format!("info string {}", line));
+        }
+    }
+
+    #[tokio::test]
+    async fn engine_best_move() {
+        // Plays e2e4 when it gets the default move time, and d2d4 otherwise.
+        let script = r#"
+            while read -r line; do
+                case "$line" in
+                    isready) echo "readyok";;
+                    "go movetime 1000") echo "bestmove e2e4";;
+                    go*) echo "info depth 1"; echo "bestmove d2d4";;
+                esac
+            done
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let pos = Pos::new();
+        assert_eq!(
+            eng.best_move(&pos, &Go::new()).await,
+            Ok(Pm::from_str("e2e4").unwrap())
+        );
+        let mut go = Go::new();
+        go.set_depth(5);
+        assert_eq!(
+            eng.best_move(&pos, &go).await,
+            Ok(Pm::from_str("d2d4").unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn engine_shutdown() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        assert_eq!(eng.shutdown(TIMEOUT).await, Ok(Shutdown::Quit));
+
+        let mut eng = Engine::new(fake_engine("trap '' TERM; cat > /dev/null; sleep 60"));
+        assert_eq!(
+            eng.shutdown(Duration::from_millis(50)).await,
+            Ok(Shutdown::Killed)
+        );
+    }
+
+    #[tokio::test]
+    async fn engine_sync() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        assert_eq!(eng.sync(TIMEOUT).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn engine_latency() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        eng.sync(TIMEOUT).await.unwrap();
+        eng.go(&Go::new()).await.unwrap().wait().await.unwrap();
+        let latency = eng.latency();
+        assert_eq!(latency.ready.count, 1);
+        assert_eq!(latency.best_move.count, 1);
+        let search = latency.last_search.unwrap();
+        assert!(search.first_info.unwrap() <= search.best_move);
+        assert_eq!(search.stop_to_best_move, None);
+
+        eng.reset_latency();
+        assert_eq!(*eng.latency(), Latency::default());
+    }
+
+    #[tokio::test]
+    async fn engine_traffic() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        eng.uci(TIMEOUT).await.unwrap();
+        eng.go(&Go::new()).await.unwrap().wait().await.unwrap();
+        let traffic = eng.traffic();
+        assert_eq!((traffic.sent("uci"), traffic.sent("go")), (1, 1));
+        assert_eq!(traffic.received("id"), 2);
+        assert_eq!(traffic.received("info"), 2);
+        assert_eq!(traffic.received("bestmove"), 1);
+        assert_eq!(traffic.bytes_sent(), "uci\ngo\n".len() as u64);
+
+        eng.reset_traffic();
+        assert!(eng.traffic().received_by_kind().is_empty());
+    }
+
+    #[tokio::test]
+    async fn engine_observers() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        let mut panel = eng.observe();
+        let mut logger = eng.observe();
+        let best = eng.best_move(&Pos::new(), &Go::new()).await;
+        assert_eq!(best, Ok(Pm::from_str("e2e4").unwrap()));
+        drop(eng);
+
+        let mut cmds = Vec::new();
+        while let Some(cmd) = panel.recv().await {
+            cmds.push(cmd.to_string());
+        }
+        assert_eq!(
+            cmds,
+            [
+                "readyok",
+                "info depth 1 pv e2e4 score cp 10",
+                "info depth 2 pv e2e4 e7e5 score cp 20",
+                "bestmove e2e4 ponder e7e5",
+            ]
+        );
+        assert_eq!(logger.try_recv(), Some(EngCmd::ReadyOk));
+        assert_eq!(logger.missed(), 0);
+    }
+
+    #[tokio::test]
+    async fn engine_sync_buffers_info() {
+        let script = r#"
+            read -r line
+            echo "info depth 1 score cp 10"
+            echo "some banner"
+            echo "readyok"
+            read -r line
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        assert_eq!(eng.sync(TIMEOUT).await, Ok(()));
+        assert!(matches!(eng.recv().await, Ok(EngCmd::Info(_))));
+    }
+
+    #[tokio::test]
+    async fn engine_sync_timeout() {
+        let mut eng = Engine::new(fake_engine("cat > /dev/null"));
+        assert_eq!(
+            eng.sync(Duration::from_millis(50))
+                .await
+                .map_err(UziErr::into_root),
+            Err(UziErr::Timeout)
+        );
+    }
+
+    #[tokio::test]
+    async fn engine_wait_best_move() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        eng.send(&GuiCmd::from_str("go depth 2").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            eng.wait_best_move().await,
+            Ok((
+                Pm::from_str("e2e4").unwrap(),
+                Some(Pm::from_str("e7e5").unwrap())
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn watchdog_kills_hung_engine() {
+        let mut watchdog = Watchdog::new();
+        watchdog.set_ready_timeout(Duration::from_millis(50));
+        let mut events = watchdog.subscribe();
+
+        let mut eng = Engine::new(fake_engine("cat > /dev/null"));
+        eng.set_watchdog(watchdog);
+
+        assert_eq!(
+            eng.sync(TIMEOUT).await.map_err(UziErr::into_root),
+            Err(UziErr::EngineHung)
+        );
+        assert_eq!(
+            events.recv().await,
+            Some(WatchdogEvent::Killed {
+                awaited: Awaited::ReadyOk,
+                deadline: Duration::from_millis(50)
+            })
+        );
+        assert!(eng.sync(TIMEOUT).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn watchdog_respawns_engine_without_best_move() {
+        // The first engine never sends bestmove, the replacement behaves.
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let spawner = Spawner::new(move || match spawned.fetch_add(1, Ordering::SeqCst) {
+            0 => Ok(fake_engine("cat > /dev/null")),
+            _ => Ok(fake_engine(FAKE_ENGINE)),
+        });
+
+        let mut watchdog = Watchdog::new();
+        watchdog
+            .set_best_move_timeout(Duration::from_millis(50))
+            .set_respawn(true);
+        let mut events = watchdog.subscribe();
+
+        let mut eng = Engine::from_spawner(spawner).unwrap();
+        eng.set_watchdog(watchdog);
+
+        eng.send(&GuiCmd::from_str("go depth 1").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            eng.wait_best_move().await.map_err(UziErr::into_root),
+            Err(UziErr::EngineHung)
+        );
+        assert_eq!(
+            events.recv().await,
+            Some(WatchdogEvent::Killed {
+                awaited: Awaited::BestMove,
+                deadline: Duration::from_millis(50)
+            })
+        );
+        assert_eq!(events.recv().await, Some(WatchdogEvent::Respawned));
+        assert_eq!(eng.sync(TIMEOUT).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn engine_restart_applies_options() {
+        // Echoes the options it receives, so that the test can see them.
+        let script = r#"
+            while read -r line; do
+                case "$line" in
+                    uci) echo "id name Fake"; echo "uciok";;
+                    isready) echo "readyok";;
+                    setoption*) echo "info string $line";;
+                esac
+            done
+        "#;
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let counter = spawned.clone();
+        let spawner = Spawner::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(fake_engine(script))
+        });
+
+        let mut eng = Engine::from_spawner(spawner).unwrap();
+        eng.uci(TIMEOUT).await.unwrap();
+        eng.set_opt(SetOpt::Hash(32)).await.unwrap();
+        eng.set_option("Threads", 2).await.unwrap();
+        eng.set_opt(SetOpt::Hash(64)).await.unwrap();
+        eng.press_button("Clear Hash").await.unwrap();
+        eng.sync(TIMEOUT).await.unwrap();
+
+        assert_eq!(eng.restart().await, Ok(()));
+        assert_eq!(spawned.load(Ordering::SeqCst), 2);
+        assert_eq!(eng.name(), Some("Fake"));
+        let expected = [
+            "setoption name Threads value 2",
+            "setoption name Hash value 64",
+        ];
+        for line in expected {
+            assert_eq!(recv_string(&mut eng).await, format!("info string {}", line));
+        }
+    }
+
+    #[tokio::test]
+    async fn engine_respawn_after_crash() {
+        // The first engine crashes on the first command, the others behave.
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let counter = spawned.clone();
+        let spawner = Spawner::new(move || match counter.fetch_add(1, Ordering::SeqCst) {
+            0 => Ok(fake_engine("read -r line; exit 1")),
+            _ => Ok(fake_engine(FAKE_ENGINE)),
+        });
+        let mut policy = RespawnPolicy::new();
+        policy.set_backoff(Duration::from_millis(1), Duration::from_millis(10));
+
+        let mut eng = Engine::from_spawner(spawner).unwrap();
+        eng.set_respawn_policy(policy);
+        assert!(matches!(
+            eng.sync(TIMEOUT).await.map_err(UziErr::into_root),
+            Err(UziErr::Crashed(_))
+        ));
+        assert_eq!(eng.sync(TIMEOUT).await, Ok(()));
+        assert_eq!(spawned.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn engine_respawn_limit() {
+        let spawner = Spawner::new(|| Ok(fake_engine("read -r line; exit 1")));
+        let mut policy = RespawnPolicy::new();
+        policy
+            .set_max_restarts(1)
+            .set_backoff(Duration::from_millis(1), Duration::from_millis(10));
+
+        let mut eng = Engine::from_spawner(spawner).unwrap();
+        eng.set_respawn_policy(policy);
+        for _ in 0..2 {
+            assert!(matches!(
+                eng.sync(TIMEOUT).await.map_err(UziErr::into_root),
+                Err(UziErr::Crashed(_))
+            ));
+        }
+        assert_eq!(
+            eng.sync(TIMEOUT).await.map_err(UziErr::into_root),
+            Err(UziErr::RespawnLimit)
+        );
+    }
+
+    #[tokio::test]
+    async fn engine_restart_without_spawner() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        assert_eq!(
+            eng.restart().await.map_err(UziErr::into_root),
+            Err(UziErr::CannotRestart)
+        );
+    }
+
+    #[tokio::test]
+    async fn engine_sync_engine_crashed() {
+        let mut eng = Engine::new(fake_engine("read -r line; echo 'NNUE not found' >&2"));
+        assert_eq!(
+            eng.sync(TIMEOUT).await.map_err(UziErr::into_root),
+            Err(UziErr::Crashed(CrashReport {
+                exit_code: Some(0),
+                signal: None,
+                in_flight: Some("isready".into()),
+                stdout: vec![],
+                stderr: vec!["NNUE not found".into()]
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn engine_errors_have_transcript() {
+        let mut eng = Engine::new(fake_engine("read -r line; echo 'id name Fake'"));
+        let err = eng.sync(TIMEOUT).await.unwrap_err();
+        assert!(matches!(err.root(), UziErr::Crashed(_)));
+        assert_eq!(
+            err.transcript(),
+            Some(&["> isready".to_string(), "< id name Fake".to_string()][..])
+        );
+        assert_eq!(eng.dump_transcript(), "> isready\n< id name Fake");
+
+        eng.set_transcript_len(0);
+        assert!(eng.sync(TIMEOUT).await.unwrap_err().transcript().is_none());
+    }
+}
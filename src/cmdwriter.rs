@@ -0,0 +1,111 @@
+// This module contains CmdWriter, which serializes commands into a buffer it
+// keeps between writes. An engine can send thousands of infos per second, and
+// formatting each of them into a String of its own allocates every time, while
+// the buffer of a CmdWriter grows to the longest batch once and is reused.
+
+use crate::err::UziErr;
+use std::fmt::{Display, Write as _};
+use std::io::Write;
+
+// Writes commands, or any other lines, to a byte stream, e.g. the stdout of an
+// engine or the stdin of an engine process. Commands are buffered until flush,
+// so that a batch of them is written at once.
+#[derive(Debug)]
+pub struct CmdWriter<W> {
+    out: W,
+    // The lines serialized since the last flush, each with its newline.
+    buf: String,
+}
+
+impl<W: Write> CmdWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self::with_capacity(out, 0)
+    }
+
+    // Creates a writer whose buffer can hold capacity bytes before it grows.
+    pub fn with_capacity(out: W, capacity: usize) -> Self {
+        Self {
+            out,
+            buf: String::with_capacity(capacity),
+        }
+    }
+
+    // Serializes a command into the buffer, followed by a newline. Nothing is
+    // written until flush.
+    pub fn push(&mut self, cmd: &impl Display) -> &mut Self {
+        // Writing to a String only fails if Display does, which the commands
+        // of the crate never do.
+        let _ = writeln!(self.buf, "{}", cmd);
+        self
+    }
+
+    // Serializes a command and writes it with whatever was buffered before it.
+    pub fn write(&mut self, cmd: &impl Display) -> Result<(), UziErr> {
+        self.push(cmd);
+        self.flush()
+    }
+
+    // Writes the buffered lines and flushes the stream. The buffer keeps its
+    // capacity for the next lines. On error, the buffered lines are dropped.
+    pub fn flush(&mut self) -> Result<(), UziErr> {
+        let result = self
+            .out
+            .write_all(self.buf.as_bytes())
+            .and_then(|_| self.out.flush());
+        self.buf.clear();
+        Ok(result?)
+    }
+
+    // Returns the lines buffered since the last flush.
+    pub fn buffered(&self) -> &str {
+        &self.buf
+    }
+
+    // Returns how many bytes the buffer can hold before it grows.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+
+    // Returns the stream, dropping anything that was not flushed.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engcmd::EngCmd;
+    use crate::pm::Pm;
+    use std::str::FromStr;
+
+    #[test]
+    fn cmd_writer() {
+        let mut writer = CmdWriter::new(Vec::new());
+        let info = EngCmd::from_str("info depth 3 pv e2e4 e7e5 score cp 20").unwrap();
+        writer.push(&info).push(&"readyok");
+        assert_eq!(
+            writer.buffered(),
+            "info depth 3 pv e2e4 e7e5 score cp 20\nreadyok\n"
+        );
+        assert!(writer.get_ref().is_empty());
+        writer.flush().unwrap();
+        assert!(writer.buffered().is_empty());
+
+        let capacity = writer.capacity();
+        let best = EngCmd::BestMove {
+            best: Pm::from_str("e2e4").unwrap(),
+            ponder: None,
+        };
+        writer.write(&best).unwrap();
+        assert_eq!(writer.capacity(), capacity);
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "info depth 3 pv e2e4 e7e5 score cp 20\nreadyok\nbestmove e2e4\n"
+        );
+    }
+}
@@ -0,0 +1,276 @@
+// This module contains EvalComparison, which evaluates the same positions on
+// several engines and reports how their evaluations differ, e.g. to compare
+// two versions of an engine or two settings of the same one.
+
+use crate::analysis::AnalysisResult;
+use crate::batch::BatchEval;
+use crate::client::Engine;
+use crate::engcmd::Score;
+use crate::err::UziErr;
+use crate::guicmd::Go;
+use crate::metrics::CP_LIMIT;
+use crate::pm::Pm;
+use std::time::Duration;
+
+// Evaluates positions with the same limits on every engine. The first engine
+// is the baseline the others are compared to.
+#[derive(Clone, Debug)]
+pub struct EvalComparison {
+    limits: Go,
+}
+
+// The evaluations of one position by every engine, in the order of the
+// engines.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PositionDiff {
+    // The index of the position in the set.
+    pub index: usize,
+    pub fen: String,
+    pub evals: Vec<EngineEval>,
+}
+
+// The evaluation of a position by one engine, compared to the baseline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineEval {
+    pub result: Result<AnalysisResult, UziErr>,
+    // The score minus the score of the baseline, in centipawns limited to
+    // CP_LIMIT, or None if either is missing.
+    pub delta: Option<i32>,
+    // Whether the best move is the one of the baseline, or None if either
+    // engine failed.
+    pub agrees: Option<bool>,
+}
+
+// The evaluations of every engine over the whole set.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EngineSummary {
+    // The number of positions the engine evaluated without errors.
+    pub evaluated: usize,
+    // The share of the positions, from 0 to 1, where the best move is the one
+    // of the baseline.
+    pub agreement: Option<f64>,
+    // The average of the absolute score deltas to the baseline.
+    pub mean_abs_delta: Option<f64>,
+    pub mean_depth: Option<f64>,
+    pub mean_time: Option<Duration>,
+}
+
+impl EvalComparison {
+    pub fn new(limits: Go) -> Self {
+        Self { limits }
+    }
+
+    // Evaluates the positions on every engine, one engine after the other so
+    // that they don't compete for the CPU, and returns the evaluations of
+    // every position in the order of the positions.
+    pub async fn run<I>(&self, engines: &mut [Engine], fens: I) -> Vec<PositionDiff>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let fens: Vec<String> = fens.into_iter().collect();
+        let mut positions: Vec<PositionDiff> = fens
+            .iter()
+            .enumerate()
+            .map(|(index, fen)| PositionDiff {
+                index,
+                fen: fen.clone(),
+                evals: Vec::with_capacity(engines.len()),
+            })
+            .collect();
+        for eng in engines.iter_mut() {
+            let evals = BatchEval::new(self.limits.clone())
+                .run(eng, fens.iter().cloned())
+                .await;
+            for eval in evals {
+                positions[eval.index].evals.push(EngineEval {
+                    result: eval.result,
+                    delta: None,
+                    agrees: None,
+                });
+            }
+        }
+        for position in &mut positions {
+            position.compare();
+        }
+        positions
+    }
+}
+
+impl PositionDiff {
+    // Returns true if every engine evaluated the position and found the same
+    // best move.
+    pub fn all_agree(&self) -> bool {
+        self.evals.iter().all(|eval| eval.agrees == Some(true))
+    }
+
+    fn compare(&mut self) {
+        let Some((baseline, others)) = self.evals.split_first_mut() else {
+            return;
+        };
+        let (best, score) = (baseline.best(), baseline.cp());
+        baseline.delta = score.map(|_| 0);
+        baseline.agrees = best.map(|_| true);
+        for eval in others {
+            eval.delta = eval.cp().zip(score).map(|(cp, score)| cp - score);
+            eval.agrees = eval.best().zip(best).map(|(pm, best)| pm == best);
+        }
+    }
+}
+
+impl EngineEval {
+    pub fn best(&self) -> Option<Pm> {
+        self.result.as_ref().ok().map(|result| result.best)
+    }
+
+    // Returns the score of the best line.
+    pub fn score(&self) -> Option<Score> {
+        self.result.as_ref().ok()?.lines.first()?.score
+    }
+
+    pub fn depth(&self) -> Option<u16> {
+        self.result.as_ref().ok()?.lines.first()?.depth
+    }
+
+    pub fn time(&self) -> Option<Duration> {
+        self.result.as_ref().ok()?.lines.first()?.time
+    }
+
+    fn cp(&self) -> Option<i32> {
+        self.score()?.clamp_cp(CP_LIMIT)
+    }
+}
+
+impl EngineSummary {
+    // Sums up the evaluations of the engine with the given index.
+    pub fn new(positions: &[PositionDiff], engine: usize) -> Self {
+        let evals: Vec<&EngineEval> = positions
+            .iter()
+            .filter_map(|position| position.evals.get(engine))
+            .filter(|eval| eval.result.is_ok())
+            .collect();
+        let agrees: Vec<bool> = evals.iter().filter_map(|eval| eval.agrees).collect();
+        let deltas: Vec<f64> = evals
+            .iter()
+            .filter_map(|eval| eval.delta)
+            .map(|delta| f64::from(delta.abs()))
+            .collect();
+        let depths: Vec<f64> = evals
+            .iter()
+            .filter_map(|eval| eval.depth())
+            .map(f64::from)
+            .collect();
+        let times: Vec<Duration> = evals.iter().filter_map(|eval| eval.time()).collect();
+        Self {
+            evaluated: evals.len(),
+            agreement: mean(
+                agrees.iter().map(|agrees| f64::from(u8::from(*agrees))),
+                agrees.len(),
+            ),
+            mean_abs_delta: mean(deltas.iter().copied(), deltas.len()),
+            mean_depth: mean(depths.iter().copied(), depths.len()),
+            mean_time: u32::try_from(times.len())
+                .ok()
+                .filter(|len| *len > 0)
+                .map(|len| times.iter().sum::<Duration>() / len),
+        }
+    }
+
+    // Sums up the evaluations of every engine, in the order of the engines.
+    pub fn all(positions: &[PositionDiff]) -> Vec<Self> {
+        let engines = positions.first().map_or(0, |position| position.evals.len());
+        (0..engines)
+            .map(|engine| Self::new(positions, engine))
+            .collect()
+    }
+}
+
+fn mean<I: Iterator<Item = f64>>(values: I, len: usize) -> Option<f64> {
+    (len > 0).then(|| values.sum::<f64>() / len as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::fake_engine;
+    use std::str::FromStr;
+
+    // Plays the move after the FEN, and reports the given score and time for
+    // every position.
+    fn engine(score: &str, time: u64) -> Engine {
+        let script = format!(
+            r#"
+while read -r line; do
+    case "$line" in
+        isready) echo "readyok";;
+        position*) best=${{line##* }};;
+        go*)
+            echo "info depth 5 time {} score {} pv $best"
+            echo "bestmove $best";;
+    esac
+done
+"#,
+            time, score
+        );
+        Engine::new(fake_engine(&script))
+    }
+
+    fn fen(pm: &str) -> String {
+        format!("8/8/8/8/8/8/8/K6k w - - 0 1 {}", pm)
+    }
+
+    #[tokio::test]
+    async fn comparison_diffs_engines() {
+        let mut engines = vec![engine("cp 10", 100), engine("cp 40", 300)];
+        let mut go = Go::new();
+        go.set_depth(5);
+        let positions = EvalComparison::new(go)
+            .run(&mut engines, vec![fen("e2e4"), fen("d2d4")])
+            .await;
+
+        assert_eq!(positions.len(), 2);
+        let eval = &positions[1].evals[1];
+        assert_eq!(eval.best(), Some(Pm::from_str("d2d4").unwrap()));
+        assert_eq!((eval.delta, eval.agrees), (Some(30), Some(true)));
+        assert_eq!(positions[0].evals[0].delta, Some(0));
+        assert!(positions[0].all_agree());
+
+        let summaries = EngineSummary::all(&positions);
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[1].evaluated, 2);
+        assert_eq!(summaries[1].agreement, Some(1.0));
+        assert_eq!(summaries[1].mean_abs_delta, Some(30.0));
+        assert_eq!(summaries[1].mean_depth, Some(5.0));
+        assert_eq!(summaries[1].mean_time, Some(Duration::from_millis(300)));
+    }
+
+    #[test]
+    fn comparison_disagreement() {
+        let result = |best: &str| AnalysisResult {
+            best: Pm::from_str(best).unwrap(),
+            ponder: None,
+            lines: Vec::new(),
+        };
+        let eval = |result| EngineEval {
+            result,
+            delta: None,
+            agrees: None,
+        };
+        let mut position = PositionDiff {
+            index: 0,
+            fen: fen("e2e4"),
+            evals: vec![
+                eval(Ok(result("e2e4"))),
+                eval(Ok(result("d2d4"))),
+                eval(Err(UziErr::Timeout)),
+            ],
+        };
+        position.compare();
+        let agrees: Vec<Option<bool>> = position.evals.iter().map(|eval| eval.agrees).collect();
+        assert_eq!(agrees, vec![Some(true), Some(false), None]);
+        assert!(!position.all_agree());
+
+        let summary = EngineSummary::new(&[position], 1);
+        assert_eq!(summary.agreement, Some(0.0));
+        assert_eq!(summary.mean_abs_delta, None);
+    }
+}
@@ -2,7 +2,7 @@
 // enabled and disabled.
 
 use crate::opt::{HasOpt, Opponent, PosValueOpt, UziOpt, UziOptIter};
-use crate::types::{SpinType, StrType};
+use crate::types::{ComboType, SpinType, StrType};
 use std::path::PathBuf;
 
 // Config represents the current configuration for the chess engine, i.e. what
@@ -27,6 +27,7 @@ pub struct Config {
     pub about: Option<StrType>,
     pub shredder_bases: Option<PathBuf>,
     pub pos_value: Option<PosValueOpt>,
+    pub variant: Option<ComboType>,
 }
 
 impl Config {
@@ -56,7 +57,7 @@ impl<'a> ConfigIter<'a> {
 impl Iterator for ConfigIter<'_> {
     type Item = HasOpt;
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some(opt) = self.opt_iter.next() {
+        for opt in self.opt_iter.by_ref() {
             match opt {
                 UziOpt::Hash if self.conf.hash_table.is_some() => {
                     return Some(HasOpt::Hash(self.conf.hash_table.unwrap()));
@@ -129,6 +130,9 @@ impl Iterator for ConfigIter<'_> {
                         self.conf.pos_value.clone().unwrap().into(),
                     ));
                 }
+                UziOpt::Variant if self.conf.variant.is_some() => {
+                    return Some(HasOpt::Variant(self.conf.variant.clone().unwrap()));
+                }
                 _ => continue,
             };
         }
@@ -0,0 +1,632 @@
+// This module contains ConformanceSuite, a protocol linter for engine authors.
+// It drives an engine through a battery of checks and reports which of them
+// pass:
+// - handshake - the engine answers uci with its name and uciok in time.
+// - option declarations - the defaults of spin options are within their
+//   limits, and the defaults of combo options are among their values.
+// - isready - the engine answers isready while it is idle.
+// - option limits - the engine survives a spin option set below its minimum,
+//   which it should ignore or clamp, and then to its minimum and its default.
+//   The maximum is left alone, since e.g. the largest Hash may not fit.
+// - bestmove legality - the bestmove, and the ponder move, are legal in a set
+//   of positions with castling, en passant, promotions and checks, and the
+//   engine sends the null move when there is no legal move. When the suite
+//   sets a variant other than chess, Board can't tell legal moves apart, so
+//   the check only makes sure the engine sends a bestmove that parses, from
+//   the start position of the variant and from the added positions.
+// - isready during search - the engine answers isready while it searches.
+// - stop during search - the engine sends bestmove after stop.
+// - stop right after go - likewise, when stop follows go at once.
+//
+// A check that leaves the engine searching or unresponsive is followed by a
+// restart, if the engine was created from a spawner. Otherwise the remaining
+// checks are skipped.
+
+use crate::board::Board;
+use crate::client::Engine;
+use crate::engproc::Launcher;
+use crate::err::UziErr;
+use crate::guicmd::{Go, GuiCmd, Pos};
+use crate::opt::{HasOpt, Variant};
+use crate::pm::Pm;
+use crate::types::{OptKind, SpinType};
+use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, Instant};
+use tokio::time;
+
+// The positions of the bestmove legality check, by default.
+const POSITIONS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    // Castling on both sides, and pins.
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    // En passant.
+    "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+    // Promotion.
+    "8/P6k/8/8/8/8/8/K7 w - - 0 1",
+    // Check.
+    "4k3/8/8/8/8/8/4q3/4K3 w - - 0 1",
+    // Checkmate, with no legal move.
+    "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+];
+
+// The checks, in the order they run.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Check {
+    Handshake,
+    OptionDecls,
+    IsReady,
+    OptionLimits,
+    BestMoveLegality,
+    IsReadyDuringSearch,
+    StopDuringSearch,
+    StopRightAfterGo,
+}
+
+impl Check {
+    pub const ALL: [Check; 8] = [
+        Check::Handshake,
+        Check::OptionDecls,
+        Check::IsReady,
+        Check::OptionLimits,
+        Check::BestMoveLegality,
+        Check::IsReadyDuringSearch,
+        Check::StopDuringSearch,
+        Check::StopRightAfterGo,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::Handshake => "handshake",
+            Check::OptionDecls => "option declarations",
+            Check::IsReady => "isready",
+            Check::OptionLimits => "option limits",
+            Check::BestMoveLegality => "bestmove legality",
+            Check::IsReadyDuringSearch => "isready during search",
+            Check::StopDuringSearch => "stop during search",
+            Check::StopRightAfterGo => "stop right after go",
+        }
+    }
+}
+
+// The outcome of a check. A failure or a skip comes with the reason.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Verdict {
+    Pass,
+    Fail(String),
+    Skip(String),
+}
+
+// The outcome of a check, with how long it took.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CheckResult {
+    pub check: Check,
+    pub verdict: Verdict,
+    pub elapsed: Duration,
+}
+
+// The outcomes of the checks, in the order they ran. This displays as a line
+// per check, e.g.
+//
+// PASS handshake (12 ms)
+// FAIL stop during search: no bestmove within 1000 ms of stop
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConformanceReport {
+    // The name the engine sent in the handshake, if any.
+    pub engine: Option<String>,
+    pub results: Vec<CheckResult>,
+}
+
+// The settings of the checks.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConformanceSuite {
+    // How long the engine may take to send uciok.
+    handshake_timeout: Duration,
+    // How long the engine may take to answer isready, or to send bestmove
+    // after stop or after the movetime of a search.
+    reply_timeout: Duration,
+    // The movetime of the searches.
+    movetime: Duration,
+    // The FENs of the bestmove legality check.
+    positions: Vec<String>,
+    // The variant set through UCI_Variant after the handshake, if any.
+    variant: Option<Variant>,
+}
+
+impl ConformanceReport {
+    // Returns true if no check failed. Skipped checks don't count as failed.
+    pub fn is_pass(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results
+            .iter()
+            .filter(|result| matches!(result.verdict, Verdict::Fail(_)))
+    }
+
+    pub fn verdict(&self, check: Check) -> Option<&Verdict> {
+        self.results
+            .iter()
+            .find(|result| result.check == check)
+            .map(|result| &result.verdict)
+    }
+}
+
+impl Display for ConformanceReport {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            let name = result.check.name();
+            match &result.verdict {
+                Verdict::Pass => writeln!(
+                    formatter,
+                    "PASS {} ({} ms)",
+                    name,
+                    result.elapsed.as_millis()
+                )?,
+                Verdict::Fail(reason) => writeln!(formatter, "FAIL {}: {}", name, reason)?,
+                Verdict::Skip(reason) => writeln!(formatter, "SKIP {}: {}", name, reason)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ConformanceSuite {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Sets how long the engine may take to send uciok. The default is 2
+    // seconds.
+    pub fn set_handshake_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    // Sets how long the engine may take to answer isready, or to send bestmove
+    // when it should. The default is 1 second.
+    pub fn set_reply_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.reply_timeout = timeout;
+        self
+    }
+
+    // Sets the movetime of the searches. The default is 100 ms.
+    pub fn set_movetime(&mut self, movetime: Duration) -> &mut Self {
+        self.movetime = movetime;
+        self
+    }
+
+    // Adds a position to the bestmove legality check, e.g. one the engine got
+    // wrong before.
+    pub fn add_position(&mut self, fen: &str) -> &mut Self {
+        self.positions.push(fen.to_string());
+        self
+    }
+
+    // Sets the variant the engine plays through UCI_Variant. The handshake
+    // fails if the engine doesn't declare it.
+    pub fn set_variant(&mut self, variant: Variant) -> &mut Self {
+        self.variant = Some(variant);
+        self
+    }
+
+    // Launches the engine, runs the checks, and shuts it down.
+    pub async fn run_launcher(&self, launcher: Launcher) -> Result<ConformanceReport, UziErr> {
+        Ok(self.run(Engine::launch(launcher)?).await)
+    }
+
+    // Runs the checks on an engine that hasn't gone through the handshake yet,
+    // and shuts it down.
+    pub async fn run(&self, mut eng: Engine) -> ConformanceReport {
+        let mut results = Vec::new();
+        let mut skip_reason: Option<String> = None;
+        for check in Check::ALL {
+            let started = Instant::now();
+            let verdict = match skip_reason {
+                Some(ref reason) => Verdict::Skip(reason.clone()),
+                None => self.run_check(check, &mut eng).await,
+            };
+            let elapsed = started.elapsed();
+            let is_failed = matches!(verdict, Verdict::Fail(_));
+            // The search of isready during search is left for the stop check.
+            let is_left_searching = eng.is_searching() && check != Check::IsReadyDuringSearch;
+            if check == Check::Handshake && is_failed {
+                skip_reason = Some("the handshake failed".to_string());
+            } else if skip_reason.is_none() && (is_failed || is_left_searching) {
+                skip_reason = self.recover(&mut eng).await;
+            }
+            results.push(CheckResult {
+                check,
+                verdict,
+                elapsed,
+            });
+        }
+        let _ = eng.shutdown(self.reply_timeout).await;
+        ConformanceReport {
+            engine: eng.name().map(str::to_string),
+            results,
+        }
+    }
+
+    // Gets the engine ready for the next check after a failure. Returns why
+    // the remaining checks are skipped if that isn't possible.
+    async fn recover(&self, eng: &mut Engine) -> Option<String> {
+        if !eng.is_searching() && eng.sync(self.reply_timeout).await.is_ok() {
+            return None;
+        }
+        match eng.restart().await {
+            Ok(()) => None,
+            Err(_) => Some("the engine could not be restarted".to_string()),
+        }
+    }
+
+    async fn run_check(&self, check: Check, eng: &mut Engine) -> Verdict {
+        match check {
+            Check::Handshake => self.handshake(eng).await,
+            Check::OptionDecls => option_decls(eng),
+            Check::IsReady => self.is_ready(eng).await,
+            Check::OptionLimits => self.option_limits(eng).await,
+            Check::BestMoveLegality => self.best_move_legality(eng).await,
+            Check::IsReadyDuringSearch => self.is_ready_during_search(eng).await,
+            Check::StopDuringSearch => self.stop(eng, self.movetime).await,
+            Check::StopRightAfterGo => self.stop(eng, Duration::ZERO).await,
+        }
+    }
+
+    async fn handshake(&self, eng: &mut Engine) -> Verdict {
+        match eng
+            .uci(self.handshake_timeout)
+            .await
+            .map_err(UziErr::into_root)
+        {
+            Ok(()) if eng.name().is_none() => Verdict::Fail("no id name".to_string()),
+            Ok(()) => match &self.variant {
+                None => Verdict::Pass,
+                Some(variant) => match eng.set_variant(variant.clone()).await {
+                    Ok(()) => Verdict::Pass,
+                    Err(_) => Verdict::Fail(format!("variant {} is not declared", variant)),
+                },
+            },
+            Err(UziErr::Timeout) => Verdict::Fail(format!(
+                "no uciok within {} ms",
+                self.handshake_timeout.as_millis()
+            )),
+            Err(err) => Verdict::Fail(format!("{:?}", err)),
+        }
+    }
+
+    async fn is_ready(&self, eng: &mut Engine) -> Verdict {
+        match eng.sync(self.reply_timeout).await {
+            Ok(()) => Verdict::Pass,
+            Err(err) => self.no_readyok(err),
+        }
+    }
+
+    async fn option_limits(&self, eng: &mut Engine) -> Verdict {
+        let spins: Vec<(String, SpinType<i64>)> = eng
+            .options()
+            .iter()
+            .filter_map(|opt| Some((opt.name().to_string(), spin_of(opt)?)))
+            .collect();
+        if spins.is_empty() {
+            return Verdict::Skip("no spin options".to_string());
+        }
+        for (name, spin) in spins {
+            let values = [spin.min.checked_sub(1), Some(spin.min), Some(spin.default)];
+            for value in values.into_iter().flatten() {
+                let result = match eng.set_option(&name, value).await {
+                    Ok(()) => eng.sync(self.reply_timeout).await,
+                    Err(err) => Err(err),
+                };
+                if result.is_err() {
+                    return Verdict::Fail(format!(
+                        "no readyok after setting {} to {} (min {}, max {})",
+                        name, value, spin.min, spin.max
+                    ));
+                }
+            }
+        }
+        Verdict::Pass
+    }
+
+    async fn best_move_legality(&self, eng: &mut Engine) -> Verdict {
+        if self.variant.as_ref().is_some_and(|v| !v.is_chess()) {
+            return self.variant_best_move(eng).await;
+        }
+        let mut go = Go::new();
+        go.set_move_time(self.movetime);
+        for fen in &self.positions {
+            let Some(board) = Board::from_fen(fen) else {
+                return Verdict::Skip(format!("bad position {}", fen));
+            };
+            let search = async {
+                eng.position(&Pos::with_fen(fen)).await?;
+                eng.go(&go).await?.wait().await
+            };
+            let (best, ponder) = match time::timeout(self.movetime + self.reply_timeout, search)
+                .await
+            {
+                Ok(Ok(moves)) => moves,
+                Ok(Err(err)) => return Verdict::Fail(format!("{:?} in {}", err.into_root(), fen)),
+                Err(_) => {
+                    return Verdict::Fail(format!(
+                        "no bestmove within {} ms of the movetime in {}",
+                        self.reply_timeout.as_millis(),
+                        fen
+                    ))
+                }
+            };
+            let legal = board.legal_moves();
+            let is_legal = match best {
+                Pm::Null => legal.is_empty(),
+                best => legal.contains(&best),
+            };
+            if !is_legal {
+                return Verdict::Fail(format!("illegal bestmove {} in {}", best, fen));
+            }
+            if let Some(ponder) = ponder {
+                let mut after = board.clone();
+                after.play(best);
+                if !after.legal_moves().contains(&ponder) {
+                    return Verdict::Fail(format!(
+                        "illegal ponder move {} after {} in {}",
+                        ponder, best, fen
+                    ));
+                }
+            }
+        }
+        Verdict::Pass
+    }
+
+    // Searches the start position of the variant and the added positions,
+    // which the default positions of chess don't belong to.
+    async fn variant_best_move(&self, eng: &mut Engine) -> Verdict {
+        let mut go = Go::new();
+        go.set_move_time(self.movetime);
+        let added = self.positions.iter().skip(POSITIONS.len());
+        let positions = [None].into_iter().chain(added.map(Some));
+        for fen in positions {
+            let pos = fen.map_or_else(Pos::new, |fen| Pos::with_fen(fen));
+            let search = async {
+                eng.position(&pos).await?;
+                eng.go(&go).await?.wait().await
+            };
+            let fen = fen.map_or("startpos", String::as_str);
+            match time::timeout(self.movetime + self.reply_timeout, search).await {
+                Ok(Ok(_)) => (),
+                Ok(Err(err)) => return Verdict::Fail(format!("{:?} in {}", err.into_root(), fen)),
+                Err(_) => {
+                    return Verdict::Fail(format!(
+                        "no bestmove within {} ms of the movetime in {}",
+                        self.reply_timeout.as_millis(),
+                        fen
+                    ))
+                }
+            }
+        }
+        Verdict::Pass
+    }
+
+    async fn is_ready_during_search(&self, eng: &mut Engine) -> Verdict {
+        if let Err(err) = self.go_infinite(eng).await {
+            return Verdict::Fail(format!("{:?}", err.into_root()));
+        }
+        // The search is left running for the stop during search check.
+        match eng.sync(self.reply_timeout).await {
+            Ok(()) => Verdict::Pass,
+            Err(err) => self.no_readyok(err),
+        }
+    }
+
+    // Stops an infinite search after the delay. The search is the one left by
+    // the previous check, if any.
+    async fn stop(&self, eng: &mut Engine, delay: Duration) -> Verdict {
+        if !eng.is_searching() {
+            if let Err(err) = self.go_infinite(eng).await {
+                return Verdict::Fail(format!("{:?}", err.into_root()));
+            }
+        }
+        time::sleep(delay).await;
+        if let Err(err) = eng.stop().await {
+            return Verdict::Fail(format!("{:?}", err.into_root()));
+        }
+        match time::timeout(self.reply_timeout, eng.wait_best_move()).await {
+            Ok(Ok(_)) => Verdict::Pass,
+            Ok(Err(err)) => Verdict::Fail(format!("{:?}", err.into_root())),
+            Err(_) => Verdict::Fail(format!(
+                "no bestmove within {} ms of stop",
+                self.reply_timeout.as_millis()
+            )),
+        }
+    }
+
+    // Starts an infinite search from the start position. The search is left
+    // running, unlike with go, which returns a handle that borrows the engine.
+    async fn go_infinite(&self, eng: &mut Engine) -> Result<(), UziErr> {
+        eng.position(&Pos::new()).await?;
+        let mut go = Go::new();
+        go.set_infinite();
+        eng.send(&GuiCmd::Go(go)).await
+    }
+
+    fn no_readyok(&self, err: UziErr) -> Verdict {
+        match err.into_root() {
+            UziErr::Timeout => Verdict::Fail(format!(
+                "no readyok within {} ms",
+                self.reply_timeout.as_millis()
+            )),
+            err => Verdict::Fail(format!("{:?}", err)),
+        }
+    }
+}
+
+impl Default for ConformanceSuite {
+    fn default() -> Self {
+        Self {
+            handshake_timeout: Duration::from_secs(2),
+            reply_timeout: Duration::from_secs(1),
+            movetime: Duration::from_millis(100),
+            positions: POSITIONS.iter().map(|fen| fen.to_string()).collect(),
+            variant: None,
+        }
+    }
+}
+
+fn option_decls(eng: &Engine) -> Verdict {
+    for opt in eng.options() {
+        let problem = match opt {
+            HasOpt::Custom {
+                kind: OptKind::Combo(combo),
+                ..
+            } if !combo.var.contains(&combo.default) => {
+                Some(format!("default {} is not a var", combo.default))
+            }
+            opt => spin_of(opt)
+                .filter(|spin| spin.default < spin.min || spin.default > spin.max)
+                .map(|spin| {
+                    format!(
+                        "default {} is not within min {} and max {}",
+                        spin.default, spin.min, spin.max
+                    )
+                }),
+        };
+        if let Some(problem) = problem {
+            return Verdict::Fail(format!("{}: {}", opt.name(), problem));
+        }
+    }
+    Verdict::Pass
+}
+
+// Returns the limits of a spin option, standard or not.
+fn spin_of(opt: &HasOpt) -> Option<SpinType<i64>> {
+    fn widen<T: Copy + Into<i128>>(spin: &SpinType<T>) -> Option<SpinType<i64>> {
+        let widen = |value: T| i64::try_from(value.into()).ok();
+        Some(SpinType {
+            default: widen(spin.default)?,
+            min: widen(spin.min)?,
+            max: widen(spin.max)?,
+        })
+    }
+    match opt {
+        HasOpt::Hash(spin) | HasOpt::NalimovCache(spin) | HasOpt::MultiPv(spin) => widen(spin),
+        HasOpt::Elo(spin) => widen(spin),
+        HasOpt::Custom {
+            kind: OptKind::Spin(spin),
+            ..
+        } => Some(*spin),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GamePos;
+    use crate::mockgui::RunnerTransport;
+    use crate::optreg::OptionRegistry;
+    use crate::server::{EngineMeta, InfoSender, Runner, StopFlag, UciEngine};
+    use crate::testutil::fake_engine;
+    use std::thread;
+
+    // Plays the first legal move, and follows the rules for infinite searches.
+    struct FirstLegal;
+
+    impl UciEngine for FirstLegal {
+        fn go(
+            &mut self,
+            pos: &GamePos,
+            go: &Go,
+            stop: &StopFlag,
+            _info: &InfoSender,
+        ) -> (Pm, Option<Pm>) {
+            while go.is_infinite() && !stop.is_stopped() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            let moves = Board::from_pos(pos.pos()).map_or_else(Vec::new, |b| b.legal_moves());
+            (moves.first().copied().unwrap_or(Pm::Null), None)
+        }
+    }
+
+    #[tokio::test]
+    async fn conforming_engine_passes() {
+        let mut options = OptionRegistry::new();
+        options.add_spin("Hash", 16, 1, 1024);
+        let mut runner = Runner::new(EngineMeta::new("First", "1.0", &["uzi"]), FirstLegal);
+        runner.set_options(options);
+        let eng = Engine::new(RunnerTransport::new(runner));
+        let report = ConformanceSuite::new().run(eng).await;
+        assert!(report.is_pass(), "{}", report);
+        assert_eq!(report.engine.as_deref(), Some("First 1.0"));
+        assert_eq!(report.results.len(), Check::ALL.len());
+        assert!(report.to_string().starts_with("PASS handshake ("));
+    }
+
+    // Declares a default above the maximum, plays e2e4 everywhere, and never
+    // stops an infinite search.
+    const SLOPPY_ENGINE: &str = r#"
+        while read -r line; do
+            case "$line" in
+                uci) echo "id name Sloppy"
+                     echo "option name Hash type spin default 2048 min 1 max 1024"
+                     echo "uciok";;
+                isready) echo "readyok";;
+                "go infinite") ;;
+                go*) echo "bestmove e2e4";;
+            esac
+        done
+    "#;
+
+    #[tokio::test]
+    async fn sloppy_engine_fails() {
+        let mut suite = ConformanceSuite::new();
+        suite.set_reply_timeout(Duration::from_millis(200));
+        let report = suite.run(Engine::new(fake_engine(SLOPPY_ENGINE))).await;
+        let verdicts: Vec<&Verdict> = report.results.iter().map(|r| &r.verdict).collect();
+        assert_eq!(
+            verdicts,
+            [
+                &Verdict::Pass,
+                &Verdict::Fail("Hash: default 2048 is not within min 1 and max 1024".to_string()),
+                &Verdict::Pass,
+                &Verdict::Pass,
+                &Verdict::Fail(format!("illegal bestmove e2e4 in {}", POSITIONS[1])),
+                &Verdict::Pass,
+                &Verdict::Fail("no bestmove within 200 ms of stop".to_string()),
+                &Verdict::Skip("the engine could not be restarted".to_string()),
+            ]
+        );
+        assert!(!report.is_pass());
+        assert_eq!(report.failures().count(), 3);
+    }
+
+    // Plays crazyhouse, where drops are legal, but declares no atomic.
+    const VARIANT_ENGINE: &str = r#"
+        while read -r line; do
+            case "$line" in
+                uci) echo "id name Fairy"
+                     echo "option name UCI_Variant type combo default chess var chess var crazyhouse"
+                     echo "uciok";;
+                isready) echo "readyok";;
+                "setoption name UCI_Variant value crazyhouse") variant=crazyhouse;;
+                "go infinite") ;;
+                stop) echo "bestmove e2e4";;
+                go*) if [ "$variant" = crazyhouse ]; then echo "bestmove P@e4"; else echo "bestmove a1a1"; fi;;
+            esac
+        done
+    "#;
+
+    #[tokio::test]
+    async fn variant_engine() {
+        let mut suite = ConformanceSuite::new();
+        suite.set_variant(Variant::Crazyhouse);
+        let report = suite.run(Engine::new(fake_engine(VARIANT_ENGINE))).await;
+        assert!(report.is_pass(), "{}", report);
+
+        suite.set_variant(Variant::Atomic);
+        let report = suite.run(Engine::new(fake_engine(VARIANT_ENGINE))).await;
+        assert_eq!(
+            report.verdict(Check::Handshake),
+            Some(&Verdict::Fail("variant atomic is not declared".to_string()))
+        );
+    }
+}
@@ -2,15 +2,16 @@
 // modules.
 
 use crate::err::UziErr;
+use std::fmt;
 use std::str::FromStr;
 use std::time::Duration;
 
 // A function to parse time as milliseconds, with parse errors mapped to thne
 // UziErr::BadMillis error.
 pub(crate) fn to_millis(word: &str, opt_name: &str) -> Result<Duration, UziErr> {
-    word.parse::<u64>()
-        .map_err(|_| UziErr::BadMillis(opt_name.into(), word.into()))
-        .map(|ms| Duration::from_millis(ms))
+    parse_unsigned(word.as_bytes())
+        .ok_or_else(|| UziErr::BadMillis(opt_name.into(), word.into()))
+        .map(Duration::from_millis)
 }
 
 // A function to parse a generic number which maps an error to a
@@ -20,7 +21,228 @@ pub(crate) fn to_number<T: FromStr>(word: &str) -> Result<T, UziErr> {
         .map_err(|_| UziErr::BadNumber(word.into()))
 }
 
+// Like to_number, for the unsigned numbers of infos, e.g. depth or nodes. An
+// engine can send thousands of infos per second, which are mostly numbers, so
+// these are parsed byte by byte rather than with str::parse.
+pub(crate) fn to_unsigned<T: TryFrom<u64>>(word: &str) -> Result<T, UziErr> {
+    parse_unsigned(word.as_bytes())
+        .and_then(|number| T::try_from(number).ok())
+        .ok_or_else(|| UziErr::BadNumber(word.into()))
+}
+
+// Like to_unsigned, for numbers that can be negative, e.g. scores.
+pub(crate) fn to_signed<T: TryFrom<i64>>(word: &str) -> Result<T, UziErr> {
+    parse_signed(word.as_bytes())
+        .and_then(|number| T::try_from(number).ok())
+        .ok_or_else(|| UziErr::BadNumber(word.into()))
+}
+
+// Parses what str::parse::<u64> does, i.e. digits with an optional plus sign.
+fn parse_unsigned(bytes: &[u8]) -> Option<u64> {
+    parse_digits(bytes.strip_prefix(b"+").unwrap_or(bytes))
+}
+
+// Parses what str::parse::<i64> does, i.e. digits with an optional sign.
+fn parse_signed(bytes: &[u8]) -> Option<i64> {
+    match bytes {
+        [b'-', digits @ ..] => 0i64.checked_sub_unsigned(parse_digits(digits)?),
+        [b'+', digits @ ..] => i64::try_from(parse_digits(digits)?).ok(),
+        digits => i64::try_from(parse_digits(digits)?).ok(),
+    }
+}
+
+// Parses one or more digits, or returns None if they overflow a u64. Numbers
+// of up to 19 digits, i.e. all of them in practice, can't overflow, so they
+// skip the checks.
+fn parse_digits(bytes: &[u8]) -> Option<u64> {
+    match bytes.len() {
+        0 => None,
+        1..=19 => bytes.iter().try_fold(0u64, |number, byte| {
+            let digit = byte.wrapping_sub(b'0');
+            (digit <= 9).then(|| number * 10 + u64::from(digit))
+        }),
+        _ => bytes.iter().try_fold(0u64, |number, byte| {
+            let digit = byte.wrapping_sub(b'0');
+            if digit > 9 {
+                return None;
+            }
+            number.checked_mul(10)?.checked_add(digit.into())
+        }),
+    }
+}
+
+// Writes a prefix, e.g. " depth ", followed by a number. Infos are mostly
+// numbers, and writing the digits from a buffer is a lot faster than write!,
+// which goes through the formatting machinery for every field. With the itoa
+// feature, the digits are written by itoa, which is faster still.
+pub(crate) fn write_number(out: &mut impl fmt::Write, prefix: &str, number: u64) -> fmt::Result {
+    out.write_str(prefix)?;
+    #[cfg(feature = "itoa")]
+    return out.write_str(itoa::Buffer::new().format(number));
+    #[cfg(not(feature = "itoa"))]
+    out.write_str(digits(number, false, &mut [0; 21]))
+}
+
+// Like write_number, for numbers that can be negative, e.g. scores.
+pub(crate) fn write_signed(out: &mut impl fmt::Write, prefix: &str, number: i64) -> fmt::Result {
+    out.write_str(prefix)?;
+    #[cfg(feature = "itoa")]
+    return out.write_str(itoa::Buffer::new().format(number));
+    #[cfg(not(feature = "itoa"))]
+    out.write_str(digits(number.unsigned_abs(), number < 0, &mut [0; 21]))
+}
+
+// Like write_number, for times, which are written in milliseconds.
+pub(crate) fn write_millis(out: &mut impl fmt::Write, prefix: &str, time: Duration) -> fmt::Result {
+    let millis = u64::try_from(time.as_millis()).unwrap_or(u64::MAX);
+    write_number(out, prefix, millis)
+}
+
+// Writes the digits of a number to the end of buf, and returns them.
+#[cfg(not(feature = "itoa"))]
+fn digits(mut number: u64, is_negative: bool, buf: &mut [u8; 21]) -> &str {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (number % 10) as u8;
+        number /= 10;
+        if number == 0 {
+            break;
+        }
+    }
+    if is_negative {
+        start -= 1;
+        buf[start] = b'-';
+    }
+    // The buffer only holds ASCII digits and the sign.
+    std::str::from_utf8(&buf[start..]).unwrap_or_default()
+}
+
 // A function to parse a bool and map the error to UziErr::BadBool.
 pub(crate) fn to_bool(word: &str) -> Result<bool, UziErr> {
     bool::from_str(word).map_err(|_| UziErr::BadBool)
 }
+
+// Splits a line into words like split_whitespace, but can also hand out the
+// parts of the line it spans, for the borrowed commands.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Words<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Words<'a> {
+    pub fn new(line: &'a str) -> Self {
+        Self { rest: line }
+    }
+
+    // Returns the part of the line that hasn't been read, trimmed.
+    pub fn rest(&self) -> &'a str {
+        self.rest.trim()
+    }
+
+    // Returns the next word without reading it.
+    pub fn peek(&self) -> Option<&'a str> {
+        let mut words = *self;
+        words.next()
+    }
+
+    // Returns the part of the line read since start, trimmed.
+    pub fn since(&self, start: Words<'a>) -> &'a str {
+        start.rest[..start.rest.len() - self.rest.len()].trim()
+    }
+}
+
+impl<'a> Iterator for Words<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        // Commands are ASCII, so the bytes are checked first, and the rest of a
+        // line with other characters is split like split_whitespace would.
+        let start = self
+            .rest
+            .bytes()
+            .position(|byte| !is_ascii_space(byte))
+            .unwrap_or(self.rest.len());
+        let rest = match self.rest.as_bytes().get(start) {
+            Some(byte) if !byte.is_ascii() => self.rest[start..].trim_start(),
+            _ => &self.rest[start..],
+        };
+        let end = match rest
+            .bytes()
+            .position(|byte| is_ascii_space(byte) || !byte.is_ascii())
+        {
+            Some(end) if !rest.as_bytes()[end].is_ascii() => rest[end..]
+                .find(char::is_whitespace)
+                .map_or(rest.len(), |len| end + len),
+            Some(end) => end,
+            None => rest.len(),
+        };
+        let (word, rest) = rest.split_at(end);
+        self.rest = rest;
+        (!word.is_empty()).then_some(word)
+    }
+}
+
+// Whether the byte is one of the ASCII characters that char::is_whitespace
+// accepts.
+fn is_ascii_space(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t'..=b'\r')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_parse_like_str_parse() {
+        let words = [
+            "0",
+            "+7",
+            "-7",
+            "65535",
+            "65536",
+            "-32768",
+            "18446744073709551615",
+            "18446744073709551616",
+            "-9223372036854775808",
+            "9223372036854775808",
+            "",
+            "+",
+            "-",
+            "+-1",
+            "1e3",
+            "12 ",
+            "\u{664}",
+        ];
+        for word in words {
+            assert_eq!(to_unsigned::<u16>(word).ok(), word.parse().ok(), "{}", word);
+            assert_eq!(to_unsigned::<u64>(word).ok(), word.parse().ok(), "{}", word);
+            assert_eq!(to_signed::<i16>(word).ok(), word.parse().ok(), "{}", word);
+            assert_eq!(to_signed::<i64>(word).ok(), word.parse().ok(), "{}", word);
+        }
+        assert_eq!(
+            to_unsigned::<u16>("many"),
+            Err(UziErr::BadNumber("many".into()))
+        );
+    }
+
+    #[test]
+    fn words_split_like_split_whitespace() {
+        let lines = [
+            "info depth 1 pv e2e4",
+            "  info\tstring  two  spaces \r\n",
+            "info string caf\u{e9}\u{a0}au\u{2003}lait \u{b}x",
+            "\u{a0}\u{e9}",
+            "",
+        ];
+        for line in lines {
+            let words = Words::new(line).collect::<Vec<_>>();
+            assert_eq!(
+                words,
+                line.split_whitespace().collect::<Vec<_>>(),
+                "{:?}",
+                line
+            );
+        }
+    }
+}
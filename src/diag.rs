@@ -0,0 +1,214 @@
+// This module contains the diagnostic commands of Stockfish, which many other
+// engines copied, and their replies. They are not part of UCI, and the replies
+// are free text rather than commands:
+// - eval - prints the static evaluation of the current position, ending with
+//   its final value.
+// - compiler - prints the compiler the engine was built with, and its settings.
+// - flip - mirrors the current position and swaps the colors, which is used to
+//   check that the evaluation is symmetric. There is no reply.
+
+use crate::err::UziErr;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DiagCmd {
+    Eval,
+    Compiler,
+    Flip,
+}
+
+impl DiagCmd {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagCmd::Eval => "eval",
+            DiagCmd::Compiler => "compiler",
+            DiagCmd::Flip => "flip",
+        }
+    }
+}
+
+impl Display for DiagCmd {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DiagCmd {
+    type Err = UziErr;
+
+    fn from_str(cmd: &str) -> Result<DiagCmd, Self::Err> {
+        match cmd.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [] => Err(UziErr::MissingCmd),
+            ["eval"] => Ok(DiagCmd::Eval),
+            ["compiler"] => Ok(DiagCmd::Compiler),
+            ["flip"] => Ok(DiagCmd::Flip),
+            _ => Err(UziErr::What),
+        }
+    }
+}
+
+// The reply to eval. Stockfish ends it with e.g. "Final evaluation +0.08
+// (white side)", and older versions with "Total evaluation: 0.08 (white side)".
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct EvalReport {
+    // The lines before the final evaluation, e.g. the evaluation terms or the
+    // NNUE tables.
+    pub details: Vec<String>,
+    // The evaluation from the point of view of white, in centipawns, or None
+    // if the engine doesn't evaluate the position, e.g. when it is in check.
+    pub final_eval: Option<i32>,
+}
+
+impl Display for EvalReport {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        for line in &self.details {
+            writeln!(formatter, "{}", line)?;
+        }
+        match self.final_eval {
+            Some(cp) => write!(
+                formatter,
+                "Final evaluation {}{}.{:02} (white side)",
+                if cp < 0 { "-" } else { "+" },
+                cp.abs() / 100,
+                cp.abs() % 100
+            ),
+            None => formatter.write_str("Final evaluation: none (in check)"),
+        }
+    }
+}
+
+impl FromStr for EvalReport {
+    type Err = UziErr;
+
+    fn from_str(text: &str) -> Result<EvalReport, Self::Err> {
+        let lines: Vec<&str> = text.lines().collect();
+        let last = lines
+            .iter()
+            .rposition(|line| final_value(line).is_some())
+            .ok_or(UziErr::BadDiagnostic)?;
+        let value = final_value(lines[last]).unwrap_or_default();
+        let final_eval = match value.split_whitespace().next() {
+            Some("none") => None,
+            Some(pawns) => Some(to_centipawns(pawns)?),
+            None => return Err(UziErr::BadDiagnostic),
+        };
+        Ok(EvalReport {
+            details: lines[..last].iter().map(|line| line.to_string()).collect(),
+            final_eval,
+        })
+    }
+}
+
+// Returns what follows the label of the final evaluation line, if it is one.
+fn final_value(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let value = line
+        .strip_prefix("Final evaluation")
+        .or_else(|| line.strip_prefix("Total evaluation"))?;
+    Some(value.trim_start().trim_start_matches(':'))
+}
+
+// Converts pawns, e.g. +0.08, to centipawns.
+fn to_centipawns(pawns: &str) -> Result<i32, UziErr> {
+    let pawns: f64 = pawns.parse().map_err(|_| UziErr::BadDiagnostic)?;
+    Ok((pawns * 100.0).round() as i32)
+}
+
+// The reply to compiler. Stockfish starts it with e.g. "Compiled by g++
+// (GNUC) 13.2.0 on Linux", with a colon after the label in newer versions.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CompilerInfo {
+    // The compiler and the platform, e.g. g++ (GNUC) 13.2.0 on Linux.
+    pub compiler: String,
+    // The lines after the compiler, e.g. the architecture and the settings.
+    pub details: Vec<String>,
+}
+
+impl Display for CompilerInfo {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(formatter, "Compiled by {}", self.compiler)?;
+        for line in &self.details {
+            write!(formatter, "\n{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CompilerInfo {
+    type Err = UziErr;
+
+    fn from_str(text: &str) -> Result<CompilerInfo, Self::Err> {
+        let mut lines = text
+            .lines()
+            .skip_while(|line| !line.trim_start().starts_with("Compiled by"));
+        let compiler = lines
+            .next()
+            .and_then(|line| line.trim_start().strip_prefix("Compiled by"))
+            .map(|compiler| compiler.trim_start().trim_start_matches(':').trim())
+            .filter(|compiler| !compiler.is_empty())
+            .ok_or(UziErr::BadDiagnostic)?;
+        Ok(CompilerInfo {
+            compiler: compiler.to_string(),
+            details: lines.map(|line| line.to_string()).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diag_cmds() {
+        for cmd in [DiagCmd::Eval, DiagCmd::Compiler, DiagCmd::Flip] {
+            assert_eq!(DiagCmd::from_str(&cmd.to_string()), Ok(cmd));
+        }
+        assert_eq!(DiagCmd::from_str(" "), Err(UziErr::MissingCmd));
+        assert_eq!(DiagCmd::from_str("eval now"), Err(UziErr::What));
+    }
+
+    #[test]
+    fn eval_report() {
+        let text = " NNUE network contributions (White to move)\n\
+                    Classical evaluation   +0.12 (white side)\n\
+                    Final evaluation       -1.05 (white side) [with scaled NNUE, ...]";
+        let report = EvalReport::from_str(text).unwrap();
+        assert_eq!(report.final_eval, Some(-105));
+        assert_eq!(report.details.len(), 2);
+        assert_eq!(EvalReport::from_str(&report.to_string()), Ok(report));
+
+        let report = EvalReport::from_str("Total evaluation: 0.08 (white side)").unwrap();
+        assert_eq!(report.final_eval, Some(8));
+        assert!(report.details.is_empty());
+        let report = EvalReport::from_str("Final evaluation: none (in check)").unwrap();
+        assert_eq!(report.final_eval, None);
+        assert_eq!(EvalReport::from_str(&report.to_string()), Ok(report));
+
+        assert_eq!(
+            EvalReport::from_str("Unknown command: 'eval'"),
+            Err(UziErr::BadDiagnostic)
+        );
+        assert_eq!(
+            EvalReport::from_str("Final evaluation: huge"),
+            Err(UziErr::BadDiagnostic)
+        );
+    }
+
+    #[test]
+    fn compiler_info() {
+        let text = "Compiled by                : g++ (GNUC) 13.2.0 on Linux\n\
+                    Compilation architecture   : x86-64-avx2";
+        let info = CompilerInfo::from_str(text).unwrap();
+        assert_eq!(info.compiler, "g++ (GNUC) 13.2.0 on Linux");
+        assert_eq!(info.details, ["Compilation architecture   : x86-64-avx2"]);
+        assert_eq!(CompilerInfo::from_str(&info.to_string()), Ok(info));
+
+        let info = CompilerInfo::from_str("Compiled by clang++ 17.0.1 on Apple").unwrap();
+        assert_eq!(info.compiler, "clang++ 17.0.1 on Apple");
+        assert_eq!(
+            CompilerInfo::from_str("Compiled by"),
+            Err(UziErr::BadDiagnostic)
+        );
+    }
+}
@@ -5,7 +5,7 @@ use crate::conf::Config;
 use crate::engtx::EngOutTx;
 use crate::err::UziErr;
 use crate::guicmd::{Go, GuiCmd, Pos};
-use crate::opt::{Opponent, PosValueOpt, SetOpt};
+use crate::opt::{Opponent, PosValueOpt, SetOpt, Variant};
 use crate::types::SpinType;
 use std::cmp::PartialOrd;
 use std::io::stdin;
@@ -28,6 +28,7 @@ pub trait Eng {
     fn shredder_bases(&mut self, path: &Path) -> Result<(), UziErr>;
     fn opponent(&mut self, opponent: &Opponent) -> Result<(), UziErr>;
     fn pos_val(&mut self, pos_val: &PosValueOpt) -> Result<(), UziErr>;
+    fn variant(&mut self, variant: &Variant) -> Result<(), UziErr>;
     fn position(&mut self, pos: &Pos) -> Result<(), UziErr>;
     fn go(&mut self, go_cmd: &Go) -> Result<(), UziErr>;
     fn stop(&mut self) -> Result<(), UziErr>;
@@ -46,9 +47,9 @@ struct EngCon<E: Eng, O: EngOutTx> {
 impl<E: Eng, O: EngOutTx> EngCon<E, O> {
     pub fn create(eng: E, eng_out: Arc<O>, conf: Config) -> Self {
         Self {
-            eng: eng,
-            eng_out: eng_out,
-            conf: conf,
+            eng,
+            eng_out,
+            conf,
             state: EngState::Waiting,
         }
     }
@@ -86,25 +87,25 @@ impl<E: Eng, O: EngOutTx> EngCon<E, O> {
             GuiCmd::Debug(_is_enabled) => todo!(),
             GuiCmd::SetOpt(opt) if self.state.is_connected_or_game() => self.set_opt(opt),
             GuiCmd::NewGame if !self.state.is_waiting() => {
-                if let Err(_) = self.eng.new_game() {
+                if self.eng.new_game().is_err() {
                     // TODO: Log some error here.
                 }
                 self.state = EngState::NewGame;
             }
             GuiCmd::Pos(pos) if self.state.is_new_game() => {
-                if let Err(_) = self.eng.position(&pos) {
+                if self.eng.position(&pos).is_err() {
                     // TODO: Log some error here.
                 }
                 self.state = EngState::GamePosition;
             }
             GuiCmd::Go(go) if self.state.is_game_position() => {
-                if let Err(_) = self.eng.go(&go) {
+                if self.eng.go(&go).is_err() {
                     // TODO: Log some error here.
                 }
                 self.state = EngState::Go;
             }
             GuiCmd::Stop if self.state.is_go() => {
-                if let Err(_) = self.eng.stop() {
+                if self.eng.stop().is_err() {
                     // TODO: Log some error here.
                 }
                 self.state = EngState::GamePosition;
@@ -173,6 +174,17 @@ impl<E: Eng, O: EngOutTx> EngCon<E, O> {
                     self.eng.pos_val(x)
                 })
             }
+            SetOpt::Variant(variant) => {
+                // Only the variants the engine declared are supported.
+                let is_supported = self
+                    .conf
+                    .variant
+                    .as_ref()
+                    .is_some_and(|t| t.var.iter().any(|v| v == variant.as_str()));
+                set_opt_val(variant, is_supported, |x| self.eng.variant(x))
+            }
+            // TODO: log that custom options are not supported.
+            SetOpt::Custom { .. } => (),
         }
     }
 }
@@ -227,9 +239,8 @@ where
         return;
     }
 
-    if let Err(_) = setter_fn(&val) {
+    if setter_fn(&val).is_err() {
         // TODO: Log some error here.
-        return;
     }
 }
 
@@ -242,9 +253,8 @@ where
         return;
     }
 
-    if let Err(_) = setter_fn(enabled) {
+    if setter_fn(enabled).is_err() {
         // TODO: Log some error here.
-        return;
     }
 }
 
@@ -261,9 +271,8 @@ where
                 // TODO: Log that value is out of range.
                 return;
             }
-            if let Err(_) = setter_fn(val) {
+            if setter_fn(val).is_err() {
                 // TODO: Log some error here.
-                return;
             }
         }
     }
@@ -1,17 +1,23 @@
 // This module contains the types to represent commands from the chess engine to
 // a GUI.
 
+use crate::conv::{
+    to_millis, to_signed, to_unsigned, write_millis, write_number, write_signed, Words,
+};
+use crate::err::UziErr;
+use crate::guicmd::Pos;
 use crate::opt::HasOpt;
-use crate::pm::Pm;
+use crate::pm::{write_moves, MoveList, MovesRef, Pm};
+use std::cmp::Ordering;
 use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
 use std::time::Duration;
 
-// Represents a command from the engine to the GUI.
-// TODO: support copyprotection, registration, and custom commands:
-// - copyprotection: Used by copyprotected engines.
-// - registration [ok | error]: Needed for engines that need a username and or a
-//   code to function
-// with all the features.
+// Represents a command from the engine to the GUI. Infos are most of the
+// commands, so they are not boxed, even though their moves make them large
+// when they are stored inline.
+// TODO: support custom commands.
+#[cfg_attr(feature = "smallvec", allow(clippy::large_enum_variant))]
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum EngCmd {
     // id name <x>: The name and version of the chess engine, as response to
@@ -42,6 +48,59 @@ pub enum EngCmd {
     // option name <id> [opts..]: To tell the engine which options can be
     // changed.
     HasOpt(HasOpt),
+    // copyprotection <status>: Used by copyprotected engines, which send
+    // checking after uciok, followed by ok or error.
+    CopyProtection(CheckStatus),
+    // registration <status>: Used by engines that need a username and or a
+    // code to function with all the features. They send checking after uciok
+    // and after register, followed by ok or error.
+    Registration(CheckStatus),
+}
+
+// The state of the copyprotection and registration checks.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CheckStatus {
+    Checking,
+    Ok,
+    Error,
+}
+
+impl CheckStatus {
+    // Returns Ok if the check passed, and Error otherwise.
+    pub fn from_result(is_ok: bool) -> Self {
+        if is_ok {
+            CheckStatus::Ok
+        } else {
+            CheckStatus::Error
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckStatus::Checking => "checking",
+            CheckStatus::Ok => "ok",
+            CheckStatus::Error => "error",
+        }
+    }
+}
+
+impl FromStr for CheckStatus {
+    type Err = UziErr;
+
+    fn from_str(status: &str) -> Result<CheckStatus, Self::Err> {
+        match status {
+            "checking" => Ok(CheckStatus::Checking),
+            "ok" => Ok(CheckStatus::Ok),
+            "error" => Ok(CheckStatus::Error),
+            _ => Err(UziErr::StatusErr),
+        }
+    }
+}
+
+impl Display for CheckStatus {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
 }
 
 impl Display for EngCmd {
@@ -53,6 +112,8 @@ impl Display for EngCmd {
             EngCmd::IdAuthor(ref author) => write!(formatter, "id author {}", author),
             EngCmd::Info(ref info) => info.fmt(formatter),
             EngCmd::HasOpt(ref has_opt) => has_opt.fmt(formatter),
+            EngCmd::CopyProtection(status) => write!(formatter, "copyprotection {}", status),
+            EngCmd::Registration(status) => write!(formatter, "registration {}", status),
             EngCmd::BestMove { best, ponder } => {
                 write!(formatter, "bestmove {}", best)?;
                 if let Some(pm) = ponder {
@@ -64,6 +125,66 @@ impl Display for EngCmd {
     }
 }
 
+// Parses a line sent by the engine to the GUI. This is the counterpart of the
+// Display implementation, used by clients talking to an engine.
+impl FromStr for EngCmd {
+    type Err = UziErr;
+
+    fn from_str(cmd: &str) -> Result<EngCmd, Self::Err> {
+        let Some(name) = cmd.split_whitespace().next() else {
+            return Err(UziErr::MissingCmd);
+        };
+        match name {
+            "uciok" => Ok(EngCmd::UciOk),
+            "readyok" => Ok(EngCmd::ReadyOk),
+            // Infos are the most frequent commands, and they are parsed from
+            // the line as it is, without splitting it first.
+            "info" => Ok(EngCmd::Info(parse_info(cmd)?)),
+            "id" | "bestmove" | "option" | "copyprotection" | "registration" => {
+                let words = cmd.split_whitespace().collect::<Vec<_>>();
+                match words.as_slice() {
+                    ["id", "name", name @ ..] if !name.is_empty() => {
+                        Ok(EngCmd::IdName(name.join(" ")))
+                    }
+                    ["id", "author", author @ ..] if !author.is_empty() => {
+                        Ok(EngCmd::IdAuthor(author.join(" ")))
+                    }
+                    ["id", ..] => Err(UziErr::IdErr),
+                    ["bestmove", ..] => parse_best_move(&words),
+                    ["option", ..] => Ok(EngCmd::HasOpt(HasOpt::try_from(words.as_slice())?)),
+                    [_, status] if name == "copyprotection" => {
+                        Ok(EngCmd::CopyProtection(status.parse()?))
+                    }
+                    [_, status] => Ok(EngCmd::Registration(status.parse()?)),
+                    _ => Err(UziErr::StatusErr),
+                }
+            }
+            _ => Err(UziErr::What),
+        }
+    }
+}
+
+// Parses "bestmove <move1> [ponder <move2>]". Some engines reply with
+// "bestmove (none)" when there are no legal moves, which is mapped to a null
+// move.
+fn parse_best_move(words: &[&str]) -> Result<EngCmd, UziErr> {
+    let to_pm = |word: &str| match word {
+        "(none)" => Ok(Pm::Null),
+        _ => Pm::from_str(word).map_err(|_| UziErr::BestMoveErr),
+    };
+    match words {
+        [_, best] => Ok(EngCmd::BestMove {
+            best: to_pm(best)?,
+            ponder: None,
+        }),
+        [_, best, "ponder", ponder] => Ok(EngCmd::BestMove {
+            best: to_pm(best)?,
+            ponder: Some(to_pm(ponder)?),
+        }),
+        _ => Err(UziErr::BestMoveErr),
+    }
+}
+
 // Represents the various options to encode the "info" command, when the engine
 // wants to send information to the GUI. This should be done whenever one of the
 // info has changed. The engine can send only selected infos or mutliple infos
@@ -72,7 +193,7 @@ impl Display for EngCmd {
 // sent together, e.g. "info depth 2 score cp 214 time 1242 nodes 2124 nps 34928
 // pv e2e4 e7e5 g1f3". Suggest to send "currmove", "currmovenumber", "currline",
 // and "refutation" only after 1 second to avoid too much traffic.
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct Info {
     // depth <x>: Search depth in plies.
     depth: Option<u16>,
@@ -81,15 +202,15 @@ pub struct Info {
     // "seldepth", there must also be a "depth" present in the same string.
     sel_depth: Option<u16>,
 
-    // node <x>: x nodes searched. The engine should send this info regularly.
-    node: Option<u32>,
+    // nodes <x>: x nodes searched. The engine should send this info regularly.
+    node: Option<u64>,
 
     // time <x>: The time searched in ms. This should be sent together with the
     // PV.
     time: Option<Duration>,
 
     // pv <move1> .. <movei>: The best line found.
-    pv: Option<Vec<Pm>>,
+    pv: Option<MoveList>,
 
     // multipv <num>: This for the multipv mode. For the best move/pv add
     // "multipv 1" in the string when you send the pv. In k-best mode always
@@ -102,19 +223,23 @@ pub struct Info {
     // currmove <move>: Currently searching this move.
     curr_move: Option<Pm>,
 
+    // currmovenumber <x>: Currently searching move number x, for the first
+    // move x should be 1 not 0.
+    curr_move_num: Option<u16>,
+
     // hashfull <x>: The hashfull is x permill full. The engine should send this
     // info regularly.
     hash_full: Option<u16>,
 
     // nps <x>: x nodes per second searched. The engine should send this info
     // regularly.
-    nodes_per_sec: Option<u32>,
+    nodes_per_sec: Option<u64>,
 
     // tbhits <x>: x positions where found in the endgame table base.
-    tb_hits: Option<u32>,
+    tb_hits: Option<u64>,
 
     // sbhits <x>: x positions where found in the shredder endgame databases.
-    sb_hits: Option<u32>,
+    sb_hits: Option<u64>,
 
     // cpuload <x>: The CPU usage of the engine is <x> permill.
     cpu_load: Option<u16>,
@@ -133,26 +258,242 @@ pub struct Info {
     curr_line: Option<CurrLine>,
 }
 
+// The fields of an info, see Info::remove.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum InfoField {
+    Depth,
+    SelDepth,
+    Nodes,
+    Time,
+    Pv,
+    MultiPv,
+    Score,
+    CurrMove,
+    CurrMoveNum,
+    HashFull,
+    NodesPerSec,
+    TbHits,
+    SbHits,
+    CpuLoad,
+    String,
+    Refutation,
+    CurrLine,
+}
+
+impl InfoField {
+    // Returns the field of the key of an info line, e.g. NodesPerSec for nps.
+    fn from_key(key: &str) -> Option<Self> {
+        let field = match key {
+            "depth" => Self::Depth,
+            "seldepth" => Self::SelDepth,
+            "nodes" => Self::Nodes,
+            "time" => Self::Time,
+            "pv" => Self::Pv,
+            "multipv" => Self::MultiPv,
+            "score" => Self::Score,
+            "currmove" => Self::CurrMove,
+            "currmovenumber" => Self::CurrMoveNum,
+            "hashfull" => Self::HashFull,
+            "nps" => Self::NodesPerSec,
+            "tbhits" => Self::TbHits,
+            "sbhits" => Self::SbHits,
+            "cpuload" => Self::CpuLoad,
+            "string" => Self::String,
+            "refutation" => Self::Refutation,
+            "currline" => Self::CurrLine,
+            _ => return None,
+        };
+        Some(field)
+    }
+}
+
+impl Info {
+    // Creates an info that only carries a message, i.e. info string <string>.
+    pub fn from_string(string: &str) -> Self {
+        Self {
+            string: Some(string.into()),
+            ..Self::default()
+        }
+    }
+
+    pub fn depth(&self) -> Option<u16> {
+        self.depth
+    }
+
+    pub fn sel_depth(&self) -> Option<u16> {
+        self.sel_depth
+    }
+
+    pub fn nodes(&self) -> Option<u64> {
+        self.node
+    }
+
+    pub fn time(&self) -> Option<Duration> {
+        self.time
+    }
+
+    pub fn pv(&self) -> Option<&[Pm]> {
+        self.pv.as_deref()
+    }
+
+    // The rank of the line in multipv mode, starting at 1.
+    pub fn multi_pv(&self) -> Option<u64> {
+        self.multi_pv.as_ref().map(|multi_pv| multi_pv.rank)
+    }
+
+    pub fn score(&self) -> Option<Score> {
+        self.score
+    }
+
+    pub fn curr_move(&self) -> Option<Pm> {
+        self.curr_move
+    }
+
+    pub fn curr_move_num(&self) -> Option<u16> {
+        self.curr_move_num
+    }
+
+    pub fn hash_full(&self) -> Option<u16> {
+        self.hash_full
+    }
+
+    pub fn nodes_per_sec(&self) -> Option<u64> {
+        self.nodes_per_sec
+    }
+
+    pub fn tb_hits(&self) -> Option<u64> {
+        self.tb_hits
+    }
+
+    pub fn sb_hits(&self) -> Option<u64> {
+        self.sb_hits
+    }
+
+    pub fn cpu_load(&self) -> Option<u16> {
+        self.cpu_load
+    }
+
+    pub fn string(&self) -> Option<&str> {
+        self.string.as_deref()
+    }
+
+    pub fn refutation(&self) -> Option<&Refutation> {
+        self.refutation.as_ref()
+    }
+
+    pub fn curr_line(&self) -> Option<&CurrLine> {
+        self.curr_line.as_ref()
+    }
+
+    // Removes the field from the info, e.g. the currline of an engine that
+    // sends too many.
+    pub fn remove(&mut self, field: InfoField) {
+        match field {
+            InfoField::Depth => self.depth = None,
+            InfoField::SelDepth => self.sel_depth = None,
+            InfoField::Nodes => self.node = None,
+            InfoField::Time => self.time = None,
+            InfoField::Pv => self.pv = None,
+            InfoField::MultiPv => self.multi_pv = None,
+            InfoField::Score => self.score = None,
+            InfoField::CurrMove => self.curr_move = None,
+            InfoField::CurrMoveNum => self.curr_move_num = None,
+            InfoField::HashFull => self.hash_full = None,
+            InfoField::NodesPerSec => self.nodes_per_sec = None,
+            InfoField::TbHits => self.tb_hits = None,
+            InfoField::SbHits => self.sb_hits = None,
+            InfoField::CpuLoad => self.cpu_load = None,
+            InfoField::String => self.string = None,
+            InfoField::Refutation => self.refutation = None,
+            InfoField::CurrLine => self.curr_line = None,
+        }
+    }
+
+    // Returns true if the info has no fields left.
+    pub fn is_empty(&self) -> bool {
+        *self == Info::default()
+    }
+
+    // Parses an info line into this info, replacing its fields. The vector of
+    // the pv is reused, so that a client that parses every info of a search
+    // into a few recycled ones doesn't allocate a pv for every update. On
+    // error, the info is left with some of the fields of the line.
+    pub fn parse_into(&mut self, line: &str) -> Result<(), UziErr> {
+        let mut pv = self.pv.take().unwrap_or_default();
+        match InfoRef::parse_with_pv(line, Some(&mut pv)) {
+            Ok(info) => {
+                *self = Info::from_ref(info, info.pv.map(|_| pv));
+                Ok(())
+            }
+            Err(err) => {
+                self.pv = Some(pv);
+                Err(err)
+            }
+        }
+    }
+
+    // Copies the borrowed info, whose pv, if it has one, is already parsed
+    // into pv.
+    fn from_ref(info: InfoRef<'_>, pv: Option<MoveList>) -> Self {
+        Info {
+            depth: info.depth,
+            sel_depth: info.sel_depth,
+            node: info.node,
+            time: info.time,
+            pv,
+            multi_pv: info.multi_pv.map(|rank| MultiPv {
+                rank,
+                moves: MoveList::new(),
+            }),
+            score: info.score,
+            curr_move: info.curr_move,
+            curr_move_num: info.curr_move_num,
+            hash_full: info.hash_full,
+            nodes_per_sec: info.nodes_per_sec,
+            tb_hits: info.tb_hits,
+            sb_hits: info.sb_hits,
+            cpu_load: info.cpu_load,
+            // The words of the string are joined with single spaces.
+            string: info.string.map(|string| {
+                let mut joined = String::with_capacity(string.len());
+                for word in string.split_whitespace() {
+                    if !joined.is_empty() {
+                        joined.push(' ');
+                    }
+                    joined.push_str(word);
+                }
+                joined
+            }),
+            refutation: info.refutation.map(|(refuted_move, moves)| Refutation {
+                refuted_move,
+                moves: moves.iter().collect(),
+            }),
+            curr_line: info.curr_line.map(|(cpu_id, line)| CurrLine {
+                cpu_id,
+                line: line.iter().collect(),
+            }),
+        }
+    }
+}
+
 impl Display for Info {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
-        write!(formatter, "info")?;
+        formatter.write_str("info")?;
         if let Some(depth) = self.depth {
-            write!(formatter, " depth {}", depth)?;
+            write_number(formatter, " depth ", depth.into())?;
         }
         if let Some(sel_depth) = self.sel_depth {
-            write!(formatter, " seldepth {}", sel_depth)?;
+            write_number(formatter, " seldepth ", sel_depth.into())?;
         }
         if let Some(node) = self.node {
-            write!(formatter, " node {}", node)?;
+            write_number(formatter, " nodes ", node)?;
         }
         if let Some(time) = self.time {
-            write!(formatter, " time {}", time.as_millis())?;
+            write_millis(formatter, " time ", time)?;
         }
         if let Some(ref pv) = self.pv {
-            write!(formatter, " pv")?;
-            for pm in pv {
-                write!(formatter, " {}", pm)?;
-            }
+            formatter.write_str(" pv")?;
+            write_moves(formatter, pv)?;
         }
         if let Some(ref multi_pv) = self.multi_pv {
             write!(formatter, " {}", multi_pv)?;
@@ -163,20 +504,23 @@ impl Display for Info {
         if let Some(curr_move) = self.curr_move {
             write!(formatter, " currmove {}", curr_move)?;
         }
+        if let Some(curr_move_num) = self.curr_move_num {
+            write_number(formatter, " currmovenumber ", curr_move_num.into())?;
+        }
         if let Some(hash_full) = self.hash_full {
-            write!(formatter, " hashfull {}", hash_full)?;
+            write_number(formatter, " hashfull ", hash_full.into())?;
         }
         if let Some(nps) = self.nodes_per_sec {
-            write!(formatter, " nps {}", nps)?;
+            write_number(formatter, " nps ", nps)?;
         }
         if let Some(tb_hits) = self.tb_hits {
-            write!(formatter, " tbhits {}", tb_hits)?;
+            write_number(formatter, " tbhits ", tb_hits)?;
         }
         if let Some(sb_hits) = self.sb_hits {
-            write!(formatter, " sbhits {}", sb_hits)?;
+            write_number(formatter, " sbhits ", sb_hits)?;
         }
         if let Some(cpu_load) = self.cpu_load {
-            write!(formatter, " cpuload {}", cpu_load)?;
+            write_number(formatter, " cpuload ", cpu_load.into())?;
         }
         if let Some(ref string) = self.string {
             write!(formatter, " string {}", string)?;
@@ -191,6 +535,371 @@ impl Display for Info {
     }
 }
 
+impl TryFrom<&[&str]> for Info {
+    type Error = UziErr;
+
+    fn try_from(cmd: &[&str]) -> Result<Info, Self::Error> {
+        parse_info(&cmd.join(" "))
+    }
+}
+
+// Parses an info line, starting with info.
+fn parse_info(line: &str) -> Result<Info, UziErr> {
+    let mut info = Info::default();
+    info.parse_into(line)?;
+    Ok(info)
+}
+
+// A borrowed info, whose string and moves are slices of the line it was parsed
+// from, so that proxies and loggers can read infos without allocating. It is
+// parsed like Info, except that the string keeps its spacing, and to_info
+// converts the ones worth keeping.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct InfoRef<'a> {
+    depth: Option<u16>,
+    sel_depth: Option<u16>,
+    node: Option<u64>,
+    time: Option<Duration>,
+    pv: Option<MovesRef<'a>>,
+    multi_pv: Option<u64>,
+    score: Option<Score>,
+    curr_move: Option<Pm>,
+    curr_move_num: Option<u16>,
+    hash_full: Option<u16>,
+    nodes_per_sec: Option<u64>,
+    tb_hits: Option<u64>,
+    sb_hits: Option<u64>,
+    cpu_load: Option<u16>,
+    string: Option<&'a str>,
+    // The refuted move and the moves that refute it.
+    refutation: Option<(Pm, MovesRef<'a>)>,
+    // The cpu number and the line.
+    curr_line: Option<(Option<u16>, MovesRef<'a>)>,
+}
+
+impl<'a> InfoRef<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, UziErr> {
+        Self::parse_with_pv(line, None)
+    }
+
+    // Like parse, also adding the moves of the pv to pv, if there is one, so
+    // that Info doesn't parse them again.
+    fn parse_with_pv(line: &'a str, mut pv: Option<&mut MoveList>) -> Result<Self, UziErr> {
+        let mut words = Words::new(line);
+        if words.next() != Some("info") {
+            return Err(UziErr::InfoErr);
+        }
+        let mut info = InfoRef::default();
+        while let Some(word) = words.next() {
+            // Skip anything we don't understand, e.g. "wdl", to be lenient
+            // with engines that extend the protocol.
+            if let Some(field) = InfoField::from_key(word) {
+                info.read(field, &mut words, pv.as_deref_mut())?;
+            }
+        }
+        Ok(info)
+    }
+
+    // Reads the value of the field from the words after its key.
+    fn read(
+        &mut self,
+        field: InfoField,
+        words: &mut Words<'a>,
+        pv: Option<&mut MoveList>,
+    ) -> Result<(), UziErr> {
+        let mut value = || words.next().ok_or(UziErr::InfoErr);
+        match field {
+            InfoField::Depth => self.depth = Some(to_unsigned(value()?)?),
+            InfoField::SelDepth => self.sel_depth = Some(to_unsigned(value()?)?),
+            InfoField::Nodes => self.node = Some(to_unsigned(value()?)?),
+            InfoField::Time => self.time = Some(to_millis(value()?, "time")?),
+            InfoField::MultiPv => self.multi_pv = Some(to_unsigned(value()?)?),
+            InfoField::CurrMove => self.curr_move = Some(Pm::from_str(value()?)?),
+            InfoField::CurrMoveNum => self.curr_move_num = Some(to_unsigned(value()?)?),
+            InfoField::HashFull => self.hash_full = Some(to_unsigned(value()?)?),
+            InfoField::NodesPerSec => self.nodes_per_sec = Some(to_unsigned(value()?)?),
+            InfoField::TbHits => self.tb_hits = Some(to_unsigned(value()?)?),
+            InfoField::SbHits => self.sb_hits = Some(to_unsigned(value()?)?),
+            InfoField::CpuLoad => self.cpu_load = Some(to_unsigned(value()?)?),
+            InfoField::Score => self.score = Some(parse_score(words)?),
+            InfoField::Pv => {
+                self.pv = Some(match pv {
+                    Some(pv) => {
+                        pv.clear();
+                        MovesRef::take_with(words, |pm| pv.push(pm))
+                    }
+                    None => MovesRef::take(words),
+                })
+            }
+            InfoField::Refutation => {
+                let refuted_move = words.peek().map(Pm::from_str);
+                let Some(Ok(refuted_move)) = refuted_move else {
+                    return Err(UziErr::InfoErr);
+                };
+                words.next();
+                self.refutation = Some((refuted_move, MovesRef::take(words)));
+            }
+            InfoField::CurrLine => {
+                let cpu_id = match words.peek().map(to_unsigned::<u16>) {
+                    Some(Ok(cpu_id)) => {
+                        words.next();
+                        Some(cpu_id)
+                    }
+                    _ => None,
+                };
+                self.curr_line = Some((cpu_id, MovesRef::take(words)));
+            }
+            // The string is the rest of the line.
+            InfoField::String => {
+                self.string = Some(words.rest());
+                *words = Words::new("");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn depth(&self) -> Option<u16> {
+        self.depth
+    }
+
+    pub fn sel_depth(&self) -> Option<u16> {
+        self.sel_depth
+    }
+
+    pub fn nodes(&self) -> Option<u64> {
+        self.node
+    }
+
+    pub fn time(&self) -> Option<Duration> {
+        self.time
+    }
+
+    pub fn pv(&self) -> Option<MovesRef<'a>> {
+        self.pv
+    }
+
+    pub fn multi_pv(&self) -> Option<u64> {
+        self.multi_pv
+    }
+
+    pub fn score(&self) -> Option<Score> {
+        self.score
+    }
+
+    pub fn curr_move(&self) -> Option<Pm> {
+        self.curr_move
+    }
+
+    pub fn curr_move_num(&self) -> Option<u16> {
+        self.curr_move_num
+    }
+
+    pub fn hash_full(&self) -> Option<u16> {
+        self.hash_full
+    }
+
+    pub fn nodes_per_sec(&self) -> Option<u64> {
+        self.nodes_per_sec
+    }
+
+    pub fn tb_hits(&self) -> Option<u64> {
+        self.tb_hits
+    }
+
+    pub fn sb_hits(&self) -> Option<u64> {
+        self.sb_hits
+    }
+
+    pub fn cpu_load(&self) -> Option<u16> {
+        self.cpu_load
+    }
+
+    pub fn string(&self) -> Option<&'a str> {
+        self.string
+    }
+
+    pub fn refutation(&self) -> Option<(Pm, MovesRef<'a>)> {
+        self.refutation
+    }
+
+    pub fn curr_line(&self) -> Option<(Option<u16>, MovesRef<'a>)> {
+        self.curr_line
+    }
+
+    pub fn to_info(self) -> Info {
+        Info::from_ref(self, self.pv.map(|pv| pv.iter().collect()))
+    }
+}
+
+// An info that keeps its line and only parses the fields that are read, for
+// loggers and filters that look at one or two fields of most infos. Every read
+// scans the line up to the field and parses it like InfoRef, so a consumer that
+// reads most of the fields is better off with Info or InfoRef. A field whose
+// value is malformed reads as None, and to_info tells what is wrong with it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct LazyInfo {
+    line: String,
+}
+
+impl LazyInfo {
+    // Wraps an info line. Only the first word is checked.
+    pub fn new(line: String) -> Result<Self, UziErr> {
+        match line.split_whitespace().next() {
+            Some("info") => Ok(Self { line }),
+            _ => Err(UziErr::InfoErr),
+        }
+    }
+
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    pub fn depth(&self) -> Option<u16> {
+        self.get(InfoField::Depth)?.depth()
+    }
+
+    pub fn sel_depth(&self) -> Option<u16> {
+        self.get(InfoField::SelDepth)?.sel_depth()
+    }
+
+    pub fn nodes(&self) -> Option<u64> {
+        self.get(InfoField::Nodes)?.nodes()
+    }
+
+    pub fn time(&self) -> Option<Duration> {
+        self.get(InfoField::Time)?.time()
+    }
+
+    pub fn pv(&self) -> Option<MovesRef<'_>> {
+        self.get(InfoField::Pv)?.pv()
+    }
+
+    pub fn multi_pv(&self) -> Option<u64> {
+        self.get(InfoField::MultiPv)?.multi_pv()
+    }
+
+    pub fn score(&self) -> Option<Score> {
+        self.get(InfoField::Score)?.score()
+    }
+
+    pub fn curr_move(&self) -> Option<Pm> {
+        self.get(InfoField::CurrMove)?.curr_move()
+    }
+
+    pub fn curr_move_num(&self) -> Option<u16> {
+        self.get(InfoField::CurrMoveNum)?.curr_move_num()
+    }
+
+    pub fn hash_full(&self) -> Option<u16> {
+        self.get(InfoField::HashFull)?.hash_full()
+    }
+
+    pub fn nodes_per_sec(&self) -> Option<u64> {
+        self.get(InfoField::NodesPerSec)?.nodes_per_sec()
+    }
+
+    pub fn tb_hits(&self) -> Option<u64> {
+        self.get(InfoField::TbHits)?.tb_hits()
+    }
+
+    pub fn sb_hits(&self) -> Option<u64> {
+        self.get(InfoField::SbHits)?.sb_hits()
+    }
+
+    pub fn cpu_load(&self) -> Option<u16> {
+        self.get(InfoField::CpuLoad)?.cpu_load()
+    }
+
+    pub fn string(&self) -> Option<&str> {
+        self.get(InfoField::String)?.string()
+    }
+
+    // Parses every field, e.g. for the infos a filter lets through.
+    pub fn to_info(&self) -> Result<Info, UziErr> {
+        parse_info(&self.line)
+    }
+
+    pub fn into_line(self) -> String {
+        self.line
+    }
+
+    // Reads the field, if the info has it, into an info of its own. Values
+    // are numbers and moves, which are never keys, except for the string,
+    // which is the rest of the line and is not searched.
+    fn get(&self, field: InfoField) -> Option<InfoRef<'_>> {
+        let mut words = Words::new(&self.line);
+        words.next();
+        while let Some(word) = words.next() {
+            match InfoField::from_key(word) {
+                Some(key) if key == field => {
+                    let mut info = InfoRef::default();
+                    info.read(field, &mut words, None).ok()?;
+                    return Some(info);
+                }
+                Some(InfoField::String) => return None,
+                _ => continue,
+            }
+        }
+        None
+    }
+}
+
+impl FromStr for LazyInfo {
+    type Err = UziErr;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        LazyInfo::new(line.to_string())
+    }
+}
+
+impl Display for LazyInfo {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&self.line)
+    }
+}
+
+// Returns the next word and advances the index, or an error if there are no
+// words left.
+pub(crate) fn next_word<'a>(cmd: &[&'a str], i: &mut usize) -> Result<&'a str, UziErr> {
+    let word = cmd.get(*i).ok_or(UziErr::InfoErr)?;
+    *i += 1;
+    Ok(word)
+}
+
+// Parses the score options, i.e. [cp <x>] [mate <y>] [lowerbound|upperbound].
+fn parse_score(words: &mut Words) -> Result<Score, UziErr> {
+    let mut score = Score {
+        cp: None,
+        mate: None,
+        bound: None,
+    };
+    while let Some(word) = words.peek() {
+        match word {
+            "cp" => {
+                words.next();
+                score.cp = Some(to_signed(words.next().ok_or(UziErr::InfoErr)?)?);
+            }
+            "mate" => {
+                words.next();
+                score.mate = Some(to_signed(words.next().ok_or(UziErr::InfoErr)?)?);
+            }
+            "lowerbound" => {
+                words.next();
+                score.bound = Some(ScoreBound::Lower);
+            }
+            "upperbound" => {
+                words.next();
+                score.bound = Some(ScoreBound::Upper);
+            }
+            _ => break,
+        }
+    }
+    if score.cp.is_none() && score.mate.is_none() {
+        return Err(UziErr::InfoErr);
+    }
+    Ok(score)
+}
+
 // currline <cpunr> <move1> .. <movei>: Represents the current line the engine
 // is calculating. <cpunr> is the number of the cpu if the   engine is running
 // on more than one cpu. <cpunr> = 1, 2, 3, etc. If the engien is just using one
@@ -200,7 +909,17 @@ impl Display for Info {
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct CurrLine {
     cpu_id: Option<u16>,
-    line: Vec<Pm>,
+    line: MoveList,
+}
+
+impl CurrLine {
+    pub fn cpu_id(&self) -> Option<u16> {
+        self.cpu_id
+    }
+
+    pub fn line(&self) -> &[Pm] {
+        &self.line
+    }
 }
 
 impl Display for CurrLine {
@@ -209,10 +928,7 @@ impl Display for CurrLine {
         if let Some(cpu_id) = self.cpu_id {
             write!(formatter, " {}", cpu_id)?;
         }
-        for pm in &self.line {
-            write!(formatter, " {}", *pm)?;
-        }
-        Ok(())
+        write_moves(formatter, &self.line)
     }
 }
 
@@ -224,25 +940,32 @@ pub struct Refutation {
     refuted_move: Pm,
 
     // The line of moves that refute refuted_move.
-    moves: Vec<Pm>,
+    moves: MoveList,
+}
+
+impl Refutation {
+    pub fn refuted_move(&self) -> Pm {
+        self.refuted_move
+    }
+
+    pub fn moves(&self) -> &[Pm] {
+        &self.moves
+    }
 }
 
 impl Display for Refutation {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
         write!(formatter, "refutation {}", self.refuted_move)?;
-        for pm in &self.moves {
-            write!(formatter, " {}", *pm)?;
-        }
-        Ok(())
+        write_moves(formatter, &self.moves)
     }
 }
 
-// score cp <x> [mate <y>] [lowerbound] [upperbound]: Represents the score
-// option to the info command.
+// score [cp <x>] [mate <y>] [lowerbound] [upperbound]: Represents the score
+// option to the info command. At least one of cp or mate is set.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct Score {
     // cp <x>: The score from the engine's point of view in centipawns.
-    cp: i32,
+    cp: Option<i32>,
 
     // mate <y>: Mate in y moves, not plies. If the engine is getting mated, use
     // negative values for y.
@@ -252,11 +975,129 @@ pub struct Score {
     bound: Option<ScoreBound>,
 }
 
+impl Score {
+    pub fn cp(&self) -> Option<i32> {
+        self.cp
+    }
+
+    pub fn mate(&self) -> Option<i16> {
+        self.mate
+    }
+
+    pub fn bound(&self) -> Option<ScoreBound> {
+        self.bound
+    }
+
+    // Returns the score from white's point of view, given the side to move in
+    // the position that was searched.
+    pub fn for_white(&self, is_white_to_move: bool) -> Score {
+        match is_white_to_move {
+            true => *self,
+            false => self.negate(),
+        }
+    }
+
+    // Like for_white, with the side to move taken from the position.
+    pub fn for_white_in(&self, pos: &Pos) -> Score {
+        self.for_white(pos.is_white_to_move())
+    }
+
+    // Returns the score from the point of view of the other side.
+    pub fn negate(&self) -> Score {
+        Score {
+            cp: self.cp.map(|cp| -cp),
+            mate: self.mate.map(|mate| -mate),
+            bound: self.bound.map(|bound| match bound {
+                ScoreBound::Lower => ScoreBound::Upper,
+                ScoreBound::Upper => ScoreBound::Lower,
+            }),
+        }
+    }
+
+    // Returns the plies to mate, positive if the side to move mates and
+    // negative if it gets mated, or None if the score is not a mate. A mate in
+    // 0 means the side to move is mated already.
+    pub fn mate_plies(&self) -> Option<i32> {
+        let mate = i32::from(self.mate?);
+        Some(if mate > 0 { 2 * mate - 1 } else { 2 * mate })
+    }
+
+    // Returns the score in centipawns, with mates counted as MATE_CP minus the
+    // plies to mate, so that a shorter mate is better than a longer one.
+    pub fn cp_value(&self) -> Option<i32> {
+        match self.mate_plies() {
+            Some(plies) if plies > 0 => Some(MATE_CP - plies),
+            Some(plies) => Some(-MATE_CP - plies),
+            None => self.cp,
+        }
+    }
+
+    // Like cp_value, but limited to -limit..=limit, e.g. to draw a graph.
+    pub fn clamp_cp(&self, limit: i32) -> Option<i32> {
+        self.cp_value().map(|cp| cp.clamp(-limit, limit))
+    }
+
+    // Compares scores by how good they are for the side to move, so that any
+    // mate is better than any centipawn score and a shorter mate is better
+    // than a longer one. Bounds are ignored, as are scores with neither
+    // centipawns nor mate, which are the worst.
+    pub fn compare(&self, other: &Score) -> Ordering {
+        self.cp_value().cmp(&other.cp_value())
+    }
+
+    // Returns the score of the position the given number of plies later along
+    // the pv, from the point of view of the side to move then. A mate gets
+    // closer with every ply.
+    pub fn after_plies(&self, plies: u16) -> Score {
+        (0..plies).fold(*self, |score, _| {
+            let mut next = score.negate();
+            next.mate = match score.mate_plies() {
+                Some(0) => score.mate,
+                Some(p) if p > 0 => Some(from_mate_plies(-(p - 1))),
+                Some(p) => Some(from_mate_plies(-p - 1)),
+                None => None,
+            };
+            next
+        })
+    }
+
+    // The inverse of after_plies: returns the score of the position the given
+    // number of plies earlier, e.g. to back up the score of a child position to
+    // its parent.
+    pub fn before_plies(&self, plies: u16) -> Score {
+        (0..plies).fold(*self, |score, _| {
+            let mut prev = score.negate();
+            prev.mate = match score.mate_plies() {
+                Some(p) if p <= 0 => Some(from_mate_plies(-p + 1)),
+                Some(p) => Some(from_mate_plies(-p - 1)),
+                None => None,
+            };
+            prev
+        })
+    }
+}
+
+// The centipawn value of a mate on the board, see Score::cp_value.
+pub const MATE_CP: i32 = 10_000;
+
+// Returns the mate in moves for the plies to mate, see Score::mate_plies.
+fn from_mate_plies(plies: i32) -> i16 {
+    let mate = if plies > 0 {
+        (plies + 1) / 2
+    } else {
+        plies / 2
+    };
+    mate as i16
+}
+
 impl Display for Score {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
-        write!(formatter, "score cp {}", self.cp)?;
+        formatter.write_str("score")?;
+        if let Some(cp) = self.cp {
+            write_signed(formatter, " cp ", cp.into())?;
+        }
         if let Some(mate) = self.mate {
-            write!(formatter, " {}", mate)?;
+            write_signed(formatter, " mate ", mate.into())?;
         }
         if let Some(bound) = self.bound {
             write!(formatter, " {}", bound)?;
@@ -293,16 +1134,13 @@ impl Display for ScoreBound {
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct MultiPv {
     rank: u64,
-    moves: Vec<Pm>,
+    moves: MoveList,
 }
 
 impl Display for MultiPv {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
-        write!(formatter, "multipv {}", self.rank)?;
-        for pm in &self.moves {
-            write!(formatter, " {}", *pm)?;
-        }
-        Ok(())
+        write_number(formatter, "multipv ", self.rank)?;
+        write_moves(formatter, &self.moves)
     }
 }
 
@@ -311,6 +1149,12 @@ mod tests {
     use super::*;
     use std::str::FromStr;
 
+    fn moves(line: &str) -> MoveList {
+        line.split_whitespace()
+            .map(|word| Pm::from_str(word).unwrap())
+            .collect()
+    }
+
     #[test]
     fn engcmd_id_name() {
         let cmd = EngCmd::IdName("funnychess".into());
@@ -337,18 +1181,325 @@ mod tests {
     fn engcmd_bestmove() {
         let best = Pm::from_str("e2e4").unwrap();
 
-        let best_move = EngCmd::BestMove {
-            best: best,
-            ponder: None,
-        };
+        let best_move = EngCmd::BestMove { best, ponder: None };
 
         assert_eq!(best_move.to_string().as_str(), "bestmove e2e4");
 
         let best_move = EngCmd::BestMove {
-            best: best,
+            best,
             ponder: Some(Pm::from_str("e7e6").unwrap()),
         };
 
         assert_eq!(best_move.to_string().as_str(), "bestmove e2e4 ponder e7e6");
     }
+
+    #[test]
+    fn engcmd_from_str_simple() {
+        assert_eq!(EngCmd::from_str("uciok"), Ok(EngCmd::UciOk));
+        assert_eq!(EngCmd::from_str("readyok"), Ok(EngCmd::ReadyOk));
+        assert_eq!(
+            EngCmd::from_str("id name Stockfish 16"),
+            Ok(EngCmd::IdName("Stockfish 16".into()))
+        );
+        assert_eq!(
+            EngCmd::from_str("id author the Stockfish developers"),
+            Ok(EngCmd::IdAuthor("the Stockfish developers".into()))
+        );
+        assert_eq!(EngCmd::from_str("id name"), Err(UziErr::IdErr));
+        assert_eq!(EngCmd::from_str(""), Err(UziErr::MissingCmd));
+        assert_eq!(EngCmd::from_str("Stockfish 16 by"), Err(UziErr::What));
+    }
+
+    #[test]
+    fn engcmd_from_str_bestmove() {
+        let best = Pm::from_str("e2e4").unwrap();
+        assert_eq!(
+            EngCmd::from_str("bestmove e2e4"),
+            Ok(EngCmd::BestMove { best, ponder: None })
+        );
+        assert_eq!(
+            EngCmd::from_str("bestmove e2e4 ponder e7e6"),
+            Ok(EngCmd::BestMove {
+                best,
+                ponder: Some(Pm::from_str("e7e6").unwrap())
+            })
+        );
+        assert_eq!(
+            EngCmd::from_str("bestmove (none)"),
+            Ok(EngCmd::BestMove {
+                best: Pm::Null,
+                ponder: None
+            })
+        );
+        assert_eq!(EngCmd::from_str("bestmove"), Err(UziErr::BestMoveErr));
+        assert_eq!(
+            EngCmd::from_str("bestmove e2e4 e7e6"),
+            Err(UziErr::BestMoveErr)
+        );
+    }
+
+    #[test]
+    fn engcmd_copyprotection_and_registration() {
+        let cmd = EngCmd::CopyProtection(CheckStatus::Checking);
+        assert_eq!(cmd.to_string(), "copyprotection checking");
+        assert_eq!(EngCmd::from_str("copyprotection checking"), Ok(cmd));
+        let cmd = EngCmd::Registration(CheckStatus::from_result(false));
+        assert_eq!(cmd.to_string(), "registration error");
+        assert_eq!(EngCmd::from_str("registration error"), Ok(cmd));
+        assert_eq!(
+            EngCmd::from_str("registration ok"),
+            Ok(EngCmd::Registration(CheckStatus::Ok))
+        );
+        assert_eq!(EngCmd::from_str("registration"), Err(UziErr::StatusErr));
+        assert_eq!(
+            EngCmd::from_str("copyprotection fine"),
+            Err(UziErr::StatusErr)
+        );
+    }
+
+    #[test]
+    fn score_for_white() {
+        let words = ["info", "score", "cp", "30", "lowerbound"];
+        let score = Info::try_from(&words[..]).unwrap().score().unwrap();
+        assert_eq!(score.for_white(true), score);
+        let for_white = score.for_white(false);
+        assert_eq!(for_white.cp(), Some(-30));
+        assert_eq!(for_white.bound(), Some(ScoreBound::Upper));
+
+        let mut pos = Pos::new();
+        pos.add_move(Pm::from_str("e2e4").unwrap());
+        assert_eq!(score.for_white_in(&pos), for_white);
+    }
+
+    fn score(line: &str) -> Score {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        Info::try_from(words.as_slice()).unwrap().score().unwrap()
+    }
+
+    #[test]
+    fn score_mate_arithmetic() {
+        let mate_in_3 = score("info score mate 3");
+        assert_eq!(mate_in_3.mate_plies(), Some(5));
+        assert_eq!(mate_in_3.cp_value(), Some(MATE_CP - 5));
+        assert_eq!(score("info score mate -2").cp_value(), Some(-MATE_CP + 4));
+        assert_eq!(score("info score cp 1500").clamp_cp(1000), Some(1000));
+        assert_eq!(mate_in_3.clamp_cp(1000), Some(1000));
+
+        // Shorter mates are better, and being mated later is better.
+        let mut scores = [
+            score("info score mate -1"),
+            score("info score mate 3"),
+            score("info score cp 900"),
+            score("info score mate 1"),
+            score("info score mate -4"),
+            score("info score cp -900"),
+        ];
+        scores.sort_by(|a, b| b.compare(a));
+        let sorted: Vec<String> = scores.iter().map(|score| score.to_string()).collect();
+        assert_eq!(
+            sorted,
+            [
+                "score mate 1",
+                "score mate 3",
+                "score cp 900",
+                "score cp -900",
+                "score mate -4",
+                "score mate -1"
+            ]
+        );
+
+        // Along the pv, the mate gets closer and the side to move alternates.
+        assert_eq!(mate_in_3.after_plies(1).mate(), Some(-2));
+        assert_eq!(mate_in_3.after_plies(2).mate(), Some(2));
+        assert_eq!(mate_in_3.after_plies(5).mate(), Some(0));
+        assert_eq!(mate_in_3.after_plies(5).before_plies(5), mate_in_3);
+        assert_eq!(score("info score mate 0").before_plies(1).mate(), Some(1));
+        assert_eq!(score("info score cp 20").after_plies(3).cp(), Some(-20));
+    }
+
+    #[test]
+    fn info_try_from_full_line() {
+        let line = "info depth 12 seldepth 18 multipv 1 score cp 35 lowerbound nodes 123456 \
+                    nps 1000000 hashfull 12 tbhits 0 time 123 pv e2e4 e7e5 g1f3";
+        let info = Info {
+            depth: Some(12),
+            sel_depth: Some(18),
+            node: Some(123456),
+            time: Some(Duration::from_millis(123)),
+            pv: Some(moves("e2e4 e7e5 g1f3")),
+            multi_pv: Some(MultiPv {
+                rank: 1,
+                moves: MoveList::new(),
+            }),
+            score: Some(Score {
+                cp: Some(35),
+                mate: None,
+                bound: Some(ScoreBound::Lower),
+            }),
+            hash_full: Some(12),
+            nodes_per_sec: Some(1_000_000),
+            tb_hits: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(EngCmd::from_str(line), Ok(EngCmd::Info(info)));
+    }
+
+    #[test]
+    fn info_try_from_mate_and_lines() {
+        let line = "info score mate -3 currmove e2e4 currmovenumber 1 refutation d1h5 g6h5 \
+                    currline 1 e2e4 e7e5 wdl 10 20 970";
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let info = Info::try_from(words.as_slice()).unwrap();
+        assert_eq!(
+            info.score,
+            Some(Score {
+                cp: None,
+                mate: Some(-3),
+                bound: None
+            })
+        );
+        assert_eq!(info.curr_move, Some(Pm::from_str("e2e4").unwrap()));
+        assert_eq!(info.curr_move_num, Some(1));
+        assert_eq!(
+            info.refutation,
+            Some(Refutation {
+                refuted_move: Pm::from_str("d1h5").unwrap(),
+                moves: moves("g6h5"),
+            })
+        );
+        assert_eq!(
+            info.curr_line,
+            Some(CurrLine {
+                cpu_id: Some(1),
+                line: moves("e2e4 e7e5"),
+            })
+        );
+    }
+
+    #[test]
+    fn info_try_from_string_and_errors() {
+        let words = [
+            "info",
+            "depth",
+            "3",
+            "string",
+            "NNUE",
+            "evaluation",
+            "enabled",
+        ];
+        let info = Info::try_from(&words[..]).unwrap();
+        assert_eq!(info.depth, Some(3));
+        assert_eq!(info.string, Some("NNUE evaluation enabled".into()));
+
+        assert_eq!(Info::try_from(&["info", "depth"][..]), Err(UziErr::InfoErr));
+        assert_eq!(
+            Info::try_from(&["info", "score", "lowerbound"][..]),
+            Err(UziErr::InfoErr)
+        );
+        assert_eq!(
+            Info::try_from(&["info", "nodes", "many"][..]),
+            Err(UziErr::BadNumber("many".into()))
+        );
+    }
+
+    #[test]
+    fn info_display_round_trip() {
+        let line = "info depth 20 seldepth 30 nodes 5000000000 time 2000 pv e2e4 e7e5 multipv 2 \
+                    score mate 4 upperbound currmove e2e4 currmovenumber 3 hashfull 500 nps 2500000 \
+                    string hello world";
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let info = Info::try_from(words.as_slice()).unwrap();
+        assert_eq!(info.to_string().as_str(), line);
+    }
+
+    #[test]
+    fn info_numbers_round_trip() {
+        for line in [
+            "info depth 0 nodes 18446744073709551615 time 0 score cp -2147483648",
+            "info multipv 10 score mate -32768 lowerbound",
+            "info seldepth 65535 score cp 2147483647 currmove e7e8q currmovenumber 9",
+        ] {
+            assert_eq!(EngCmd::from_str(line).unwrap().to_string(), line);
+        }
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn info_short_lines_are_inline() {
+        let line = "info depth 9 pv e2e4 e7e5 g1f3 refutation d1h5 g6h5 currline e2e4";
+        let info = Info::try_from(line.split_whitespace().collect::<Vec<_>>().as_slice()).unwrap();
+        assert!(!info.pv.unwrap().spilled());
+        assert!(!info.refutation.unwrap().moves.spilled());
+        assert!(!info.curr_line.unwrap().line.spilled());
+        let long = format!("info pv {}", ["g1f3", "g8f6"].repeat(5).join(" "));
+        let info = Info::try_from(long.split_whitespace().collect::<Vec<_>>().as_slice()).unwrap();
+        assert_eq!(info.pv().map(<[Pm]>::len), Some(10));
+    }
+
+    #[test]
+    fn info_ref_matches_info() {
+        let lines = [
+            "info depth 20 seldepth 30 nodes 5000000000 time 2000 pv e2e4 e7e5 multipv 2 \
+             score mate 4 upperbound currmove e2e4 currmovenumber 3 hashfull 500 nps 2500000",
+            "info score mate -3 currmove e2e4 currmovenumber 1 refutation d1h5 g6h5 \
+             currline 1 e2e4 e7e5 wdl 10 20 970",
+            "info depth 3 tbhits 0 sbhits 1 cpuload 900 string NNUE evaluation enabled",
+        ];
+        for line in lines {
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let info = InfoRef::parse(line).unwrap();
+            assert_eq!(info.to_info(), Info::try_from(words.as_slice()).unwrap());
+        }
+
+        let line = "info depth 9 pv e2e4  e7e5 score cp 20 string  two  spaces ";
+        let info = InfoRef::parse(line).unwrap();
+        assert_eq!(info.pv().map(|pv| pv.as_str()), Some("e2e4  e7e5"));
+        assert_eq!(info.pv().unwrap().iter().count(), 2);
+        assert_eq!(info.score().and_then(|score| score.cp()), Some(20));
+        assert_eq!(info.string(), Some("two  spaces"));
+
+        assert_eq!(InfoRef::parse("info depth"), Err(UziErr::InfoErr));
+        assert_eq!(InfoRef::parse("info refutation"), Err(UziErr::InfoErr));
+        assert_eq!(InfoRef::parse("bestmove e2e4"), Err(UziErr::InfoErr));
+        assert_eq!(
+            InfoRef::parse("info score lowerbound"),
+            Err(UziErr::InfoErr)
+        );
+    }
+
+    #[test]
+    fn lazy_info_matches_info() {
+        let lines = [
+            "info depth 20 seldepth 30 nodes 5000000000 time 2000 pv e2e4 e7e5 multipv 2 \
+             score mate 4 upperbound currmove e2e4 currmovenumber 3 hashfull 500 nps 2500000",
+            "info depth 3 tbhits 0 sbhits 1 cpuload 900 string depth 4 pv e2e4",
+            "info string",
+        ];
+        for line in lines {
+            let lazy = LazyInfo::from_str(line).unwrap();
+            let info = lazy.to_info().unwrap();
+            assert_eq!(lazy.depth(), info.depth(), "{}", line);
+            assert_eq!(lazy.sel_depth(), info.sel_depth());
+            assert_eq!(lazy.nodes(), info.nodes());
+            assert_eq!(lazy.time(), info.time());
+            assert_eq!(lazy.pv().map(|pv| pv.to_vec()).as_deref(), info.pv());
+            assert_eq!(lazy.multi_pv(), info.multi_pv());
+            assert_eq!(lazy.score(), info.score());
+            assert_eq!(lazy.curr_move(), info.curr_move());
+            assert_eq!(lazy.curr_move_num(), info.curr_move_num());
+            assert_eq!(lazy.hash_full(), info.hash_full());
+            assert_eq!(lazy.nodes_per_sec(), info.nodes_per_sec());
+            assert_eq!(lazy.tb_hits(), info.tb_hits());
+            assert_eq!(lazy.sb_hits(), info.sb_hits());
+            assert_eq!(lazy.cpu_load(), info.cpu_load());
+            assert_eq!(lazy.string(), info.string());
+            assert_eq!(lazy.to_string(), line);
+        }
+
+        let lazy = LazyInfo::from_str("info depth many nodes 10").unwrap();
+        assert_eq!(lazy.depth(), None);
+        assert_eq!(lazy.nodes(), Some(10));
+        assert_eq!(lazy.to_info(), Err(UziErr::BadNumber("many".into())));
+        assert_eq!(LazyInfo::from_str("bestmove e2e4"), Err(UziErr::InfoErr));
+    }
 }
@@ -0,0 +1,448 @@
+// This module contains EngineCatalog, the engines a user has set up, as read
+// from an engine definition file of the kind GUIs and tournament managers like
+// cutechess and banksia keep. An engine has a name, a command line, a working
+// directory, a protocol, text to send when it starts, and the options to set.
+// A catalog can be read from:
+//
+// - JSON, an array of engines as in the engines.json of cutechess, e.g.
+//   [{"name": "Stockfish", "command": "./stockfish", "workingDirectory":
+//   "/opt/sf", "protocol": "uci", "initStrings": ["setoption name Threads
+//   value 4"], "options": [{"name": "Hash", "value": 64}]}]. The working
+//   folder key of banksia is accepted too, and so is an object with the array
+//   under engines. Reading JSON needs the serde feature.
+// - INI, with a section per engine named after the engine, e.g.
+//   [Stockfish]
+//   command=./stockfish
+//   dir=/opt/sf
+//   protocol=uci
+//   init=setoption name Threads value 4
+//   option.Hash=64
+//   Blank lines and lines starting with ; or # are ignored, and init can be
+//   given more than once.
+//
+// Keys that are not known are ignored in both, since the files of GUIs have
+// many settings of their own. The command line is split at whitespace, except
+// inside single or double quotes.
+
+use crate::engproc::Launcher;
+use crate::err::UziErr;
+use crate::opt::SetOpt;
+use crate::profile::EngineProfile;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The protocol an engine speaks.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Protocol {
+    #[default]
+    Uci,
+    // Also known as CECP or winboard.
+    Xboard,
+    Usi,
+    Ucci,
+}
+
+impl Protocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Uci => "uci",
+            Protocol::Xboard => "xboard",
+            Protocol::Usi => "usi",
+            Protocol::Ucci => "ucci",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "uci" => Some(Protocol::Uci),
+            "xboard" | "winboard" | "cecp" => Some(Protocol::Xboard),
+            "usi" => Some(Protocol::Usi),
+            "ucci" => Some(Protocol::Ucci),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Protocol {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
+}
+
+// An engine of a catalog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineDef {
+    name: String,
+    protocol: Protocol,
+    // The program and its arguments, the working directory and the init
+    // strings as the startup text.
+    launcher: Launcher,
+    options: Vec<SetOpt>,
+}
+
+impl EngineDef {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    // Returns the parameters to launch the engine with, whatever its
+    // protocol.
+    pub fn launcher(&self) -> &Launcher {
+        &self.launcher
+    }
+
+    pub fn options(&self) -> &[SetOpt] {
+        &self.options
+    }
+
+    // Returns the profile of the engine for a match or a tournament, with the
+    // options of the definition. This fails with UnsupportedProtocol if the
+    // engine doesn't speak UCI, since that is what the match runner speaks.
+    pub fn profile(&self) -> Result<EngineProfile, UziErr> {
+        if self.protocol != Protocol::Uci {
+            return Err(UziErr::UnsupportedProtocol(self.protocol.to_string()));
+        }
+        let mut profile = EngineProfile::new(self.launcher.clone());
+        for opt in &self.options {
+            profile.add_option(opt.clone());
+        }
+        Ok(profile)
+    }
+}
+
+// The engines of a definition file, in the order of the file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EngineCatalog {
+    engines: Vec<EngineDef>,
+}
+
+impl EngineCatalog {
+    // Reads the catalog from a file, as JSON if its extension is json and as
+    // INI otherwise.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, UziErr> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        match extension.map(str::to_ascii_lowercase).as_deref() {
+            #[cfg(feature = "serde")]
+            Some("json") => Self::from_json(&text),
+            _ => Self::from_ini(&text),
+        }
+    }
+
+    // Parses an INI catalog. This fails with BadEngineDef and the number of
+    // the line, starting at 1, if a line is neither a section, a key and a
+    // value, nor a comment, or if a section has no command or a protocol that
+    // is not known. The line of a section is the one with its name.
+    pub fn from_ini(text: &str) -> Result<Self, UziErr> {
+        let mut engines = Vec::new();
+        // The line of the section being read, and its fields.
+        let mut section: Option<(usize, Fields)> = None;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                if let Some((line, fields)) = section.take() {
+                    engines.push(fields.into_def().ok_or(UziErr::BadEngineDef(line))?);
+                }
+                let fields = Fields {
+                    name: Some(name.trim().to_string()),
+                    ..Fields::default()
+                };
+                section = Some((i + 1, fields));
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or(UziErr::BadEngineDef(i + 1))?;
+            let (_, fields) = section.as_mut().ok_or(UziErr::BadEngineDef(i + 1))?;
+            let value = value.trim().to_string();
+            match key.trim() {
+                "command" => fields.command = Some(value),
+                "dir" => fields.dir = Some(value.into()),
+                "protocol" => fields.protocol = Some(value),
+                "init" => fields.init.push(value),
+                key => {
+                    if let Some(name) = key.strip_prefix("option.") {
+                        fields.options.push(SetOpt::Custom {
+                            name: name.trim().to_string(),
+                            value: Some(value),
+                        });
+                    }
+                }
+            }
+        }
+        if let Some((line, fields)) = section {
+            engines.push(fields.into_def().ok_or(UziErr::BadEngineDef(line))?);
+        }
+        Ok(Self { engines })
+    }
+
+    // Parses a JSON catalog. This fails with JsonErr if the text is not JSON,
+    // and with BadEngineDef and the number of the engine, starting at 1, if
+    // an engine has no name or command, or a protocol that is not known.
+    // Options without a value are skipped.
+    #[cfg(feature = "serde")]
+    pub fn from_json(text: &str) -> Result<Self, UziErr> {
+        use serde_json::Value;
+
+        let json: Value =
+            serde_json::from_str(text).map_err(|err| UziErr::JsonErr(err.to_string()))?;
+        let items = match json {
+            Value::Array(items) => items,
+            Value::Object(mut object) => match object.remove("engines") {
+                Some(Value::Array(items)) => items,
+                _ => return Err(UziErr::JsonErr("no engines".to_string())),
+            },
+            _ => return Err(UziErr::JsonErr("no engines".to_string())),
+        };
+        // Returns the value as the text of a setoption, e.g. 64 or true.
+        let to_text = |value: &Value| match value {
+            Value::String(text) => Some(text.clone()),
+            Value::Number(_) | Value::Bool(_) => Some(value.to_string()),
+            _ => None,
+        };
+        let mut engines = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            let field = |key: &str| item.get(key).and_then(Value::as_str).map(str::to_string);
+            let list = |key: &str| {
+                item.get(key)
+                    .and_then(Value::as_array)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default()
+            };
+            let fields = Fields {
+                name: field("name"),
+                command: field("command"),
+                dir: field("workingDirectory")
+                    .or_else(|| field("working folder"))
+                    .map(PathBuf::from),
+                protocol: field("protocol"),
+                init: list("initStrings").iter().filter_map(to_text).collect(),
+                options: list("options")
+                    .iter()
+                    .filter_map(|opt| {
+                        Some(SetOpt::Custom {
+                            name: opt.get("name")?.as_str()?.to_string(),
+                            value: Some(to_text(opt.get("value")?)?),
+                        })
+                    })
+                    .collect(),
+            };
+            engines.push(fields.into_def().ok_or(UziErr::BadEngineDef(i + 1))?);
+        }
+        Ok(Self { engines })
+    }
+
+    pub fn len(&self) -> usize {
+        self.engines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.engines.is_empty()
+    }
+
+    // Returns the first engine with the name, if any.
+    pub fn get(&self, name: &str) -> Option<&EngineDef> {
+        self.engines.iter().find(|def| def.name == name)
+    }
+
+    pub fn engines(&self) -> &[EngineDef] {
+        &self.engines
+    }
+}
+
+// The fields of an engine as read from a file, before they are checked.
+#[derive(Default)]
+struct Fields {
+    name: Option<String>,
+    command: Option<String>,
+    dir: Option<PathBuf>,
+    protocol: Option<String>,
+    init: Vec<String>,
+    options: Vec<SetOpt>,
+}
+
+impl Fields {
+    // Returns the engine, or None if a field is missing or malformed.
+    fn into_def(self) -> Option<EngineDef> {
+        let name = self.name.filter(|name| !name.is_empty())?;
+        let protocol = match self.protocol {
+            Some(protocol) => Protocol::from_name(&protocol)?,
+            None => Protocol::default(),
+        };
+        let words = split_command(&self.command?)?;
+        let (program, args) = words.split_first()?;
+        let mut launcher = Launcher::new(program);
+        for arg in args {
+            launcher.add_arg(arg);
+        }
+        if let Some(dir) = self.dir {
+            launcher.set_cwd(dir);
+        }
+        if !self.init.is_empty() {
+            launcher.set_startup(&self.init.join("\n"));
+        }
+        Some(EngineDef {
+            name,
+            protocol,
+            launcher,
+            options: self.options,
+        })
+    }
+}
+
+// Splits a command line into words at whitespace, except inside single or
+// double quotes, which are removed. Returns None if a quote isn't closed.
+fn split_command(cmd: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in cmd.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return None;
+    }
+    words.extend(word);
+    Some(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INI: &str = r#"
+        ; Engines for the weekly gauntlet.
+        [Stockfish 16]
+        command=./stockfish --threads "4 cores"
+        dir=/opt/sf
+        init=setoption name EvalFile value nn.nnue
+        init=setoption name Threads value 4
+        option.Hash=64
+        option.UCI_Chess960=false
+        ponder=true
+
+        [Crafty]
+        command=crafty xboard
+        protocol=winboard
+    "#;
+
+    #[test]
+    fn ini_catalog() {
+        let catalog = EngineCatalog::from_ini(INI).unwrap();
+        assert_eq!(catalog.len(), 2);
+        let sf = catalog.get("Stockfish 16").unwrap();
+        let mut launcher = Launcher::new("./stockfish");
+        launcher
+            .add_arg("--threads")
+            .add_arg("4 cores")
+            .set_cwd("/opt/sf")
+            .set_startup("setoption name EvalFile value nn.nnue\nsetoption name Threads value 4");
+        assert_eq!(sf.launcher(), &launcher);
+        assert_eq!(sf.protocol(), Protocol::Uci);
+        let profile = sf.profile().unwrap();
+        let options: Vec<String> = profile.options().iter().map(SetOpt::to_string).collect();
+        assert_eq!(
+            options,
+            [
+                "setoption name Hash value 64",
+                "setoption name UCI_Chess960 value false"
+            ]
+        );
+        let crafty = catalog.get("Crafty").unwrap();
+        assert_eq!(crafty.protocol(), Protocol::Xboard);
+        assert_eq!(
+            crafty.profile(),
+            Err(UziErr::UnsupportedProtocol("xboard".to_string()))
+        );
+    }
+
+    #[test]
+    fn bad_ini() {
+        for (ini, line) in [
+            ("command=sf", 1),
+            ("[A]\ncommand=sf\n[B]\ndir=/opt", 3),
+            ("[A]\ncommand=sf\nprotocol=fics", 1),
+            ("[A]\n\ncommand=sf 'unclosed", 1),
+            ("[A]\ncommand sf", 2),
+        ] {
+            assert_eq!(
+                EngineCatalog::from_ini(ini),
+                Err(UziErr::BadEngineDef(line)),
+                "{}",
+                ini
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_catalog() {
+        let json = r#"[
+            {"name": "Stockfish", "command": "stockfish", "workingDirectory": "/opt/sf",
+             "protocol": "uci", "stderrFile": "", "initStrings": ["setoption name Threads value 2"],
+             "options": [{"name": "Hash", "type": "spin", "value": 128},
+                         {"name": "Ponder", "type": "check", "value": true},
+                         {"name": "Clear Hash", "type": "button"}]},
+            {"name": "Fairy", "command": "fairy-stockfish", "working folder": "/opt/fsf",
+             "protocol": "usi"}
+        ]"#;
+        let catalog = EngineCatalog::from_json(json).unwrap();
+        assert_eq!(catalog.len(), 2);
+        let sf = &catalog.engines()[0];
+        let mut launcher = Launcher::new("stockfish");
+        launcher
+            .set_cwd("/opt/sf")
+            .set_startup("setoption name Threads value 2");
+        assert_eq!(sf.launcher(), &launcher);
+        let options: Vec<String> = sf.options().iter().map(SetOpt::to_string).collect();
+        assert_eq!(
+            options,
+            [
+                "setoption name Hash value 128",
+                "setoption name Ponder value true"
+            ]
+        );
+        assert_eq!(catalog.get("Fairy").unwrap().protocol(), Protocol::Usi);
+        let nested = r#"{"engines": [{"name": "A", "command": "a"}, {"name": "B"}]}"#;
+        assert_eq!(
+            EngineCatalog::from_json(nested),
+            Err(UziErr::BadEngineDef(2))
+        );
+        assert!(matches!(
+            EngineCatalog::from_json("[{"),
+            Err(UziErr::JsonErr(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn launch_from_definition() {
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let ini = format!(
+            "[Pwd]\ncommand=sh -c 'read -r line; echo \"$line\"; pwd'\ndir={}\ninit=uci",
+            dir.display()
+        );
+        let catalog = EngineCatalog::from_ini(&ini).unwrap();
+        let mut proc = catalog.get("Pwd").unwrap().launcher().spawn().unwrap();
+        proc.send_line("isready").await.unwrap();
+        assert_eq!(proc.recv_line().await, Ok(Some("uci".to_string())));
+        let cwd = proc.recv_line().await.unwrap().unwrap();
+        assert_eq!(Path::new(&cwd).canonicalize().unwrap(), dir);
+    }
+}
@@ -0,0 +1,920 @@
+// This module contains EngineMatch, which plays games between two engines with
+// a real clock, like a tournament manager does. Every move is checked, and the
+// game ends by the rules, on time, or when an engine misbehaves. The progress
+// of the games is sent as MatchEvents, e.g. for live dashboards.
+
+use crate::board::Board;
+use crate::client::{Engine, SYNC_TIMEOUT};
+use crate::engcmd::Score;
+use crate::err::UziErr;
+use crate::guicmd::{Go, Pos};
+use crate::pm::Pm;
+use crate::tournament::Crosstable;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+// The time of each side at the start of the game, and the increment added
+// after each of its moves.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub inc: Duration,
+}
+
+// Plays games from a start position with a time control. The engines have to
+// be ready, i.e. past the handshake and with their options set.
+#[derive(Clone)]
+pub struct EngineMatch {
+    time_control: TimeControl,
+    start: Pos,
+    // The factors the time control of white and black is multiplied by.
+    time_odds: [f64; 2],
+    // The number of plies after which the game is adjudicated a draw.
+    max_plies: Option<usize>,
+    // How long an engine can overstep its time before it loses, to allow for
+    // the time it takes to exchange the commands.
+    grace: Duration,
+    adjudication: Adjudication,
+    adjudicator: Option<Arc<dyn Adjudicator + Send + Sync>>,
+    events: broadcast::Sender<MatchEvent>,
+    // The number of the next game played with play or play_from, shared by
+    // the clones of the match.
+    next_game: Arc<AtomicUsize>,
+}
+
+// What happens during the games of a match or a tournament. Every game has a
+// number, which is its index in the schedule of a tournament, or the number of
+// games played before it for a match, starting at 0.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MatchEvent {
+    // The names are the ones the engines sent in their id, if any.
+    GameStarted {
+        game: usize,
+        start: Pos,
+        white: Option<String>,
+        black: Option<String>,
+    },
+    // A move was played, with the time left on both clocks after it.
+    MovePlayed {
+        game: usize,
+        side: Side,
+        pm: Pm,
+        san: String,
+        stats: MoveStats,
+        white_time: Duration,
+        black_time: Duration,
+    },
+    // The game was decided by adjudication rather than played out, i.e. by
+    // the Adjudication rules, the ply limit, or an Adjudicator. The game
+    // finishes right after.
+    Adjudicated {
+        game: usize,
+        result: GameResult,
+    },
+    GameFinished {
+        game: usize,
+        record: GameRecord,
+    },
+    // A game of a tournament finished and was added to the crosstable.
+    CrosstableUpdated(Crosstable),
+}
+
+// Decides games by rules of its own, e.g. by probing tablebases. It is asked
+// before every move, after the rules of chess and the Adjudication rules.
+pub trait Adjudicator {
+    // Returns the verdict for the position, given the score every engine
+    // reported for its moves so far, from its own point of view.
+    fn adjudicate(&self, pos: &Pos, scores: &[Option<Score>]) -> Verdict;
+}
+
+// The decision of an Adjudicator.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Verdict {
+    Continue,
+    Draw,
+    WinFor(Side),
+}
+
+// Ends games early by the scores of the engines, like tournament managers do
+// to save time. Each rule looks at the last moves of both engines, and only
+// fires if all of them reported a score.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Adjudication {
+    // The centipawns and the number of moves of each engine.
+    resign: Option<(i32, usize)>,
+    // The centipawns, the number of moves of each engine, and the number of
+    // moves of the game after which the rule applies.
+    draw: Option<(i32, usize, usize)>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Side {
+    White,
+    Black,
+}
+
+// How a game ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameResult {
+    Win(Side, WinReason),
+    Draw(DrawReason),
+}
+
+// Why the winner won.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WinReason {
+    Checkmate,
+    // The opponent ran out of time.
+    Timeout,
+    // The opponent played an illegal move.
+    IllegalMove(Pm),
+    // The opponent failed, e.g. it crashed.
+    EngineFailed(UziErr),
+    // The opponent resigned by the adjudication rules.
+    Resignation,
+    // The Adjudicator of the match decided the game.
+    Adjudicator,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DrawReason {
+    Stalemate,
+    FiftyMoves,
+    Repetition,
+    InsufficientMaterial,
+    MaxPlies,
+    // The scores stayed close to 0, by the adjudication rules.
+    Adjudication,
+    // The Adjudicator of the match decided the game.
+    Adjudicator,
+}
+
+// A finished game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameRecord {
+    pub start: Pos,
+    // The time controls differ when the match gives time odds.
+    pub white_time_control: TimeControl,
+    pub black_time_control: TimeControl,
+    pub moves: Vec<Pm>,
+    // What the engine reported for each move, and its time.
+    pub stats: Vec<MoveStats>,
+    pub result: GameResult,
+    // The time left on the clocks at the end of the game.
+    pub white_time: Duration,
+    pub black_time: Duration,
+}
+
+// The search behind a move of a game.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct MoveStats {
+    // The last score and depth the engine reported for its best line, with
+    // the score from its point of view.
+    pub score: Option<Score>,
+    pub depth: Option<u16>,
+    // The time the engine took for the move.
+    pub time: Duration,
+    // The time left on the clock of the engine after the move, including the
+    // increment.
+    pub clock: Duration,
+}
+
+impl TimeControl {
+    pub fn new(base: Duration, inc: Duration) -> Self {
+        Self { base, inc }
+    }
+
+    // Returns the time control with the base and the increment multiplied by
+    // the factor, e.g. 0.5 for half the time. A factor that doesn't give valid
+    // times, e.g. a negative one, leaves the time control as it is.
+    pub fn scaled(&self, factor: f64) -> Self {
+        let scale = |time: Duration| Duration::try_from_secs_f64(time.as_secs_f64() * factor);
+        match (scale(self.base), scale(self.inc)) {
+            (Ok(base), Ok(inc)) => Self { base, inc },
+            _ => *self,
+        }
+    }
+}
+
+impl Adjudication {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Makes an engine resign when, for the given number of moves of each
+    // engine, it reports a score of -cp or less and its opponent a score of
+    // cp or more.
+    pub fn set_resign(&mut self, cp: i32, moves: usize) -> &mut Self {
+        self.resign = Some((cp, moves));
+        self
+    }
+
+    // Adjudicates a draw when, from the given move number on, both engines
+    // report scores within -cp..=cp for the given number of moves each.
+    pub fn set_draw(&mut self, cp: i32, moves: usize, after: usize) -> &mut Self {
+        self.draw = Some((cp, moves, after));
+        self
+    }
+
+    // Checks the rules, given the score of every move from white's point of
+    // view.
+    fn check(&self, scores: &[Option<i32>]) -> Option<GameResult> {
+        // The scores of the last moves of both engines, if they all have one.
+        let last = |moves: usize| -> Option<Vec<i32>> {
+            let start = scores.len().checked_sub(2 * moves).filter(|_| moves > 0)?;
+            scores[start..].iter().copied().collect()
+        };
+        if let Some((cp, moves)) = self.resign {
+            if let Some(last) = last(moves) {
+                if last.iter().all(|score| *score <= -cp) {
+                    return Some(GameResult::Win(Side::Black, WinReason::Resignation));
+                }
+                if last.iter().all(|score| *score >= cp) {
+                    return Some(GameResult::Win(Side::White, WinReason::Resignation));
+                }
+            }
+        }
+        if let Some((cp, moves, after)) = self.draw {
+            let is_late = scores.len() >= 2 * after;
+            if let Some(last) = last(moves).filter(|_| is_late) {
+                if last.iter().all(|score| score.abs() <= cp) {
+                    return Some(GameResult::Draw(DrawReason::Adjudication));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Side {
+    pub fn other(self) -> Self {
+        match self {
+            Side::White => Side::Black,
+            Side::Black => Side::White,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Side::White => "White",
+            Side::Black => "Black",
+        }
+    }
+}
+
+impl GameResult {
+    // Returns the result as in PGN, i.e. 1-0, 0-1 or 1/2-1/2.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GameResult::Win(Side::White, _) => "1-0",
+            GameResult::Win(Side::Black, _) => "0-1",
+            GameResult::Draw(_) => "1/2-1/2",
+        }
+    }
+
+    // Returns true if the game was decided by adjudication rather than played
+    // out.
+    pub fn is_adjudicated(&self) -> bool {
+        matches!(
+            self,
+            GameResult::Win(_, WinReason::Resignation | WinReason::Adjudicator)
+                | GameResult::Draw(
+                    DrawReason::MaxPlies | DrawReason::Adjudication | DrawReason::Adjudicator
+                )
+        )
+    }
+
+    // Returns why the game ended, e.g. "Black loses on time" or "Draw by
+    // stalemate", as cutechess puts it in the last comment of a PGN.
+    pub fn reason(&self) -> String {
+        let (winner, reason) = match self {
+            GameResult::Win(winner, reason) => (winner, reason),
+            GameResult::Draw(reason) => {
+                let reason = match reason {
+                    DrawReason::Stalemate => "stalemate",
+                    DrawReason::FiftyMoves => "fifty moves rule",
+                    DrawReason::Repetition => "3-fold repetition",
+                    DrawReason::InsufficientMaterial => "insufficient mating material",
+                    DrawReason::MaxPlies => "move limit",
+                    DrawReason::Adjudication | DrawReason::Adjudicator => "adjudication",
+                };
+                return format!("Draw by {}", reason);
+            }
+        };
+        let loser = winner.other().as_str();
+        match reason {
+            WinReason::Checkmate => format!("{} mates", winner.as_str()),
+            WinReason::Timeout => format!("{} loses on time", loser),
+            WinReason::IllegalMove(pm) => format!("{} makes an illegal move: {}", loser, pm),
+            WinReason::EngineFailed(err) => match err.root() {
+                UziErr::Crashed(_) | UziErr::EngineExited => format!("{}'s engine crashed", loser),
+                UziErr::EngineHung | UziErr::Timeout => {
+                    format!("{}'s engine stopped responding", loser)
+                }
+                _ => format!("{}'s engine failed", loser),
+            },
+            WinReason::Resignation => format!("{} resigns", loser),
+            WinReason::Adjudicator => format!("{} wins by adjudication", winner.as_str()),
+        }
+    }
+}
+
+impl GameRecord {
+    // Returns the final position, i.e. the start position with the moves of
+    // the game.
+    pub fn pos(&self) -> Pos {
+        let mut pos = self.start.clone();
+        for pm in &self.moves {
+            pos.add_move(*pm);
+        }
+        pos
+    }
+}
+
+impl EngineMatch {
+    pub fn new(time_control: TimeControl) -> Self {
+        Self {
+            time_control,
+            time_odds: [1.0; 2],
+            start: Pos::new(),
+            max_plies: None,
+            grace: Duration::from_millis(50),
+            adjudication: Adjudication::default(),
+            adjudicator: None,
+            events: broadcast::channel(256).0,
+            next_game: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    // Returns a receiver for the events of the games played from now on. The
+    // clones of the match, e.g. the one of a Tournament, send to the same
+    // receivers. Slow receivers may miss events, see tokio::sync::broadcast.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<MatchEvent> {
+        self.events.subscribe()
+    }
+
+    pub(crate) fn send_event(&self, event: MatchEvent) {
+        // This fails if nobody is subscribed.
+        let _ = self.events.send(event);
+    }
+
+    pub fn start(&self) -> &Pos {
+        &self.start
+    }
+
+    // Sets the position the games start from, which may have moves.
+    pub fn set_start(&mut self, start: Pos) -> &mut Self {
+        self.start = start;
+        self
+    }
+
+    // Gives the engines time odds: the time control of each side is scaled by
+    // its factor, see TimeControl::scaled. The default is 1.0 for both.
+    pub fn set_time_odds(&mut self, white: f64, black: f64) -> &mut Self {
+        self.time_odds = [white, black];
+        self
+    }
+
+    pub fn set_max_plies(&mut self, max_plies: usize) -> &mut Self {
+        self.max_plies = Some(max_plies);
+        self
+    }
+
+    pub fn set_grace(&mut self, grace: Duration) -> &mut Self {
+        self.grace = grace;
+        self
+    }
+
+    pub fn set_adjudication(&mut self, adjudication: Adjudication) -> &mut Self {
+        self.adjudication = adjudication;
+        self
+    }
+
+    pub fn set_adjudicator<A>(&mut self, adjudicator: A) -> &mut Self
+    where
+        A: Adjudicator + Send + Sync + 'static,
+    {
+        self.adjudicator = Some(Arc::new(adjudicator));
+        self
+    }
+
+    // Plays a game. The engines get the whole game as position with
+    // wtime/btime and winc/binc, and their moves are played until the game
+    // ends. This only fails if the start position is not valid.
+    pub async fn play(&self, white: &mut Engine, black: &mut Engine) -> Result<GameRecord, UziErr> {
+        self.play_from(&self.start, white, black).await
+    }
+
+    // Like play, but starts from the given position instead of the one of the
+    // match, e.g. an opening of a suite.
+    pub async fn play_from(
+        &self,
+        start: &Pos,
+        white: &mut Engine,
+        black: &mut Engine,
+    ) -> Result<GameRecord, UziErr> {
+        let game = self.next_game.fetch_add(1, Ordering::Relaxed);
+        self.play_game(game, start, white, black, self.time_odds)
+            .await
+    }
+
+    // Plays a game with the given number in the events, and the given time
+    // odds of white and black instead of the ones of the match.
+    pub(crate) async fn play_game(
+        &self,
+        game: usize,
+        start: &Pos,
+        white: &mut Engine,
+        black: &mut Engine,
+        time_odds: [f64; 2],
+    ) -> Result<GameRecord, UziErr> {
+        let time_controls = time_odds.map(|odds| self.time_control.scaled(odds));
+        let mut board = Board::from_pos(start).ok_or(UziErr::Position)?;
+        let mut pos = start.clone();
+        let mut moves = Vec::new();
+        let mut stats = Vec::new();
+        let mut scores = Vec::new();
+        // The scores from white's point of view, for the adjudication.
+        let mut white_scores = Vec::new();
+        let mut clocks = time_controls.map(|tc| tc.base);
+        let mut seen = HashMap::from([(board.key(), 1)]);
+        self.send_event(MatchEvent::GameStarted {
+            game,
+            start: start.clone(),
+            white: white.name().map(str::to_string),
+            black: black.name().map(str::to_string),
+        });
+
+        // Both engines start every game with ucinewgame, off the clock. The
+        // client can't tell games from the same start position apart, and
+        // would keep the hash and history of the previous game.
+        let mut failed = None;
+        for (side, eng) in [(Side::White, &mut *white), (Side::Black, &mut *black)] {
+            if let Err(err) = new_game(eng).await {
+                failed = Some(GameResult::Win(side.other(), WinReason::EngineFailed(err)));
+                break;
+            }
+        }
+
+        let result = loop {
+            if let Some(result) = failed.take() {
+                break result;
+            }
+            if let Some(result) = self.adjudicate(&board, &seen, moves.len()) {
+                break result;
+            }
+            if let Some(result) = self.adjudication.check(&white_scores) {
+                break result;
+            }
+            if let Some(ref adjudicator) = self.adjudicator {
+                match adjudicator.adjudicate(&pos, &scores) {
+                    Verdict::Continue => (),
+                    Verdict::Draw => break GameResult::Draw(DrawReason::Adjudicator),
+                    Verdict::WinFor(side) => break GameResult::Win(side, WinReason::Adjudicator),
+                }
+            }
+            let side = if board.is_white_to_move() {
+                Side::White
+            } else {
+                Side::Black
+            };
+            let (eng, clock) = match side {
+                Side::White => (&mut *white, 0),
+                Side::Black => (&mut *black, 1),
+            };
+            let mut go = Go::new();
+            go.set_wtime(clocks[0])
+                .set_btime(clocks[1])
+                .set_winc(time_controls[0].inc)
+                .set_binc(time_controls[1].inc);
+
+            // The clock starts at go, since position may sync with the engine.
+            if let Err(err) = eng.position(&pos).await {
+                break GameResult::Win(side.other(), WinReason::EngineFailed(err));
+            }
+            let limit = clocks[clock] + self.grace;
+            let started = Instant::now();
+            let best = search(eng, &go, limit).await;
+            let elapsed = started.elapsed();
+            clocks[clock] = clocks[clock].saturating_sub(elapsed);
+            if elapsed > limit {
+                break GameResult::Win(side.other(), WinReason::Timeout);
+            }
+            let (pm, score, depth) = match best {
+                Ok(best) => best,
+                Err(err) => break GameResult::Win(side.other(), WinReason::EngineFailed(err)),
+            };
+            if !board.legal_moves().contains(&pm) {
+                break GameResult::Win(side.other(), WinReason::IllegalMove(pm));
+            }
+            clocks[clock] += time_controls[clock].inc;
+            let is_white = side == Side::White;
+            white_scores.push(score.and_then(|score| score.for_white(is_white).cp_value()));
+            scores.push(score);
+            let move_stats = MoveStats {
+                score,
+                depth,
+                time: elapsed,
+                clock: clocks[clock],
+            };
+            stats.push(move_stats);
+            self.send_event(MatchEvent::MovePlayed {
+                game,
+                side,
+                pm,
+                san: board.san(pm),
+                stats: move_stats,
+                white_time: clocks[0],
+                black_time: clocks[1],
+            });
+            board.play(pm);
+            pos.add_move(pm);
+            moves.push(pm);
+            *seen.entry(board.key()).or_insert(0) += 1;
+        };
+
+        if result.is_adjudicated() {
+            self.send_event(MatchEvent::Adjudicated {
+                game,
+                result: result.clone(),
+            });
+        }
+        let record = GameRecord {
+            start: start.clone(),
+            white_time_control: time_controls[0],
+            black_time_control: time_controls[1],
+            moves,
+            stats,
+            result,
+            white_time: clocks[0],
+            black_time: clocks[1],
+        };
+        self.send_event(MatchEvent::GameFinished {
+            game,
+            record: record.clone(),
+        });
+        Ok(record)
+    }
+
+    // Returns the result if the game is over by the rules or by the ply limit.
+    fn adjudicate(
+        &self,
+        board: &Board,
+        seen: &HashMap<Vec<u8>, usize>,
+        plies: usize,
+    ) -> Option<GameResult> {
+        if board.legal_moves().is_empty() {
+            if !board.is_check() {
+                return Some(GameResult::Draw(DrawReason::Stalemate));
+            }
+            let winner = if board.is_white_to_move() {
+                Side::Black
+            } else {
+                Side::White
+            };
+            return Some(GameResult::Win(winner, WinReason::Checkmate));
+        }
+        let reason = if board.is_insufficient_material() {
+            DrawReason::InsufficientMaterial
+        } else if seen.get(&board.key()).is_some_and(|count| *count >= 3) {
+            DrawReason::Repetition
+        } else if board.halfmove_clock() >= 100 {
+            DrawReason::FiftyMoves
+        } else if self.max_plies.is_some_and(|max_plies| plies >= max_plies) {
+            DrawReason::MaxPlies
+        } else {
+            return None;
+        };
+        Some(GameResult::Draw(reason))
+    }
+}
+
+impl Debug for EngineMatch {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("EngineMatch")
+            .field("time_control", &self.time_control)
+            .field("time_odds", &self.time_odds)
+            .field("start", &self.start)
+            .field("max_plies", &self.max_plies)
+            .field("grace", &self.grace)
+            .field("adjudication", &self.adjudication)
+            .field("has_adjudicator", &self.adjudicator.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+// Starts a new game on the engine, and waits until it is ready.
+async fn new_game(eng: &mut Engine) -> Result<(), UziErr> {
+    eng.new_game().await?;
+    eng.sync(SYNC_TIMEOUT).await
+}
+
+// Searches the position that was sent, stopping the engine once the limit
+// passes. Returns the best move, and the last score of the best line with its
+// depth.
+async fn search(
+    eng: &mut Engine,
+    go: &Go,
+    limit: Duration,
+) -> Result<(Pm, Option<Score>, Option<u16>), UziErr> {
+    let mut search = eng.go_with_deadline(go, limit).await?;
+    let (mut score, mut depth) = (None, None);
+    while let Some(info) = search.next_info().await? {
+        if info.multi_pv().unwrap_or(1) == 1 && info.score().is_some() {
+            score = info.score();
+            depth = info.depth();
+        }
+    }
+    let (best, _) = search.wait().await?;
+    Ok((best, score, depth))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::fake_engine;
+    use std::str::FromStr;
+
+    fn engine(replies: &[&str], sleep: &str) -> Engine {
+        scoring_engine(replies, sleep, "cp 0")
+    }
+
+    // Replies to the last move of the position with the move it is mapped to,
+    // e.g. "startpos:f2f3", after sleeping for the given time, and always
+    // reports the given score.
+    fn scoring_engine(replies: &[&str], sleep: &str, score: &str) -> Engine {
+        let cases: String = replies
+            .iter()
+            .map(|reply| {
+                let (last, pm) = reply.split_once(':').unwrap();
+                format!("{}) best={};; ", last, pm)
+            })
+            .collect();
+        let script = format!(
+            r#"
+while read -r line; do
+    case "$line" in
+        isready) echo "readyok";;
+        position*) last=${{line##* }};;
+        go*)
+            case "$last" in {} esac
+            sleep {}
+            echo "info depth 1 score {} pv $best"
+            echo "bestmove $best";;
+    esac
+done
+"#,
+            cases, sleep, score
+        );
+        Engine::new(fake_engine(&script))
+    }
+
+    fn time_control() -> TimeControl {
+        TimeControl::new(Duration::from_secs(5), Duration::from_millis(100))
+    }
+
+    fn moves(moves: &[&str]) -> Vec<Pm> {
+        moves.iter().map(|pm| Pm::from_str(pm).unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn match_ends_in_checkmate() {
+        let mut white = engine(&["startpos:f2f3", "e7e5:g2g4"], "0");
+        let mut black = engine(&["f2f3:e7e5", "g2g4:d8h4"], "0");
+        let game = EngineMatch::new(time_control())
+            .play(&mut white, &mut black)
+            .await
+            .unwrap();
+        assert_eq!(game.moves, moves(&["f2f3", "e7e5", "g2g4", "d8h4"]));
+        assert_eq!(
+            game.result,
+            GameResult::Win(Side::Black, WinReason::Checkmate)
+        );
+        assert_eq!(game.result.as_str(), "0-1");
+        // Both sides got the increment twice.
+        assert!(game.white_time > Duration::from_secs(5));
+        assert!(game.black_time > Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn match_starts_every_game_with_new_game() {
+        let mut white = engine(&["startpos:f2f3", "e7e5:g2g4"], "0");
+        let mut black = engine(&["f2f3:e7e5", "g2g4:d8h4"], "0");
+        let game_match = EngineMatch::new(time_control());
+        for _ in 0..2 {
+            game_match.play(&mut white, &mut black).await.unwrap();
+        }
+        for eng in [&white, &black] {
+            let traffic = eng.traffic();
+            assert_eq!(traffic.sent("ucinewgame"), 2);
+            assert_eq!(traffic.sent("isready"), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn match_with_time_odds() {
+        let mut white = engine(&["startpos:f2f3", "e7e5:g2g4"], "0");
+        let mut black = engine(&["f2f3:e7e5", "g2g4:d8h4"], "0");
+        let game = EngineMatch::new(time_control())
+            .set_time_odds(0.5, 1.0)
+            .play(&mut white, &mut black)
+            .await
+            .unwrap();
+        assert_eq!(
+            game.white_time_control,
+            TimeControl::new(Duration::from_millis(2500), Duration::from_millis(50))
+        );
+        assert_eq!(game.black_time_control, time_control());
+        assert!(game.white_time < Duration::from_secs(3));
+        assert!(game.black_time > Duration::from_secs(5));
+        assert_eq!(time_control().scaled(-1.0), time_control());
+    }
+
+    #[tokio::test]
+    async fn match_sends_events() {
+        let mut white = engine(&["startpos:f2f3", "e7e5:g2g4"], "0");
+        let mut black = engine(&["f2f3:e7e5", "g2g4:d8h4"], "0");
+        let mut game_match = EngineMatch::new(time_control());
+        let mut receiver = game_match.subscribe_events();
+        game_match.play(&mut white, &mut black).await.unwrap();
+        let events: Vec<MatchEvent> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert_eq!(events.len(), 6);
+        assert!(matches!(
+            events[0],
+            MatchEvent::GameStarted {
+                game: 0,
+                white: None,
+                ..
+            }
+        ));
+        let MatchEvent::MovePlayed {
+            side,
+            ref san,
+            stats,
+            ..
+        } = events[4]
+        else {
+            panic!("not a move: {:?}", events[4]);
+        };
+        assert_eq!(
+            (side, san.as_str(), stats.depth),
+            (Side::Black, "Qh4#", Some(1))
+        );
+        assert!(matches!(
+            events[5],
+            MatchEvent::GameFinished { game: 0, .. }
+        ));
+
+        game_match.set_max_plies(1);
+        let game = game_match.play(&mut white, &mut black).await.unwrap();
+        let events: Vec<MatchEvent> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert_eq!(events.len(), 4);
+        assert_eq!(
+            events[2],
+            MatchEvent::Adjudicated {
+                game: 1,
+                result: GameResult::Draw(DrawReason::MaxPlies),
+            }
+        );
+        assert_eq!(
+            events[3],
+            MatchEvent::GameFinished {
+                game: 1,
+                record: game
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn match_draws_by_repetition_and_material() {
+        let mut white = engine(&["startpos:g1f3", "f6g8:g1f3", "g8f6:f3g1"], "0");
+        let mut black = engine(&["g1f3:g8f6", "f3g1:f6g8"], "0");
+        let game = EngineMatch::new(time_control())
+            .play(&mut white, &mut black)
+            .await
+            .unwrap();
+        assert_eq!(game.moves.len(), 8);
+        assert_eq!(game.result, GameResult::Draw(DrawReason::Repetition));
+
+        let mut game_match = EngineMatch::new(time_control());
+        game_match.set_start(Pos::with_fen("8/8/8/8/8/8/8/KB5k w - - 0 1"));
+        let game = game_match.play(&mut white, &mut black).await.unwrap();
+        assert!(game.moves.is_empty());
+        assert_eq!(
+            game.result,
+            GameResult::Draw(DrawReason::InsufficientMaterial)
+        );
+    }
+
+    #[tokio::test]
+    async fn match_forfeits_illegal_moves_and_time() {
+        let mut white = engine(&["startpos:e2e5"], "0");
+        let mut black = engine(&[], "0");
+        let game = EngineMatch::new(time_control())
+            .play(&mut white, &mut black)
+            .await
+            .unwrap();
+        let illegal = Pm::from_str("e2e5").unwrap();
+        assert_eq!(
+            game.result,
+            GameResult::Win(Side::Black, WinReason::IllegalMove(illegal))
+        );
+        assert_eq!(game.result.reason(), "White makes an illegal move: e2e5");
+
+        let mut white = engine(&["startpos:e2e4"], "0.5");
+        let tc = TimeControl::new(Duration::from_millis(100), Duration::ZERO);
+        let game = EngineMatch::new(tc)
+            .play(&mut white, &mut black)
+            .await
+            .unwrap();
+        assert!(game.moves.is_empty());
+        assert_eq!(
+            game.result,
+            GameResult::Win(Side::Black, WinReason::Timeout)
+        );
+        assert_eq!(game.result.reason(), "White loses on time");
+        assert_eq!(game.white_time, Duration::ZERO);
+
+        let mut white = engine(&["startpos:e2e4"], "0");
+        let mut black = Engine::new(fake_engine(
+            r#"
+while read -r line; do
+    case "$line" in
+        isready) echo "readyok";;
+        go*) exit 1;;
+    esac
+done
+"#,
+        ));
+        let game = EngineMatch::new(time_control())
+            .play(&mut white, &mut black)
+            .await
+            .unwrap();
+        assert_eq!(game.moves.len(), 1);
+        assert!(matches!(
+            game.result,
+            GameResult::Win(Side::White, WinReason::EngineFailed(ref err))
+                if matches!(err.root(), UziErr::Crashed(_))
+        ));
+        assert_eq!(game.result.reason(), "Black's engine crashed");
+    }
+
+    #[tokio::test]
+    async fn match_adjudicates_resignation_and_draws() {
+        let white_moves = ["startpos:g1f3", "f6g8:g1f3", "g8f6:f3g1"];
+        let black_moves = ["g1f3:g8f6", "f3g1:f6g8"];
+        let mut white = scoring_engine(&white_moves, "0", "cp -600");
+        let mut black = scoring_engine(&black_moves, "0", "cp 600");
+        let mut adjudication = Adjudication::new();
+        adjudication.set_resign(500, 2).set_draw(10, 1, 1);
+        let mut game_match = EngineMatch::new(time_control());
+        game_match.set_adjudication(adjudication);
+        let game = game_match.play(&mut white, &mut black).await.unwrap();
+        assert_eq!(game.moves.len(), 4);
+        assert_eq!(
+            game.result,
+            GameResult::Win(Side::Black, WinReason::Resignation)
+        );
+        assert_eq!(game.stats[0].score.and_then(|score| score.cp()), Some(-600));
+        assert_eq!(game.stats[0].depth, Some(1));
+
+        // Both engines think the position is equal after their first move.
+        let mut white = scoring_engine(&white_moves, "0", "cp 5");
+        let mut black = scoring_engine(&black_moves, "0", "cp -5");
+        let game = game_match.play(&mut white, &mut black).await.unwrap();
+        assert_eq!(game.moves.len(), 2);
+        assert_eq!(game.result, GameResult::Draw(DrawReason::Adjudication));
+    }
+
+    // Declares the game won by white once black has moved twice.
+    struct AfterTwoMoves;
+
+    impl Adjudicator for AfterTwoMoves {
+        fn adjudicate(&self, pos: &Pos, scores: &[Option<Score>]) -> Verdict {
+            assert_eq!(pos.moves().len(), scores.len());
+            match pos.moves().len() {
+                4 => Verdict::WinFor(Side::White),
+                _ => Verdict::Continue,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn match_asks_adjudicator() {
+        let mut white = engine(&["startpos:g1f3", "f6g8:g1f3", "g8f6:f3g1"], "0");
+        let mut black = engine(&["g1f3:g8f6", "f3g1:f6g8"], "0");
+        let mut game_match = EngineMatch::new(time_control());
+        game_match.set_adjudicator(AfterTwoMoves);
+        let game = game_match.play(&mut white, &mut black).await.unwrap();
+        assert_eq!(game.moves.len(), 4);
+        assert_eq!(
+            game.result,
+            GameResult::Win(Side::White, WinReason::Adjudicator)
+        );
+    }
+}
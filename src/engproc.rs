@@ -0,0 +1,474 @@
+// This module contains EngineProcess, which runs a chess engine as a child
+// process and exchanges lines of text with it through its stdin and stdout.
+
+use crate::err::UziErr;
+use crate::framing::LineReader;
+use crate::sched;
+use crate::transport::Transport;
+#[cfg(windows)]
+use crate::winproc::{self, Job};
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Debug, Formatter};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+// The number of stdout and stderr lines kept for crash reports.
+const STDERR_TAIL_LEN: usize = 32;
+const STDOUT_TAIL_LEN: usize = 32;
+
+// A running engine process. The process is killed when this is dropped.
+#[derive(Debug)]
+pub struct EngineProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: LineReader<ChildStdout>,
+
+    // The stderr of the engine is read by a separate task, which keeps the last
+    // lines for crash reports and forwards every line to the subscribers.
+    stderr_task: JoinHandle<()>,
+    stderr_tail: Arc<Mutex<VecDeque<String>>>,
+    stderr_tx: broadcast::Sender<String>,
+
+    // Text sent to the engine before the first line, see Launcher.
+    startup: Option<String>,
+
+    // The last line sent and the last lines received, for crash reports.
+    last_sent: Option<String>,
+    stdout_tail: VecDeque<String>,
+
+    // On Windows the engine runs in a job, so that the processes it starts
+    // are killed with it. Closing the job when this is dropped kills them.
+    #[cfg(windows)]
+    job: Job,
+}
+
+impl EngineProcess {
+    // Spawns the engine binary at the given path.
+    pub fn spawn<S: AsRef<OsStr>>(program: S) -> Result<Self, UziErr> {
+        Self::from_cmd(Command::new(program))
+    }
+
+    // Spawns the engine from a command that has been set up by the caller,
+    // e.g. with arguments. The standard streams are overridden. On Windows the
+    // engine is started without a console window.
+    pub fn from_cmd(mut cmd: Command) -> Result<Self, UziErr> {
+        #[cfg(windows)]
+        winproc::set_no_window(&mut cmd);
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        #[cfg(windows)]
+        let job = Job::assign(&child)?;
+        let stdin = child.stdin.take().ok_or(UziErr::EngineExited)?;
+        let stdout = child.stdout.take().ok_or(UziErr::EngineExited)?;
+        let stderr = child.stderr.take().ok_or(UziErr::EngineExited)?;
+
+        let stderr_tail = Arc::new(Mutex::new(VecDeque::with_capacity(STDERR_TAIL_LEN)));
+        let (stderr_tx, _) = broadcast::channel(256);
+        let stderr_task = tokio::spawn(read_stderr(stderr, stderr_tail.clone(), stderr_tx.clone()));
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout: LineReader::new(stdout),
+            stderr_task,
+            stderr_tail,
+            stderr_tx,
+            startup: None,
+            last_sent: None,
+            stdout_tail: VecDeque::with_capacity(STDOUT_TAIL_LEN),
+            #[cfg(windows)]
+            job,
+        })
+    }
+
+    // Returns the OS identifier of the process, if it is still running.
+    pub fn id(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    // Sends a line to the engine. The newline is added here.
+    pub async fn send_line(&mut self, line: &str) -> Result<(), UziErr> {
+        if let Some(startup) = self.startup.take() {
+            self.stdin.write_all(startup.as_bytes()).await?;
+            self.stdin.write_all(b"\n").await?;
+        }
+        self.last_sent = Some(line.into());
+        self.stdin.write_all(line.as_bytes()).await?;
+        self.stdin.write_all(b"\n").await?;
+        self.stdin.flush().await?;
+        Ok(())
+    }
+
+    // Reads the next line from the engine, without the line end, see
+    // LineReader. Returns None if the engine closed its output. This is cancel
+    // safe, so it can be used with timeouts without losing lines.
+    pub async fn recv_line(&mut self) -> Result<Option<String>, UziErr> {
+        let line = self.stdout.next_line().await?;
+        if let Some(ref line) = line {
+            if self.stdout_tail.len() == STDOUT_TAIL_LEN {
+                self.stdout_tail.pop_front();
+            }
+            self.stdout_tail.push_back(line.clone());
+        }
+        Ok(line)
+    }
+
+    // Returns a receiver for the lines the engine writes to stderr from now on.
+    // Slow receivers may miss lines, see tokio::sync::broadcast.
+    pub fn subscribe_stderr(&self) -> broadcast::Receiver<String> {
+        self.stderr_tx.subscribe()
+    }
+
+    // Returns the last lines the engine wrote to stderr, oldest first.
+    pub fn stderr_tail(&self) -> Vec<String> {
+        self.stderr_tail.lock().unwrap().iter().cloned().collect()
+    }
+
+    // Creates the report for an engine that exited unexpectedly. The engine
+    // usually writes the reason to stderr right before exiting, so this waits
+    // briefly for the rest of stderr to be read and for the process to exit.
+    pub async fn crash_report(&mut self) -> CrashReport {
+        let wait = Duration::from_millis(100);
+        let _ = time::timeout(wait, &mut self.stderr_task).await;
+        let status = match time::timeout(wait, self.child.wait()).await {
+            Ok(Ok(status)) => Some(status),
+            _ => None,
+        };
+        CrashReport {
+            exit_code: status.and_then(|status| status.code()),
+            signal: status.and_then(exit_signal),
+            in_flight: self.last_sent.clone(),
+            stdout: self.stdout_tail.iter().cloned().collect(),
+            stderr: self.stderr_tail(),
+        }
+    }
+
+    // Waits for the process to exit. The output is read until then, so that
+    // the engine does not block writing to a full pipe.
+    pub async fn wait_exit(&mut self) -> Result<(), UziErr> {
+        while self.stdout.next_line().await?.is_some() {}
+        self.child.wait().await?;
+        Ok(())
+    }
+
+    // Kills the process and waits for it to exit.
+    pub async fn kill(&mut self) -> Result<(), UziErr> {
+        Ok(self.child.kill().await?)
+    }
+}
+
+impl Drop for EngineProcess {
+    fn drop(&mut self) {
+        self.stderr_task.abort();
+    }
+}
+
+// Reads the stderr of the engine until it is closed.
+async fn read_stderr(
+    stderr: ChildStderr,
+    tail: Arc<Mutex<VecDeque<String>>>,
+    tx: broadcast::Sender<String>,
+) {
+    let mut lines = LineReader::new(stderr);
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            // The rest of a line that is too long is skipped.
+            Err(UziErr::LineTooLong(_)) => continue,
+            _ => break,
+        };
+        {
+            let mut tail = tail.lock().unwrap();
+            if tail.len() == STDERR_TAIL_LEN {
+                tail.pop_front();
+            }
+            tail.push_back(line.clone());
+        }
+        // This only fails if there are no subscribers.
+        let _ = tx.send(line);
+    }
+}
+
+// Returns the signal that terminated the process, if any.
+#[cfg(unix)]
+pub(crate) fn exit_signal(status: ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn exit_signal(_status: ExitStatus) -> Option<i32> {
+    None
+}
+
+// Describes an engine that exited unexpectedly.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CrashReport {
+    // The exit code of the process, or the signal that killed it. Both are
+    // None if the process did not exit in time to be reported.
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+
+    // The last command sent to the engine, which is likely the one that made
+    // it crash.
+    pub in_flight: Option<String>,
+
+    // The last lines the engine wrote to stdout and stderr, oldest first.
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+}
+
+// The parameters used to launch an engine process, e.g.:
+//
+// let mut launcher = Launcher::new("stockfish");
+// launcher.set_cwd("/opt/engines").set_env("OMP_NUM_THREADS", "1");
+// let proc = launcher.spawn()?;
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Launcher {
+    program: OsString,
+    args: Vec<OsString>,
+
+    // The working directory, the current one if not set.
+    cwd: Option<PathBuf>,
+
+    // Variables added to the environment inherited from this process.
+    env: Vec<(OsString, OsString)>,
+
+    // Text sent to the engine before anything else, e.g. a setoption with the
+    // path to the NNUE file for engines that need it before uci.
+    startup: Option<String>,
+
+    // The niceness of the process and the CPUs it can run on, see sched.rs for
+    // the supported platforms.
+    nice: Option<i32>,
+    cpus: Option<Vec<usize>>,
+}
+
+impl Launcher {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Self {
+        Self {
+            program: program.as_ref().into(),
+            ..Self::default()
+        }
+    }
+
+    // Adds a command-line argument. Arguments are passed in the order they are
+    // added.
+    pub fn add_arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.args.push(arg.as_ref().into());
+        self
+    }
+
+    pub fn set_cwd<P: AsRef<Path>>(&mut self, cwd: P) -> &mut Self {
+        self.cwd = Some(cwd.as_ref().into());
+        self
+    }
+
+    pub fn set_env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, val: V) -> &mut Self {
+        self.env.push((key.as_ref().into(), val.as_ref().into()));
+        self
+    }
+
+    // Sets the text sent to the engine before the first command. It can span
+    // several lines, the last newline is added when it is sent.
+    pub fn set_startup(&mut self, startup: &str) -> &mut Self {
+        self.startup = Some(startup.into());
+        self
+    }
+
+    // Sets the niceness of the process, from -20 (highest priority) to 19
+    // (lowest). Raising the priority usually needs special privileges.
+    pub fn set_nice(&mut self, nice: i32) -> &mut Self {
+        self.nice = Some(nice);
+        self
+    }
+
+    // Restricts the process to the given CPUs, numbered from 0. This keeps
+    // engines that play each other from competing for the same cores.
+    pub fn set_affinity(&mut self, cpus: &[usize]) -> &mut Self {
+        self.cpus = Some(cpus.into());
+        self
+    }
+
+    // Launches the engine.
+    pub fn spawn(&self) -> Result<EngineProcess, UziErr> {
+        let mut proc = EngineProcess::from_cmd(Command::from(self.command()?))?;
+        proc.startup = self.startup.clone();
+        Ok(proc)
+    }
+
+    // Returns the command that launches the engine, without the standard
+    // streams set up.
+    pub(crate) fn command(&self) -> Result<std::process::Command, UziErr> {
+        let mut cmd = std::process::Command::new(&self.program);
+        cmd.args(&self.args)
+            .envs(self.env.iter().map(|(k, v)| (k, v)));
+        if let Some(ref cwd) = self.cwd {
+            cmd.current_dir(cwd);
+        }
+        sched::set_sched(&mut cmd, self.nice, self.cpus.as_deref())?;
+        Ok(cmd)
+    }
+
+    pub(crate) fn startup(&self) -> Option<&str> {
+        self.startup.as_deref()
+    }
+}
+
+// A function that creates new engine connections, usually by spawning engine
+// processes. It is also used to restart an engine that hung or crashed.
+#[derive(Clone)]
+pub struct Spawner(Arc<dyn Fn() -> Result<Box<dyn Transport>, UziErr> + Send + Sync>);
+
+impl Spawner {
+    pub fn new<T, F>(spawn_fn: F) -> Self
+    where
+        T: Transport + 'static,
+        F: Fn() -> Result<T, UziErr> + Send + Sync + 'static,
+    {
+        Self(Arc::new(move || {
+            spawn_fn().map(|transport| Box::new(transport) as Box<dyn Transport>)
+        }))
+    }
+
+    // A spawner that runs the engine binary at the given path.
+    pub fn from_program<S: AsRef<OsStr>>(program: S) -> Self {
+        Self::from_launcher(Launcher::new(program))
+    }
+
+    // A spawner that launches the engine with the given parameters.
+    pub fn from_launcher(launcher: Launcher) -> Self {
+        Self::new(move || launcher.spawn())
+    }
+
+    pub fn spawn(&self) -> Result<Box<dyn Transport>, UziErr> {
+        (self.0)()
+    }
+}
+
+impl Debug for Spawner {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("Spawner")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn engine_process_send_and_recv() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("read -r line; echo \"got $line\"");
+        let mut proc = EngineProcess::from_cmd(cmd).unwrap();
+        proc.send_line("uci").await.unwrap();
+        assert_eq!(proc.recv_line().await, Ok(Some("got uci".into())));
+        assert_eq!(proc.recv_line().await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn engine_process_crlf() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("printf 'id name Fake\\r\\nuciok\\r\\n'");
+        let mut proc = EngineProcess::from_cmd(cmd).unwrap();
+        assert_eq!(proc.recv_line().await, Ok(Some("id name Fake".into())));
+        assert_eq!(proc.recv_line().await, Ok(Some("uciok".into())));
+    }
+
+    #[tokio::test]
+    async fn engine_process_stderr() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg("read -r line; echo warning >&2; echo \"$line\"; echo dying >&2; exit 1");
+        let mut proc = EngineProcess::from_cmd(cmd).unwrap();
+        let mut stderr = proc.subscribe_stderr();
+
+        proc.send_line("uci").await.unwrap();
+        assert_eq!(stderr.recv().await, Ok("warning".into()));
+        assert_eq!(proc.recv_line().await, Ok(Some("uci".into())));
+        assert_eq!(proc.recv_line().await, Ok(None));
+        assert_eq!(
+            proc.crash_report().await,
+            CrashReport {
+                exit_code: Some(1),
+                signal: None,
+                in_flight: Some("uci".into()),
+                stdout: vec!["uci".into()],
+                stderr: vec!["warning".into(), "dying".into()]
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn engine_process_crash_signal() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("read -r line; kill -SEGV $$");
+        let mut proc = EngineProcess::from_cmd(cmd).unwrap();
+        proc.send_line("go depth 5").await.unwrap();
+        assert_eq!(proc.recv_line().await, Ok(None));
+
+        let report = proc.crash_report().await;
+        assert_eq!(report.exit_code, None);
+        assert_eq!(report.signal, Some(11));
+        assert_eq!(report.in_flight, Some("go depth 5".into()));
+    }
+
+    #[tokio::test]
+    async fn launcher_spawn() {
+        let mut launcher = Launcher::new("sh");
+        launcher
+            .add_arg("-c")
+            .add_arg("while read -r line; do echo \"$line $UZI_TEST $(pwd)\"; done")
+            .set_cwd("/")
+            .set_env("UZI_TEST", "set")
+            .set_startup("setoption name EvalFile value nn.nnue");
+        let mut proc = launcher.spawn().unwrap();
+
+        proc.send_line("uci").await.unwrap();
+        assert_eq!(
+            proc.recv_line().await,
+            Ok(Some("setoption name EvalFile value nn.nnue set /".into()))
+        );
+        assert_eq!(proc.recv_line().await, Ok(Some("uci set /".into())));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn launcher_nice_and_affinity() {
+        let mut launcher = Launcher::new("sh");
+        launcher
+            .add_arg("-c")
+            .add_arg("read -r line; nice; grep Cpus_allowed_list /proc/self/status")
+            .set_nice(5)
+            .set_affinity(&[0]);
+        let mut proc = launcher.spawn().unwrap();
+
+        proc.send_line("uci").await.unwrap();
+        assert_eq!(proc.recv_line().await, Ok(Some("5".into())));
+        assert_eq!(
+            proc.recv_line().await,
+            Ok(Some("Cpus_allowed_list:\t0".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn engine_process_spawn_missing_binary() {
+        assert!(matches!(
+            EngineProcess::spawn("/no/such/engine"),
+            Err(UziErr::IoErr(_))
+        ));
+    }
+}
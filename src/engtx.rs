@@ -43,7 +43,7 @@ impl UziOut {
     fn send_cmd(&self, cmd: EngCmd) {
         self.run_time.spawn(async move {
             let result = stdout().write(cmd.to_string().as_bytes()).await;
-            if let Err(_) = result {
+            if result.is_err() {
                 todo!();
             }
         });
@@ -68,15 +68,12 @@ impl EngOutTx for UziOut {
     }
 
     fn send_best(&self, best: Pm) {
-        self.send_cmd(EngCmd::BestMove {
-            best: best,
-            ponder: None,
-        });
+        self.send_cmd(EngCmd::BestMove { best, ponder: None });
     }
 
     fn send_ponder(&self, best: Pm, ponder: Pm) {
         self.send_cmd(EngCmd::BestMove {
-            best: best,
+            best,
             ponder: Some(ponder),
         });
     }
@@ -118,6 +115,6 @@ impl EngTx for UziEngTx {
 
 impl From<Arc<UziOut>> for UziEngTx {
     fn from(uzi_out: Arc<UziOut>) -> Self {
-        UziEngTx { uzi_out: uzi_out }
+        UziEngTx { uzi_out }
     }
 }
@@ -1,22 +1,139 @@
+use crate::engproc::CrashReport;
+#[cfg(feature = "websocket")]
+use tokio_tungstenite::tungstenite;
+
 // En enum to represent all errors in the library.
 #[derive(Debug, Clone, PartialEq)]
 pub enum UziErr {
     BadBool,
+    // A tournament checkpoint is from another tournament, or a game in it
+    // can't be parsed.
+    BadCheckpoint,
+    // The reply to a diagnostic command, e.g. eval, can't be parsed.
+    BadDiagnostic,
+    // An engine of a definition file is malformed, with the number of the
+    // line of an INI file or of the engine of a JSON file, starting at 1.
+    BadEngineDef(usize),
     BadMillis(String, String),
     BadNumber(String),
+    // An opening of a suite is not a valid position, with the number of the
+    // opening, starting at 1.
+    BadOpening(usize),
     BadOpponent,
+    BadOptDecl,
+    // The value of a setoption does not fit the declared option.
+    BadOptValue,
     BadPlayerType,
     BadPositionVal,
+    // A line of a session recording can't be parsed, with the number of the
+    // line, starting at 1.
+    BadRecording(usize),
+    // A line of a MockGui script is neither a command nor a reply, with the
+    // number of the line, starting at 1.
+    BadScript(usize),
+    BadSearchState,
+    // A line of a snapshot can't be parsed, with the number of the line,
+    // starting at 1.
+    BadSnapshot(usize),
     BadTitle,
+    BestMoveErr,
+    // The engine cannot be restarted because it was not spawned by the client.
+    CannotRestart,
+    // The engine exited unexpectedly.
+    Crashed(CrashReport),
+    // The engine closed its output, i.e. the process exited or crashed.
+    EngineExited,
+    // The engine did not reply in time and was killed by the watchdog.
+    EngineHung,
     GoErr,
+    // An HTTP response with a status other than 2xx, or that can't be parsed,
+    // with its status line.
+    HttpErr(String),
+    IdErr,
+    InfoErr,
+    // An I/O error while talking to the engine, with the error message.
+    IoErr(String),
+    // A value could not be serialized to JSON or parsed from it, with the
+    // error message.
+    JsonErr(String),
+    // A line from the peer is longer than the maximum, with the maximum.
+    LineTooLong(usize),
     MissingCmd,
     MissingOnOff,
+    // The engine didn't send a line starting with the text in time.
+    MissingReply(String),
     NothingSetForGo,
     ParseMoveErr,
     ParsePieceErr(String),
     ParseSqErr,
     Position,
+    // A register command without later, a name, or a code.
+    RegisterErr,
+    // The client sent a line that a replayed engine didn't get at that point
+    // of the recording, with the line.
+    ReplayMismatch(String),
+    // The engine was not respawned because it reached the respawn limit.
+    RespawnLimit,
     SetOptErr,
+    // A copyprotection or registration status other than checking, ok, or
+    // error.
+    StatusErr,
+    // The engine did not respond within the expected time.
+    Timeout,
     UnknownOpt,
+    // An engine speaks a protocol that isn't supported there, with the
+    // protocol.
+    UnsupportedProtocol(String),
     What,
+    // An error returned by the client, with the last lines exchanged with the
+    // engine when it happened, oldest first.
+    WithTranscript(Box<UziErr>, Vec<String>),
+    // An xboard command with a missing or malformed part, e.g. a feature
+    // with an unterminated string.
+    XboardErr,
+}
+
+impl UziErr {
+    // Returns the error without the transcript attached by the client.
+    pub fn root(&self) -> &UziErr {
+        match self {
+            UziErr::WithTranscript(err, _) => err.root(),
+            err => err,
+        }
+    }
+
+    // Like root, but takes the error.
+    pub fn into_root(self) -> UziErr {
+        match self {
+            UziErr::WithTranscript(err, _) => err.into_root(),
+            err => err,
+        }
+    }
+
+    // Returns the transcript attached by the client, if any.
+    pub fn transcript(&self) -> Option<&[String]> {
+        match self {
+            UziErr::WithTranscript(_, lines) => Some(lines),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for UziErr {
+    fn from(err: std::io::Error) -> Self {
+        UziErr::IoErr(err.to_string())
+    }
+}
+
+#[cfg(feature = "websocket")]
+impl From<tungstenite::Error> for UziErr {
+    fn from(err: tungstenite::Error) -> Self {
+        match err {
+            tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+                UziErr::EngineExited
+            }
+            tungstenite::Error::Io(err) => err.into(),
+            err => UziErr::IoErr(err.to_string()),
+        }
+    }
 }
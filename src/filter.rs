@@ -0,0 +1,236 @@
+// This module contains FilterTransport, which changes the commands an engine
+// sends before the client gets them, e.g. to drop the currline and refutation
+// fields of a chatty engine, or the info strings of one that debugs to stdout.
+// Filters are applied in the order they were added, and transports can be
+// wrapped in more than one layer, e.g. a filter around a TapTransport that
+// still logs the lines as the engine sent them:
+//
+// let mut transport = FilterTransport::new(TapTransport::new(process, "sf", log));
+// transport
+//     .strip_info_fields(&[InfoField::CurrLine, InfoField::String])
+//     .set_max_multipv(4);
+// let eng = Engine::new(transport);
+
+use crate::engcmd::{EngCmd, InfoField};
+use crate::engproc::CrashReport;
+use crate::err::UziErr;
+use crate::guicmd::GuiCmd;
+use crate::opt::SetOpt;
+use crate::transport::{BoxFuture, Transport};
+use std::fmt::{self, Debug, Formatter};
+use std::str::FromStr;
+use tokio::sync::broadcast;
+
+// A filter gets every command of the engine and returns the command to pass
+// on, if any.
+type CmdFilter = Box<dyn FnMut(EngCmd) -> Option<EngCmd> + Send>;
+
+// A transport that filters the commands of another one. Lines that are not UCI
+// commands, and commands that the filters leave as they are, are passed on
+// unchanged.
+pub struct FilterTransport<T> {
+    inner: T,
+    filters: Vec<CmdFilter>,
+    max_multipv: Option<u64>,
+}
+
+impl<T: Transport> FilterTransport<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            filters: Vec::new(),
+            max_multipv: None,
+        }
+    }
+
+    // Adds a filter that returns the command to pass on, possibly changed, or
+    // None to drop it.
+    pub fn add_filter<F>(&mut self, filter: F) -> &mut Self
+    where
+        F: FnMut(EngCmd) -> Option<EngCmd> + Send + 'static,
+    {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    // Removes the fields from the infos of the engine. Infos that have no
+    // fields left, e.g. an info string without its string, are dropped.
+    pub fn strip_info_fields(&mut self, fields: &[InfoField]) -> &mut Self {
+        let fields = fields.to_vec();
+        self.add_filter(move |cmd| match cmd {
+            EngCmd::Info(mut info) => {
+                for field in &fields {
+                    info.remove(*field);
+                }
+                (!info.is_empty()).then_some(EngCmd::Info(info))
+            }
+            cmd => Some(cmd),
+        })
+    }
+
+    // Lowers the MultiPV values set on the engine to at most max, and drops
+    // the lines of higher ranks, for engines that send them anyway.
+    pub fn set_max_multipv(&mut self, max: u64) -> &mut Self {
+        self.max_multipv = Some(max);
+        self.add_filter(move |cmd| match cmd {
+            EngCmd::Info(ref info) if info.multi_pv().is_some_and(|rank| rank > max) => None,
+            cmd => Some(cmd),
+        })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    // Returns the line to pass on for a line of the engine, if any.
+    fn filter(&mut self, line: String) -> Option<String> {
+        let Ok(cmd) = EngCmd::from_str(&line) else {
+            return Some(line);
+        };
+        let mut filtered = cmd.clone();
+        for filter in &mut self.filters {
+            filtered = filter(filtered)?;
+        }
+        match filtered == cmd {
+            true => Some(line),
+            false => Some(filtered.to_string()),
+        }
+    }
+}
+
+impl<T> Debug for FilterTransport<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("FilterTransport")
+            .field("inner", &self.inner)
+            .field("filters", &self.filters.len())
+            .field("max_multipv", &self.max_multipv)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Transport> Transport for FilterTransport<T> {
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        if let Some(max) = self.max_multipv {
+            if let Ok(GuiCmd::SetOpt(SetOpt::MultiPv(value))) = GuiCmd::from_str(line) {
+                if value > max {
+                    let line = GuiCmd::SetOpt(SetOpt::MultiPv(max)).to_string();
+                    return Box::pin(async move { self.inner.send_line(&line).await });
+                }
+            }
+        }
+        self.inner.send_line(line)
+    }
+
+    // Lines are only dropped after they were read, so this is as cancel safe
+    // as the inner transport.
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        Box::pin(async move {
+            loop {
+                let Some(line) = self.inner.recv_line().await? else {
+                    return Ok(None);
+                };
+                if self.filters.is_empty() {
+                    return Ok(Some(line));
+                }
+                if let Some(line) = self.filter(line) {
+                    return Ok(Some(line));
+                }
+            }
+        })
+    }
+
+    fn subscribe_stderr(&self) -> broadcast::Receiver<String> {
+        self.inner.subscribe_stderr()
+    }
+
+    fn crash_report(&mut self) -> BoxFuture<'_, CrashReport> {
+        self.inner.crash_report()
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        self.inner.kill()
+    }
+
+    fn wait_exit(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        self.inner.wait_exit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Engine;
+    use crate::testutil::fake_engine;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    // Sends the lines below on go, and echoes the options it gets.
+    const CHATTY_ENGINE: &str = r#"
+        while read -r line; do
+            case "$line" in
+                isready) echo "readyok";;
+                setoption*) echo "id name $line";;
+                go*)
+                    echo "Chatty 1.0"
+                    echo "info depth 1 currline 1 e2e4 e7e5 string thinking"
+                    echo "info string only a string"
+                    echo "info depth 1 multipv 1 score cp 10 pv e2e4"
+                    echo "info depth 1 multipv 3 score cp -5 pv a2a3"
+                    echo "info  depth 1   nodes 40"
+                    echo "bestmove e2e4";;
+            esac
+        done
+    "#;
+
+    #[tokio::test]
+    async fn filter_infos() {
+        let mut transport = FilterTransport::new(fake_engine(CHATTY_ENGINE));
+        transport
+            .strip_info_fields(&[InfoField::CurrLine, InfoField::String])
+            .set_max_multipv(2)
+            .add_filter(|cmd| match cmd {
+                EngCmd::BestMove { .. } => Some(EngCmd::ReadyOk),
+                cmd => Some(cmd),
+            });
+        transport
+            .send_line("setoption name MultiPV value 8")
+            .await
+            .unwrap();
+        transport.send_line("go depth 1").await.unwrap();
+        let mut lines = Vec::new();
+        while lines.last().map(String::as_str) != Some("readyok") {
+            lines.push(transport.recv_line().await.unwrap().unwrap());
+        }
+        assert_eq!(
+            lines,
+            [
+                "id name setoption name MultiPV value 2",
+                "Chatty 1.0",
+                "info depth 1",
+                "info depth 1 multipv 1 score cp 10 pv e2e4",
+                // Lines the filters don't change keep their spacing.
+                "info  depth 1   nodes 40",
+                "readyok",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn filter_with_engine() {
+        let mut transport = FilterTransport::new(fake_engine(CHATTY_ENGINE));
+        transport.strip_info_fields(&[InfoField::String]);
+        let mut eng = Engine::new(transport);
+        eng.sync(TIMEOUT).await.unwrap();
+        let mut search = eng.go(&crate::guicmd::Go::new()).await.unwrap();
+        let mut strings = 0;
+        while let Some(info) = search.next_info().await.unwrap() {
+            strings += usize::from(info.string().is_some());
+        }
+        assert_eq!(strings, 0);
+    }
+}
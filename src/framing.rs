@@ -0,0 +1,328 @@
+// This module contains LineReader, which splits a byte stream into lines the
+// way engines and GUIs actually write them, rather than the way the protocol
+// says they should:
+// - lines can end with LF, CRLF, or a bare CR, as written by some old engines.
+// - bytes that are not valid UTF-8, e.g. a name in Latin-1, are replaced with
+//   U+FFFD rather than failing the stream.
+// - a line longer than the maximum fails with LineTooLong, and the rest of it
+//   is skipped, so that a broken peer can't make the reader buffer without
+//   end. The lines after it are read as usual.
+// - a last line without a line end is returned when the stream ends.
+//
+// With the arena feature, lines can also be read in batches allocated in an
+// arena, see next_batch. Blocking streams are read the same way on threads
+// without a runtime, see next_line_blocking.
+
+use crate::err::UziErr;
+#[cfg(feature = "arena")]
+use bumpalo::Bump;
+use std::io::Read;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// The default maximum length of a line in bytes, without the line end. UCI
+// lines are short, even a long pv with many lines of multipv is a few KB.
+pub const MAX_LINE_LEN: usize = 1 << 16;
+
+// How many bytes are read from the stream at a time.
+const CHUNK_LEN: usize = 4096;
+
+// The lines returned by LineReader::next_batch, which live in the arena they
+// were read into.
+#[cfg(feature = "arena")]
+pub type LineBatch<'b> = bumpalo::collections::Vec<'b, &'b str>;
+
+// Reads lines from a byte stream. This is cancel safe: the bytes read so far
+// are kept in the reader, so that a read that times out loses nothing.
+#[derive(Debug)]
+pub struct LineReader<R> {
+    reader: R,
+    // The bytes read but not returned yet.
+    buf: Vec<u8>,
+    max_len: usize,
+    // Whether the last line ended with a CR, so that an LF right after it is
+    // the rest of a CRLF rather than an empty line.
+    after_cr: bool,
+    // Whether the rest of a line that was too long is being skipped.
+    skipping: bool,
+    is_eof: bool,
+}
+
+impl<R> LineReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            max_len: MAX_LINE_LEN,
+            after_cr: false,
+            skipping: false,
+            is_eof: false,
+        }
+    }
+
+    // Sets the maximum length of a line in bytes, MAX_LINE_LEN by default.
+    pub fn set_max_line_len(&mut self, max_len: usize) -> &mut Self {
+        self.max_len = max_len;
+        self
+    }
+
+    // Takes the next line out of the buffer and converts it with to_line.
+    // Returns None if the buffer doesn't hold a whole line yet.
+    fn pop_line<T>(
+        &mut self,
+        mut to_line: impl FnMut(&[u8]) -> T,
+    ) -> Option<Result<Option<T>, UziErr>> {
+        loop {
+            if let Some(end) = self.line_end() {
+                self.after_cr = self.buf[end] == b'\r';
+                let line = if std::mem::take(&mut self.skipping) {
+                    None
+                } else if end > self.max_len {
+                    Some(Err(UziErr::LineTooLong(self.max_len)))
+                } else {
+                    Some(Ok(Some(to_line(&self.buf[..end]))))
+                };
+                self.buf.drain(..=end);
+                match line {
+                    Some(line) => return Some(line),
+                    None => continue,
+                }
+            }
+            if self.buf.len() > self.max_len {
+                self.buf.clear();
+                if !self.skipping {
+                    self.skipping = true;
+                    return Some(Err(UziErr::LineTooLong(self.max_len)));
+                }
+            }
+            if !self.is_eof {
+                return None;
+            }
+            let line = (!self.buf.is_empty() && !std::mem::take(&mut self.skipping))
+                .then(|| to_line(&self.buf));
+            self.buf.clear();
+            return Some(Ok(line));
+        }
+    }
+
+    // Returns where the next line ends in the buffer, if it holds a whole one.
+    fn line_end(&mut self) -> Option<usize> {
+        if self.after_cr && !self.buf.is_empty() {
+            self.after_cr = false;
+            if self.buf[0] == b'\n' {
+                self.buf.remove(0);
+            }
+        }
+        self.buf.iter().position(|b| *b == b'\n' || *b == b'\r')
+    }
+
+    // Returns true if the next line can be taken from the buffer without
+    // reading or failing.
+    #[cfg(feature = "arena")]
+    fn is_line_ready(&mut self) -> bool {
+        !self.skipping && self.line_end().is_some_and(|end| end <= self.max_len)
+    }
+}
+
+impl<R: AsyncRead + Unpin> LineReader<R> {
+    // Returns the next line without its line end, or None once the stream
+    // has ended and every line has been returned.
+    pub async fn next_line(&mut self) -> Result<Option<String>, UziErr> {
+        loop {
+            match self.pop_line(|bytes| String::from_utf8_lossy(bytes).into_owned()) {
+                Some(line) => return line,
+                None => self.fill().await?,
+            }
+        }
+    }
+
+    // Like next_line, but reads the line into a buffer of the caller, so that a
+    // loop over the lines reuses the same buffer rather than allocating every
+    // line. Returns false once the stream has ended and every line has been
+    // returned, with the buffer left empty.
+    pub async fn read_into(&mut self, line: &mut String) -> Result<bool, UziErr> {
+        line.clear();
+        loop {
+            match self.pop_line(|bytes| line.push_str(&String::from_utf8_lossy(bytes))) {
+                Some(result) => return result.map(|line| line.is_some()),
+                None => self.fill().await?,
+            }
+        }
+    }
+
+    // Returns the lines that have been read, at least one, with their strings
+    // and the batch itself allocated in arena, or None once the stream has
+    // ended and every line has been returned. This is for proxies and
+    // recorders that go through massive logs: parsing the lines with the
+    // borrowed commands, e.g. InfoRef, and resetting the arena between batches
+    // reuses the same memory for every batch. A line that is too long fails
+    // the batch after the lines before it.
+    #[cfg(feature = "arena")]
+    pub async fn next_batch<'b>(
+        &mut self,
+        arena: &'b Bump,
+    ) -> Result<Option<LineBatch<'b>>, UziErr> {
+        let mut lines = LineBatch::new_in(arena);
+        loop {
+            if !lines.is_empty() && !self.is_line_ready() {
+                return Ok(Some(lines));
+            }
+            let line = self.pop_line(|bytes| &*arena.alloc_str(&String::from_utf8_lossy(bytes)));
+            match line {
+                Some(Ok(Some(line))) => lines.push(line),
+                Some(Ok(None)) => return Ok(Some(lines).filter(|lines| !lines.is_empty())),
+                Some(Err(err)) => return Err(err),
+                None if lines.is_empty() => self.fill().await?,
+                None => return Ok(Some(lines)),
+            }
+        }
+    }
+
+    // Reads the next chunk of the stream into the buffer.
+    async fn fill(&mut self) -> Result<(), UziErr> {
+        let mut chunk = [0; CHUNK_LEN];
+        let len = self.reader.read(&mut chunk).await?;
+        self.is_eof = len == 0;
+        self.buf.extend_from_slice(&chunk[..len]);
+        Ok(())
+    }
+}
+
+impl<R: Read> LineReader<R> {
+    // Like next_line, but blocks the thread on the stream, for readers that
+    // are not async, e.g. the pipes of a process in the blocking client.
+    pub fn next_line_blocking(&mut self) -> Result<Option<String>, UziErr> {
+        loop {
+            match self.pop_line(|bytes| String::from_utf8_lossy(bytes).into_owned()) {
+                Some(line) => return line,
+                None => self.fill_blocking()?,
+            }
+        }
+    }
+
+    // Reads the next chunk of the stream into the buffer, blocking the thread.
+    fn fill_blocking(&mut self) -> Result<(), UziErr> {
+        let mut chunk = [0; CHUNK_LEN];
+        let len = self.reader.read(&mut chunk)?;
+        self.is_eof = len == 0;
+        self.buf.extend_from_slice(&chunk[..len]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "arena")]
+    use crate::engcmd::InfoRef;
+    use tokio::io::{self, AsyncWriteExt};
+
+    async fn read_all(bytes: &[u8], max_len: usize) -> Vec<Result<String, UziErr>> {
+        let mut reader = LineReader::new(bytes);
+        reader.set_max_line_len(max_len);
+        let mut lines = Vec::new();
+        while let Some(line) = reader.next_line().await.transpose() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    #[tokio::test]
+    async fn line_endings() {
+        let lines = read_all(b"uciok\r\nreadyok\rbestmove e2e4\n\nid name A\xe9", 64).await;
+        let expected = ["uciok", "readyok", "bestmove e2e4", "", "id name A\u{fffd}"];
+        assert_eq!(lines, expected.map(|line| Ok(line.to_string())));
+    }
+
+    #[tokio::test]
+    async fn line_too_long() {
+        let long = "x".repeat(CHUNK_LEN * 2);
+        let text = format!("uciok\n{}\nreadyok\n123456789\n{}", long, long);
+        let lines = read_all(text.as_bytes(), 8).await;
+        assert_eq!(
+            lines,
+            [
+                Ok("uciok".to_string()),
+                Err(UziErr::LineTooLong(8)),
+                Ok("readyok".to_string()),
+                Err(UziErr::LineTooLong(8)),
+                Err(UziErr::LineTooLong(8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn next_line_blocking() {
+        let mut text = format!("uciok\r\n{}\nreadyok\r", "x".repeat(20)).into_bytes();
+        text.extend_from_slice(b"id name A\xe9");
+        let mut reader = LineReader::new(std::io::Cursor::new(text));
+        reader.set_max_line_len(16);
+        let mut lines = Vec::new();
+        while let Some(line) = reader.next_line_blocking().transpose() {
+            lines.push(line);
+        }
+        assert_eq!(
+            lines,
+            [
+                Ok("uciok".to_string()),
+                Err(UziErr::LineTooLong(16)),
+                Ok("readyok".to_string()),
+                Ok("id name A\u{fffd}".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn crlf_split_between_reads() {
+        let (mut writer, reader) = io::duplex(64);
+        let mut reader = LineReader::new(reader);
+        writer.write_all(b"uciok\r").await.unwrap();
+        assert_eq!(reader.next_line().await, Ok(Some("uciok".to_string())));
+        writer.write_all(b"\nreadyok\r\n").await.unwrap();
+        drop(writer);
+        assert_eq!(reader.next_line().await, Ok(Some("readyok".to_string())));
+        assert_eq!(reader.next_line().await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn read_into() {
+        let mut text = "x".repeat(100).into_bytes();
+        text.extend_from_slice(b"\ninfo depth 1\nid name A\xe9\n");
+        let mut reader = LineReader::new(text.as_slice());
+        let mut line = String::new();
+        assert_eq!(reader.read_into(&mut line).await, Ok(true));
+        let capacity = line.capacity();
+        assert_eq!(reader.read_into(&mut line).await, Ok(true));
+        assert_eq!(line, "info depth 1");
+        assert_eq!(reader.read_into(&mut line).await, Ok(true));
+        assert_eq!(line, "id name A\u{fffd}");
+        assert_eq!(line.capacity(), capacity);
+        assert_eq!(reader.read_into(&mut line).await, Ok(false));
+        assert!(line.is_empty());
+    }
+
+    #[cfg(feature = "arena")]
+    #[tokio::test]
+    async fn next_batch() {
+        let text = format!("info depth 1\r\ninfo depth 2\n{}\nreadyok", "x".repeat(20));
+        let mut reader = LineReader::new(text.as_bytes());
+        reader.set_max_line_len(16);
+        let mut arena = Bump::new();
+        let lines = reader.next_batch(&arena).await.unwrap().unwrap();
+        assert_eq!(lines, ["info depth 1", "info depth 2"]);
+        let depths: Vec<_> = lines
+            .iter()
+            .map(|line| InfoRef::parse(line).unwrap().depth())
+            .collect();
+        assert_eq!(depths, [Some(1), Some(2)]);
+        drop(lines);
+        assert_eq!(
+            reader.next_batch(&arena).await,
+            Err(UziErr::LineTooLong(16))
+        );
+
+        arena.reset();
+        let lines = reader.next_batch(&arena).await.unwrap().unwrap();
+        assert_eq!(lines, ["readyok"]);
+        assert_eq!(reader.next_batch(&arena).await, Ok(None));
+    }
+}
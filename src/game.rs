@@ -0,0 +1,175 @@
+// This module contains the game state tracked by the runner, i.e. the position
+// to search and whether it starts a new game.
+
+use crate::guicmd::Pos;
+use crate::pm::Pm;
+
+// The position to search, resolved by the runner from the position and
+// ucinewgame commands.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GamePos {
+    pos: Pos,
+    // The number of moves at the end of pos played since the previous search.
+    new_moves: usize,
+    is_continuation: bool,
+    is_new_game: bool,
+}
+
+impl GamePos {
+    pub fn pos(&self) -> &Pos {
+        &self.pos
+    }
+
+    // Returns the FEN of the initial position, or None for the start position.
+    pub fn fen(&self) -> Option<&str> {
+        self.pos.fen()
+    }
+
+    // Returns all the moves played from the initial position.
+    pub fn moves(&self) -> &[Pm] {
+        self.pos.moves()
+    }
+
+    // Returns the moves played since the previous search, so that an engine
+    // can update its board rather than setting it up again. If the position
+    // does not continue the previous one, e.g. after a takeback or in a new
+    // game, these are all the moves.
+    pub fn new_moves(&self) -> &[Pm] {
+        let moves = self.moves();
+        &moves[moves.len() - self.new_moves..]
+    }
+
+    // Returns true if the position continues the position of the previous
+    // search, i.e. only new_moves have to be played on top of it.
+    pub fn is_continuation(&self) -> bool {
+        self.is_continuation
+    }
+
+    // Returns true if this is the first search of a game.
+    pub fn is_new_game(&self) -> bool {
+        self.is_new_game
+    }
+}
+
+// Tracks the position and game commands from the GUI between searches.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GameTracker {
+    // The position set by the GUI, which is the start position if none is set.
+    pos: Pos,
+    // The position of the previous search in the current game.
+    searched: Option<Pos>,
+    got_new_game: bool,
+}
+
+impl GameTracker {
+    pub fn on_new_game(&mut self) {
+        self.got_new_game = true;
+        self.searched = None;
+    }
+
+    pub fn on_position(&mut self, pos: Pos) {
+        self.pos = pos;
+    }
+
+    // Returns the position set by the GUI.
+    pub fn pos(&self) -> &Pos {
+        &self.pos
+    }
+
+    // Resolves the position for a search. Also returns true if the position
+    // starts a new game the GUI did not announce with ucinewgame, i.e. it is
+    // the first search, or it is not from the same game as the previous one.
+    pub fn on_go(&mut self) -> (GamePos, bool) {
+        let got_new_game = std::mem::take(&mut self.got_new_game);
+        let missed_new_game = !got_new_game
+            && self
+                .searched
+                .as_ref()
+                .is_none_or(|searched| !self.pos.is_same_game(searched));
+
+        // The previous search is forgotten in a new game, so the position
+        // continues it if the moves extend the moves searched before.
+        let moves = self.pos.moves();
+        let searched_len = match self.searched {
+            Some(ref searched) if !missed_new_game && moves.starts_with(searched.moves()) => {
+                Some(searched.moves().len())
+            }
+            _ => None,
+        };
+        let game_pos = GamePos {
+            pos: self.pos.clone(),
+            new_moves: moves.len() - searched_len.unwrap_or(0),
+            is_continuation: searched_len.is_some(),
+            is_new_game: got_new_game || missed_new_game,
+        };
+        self.searched = Some(self.pos.clone());
+        (game_pos, missed_new_game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guicmd::GuiCmd;
+    use std::str::FromStr;
+
+    fn pos(cmd: &str) -> Pos {
+        match GuiCmd::from_str(cmd) {
+            Ok(GuiCmd::Pos(pos)) => pos,
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+    }
+
+    fn moves(pos: &GamePos) -> Vec<String> {
+        pos.new_moves().iter().map(|pm| pm.to_string()).collect()
+    }
+
+    #[test]
+    fn tracker_follows_game() {
+        let mut tracker = GameTracker::default();
+        tracker.on_new_game();
+        tracker.on_position(pos("position startpos moves e2e4"));
+        let (game_pos, missed_new_game) = tracker.on_go();
+        assert!(game_pos.is_new_game());
+        assert!(!game_pos.is_continuation());
+        assert!(!missed_new_game);
+        assert_eq!(moves(&game_pos), vec!["e2e4"]);
+
+        tracker.on_position(pos("position startpos moves e2e4 e7e5 g1f3"));
+        let (game_pos, missed_new_game) = tracker.on_go();
+        assert!(!game_pos.is_new_game());
+        assert!(game_pos.is_continuation());
+        assert!(!missed_new_game);
+        assert_eq!(moves(&game_pos), vec!["e7e5", "g1f3"]);
+
+        // The GUI sends the same position again, e.g. after a stopped search.
+        let (game_pos, _) = tracker.on_go();
+        assert!(game_pos.is_continuation());
+        assert!(game_pos.new_moves().is_empty());
+
+        // A takeback is the same game, but the engine has to set up the board.
+        tracker.on_position(pos("position startpos moves e2e4"));
+        let (game_pos, missed_new_game) = tracker.on_go();
+        assert!(!game_pos.is_new_game());
+        assert!(!game_pos.is_continuation());
+        assert!(!missed_new_game);
+        assert_eq!(moves(&game_pos), vec!["e2e4"]);
+    }
+
+    #[test]
+    fn tracker_detects_missing_new_game() {
+        let mut tracker = GameTracker::default();
+        // The first search is a new game, and the start position is searched
+        // if the GUI sent no position.
+        let (game_pos, missed_new_game) = tracker.on_go();
+        assert!(game_pos.is_new_game());
+        assert!(missed_new_game);
+        assert_eq!(game_pos.pos(), &Pos::new());
+
+        tracker.on_position(pos("position fen 8/8/8/8/8/8/8/K1k5 w - - 0 1"));
+        let (game_pos, missed_new_game) = tracker.on_go();
+        assert!(game_pos.is_new_game());
+        assert!(missed_new_game);
+        assert_eq!(game_pos.fen(), Some("8/8/8/8/8/8/8/K1k5 w - - 0 1"));
+    }
+}
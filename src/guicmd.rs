@@ -1,10 +1,11 @@
 // This module contains artifacts used to build and represent commands from the
 // GUI to the engine.
 
-use crate::conv::{to_millis, to_number};
+use crate::conv::{to_millis, to_number, write_millis, write_number, Words};
 use crate::err::UziErr;
 use crate::opt::SetOpt;
-use crate::pm::Pm;
+use crate::pm::{write_moves, MovesRef, Pm};
+use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 use std::time::Duration;
 
@@ -48,45 +49,216 @@ pub enum GuiCmd {
     // the engine was told to ponder on the same move the engine has played. The
     // engine has switched from pondering to normal search.
     Ponderhit,
+
+    // quit: Quit the program as soon as possible.
+    Quit,
+
+    // register later | register [name <x>] [code <y>]: Sent to engines that
+    // need registration, either to postpone it or with the user's name and
+    // code.
+    Register(Register),
 }
 
 impl FromStr for GuiCmd {
     type Err = UziErr;
 
     fn from_str(cmd: &str) -> Result<GuiCmd, Self::Err> {
-        let words = cmd.split_whitespace().collect::<Vec<_>>();
-        if words.is_empty() {
+        let mut words = cmd.split_whitespace();
+        let Some(name) = words.next() else {
             return Err(UziErr::MissingCmd);
-        }
-        match words[0] {
+        };
+        match name {
             "uci" => Ok(GuiCmd::Uci),
             "isready" => Ok(GuiCmd::IsReady),
             "ucinewgame" => Ok(GuiCmd::NewGame),
             "stop" => Ok(GuiCmd::Stop),
             "ponderhit" => Ok(GuiCmd::Ponderhit),
-            "debug" => {
-                if words.len() <= 1 {
-                    return Err(UziErr::MissingOnOff);
-                }
-                match words[1] {
-                    "on" => Ok(GuiCmd::Debug(true)),
-                    "off" => Ok(GuiCmd::Debug(false)),
-                    _ => Err(UziErr::MissingOnOff),
+            "quit" => Ok(GuiCmd::Quit),
+            "debug" => match words.next() {
+                Some("on") => Ok(GuiCmd::Debug(true)),
+                Some("off") => Ok(GuiCmd::Debug(false)),
+                _ => Err(UziErr::MissingOnOff),
+            },
+            // The moves of position and go are parsed as they are read, since
+            // there may be many of them.
+            "position" => Ok(GuiCmd::Pos(parse_pos(cmd.split_whitespace())?)),
+            "go" => Ok(GuiCmd::Go(parse_go(cmd.split_whitespace())?)),
+            "setoption" | "register" => {
+                let words = cmd.split_whitespace().collect::<Vec<_>>();
+                match name {
+                    "setoption" => Ok(GuiCmd::SetOpt(SetOpt::try_from(words.as_slice())?)),
+                    _ => Ok(GuiCmd::Register(Register::try_from(words.as_slice())?)),
                 }
             }
-            "setoption" => Ok(GuiCmd::SetOpt(SetOpt::try_from(words.as_slice())?)),
-            "position" => Ok(GuiCmd::Pos(Pos::try_from(words.as_slice())?)),
-            "go" => Ok(GuiCmd::Go(Go::try_from(words.as_slice())?)),
             _ => Err(UziErr::What),
         }
     }
 }
 
+impl Display for GuiCmd {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GuiCmd::Uci => formatter.write_str("uci"),
+            GuiCmd::Debug(true) => formatter.write_str("debug on"),
+            GuiCmd::Debug(false) => formatter.write_str("debug off"),
+            GuiCmd::IsReady => formatter.write_str("isready"),
+            GuiCmd::SetOpt(ref opt) => opt.fmt(formatter),
+            GuiCmd::NewGame => formatter.write_str("ucinewgame"),
+            GuiCmd::Pos(ref pos) => pos.fmt(formatter),
+            GuiCmd::Go(ref go) => go.fmt(formatter),
+            GuiCmd::Stop => formatter.write_str("stop"),
+            GuiCmd::Ponderhit => formatter.write_str("ponderhit"),
+            GuiCmd::Quit => formatter.write_str("quit"),
+            GuiCmd::Register(ref register) => register.fmt(formatter),
+        }
+    }
+}
+
+// A borrowed GUI command, whose option names and values, FEN and moves are
+// slices of the line it was parsed from, so that proxies and loggers can read
+// commands without allocating. go and register are sent rarely, so they are
+// parsed into their owned types.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum GuiCmdRef<'a> {
+    Uci,
+    Debug(bool),
+    IsReady,
+    // The name and the value of the option as sent.
+    SetOpt {
+        name: &'a str,
+        value: Option<&'a str>,
+    },
+    NewGame,
+    // The FEN as sent, or None for the start position, and the moves.
+    Pos {
+        fen: Option<&'a str>,
+        moves: MovesRef<'a>,
+    },
+    Go(Go),
+    Stop,
+    Ponderhit,
+    Quit,
+    Register(Register),
+}
+
+impl<'a> GuiCmdRef<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, UziErr> {
+        let mut words = Words::new(line);
+        let Some(name) = words.next() else {
+            return Err(UziErr::MissingCmd);
+        };
+        match name {
+            "uci" => Ok(GuiCmdRef::Uci),
+            "isready" => Ok(GuiCmdRef::IsReady),
+            "ucinewgame" => Ok(GuiCmdRef::NewGame),
+            "stop" => Ok(GuiCmdRef::Stop),
+            "ponderhit" => Ok(GuiCmdRef::Ponderhit),
+            "quit" => Ok(GuiCmdRef::Quit),
+            "debug" => match words.next() {
+                Some("on") => Ok(GuiCmdRef::Debug(true)),
+                Some("off") => Ok(GuiCmdRef::Debug(false)),
+                _ => Err(UziErr::MissingOnOff),
+            },
+            "setoption" => parse_set_opt_ref(words),
+            "position" => parse_pos_ref(words),
+            "go" => Ok(GuiCmdRef::Go(parse_go(line.split_whitespace())?)),
+            "register" => {
+                let words = line.split_whitespace().collect::<Vec<_>>();
+                Ok(GuiCmdRef::Register(Register::try_from(words.as_slice())?))
+            }
+            _ => Err(UziErr::What),
+        }
+    }
+
+    // Converts the command to a GuiCmd, which fails if it sets one of the
+    // standard options to a bad value.
+    pub fn to_cmd(&self) -> Result<GuiCmd, UziErr> {
+        Ok(match self {
+            GuiCmdRef::Uci => GuiCmd::Uci,
+            GuiCmdRef::Debug(is_enabled) => GuiCmd::Debug(*is_enabled),
+            GuiCmdRef::IsReady => GuiCmd::IsReady,
+            GuiCmdRef::SetOpt { name, value } => {
+                let mut words = vec!["setoption", "name"];
+                words.extend(name.split_whitespace());
+                if let Some(value) = value {
+                    words.push("value");
+                    words.extend(value.split_whitespace());
+                }
+                GuiCmd::SetOpt(SetOpt::try_from(words.as_slice())?)
+            }
+            GuiCmdRef::NewGame => GuiCmd::NewGame,
+            GuiCmdRef::Pos { fen, moves } => {
+                let mut pos = match fen {
+                    Some(fen) => {
+                        Pos::with_fen(&fen.split_whitespace().collect::<Vec<_>>().join(" "))
+                    }
+                    None => Pos::new(),
+                };
+                for pm in moves.iter() {
+                    pos.add_move(pm);
+                }
+                GuiCmd::Pos(pos)
+            }
+            GuiCmdRef::Go(go) => GuiCmd::Go(go.clone()),
+            GuiCmdRef::Stop => GuiCmd::Stop,
+            GuiCmdRef::Ponderhit => GuiCmd::Ponderhit,
+            GuiCmdRef::Quit => GuiCmd::Quit,
+            GuiCmdRef::Register(register) => GuiCmd::Register(register.clone()),
+        })
+    }
+}
+
+// Parses the words after setoption, i.e. name <id> [value <x>].
+fn parse_set_opt_ref(mut words: Words) -> Result<GuiCmdRef, UziErr> {
+    if words.next() != Some("name") {
+        return Err(UziErr::SetOptErr);
+    }
+    let start = words;
+    while words.peek().is_some_and(|word| word != "value") {
+        words.next();
+    }
+    let name = words.since(start);
+    let value = words.next().map(|_| words.rest());
+    if name.is_empty() || value == Some("") {
+        return Err(UziErr::SetOptErr);
+    }
+    Ok(GuiCmdRef::SetOpt { name, value })
+}
+
+// Parses the words after position, i.e. [fen <fenstring> | startpos] [moves
+// <move1> ... <movei>].
+fn parse_pos_ref(mut words: Words) -> Result<GuiCmdRef, UziErr> {
+    let fen = match words.next() {
+        Some("startpos") => None,
+        Some("fen") => {
+            let start = words;
+            while words.peek().is_some_and(|word| word != "moves") {
+                words.next();
+            }
+            match words.since(start) {
+                "" => return Err(UziErr::Position),
+                fen => Some(fen),
+            }
+        }
+        _ => return Err(UziErr::Position),
+    };
+    let moves = match words.next() {
+        Some("moves") => MovesRef::take(&mut words),
+        Some(_) => return Err(UziErr::Position),
+        None => MovesRef::default(),
+    };
+    // The move that stopped the moves, if any, is not a move.
+    if let Some(word) = words.next() {
+        Pm::from_str(word)?;
+    }
+    Ok(GuiCmdRef::Pos { fen, moves })
+}
+
 // A struct to represent the UCI "go" command, used to tell the engine to begin
 // calculating the best move given an intial position. The command can take
 // multiple options. Start calculating on the current position set up with the
 // "position" command.
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct Go {
     // searchmoves <move1> ... <movei>: Restricts calculation by one or more
     // moves.
@@ -210,6 +382,61 @@ impl Go {
         self
     }
 
+    // Returns true if the search runs until stop.
+    pub fn is_infinite(&self) -> bool {
+        self.infinite.is_some()
+    }
+
+    // Returns true if the search is in ponder mode.
+    pub fn is_ponder(&self) -> bool {
+        self.ponder.is_some()
+    }
+
+    // Returns the moves the search is restricted to, if any.
+    pub fn search_moves(&self) -> Option<&[Pm]> {
+        self.search_moves.as_deref()
+    }
+
+    pub fn wtime(&self) -> Option<Duration> {
+        self.wtime
+    }
+
+    pub fn btime(&self) -> Option<Duration> {
+        self.btime
+    }
+
+    pub fn winc(&self) -> Option<Duration> {
+        self.winc
+    }
+
+    pub fn binc(&self) -> Option<Duration> {
+        self.binc
+    }
+
+    pub fn moves_to_go(&self) -> Option<u16> {
+        self.moves_to_go
+    }
+
+    pub fn depth(&self) -> Option<u16> {
+        self.depth
+    }
+
+    pub fn move_time(&self) -> Option<Duration> {
+        self.move_time
+    }
+
+    // Returns true if anything limits how long the search runs, including
+    // infinite.
+    pub fn has_limit(&self) -> bool {
+        self.wtime.is_some()
+            || self.btime.is_some()
+            || self.depth.is_some()
+            || self.nodes.is_some()
+            || self.mate.is_some()
+            || self.move_time.is_some()
+            || self.infinite.is_some()
+    }
+
     // Returns true if any options are set.
     pub fn has_any(&self) -> bool {
         self.search_moves.is_some()
@@ -227,23 +454,49 @@ impl Go {
     }
 }
 
-// Default initialization for Go.
-impl Default for Go {
-    fn default() -> Self {
-        Self {
-            search_moves: None,
-            ponder: None,
-            wtime: None,
-            btime: None,
-            winc: None,
-            binc: None,
-            moves_to_go: None,
-            depth: None,
-            nodes: None,
-            mate: None,
-            move_time: None,
-            infinite: None,
+// Formats the command with searchmoves last, because some engines treat every
+// word after searchmoves as a move.
+impl Display for Go {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("go")?;
+        if self.ponder.is_some() {
+            formatter.write_str(" ponder")?;
+        }
+        if let Some(wtime) = self.wtime {
+            write_millis(formatter, " wtime ", wtime)?;
+        }
+        if let Some(btime) = self.btime {
+            write_millis(formatter, " btime ", btime)?;
+        }
+        if let Some(winc) = self.winc {
+            write_millis(formatter, " winc ", winc)?;
         }
+        if let Some(binc) = self.binc {
+            write_millis(formatter, " binc ", binc)?;
+        }
+        if let Some(moves_to_go) = self.moves_to_go {
+            write_number(formatter, " movestogo ", moves_to_go.into())?;
+        }
+        if let Some(depth) = self.depth {
+            write_number(formatter, " depth ", depth.into())?;
+        }
+        if let Some(nodes) = self.nodes {
+            write_number(formatter, " nodes ", nodes)?;
+        }
+        if let Some(mate) = self.mate {
+            write_number(formatter, " mate ", mate.into())?;
+        }
+        if let Some(move_time) = self.move_time {
+            write_millis(formatter, " movetime ", move_time)?;
+        }
+        if self.infinite.is_some() {
+            formatter.write_str(" infinite")?;
+        }
+        if let Some(ref moves) = self.search_moves {
+            formatter.write_str(" searchmoves")?;
+            write_moves(formatter, moves)?;
+        }
+        Ok(())
     }
 }
 
@@ -251,44 +504,50 @@ impl TryFrom<&[&str]> for Go {
     type Error = UziErr;
 
     fn try_from(cmd: &[&str]) -> Result<Go, Self::Error> {
-        let mut go = Go::new();
-        let mut parse_state = GoParseState::Begin;
+        parse_go(cmd.iter().copied())
+    }
+}
 
-        for word in cmd {
-            match *word {
-                "go" => {
-                    if parse_state != GoParseState::Begin {
-                        return Err(UziErr::GoErr);
-                    }
-                    parse_state = GoParseState::Go;
-                }
-                "wtime" => parse_state = GoParseState::Wtime,
-                "btime" => parse_state = GoParseState::Btime,
-                "winc" => parse_state = GoParseState::Winc,
-                "binc" => parse_state = GoParseState::Binc,
-                "movetime" => parse_state = GoParseState::MoveTime,
-                "movestogo" => parse_state = GoParseState::MovesToGo,
-                "depth" => parse_state = GoParseState::Depth,
-                "nodes" => parse_state = GoParseState::Nodes,
-                "mate" => parse_state = GoParseState::Mate,
-                "searchmoves" => parse_state = GoParseState::SearchMoves,
-                "infinite" => {
-                    parse_state = GoParseState::Infinite;
-                    go.set_infinite();
-                }
-                "ponder" => {
-                    parse_state = GoParseState::Ponder;
-                    go.set_ponder();
+// Parses the words of a go command, starting with go.
+fn parse_go<'a>(words: impl Iterator<Item = &'a str>) -> Result<Go, UziErr> {
+    let mut go = Go::new();
+    let mut parse_state = GoParseState::Begin;
+
+    for word in words {
+        match word {
+            "go" => {
+                if parse_state != GoParseState::Begin {
+                    return Err(UziErr::GoErr);
                 }
-                _ => parse_go_opt(parse_state, word, &mut go)?,
+                parse_state = GoParseState::Go;
+            }
+            "wtime" => parse_state = GoParseState::Wtime,
+            "btime" => parse_state = GoParseState::Btime,
+            "winc" => parse_state = GoParseState::Winc,
+            "binc" => parse_state = GoParseState::Binc,
+            "movetime" => parse_state = GoParseState::MoveTime,
+            "movestogo" => parse_state = GoParseState::MovesToGo,
+            "depth" => parse_state = GoParseState::Depth,
+            "nodes" => parse_state = GoParseState::Nodes,
+            "mate" => parse_state = GoParseState::Mate,
+            "searchmoves" => parse_state = GoParseState::SearchMoves,
+            "infinite" => {
+                parse_state = GoParseState::Infinite;
+                go.set_infinite();
+            }
+            "ponder" => {
+                parse_state = GoParseState::Ponder;
+                go.set_ponder();
             }
+            _ => parse_go_opt(parse_state, word, &mut go)?,
         }
+    }
 
-        if !go.has_any() {
-            Err(UziErr::GoErr)
-        } else {
-            Ok(go)
-        }
+    // A bare go, which Go::new formats, has no options.
+    if !go.has_any() && parse_state != GoParseState::Go {
+        Err(UziErr::GoErr)
+    } else {
+        Ok(go)
     }
 }
 
@@ -381,6 +640,36 @@ impl Pos {
         }
         self
     }
+
+    // Returns the FEN of the initial position, or None for the start position.
+    pub fn fen(&self) -> Option<&str> {
+        match self.pos {
+            PosOpt::StartPos => None,
+            PosOpt::Fen(ref fen) => Some(fen),
+        }
+    }
+
+    // Returns the moves played from the initial position.
+    pub fn moves(&self) -> &[Pm] {
+        self.moves.as_deref().unwrap_or_default()
+    }
+
+    // Returns true if white is to move after the moves, going by the side to
+    // move in the FEN. A FEN without one is taken as white to move.
+    pub fn is_white_to_move(&self) -> bool {
+        let is_white_first = match self.pos {
+            PosOpt::StartPos => true,
+            PosOpt::Fen(ref fen) => fen.split_whitespace().nth(1) != Some("b"),
+        };
+        is_white_first == self.moves().len().is_multiple_of(2)
+    }
+
+    // Returns true if both positions can be from the same game, i.e. they start
+    // from the same position and the moves of one extend the moves of the other.
+    pub fn is_same_game(&self, other: &Pos) -> bool {
+        let (moves, other_moves) = (self.moves(), other.moves());
+        self.pos == other.pos && (moves.starts_with(other_moves) || other_moves.starts_with(moves))
+    }
 }
 
 impl Default for Pos {
@@ -392,53 +681,72 @@ impl Default for Pos {
     }
 }
 
+impl Display for Pos {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self.pos {
+            PosOpt::StartPos => write!(formatter, "position startpos")?,
+            PosOpt::Fen(ref fen) => write!(formatter, "position fen {}", fen)?,
+        }
+        if let Some(ref moves) = self.moves {
+            formatter.write_str(" moves")?;
+            write_moves(formatter, moves)?;
+        }
+        Ok(())
+    }
+}
+
 impl TryFrom<&[&str]> for Pos {
     type Error = UziErr;
 
     fn try_from(cmd: &[&str]) -> Result<Pos, Self::Error> {
-        let mut pos = Pos::new();
-        let mut pos_state = PosState::Begin;
-        let mut fen_buf: Option<String> = None;
-
-        for word in cmd {
-            match *word {
-                "position" if pos_state.is_begin() => pos_state = PosState::Position,
-                "startpos" if pos_state.is_pos() => pos_state = PosState::StartPos,
-                "fen" if pos_state.is_pos() => {
-                    pos_state = PosState::Fen;
-                    fen_buf = Some(String::with_capacity(128));
-                }
-                "moves" if pos_state.is_fen() || pos_state.is_start() => {
-                    pos_state = PosState::Moves
-                }
-                _ if pos_state.is_moves() => {
-                    pos.add_move(Pm::from_str(*word)?);
-                }
-                _ if pos_state.is_fen() => {
-                    if let Some(ref mut buf) = fen_buf {
-                        if !buf.is_empty() {
-                            buf.push(' ');
-                        }
-                        buf.push_str(*word);
-                    } else {
-                        return Err(UziErr::Position);
+        parse_pos(cmd.iter().copied())
+    }
+}
+
+// Parses the words of a position command, starting with position.
+fn parse_pos<'a>(words: impl Iterator<Item = &'a str>) -> Result<Pos, UziErr> {
+    let mut pos = Pos::new();
+    let mut pos_state = PosState::Begin;
+    let mut fen_buf: Option<String> = None;
+
+    for word in words {
+        // The moves come first, since they are most of the words of a long
+        // game.
+        match word {
+            _ if pos_state.is_moves() => {
+                pos.add_move(Pm::from_str(word)?);
+            }
+            "position" if pos_state.is_begin() => pos_state = PosState::Position,
+            "startpos" if pos_state.is_pos() => pos_state = PosState::StartPos,
+            "fen" if pos_state.is_pos() => {
+                pos_state = PosState::Fen;
+                fen_buf = Some(String::with_capacity(128));
+            }
+            "moves" if pos_state.is_fen() || pos_state.is_start() => pos_state = PosState::Moves,
+            _ if pos_state.is_fen() => {
+                if let Some(ref mut buf) = fen_buf {
+                    if !buf.is_empty() {
+                        buf.push(' ');
                     }
+                    buf.push_str(word);
+                } else {
+                    return Err(UziErr::Position);
                 }
-                _ => return Err(UziErr::Position),
-            };
-        }
-
-        if let Some(buf) = fen_buf {
-            if buf.is_empty() {
-                return Err(UziErr::Position);
             }
-            pos.set_fen_string(buf);
-        }
+            _ => return Err(UziErr::Position),
+        };
+    }
 
-        match pos_state {
-            PosState::Fen | PosState::Moves | PosState::StartPos => Ok(pos),
-            _ => Err(UziErr::Position),
+    if let Some(buf) = fen_buf {
+        if buf.is_empty() {
+            return Err(UziErr::Position);
         }
+        pos.set_fen_string(buf);
+    }
+
+    match pos_state {
+        PosState::Fen | PosState::Moves | PosState::StartPos => Ok(pos),
+        _ => Err(UziErr::Position),
     }
 }
 
@@ -482,6 +790,66 @@ pub enum PosOpt {
     Fen(String),
 }
 
+// The register command, i.e. either
+//
+// register later
+// register name <x> code <y>
+//
+// The name may contain spaces. Either the name or the code may be missing.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Register {
+    Later,
+    Now {
+        name: Option<String>,
+        code: Option<String>,
+    },
+}
+
+impl Display for Register {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::Later => formatter.write_str("register later"),
+            Register::Now { name, code } => {
+                formatter.write_str("register")?;
+                if let Some(name) = name {
+                    write!(formatter, " name {}", name)?;
+                }
+                if let Some(code) = code {
+                    write!(formatter, " code {}", code)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl TryFrom<&[&str]> for Register {
+    type Error = UziErr;
+
+    fn try_from(cmd: &[&str]) -> Result<Register, Self::Error> {
+        match cmd {
+            ["register", "later"] => return Ok(Register::Later),
+            ["register", "name" | "code", ..] => (),
+            _ => return Err(UziErr::RegisterErr),
+        }
+        let code_index = cmd.iter().position(|word| *word == "code");
+        let name_end = code_index.unwrap_or(cmd.len());
+        let join = |words: &[&str]| match words {
+            [] => Err(UziErr::RegisterErr),
+            words => Ok(Some(words.join(" "))),
+        };
+        let name = match cmd[1] {
+            "name" => join(&cmd[2..name_end])?,
+            _ => None,
+        };
+        let code = match code_index {
+            Some(i) => join(&cmd[i + 1..])?,
+            None => None,
+        };
+        Ok(Register::Now { name, code })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -618,6 +986,15 @@ mod tests {
         assert_eq!(Go::try_from(&["hello", "mother"][..]), Err(UziErr::GoErr));
     }
 
+    #[test]
+    fn go_try_from_bare_go() {
+        assert_eq!(Go::try_from(&["go"][..]), Ok(Go::new()));
+        assert_eq!(
+            GuiCmd::from_str(&Go::new().to_string()),
+            Ok(GuiCmd::Go(Go::new()))
+        );
+    }
+
     #[test]
     fn go_try_from_all_opts() {
         let opts = [
@@ -675,6 +1052,7 @@ mod tests {
         assert_eq!(GuiCmd::from_str("ucinewgame"), Ok(GuiCmd::NewGame));
         assert_eq!(GuiCmd::from_str("stop"), Ok(GuiCmd::Stop));
         assert_eq!(GuiCmd::from_str("ponderhit"), Ok(GuiCmd::Ponderhit));
+        assert_eq!(GuiCmd::from_str("quit"), Ok(GuiCmd::Quit));
         assert_eq!(GuiCmd::from_str("debug on"), Ok(GuiCmd::Debug(true)));
         assert_eq!(GuiCmd::from_str("debug off"), Ok(GuiCmd::Debug(false)));
         assert_eq!(GuiCmd::from_str("hello"), Err(UziErr::What));
@@ -707,6 +1085,92 @@ mod tests {
         );
     }
 
+    #[test]
+    fn guicmd_display_round_trip() {
+        let mut go = Go::new();
+        go.add_search_move(Pm::from_str("e2e4").unwrap())
+            .set_ponder()
+            .set_wtime(Duration::from_millis(1000))
+            .set_btime(Duration::from_millis(2000))
+            .set_winc(Duration::from_millis(10))
+            .set_binc(Duration::from_millis(20))
+            .set_moves_to_go(40)
+            .set_depth(12)
+            .set_nodes(100_000)
+            .set_mate(3)
+            .set_move_time(Duration::from_millis(500))
+            .set_infinite();
+        let mut pos = Pos::with_fen(FEN_STR);
+        pos.add_move(Pm::from_str("f7e7").unwrap());
+
+        let cmds = [
+            GuiCmd::Uci,
+            GuiCmd::Debug(true),
+            GuiCmd::Debug(false),
+            GuiCmd::IsReady,
+            GuiCmd::SetOpt(SetOpt::Hash(64)),
+            GuiCmd::NewGame,
+            GuiCmd::Pos(Pos::new()),
+            GuiCmd::Pos(pos),
+            GuiCmd::Go(go),
+            GuiCmd::Stop,
+            GuiCmd::Ponderhit,
+            GuiCmd::Quit,
+            GuiCmd::Register(Register::Later),
+            GuiCmd::Register(Register::Now {
+                name: Some("Stefan MK".into()),
+                code: Some("4359874324".into()),
+            }),
+        ];
+        for cmd in cmds {
+            assert_eq!(GuiCmd::from_str(&cmd.to_string()), Ok(cmd));
+        }
+    }
+
+    #[test]
+    fn go_display_puts_search_moves_last() {
+        let mut go = Go::new();
+        go.add_search_move(Pm::from_str("e2e4").unwrap())
+            .set_wtime(Duration::from_millis(1))
+            .set_btime(Duration::from_millis(2));
+        assert_eq!(
+            go.to_string().as_str(),
+            "go wtime 1 btime 2 searchmoves e2e4"
+        );
+
+        let line = "go ponder wtime 300000 btime 0 winc 2000 binc 2000 movestogo 34 depth 20 \
+                    nodes 18446744073709551615 mate 3 movetime 100 infinite searchmoves e2e4 e7e8q";
+        assert_eq!(GuiCmd::from_str(line).unwrap().to_string(), line);
+    }
+
+    #[test]
+    fn guicmd_register() {
+        let register = |cmd| match GuiCmd::from_str(cmd) {
+            Ok(GuiCmd::Register(register)) => Ok(register),
+            Ok(cmd) => panic!("not register: {:?}", cmd),
+            Err(err) => Err(err),
+        };
+        assert_eq!(register("register later"), Ok(Register::Later));
+        assert_eq!(
+            register("register name Stefan MK code 4359874324"),
+            Ok(Register::Now {
+                name: Some("Stefan MK".into()),
+                code: Some("4359874324".into())
+            })
+        );
+        assert_eq!(
+            register("register code 4359874324"),
+            Ok(Register::Now {
+                name: None,
+                code: Some("4359874324".into())
+            })
+        );
+        assert_eq!(register("register"), Err(UziErr::RegisterErr));
+        assert_eq!(register("register name"), Err(UziErr::RegisterErr));
+        assert_eq!(register("register name x code"), Err(UziErr::RegisterErr));
+        assert_eq!(register("register soon"), Err(UziErr::RegisterErr));
+    }
+
     #[test]
     fn guicmd_go() {
         let mut go = Go::new();
@@ -719,4 +1183,92 @@ mod tests {
             Ok(GuiCmd::Go(go))
         );
     }
+
+    #[test]
+    fn pos_is_same_game() {
+        let pos = |s: &str| match GuiCmd::from_str(s) {
+            Ok(GuiCmd::Pos(pos)) => pos,
+            cmd => panic!("unexpected {:?}", cmd),
+        };
+        let game = pos("position startpos moves e2e4 e7e5");
+        assert!(game.is_same_game(&pos("position startpos")));
+        assert!(game.is_same_game(&pos("position startpos moves e2e4 e7e5 g1f3")));
+        assert!(!game.is_same_game(&pos("position startpos moves d2d4")));
+        assert!(!game.is_same_game(&pos(&format!("position fen {}", FEN_STR))));
+    }
+
+    #[test]
+    fn pos_side_to_move() {
+        let mut pos = Pos::new();
+        assert!(pos.is_white_to_move());
+        pos.add_move(Pm::from_str("e2e4").unwrap());
+        assert!(!pos.is_white_to_move());
+
+        // The FEN has black to move.
+        let mut pos = Pos::with_fen(FEN_STR);
+        assert!(!pos.is_white_to_move());
+        pos.add_move(Pm::from_str("f7e7").unwrap());
+        assert!(pos.is_white_to_move());
+    }
+
+    #[test]
+    fn gui_cmd_from_str_long_position() {
+        let moves = ["g1f3", "g8f6", "f3g1", "f6g8"].repeat(100).join(" ");
+        let line = format!("position startpos moves {}", moves);
+        let Ok(GuiCmd::Pos(pos)) = GuiCmd::from_str(&line) else {
+            panic!("not a position");
+        };
+        assert_eq!(pos.moves().len(), 400);
+        assert_eq!(pos.to_string(), line);
+    }
+
+    #[test]
+    fn gui_cmd_ref_matches_gui_cmd() {
+        let lines = [
+            "uci",
+            "debug off",
+            "setoption name Clear Hash",
+            "setoption name Hash value 64",
+            "position startpos",
+            "position startpos moves e2e4 e7e5",
+            "position fen 8/5k2/3p4/1p1Pp2p/pP2Pp1P/P4P1K/8/8 b - - 40 50 moves f7e7",
+            "go wtime 1000 btime 1000 searchmoves e2e4",
+            "register name Stefan MK code 4359874324",
+            "quit",
+        ];
+        for line in lines {
+            let cmd = GuiCmdRef::parse(line).unwrap();
+            assert_eq!(cmd.to_cmd(), GuiCmd::from_str(line), "{}", line);
+        }
+
+        let line = "position fen  8/5k2/3p4/1p1Pp2p/pP2Pp1P/P4P1K/8/8 b - - 40 50  moves f7e7 e7e6";
+        let Ok(GuiCmdRef::Pos { fen, moves }) = GuiCmdRef::parse(line) else {
+            panic!("not a position");
+        };
+        assert_eq!(fen, Some(FEN_STR));
+        assert_eq!(moves.as_str(), "f7e7 e7e6");
+        assert_eq!(
+            GuiCmdRef::parse("setoption name Skill Level value 10"),
+            Ok(GuiCmdRef::SetOpt {
+                name: "Skill Level",
+                value: Some("10")
+            })
+        );
+
+        assert_eq!(GuiCmdRef::parse(" "), Err(UziErr::MissingCmd));
+        assert_eq!(GuiCmdRef::parse("debug"), Err(UziErr::MissingOnOff));
+        assert_eq!(
+            GuiCmdRef::parse("setoption value 1"),
+            Err(UziErr::SetOptErr)
+        );
+        assert_eq!(
+            GuiCmdRef::parse("position fen moves"),
+            Err(UziErr::Position)
+        );
+        assert_eq!(
+            GuiCmdRef::parse("position startpos moves e2e4 e9e5"),
+            Err(UziErr::ParseSqErr)
+        );
+        assert_eq!(GuiCmdRef::parse("eval"), Err(UziErr::What));
+    }
 }
@@ -0,0 +1,114 @@
+// This module contains InfoPool, which keeps the infos a consumer is done with,
+// so that the client parses the next infos into them rather than allocating
+// new ones. An engine sends thousands of infos per search, and each one with a
+// pv is an allocation that is freed as soon as the consumer has read it.
+
+use crate::engcmd::{EngCmd, Info};
+use crate::err::UziErr;
+use std::str::FromStr;
+
+// The recycled infos, up to a capacity. A pool with a capacity of 0, the
+// default, keeps nothing and every info is allocated.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InfoPool {
+    free: Vec<Info>,
+    capacity: usize,
+}
+
+impl InfoPool {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            free: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    // Changes how many infos are kept, dropping the ones over it.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.free.truncate(capacity);
+    }
+
+    // Keeps an info for the next info line, unless the pool is full.
+    pub fn recycle(&mut self, info: Info) {
+        if self.free.len() < self.capacity {
+            self.free.push(info);
+        }
+    }
+
+    // Returns how many infos are waiting to be reused.
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    // Parses a line from the engine like EngCmd::from_str, with an info parsed
+    // into a recycled one if there is any.
+    pub fn parse(&mut self, line: &str) -> Result<EngCmd, UziErr> {
+        if line.split_whitespace().next() != Some("info") {
+            return EngCmd::from_str(line);
+        }
+        let Some(mut info) = self.free.pop() else {
+            return EngCmd::from_str(line);
+        };
+        match info.parse_into(line) {
+            Ok(()) => Ok(EngCmd::Info(info)),
+            Err(err) => {
+                self.free.push(info);
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONG_PV: &str = "info depth 9 pv e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6 e1g1 f8e7";
+
+    fn info(line: &str) -> Info {
+        match EngCmd::from_str(line) {
+            Ok(EngCmd::Info(info)) => info,
+            cmd => panic!("not an info: {:?}", cmd),
+        }
+    }
+
+    #[test]
+    fn info_pool_reuses_pv() {
+        let mut pool = InfoPool::new(1);
+        let recycled = info(LONG_PV);
+        let pv = recycled.pv().unwrap().as_ptr();
+        pool.recycle(recycled);
+        pool.recycle(info(LONG_PV));
+        assert_eq!(pool.len(), 1);
+
+        assert_eq!(pool.parse("readyok"), Ok(EngCmd::ReadyOk));
+        assert_eq!(pool.len(), 1);
+        let line = "info depth 10 score cp 20 pv d2d4 d7d5 c2c4 e7e6 b1c3 g8f6 c1g5 f8e7";
+        let cmd = pool.parse(line).unwrap();
+        assert_eq!(cmd, EngCmd::Info(info(line)));
+        let EngCmd::Info(parsed) = cmd else {
+            unreachable!()
+        };
+        assert_eq!(parsed.pv().unwrap().as_ptr(), pv);
+        assert_eq!(pool.len(), 0);
+
+        // Without a recycled info, infos are parsed as usual.
+        assert_eq!(pool.parse(LONG_PV), Ok(EngCmd::Info(info(LONG_PV))));
+    }
+
+    #[test]
+    fn info_pool_keeps_info_on_error() {
+        let mut pool = InfoPool::new(2);
+        pool.recycle(info(LONG_PV));
+        assert_eq!(
+            pool.parse("info depth many"),
+            Err(UziErr::BadNumber("many".into()))
+        );
+        assert_eq!(pool.len(), 1);
+        pool.set_capacity(0);
+        assert_eq!(pool.len(), 0);
+        pool.recycle(info(LONG_PV));
+        assert_eq!(pool.len(), 0);
+    }
+}
@@ -0,0 +1,208 @@
+// This module serializes the results of analyses and game reviews, so that they
+// can be exported as JSON, e.g. to a web frontend. The structure is:
+//
+// - A move is its UCI string, e.g. "e2e4" or "e7e8q", and "0000" for the null
+//   move.
+// - A score is {"cp": 30, "mate": null, "bound": null}, where cp and mate are
+//   from the point of view of the side to move, mate is in moves, and bound is
+//   null, "lowerbound" or "upperbound".
+// - A PvLine is {"rank": 1, "depth": 20, "seldepth": 28, "score": <score>,
+//   "moves": [<move>, ...], "nodes": 123456, "time_ms": 250}.
+// - An AnalysisResult is {"best": <move>, "ponder": <move>, "lines": [<PvLine>,
+//   ...]}, with the lines best first.
+// - A MoveReport is {"ply": 0, "move": <move>, "best": <move>, "before":
+//   <score>, "after": <score>, "loss": 25, "tag": null}, where tag is null,
+//   "inaccuracy", "mistake" or "blunder".
+// - A SideMetrics is {"moves": 20, "acpl": 35.5, "accuracy": 87.2,
+//   "inaccuracies": 2, "mistakes": 1, "blunders": 0}.
+// - A GameMetrics is {"white": <SideMetrics>, "black": <SideMetrics>}.
+//
+// Any field that isn't known is null.
+
+use crate::analysis::{AnalysisResult, PvLine};
+use crate::engcmd::Score;
+use crate::err::UziErr;
+use crate::metrics::{GameMetrics, SideMetrics};
+use crate::pm::Pm;
+use crate::review::{MoveReport, MoveTag};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+// Returns the value as pretty printed JSON.
+pub fn to_json<T: Serialize>(value: &T) -> Result<String, UziErr> {
+    serde_json::to_string_pretty(value).map_err(|err| UziErr::JsonErr(err.to_string()))
+}
+
+impl Serialize for Pm {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Serialize for Score {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut score = serializer.serialize_struct("Score", 3)?;
+        score.serialize_field("cp", &self.cp())?;
+        score.serialize_field("mate", &self.mate())?;
+        score.serialize_field("bound", &self.bound().map(|bound| bound.as_str()))?;
+        score.end()
+    }
+}
+
+impl Serialize for PvLine {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut line = serializer.serialize_struct("PvLine", 7)?;
+        line.serialize_field("rank", &self.rank)?;
+        line.serialize_field("depth", &self.depth)?;
+        line.serialize_field("seldepth", &self.sel_depth)?;
+        line.serialize_field("score", &self.score)?;
+        line.serialize_field("moves", &self.moves)?;
+        line.serialize_field("nodes", &self.nodes)?;
+        line.serialize_field("time_ms", &self.time.map(|time| time.as_millis() as u64))?;
+        line.end()
+    }
+}
+
+impl Serialize for AnalysisResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut result = serializer.serialize_struct("AnalysisResult", 3)?;
+        result.serialize_field("best", &self.best)?;
+        result.serialize_field("ponder", &self.ponder)?;
+        result.serialize_field("lines", &self.lines)?;
+        result.end()
+    }
+}
+
+impl Serialize for MoveTag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            MoveTag::Inaccuracy => "inaccuracy",
+            MoveTag::Mistake => "mistake",
+            MoveTag::Blunder => "blunder",
+        })
+    }
+}
+
+impl Serialize for MoveReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut report = serializer.serialize_struct("MoveReport", 7)?;
+        report.serialize_field("ply", &self.ply)?;
+        report.serialize_field("move", &self.pm)?;
+        report.serialize_field("best", &self.best)?;
+        report.serialize_field("before", &self.before)?;
+        report.serialize_field("after", &self.after)?;
+        report.serialize_field("loss", &self.loss)?;
+        report.serialize_field("tag", &self.tag)?;
+        report.end()
+    }
+}
+
+impl Serialize for SideMetrics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut metrics = serializer.serialize_struct("SideMetrics", 6)?;
+        metrics.serialize_field("moves", &self.moves)?;
+        metrics.serialize_field("acpl", &self.acpl)?;
+        metrics.serialize_field("accuracy", &self.accuracy)?;
+        metrics.serialize_field("inaccuracies", &self.inaccuracies)?;
+        metrics.serialize_field("mistakes", &self.mistakes)?;
+        metrics.serialize_field("blunders", &self.blunders)?;
+        metrics.end()
+    }
+}
+
+impl Serialize for GameMetrics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut metrics = serializer.serialize_struct("GameMetrics", 2)?;
+        metrics.serialize_field("white", &self.white)?;
+        metrics.serialize_field("black", &self.black)?;
+        metrics.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engcmd::Info;
+    use crate::guicmd::Pos;
+    use crate::metrics::AccuracyFormula;
+    use serde_json::{json, Value};
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    fn pm(s: &str) -> Pm {
+        Pm::from_str(s).unwrap()
+    }
+
+    fn score(words: &[&str]) -> Option<Score> {
+        let mut info = vec!["info", "score"];
+        info.extend(words);
+        Info::try_from(info.as_slice()).unwrap().score()
+    }
+
+    fn value<T: Serialize>(value: &T) -> Value {
+        serde_json::from_str(&to_json(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn json_analysis_result() {
+        let result = AnalysisResult {
+            best: pm("e7e8q"),
+            ponder: None,
+            lines: vec![PvLine {
+                rank: 1,
+                depth: Some(20),
+                sel_depth: None,
+                score: score(&["mate", "3", "lowerbound"]),
+                moves: vec![pm("e7e8q"), pm("0000")],
+                nodes: Some(123456),
+                time: Some(Duration::from_millis(250)),
+            }],
+        };
+        assert_eq!(
+            value(&result),
+            json!({
+                "best": "e7e8q",
+                "ponder": null,
+                "lines": [{
+                    "rank": 1,
+                    "depth": 20,
+                    "seldepth": null,
+                    "score": {"cp": null, "mate": 3, "bound": "lowerbound"},
+                    "moves": ["e7e8q", "0000"],
+                    "nodes": 123456,
+                    "time_ms": 250,
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn json_game_review() {
+        let report = MoveReport {
+            ply: 0,
+            pm: pm("e2e4"),
+            best: pm("d2d4"),
+            before: score(&["cp", "20"]),
+            after: score(&["cp", "-480"]),
+            loss: Some(500),
+            tag: Some(MoveTag::Blunder),
+        };
+        assert_eq!(
+            value(&report),
+            json!({
+                "ply": 0,
+                "move": "e2e4",
+                "best": "d2d4",
+                "before": {"cp": 20, "mate": null, "bound": null},
+                "after": {"cp": -480, "mate": null, "bound": null},
+                "loss": 500,
+                "tag": "blunder",
+            })
+        );
+
+        let metrics = GameMetrics::new(&[report], &Pos::new(), AccuracyFormula::Linear(0.1));
+        let json = value(&metrics);
+        assert_eq!(json["white"]["acpl"], json!(500.0));
+        assert_eq!(json["white"]["blunders"], json!(1));
+        assert_eq!(json["black"]["accuracy"], Value::Null);
+    }
+}
@@ -0,0 +1,242 @@
+// This module contains the latency metrics of an engine, which the client
+// measures for every search and every isready, e.g. to find out why an engine
+// feels sluggish, or why a GUI times out waiting for it. The times are taken
+// when the client sends a command and when it reads the reply, so they include
+// the transport, and replies are only read when the caller asks for them.
+
+use crate::engcmd::EngCmd;
+use crate::guicmd::GuiCmd;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// The latencies of one search, from go.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct SearchLatency {
+    // The time to the first info, and to the first info with a pv, or None
+    // if the engine sent none.
+    pub first_info: Option<Duration>,
+    pub first_pv: Option<Duration>,
+    pub best_move: Duration,
+    // The time from stop to bestmove, or None if the search wasn't stopped.
+    pub stop_to_best_move: Option<Duration>,
+}
+
+// A summary of the samples of one latency.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct LatencyStats {
+    pub count: u32,
+    pub min: Duration,
+    pub max: Duration,
+    pub total: Duration,
+}
+
+// The latencies of an engine since it was created, or since they were reset.
+// A search that doesn't end with a bestmove, e.g. because the engine was
+// restarted, is not counted.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Latency {
+    pub first_info: LatencyStats,
+    pub first_pv: LatencyStats,
+    pub best_move: LatencyStats,
+    pub stop_to_best_move: LatencyStats,
+    // The round trip of isready to readyok.
+    pub ready: LatencyStats,
+    pub last_search: Option<SearchLatency>,
+    pub last_ready: Option<Duration>,
+}
+
+impl LatencyStats {
+    pub fn add(&mut self, sample: Duration) {
+        if self.count == 0 || sample < self.min {
+            self.min = sample;
+        }
+        self.max = self.max.max(sample);
+        self.total += sample;
+        self.count += 1;
+    }
+
+    // Returns the average, or None without samples.
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.total / self.count)
+    }
+}
+
+impl Latency {
+    fn add_search(&mut self, search: SearchLatency) {
+        let samples = [
+            (&mut self.first_info, search.first_info),
+            (&mut self.first_pv, search.first_pv),
+            (&mut self.best_move, Some(search.best_move)),
+            (&mut self.stop_to_best_move, search.stop_to_best_move),
+        ];
+        for (stats, sample) in samples {
+            if let Some(sample) = sample {
+                stats.add(sample);
+            }
+        }
+        self.last_search = Some(search);
+    }
+
+    fn add_ready(&mut self, sample: Duration) {
+        self.ready.add(sample);
+        self.last_ready = Some(sample);
+    }
+}
+
+// Measures the latencies from the commands the client sends and receives.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LatencyTracker {
+    latency: Latency,
+    // The search in progress: when go and stop were sent, and the latencies
+    // measured so far.
+    go_at: Option<Instant>,
+    stop_at: Option<Instant>,
+    first_info: Option<Duration>,
+    first_pv: Option<Duration>,
+    // When the isready commands that have not been answered yet were sent,
+    // oldest first.
+    ready_at: VecDeque<Instant>,
+}
+
+impl LatencyTracker {
+    pub fn latency(&self) -> &Latency {
+        &self.latency
+    }
+
+    pub fn sent(&mut self, cmd: &GuiCmd, now: Instant) {
+        match cmd {
+            GuiCmd::Go(_) => {
+                self.go_at = Some(now);
+                self.stop_at = None;
+                self.first_info = None;
+                self.first_pv = None;
+            }
+            GuiCmd::Stop if self.go_at.is_some() => {
+                self.stop_at.get_or_insert(now);
+            }
+            GuiCmd::IsReady => self.ready_at.push_back(now),
+            _ => (),
+        }
+    }
+
+    pub fn received(&mut self, cmd: &EngCmd, now: Instant) {
+        match cmd {
+            EngCmd::Info(info) => {
+                let Some(go_at) = self.go_at else {
+                    return;
+                };
+                let elapsed = now.duration_since(go_at);
+                self.first_info.get_or_insert(elapsed);
+                if info.pv().is_some() {
+                    self.first_pv.get_or_insert(elapsed);
+                }
+            }
+            EngCmd::BestMove { .. } => {
+                let Some(go_at) = self.go_at.take() else {
+                    return;
+                };
+                self.latency.add_search(SearchLatency {
+                    first_info: self.first_info,
+                    first_pv: self.first_pv,
+                    best_move: now.duration_since(go_at),
+                    stop_to_best_move: self.stop_at.take().map(|at| now.duration_since(at)),
+                });
+            }
+            EngCmd::ReadyOk => {
+                if let Some(sent) = self.ready_at.pop_front() {
+                    self.latency.add_ready(now.duration_since(sent));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Forgets the commands in flight, e.g. when the engine is replaced, but
+    // keeps the latencies measured so far.
+    pub fn abort(&mut self) {
+        self.go_at = None;
+        self.stop_at = None;
+        self.ready_at.clear();
+    }
+
+    pub fn reset(&mut self) {
+        self.latency = Latency::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn latency_of_searches() {
+        let start = Instant::now();
+        let mut tracker = LatencyTracker::default();
+        let send = |tracker: &mut LatencyTracker, cmd: &str, at: u64| {
+            tracker.sent(&GuiCmd::from_str(cmd).unwrap(), start + ms(at));
+        };
+        let recv = |tracker: &mut LatencyTracker, cmd: &str, at: u64| {
+            tracker.received(&EngCmd::from_str(cmd).unwrap(), start + ms(at));
+        };
+
+        send(&mut tracker, "go infinite", 0);
+        recv(&mut tracker, "info depth 1", 5);
+        recv(&mut tracker, "info depth 1 pv e2e4", 8);
+        recv(&mut tracker, "info depth 2 pv d2d4", 9);
+        send(&mut tracker, "stop", 100);
+        recv(&mut tracker, "bestmove d2d4", 130);
+        assert_eq!(
+            tracker.latency().last_search,
+            Some(SearchLatency {
+                first_info: Some(ms(5)),
+                first_pv: Some(ms(8)),
+                best_move: ms(130),
+                stop_to_best_move: Some(ms(30)),
+            })
+        );
+
+        // A search without infos, and a bestmove that belongs to no search.
+        send(&mut tracker, "go depth 1", 200);
+        recv(&mut tracker, "bestmove e2e4", 210);
+        recv(&mut tracker, "bestmove e2e4", 220);
+        let latency = tracker.latency();
+        assert_eq!(latency.best_move.count, 2);
+        assert_eq!(
+            (latency.best_move.min, latency.best_move.max),
+            (ms(10), ms(130))
+        );
+        assert_eq!(latency.best_move.mean(), Some(ms(70)));
+        assert_eq!(latency.first_info.count, 1);
+        assert_eq!(latency.stop_to_best_move.count, 1);
+        assert_eq!(latency.last_search.unwrap().first_info, None);
+
+        // A search aborted by a restart isn't counted.
+        send(&mut tracker, "go infinite", 300);
+        tracker.abort();
+        recv(&mut tracker, "bestmove e2e4", 310);
+        assert_eq!(tracker.latency().best_move.count, 2);
+    }
+
+    #[test]
+    fn latency_of_isready() {
+        let start = Instant::now();
+        let mut tracker = LatencyTracker::default();
+        tracker.sent(&GuiCmd::IsReady, start);
+        tracker.sent(&GuiCmd::IsReady, start + ms(10));
+        tracker.received(&EngCmd::ReadyOk, start + ms(15));
+        tracker.received(&EngCmd::ReadyOk, start + ms(40));
+        let latency = tracker.latency();
+        assert_eq!(latency.ready.count, 2);
+        assert_eq!(latency.ready.min, ms(15));
+        assert_eq!(latency.last_ready, Some(ms(30)));
+        assert_eq!(LatencyStats::default().mean(), None);
+
+        tracker.reset();
+        assert_eq!(*tracker.latency(), Latency::default());
+    }
+}
@@ -4,15 +4,176 @@
 // TODO: Try to remove this at crate level when the lib is more fleshed out.
 #![allow(dead_code)]
 
+mod analysis;
+mod batch;
+#[cfg(feature = "sync-client")]
+mod blocking;
+mod board;
+mod cache;
+mod checkpoint;
+mod client;
+mod cmdwriter;
+mod compare;
 mod conf;
+mod conformance;
 mod conv;
+mod diag;
 mod eng;
 mod engcmd;
+mod engdef;
+mod engmatch;
+mod engproc;
 mod engtx;
 mod err;
+mod filter;
+mod framing;
+mod game;
 mod guicmd;
+mod infopool;
+#[cfg(feature = "serde")]
+mod json;
+mod latency;
+#[cfg(feature = "lichess")]
+mod lichess;
+mod listen;
+mod metrics;
+mod mock;
+mod mockgui;
+mod mux;
+#[cfg(windows)]
+mod namedpipe;
+mod opening;
 mod opt;
+mod optreg;
+mod pgn;
 mod piece;
 mod pm;
+mod pool;
+mod profile;
+mod proxy;
+mod replay;
+mod review;
+mod sched;
+mod search;
+mod server;
+mod signals;
+mod snapshot;
+#[cfg(feature = "spsc")]
+mod spsc;
 mod sq;
+mod tap;
+mod tcp;
+#[cfg(test)]
+mod testutil;
+mod throttle;
+mod tournament;
+mod trace;
+mod traffic;
+mod transcript;
+mod transport;
 mod types;
+#[cfg(feature = "ucci")]
+mod ucci;
+#[cfg(unix)]
+mod unix;
+#[cfg(feature = "usi")]
+mod usi;
+mod watchdog;
+#[cfg(feature = "websocket")]
+mod websocket;
+#[cfg(windows)]
+mod winproc;
+mod xbadapter;
+mod xboard;
+
+pub use analysis::{
+    Analysis, AnalysisResult, AnalysisSession, EarlyStop, InfiniteAnalysis, PvLine, StopReason,
+    StopWhen,
+};
+pub use batch::{BatchCancel, BatchEval, BatchProgress, FenEval};
+#[cfg(feature = "sync-client")]
+pub use blocking::{Engine as BlockingEngine, SearchHandle as BlockingSearchHandle};
+pub use board::Board;
+#[cfg(feature = "arena")]
+pub use bumpalo::Bump;
+pub use cache::AnalysisCache;
+pub use client::{Engine, Shutdown};
+pub use cmdwriter::CmdWriter;
+pub use compare::{EngineEval, EngineSummary, EvalComparison, PositionDiff};
+pub use conformance::{
+    Check, CheckResult, ConformanceReport, ConformanceSuite, Verdict as CheckVerdict,
+};
+pub use diag::{CompilerInfo, DiagCmd, EvalReport};
+pub use engcmd::{
+    CheckStatus, CurrLine, EngCmd, Info, InfoField, InfoRef, LazyInfo, Refutation, Score,
+    ScoreBound, MATE_CP,
+};
+pub use engdef::{EngineCatalog, EngineDef, Protocol};
+pub use engmatch::{
+    Adjudication, Adjudicator, DrawReason, EngineMatch, GameRecord, GameResult, MatchEvent,
+    MoveStats, Side, TimeControl, Verdict, WinReason,
+};
+pub use engproc::{CrashReport, EngineProcess, Launcher, Spawner};
+pub use engtx::EngTx;
+pub use err::UziErr;
+pub use filter::FilterTransport;
+#[cfg(feature = "arena")]
+pub use framing::LineBatch;
+pub use framing::LineReader;
+pub use game::GamePos;
+pub use guicmd::{Go, GuiCmd, GuiCmdRef, Pos, Register};
+#[cfg(feature = "serde")]
+pub use json::to_json;
+pub use latency::{Latency, LatencyStats, SearchLatency};
+#[cfg(feature = "lichess")]
+pub use lichess::{
+    BotEvent, ExternalEngine, HttpApi, HttpStream, LichessApi, LichessBot, ResponseLines,
+};
+pub use metrics::{AccuracyFormula, GameMetrics, SideMetrics, CP_LIMIT};
+pub use mock::{MockEngine, MockLog, MockSearch};
+pub use mockgui::MockGui;
+pub use mux::SessionObserver;
+#[cfg(windows)]
+pub use namedpipe::{PipeEngineServer, PipeTransport};
+pub use opening::OpeningSuite;
+pub use opt::{HasOpt, Opponent, PlayerType, PosValueOpt, SetOpt, Title, Variant};
+pub use optreg::{OptValue, OptionRegistry};
+pub use pgn::{to_pgn, PgnTags};
+pub use piece::Piece;
+pub use pm::{MoveList, MovesRef, Pm};
+pub use pool::{EnginePool, PooledEngine};
+pub use profile::{EngineProfile, Quirks};
+pub use proxy::{LineAction, UciProxy};
+pub use replay::{Recording, ReplayTransport};
+pub use review::{GameReview, MoveReport, MoveTag, Thresholds};
+pub use search::SearchHandle;
+pub use server::{run, Bench, EngineMeta, InfoSender, Runner, StopFlag, UciEngine, UciOut};
+pub use snapshot::{Snapshot, SnapshotDiff, SnapshotEntry, SnapshotResult};
+pub use sq::Sq;
+pub use tap::{TapLog, TapTransport};
+pub use tcp::{TcpEngineServer, TcpTransport};
+pub use tournament::{
+    Crosstable, Format, Pairing, Tally, Tournament, TournamentGame, TournamentProgress,
+};
+pub use traffic::TrafficStats;
+pub use transport::{
+    BlockingTransport, BoxFuture, LineSink, LineSource, StreamTransport, Transport,
+};
+pub use types::{ButtonType, CheckType, ComboType, OptKind, SpinType, StrType};
+#[cfg(feature = "ucci")]
+pub use ucci::{
+    Claim, PopHash, UcciClock, UcciEngCmd, UcciGo, UcciGuiCmd, UcciInfo, UcciLimit, UcciMove,
+    UcciPos, UcciSq,
+};
+#[cfg(unix)]
+pub use unix::{UnixEngineServer, UnixTransport};
+#[cfg(feature = "usi")]
+pub use usi::{
+    Checkmate, DropPiece, GameOver, MateLimit, UsiBest, UsiEngCmd, UsiGo, UsiGuiCmd, UsiInfo,
+    UsiMove, UsiPos, UsiScore, UsiSq,
+};
+pub use watchdog::{Awaited, RespawnPolicy, Watchdog, WatchdogEvent};
+#[cfg(feature = "websocket")]
+pub use websocket::{WsEngineServer, WsTransport};
+pub use xbadapter::{UciAdapter, XboardAdapter};
+pub use xboard::{FeatureValue, Thinking, XbEngCmd, XbGuiCmd, XbResult};
@@ -0,0 +1,813 @@
+// This module contains a bridge between engines and the Lichess APIs, behind
+// the lichess feature:
+//
+// - LichessBot plays the games of a bot account. It accepts the challenges in
+//   standard chess or from a position, declines the others, and plays every
+//   game that starts on a task of its own, with an engine spawned from the
+//   profile. A game follows the ndjson stream of its states: whenever it's the
+//   turn of the bot, the engine searches with the clocks of the game and its
+//   best move is sent as the move of the bot.
+// - ExternalEngine serves the analysis requests of the external engine API.
+//   It waits for work, sets up the engine as asked, and streams the info lines
+//   of the search back until the search ends or Lichess stops listening.
+//
+// The requests go through LichessApi, which HttpApi implements with HTTP/1.1.
+// Lichess only speaks HTTPS, so HttpApi takes a connector that wraps the
+// connection in TLS, e.g. with tokio-rustls, and never sends the token over
+// plain TCP on its own. Or implement LichessApi with the HTTP client of the
+// application:
+//
+// let connect = |host: &str| Box::pin(tls_connect(host.to_string()));
+// let api = HttpApi::new("lichess.org", &token, connect);
+// let bot = LichessBot::new(Arc::new(api), profile);
+// bot.run().await?;
+
+use crate::client::Engine;
+use crate::err::UziErr;
+use crate::guicmd::{Go, Pos};
+use crate::pm::Pm;
+use crate::profile::EngineProfile;
+use crate::transport::BoxFuture;
+use serde_json::{json, Value};
+use std::fmt::{self, Debug, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, mpsc, OnceCell};
+use tokio::task::JoinSet;
+
+// How long a search of ExternalEngine that isn't infinite runs by default.
+const ANALYSIS_TIME: Duration = Duration::from_secs(10);
+
+// How long an engine of LichessBot gets to quit after a game.
+const QUIT_GRACE: Duration = Duration::from_secs(1);
+
+// How many events of LichessBot are kept for slow subscribers.
+const EVENT_CAPACITY: usize = 64;
+
+// The requests the bridge makes to Lichess. The paths are those of the API,
+// e.g. /api/bot/game/stream/{id}, and the bodies are JSON.
+pub trait LichessApi: Send + Sync {
+    // Sends a request and returns the lines of the response body as they
+    // arrive, e.g. the events of an ndjson stream. Fails with HttpErr if the
+    // status is not 2xx.
+    fn request<'a>(
+        &'a self,
+        method: &'a str,
+        path: &'a str,
+        body: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Box<dyn ResponseLines>, UziErr>>;
+
+    // Sends a POST request whose body is the lines from the receiver, as they
+    // come, until the sender is dropped. Fails with HttpErr if the status is
+    // not 2xx, or with IoErr if the server closes the connection first.
+    fn post_lines<'a>(
+        &'a self,
+        path: &'a str,
+        lines: mpsc::Receiver<String>,
+    ) -> BoxFuture<'a, Result<(), UziErr>>;
+}
+
+// The lines of a response body.
+pub trait ResponseLines: Send {
+    // Returns the next line without its line end, or None at the end of the
+    // body.
+    fn next_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>>;
+}
+
+// A connection to the server, e.g. a TLS stream over a TcpStream.
+pub trait HttpStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> HttpStream for T {}
+
+type Connector =
+    Arc<dyn Fn(&str) -> BoxFuture<'static, Result<Box<dyn HttpStream>, UziErr>> + Send + Sync>;
+
+// LichessApi over HTTP/1.1, with a connection per request.
+#[derive(Clone)]
+pub struct HttpApi {
+    // The host with an optional port, e.g. "lichess.org" or "localhost:9663".
+    host: String,
+    token: String,
+    connector: Connector,
+}
+
+impl HttpApi {
+    // Returns the API of the host, authorized with the token, which connects
+    // with the connector. The connector gets the host with an optional port,
+    // and must encrypt the connection, since every request carries the token.
+    pub fn new<F>(host: &str, token: &str, connector: F) -> Self
+    where
+        F: Fn(&str) -> BoxFuture<'static, Result<Box<dyn HttpStream>, UziErr>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            host: host.to_string(),
+            token: token.to_string(),
+            connector: Arc::new(connector),
+        }
+    }
+
+    // Sets the function that connects to the host, e.g. with TLS.
+    pub fn set_connector<F>(&mut self, connector: F) -> &mut Self
+    where
+        F: Fn(&str) -> BoxFuture<'static, Result<Box<dyn HttpStream>, UziErr>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.connector = Arc::new(connector);
+        self
+    }
+
+    // Connects and sends the request line and the headers, the last of which
+    // is the given one.
+    async fn open(
+        &self,
+        method: &str,
+        path: &str,
+        header: &str,
+    ) -> Result<Box<dyn HttpStream>, UziErr> {
+        let mut stream = (self.connector)(&self.host).await?;
+        let head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\nAccept: application/x-ndjson\r\nConnection: close\r\n{}\r\n",
+            method, path, self.host, self.token, header
+        );
+        stream.write_all(head.as_bytes()).await?;
+        Ok(stream)
+    }
+}
+
+impl Debug for HttpApi {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("HttpApi")
+            .field("host", &self.host)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LichessApi for HttpApi {
+    fn request<'a>(
+        &'a self,
+        method: &'a str,
+        path: &'a str,
+        body: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Box<dyn ResponseLines>, UziErr>> {
+        Box::pin(async move {
+            let header = match body {
+                Some(body) => format!(
+                    "Content-Type: application/json\r\nContent-Length: {}\r\n",
+                    body.len()
+                ),
+                None => String::new(),
+            };
+            let mut stream = self.open(method, path, &header).await?;
+            stream
+                .write_all(body.unwrap_or_default().as_bytes())
+                .await?;
+            stream.flush().await?;
+            let body = HttpBody::read_head(stream).await?;
+            Ok(Box::new(body) as Box<dyn ResponseLines>)
+        })
+    }
+
+    fn post_lines<'a>(
+        &'a self,
+        path: &'a str,
+        mut lines: mpsc::Receiver<String>,
+    ) -> BoxFuture<'a, Result<(), UziErr>> {
+        Box::pin(async move {
+            let header = "Content-Type: text/plain\r\nTransfer-Encoding: chunked\r\n";
+            let mut stream = self.open("POST", path, header).await?;
+            stream.flush().await?;
+            while let Some(line) = lines.recv().await {
+                let chunk = format!("{:x}\r\n{}\n\r\n", line.len() + 1, line);
+                stream.write_all(chunk.as_bytes()).await?;
+                stream.flush().await?;
+            }
+            stream.write_all(b"0\r\n\r\n").await?;
+            stream.flush().await?;
+            let mut body = HttpBody::read_head(stream).await?;
+            while body.next_line().await?.is_some() {}
+            Ok(())
+        })
+    }
+}
+
+// The body of a response, either chunked, of a content length, or up to the
+// end of the connection.
+struct HttpBody {
+    reader: BufReader<Box<dyn HttpStream>>,
+    is_chunked: bool,
+    // The bytes left of the chunk or of the content length, if known. A
+    // chunked body starts with none left, so that the size of the first chunk
+    // is read.
+    left: Option<u64>,
+    // The bytes read but not returned yet.
+    buf: Vec<u8>,
+    is_eof: bool,
+}
+
+impl HttpBody {
+    // Reads the status line and the headers of the response.
+    async fn read_head(stream: Box<dyn HttpStream>) -> Result<Self, UziErr> {
+        let mut reader = BufReader::new(stream);
+        let mut status = String::new();
+        reader.read_line(&mut status).await?;
+        let status = status.trim().to_string();
+        let code = status
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok());
+        let Some(code @ 200..=299) = code else {
+            return Err(UziErr::HttpErr(status));
+        };
+        let mut is_chunked = false;
+        let mut left = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).await? == 0 {
+                return Err(UziErr::HttpErr(status));
+            }
+            let header = header.trim().to_ascii_lowercase();
+            if header.is_empty() {
+                break;
+            }
+            match header
+                .split_once(':')
+                .map(|(name, value)| (name.trim(), value.trim()))
+            {
+                Some(("transfer-encoding", "chunked")) => is_chunked = true,
+                Some(("content-length", len)) => {
+                    left = Some(len.parse().map_err(|_| UziErr::HttpErr(status.clone()))?)
+                }
+                _ => (),
+            }
+        }
+        if code == 204 || is_chunked {
+            left = Some(0);
+        }
+        Ok(Self {
+            reader,
+            is_chunked,
+            left,
+            buf: Vec::new(),
+            is_eof: code == 204 || (!is_chunked && left == Some(0)),
+        })
+    }
+
+    // Reads more of the body into the buffer.
+    async fn fill(&mut self) -> Result<(), UziErr> {
+        if self.is_chunked && self.left == Some(0) {
+            // The size of the next chunk, after the line end of the previous
+            // one, if any.
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if self.reader.read_line(&mut line).await? == 0 {
+                    self.is_eof = true;
+                    return Ok(());
+                }
+                if !line.trim().is_empty() {
+                    break;
+                }
+            }
+            let size = line.trim().split(';').next().unwrap_or_default();
+            let size = u64::from_str_radix(size, 16).map_err(|_| UziErr::HttpErr(line.clone()))?;
+            if size == 0 {
+                self.is_eof = true;
+                return Ok(());
+            }
+            self.left = Some(size);
+        }
+        let mut chunk = [0; 4096];
+        let max = self
+            .left
+            .map_or(chunk.len(), |left| chunk.len().min(left as usize));
+        let len = self.reader.read(&mut chunk[..max]).await?;
+        self.buf.extend_from_slice(&chunk[..len]);
+        self.left = self.left.map(|left| left - len as u64);
+        self.is_eof = len == 0 || (!self.is_chunked && self.left == Some(0));
+        Ok(())
+    }
+}
+
+impl ResponseLines for HttpBody {
+    fn next_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        Box::pin(async move {
+            loop {
+                if let Some(end) = self.buf.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = self.buf.drain(..=end).take(end).collect();
+                    let line = String::from_utf8_lossy(&line);
+                    return Ok(Some(line.trim_end_matches('\r').to_string()));
+                }
+                if self.is_eof {
+                    if self.buf.is_empty() {
+                        return Ok(None);
+                    }
+                    let line = std::mem::take(&mut self.buf);
+                    return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+                }
+                self.fill().await?;
+            }
+        })
+    }
+}
+
+// Returns the next event of an ndjson stream, skipping the empty lines Lichess
+// sends to keep the connection alive, or None at the end of the stream.
+async fn next_json(lines: &mut dyn ResponseLines) -> Result<Option<Value>, UziErr> {
+    while let Some(line) = lines.next_line().await? {
+        if !line.trim().is_empty() {
+            let json =
+                serde_json::from_str(&line).map_err(|err| UziErr::JsonErr(err.to_string()))?;
+            return Ok(Some(json));
+        }
+    }
+    Ok(None)
+}
+
+// What happened to a game of LichessBot.
+// - GameOver - the game ended with the status, e.g. mate or resign, or with
+//   none if its stream ended first.
+// - GameFailed - the game was given up on because of the error, e.g. the
+//   engine crashed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BotEvent {
+    GameOver {
+        game_id: String,
+        status: Option<String>,
+    },
+    GameFailed {
+        game_id: String,
+        err: UziErr,
+    },
+}
+
+// Plays the games of a bot account with the engine of a profile.
+#[derive(Clone)]
+pub struct LichessBot {
+    api: Arc<dyn LichessApi>,
+    profile: EngineProfile,
+    // The id of the bot account, read from the API the first time it is
+    // needed.
+    id: OnceCell<String>,
+    events: broadcast::Sender<BotEvent>,
+}
+
+impl LichessBot {
+    pub fn new(api: Arc<dyn LichessApi>, profile: EngineProfile) -> Self {
+        Self {
+            api,
+            profile,
+            id: OnceCell::new(),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<BotEvent> {
+        self.events.subscribe()
+    }
+
+    // Follows the events of the account, accepting challenges and playing
+    // the games that start, until the event stream ends. The games still in
+    // progress then are played to the end before this returns. A game that
+    // fails doesn't stop the bot, it is reported as GameFailed.
+    pub async fn run(&self) -> Result<(), UziErr> {
+        let mut lines = self.api.request("GET", "/api/stream/event", None).await?;
+        let mut games = JoinSet::new();
+        while let Some(event) = next_json(lines.as_mut()).await? {
+            match event["type"].as_str() {
+                Some("challenge") => {
+                    let challenge = &event["challenge"];
+                    let Some(id) = challenge["id"].as_str() else {
+                        continue;
+                    };
+                    let action = match challenge["variant"]["key"].as_str() {
+                        Some("standard" | "fromPosition") => "accept",
+                        _ => "decline",
+                    };
+                    // The challenge may be gone by now, which is no reason
+                    // to stop.
+                    let path = format!("/api/challenge/{}/{}", id, action);
+                    let _ = self.api.request("POST", &path, None).await;
+                }
+                Some("gameStart") => {
+                    let game = &event["game"];
+                    let Some(game_id) = game["gameId"].as_str().or(game["id"].as_str()) else {
+                        continue;
+                    };
+                    let bot = self.clone();
+                    let game_id = game_id.to_string();
+                    games.spawn(async move {
+                        let event = match bot.play_game(&game_id).await {
+                            Ok(status) => BotEvent::GameOver { game_id, status },
+                            Err(err) => BotEvent::GameFailed { game_id, err },
+                        };
+                        // This fails if nobody is subscribed.
+                        let _ = bot.events.send(event);
+                    });
+                }
+                _ => (),
+            }
+        }
+        while games.join_next().await.is_some() {}
+        Ok(())
+    }
+
+    // Plays the game with an engine spawned from the profile, and quits the
+    // engine when the game is over. Returns the status the game ended with.
+    pub async fn play_game(&self, game_id: &str) -> Result<Option<String>, UziErr> {
+        let mut eng = self.profile.spawn().await?;
+        let status = self.play_with(&mut eng, game_id).await;
+        let _ = eng.shutdown(QUIT_GRACE).await;
+        status
+    }
+
+    // Plays the game with the engine, which must have gone through the
+    // handshake. Returns the status the game ended with, e.g. mate, or None if
+    // the stream of the game ended before the game did.
+    pub async fn play_with(
+        &self,
+        eng: &mut Engine,
+        game_id: &str,
+    ) -> Result<Option<String>, UziErr> {
+        let bot_id = self.id().await?;
+        let path = format!("/api/bot/game/stream/{}", game_id);
+        let mut lines = self.api.request("GET", &path, None).await?;
+        let mut start = Pos::new();
+        let mut is_white = true;
+        // The number of moves of the last position searched, so that a state
+        // that only changes e.g. a draw offer is not searched again.
+        let mut searched = None;
+        while let Some(event) = next_json(lines.as_mut()).await? {
+            let state = match event["type"].as_str() {
+                Some("gameFull") => {
+                    let white_id = event["white"]["id"].as_str().unwrap_or_default();
+                    is_white = white_id.eq_ignore_ascii_case(bot_id);
+                    start = match event["initialFen"].as_str() {
+                        Some("startpos") | None => Pos::new(),
+                        Some(fen) => Pos::with_fen(fen),
+                    };
+                    &event["state"]
+                }
+                Some("gameState") => &event,
+                // E.g. chatLine or opponentGone.
+                _ => continue,
+            };
+            match state["status"].as_str() {
+                Some("created" | "started") => (),
+                status => return Ok(status.map(str::to_string)),
+            }
+            let mut pos = start.clone();
+            for word in state["moves"]
+                .as_str()
+                .unwrap_or_default()
+                .split_whitespace()
+            {
+                pos.add_move(Pm::from_str(word)?);
+            }
+            if pos.is_white_to_move() != is_white || searched == Some(pos.moves().len()) {
+                continue;
+            }
+            searched = Some(pos.moves().len());
+            let millis = |key: &str| Duration::from_millis(state[key].as_u64().unwrap_or_default());
+            let mut go = Go::new();
+            go.set_wtime(millis("wtime"))
+                .set_btime(millis("btime"))
+                .set_winc(millis("winc"))
+                .set_binc(millis("binc"));
+            eng.position(&pos).await?;
+            let (best, _) = eng.go(&go).await?.wait().await?;
+            let path = format!("/api/bot/game/{}/move/{}", game_id, best);
+            let mut reply = self.api.request("POST", &path, None).await?;
+            while reply.next_line().await?.is_some() {}
+        }
+        Ok(None)
+    }
+
+    async fn id(&self) -> Result<&str, UziErr> {
+        let id = self
+            .id
+            .get_or_try_init(|| async {
+                let mut lines = self.api.request("GET", "/api/account", None).await?;
+                let account = next_json(lines.as_mut()).await?;
+                let id = account.as_ref().and_then(|account| account["id"].as_str());
+                id.map(str::to_string)
+                    .ok_or(UziErr::JsonErr("no account id".to_string()))
+            })
+            .await?;
+        Ok(id)
+    }
+}
+
+impl Debug for LichessBot {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("LichessBot")
+            .field("profile", &self.profile)
+            .field("id", &self.id.get())
+            .finish_non_exhaustive()
+    }
+}
+
+// Serves the analysis requests of an external engine registered with Lichess,
+// with the secret of its provider.
+#[derive(Clone)]
+pub struct ExternalEngine {
+    api: Arc<dyn LichessApi>,
+    secret: String,
+    analysis_time: Duration,
+}
+
+impl ExternalEngine {
+    pub fn new(api: Arc<dyn LichessApi>, provider_secret: &str) -> Self {
+        Self {
+            api,
+            secret: provider_secret.to_string(),
+            analysis_time: ANALYSIS_TIME,
+        }
+    }
+
+    // Sets how long a search runs when Lichess doesn't ask for an infinite
+    // one, ANALYSIS_TIME by default.
+    pub fn set_analysis_time(&mut self, analysis_time: Duration) -> &mut Self {
+        self.analysis_time = analysis_time;
+        self
+    }
+
+    // Serves requests with the engine until an error occurs.
+    pub async fn run(&self, eng: &mut Engine) -> Result<(), UziErr> {
+        loop {
+            self.serve(eng).await?;
+        }
+    }
+
+    // Waits for a request and serves it with the engine, which must have gone
+    // through the handshake. Returns false if Lichess had no work before the
+    // wait timed out.
+    pub async fn serve(&self, eng: &mut Engine) -> Result<bool, UziErr> {
+        let body = json!({ "providerSecret": self.secret }).to_string();
+        let mut lines = self
+            .api
+            .request("POST", "/api/external-engine/work", Some(&body))
+            .await?;
+        let Some(job) = next_json(lines.as_mut()).await? else {
+            return Ok(false);
+        };
+        let (Some(id), work) = (job["id"].as_str(), &job["work"]) else {
+            return Err(UziErr::JsonErr("no work id".to_string()));
+        };
+        for (name, key) in [
+            ("Threads", "threads"),
+            ("Hash", "hash"),
+            ("MultiPV", "multiPv"),
+        ] {
+            if let Some(value) = work[key].as_u64() {
+                eng.set_option(name, value).await?;
+            }
+        }
+        let mut pos = match work["initialFen"].as_str() {
+            Some(fen) => Pos::with_fen(fen),
+            None => Pos::new(),
+        };
+        for word in work["moves"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+        {
+            pos.add_move(Pm::from_str(word.as_str().unwrap_or_default())?);
+        }
+        let mut go = Go::new();
+        match work["infinite"].as_bool() {
+            Some(true) => go.set_infinite(),
+            _ => go.set_move_time(self.analysis_time),
+        };
+
+        // The lines are sent on a task of their own, which ends when Lichess
+        // stops listening, e.g. because the user left the analysis.
+        let (sender, receiver) = mpsc::channel(EVENT_CAPACITY);
+        let api = self.api.clone();
+        let path = format!("/api/external-engine/work/{}", id);
+        let submit = tokio::spawn(async move { api.post_lines(&path, receiver).await });
+        eng.position(&pos).await?;
+        let mut search = eng.go(&go).await?;
+        let mut is_stopped = false;
+        while let Some(info) = search.next_info().await? {
+            if sender.send(info.to_string()).await.is_err() && !is_stopped {
+                search.stop().await?;
+                is_stopped = true;
+            }
+        }
+        let (best, _) = search.wait().await?;
+        let _ = sender.send(format!("bestmove {}", best)).await;
+        drop(sender);
+        match submit.await {
+            Ok(Err(err)) if !is_stopped => Err(err),
+            _ => Ok(true),
+        }
+    }
+}
+
+impl Debug for ExternalEngine {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("ExternalEngine")
+            .field("analysis_time", &self.analysis_time)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEngine, MockSearch};
+    use tokio::net::{TcpListener, TcpStream};
+
+    // Returns the API of the local test server, over plain TCP.
+    fn local_api(listener: &TcpListener) -> HttpApi {
+        let host = listener.local_addr().unwrap().to_string();
+        HttpApi::new(&host, "token", |host: &str| {
+            let host = host.to_string();
+            Box::pin(async move {
+                let stream = TcpStream::connect(host).await?;
+                Ok(Box::new(stream) as Box<dyn HttpStream>)
+            })
+        })
+    }
+
+    // Accepts a request, returning its request line, its body, and the
+    // connection to answer on. Chunked bodies are read up to the last chunk.
+    async fn accept(listener: &TcpListener) -> (String, String, BufReader<TcpStream>) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        reader.read_line(&mut request).await.unwrap();
+        let (mut len, mut is_chunked) = (0, false);
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).await.unwrap();
+            let header = header.trim().to_ascii_lowercase();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("content-length:") {
+                len = value.trim().parse().unwrap();
+            }
+            is_chunked |= header == "transfer-encoding: chunked";
+        }
+        let mut body = String::new();
+        if is_chunked {
+            loop {
+                let mut size = String::new();
+                reader.read_line(&mut size).await.unwrap();
+                let size = usize::from_str_radix(size.trim(), 16).unwrap();
+                let mut chunk = vec![0; size + 2];
+                reader.read_exact(&mut chunk).await.unwrap();
+                if size == 0 {
+                    break;
+                }
+                body.push_str(std::str::from_utf8(&chunk[..size]).unwrap());
+            }
+        } else {
+            let mut bytes = vec![0; len];
+            reader.read_exact(&mut bytes).await.unwrap();
+            body = String::from_utf8(bytes).unwrap();
+        }
+        (request.trim().to_string(), body, reader)
+    }
+
+    async fn reply(conn: &mut BufReader<TcpStream>, body: &str) {
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        conn.write_all(response.as_bytes()).await.unwrap();
+    }
+
+    // Sends the lines as a chunk of an ndjson stream.
+    async fn stream(conn: &mut BufReader<TcpStream>, lines: &str) {
+        let chunk = format!("{:x}\r\n{}\r\n", lines.len(), lines);
+        conn.write_all(chunk.as_bytes()).await.unwrap();
+    }
+
+    async fn engine(mock: MockEngine) -> Engine {
+        let mut eng = Engine::new(mock);
+        eng.uci(Duration::from_secs(1)).await.unwrap();
+        eng
+    }
+
+    #[tokio::test]
+    async fn bot_plays_game() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api = local_api(&listener);
+        let bot = LichessBot::new(Arc::new(api), EngineProfile::new(Default::default()));
+        let mut mock = MockEngine::new("Mock");
+        mock.add_search(MockSearch::new(Pm::from_str("e2e4").unwrap()))
+            .add_search(MockSearch::new(Pm::from_str("g1f3").unwrap()));
+        let log = mock.log();
+        let mut eng = engine(mock).await;
+        let game = tokio::spawn(async move { bot.play_with(&mut eng, "g1").await });
+
+        let (request, _, mut conn) = accept(&listener).await;
+        assert_eq!(request, "GET /api/account HTTP/1.1");
+        reply(&mut conn, r#"{"id": "uzibot", "username": "UziBot"}"#).await;
+        let (request, _, mut game_conn) = accept(&listener).await;
+        assert_eq!(request, "GET /api/bot/game/stream/g1 HTTP/1.1");
+        game_conn
+            .write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n")
+            .await
+            .unwrap();
+        stream(
+            &mut game_conn,
+            concat!(
+                r#"{"type": "gameFull", "id": "g1", "white": {"id": "uzibot"}, "black": {"id": "human"}, "#,
+                r#""initialFen": "startpos", "state": {"type": "gameState", "moves": "", "#,
+                r#""wtime": 60000, "btime": 60000, "winc": 1000, "binc": 1000, "status": "started"}}"#,
+                "\n\n"
+            ),
+        )
+        .await;
+        let (request, _, mut conn) = accept(&listener).await;
+        assert_eq!(request, "POST /api/bot/game/g1/move/e2e4 HTTP/1.1");
+        reply(&mut conn, r#"{"ok": true}"#).await;
+        for moves in ["e2e4", "e2e4 e7e5"] {
+            let state = format!(
+                r#"{{"type": "gameState", "moves": "{}", "wtime": 59000, "btime": 58000, "winc": 1000, "binc": 1000, "status": "started"}}"#,
+                moves
+            );
+            stream(&mut game_conn, &format!("{}\n", state)).await;
+        }
+        let (request, _, mut conn) = accept(&listener).await;
+        assert_eq!(request, "POST /api/bot/game/g1/move/g1f3 HTTP/1.1");
+        reply(&mut conn, r#"{"ok": true}"#).await;
+        stream(
+            &mut game_conn,
+            r#"{"type": "gameState", "moves": "e2e4 e7e5 g1f3", "status": "resign", "winner": "white"}
+"#,
+        )
+        .await;
+        assert_eq!(game.await.unwrap(), Ok(Some("resign".to_string())));
+        let lines = log.lines();
+        assert!(lines.contains(&"position startpos moves e2e4 e7e5".to_string()));
+        assert!(lines.contains(&"go wtime 59000 btime 58000 winc 1000 binc 1000".to_string()));
+    }
+
+    #[tokio::test]
+    async fn external_engine_serves_work() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api = local_api(&listener);
+        let mut ext = ExternalEngine::new(Arc::new(api), "secret");
+        ext.set_analysis_time(Duration::from_millis(100));
+        let mut mock = MockEngine::new("Mock");
+        let mut search = MockSearch::new(Pm::from_str("e7e5").unwrap());
+        search.add_info("depth 1 score cp 20 pv e7e5");
+        mock.add_search(search);
+        let log = mock.log();
+        let mut eng = engine(mock).await;
+        let serve = tokio::spawn(async move { ext.serve(&mut eng).await });
+
+        let (request, body, mut conn) = accept(&listener).await;
+        assert_eq!(request, "POST /api/external-engine/work HTTP/1.1");
+        assert_eq!(body, r#"{"providerSecret":"secret"}"#);
+        let job = concat!(
+            r#"{"id": "w1", "work": {"sessionId": "s1", "threads": 2, "hash": 64, "infinite": false, "#,
+            r#""multiPv": 1, "variant": "chess", "#,
+            r#""initialFen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "moves": ["e2e4"]}}"#
+        );
+        reply(&mut conn, job).await;
+        let (request, body, mut conn) = accept(&listener).await;
+        assert_eq!(request, "POST /api/external-engine/work/w1 HTTP/1.1");
+        assert_eq!(body, "info depth 1 pv e7e5 score cp 20\nbestmove e7e5\n");
+        conn.write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(serve.await.unwrap(), Ok(true));
+        let lines = log.lines();
+        assert!(lines.contains(&"setoption name Threads value 2".to_string()));
+        assert!(lines.contains(&"go movetime 100".to_string()));
+    }
+
+    #[tokio::test]
+    async fn http_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api = local_api(&listener);
+        let request = tokio::spawn(async move {
+            let result = api.request("GET", "/api/account", None).await;
+            result.map(|_| ())
+        });
+        let (_, _, mut conn) = accept(&listener).await;
+        conn.write_all(b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            request.await.unwrap(),
+            Err(UziErr::HttpErr("HTTP/1.1 401 Unauthorized".to_string()))
+        );
+    }
+}
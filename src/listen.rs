@@ -0,0 +1,140 @@
+// This module contains the accept loop that the engine servers share, see
+// TcpEngineServer. The loop accepts connections on a listener and runs each
+// session on a task of its own, with an engine from the spawner. It keeps going
+// when accepting fails, e.g. when the process is out of file descriptors, after
+// a short pause so that it doesn't spin. The number of sessions is limited,
+// since each one runs an engine, and the connections over the limit wait in
+// the backlog of the listener until a session ends.
+
+use crate::engproc::Spawner;
+use crate::proxy::UciProxy;
+use crate::trace;
+use crate::transport::BoxFuture;
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time;
+
+// How many sessions a server runs at once, unless it is told otherwise.
+pub(crate) const DEFAULT_MAX_SESSIONS: usize = 64;
+
+// How long the loop waits after accepting failed.
+const ACCEPT_DELAY: Duration = Duration::from_millis(100);
+
+// Where a server gets its connections from.
+pub(crate) trait Listener: Send {
+    type Conn: Send + 'static;
+
+    // Waits for the next connection.
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<Self::Conn>>;
+}
+
+// Accepts connections forever, and runs up to max_sessions sessions at once.
+// A connection whose engine fails to start is closed.
+pub(crate) async fn serve<L, S, F>(
+    listener: &mut L,
+    spawner: &Spawner,
+    max_sessions: usize,
+    session: S,
+) where
+    L: Listener,
+    S: Fn(UciProxy, L::Conn) -> F,
+    F: Future<Output = ()> + Send + 'static,
+{
+    let sessions = Arc::new(Semaphore::new(
+        max_sessions.clamp(1, Semaphore::MAX_PERMITS),
+    ));
+    loop {
+        let permit = Arc::clone(&sessions)
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let conn = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                trace::accept_failed(&err);
+                time::sleep(ACCEPT_DELAY).await;
+                continue;
+            }
+        };
+        let Ok(proxy) = UciProxy::from_spawner(spawner) else {
+            continue;
+        };
+        let session = session(proxy, conn);
+        tokio::spawn(async move {
+            session.await;
+            drop(permit);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Engine;
+    use crate::mock::MockEngine;
+    use crate::transport::StreamTransport;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::DuplexStream;
+    use tokio::sync::mpsc;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    // Hands out the connections and errors it is sent.
+    struct FakeListener(mpsc::UnboundedReceiver<io::Result<DuplexStream>>);
+
+    impl Listener for FakeListener {
+        type Conn = DuplexStream;
+
+        fn accept(&mut self) -> BoxFuture<'_, io::Result<DuplexStream>> {
+            Box::pin(async move {
+                match self.0.recv().await {
+                    Some(conn) => conn,
+                    None => std::future::pending().await,
+                }
+            })
+        }
+    }
+
+    fn connect(tx: &mpsc::UnboundedSender<io::Result<DuplexStream>>) -> Engine {
+        let (client, server) = tokio::io::duplex(4096);
+        tx.send(Ok(server)).unwrap();
+        let (reader, writer) = tokio::io::split(client);
+        Engine::new(StreamTransport::new(reader, writer))
+    }
+
+    #[tokio::test]
+    async fn serve_survives_errors_and_limits_sessions() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let started = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&started);
+        let spawner = Spawner::new(|| Ok(MockEngine::new("Mock")));
+        tokio::spawn(async move {
+            let mut listener = FakeListener(rx);
+            serve(&mut listener, &spawner, 1, move |proxy, conn| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let (reader, writer) = tokio::io::split(conn);
+                    let _ = proxy.run_with(reader, writer).await;
+                }
+            })
+            .await
+        });
+
+        // A failed accept doesn't end the server.
+        tx.send(Err(io::Error::other("too many open files")))
+            .unwrap();
+        let mut first = connect(&tx);
+        let mut second = connect(&tx);
+        assert_eq!(first.uci(TIMEOUT).await, Ok(()));
+
+        // The second connection waits for the first session to end.
+        time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+        assert!(first.shutdown(TIMEOUT).await.is_ok());
+        assert_eq!(second.uci(TIMEOUT).await, Ok(()));
+        assert_eq!(started.load(Ordering::SeqCst), 2);
+    }
+}
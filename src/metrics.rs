@@ -0,0 +1,185 @@
+// This module contains GameMetrics, which sums up how well each side played a
+// game reviewed by GameReview, as the average centipawn loss and an accuracy
+// percentage.
+
+use crate::guicmd::Pos;
+use crate::review::{MoveReport, MoveTag};
+
+// The evaluations are limited to this many centipawns before the loss of a
+// move is computed, so that a missed mate doesn't outweigh the rest of the
+// game, and moves in lost positions lose little.
+pub const CP_LIMIT: i32 = 1000;
+
+// How the accuracy of a move is computed from the evaluations before and after
+// it, in centipawns from the point of view of the side that played it.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum AccuracyFormula {
+    // Converts the evaluations to winning chances, and maps the chances lost
+    // to an accuracy with an exponential curve, as lichess does. Losing a
+    // pawn in an equal position costs more than in a won one.
+    #[default]
+    WinChance,
+    // Takes the given percentage off for every centipawn lost.
+    Linear(f64),
+    // Any other formula. The result is limited to 0..=100.
+    Custom(fn(i32, i32) -> f64),
+}
+
+// The metrics of one side.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SideMetrics {
+    // The number of moves with both evaluations, which are the moves the
+    // averages are computed over.
+    pub moves: usize,
+    // The average centipawn loss, or None without moves.
+    pub acpl: Option<f64>,
+    // The average accuracy of the moves, from 0 to 100, or None without
+    // moves.
+    pub accuracy: Option<f64>,
+    pub inaccuracies: usize,
+    pub mistakes: usize,
+    pub blunders: usize,
+}
+
+// The metrics of both sides of a game.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GameMetrics {
+    pub white: SideMetrics,
+    pub black: SideMetrics,
+}
+
+impl AccuracyFormula {
+    // Returns the accuracy of a move, from 0 to 100, given the evaluations
+    // before and after it.
+    pub fn accuracy(&self, before: i32, after: i32) -> f64 {
+        let accuracy = match *self {
+            AccuracyFormula::WinChance => {
+                let lost = (win_chance(before) - win_chance(after)).max(0.0);
+                103.1668 * (-0.04354 * lost).exp() - 3.1669
+            }
+            AccuracyFormula::Linear(per_cp) => 100.0 - per_cp * f64::from((before - after).max(0)),
+            AccuracyFormula::Custom(formula) => formula(before, after),
+        };
+        accuracy.clamp(0.0, 100.0)
+    }
+}
+
+impl SideMetrics {
+    // Computes the metrics of the moves, which are all played by one side.
+    pub fn new<'a, I>(reports: I, formula: AccuracyFormula) -> Self
+    where
+        I: IntoIterator<Item = &'a MoveReport>,
+    {
+        let mut metrics = SideMetrics::default();
+        let (mut loss, mut accuracy) = (0, 0.0);
+        for report in reports {
+            match report.tag {
+                Some(MoveTag::Inaccuracy) => metrics.inaccuracies += 1,
+                Some(MoveTag::Mistake) => metrics.mistakes += 1,
+                Some(MoveTag::Blunder) => metrics.blunders += 1,
+                None => (),
+            }
+            let before = report.before.and_then(|score| score.clamp_cp(CP_LIMIT));
+            let after = report.after.and_then(|score| score.clamp_cp(CP_LIMIT));
+            if let (Some(before), Some(after)) = (before, after) {
+                metrics.moves += 1;
+                loss += i64::from((before - after).max(0));
+                accuracy += formula.accuracy(before, after);
+            }
+        }
+        if metrics.moves > 0 {
+            let moves = metrics.moves as f64;
+            metrics.acpl = Some(loss as f64 / moves);
+            metrics.accuracy = Some(accuracy / moves);
+        }
+        metrics
+    }
+}
+
+impl GameMetrics {
+    // Computes the metrics of both sides from the reports of a game review,
+    // which starts at the given position.
+    pub fn new(reports: &[MoveReport], start: &Pos, formula: AccuracyFormula) -> Self {
+        let is_white_first = start.is_white_to_move();
+        let side = |is_white: bool| {
+            let first = if is_white == is_white_first { 0 } else { 1 };
+            SideMetrics::new(reports.iter().skip(first).step_by(2), formula)
+        };
+        Self {
+            white: side(true),
+            black: side(false),
+        }
+    }
+}
+
+// Returns the winning chances, from 0 to 100, of an evaluation.
+fn win_chance(cp: i32) -> f64 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * f64::from(cp)).exp()) - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engcmd::{Info, Score};
+    use crate::pm::Pm;
+    use crate::review::Thresholds;
+
+    fn score(cp: &str) -> Option<Score> {
+        Info::try_from(&["info", "score", "cp", cp][..])
+            .unwrap()
+            .score()
+    }
+
+    fn report(ply: usize, before: &str, after: &str) -> MoveReport {
+        let (before, after) = (score(before), score(after));
+        let loss = before
+            .and_then(|score| score.cp_value())
+            .zip(after.and_then(|score| score.cp_value()))
+            .map(|(before, after)| (before - after).max(0));
+        MoveReport {
+            ply,
+            pm: Pm::Null,
+            best: Pm::Null,
+            before,
+            after,
+            loss,
+            tag: loss.and_then(|loss| Thresholds::default().tag(loss)),
+        }
+    }
+
+    #[test]
+    fn metrics_per_side() {
+        let reports = [
+            report(0, "20", "20"),
+            report(1, "-20", "-120"),
+            report(2, "120", "100"),
+            report(3, "-100", "-2000"),
+        ];
+        let metrics = GameMetrics::new(&reports, &Pos::new(), AccuracyFormula::Linear(0.1));
+        assert_eq!(metrics.white.moves, 2);
+        assert_eq!(metrics.white.acpl, Some(10.0));
+        assert_eq!(metrics.white.accuracy, Some(99.0));
+        assert_eq!(metrics.white.inaccuracies, 0);
+        // The blunder loses 900 centipawns once limited to CP_LIMIT.
+        assert_eq!(metrics.black.acpl, Some(500.0));
+        assert_eq!(metrics.black.accuracy, Some(50.0));
+        assert_eq!((metrics.black.mistakes, metrics.black.blunders), (1, 1));
+
+        // Black moves first from this FEN.
+        let pos = Pos::with_fen("8/8/8/8/8/8/8/K6k b - - 0 1");
+        let metrics = GameMetrics::new(&reports[..1], &pos, AccuracyFormula::default());
+        assert_eq!(metrics.black.accuracy.map(f64::round), Some(100.0));
+        assert_eq!(metrics.white, SideMetrics::default());
+    }
+
+    #[test]
+    fn metrics_accuracy_formulas() {
+        let formula = AccuracyFormula::WinChance;
+        // The same loss costs more in an equal position than in a won one.
+        assert!(formula.accuracy(0, -100) < formula.accuracy(600, 500));
+        assert!(formula.accuracy(0, -100) > 0.0);
+        assert_eq!(AccuracyFormula::Linear(0.1).accuracy(50, 0), 95.0);
+        let custom = AccuracyFormula::Custom(|before, after| f64::from(after - before));
+        assert_eq!(custom.accuracy(0, 500), 100.0);
+    }
+}
@@ -0,0 +1,332 @@
+// This module contains MockEngine, a transport that behaves like an engine
+// following a script, for testing code built on the client without an engine
+// binary. For example, a mock that answers go with an info line and, after 50
+// ms, e2e4:
+//
+// let mut mock = MockEngine::new("Mock");
+// let mut search = MockSearch::new(e2e4);
+// search
+//     .add_info("depth 1 score cp 20 pv e2e4")
+//     .set_delay(Duration::from_millis(50));
+// mock.add_search(search);
+// let log = mock.log();
+// let mut eng = Engine::new(mock);
+//
+// The mock answers uci with its id and options, isready with readyok, and go
+// with the next search of the script. It follows the rules of UCI for go
+// infinite and go ponder, i.e. it only sends bestmove after stop, or after
+// ponderhit and the delay. The commands it gets are kept in its log.
+
+use crate::err::UziErr;
+use crate::opt::HasOpt;
+use crate::pm::Pm;
+use crate::transport::{BoxFuture, Transport};
+use std::collections::VecDeque;
+use std::future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{self, Instant};
+
+// A scripted engine. Clones share the log, so a clone can be handed to a
+// Spawner to replace a mock that crashed.
+#[derive(Clone, Debug)]
+pub struct MockEngine {
+    name: String,
+    author: String,
+    options: Vec<HasOpt>,
+    // The searches for the next go commands. The last one is repeated.
+    searches: VecDeque<MockSearch>,
+    // The commands that make the mock exit, e.g. go to test a crash while
+    // searching.
+    crash_on: Vec<String>,
+    log: MockLog,
+
+    // The lines to send, with when they are due, in the order they are due.
+    outgoing: VecDeque<(Instant, String)>,
+    // The bestmove of a go infinite or go ponder, until stop or ponderhit.
+    held: Option<(String, Duration)>,
+    is_closed: bool,
+}
+
+// What the mock does for a go: it sends the info lines right away, and
+// bestmove after the delay.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MockSearch {
+    infos: Vec<String>,
+    best: Pm,
+    ponder: Option<Pm>,
+    delay: Duration,
+}
+
+// The commands a mock got, in order, shared by the clones of the mock.
+#[derive(Clone, Debug, Default)]
+pub struct MockLog(Arc<Mutex<Vec<String>>>);
+
+impl MockEngine {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            author: "uzi".to_string(),
+            options: Vec::new(),
+            searches: VecDeque::new(),
+            crash_on: Vec::new(),
+            log: MockLog::default(),
+            outgoing: VecDeque::new(),
+            held: None,
+            is_closed: false,
+        }
+    }
+
+    pub fn set_author(&mut self, author: &str) -> &mut Self {
+        self.author = author.to_string();
+        self
+    }
+
+    // Adds an option to declare in the handshake.
+    pub fn add_option(&mut self, opt: HasOpt) -> &mut Self {
+        self.options.push(opt);
+        self
+    }
+
+    // Adds the search for the next go. Without searches, the mock answers go
+    // with the null move, as engines do when there are no legal moves.
+    pub fn add_search(&mut self, search: MockSearch) -> &mut Self {
+        self.searches.push_back(search);
+        self
+    }
+
+    // Makes the mock exit when it gets the command, e.g. "go".
+    pub fn crash_on(&mut self, cmd: &str) -> &mut Self {
+        self.crash_on.push(cmd.to_string());
+        self
+    }
+
+    pub fn log(&self) -> MockLog {
+        self.log.clone()
+    }
+
+    fn send(&mut self, due: Instant, line: String) {
+        let i = self.outgoing.partition_point(|(other, _)| *other <= due);
+        self.outgoing.insert(i, (due, line));
+    }
+
+    fn on_line(&mut self, line: &str) {
+        self.log.0.lock().unwrap().push(line.to_string());
+        let cmd = line.split_whitespace().next().unwrap_or_default();
+        if self.crash_on.iter().any(|crash_on| crash_on == cmd) {
+            self.close();
+            return;
+        }
+        let now = Instant::now();
+        // The commands are told apart by their name, since a go without
+        // limits is not a valid GuiCmd.
+        match cmd {
+            "uci" => {
+                self.send(now, format!("id name {}", self.name));
+                self.send(now, format!("id author {}", self.author));
+                for opt in self.options.clone() {
+                    self.send(now, opt.to_string());
+                }
+                self.send(now, "uciok".to_string());
+            }
+            "isready" => self.send(now, "readyok".to_string()),
+            "go" => {
+                let search = match self.searches.len() {
+                    0 => MockSearch::new(Pm::Null),
+                    1 => self.searches[0].clone(),
+                    _ => self.searches.pop_front().unwrap(),
+                };
+                for info in &search.infos {
+                    self.send(now, format!("info {}", info));
+                }
+                let best = search.best_line();
+                let words: Vec<&str> = line.split_whitespace().collect();
+                if words.contains(&"infinite") || words.contains(&"ponder") {
+                    self.held = Some((best, search.delay));
+                } else {
+                    self.send(now + search.delay, best);
+                }
+            }
+            "stop" => {
+                // The bestmove of the search is sent now, or right after the
+                // lines that are due already.
+                let best = self.held.take().map(|(best, _)| best).or_else(|| {
+                    let i = self.outgoing.iter().position(|(_, line)| is_best(line))?;
+                    self.outgoing.remove(i).map(|(_, best)| best)
+                });
+                if let Some(best) = best {
+                    self.send(now, best);
+                }
+            }
+            "ponderhit" => {
+                if let Some((best, delay)) = self.held.take() {
+                    self.send(now + delay, best);
+                }
+            }
+            "quit" => self.close(),
+            _ => (),
+        }
+    }
+
+    fn close(&mut self) {
+        self.is_closed = true;
+        self.outgoing.clear();
+        self.held = None;
+    }
+}
+
+fn is_best(line: &str) -> bool {
+    line.starts_with("bestmove")
+}
+
+impl Transport for MockEngine {
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        let result = if self.is_closed {
+            Err(UziErr::EngineExited)
+        } else {
+            self.on_line(line);
+            Ok(())
+        };
+        Box::pin(async move { result })
+    }
+
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        Box::pin(async move {
+            let Some(&(due, _)) = self.outgoing.front() else {
+                if self.is_closed {
+                    return Ok(None);
+                }
+                // Like an engine waiting for a command.
+                return future::pending().await;
+            };
+            // The line is only taken once it is due, so that this is cancel
+            // safe.
+            time::sleep_until(due).await;
+            Ok(self.outgoing.pop_front().map(|(_, line)| line))
+        })
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        self.close();
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl MockSearch {
+    pub fn new(best: Pm) -> Self {
+        Self {
+            infos: Vec::new(),
+            best,
+            ponder: None,
+            delay: Duration::ZERO,
+        }
+    }
+
+    // Adds an info line, without "info", e.g. "depth 1 score cp 20 pv e2e4".
+    pub fn add_info(&mut self, info: &str) -> &mut Self {
+        self.infos.push(info.to_string());
+        self
+    }
+
+    pub fn set_ponder(&mut self, ponder: Pm) -> &mut Self {
+        self.ponder = Some(ponder);
+        self
+    }
+
+    // Sets the time between go and bestmove.
+    pub fn set_delay(&mut self, delay: Duration) -> &mut Self {
+        self.delay = delay;
+        self
+    }
+
+    fn best_line(&self) -> String {
+        match self.ponder {
+            Some(ponder) => format!("bestmove {} ponder {}", self.best, ponder),
+            None => format!("bestmove {}", self.best),
+        }
+    }
+}
+
+impl MockLog {
+    pub fn lines(&self) -> Vec<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Engine;
+    use crate::guicmd::{Go, Pos};
+    use crate::types::CheckType;
+    use std::str::FromStr;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn pm(pm: &str) -> Pm {
+        Pm::from_str(pm).unwrap()
+    }
+
+    fn mock() -> MockEngine {
+        let mut mock = MockEngine::new("Mock");
+        mock.add_option(HasOpt::Ponder(CheckType(false)));
+        let mut search = MockSearch::new(pm("e2e4"));
+        search
+            .add_info("depth 1 score cp 20 pv e2e4")
+            .set_ponder(pm("e7e5"))
+            .set_delay(Duration::from_millis(50));
+        mock.add_search(search);
+        mock.add_search(MockSearch::new(pm("d2d4")));
+        mock
+    }
+
+    #[tokio::test]
+    async fn mock_engine_follows_script() {
+        let mock = mock();
+        let log = mock.log();
+        let mut eng = Engine::new(mock);
+        eng.uci(TIMEOUT).await.unwrap();
+        assert_eq!(eng.name(), Some("Mock"));
+        assert_eq!(eng.options().len(), 1);
+
+        let pos = Pos::new();
+        eng.position(&pos).await.unwrap();
+        let started = Instant::now();
+        let mut search = eng.go(&Go::new()).await.unwrap();
+        let info = search.next_info().await.unwrap().unwrap();
+        assert_eq!(info.to_string(), "info depth 1 pv e2e4 score cp 20");
+        assert_eq!(search.wait().await, Ok((pm("e2e4"), Some(pm("e7e5")))));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        // The last search is repeated.
+        for _ in 0..2 {
+            assert_eq!(eng.best_move(&pos, &Go::new()).await, Ok(pm("d2d4")));
+        }
+        assert_eq!(
+            log.lines()[..4],
+            ["uci", "ucinewgame", "isready", "position startpos"]
+        );
+    }
+
+    #[tokio::test]
+    async fn mock_engine_infinite_and_crash() {
+        let mut mock = mock();
+        mock.crash_on("setoption");
+        let mut eng = Engine::new(mock);
+        eng.position(&Pos::new()).await.unwrap();
+        let mut go = Go::new();
+        go.set_infinite();
+        let mut search = eng.go(&go).await.unwrap();
+        assert!(search.next_info().await.unwrap().is_some());
+        // No bestmove before stop.
+        let next = time::timeout(Duration::from_millis(100), search.next_info()).await;
+        assert!(next.is_err());
+        search.stop().await.unwrap();
+        assert_eq!(search.wait().await.map(|(best, _)| best), Ok(pm("e2e4")));
+
+        eng.set_option("Hash", 16).await.unwrap();
+        assert!(matches!(
+            eng.recv().await.map_err(UziErr::into_root),
+            Err(UziErr::Crashed(_))
+        ));
+    }
+}
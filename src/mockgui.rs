@@ -0,0 +1,336 @@
+// This module contains MockGui, which plays the part of the GUI for engine
+// authors, so that an engine can be tested at the level of the protocol. The
+// engine is either a Runner in the same process or a binary. For example:
+//
+// let mut gui = MockGui::with_runner(Runner::new(meta, engine));
+// gui.run_script(
+//     "> uci
+//      < uciok
+//      > position startpos moves e2e4
+//      > go depth 5
+//      < bestmove",
+// )
+// .await?;
+// gui.quit().await?;
+//
+// A script has a command per line. "> line" sends the line to the engine, and
+// "< text" waits for a line from the engine that starts with the text, skipping
+// the lines before it. Blank lines and lines starting with # are skipped.
+
+use crate::engproc::{EngineProcess, Launcher};
+use crate::err::UziErr;
+use crate::server::{Runner, UciEngine, UciOut};
+use crate::transport::{BoxFuture, Transport};
+use std::ffi::OsStr;
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Write};
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, DuplexStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant};
+
+// A scripted GUI connected to an engine. Every wait for the engine fails after
+// the timeout, so that a test of an engine that doesn't answer fails instead of
+// hanging.
+pub struct MockGui {
+    transport: Box<dyn Transport>,
+    timeout: Duration,
+    // The lines received from the engine, in order.
+    received: Vec<String>,
+}
+
+// A step of a script.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Step {
+    Send(String),
+    Expect(String),
+}
+
+impl MockGui {
+    // Returns a GUI for the engine at the other end of the transport.
+    pub fn new<T: Transport + 'static>(transport: T) -> Self {
+        Self {
+            transport: Box::new(transport),
+            timeout: Duration::from_secs(5),
+            received: Vec::new(),
+        }
+    }
+
+    // Spawns the engine binary, e.g. the one built by the tests of the crate
+    // of the engine, env!("CARGO_BIN_EXE_<name>").
+    pub fn spawn<S: AsRef<OsStr>>(program: S) -> Result<Self, UziErr> {
+        Ok(Self::new(EngineProcess::spawn(program)?))
+    }
+
+    // Spawns the engine binary with the arguments and the preamble of the
+    // launcher.
+    pub fn launch(launcher: Launcher) -> Result<Self, UziErr> {
+        Ok(Self::new(launcher.spawn()?))
+    }
+
+    // Runs the runner on a task of the current runtime, with the GUI as its
+    // input and output. The runner runs the engine as run_async does, so this
+    // has to be called in a tokio runtime.
+    pub fn with_runner<E: UciEngine + Send + 'static>(runner: Runner<E>) -> Self {
+        Self::new(RunnerTransport::new(runner))
+    }
+
+    // Sets how long to wait for the engine. The default is 5 seconds.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // Returns the lines received from the engine so far, including the ones
+    // expect skipped.
+    pub fn received(&self) -> &[String] {
+        &self.received
+    }
+
+    // Sends the line to the engine.
+    pub async fn send(&mut self, line: &str) -> Result<(), UziErr> {
+        self.transport.send_line(line).await
+    }
+
+    // Returns the next line from the engine. Fails with Timeout if there is
+    // none in time, and with EngineExited if the engine closed the connection.
+    pub async fn recv(&mut self) -> Result<String, UziErr> {
+        self.recv_until(Instant::now() + self.timeout).await
+    }
+
+    // Returns the first line from the engine that starts with the text, e.g.
+    // "bestmove", skipping the lines before it. Fails with MissingReply if
+    // there is none in time.
+    pub async fn expect(&mut self, text: &str) -> Result<String, UziErr> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            match self.recv_until(deadline).await {
+                Ok(line) if line.starts_with(text) => return Ok(line),
+                Ok(_) => (),
+                Err(UziErr::Timeout) => return Err(UziErr::MissingReply(text.to_string())),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Runs the script, see the module comment. A line that is not a step fails
+    // with BadScript before anything is sent.
+    pub async fn run_script(&mut self, script: &str) -> Result<(), UziErr> {
+        for step in parse_script(script)? {
+            match step {
+                Step::Send(line) => self.send(&line).await?,
+                Step::Expect(text) => {
+                    self.expect(&text).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Sends quit and waits for the engine to exit. Fails with Timeout if the
+    // engine is still running after the timeout, in which case it is killed.
+    pub async fn quit(&mut self) -> Result<(), UziErr> {
+        self.send("quit").await?;
+        match time::timeout(self.timeout, self.transport.wait_exit()).await {
+            Ok(result) => result,
+            Err(_) => {
+                self.transport.kill().await?;
+                Err(UziErr::Timeout)
+            }
+        }
+    }
+
+    async fn recv_until(&mut self, deadline: Instant) -> Result<String, UziErr> {
+        match time::timeout_at(deadline, self.transport.recv_line()).await {
+            Ok(Ok(Some(line))) => {
+                self.received.push(line.clone());
+                Ok(line)
+            }
+            Ok(Ok(None)) => Err(UziErr::EngineExited),
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err(UziErr::Timeout),
+        }
+    }
+}
+
+impl Debug for MockGui {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("MockGui")
+            .field("timeout", &self.timeout)
+            .field("received", &self.received)
+            .finish_non_exhaustive()
+    }
+}
+
+fn parse_script(script: &str) -> Result<Vec<Step>, UziErr> {
+    let mut steps = Vec::new();
+    for (i, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let step = if let Some(line) = line.strip_prefix('>') {
+            Step::Send(line.trim().to_string())
+        } else if let Some(text) = line.strip_prefix('<') {
+            Step::Expect(text.trim().to_string())
+        } else {
+            return Err(UziErr::BadScript(i + 1));
+        };
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+// The connection to a runner in the same process. The runner reads the lines
+// sent from a pipe, and its output comes back line by line over a channel.
+#[derive(Debug)]
+pub(crate) struct RunnerTransport {
+    input: DuplexStream,
+    output: UnboundedReceiver<String>,
+    run: JoinHandle<Result<(), UziErr>>,
+}
+
+impl RunnerTransport {
+    pub(crate) fn new<E: UciEngine + Send + 'static>(runner: Runner<E>) -> Self {
+        let (input, runner_input) = tokio::io::duplex(4096);
+        let (tx, output) = mpsc::unbounded_channel();
+        let out = UciOut::new(LineSender {
+            tx,
+            buf: Vec::new(),
+        });
+        let run = tokio::spawn(runner.run_with_async(runner_input, out));
+        Self { input, output, run }
+    }
+}
+
+impl Transport for RunnerTransport {
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        Box::pin(async move {
+            let line = format!("{}\n", line);
+            self.input
+                .write_all(line.as_bytes())
+                .await
+                .map_err(|_| UziErr::EngineExited)
+        })
+    }
+
+    // The output closes once the runner has returned and dropped its UciOut.
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        Box::pin(async move { Ok(self.output.recv().await) })
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        self.run.abort();
+        Box::pin(async { Ok(()) })
+    }
+
+    // Returns what the runner returned.
+    fn wait_exit(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        Box::pin(async move {
+            (&mut self.run)
+                .await
+                .map_err(|err| UziErr::IoErr(err.to_string()))?
+        })
+    }
+}
+
+// Sends what the runner writes to the channel, a line at a time.
+struct LineSender {
+    tx: UnboundedSender<String>,
+    buf: Vec<u8>,
+}
+
+impl Write for LineSender {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(bytes);
+        while let Some(i) = self.buf.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=i).collect();
+            let line = String::from_utf8_lossy(&line[..i]).into_owned();
+            self.tx
+                .send(line)
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engcmd::Info;
+    use crate::game::GamePos;
+    use crate::guicmd::Go;
+    use crate::pm::Pm;
+    use crate::server::{EngineMeta, InfoSender, StopFlag};
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+    use std::str::FromStr;
+
+    // Plays the first move of the search moves, or e2e4.
+    struct FirstMove;
+
+    impl UciEngine for FirstMove {
+        fn go(
+            &mut self,
+            _pos: &GamePos,
+            go: &Go,
+            _stop: &StopFlag,
+            info: &InfoSender,
+        ) -> (Pm, Option<Pm>) {
+            let best = go
+                .search_moves()
+                .and_then(|moves| moves.first())
+                .copied()
+                .unwrap_or_else(|| Pm::from_str("e2e4").unwrap());
+            info.send(Info::from_string("searching"));
+            (best, None)
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_gui_drives_runner() {
+        let meta = EngineMeta::new("First", "1.0", &["uzi"]);
+        let mut gui = MockGui::with_runner(Runner::new(meta, FirstMove));
+        let script = "
+            # The handshake.
+            > uci
+            < id name First 1.0
+            < uciok
+            > isready
+            < readyok
+            > position startpos
+            > go depth 1 searchmoves d2d4
+            < bestmove d2d4
+        ";
+        gui.run_script(script).await.unwrap();
+        assert_eq!(gui.received().len(), 6);
+        assert_eq!(gui.received()[4], "info string searching");
+
+        gui.set_timeout(Duration::from_millis(100));
+        assert_eq!(gui.recv().await, Err(UziErr::Timeout));
+        assert_eq!(gui.quit().await, Ok(()));
+        assert_eq!(gui.recv().await, Err(UziErr::EngineExited));
+    }
+
+    #[tokio::test]
+    async fn mock_gui_drives_binary() {
+        let mut gui = MockGui::new(fake_engine(FAKE_ENGINE));
+        gui.set_timeout(Duration::from_millis(500));
+        gui.send("uci").await.unwrap();
+        assert_eq!(gui.recv().await, Ok("id name Fake".to_string()));
+        assert_eq!(gui.expect("uciok").await, Ok("uciok".to_string()));
+        assert_eq!(
+            gui.run_script("> isready\n< bestmove").await,
+            Err(UziErr::MissingReply("bestmove".to_string()))
+        );
+        assert_eq!(
+            gui.run_script("> isready\nreadyok").await,
+            Err(UziErr::BadScript(2))
+        );
+    }
+}
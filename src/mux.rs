@@ -0,0 +1,99 @@
+// This module contains SessionObserver, which lets other parts of a program
+// follow the commands an engine sends, e.g. a panel that shows the lines of a
+// search, a logger and a stats collector, while the code that drives the
+// engine reads them as usual. Every observer gets every command on its own,
+// and an observer that doesn't keep up misses the oldest commands rather than
+// slowing down the engine or the other observers.
+
+use crate::engcmd::EngCmd;
+use tokio::sync::broadcast::{self, error::RecvError, error::TryRecvError};
+
+// The default number of commands kept for an observer that falls behind.
+pub(crate) const OBSERVER_CAPACITY: usize = 256;
+
+// Hands the commands received from an engine to its observers.
+#[derive(Debug)]
+pub(crate) struct SessionMux {
+    tx: broadcast::Sender<EngCmd>,
+}
+
+impl SessionMux {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            tx: broadcast::channel(capacity.max(1)).0,
+        }
+    }
+
+    pub fn observe(&self) -> SessionObserver {
+        SessionObserver {
+            rx: self.tx.subscribe(),
+            missed: 0,
+        }
+    }
+
+    // Hands the command to the observers. The command is only cloned if
+    // there are any.
+    pub fn publish(&self, cmd: &EngCmd) {
+        if self.tx.receiver_count() > 0 {
+            let _ = self.tx.send(cmd.clone());
+        }
+    }
+}
+
+// Receives the commands of an engine session, in the order the engine sent
+// them, from the time the observer was created. Restarted engines are
+// observed too.
+#[derive(Debug)]
+pub struct SessionObserver {
+    rx: broadcast::Receiver<EngCmd>,
+    missed: u64,
+}
+
+impl SessionObserver {
+    // Waits for the next command. Returns None once the client is dropped and
+    // all commands have been received.
+    pub async fn recv(&mut self) -> Option<EngCmd> {
+        loop {
+            match self.rx.recv().await {
+                Ok(cmd) => return Some(cmd),
+                Err(RecvError::Lagged(missed)) => self.missed += missed,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    // Returns the next command if there is one, without waiting.
+    pub fn try_recv(&mut self) -> Option<EngCmd> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(cmd) => return Some(cmd),
+                Err(TryRecvError::Lagged(missed)) => self.missed += missed,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return None,
+            }
+        }
+    }
+
+    // The number of commands the observer missed because it fell behind.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn observer_misses_oldest() {
+        let mux = SessionMux::new(2);
+        let mut observer = mux.observe();
+        for _ in 0..3 {
+            mux.publish(&EngCmd::ReadyOk);
+        }
+        mux.publish(&EngCmd::UciOk);
+        assert_eq!(observer.try_recv(), Some(EngCmd::ReadyOk));
+        assert_eq!(observer.missed(), 2);
+        assert_eq!(observer.try_recv(), Some(EngCmd::UciOk));
+        assert_eq!(observer.try_recv(), None);
+    }
+}
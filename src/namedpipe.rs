@@ -0,0 +1,240 @@
+// This module contains the transport for engines on Windows named pipes, e.g.
+// for GUIs that expect engines on a pipe rather than on stdio. It is the
+// Windows counterpart of UnixTransport: the engine side either listens with a
+// PipeEngineServer and the client connects, or the client listens and the
+// engine side connects. Pipe names have the form \\.\pipe\<name>.
+
+use crate::engproc::Spawner;
+use crate::err::UziErr;
+use crate::listen::{self, Listener, DEFAULT_MAX_SESSIONS};
+use crate::transport::{BoxFuture, StreamTransport, Transport};
+use std::fmt::Debug;
+use std::time::Duration;
+use tokio::io::{self, AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+use tokio::net::windows::named_pipe::{
+    ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions,
+};
+use tokio::time;
+use windows_sys::Win32::Foundation::ERROR_PIPE_BUSY;
+
+// How many times, and how often, connect tries a pipe whose instances are all
+// busy.
+const BUSY_RETRIES: u32 = 20;
+const BUSY_DELAY: Duration = Duration::from_millis(50);
+
+// A connection to an engine over a named pipe, from either end of the pipe.
+// Killing the engine closes the connection, which ends the session on the
+// other side.
+#[derive(Debug)]
+pub struct PipeTransport<P> {
+    stream: StreamTransport<ReadHalf<P>, WriteHalf<P>>,
+}
+
+impl PipeTransport<NamedPipeClient> {
+    // Connects to an engine served at the pipe. A pipe that is busy with other
+    // clients is tried again for a while.
+    pub async fn connect(name: &str) -> Result<Self, UziErr> {
+        let mut retries = 0;
+        loop {
+            match ClientOptions::new().open(name) {
+                Ok(pipe) => return Ok(Self::new(pipe)),
+                Err(err) if is_busy(&err) && retries < BUSY_RETRIES => retries += 1,
+                Err(err) => return Err(err.into()),
+            }
+            time::sleep(BUSY_DELAY).await;
+        }
+    }
+
+    // Returns a spawner that connects to the pipe, for an Engine that
+    // reconnects when it is restarted, or for a pool of engines on a server.
+    // The spawner doesn't wait for a busy pipe, and it has to be used in a
+    // tokio runtime.
+    pub fn spawner(name: &str) -> Spawner {
+        let name = name.to_string();
+        Spawner::new(move || Ok(Self::new(ClientOptions::new().open(&name)?)))
+    }
+}
+
+impl PipeTransport<NamedPipeServer> {
+    // Creates the pipe and waits for an engine to connect to it.
+    pub async fn listen(name: &str) -> Result<Self, UziErr> {
+        let pipe = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(name)?;
+        pipe.connect().await?;
+        Ok(Self::new(pipe))
+    }
+}
+
+impl<P: AsyncRead + AsyncWrite> PipeTransport<P> {
+    pub fn new(pipe: P) -> Self {
+        let (reader, writer) = io::split(pipe);
+        Self {
+            stream: StreamTransport::new(reader, writer),
+        }
+    }
+}
+
+impl<P> Transport for PipeTransport<P>
+where
+    P: AsyncRead + AsyncWrite + Debug + Send,
+{
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        self.stream.send_line(line)
+    }
+
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        self.stream.recv_line()
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        self.stream.kill()
+    }
+}
+
+fn is_busy(err: &std::io::Error) -> bool {
+    err.raw_os_error() == Some(ERROR_PIPE_BUSY as i32)
+}
+
+// Serves an engine to clients on the same machine. Every connection gets an
+// engine of its own from the spawner, see TcpEngineServer.
+#[derive(Debug)]
+pub struct PipeEngineServer {
+    listener: PipeListener,
+    spawner: Spawner,
+    max_sessions: usize,
+}
+
+impl PipeEngineServer {
+    // Creates the pipe. Fails if another server already has a pipe with the
+    // name.
+    pub fn bind(name: &str, spawner: Spawner) -> Result<Self, UziErr> {
+        let next = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(name)?;
+        Ok(Self {
+            listener: PipeListener {
+                name: name.to_string(),
+                next: Some(next),
+            },
+            spawner,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.listener.name
+    }
+
+    // Sets how many sessions run at once, see TcpEngineServer.
+    pub fn set_max_sessions(&mut self, max_sessions: usize) -> &mut Self {
+        self.max_sessions = max_sessions;
+        self
+    }
+
+    // Accepts connections until the task is dropped, see TcpEngineServer.
+    pub async fn serve(mut self) {
+        listen::serve(
+            &mut self.listener,
+            &self.spawner,
+            self.max_sessions,
+            |proxy, pipe: NamedPipeServer| async move {
+                let (reader, writer) = io::split(pipe);
+                let _ = proxy.run_with(reader, writer).await;
+            },
+        )
+        .await
+    }
+}
+
+// The instances of a pipe, which clients connect to one at a time.
+#[derive(Debug)]
+struct PipeListener {
+    name: String,
+    // The instance of the pipe the next client connects to, or None if it
+    // couldn't be created, or failed to connect.
+    next: Option<NamedPipeServer>,
+}
+
+impl Listener for PipeListener {
+    type Conn = NamedPipeServer;
+
+    fn accept(&mut self) -> BoxFuture<'_, std::io::Result<NamedPipeServer>> {
+        Box::pin(async move {
+            let pipe = match self.next.take() {
+                Some(pipe) => pipe,
+                None => ServerOptions::new().create(&self.name)?,
+            };
+            // An instance that failed to connect is dropped, and the next
+            // accept creates a new one.
+            pipe.connect().await?;
+            // A new instance for the next client, before this one is handed
+            // over, so that clients never find the pipe gone.
+            self.next = ServerOptions::new().create(&self.name).ok();
+            Ok(pipe)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Engine;
+    use crate::guicmd::{Go, Pos};
+    use crate::pm::Pm;
+    use crate::proxy::UciProxy;
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+    use std::str::FromStr;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn pipe_name(name: &str) -> String {
+        format!(r"\\.\pipe\uzi-{}-{}", name, std::process::id())
+    }
+
+    #[tokio::test]
+    async fn pipe_connect_to_server() {
+        let name = pipe_name("server");
+        let spawner = Spawner::new(|| Ok(fake_engine(FAKE_ENGINE)));
+        let server = PipeEngineServer::bind(&name, spawner).unwrap();
+        assert_eq!(server.name(), name);
+        tokio::spawn(server.serve());
+
+        let mut eng = Engine::new(PipeTransport::connect(&name).await.unwrap());
+        eng.uci(TIMEOUT).await.unwrap();
+        let best = eng.best_move(&Pos::new(), &Go::new()).await;
+        assert_eq!(best, Ok(Pm::from_str("e2e4").unwrap()));
+
+        let mut eng = Engine::from_spawner(PipeTransport::spawner(&name)).unwrap();
+        eng.uci(TIMEOUT).await.unwrap();
+        eng.restart().await.unwrap();
+        eng.sync(TIMEOUT).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn pipe_engine_connects_to_client() {
+        let name = pipe_name("client");
+        let listen = tokio::spawn({
+            let name = name.clone();
+            async move { PipeTransport::listen(&name).await }
+        });
+        // The engine side dials in, once the pipe exists.
+        let engine_name = name.clone();
+        tokio::spawn(async move {
+            let pipe = loop {
+                match ClientOptions::new().open(&engine_name) {
+                    Ok(pipe) => break pipe,
+                    Err(_) => time::sleep(BUSY_DELAY).await,
+                }
+            };
+            let (reader, writer) = io::split(pipe);
+            let proxy = UciProxy::new(fake_engine(FAKE_ENGINE));
+            proxy.run_with(reader, writer).await
+        });
+
+        let mut eng = Engine::new(listen.await.unwrap().unwrap());
+        eng.uci(TIMEOUT).await.unwrap();
+        assert_eq!(eng.name(), Some("Fake"));
+        assert_eq!(eng.shutdown(TIMEOUT).await.map(|_| ()), Ok(()));
+    }
+}
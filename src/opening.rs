@@ -0,0 +1,278 @@
+// This module contains OpeningSuite, the positions the games of a tournament
+// start from, so that the engines don't play the same game over and over. A
+// suite can be read from:
+//
+// - An EPD file, with a position per line. The operations after the position,
+//   e.g. bm or id, are ignored.
+// - A PGN file, where every game is an opening. A game starts from its FEN tag
+//   if it has one, and comments, variations and annotations are ignored.
+// - Lines of FENs or moves, with an opening per line, e.g.
+//   "e2e4 c7c5 g1f3" or "<fen> moves e2e4". Blank lines and lines starting
+//   with # are ignored.
+
+use crate::board::Board;
+use crate::err::UziErr;
+use crate::guicmd::Pos;
+use crate::pm::Pm;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+// The openings of a suite, in the order of the file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OpeningSuite {
+    openings: Vec<Pos>,
+}
+
+impl OpeningSuite {
+    // Returns a suite of the openings. This fails with BadOpening if an
+    // opening is not a valid position.
+    pub fn new(openings: Vec<Pos>) -> Result<Self, UziErr> {
+        for (i, opening) in openings.iter().enumerate() {
+            if Board::from_pos(opening).is_none() {
+                return Err(UziErr::BadOpening(i + 1));
+            }
+        }
+        Ok(Self { openings })
+    }
+
+    // Reads the suite from a file, as EPD if its extension is epd, as PGN if
+    // it is pgn, and as lines of FENs or moves otherwise.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, UziErr> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("epd") => Self::from_epd(&text),
+            Some("pgn") => Self::from_pgn(&text),
+            _ => Self::from_lines(text.lines()),
+        }
+    }
+
+    pub fn from_epd(text: &str) -> Result<Self, UziErr> {
+        let openings = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+                if fields.len() < 4 {
+                    return Err(UziErr::BadOpening(i + 1));
+                }
+                Ok(Pos::with_fen(&format!("{} 0 1", fields.join(" "))))
+            })
+            .collect::<Result<_, _>>()?;
+        Self::new(openings)
+    }
+
+    pub fn from_pgn(text: &str) -> Result<Self, UziErr> {
+        let mut openings = Vec::new();
+        for (i, game) in pgn_games(text).into_iter().enumerate() {
+            let mut pos = Pos::new();
+            if let Some(fen) = game.fen {
+                pos.set_fen_string(fen);
+            }
+            let mut board = Board::from_pos(&pos).ok_or(UziErr::BadOpening(i + 1))?;
+            for san in game.moves {
+                let pm = board.parse_san(&san).ok_or(UziErr::BadOpening(i + 1))?;
+                board.play(pm);
+                pos.add_move(pm);
+            }
+            openings.push(pos);
+        }
+        Self::new(openings)
+    }
+
+    pub fn from_lines<I, S>(lines: I) -> Result<Self, UziErr>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut openings = Vec::new();
+        for line in lines {
+            let line = line.as_ref().trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = UziErr::BadOpening(openings.len() + 1);
+            let (fen, moves) = match line.split_once(" moves") {
+                Some((fen, moves)) => (Some(fen), moves),
+                None if line.contains('/') => (Some(line), ""),
+                None => (None, line),
+            };
+            let mut pos = fen.map_or_else(Pos::new, Pos::with_fen);
+            for pm in moves.split_whitespace() {
+                pos.add_move(Pm::from_str(pm).map_err(|_| bad.clone())?);
+            }
+            openings.push(pos);
+        }
+        Self::new(openings)
+    }
+
+    pub fn len(&self) -> usize {
+        self.openings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.openings.is_empty()
+    }
+
+    // Returns the opening with the index, starting over after the last one,
+    // or None if the suite is empty.
+    pub fn get(&self, index: usize) -> Option<&Pos> {
+        (!self.is_empty()).then(|| &self.openings[index % self.len()])
+    }
+
+    pub fn openings(&self) -> &[Pos] {
+        &self.openings
+    }
+}
+
+// A game of a PGN file, with only what an opening needs.
+#[derive(Default)]
+struct PgnGame {
+    fen: Option<String>,
+    moves: Vec<String>,
+}
+
+// Splits the PGN into its games. A game ends with its result, or when the tags
+// of the next game start.
+fn pgn_games(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    // Whether the game has any tags or moves.
+    let mut started = false;
+    // The newline at the end ends the last word.
+    let mut chars = text.chars().chain(Some('\n'));
+    let mut variations: usize = 0;
+    let mut word = String::new();
+    while let Some(c) = chars.next() {
+        let end_word = c.is_whitespace() || "{;()[".contains(c);
+        if end_word && !word.is_empty() {
+            if variations == 0 {
+                if is_result(&word) {
+                    games.push(std::mem::take(&mut game));
+                    started = false;
+                } else if let Some(san) = san_of(&word) {
+                    game.moves.push(san.to_string());
+                    started = true;
+                }
+            }
+            word.clear();
+        }
+        match c {
+            '{' => chars.by_ref().take_while(|c| *c != '}').for_each(drop),
+            ';' => chars.by_ref().take_while(|c| *c != '\n').for_each(drop),
+            '(' => variations += 1,
+            ')' => variations = variations.saturating_sub(1),
+            '[' if variations == 0 => {
+                let tag: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                if !game.moves.is_empty() {
+                    games.push(std::mem::take(&mut game));
+                }
+                started = true;
+                if let Some(fen) = tag.strip_prefix("FEN ") {
+                    game.fen = Some(fen.trim().trim_matches('"').to_string());
+                }
+            }
+            c if c.is_whitespace() => (),
+            c => word.push(c),
+        }
+    }
+    if started {
+        games.push(game);
+    }
+    games
+}
+
+fn is_result(word: &str) -> bool {
+    matches!(word, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+// Returns the move of a word of movetext, without its number, e.g. e4 for
+// "1.e4", or None if the word has no move, e.g. "1..." or a NAG like $1.
+fn san_of(word: &str) -> Option<&str> {
+    if word.starts_with('$') {
+        return None;
+    }
+    // The number ends with a dot, unlike castling with zeros, e.g. 0-0.
+    let number = word.trim_start_matches(|c: char| c.is_ascii_digit());
+    let san = if number.starts_with('.') {
+        number.trim_start_matches('.')
+    } else {
+        word
+    };
+    (!san.is_empty()).then_some(san)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(pos: &Pos) -> Vec<String> {
+        pos.moves().iter().map(|pm| pm.to_string()).collect()
+    }
+
+    #[test]
+    fn opening_suite_from_epd_and_lines() {
+        let epd = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 id \"e4\";\n\n\
+                   4k3/8/8/8/8/8/8/4K2R w K - bm O-O;\n";
+        let suite = OpeningSuite::from_epd(epd).unwrap();
+        assert_eq!(suite.len(), 2);
+        assert_eq!(
+            suite.openings()[1].fen(),
+            Some("4k3/8/8/8/8/8/8/4K2R w K - 0 1")
+        );
+        // The openings start over after the last one.
+        assert_eq!(suite.get(3), suite.openings().get(1));
+
+        let suite = OpeningSuite::from_lines([
+            "# Sicilian and a rook ending",
+            "e2e4 c7c5 g1f3",
+            "",
+            "4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1g1",
+        ])
+        .unwrap();
+        assert_eq!(suite.len(), 2);
+        assert_eq!(moves(&suite.openings()[0]), vec!["e2e4", "c7c5", "g1f3"]);
+        assert_eq!(
+            suite.openings()[1].to_string(),
+            "position fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1g1"
+        );
+
+        assert_eq!(
+            OpeningSuite::from_lines(["e2e4", "e2e4 e2e4"]),
+            Err(UziErr::BadOpening(2))
+        );
+        assert_eq!(OpeningSuite::from_epd("8/8 w"), Err(UziErr::BadOpening(1)));
+    }
+
+    #[test]
+    fn opening_suite_from_pgn() {
+        let pgn = r#"[Event "?"]
+[White "A"]
+
+1. e4 {best by test} c5 (1... e5 2. Nf3) 2.Nf3 $1 d6?! 1-0
+
+[Event "?"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/8/4K2R w K - 0 1"]
+
+1. 0-0 Kd7 *
+"#;
+        let suite = OpeningSuite::from_pgn(pgn).unwrap();
+        assert_eq!(suite.len(), 2);
+        assert_eq!(
+            moves(&suite.openings()[0]),
+            vec!["e2e4", "c7c5", "g1f3", "d7d6"]
+        );
+        assert_eq!(
+            suite.openings()[1].fen(),
+            Some("4k3/8/8/8/8/8/8/4K2R w K - 0 1")
+        );
+        assert_eq!(moves(&suite.openings()[1]), vec!["e1g1", "e8d7"]);
+
+        let pgn = "1. e4 e5 *\n1. e4 Ke7 *";
+        assert_eq!(OpeningSuite::from_pgn(pgn), Err(UziErr::BadOpening(2)));
+    }
+}
@@ -2,7 +2,7 @@
 
 use crate::conv::{to_bool, to_number};
 use crate::err::UziErr;
-use crate::types::{CheckType, SpinType, StrType};
+use crate::types::{CheckType, ComboType, OptKind, SpinType, StrType};
 use std::fmt::{self, Display, Formatter};
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -10,7 +10,6 @@ use std::str::FromStr;
 // Represents all the different options that may be supported by a UCI compliant
 // chess engine. These are meant to be used by the engine to tell the GUI which
 // options are available, and what their default configurations are.
-// TODO: Add support for non-standard options.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum HasOpt {
     // The value in MB for memory for hash tables.
@@ -49,6 +48,111 @@ pub enum HasOpt {
     SetPosVal(StrType),
     // UCI_EngineAbout: Tells the GUI about the engine.
     About(StrType),
+    // UCI_Variant: Tells the GUI the variants the engine plays, as the values
+    // of a combo, e.g. chess, crazyhouse and atomic. This is a convention of
+    // Fairy-Stockfish and other multi-variant engines rather than part of the
+    // UCI standard.
+    Variant(ComboType),
+    // Any option that is not part of the UCI standard, e.g. "Threads", or a
+    // standard option declared with an unexpected type.
+    Custom { name: String, kind: OptKind },
+}
+
+impl HasOpt {
+    // Returns the name of the option as sent on the wire.
+    pub fn name(&self) -> &str {
+        match self {
+            HasOpt::Hash(_) => HASH,
+            HasOpt::NalimovPath(_) => NALIMOV_PATH,
+            HasOpt::NalimovCache(_) => NALIMOV_CACHE,
+            HasOpt::Ponder(_) => PONDER,
+            HasOpt::OwnBook(_) => OWN_BOOK,
+            HasOpt::MultiPv(_) => MULTI_PV,
+            HasOpt::ShowCurrLine(_) => SHOW_CURR_LINE,
+            HasOpt::ShowRefutations(_) => SHOW_REFUTATIONS,
+            HasOpt::LimitStrength(_) => LIMIT_STRENGTH,
+            HasOpt::Elo(_) => ELO,
+            HasOpt::AnalysisMode(_) => ANALYSIS_MODE,
+            HasOpt::ShredderBasesPath(_) => SHREDDER_BASES_PATH,
+            HasOpt::Opp(_) => OPPONENT,
+            HasOpt::SetPosVal(_) => SET_POSITION_VALUE,
+            HasOpt::About(_) => ABOUT,
+            HasOpt::Variant(_) => VARIANT,
+            HasOpt::Custom { ref name, .. } => name,
+        }
+    }
+
+    // Creates an option from the name and type declared by the engine. Standard
+    // options declared with the expected type are mapped to their own variant,
+    // everything else becomes a custom option.
+    pub fn from_decl(name: String, kind: OptKind) -> Self {
+        let Ok(opt) = UziOpt::from_str(&name) else {
+            return HasOpt::Custom { name, kind };
+        };
+        let has_opt = match (opt, &kind) {
+            (UziOpt::Hash, OptKind::Spin(t)) => spin_as(t).map(HasOpt::Hash),
+            (UziOpt::NalimovCache, OptKind::Spin(t)) => spin_as(t).map(HasOpt::NalimovCache),
+            (UziOpt::MultiPv, OptKind::Spin(t)) => spin_as(t).map(HasOpt::MultiPv),
+            (UziOpt::Elo, OptKind::Spin(t)) => spin_as(t).map(HasOpt::Elo),
+            (UziOpt::Ponder, OptKind::Check(t)) => Some(HasOpt::Ponder(*t)),
+            (UziOpt::OwnBook, OptKind::Check(t)) => Some(HasOpt::OwnBook(*t)),
+            (UziOpt::ShowCurrLine, OptKind::Check(t)) => Some(HasOpt::ShowCurrLine(*t)),
+            (UziOpt::ShowRefutations, OptKind::Check(t)) => Some(HasOpt::ShowRefutations(*t)),
+            (UziOpt::LimitStrength, OptKind::Check(t)) => Some(HasOpt::LimitStrength(*t)),
+            (UziOpt::AnalysisMode, OptKind::Check(t)) => Some(HasOpt::AnalysisMode(*t)),
+            (UziOpt::NalimovPath, OptKind::Str(t)) => Some(HasOpt::NalimovPath(t.clone())),
+            (UziOpt::ShredderBasesPath, OptKind::Str(t)) => {
+                Some(HasOpt::ShredderBasesPath(t.clone()))
+            }
+            (UziOpt::Opponent, OptKind::Str(t)) => Some(HasOpt::Opp(t.clone())),
+            (UziOpt::SetPosVal, OptKind::Str(t)) => Some(HasOpt::SetPosVal(t.clone())),
+            (UziOpt::About, OptKind::Str(t)) => Some(HasOpt::About(t.clone())),
+            (UziOpt::Variant, OptKind::Combo(t)) => Some(HasOpt::Variant(t.clone())),
+            _ => None,
+        };
+        has_opt.unwrap_or(HasOpt::Custom { name, kind })
+    }
+
+    // Returns the variants of a UCI_Variant declaration, or None for the
+    // other options.
+    pub fn variants(&self) -> Option<Vec<Variant>> {
+        match self {
+            HasOpt::Variant(t) => Some(t.var.iter().filter_map(|v| v.parse().ok()).collect()),
+            _ => None,
+        }
+    }
+}
+
+// Parses an option declaration sent by the engine, i.e.
+// option name <id> type <t> [default <x>] [min <x>] [max <x>] [var <x>]*
+impl TryFrom<&[&str]> for HasOpt {
+    type Error = UziErr;
+
+    fn try_from(words: &[&str]) -> Result<Self, Self::Error> {
+        if words.len() < 2 || words[0] != "option" || words[1] != "name" {
+            return Err(UziErr::BadOptDecl);
+        }
+        let type_index = words
+            .iter()
+            .position(|word| *word == "type")
+            .ok_or(UziErr::BadOptDecl)?;
+        if type_index <= 2 {
+            return Err(UziErr::BadOptDecl);
+        }
+        let name = words[2..type_index].join(" ");
+        let kind = OptKind::try_from(&words[type_index..])?;
+        Ok(HasOpt::from_decl(name, kind))
+    }
+}
+
+// Converts the limits of a spin option to a narrower integer type, returning
+// None if any of the values does not fit.
+fn spin_as<T: TryFrom<i64>>(spin: &SpinType<i64>) -> Option<SpinType<T>> {
+    Some(SpinType {
+        default: T::try_from(spin.default).ok()?,
+        min: T::try_from(spin.min).ok()?,
+        max: T::try_from(spin.max).ok()?,
+    })
 }
 
 impl Display for HasOpt {
@@ -70,11 +174,12 @@ impl Display for HasOpt {
             HasOpt::Opp(t) => write!(formatter, "{} {}", OPPONENT, t),
             HasOpt::SetPosVal(t) => write!(formatter, "{} {}", SET_POSITION_VALUE, t),
             HasOpt::About(t) => write!(formatter, "{} {}", ABOUT, t),
+            HasOpt::Variant(t) => write!(formatter, "{} {}", VARIANT, t),
+            HasOpt::Custom { name, kind } => write!(formatter, "{} {}", name, kind),
         }
     }
 }
 
-// TODO: Add support for non-standard options.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum SetOpt {
     // The value in MB for memory for hash tables.
@@ -120,20 +225,81 @@ pub enum SetOpt {
     // centipawns from white's point of view if evaluating this specific
     // position. See PosValueOpt for accepted formats.
     SetPosVal(PosValueOpt),
+    // UCI_Variant: Tells the engine which variant to play, one of those it
+    // declared.
+    Variant(Variant),
+    // Sets an option that is not part of the UCI standard, e.g. "Threads". The
+    // value is None for buttons. These are only sent by the GUI, and parsing a
+    // "setoption" with an unknown name fails with UziErr::UnknownOpt.
+    Custom { name: String, value: Option<String> },
+}
+
+impl SetOpt {
+    // The name of the option, as sent to the engine.
+    pub fn name(&self) -> &str {
+        match self {
+            SetOpt::Hash(_) => HASH,
+            SetOpt::NalimovPath(_) => NALIMOV_PATH,
+            SetOpt::NalimovCache(_) => NALIMOV_CACHE,
+            SetOpt::Ponder(_) => PONDER,
+            SetOpt::OwnBook(_) => OWN_BOOK,
+            SetOpt::MultiPv(_) => MULTI_PV,
+            SetOpt::ShowCurrLine(_) => SHOW_CURR_LINE,
+            SetOpt::ShowRefutations(_) => SHOW_REFUTATIONS,
+            SetOpt::LimitStrength(_) => LIMIT_STRENGTH,
+            SetOpt::Elo(_) => ELO,
+            SetOpt::AnalysisMode(_) => ANALYSIS_MODE,
+            SetOpt::ShredderBasesPath(_) => SHREDDER_BASES_PATH,
+            SetOpt::Opp(_) => OPPONENT,
+            SetOpt::SetPosVal(_) => SET_POSITION_VALUE,
+            SetOpt::Variant(_) => VARIANT,
+            SetOpt::Custom { ref name, .. } => name,
+        }
+    }
+}
+
+impl Display for SetOpt {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(formatter, "setoption name ")?;
+        match self {
+            SetOpt::Hash(v) => write!(formatter, "{} value {}", HASH, v),
+            SetOpt::NalimovPath(v) => write!(formatter, "{} value {}", NALIMOV_PATH, v.display()),
+            SetOpt::NalimovCache(v) => write!(formatter, "{} value {}", NALIMOV_CACHE, v),
+            SetOpt::Ponder(v) => write!(formatter, "{} value {}", PONDER, v),
+            SetOpt::OwnBook(v) => write!(formatter, "{} value {}", OWN_BOOK, v),
+            SetOpt::MultiPv(v) => write!(formatter, "{} value {}", MULTI_PV, v),
+            SetOpt::ShowCurrLine(v) => write!(formatter, "{} value {}", SHOW_CURR_LINE, v),
+            SetOpt::ShowRefutations(v) => write!(formatter, "{} value {}", SHOW_REFUTATIONS, v),
+            SetOpt::LimitStrength(v) => write!(formatter, "{} value {}", LIMIT_STRENGTH, v),
+            SetOpt::Elo(v) => write!(formatter, "{} value {}", ELO, v),
+            SetOpt::AnalysisMode(v) => write!(formatter, "{} value {}", ANALYSIS_MODE, v),
+            SetOpt::ShredderBasesPath(v) => {
+                write!(formatter, "{} value {}", SHREDDER_BASES_PATH, v.display())
+            }
+            SetOpt::Opp(v) => write!(formatter, "{} value {}", OPPONENT, v),
+            SetOpt::SetPosVal(v) => write!(formatter, "{} value {}", SET_POSITION_VALUE, v),
+            SetOpt::Variant(v) => write!(formatter, "{} value {}", VARIANT, v),
+            SetOpt::Custom { name, value: None } => formatter.write_str(name),
+            SetOpt::Custom {
+                name,
+                value: Some(value),
+            } => write!(formatter, "{} value {}", name, value),
+        }
+    }
 }
 
 impl TryFrom<&[&str]> for SetOpt {
     type Error = UziErr;
     fn try_from(cmd: &[&str]) -> Result<Self, Self::Error> {
         let mut parse_state = SetOptParseState::Begin;
-        for (i, word) in cmd.into_iter().enumerate() {
+        for (i, word) in cmd.iter().enumerate() {
             match *word {
                 "setoption" if parse_state.is_begin() => parse_state = SetOptParseState::SetOpt,
                 "name" if parse_state.is_setopt() => parse_state = SetOptParseState::Name,
                 "value" if parse_state.is_val() => continue,
                 _ => match parse_state {
                     SetOptParseState::Name => {
-                        let opt = UziOpt::from_str(*word)?;
+                        let opt = UziOpt::from_str(word)?;
                         parse_state = SetOptParseState::Value(opt);
                     }
                     SetOptParseState::Value(opt) => return parse_value(opt, &cmd[i..]),
@@ -170,6 +336,7 @@ fn parse_value(opt: UziOpt, cmd: &[&str]) -> Result<SetOpt, UziErr> {
         }
         UziOpt::Opponent => Ok(SetOpt::Opp(Opponent::try_from(cmd)?)),
         UziOpt::SetPosVal => Ok(SetOpt::SetPosVal(PosValueOpt::try_from(cmd)?)),
+        UziOpt::Variant => Ok(SetOpt::Variant(Variant::from_str(word)?)),
     }
 }
 
@@ -216,6 +383,7 @@ pub enum UziOpt {
     About,
     ShredderBasesPath,
     SetPosVal,
+    Variant,
 }
 
 impl FromStr for UziOpt {
@@ -238,6 +406,7 @@ impl FromStr for UziOpt {
             OPPONENT => Ok(UziOpt::Opponent),
             SHREDDER_BASES_PATH => Ok(UziOpt::ShredderBasesPath),
             SET_POSITION_VALUE => Ok(UziOpt::SetPosVal),
+            VARIANT => Ok(UziOpt::Variant),
             _ => Err(UziErr::UnknownOpt),
         }
     }
@@ -276,7 +445,8 @@ impl Iterator for UziOptIter {
                 UziOpt::Opponent => Some(UziOpt::About),
                 UziOpt::About => Some(UziOpt::ShredderBasesPath),
                 UziOpt::ShredderBasesPath => Some(UziOpt::SetPosVal),
-                UziOpt::SetPosVal => None,
+                UziOpt::SetPosVal => Some(UziOpt::Variant),
+                UziOpt::Variant => None,
             },
         };
         val
@@ -338,7 +508,7 @@ impl TryFrom<&[&str]> for Opponent {
 
         let mut opp = Opponent::default();
 
-        for (i, word) in opts.into_iter().enumerate() {
+        for (i, word) in opts.iter().enumerate() {
             match i {
                 0 => opp.title = Title::from_str(word)?,
                 1 if *word == "none" => continue,
@@ -357,6 +527,7 @@ impl TryFrom<&[&str]> for Opponent {
 }
 
 // Represents the title of the player, e.g. grand master.
+#[allow(clippy::upper_case_acronyms, clippy::enum_variant_names)]
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Title {
     GM,
@@ -436,6 +607,68 @@ impl FromStr for PlayerType {
     }
 }
 
+// A variant of UCI_Variant, as named by Fairy-Stockfish. Any other name, e.g.
+// shogi or xiangqi, is kept as it is.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Variant {
+    Chess,
+    Crazyhouse,
+    Atomic,
+    ThreeCheck,
+    KingOfTheHill,
+    Antichess,
+    Horde,
+    RacingKings,
+    Other(String),
+}
+
+impl Variant {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Variant::Chess => "chess",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::Atomic => "atomic",
+            Variant::ThreeCheck => "3check",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::Antichess => "antichess",
+            Variant::Horde => "horde",
+            Variant::RacingKings => "racingkings",
+            Variant::Other(name) => name,
+        }
+    }
+
+    // Returns true for standard chess, the only variant whose positions and
+    // moves Board can check.
+    pub fn is_chess(&self) -> bool {
+        matches!(self, Variant::Chess)
+    }
+}
+
+impl Display for Variant {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Variant {
+    type Err = UziErr;
+
+    fn from_str(buf: &str) -> Result<Self, Self::Err> {
+        match buf {
+            "" => Err(UziErr::BadOptValue),
+            "chess" => Ok(Variant::Chess),
+            "crazyhouse" => Ok(Variant::Crazyhouse),
+            "atomic" => Ok(Variant::Atomic),
+            "3check" => Ok(Variant::ThreeCheck),
+            "kingofthehill" => Ok(Variant::KingOfTheHill),
+            "antichess" => Ok(Variant::Antichess),
+            "horde" => Ok(Variant::Horde),
+            "racingkings" => Ok(Variant::RacingKings),
+            name => Ok(Variant::Other(name.to_string())),
+        }
+    }
+}
+
 // Represents the different values that can be used for UCI_SetPositionValue.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum PosValueOpt {
@@ -465,7 +698,7 @@ impl TryFrom<&[&str]> for PosValueOpt {
             "clear" => Ok(PosValueOpt::Clear(fen)),
             _ => Ok(PosValueOpt::Val {
                 val: to_number::<i32>(opts[0]).map_err(|_| UziErr::BadPositionVal)?,
-                fen: fen,
+                fen,
             }),
         }
     }
@@ -492,10 +725,10 @@ const HASH: &str = "Hash";
 const NALIMOV_PATH: &str = "NalimovPath";
 const NALIMOV_CACHE: &str = "NalimovCache";
 const OWN_BOOK: &str = "OwnBook";
-const MULTI_PV: &str = "MultiPv";
+const MULTI_PV: &str = "MultiPV";
 const PONDER: &str = "Ponder";
 const ABOUT: &str = "UCI_EngineAbout";
-const SHOW_CURR_LINE: &str = &"UCI_ShowCurrLine";
+const SHOW_CURR_LINE: &str = "UCI_ShowCurrLine";
 const SHOW_REFUTATIONS: &str = "UCI_ShowRefutations";
 const LIMIT_STRENGTH: &str = "UCI_LimitStrength";
 const ELO: &str = "UCI_Elo";
@@ -503,6 +736,7 @@ const ANALYSIS_MODE: &str = "UCI_AnalysisMode";
 const OPPONENT: &str = "UCI_Opponent";
 const SHREDDER_BASES_PATH: &str = "UCI_ShredderbasesPath";
 const SET_POSITION_VALUE: &str = "UCI_SetPositionValue";
+const VARIANT: &str = "UCI_Variant";
 
 #[cfg(test)]
 mod tests {
@@ -510,6 +744,127 @@ mod tests {
 
     const FEN_STR: &str = "8/5k2/3p4/1p1Pp2p/pP2Pp1P/P4P1K/8/8 b - - 40 50";
 
+    #[test]
+    fn has_opt_try_from_standard_decl() {
+        let words = "option name Hash type spin default 16 min 1 max 33554432"
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        let opt = HasOpt::Hash(SpinType {
+            default: 16,
+            min: 1,
+            max: 33554432,
+        });
+        assert_eq!(HasOpt::try_from(words.as_slice()), Ok(opt.clone()));
+        assert_eq!(
+            opt.to_string().as_str(),
+            "option name Hash type spin default 16 min 1 max 33554432"
+        );
+
+        let words = "option name Ponder type check default false"
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            HasOpt::try_from(words.as_slice()),
+            Ok(HasOpt::Ponder(CheckType(false)))
+        );
+    }
+
+    #[test]
+    fn has_opt_try_from_custom_decl() {
+        let words = "option name Skill Level type spin default 20 min 0 max 20"
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        let opt = HasOpt::Custom {
+            name: "Skill Level".into(),
+            kind: OptKind::Spin(SpinType {
+                default: 20,
+                min: 0,
+                max: 20,
+            }),
+        };
+        assert_eq!(HasOpt::try_from(words.as_slice()), Ok(opt.clone()));
+        assert_eq!(opt.name(), "Skill Level");
+
+        // A standard option with the wrong type is kept as a custom option.
+        let words = "option name Hash type check default true"
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            HasOpt::try_from(words.as_slice()),
+            Ok(HasOpt::Custom {
+                name: HASH.into(),
+                kind: OptKind::Check(CheckType(true)),
+            })
+        );
+    }
+
+    #[test]
+    fn has_opt_try_from_variant_decl() {
+        let line =
+            "option name UCI_Variant type combo default chess var chess var 3check var shogi";
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let opt = HasOpt::try_from(words.as_slice()).unwrap();
+        assert_eq!(opt.to_string(), line);
+        assert_eq!(
+            opt.variants(),
+            Some(vec![
+                Variant::Chess,
+                Variant::ThreeCheck,
+                Variant::Other("shogi".into())
+            ])
+        );
+        assert_eq!(HasOpt::About(StrType("uzi".into())).variants(), None);
+    }
+
+    #[test]
+    fn has_opt_try_from_bad_decl() {
+        let words = ["option", "name", "type", "button"];
+        assert_eq!(HasOpt::try_from(&words[..]), Err(UziErr::BadOptDecl));
+        let words = ["option", "name", "Clear", "Hash"];
+        assert_eq!(HasOpt::try_from(&words[..]), Err(UziErr::BadOptDecl));
+    }
+
+    #[test]
+    fn set_opt_display_round_trip() {
+        let opts = [
+            SetOpt::Hash(128),
+            SetOpt::Ponder(true),
+            SetOpt::Elo(1500),
+            SetOpt::NalimovPath(PathBuf::from_str("some/path").unwrap()),
+            SetOpt::SetPosVal(PosValueOpt::Val {
+                val: 100,
+                fen: FEN_STR.into(),
+            }),
+            SetOpt::Opp(Opponent {
+                title: Title::GM,
+                elo: Some(2800),
+                player_type: PlayerType::Human,
+                name: "oserr".into(),
+            }),
+            SetOpt::Variant(Variant::ThreeCheck),
+            SetOpt::Variant(Variant::Other("xiangqi".into())),
+        ];
+        for opt in opts {
+            let line = opt.to_string();
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            assert_eq!(SetOpt::try_from(words.as_slice()), Ok(opt));
+        }
+    }
+
+    #[test]
+    fn set_opt_display_custom() {
+        let opt = SetOpt::Custom {
+            name: "Clear Hash".into(),
+            value: None,
+        };
+        assert_eq!(opt.to_string().as_str(), "setoption name Clear Hash");
+        let opt = SetOpt::Custom {
+            name: "Threads".into(),
+            value: Some("4".into()),
+        };
+        assert_eq!(opt.to_string().as_str(), "setoption name Threads value 4");
+    }
+
     #[test]
     fn try_from_returns_err_missing_options() {
         let mut opts = Vec::new();
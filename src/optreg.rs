@@ -0,0 +1,235 @@
+// This module contains OptionRegistry, which an engine uses to declare the
+// options it supports, and to validate the values set by the GUI.
+
+use crate::conv::{to_bool, to_number};
+use crate::err::UziErr;
+use crate::opt::HasOpt;
+use crate::types::{ButtonType, CheckType, ComboType, OptKind, SpinType, StrType};
+
+// The options declared by an engine, in the order they are sent to the GUI.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OptionRegistry {
+    opts: Vec<(String, OptKind)>,
+}
+
+// A value set by the GUI, checked against the declared type of the option.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OptValue {
+    Check(bool),
+    Spin(i64),
+    // One of the declared vars, spelled as declared.
+    Combo(String),
+    Button,
+    Str(String),
+}
+
+impl OptionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Declares an option. An option with the same name replaces the previous
+    // declaration.
+    pub fn add(&mut self, name: &str, kind: OptKind) -> &mut Self {
+        match self.find_index(name) {
+            Some(i) => self.opts[i] = (name.into(), kind),
+            None => self.opts.push((name.into(), kind)),
+        }
+        self
+    }
+
+    pub fn add_check(&mut self, name: &str, default: bool) -> &mut Self {
+        self.add(name, OptKind::Check(CheckType(default)))
+    }
+
+    pub fn add_spin(&mut self, name: &str, default: i64, min: i64, max: i64) -> &mut Self {
+        self.add(name, OptKind::Spin(SpinType { default, min, max }))
+    }
+
+    pub fn add_combo(&mut self, name: &str, default: &str, vars: &[&str]) -> &mut Self {
+        self.add(
+            name,
+            OptKind::Combo(ComboType {
+                default: default.into(),
+                var: vars.iter().map(|var| var.to_string()).collect(),
+            }),
+        )
+    }
+
+    pub fn add_button(&mut self, name: &str) -> &mut Self {
+        self.add(name, OptKind::Button(ButtonType))
+    }
+
+    pub fn add_string(&mut self, name: &str, default: &str) -> &mut Self {
+        self.add(name, OptKind::Str(StrType(default.into())))
+    }
+
+    // Returns the declarations to send to the GUI after uci.
+    pub fn iter(&self) -> impl Iterator<Item = HasOpt> + '_ {
+        self.opts
+            .iter()
+            .map(|(name, kind)| HasOpt::from_decl(name.clone(), kind.clone()))
+    }
+
+    // Parses a setoption command, i.e.
+    // setoption name <id> [value <x>]
+    // and checks the value against the declared option. Returns the name of the
+    // option as declared, together with the value. Names are not case
+    // sensitive.
+    pub fn parse_set(&self, words: &[&str]) -> Result<(&str, OptValue), UziErr> {
+        if words.len() < 3 || words[0] != "setoption" || words[1] != "name" {
+            return Err(UziErr::SetOptErr);
+        }
+        let value_index = words.iter().position(|word| *word == "value");
+        let name_end = value_index.unwrap_or(words.len());
+        if name_end <= 2 {
+            return Err(UziErr::SetOptErr);
+        }
+        let name = words[2..name_end].join(" ");
+        let value = value_index.map(|i| words[i + 1..].join(" "));
+
+        let (name, kind) = self
+            .find_index(&name)
+            .map(|i| &self.opts[i])
+            .ok_or(UziErr::UnknownOpt)?;
+        Ok((name, check_value(kind, value)?))
+    }
+
+    fn find_index(&self, name: &str) -> Option<usize> {
+        self.opts
+            .iter()
+            .position(|(opt_name, _)| opt_name.eq_ignore_ascii_case(name))
+    }
+}
+
+// Checks the value sent by the GUI against the type of the option.
+fn check_value(kind: &OptKind, value: Option<String>) -> Result<OptValue, UziErr> {
+    let bad_value = |_| UziErr::BadOptValue;
+    match (kind, value) {
+        (OptKind::Button(_), None) => Ok(OptValue::Button),
+        // The UCI spec uses <empty> for an empty string.
+        (OptKind::Str(_), None) => Ok(OptValue::Str(String::new())),
+        (OptKind::Str(_), Some(value)) if value == "<empty>" => Ok(OptValue::Str(String::new())),
+        (OptKind::Str(_), Some(value)) => Ok(OptValue::Str(value)),
+        (OptKind::Check(_), Some(value)) => {
+            Ok(OptValue::Check(to_bool(&value).map_err(bad_value)?))
+        }
+        (OptKind::Spin(spin), Some(value)) => {
+            let value = to_number::<i64>(&value).map_err(bad_value)?;
+            if value < spin.min || value > spin.max {
+                return Err(UziErr::BadOptValue);
+            }
+            Ok(OptValue::Spin(value))
+        }
+        (OptKind::Combo(combo), Some(value)) => combo
+            .var
+            .iter()
+            .find(|var| var.eq_ignore_ascii_case(&value))
+            .map(|var| OptValue::Combo(var.clone()))
+            .ok_or(UziErr::BadOptValue),
+        _ => Err(UziErr::BadOptValue),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> OptionRegistry {
+        let mut options = OptionRegistry::new();
+        options
+            .add_spin("Hash", 16, 1, 1024)
+            .add_spin("Threads", 1, 1, 64)
+            .add_check("Ponder", false)
+            .add_combo("Style", "Normal", &["Solid", "Normal", "Risky"])
+            .add_button("Clear Hash")
+            .add_string("Book File", "book.bin");
+        options
+    }
+
+    fn parse(options: &OptionRegistry, cmd: &str) -> Result<(String, OptValue), UziErr> {
+        let words: Vec<&str> = cmd.split_whitespace().collect();
+        options
+            .parse_set(&words)
+            .map(|(name, value)| (name.to_string(), value))
+    }
+
+    #[test]
+    fn registry_declarations() {
+        let decls: Vec<String> = registry().iter().map(|opt| opt.to_string()).collect();
+        assert_eq!(
+            decls,
+            vec![
+                "option name Hash type spin default 16 min 1 max 1024",
+                "option name Threads type spin default 1 min 1 max 64",
+                "option name Ponder type check default false",
+                "option name Style type combo default Normal var Solid var Normal var Risky",
+                "option name Clear Hash type button",
+                "option name Book File type string default book.bin",
+            ]
+        );
+        assert_eq!(
+            registry().iter().next(),
+            Some(HasOpt::Hash(SpinType {
+                default: 16,
+                min: 1,
+                max: 1024
+            }))
+        );
+    }
+
+    #[test]
+    fn registry_parses_values() {
+        let options = registry();
+        assert_eq!(
+            parse(&options, "setoption name threads value 4"),
+            Ok(("Threads".into(), OptValue::Spin(4)))
+        );
+        assert_eq!(
+            parse(&options, "setoption name Ponder value true"),
+            Ok(("Ponder".into(), OptValue::Check(true)))
+        );
+        assert_eq!(
+            parse(&options, "setoption name Style value risky"),
+            Ok(("Style".into(), OptValue::Combo("Risky".into())))
+        );
+        assert_eq!(
+            parse(&options, "setoption name Clear Hash"),
+            Ok(("Clear Hash".into(), OptValue::Button))
+        );
+        assert_eq!(
+            parse(&options, "setoption name Book File value my book.bin"),
+            Ok(("Book File".into(), OptValue::Str("my book.bin".into())))
+        );
+        assert_eq!(
+            parse(&options, "setoption name Book File value <empty>"),
+            Ok(("Book File".into(), OptValue::Str(String::new())))
+        );
+    }
+
+    #[test]
+    fn registry_rejects_bad_values() {
+        let options = registry();
+        assert_eq!(
+            parse(&options, "setoption name Threads value 65"),
+            Err(UziErr::BadOptValue)
+        );
+        assert_eq!(
+            parse(&options, "setoption name Threads value many"),
+            Err(UziErr::BadOptValue)
+        );
+        assert_eq!(
+            parse(&options, "setoption name Threads"),
+            Err(UziErr::BadOptValue)
+        );
+        assert_eq!(
+            parse(&options, "setoption name Style value Wild"),
+            Err(UziErr::BadOptValue)
+        );
+        assert_eq!(
+            parse(&options, "setoption name Contempt value 10"),
+            Err(UziErr::UnknownOpt)
+        );
+        assert_eq!(parse(&options, "setoption name"), Err(UziErr::SetOptErr));
+    }
+}
@@ -0,0 +1,324 @@
+// This module writes the games of EngineMatch as PGN, with the standard tags,
+// the moves in SAN and, after every move, a comment with the evaluation of the
+// engine and its time, as cutechess does, e.g. {+0.35/18 2.1s}. The comment
+// also has the clock of the engine after the move as [%clk 0:04:58]. The last
+// comment says why the game ended, e.g. {White loses on time}.
+
+use crate::board::{Board, START_FEN};
+use crate::engcmd::Score;
+use crate::engmatch::{DrawReason, GameRecord, GameResult, MoveStats, WinReason};
+use std::fmt::Write;
+use std::time::Duration;
+
+// The lines of the movetext are at most this long.
+const LINE_LEN: usize = 80;
+
+// The tags of a game that aren't known from the GameRecord. They default to
+// "?", or "????.??.??" for the date, which is what PGN uses for unknown tags.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PgnTags {
+    event: String,
+    site: String,
+    date: String,
+    round: String,
+    white: String,
+    black: String,
+}
+
+impl Default for PgnTags {
+    fn default() -> Self {
+        Self {
+            event: "?".to_string(),
+            site: "?".to_string(),
+            date: "????.??.??".to_string(),
+            round: "?".to_string(),
+            white: "?".to_string(),
+            black: "?".to_string(),
+        }
+    }
+}
+
+impl PgnTags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_event(&mut self, event: &str) -> &mut Self {
+        self.event = event.to_string();
+        self
+    }
+
+    pub fn set_site(&mut self, site: &str) -> &mut Self {
+        self.site = site.to_string();
+        self
+    }
+
+    // Sets the date, which should be in the form YYYY.MM.DD.
+    pub fn set_date(&mut self, date: &str) -> &mut Self {
+        self.date = date.to_string();
+        self
+    }
+
+    pub fn set_round(&mut self, round: &str) -> &mut Self {
+        self.round = round.to_string();
+        self
+    }
+
+    // Sets the names of the engines, e.g. from their id name.
+    pub fn set_white(&mut self, white: &str) -> &mut Self {
+        self.white = white.to_string();
+        self
+    }
+
+    pub fn set_black(&mut self, black: &str) -> &mut Self {
+        self.black = black.to_string();
+        self
+    }
+}
+
+// Returns the game as PGN, ending with a newline. Returns None if the start
+// position of the game is not valid, which can't happen for a game that
+// EngineMatch played.
+pub fn to_pgn(game: &GameRecord, tags: &PgnTags) -> Option<String> {
+    let mut board = Board::from_pos(&game.start)?;
+    let result = game.result.as_str();
+    let mut pgn = String::new();
+    let mut tag = |name: &str, value: &str| {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = writeln!(pgn, "[{} \"{}\"]", name, value);
+    };
+    tag("Event", &tags.event);
+    tag("Site", &tags.site);
+    tag("Date", &tags.date);
+    tag("Round", &tags.round);
+    tag("White", &tags.white);
+    tag("Black", &tags.black);
+    tag("Result", result);
+    let fen = board.to_fen();
+    if fen != START_FEN {
+        tag("SetUp", "1");
+        tag("FEN", &fen);
+    }
+    // With time odds, the sides have their own tags, as cutechess does.
+    let (white_tc, black_tc) = (game.white_time_control, game.black_time_control);
+    if white_tc == black_tc {
+        tag("TimeControl", &time_control(white_tc.base, white_tc.inc));
+    } else {
+        tag(
+            "WhiteTimeControl",
+            &time_control(white_tc.base, white_tc.inc),
+        );
+        tag(
+            "BlackTimeControl",
+            &time_control(black_tc.base, black_tc.inc),
+        );
+    }
+    tag("Termination", termination(&game.result));
+    pgn.push('\n');
+
+    // The tokens of the movetext, which are not split across lines. A move
+    // and its number are one token.
+    let mut tokens = Vec::new();
+    // Whether the last token is the move of white, so that the next move
+    // doesn't need its number.
+    let mut after_white = false;
+    for (i, &pm) in game.moves.iter().enumerate() {
+        let san = board.san(pm);
+        tokens.push(if board.is_white_to_move() {
+            format!("{}. {}", board.fullmove(), san)
+        } else if after_white {
+            san
+        } else {
+            format!("{}... {}", board.fullmove(), san)
+        });
+        after_white = board.is_white_to_move();
+        if let Some(stats) = game.stats.get(i) {
+            tokens.push(comment(stats));
+            after_white = false;
+        }
+        board.play(pm);
+    }
+    tokens.push(format!("{{{}}}", game.result.reason()));
+    tokens.push(result.to_string());
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_LEN {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    pgn.push_str(&line);
+    pgn.push('\n');
+    Some(pgn)
+}
+
+// Returns the comment of a move, e.g. {+0.35/18 2.1s [%clk 0:04:58]}. Without
+// a score, the evaluation is left out.
+fn comment(stats: &MoveStats) -> String {
+    let mut comment = String::from("{");
+    if let Some(score) = stats.score.as_ref().and_then(eval) {
+        comment.push_str(&score);
+        if let Some(depth) = stats.depth {
+            let _ = write!(comment, "/{}", depth);
+        }
+        comment.push(' ');
+    }
+    let secs = stats.clock.as_secs();
+    let _ = write!(
+        comment,
+        "{:.1}s [%clk {}:{:02}:{:02}]}}",
+        stats.time.as_secs_f64(),
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    );
+    comment
+}
+
+// Returns a score in pawns, e.g. +0.35, or as a mate in moves, e.g. +M5 or
+// -M3, from the point of view of the engine that played the move.
+fn eval(score: &Score) -> Option<String> {
+    if let Some(mate) = score.mate() {
+        let sign = if mate < 0 { '-' } else { '+' };
+        return Some(format!("{}M{}", sign, mate.unsigned_abs()));
+    }
+    score
+        .cp()
+        .map(|cp| format!("{:+.2}", f64::from(cp) / 100.0))
+}
+
+// Returns the time control as in the TimeControl tag, i.e. the base and the
+// increment in seconds, e.g. 300+2 or 0.5+0.05.
+fn time_control(base: Duration, inc: Duration) -> String {
+    if inc.is_zero() {
+        secs(base)
+    } else {
+        format!("{}+{}", secs(base), secs(inc))
+    }
+}
+
+fn secs(duration: Duration) -> String {
+    if duration.subsec_millis() == 0 {
+        duration.as_secs().to_string()
+    } else {
+        let secs = format!("{:.3}", duration.as_secs_f64());
+        secs.trim_end_matches('0').to_string()
+    }
+}
+
+// Returns the Termination tag of a result.
+fn termination(result: &GameResult) -> &'static str {
+    match result {
+        GameResult::Win(_, WinReason::Checkmate) => "normal",
+        GameResult::Win(_, WinReason::Timeout) => "time forfeit",
+        GameResult::Win(_, WinReason::IllegalMove(_)) => "rules infraction",
+        GameResult::Win(_, WinReason::EngineFailed(_)) => "abandoned",
+        GameResult::Win(_, WinReason::Resignation | WinReason::Adjudicator) => "adjudication",
+        GameResult::Draw(
+            DrawReason::Stalemate
+            | DrawReason::FiftyMoves
+            | DrawReason::Repetition
+            | DrawReason::InsufficientMaterial,
+        ) => "normal",
+        GameResult::Draw(
+            DrawReason::MaxPlies | DrawReason::Adjudication | DrawReason::Adjudicator,
+        ) => "adjudication",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engcmd::Info;
+    use crate::engmatch::{Side, TimeControl};
+    use crate::guicmd::Pos;
+    use crate::pm::Pm;
+    use std::str::FromStr;
+
+    fn stats(score: &[&str], depth: u16, time: u64, clock: u64) -> MoveStats {
+        let mut info = vec!["info", "score"];
+        info.extend(score);
+        MoveStats {
+            score: Info::try_from(info.as_slice()).unwrap().score(),
+            depth: Some(depth),
+            time: Duration::from_millis(time),
+            clock: Duration::from_millis(clock),
+        }
+    }
+
+    fn game(start: Pos, moves: &[&str], stats: Vec<MoveStats>, result: GameResult) -> GameRecord {
+        GameRecord {
+            start,
+            white_time_control: TimeControl::new(Duration::from_secs(300), Duration::from_secs(2)),
+            black_time_control: TimeControl::new(Duration::from_secs(300), Duration::from_secs(2)),
+            moves: moves.iter().map(|pm| Pm::from_str(pm).unwrap()).collect(),
+            stats,
+            result,
+            white_time: Duration::ZERO,
+            black_time: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn pgn_fools_mate() {
+        let moves = ["f2f3", "e7e5", "g2g4", "d8h4"];
+        let stats = vec![
+            stats(&["cp", "-35"], 18, 2100, 299_900),
+            stats(&["cp", "40"], 17, 1000, 301_000),
+            stats(&["mate", "-1"], 20, 500, 301_400),
+            stats(&["mate", "1"], 1, 0, 303_000),
+        ];
+        let game = game(
+            Pos::new(),
+            &moves,
+            stats,
+            GameResult::Win(Side::Black, WinReason::Checkmate),
+        );
+        let mut tags = PgnTags::new();
+        tags.set_white("A").set_black("B \"2\"");
+        assert_eq!(
+            to_pgn(&game, &tags).unwrap(),
+            "[Event \"?\"]\n\
+             [Site \"?\"]\n\
+             [Date \"????.??.??\"]\n\
+             [Round \"?\"]\n\
+             [White \"A\"]\n\
+             [Black \"B \\\"2\\\"\"]\n\
+             [Result \"0-1\"]\n\
+             [TimeControl \"300+2\"]\n\
+             [Termination \"normal\"]\n\
+             \n\
+             1. f3 {-0.35/18 2.1s [%clk 0:04:59]} 1... e5 {+0.40/17 1.0s [%clk 0:05:01]}\n\
+             2. g4 {-M1/20 0.5s [%clk 0:05:01]} 2... Qh4# {+M1/1 0.0s [%clk 0:05:03]}\n\
+             {Black mates} 0-1\n"
+        );
+    }
+
+    #[test]
+    fn pgn_from_fen_without_stats() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 b Q - 0 30";
+        let mut game = game(
+            Pos::with_fen(fen),
+            &["e8d7", "e1c1"],
+            Vec::new(),
+            GameResult::Draw(DrawReason::MaxPlies),
+        );
+        let pgn = to_pgn(&game, &PgnTags::new()).unwrap();
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/8/R3K3 b Q - 0 30\"]\n"));
+        assert!(pgn.contains("[Termination \"adjudication\"]"));
+        game.black_time_control = game.white_time_control.scaled(0.5);
+        let pgn = to_pgn(&game, &PgnTags::new()).unwrap();
+        assert!(pgn.contains("[WhiteTimeControl \"300+2\"]\n[BlackTimeControl \"150+1\"]\n"));
+        assert!(pgn.ends_with("\n\n30... Kd7 31. O-O-O+ {Draw by move limit} 1/2-1/2\n"));
+        assert_eq!(
+            time_control(Duration::from_millis(500), Duration::ZERO),
+            "0.5"
+        );
+    }
+}
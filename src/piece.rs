@@ -27,8 +27,8 @@ impl Piece {
         }
     }
 
-    pub fn to_u8(&self) -> u8 {
-        match *self {
+    pub fn to_u8(self) -> u8 {
+        match self {
             Piece::King => b'k',
             Piece::Queen => b'q',
             Piece::Rook => b'r',
@@ -38,8 +38,8 @@ impl Piece {
         }
     }
 
-    pub fn to_char(&self) -> char {
-        match *self {
+    pub fn to_char(self) -> char {
+        match self {
             Piece::King => 'k',
             Piece::Queen => 'q',
             Piece::Rook => 'r',
@@ -140,7 +140,7 @@ mod tests {
             ("p", Piece::Pawn),
         ];
         for (s, p) in &str_to_piece {
-            assert_eq!(Piece::from_str(*s), Ok(*p));
+            assert_eq!(Piece::from_str(s), Ok(*p));
             assert_eq!(p.as_str(), *s);
         }
     }
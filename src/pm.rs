@@ -1,5 +1,6 @@
 // This module contains the utilities and types for representing UCI moves.
 
+use crate::conv::Words;
 use crate::err::UziErr;
 use crate::piece::Piece;
 use crate::sq::Sq;
@@ -17,16 +18,49 @@ pub enum Pm {
 
     // A pawn promotion move.
     Promo { from: Sq, to: Sq, promo: Piece },
+
+    // A piece dropped from the hand on a square, e.g. P@e4, in variants like
+    // crazyhouse. The piece is written in uppercase for both sides.
+    Drop { piece: Piece, to: Sq },
 }
 
+// Moves are written from a buffer in one go rather than with write!, since
+// every pv and every position is mostly moves.
 impl Display for Pm {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
-        match self {
-            Pm::Null => formatter.write_str("0000"),
-            Pm::Normal { from, to } => write!(formatter, "{}{}", from, to),
-            Pm::Promo { from, to, promo } => write!(formatter, "{}{}{}", from, to, promo),
-        }
+        let mut buf = [0; 5];
+        let len = match *self {
+            Pm::Null => return formatter.write_str("0000"),
+            Pm::Normal { from, to } => {
+                buf[..2].copy_from_slice(from.as_str().as_bytes());
+                buf[2..4].copy_from_slice(to.as_str().as_bytes());
+                4
+            }
+            Pm::Promo { from, to, promo } => {
+                buf[..2].copy_from_slice(from.as_str().as_bytes());
+                buf[2..4].copy_from_slice(to.as_str().as_bytes());
+                buf[4] = promo.to_char() as u8;
+                5
+            }
+            Pm::Drop { piece, to } => {
+                buf[0] = piece.to_char().to_ascii_uppercase() as u8;
+                buf[1] = b'@';
+                buf[2..4].copy_from_slice(to.as_str().as_bytes());
+                4
+            }
+        };
+        // The buffer only holds ASCII.
+        formatter.write_str(std::str::from_utf8(&buf[..len]).unwrap_or_default())
+    }
+}
+
+// Writes moves each after a space, e.g. the pv of an info.
+pub(crate) fn write_moves(formatter: &mut Formatter<'_>, moves: &[Pm]) -> fmt::Result {
+    for pm in moves {
+        formatter.write_str(" ")?;
+        Display::fmt(pm, formatter)?;
     }
+    Ok(())
 }
 
 impl TryFrom<&[u8]> for Pm {
@@ -34,6 +68,13 @@ impl TryFrom<&[u8]> for Pm {
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
         match bytes.len() {
+            4 if bytes[1] == b'@' => match Piece::try_from(bytes[0].to_ascii_lowercase()) {
+                Ok(Piece::King) | Err(_) => Err(UziErr::ParseMoveErr),
+                Ok(piece) => Ok(Pm::Drop {
+                    piece,
+                    to: Sq::try_from(&bytes[2..])?,
+                }),
+            },
             4 if bytes[0] != b'0' => Ok(Pm::Normal {
                 from: Sq::try_from(&bytes[..2])?,
                 to: Sq::try_from(&bytes[2..])?,
@@ -57,20 +98,82 @@ impl FromStr for Pm {
     }
 }
 
+// The moves of an info, e.g. the pv. With the smallvec feature, lines of up to
+// 8 moves, which most are during fast searches, are stored inline rather than
+// on the heap.
+#[cfg(feature = "smallvec")]
+pub type MoveList = smallvec::SmallVec<[Pm; 8]>;
+#[cfg(not(feature = "smallvec"))]
+pub type MoveList = Vec<Pm>;
+
+// Moves borrowed from the line they were parsed from, e.g. the pv of an
+// InfoRef. They are checked when parsed, and only turned into Pm when read.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct MovesRef<'a>(&'a str);
+
+impl<'a> MovesRef<'a> {
+    // Reads moves until a word that is not a move, which is left unread.
+    pub(crate) fn take(words: &mut Words<'a>) -> Self {
+        Self::take_with(words, |_| {})
+    }
+
+    // Like take, handing each move to add as it is read.
+    pub(crate) fn take_with(words: &mut Words<'a>, mut add: impl FnMut(Pm)) -> Self {
+        let start = *words;
+        loop {
+            // Each word is only split off once, rather than peeked and then
+            // read.
+            let before = *words;
+            match words.next().map(Pm::from_str) {
+                Some(Ok(pm)) => add(pm),
+                _ => {
+                    *words = before;
+                    return MovesRef(words.since(start));
+                }
+            }
+        }
+    }
+
+    // The moves as sent, e.g. "e2e4 e7e5".
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Pm> + 'a {
+        self.0
+            .split_whitespace()
+            .filter_map(|word| Pm::from_str(word).ok())
+    }
+
+    pub fn to_vec(self) -> Vec<Pm> {
+        self.iter().collect()
+    }
+}
+
+impl Display for MovesRef<'_> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn pm_from_null_move() {
-        let null_move = &[b'0', b'0', b'0', b'0'];
+        let null_move = b"0000";
         assert_eq!(Pm::try_from(&null_move[..]), Ok(Pm::Null));
         assert_eq!(Pm::from_str("0000"), Ok(Pm::Null));
     }
 
     #[test]
     fn pm_from_normal_move() {
-        let e2e4 = &[b'e', b'2', b'e', b'4'];
+        let e2e4 = b"e2e4";
         let pm = Pm::Normal {
             from: Sq::from((1, 4)),
             to: Sq::from((3, 4)),
@@ -82,7 +185,7 @@ mod tests {
 
     #[test]
     fn pm_from_promo_move() {
-        let promo_move = &[b'a', b'7', b'a', b'8', b'q'];
+        let promo_move = b"a7a8q";
         let pm = Pm::Promo {
             from: Sq::from((6, 0)),
             to: Sq::from((7, 0)),
@@ -92,4 +195,21 @@ mod tests {
         assert_eq!(Pm::try_from(&promo_move[..]), Ok(pm));
         assert_eq!(Pm::from_str("a7a8q"), Ok(pm));
     }
+
+    #[test]
+    fn pm_from_drop_move() {
+        let pm = Pm::Drop {
+            piece: Piece::Knight,
+            to: Sq::from((4, 3)),
+        };
+        assert_eq!(Pm::from_str("N@d5"), Ok(pm));
+        assert_eq!(pm.to_string(), "N@d5");
+        assert_eq!(
+            Pm::from_str("e7e8k").map(|pm| pm.to_string()),
+            Ok("e7e8k".into())
+        );
+        for word in ["K@d5", "X@d5", "N@d9", "N-d5"] {
+            assert!(Pm::from_str(word).is_err(), "{}", word);
+        }
+    }
 }
@@ -0,0 +1,256 @@
+// This module contains EnginePool, which manages a fixed number of identical
+// engine processes and hands them out to analysis jobs, e.g. for batch analysis
+// or for running tournaments.
+
+use crate::client::Engine;
+use crate::engproc::Spawner;
+use crate::err::UziErr;
+use crate::guicmd::{Go, Pos};
+use crate::opt::SetOpt;
+use crate::pm::Pm;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+// A pool of up to size engines. Engines are spawned lazily and reused between
+// jobs. Every new engine goes through the handshake and gets the pool options
+// before it is handed out, so engines that replace broken ones behave exactly
+// like the originals.
+#[derive(Debug)]
+pub struct EnginePool {
+    spawner: Spawner,
+    size: usize,
+
+    // The options to set on every engine, in order.
+    options: Vec<SetOpt>,
+
+    // The maximum time to wait for the engine during setup.
+    setup_timeout: Duration,
+
+    // Whether the engines send ucinewgame, see Engine::set_auto_new_game.
+    auto_new_game: bool,
+
+    // Engines that are not checked out. There are at most size engines alive,
+    // which is enforced by the permits.
+    idle: Mutex<Vec<Engine>>,
+    permits: Semaphore,
+}
+
+impl EnginePool {
+    pub fn new(spawner: Spawner, size: usize) -> Self {
+        Self {
+            spawner,
+            size,
+            options: Vec::new(),
+            setup_timeout: Duration::from_secs(10),
+            auto_new_game: true,
+            idle: Mutex::new(Vec::new()),
+            permits: Semaphore::new(size),
+        }
+    }
+
+    // Adds an option to set on every engine in the pool.
+    pub fn add_option(&mut self, opt: SetOpt) -> &mut Self {
+        self.options.push(opt);
+        self
+    }
+
+    pub fn set_setup_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.setup_timeout = timeout;
+        self
+    }
+
+    pub fn set_auto_new_game(&mut self, is_enabled: bool) -> &mut Self {
+        self.auto_new_game = is_enabled;
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Returns the number of engines that can be checked out without waiting.
+    pub fn available(&self) -> usize {
+        self.permits.available_permits()
+    }
+
+    // Checks out an engine, waiting until one is available. The engine is
+    // returned to the pool when the guard is dropped.
+    pub async fn checkout(&self) -> Result<PooledEngine<'_>, UziErr> {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|_| UziErr::EngineExited)?;
+        let idle = self.idle.lock().unwrap().pop();
+        let eng = match idle {
+            Some(eng) => eng,
+            None => self.new_engine().await?,
+        };
+        Ok(PooledEngine {
+            pool: self,
+            eng: Some(eng),
+            _permit: permit,
+        })
+    }
+
+    // Runs a job on any available engine. If the job fails, the engine is
+    // discarded, since it may be in an unknown state, and a fresh one is
+    // spawned for the next job.
+    pub async fn run<T, F>(&self, job: F) -> Result<T, UziErr>
+    where
+        F: AsyncFnOnce(&mut Engine) -> Result<T, UziErr>,
+    {
+        let mut eng = self.checkout().await?;
+        let mut result = job(&mut eng).await;
+        if result.is_ok() && eng.is_searching() {
+            // The job left a search running, finish it so that the engine can
+            // be reused.
+            if let Err(err) = finish_search(&mut eng).await {
+                result = Err(err);
+            }
+        }
+        if result.is_err() {
+            eng.discard();
+        }
+        result
+    }
+
+    // Searches the position on any available engine and returns the best move
+    // and the optional ponder move.
+    pub async fn best_move(&self, pos: &Pos, go: &Go) -> Result<(Pm, Option<Pm>), UziErr> {
+        self.run(async |eng: &mut Engine| {
+            eng.position(pos).await?;
+            eng.go(go).await?.wait().await
+        })
+        .await
+    }
+
+    // Spawns an engine and sets it up with the pool options.
+    async fn new_engine(&self) -> Result<Engine, UziErr> {
+        let mut eng = Engine::from_spawner(self.spawner.clone())?;
+        eng.set_auto_new_game(self.auto_new_game);
+        eng.uci(self.setup_timeout).await?;
+        for opt in &self.options {
+            eng.set_opt(opt.clone()).await?;
+        }
+        eng.sync(self.setup_timeout).await?;
+        Ok(eng)
+    }
+}
+
+// Stops the running search and reads its bestmove.
+async fn finish_search(eng: &mut Engine) -> Result<(), UziErr> {
+    eng.stop().await?;
+    eng.wait_best_move().await.map(|_| ())
+}
+
+// An engine checked out from the pool.
+#[derive(Debug)]
+pub struct PooledEngine<'a> {
+    pool: &'a EnginePool,
+
+    // This is only None after the engine has been discarded.
+    eng: Option<Engine>,
+
+    _permit: SemaphorePermit<'a>,
+}
+
+impl PooledEngine<'_> {
+    // Drops the engine instead of returning it to the pool, e.g. because it
+    // crashed. A new engine is spawned in its place when needed.
+    pub fn discard(&mut self) {
+        self.eng = None;
+    }
+}
+
+impl Deref for PooledEngine<'_> {
+    type Target = Engine;
+
+    fn deref(&self) -> &Engine {
+        self.eng.as_ref().expect("engine was discarded")
+    }
+}
+
+impl DerefMut for PooledEngine<'_> {
+    fn deref_mut(&mut self) -> &mut Engine {
+        self.eng.as_mut().expect("engine was discarded")
+    }
+}
+
+impl Drop for PooledEngine<'_> {
+    fn drop(&mut self) {
+        if let Some(eng) = self.eng.take() {
+            self.pool.idle.lock().unwrap().push(eng);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn fake_spawner(spawned: Arc<AtomicUsize>) -> Spawner {
+        Spawner::new(move || {
+            spawned.fetch_add(1, Ordering::SeqCst);
+            Ok(fake_engine(FAKE_ENGINE))
+        })
+    }
+
+    #[tokio::test]
+    async fn pool_reuses_engines() {
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let mut pool = EnginePool::new(fake_spawner(spawned.clone()), 2);
+        pool.add_option(SetOpt::Hash(32));
+
+        let mut go = Go::new();
+        go.set_depth(2);
+        for _ in 0..3 {
+            assert_eq!(
+                pool.best_move(&Pos::new(), &go).await,
+                Ok((
+                    Pm::from_str("e2e4").unwrap(),
+                    Some(Pm::from_str("e7e5").unwrap())
+                ))
+            );
+        }
+        assert_eq!(spawned.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.available(), 2);
+    }
+
+    #[tokio::test]
+    async fn pool_runs_jobs_concurrently() {
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let pool = EnginePool::new(fake_spawner(spawned.clone()), 2);
+
+        let eng1 = pool.checkout().await.unwrap();
+        let eng2 = pool.checkout().await.unwrap();
+        assert_eq!(eng1.name(), Some("Fake"));
+        assert_eq!(pool.available(), 0);
+        drop(eng1);
+        drop(eng2);
+
+        assert_eq!(spawned.load(Ordering::SeqCst), 2);
+        assert_eq!(pool.available(), 2);
+    }
+
+    #[tokio::test]
+    async fn pool_replaces_discarded_engines() {
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let pool = EnginePool::new(fake_spawner(spawned.clone()), 1);
+
+        let result = pool
+            .run(async |_: &mut Engine| -> Result<(), UziErr> { Err(UziErr::EngineExited) })
+            .await;
+        assert_eq!(result, Err(UziErr::EngineExited));
+
+        let eng = pool.checkout().await.unwrap();
+        assert_eq!(eng.name(), Some("Fake"));
+        assert_eq!(spawned.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,232 @@
+// This module contains EngineProfile, which describes an engine taking part in
+// a match or a tournament: how to launch it, the values of its UCI options, its
+// time odds, and the quirks of its protocol. Every process of the engine gets
+// the profile, including the ones that replace a process that crashed or hung.
+
+use crate::client::Engine;
+use crate::engproc::{Launcher, Spawner};
+use crate::err::UziErr;
+use crate::opt::SetOpt;
+use crate::pool::EnginePool;
+use crate::watchdog::RespawnPolicy;
+use std::fmt::Display;
+use std::time::Duration;
+
+// An engine with its settings. The launcher has the program, its arguments and
+// the text sent before the handshake, if any, see Launcher.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineProfile {
+    launcher: Launcher,
+    // The options to set after the handshake, in order.
+    options: Vec<SetOpt>,
+    // The factor the time control of the engine is multiplied by.
+    time_odds: f64,
+    quirks: Quirks,
+}
+
+// How an engine deviates from what the client expects by default.
+// - new_game - whether the engine gets ucinewgame before a new game. Some
+//   engines take long to clear their state, or don't handle it at all.
+// - uci_timeout - how long the handshake and the setup may take, e.g. for an
+//   engine that loads a large network when it starts.
+// - respawn_policy - the policy for respawning an engine that crashes or hangs,
+//   see Engine::set_respawn_policy. Without one, an engine that crashed is only
+//   restarted between games.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Quirks {
+    pub(crate) new_game: bool,
+    pub(crate) uci_timeout: Duration,
+    pub(crate) respawn_policy: Option<RespawnPolicy>,
+}
+
+impl Quirks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_new_game(&mut self, is_enabled: bool) -> &mut Self {
+        self.new_game = is_enabled;
+        self
+    }
+
+    pub fn set_uci_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.uci_timeout = timeout;
+        self
+    }
+
+    pub fn set_respawn_policy(&mut self, policy: RespawnPolicy) -> &mut Self {
+        self.respawn_policy = Some(policy);
+        self
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            new_game: true,
+            uci_timeout: Duration::from_secs(10),
+            respawn_policy: None,
+        }
+    }
+}
+
+impl EngineProfile {
+    pub fn new(launcher: Launcher) -> Self {
+        Self {
+            launcher,
+            options: Vec::new(),
+            time_odds: 1.0,
+            quirks: Quirks::default(),
+        }
+    }
+
+    // Adds an option to set on the engine.
+    pub fn add_option(&mut self, opt: SetOpt) -> &mut Self {
+        self.options.push(opt);
+        self
+    }
+
+    // Adds an option by name, e.g. one that SetOpt doesn't know.
+    pub fn set_option<V: Display>(&mut self, name: &str, value: V) -> &mut Self {
+        self.add_option(SetOpt::Custom {
+            name: name.into(),
+            value: Some(value.to_string()),
+        })
+    }
+
+    // Sets the factor the time control of the engine is multiplied by, e.g.
+    // 0.5 for half the time of its opponents. The default is 1.0.
+    pub fn set_time_odds(&mut self, time_odds: f64) -> &mut Self {
+        self.time_odds = time_odds;
+        self
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) -> &mut Self {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn launcher(&self) -> &Launcher {
+        &self.launcher
+    }
+
+    pub fn options(&self) -> &[SetOpt] {
+        &self.options
+    }
+
+    pub fn time_odds(&self) -> f64 {
+        self.time_odds
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    // Launches the engine, goes through the handshake and sets the options.
+    // The engine is ready when this returns. A restart of the engine, e.g.
+    // after a crash, does the same, since the engine replays the handshake and
+    // the options it has set.
+    pub async fn spawn(&self) -> Result<Engine, UziErr> {
+        let mut eng = Engine::launch(self.launcher.clone())?;
+        eng.set_auto_new_game(self.quirks.new_game);
+        if let Some(policy) = self.quirks.respawn_policy {
+            eng.set_respawn_policy(policy);
+        }
+        eng.uci(self.quirks.uci_timeout).await?;
+        for opt in &self.options {
+            eng.set_opt(opt.clone()).await?;
+        }
+        eng.sync(self.quirks.uci_timeout).await?;
+        Ok(eng)
+    }
+
+    // Returns a pool of up to size engines of the profile. The pool spawns
+    // new engines instead of respawning broken ones, so the respawn policy is
+    // not used.
+    pub fn pool(&self, size: usize) -> EnginePool {
+        let mut pool = EnginePool::new(Spawner::from_launcher(self.launcher.clone()), size);
+        pool.set_setup_timeout(self.quirks.uci_timeout)
+            .set_auto_new_game(self.quirks.new_game);
+        for opt in &self.options {
+            pool.add_option(opt.clone());
+        }
+        pool
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engcmd::EngCmd;
+    use crate::guicmd::Pos;
+
+    // Answers the handshake and echoes the other commands, so that the test
+    // can see them.
+    const SCRIPT: &str = r#"
+        while read -r line; do
+            case "$line" in
+                uci) echo "id name Echo"; echo "uciok";;
+                isready) echo "readyok";;
+                *) echo "info string $line";;
+            esac
+        done
+    "#;
+
+    async fn recv_string(eng: &mut Engine) -> String {
+        match eng.recv().await {
+            Ok(EngCmd::Info(info)) => info.to_string(),
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+    }
+
+    fn profile() -> EngineProfile {
+        let mut launcher = Launcher::new("sh");
+        launcher.add_arg("-c").add_arg(SCRIPT);
+        let mut quirks = Quirks::new();
+        quirks.set_new_game(false);
+        let mut profile = EngineProfile::new(launcher);
+        profile
+            .add_option(SetOpt::Hash(64))
+            .set_option("Threads", 2)
+            .set_time_odds(0.5)
+            .set_quirks(quirks);
+        profile
+    }
+
+    #[tokio::test]
+    async fn profile_applied_on_spawn_and_restart() {
+        let mut eng = profile().spawn().await.unwrap();
+        assert_eq!(eng.name(), Some("Echo"));
+        let options = [
+            "setoption name Hash value 64",
+            "setoption name Threads value 2",
+        ];
+        for line in options {
+            assert_eq!(recv_string(&mut eng).await, format!("info string {}", line));
+        }
+        // No ucinewgame before the position.
+        eng.position(&Pos::new()).await.unwrap();
+        assert_eq!(recv_string(&mut eng).await, "info string position startpos");
+
+        eng.restart().await.unwrap();
+        for line in options {
+            assert_eq!(recv_string(&mut eng).await, format!("info string {}", line));
+        }
+        eng.position(&Pos::new()).await.unwrap();
+        assert_eq!(recv_string(&mut eng).await, "info string position startpos");
+    }
+
+    #[tokio::test]
+    async fn profile_pool() {
+        let pool = profile().pool(1);
+        let mut eng = pool.checkout().await.unwrap();
+        eng.position(&Pos::new()).await.unwrap();
+        for line in [
+            "setoption name Hash value 64",
+            "setoption name Threads value 2",
+            "position startpos",
+        ] {
+            assert_eq!(recv_string(&mut eng).await, format!("info string {}", line));
+        }
+    }
+}
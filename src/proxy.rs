@@ -0,0 +1,342 @@
+// This module contains UciProxy, which sits between a GUI and an engine and
+// forwards the lines in both directions, so that the exchange can be watched
+// and changed, e.g. to debug a GUI and an engine that don't get along. Hooks
+// see every line before it is forwarded, and can pass it on, replace it or drop
+// it. For example, a proxy that logs what the engine sends and keeps the GUI
+// from asking for more than 4 lines:
+//
+// let mut proxy = UciProxy::spawn("stockfish")?;
+// proxy.set_max_multipv(4).add_engine_hook(|line| {
+//     eprintln!("< {}", line);
+//     LineAction::Forward
+// });
+// proxy.run().await?;
+//
+// To log the lines with their times instead, pass the engine wrapped in a
+// TapTransport to new.
+
+use crate::engproc::{EngineProcess, Spawner};
+use crate::err::UziErr;
+use crate::guicmd::{GuiCmd, GuiCmdRef};
+use crate::opt::SetOpt;
+use crate::transport::{StreamTransport, Transport};
+use std::ffi::OsStr;
+use std::fmt::{self, Debug, Formatter};
+use std::future::poll_fn;
+use std::task::Poll;
+use std::time::Duration;
+use tokio::io::{self, AsyncRead, AsyncWrite};
+use tokio::time;
+
+// What a hook does with a line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LineAction {
+    // Passes the line on unchanged.
+    Forward,
+    // Passes these lines on instead, which may be none, or the line and more.
+    Replace(Vec<String>),
+    // Drops the line.
+    Drop,
+}
+
+type Hook = Box<dyn FnMut(&str) -> LineAction + Send>;
+
+// A proxy between a GUI and an engine.
+pub struct UciProxy {
+    engine: Box<dyn Transport>,
+    // The hooks for the lines from the GUI, and for the lines from the engine,
+    // in the order they were added.
+    gui_hooks: Vec<Hook>,
+    engine_hooks: Vec<Hook>,
+    // The options set on the engine when it sends uciok, before the GUI gets
+    // it, so that the GUI can still change them.
+    options: Vec<SetOpt>,
+    // How long the engine may take to exit after quit before it is killed.
+    grace_period: Duration,
+}
+
+impl UciProxy {
+    // Returns a proxy for the engine at the other end of the transport.
+    pub fn new<T: Transport + 'static>(engine: T) -> Self {
+        Self::from_transport(Box::new(engine))
+    }
+
+    pub fn spawn<S: AsRef<OsStr>>(program: S) -> Result<Self, UziErr> {
+        Ok(Self::new(EngineProcess::spawn(program)?))
+    }
+
+    // Returns a proxy for a new engine from the spawner.
+    pub fn from_spawner(spawner: &Spawner) -> Result<Self, UziErr> {
+        Ok(Self::from_transport(spawner.spawn()?))
+    }
+
+    fn from_transport(engine: Box<dyn Transport>) -> Self {
+        Self {
+            engine,
+            gui_hooks: Vec::new(),
+            engine_hooks: Vec::new(),
+            options: Vec::new(),
+            grace_period: Duration::from_secs(5),
+        }
+    }
+
+    // Adds a hook for the lines from the GUI. The lines a hook passes on go
+    // through the hooks added after it.
+    pub fn add_gui_hook<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: FnMut(&str) -> LineAction + Send + 'static,
+    {
+        self.gui_hooks.push(Box::new(hook));
+        self
+    }
+
+    // Adds a hook for the lines from the engine, see add_gui_hook.
+    pub fn add_engine_hook<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: FnMut(&str) -> LineAction + Send + 'static,
+    {
+        self.engine_hooks.push(Box::new(hook));
+        self
+    }
+
+    // Adds an option to set on the engine after the handshake, e.g. one the GUI
+    // has no way to set.
+    pub fn add_option(&mut self, opt: SetOpt) -> &mut Self {
+        self.options.push(opt);
+        self
+    }
+
+    // Lowers the MultiPV values the GUI sets to at most max.
+    pub fn set_max_multipv(&mut self, max: u64) -> &mut Self {
+        // The hook sees every line, so only setoption is converted.
+        self.add_gui_hook(move |line| match GuiCmdRef::parse(line) {
+            Ok(cmd @ GuiCmdRef::SetOpt { .. }) => match cmd.to_cmd() {
+                Ok(GuiCmd::SetOpt(SetOpt::MultiPv(value))) if value > max => {
+                    LineAction::Replace(vec![GuiCmd::SetOpt(SetOpt::MultiPv(max)).to_string()])
+                }
+                _ => LineAction::Forward,
+            },
+            _ => LineAction::Forward,
+        })
+    }
+
+    pub fn set_grace_period(&mut self, grace_period: Duration) -> &mut Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    // Runs the proxy with the GUI on stdin and stdout, see run_with.
+    pub async fn run(self) -> Result<(), UziErr> {
+        self.run_with(io::stdin(), io::stdout()).await
+    }
+
+    // Forwards the lines between the GUI, which writes to input and reads from
+    // output, and the engine, see run_with_transport.
+    pub async fn run_with<R, W>(self, input: R, output: W) -> Result<(), UziErr>
+    where
+        R: AsyncRead + Debug + Send + Unpin,
+        W: AsyncWrite + Debug + Send + Unpin,
+    {
+        self.run_with_transport(StreamTransport::new(input, output))
+            .await
+    }
+
+    // Forwards the lines between the GUI at the other end of the transport and
+    // the engine. Returns once the engine has exited after the GUI sent quit or
+    // closed the connection, or fails with EngineExited if the engine exits
+    // before that.
+    pub async fn run_with_transport<G: Transport>(mut self, mut gui: G) -> Result<(), UziErr> {
+        loop {
+            match next_event(&mut gui, &mut self.engine).await {
+                Event::Gui(line) => {
+                    let Some(line) = line? else {
+                        // The GUI closed the connection without quit.
+                        let _ = self.engine.send_line("quit").await;
+                        break;
+                    };
+                    let is_quit = line.trim() == "quit";
+                    for line in apply(&mut self.gui_hooks, line) {
+                        self.engine.send_line(&line).await?;
+                    }
+                    if is_quit {
+                        break;
+                    }
+                }
+                Event::Engine(line) => {
+                    let Some(line) = line? else {
+                        return Err(UziErr::EngineExited);
+                    };
+                    if line.trim() == "uciok" {
+                        for opt in &self.options {
+                            let line = GuiCmd::SetOpt(opt.clone()).to_string();
+                            self.engine.send_line(&line).await?;
+                        }
+                    }
+                    for line in apply(&mut self.engine_hooks, line) {
+                        gui.send_line(&line).await?;
+                    }
+                }
+            }
+        }
+        let result = self.wait_exit(&mut gui).await;
+        // Closes the connection, so that the GUI sees the engine exit.
+        let _ = gui.kill().await;
+        result
+    }
+
+    // Forwards what the engine still sends until it exits, or kills it after
+    // the grace period.
+    async fn wait_exit<G: Transport>(&mut self, gui: &mut G) -> Result<(), UziErr> {
+        let forward = async {
+            while let Some(line) = self.engine.recv_line().await? {
+                for line in apply(&mut self.engine_hooks, line) {
+                    gui.send_line(&line).await?;
+                }
+            }
+            Ok(())
+        };
+        match time::timeout(self.grace_period, forward).await {
+            Ok(result) => result,
+            Err(_) => self.engine.kill().await,
+        }
+    }
+}
+
+impl Debug for UciProxy {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("UciProxy")
+            .field("engine", &self.engine)
+            .field("options", &self.options)
+            .field("grace_period", &self.grace_period)
+            .finish_non_exhaustive()
+    }
+}
+
+// A line, or the end of the lines, from either side.
+pub(crate) enum Event {
+    Gui(Result<Option<String>, UziErr>),
+    Engine(Result<Option<String>, UziErr>),
+}
+
+// Waits for the next line from the GUI or the engine. Reading is cancel safe
+// on both sides, so the side that loses doesn't lose a line.
+pub(crate) async fn next_event<G: Transport>(
+    gui: &mut G,
+    engine: &mut Box<dyn Transport>,
+) -> Event {
+    let mut gui = gui.recv_line();
+    let mut engine = engine.recv_line();
+    poll_fn(|cx| {
+        if let Poll::Ready(line) = gui.as_mut().poll(cx) {
+            return Poll::Ready(Event::Gui(line));
+        }
+        engine.as_mut().poll(cx).map(Event::Engine)
+    })
+    .await
+}
+
+// Passes the line through the hooks, in order, and returns the lines to
+// forward.
+fn apply(hooks: &mut [Hook], line: String) -> Vec<String> {
+    let Some((hook, rest)) = hooks.split_first_mut() else {
+        return vec![line];
+    };
+    match hook(&line) {
+        LineAction::Forward => apply(rest, line),
+        LineAction::Replace(lines) => lines
+            .into_iter()
+            .flat_map(|line| apply(rest, line))
+            .collect(),
+        LineAction::Drop => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockEngine;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn proxy_forwards_and_rewrites() {
+        let mock = MockEngine::new("Mock");
+        let log = mock.log();
+        let mut proxy = UciProxy::new(mock);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let engine_seen = Arc::clone(&seen);
+        proxy
+            .add_option(SetOpt::Hash(64))
+            .set_max_multipv(2)
+            .add_gui_hook(|line| match line {
+                "debug on" => LineAction::Drop,
+                _ => LineAction::Forward,
+            })
+            .add_engine_hook(move |line| {
+                engine_seen.lock().unwrap().push(line.to_string());
+                match line {
+                    "id author uzi" => LineAction::Replace(vec!["id author proxy".into()]),
+                    _ => LineAction::Forward,
+                }
+            });
+
+        let (gui_input, input) = io::duplex(1024);
+        let (output, gui_output) = io::duplex(1024);
+        let run = tokio::spawn(proxy.run_with(input, output));
+        let mut gui = StreamTransport::new(gui_output, gui_input);
+        // The GUI waits for uciok, as GUIs do, so the options the proxy sets
+        // come first.
+        let mut received = Vec::new();
+        for (lines, last) in [
+            (&["uci"][..], "uciok"),
+            (
+                &["debug on", "setoption name MultiPV value 8", "isready"],
+                "readyok",
+            ),
+        ] {
+            for line in lines {
+                gui.send_line(line).await.unwrap();
+            }
+            while received.last().map(String::as_str) != Some(last) {
+                received.push(gui.recv_line().await.unwrap().unwrap());
+            }
+        }
+        gui.send_line("quit").await.unwrap();
+        assert_eq!(run.await.unwrap(), Ok(()));
+
+        assert_eq!(
+            received,
+            ["id name Mock", "id author proxy", "uciok", "readyok"]
+        );
+        assert_eq!(seen.lock().unwrap().len(), 4);
+        assert_eq!(
+            log.lines(),
+            [
+                "uci",
+                "setoption name Hash value 64",
+                "setoption name MultiPV value 2",
+                "isready",
+                "quit",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn proxy_stops_engine_when_gui_leaves() {
+        let mock = MockEngine::new("Mock");
+        let log = mock.log();
+        let (gui, input) = io::duplex(1024);
+        drop(gui);
+        let result = UciProxy::new(mock).run_with(input, io::sink()).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(log.lines(), ["quit"]);
+
+        let mut mock = MockEngine::new("Mock");
+        mock.crash_on("go");
+        let (mut gui, input) = io::duplex(1024);
+        gui.write_all(b"go depth 1\n").await.unwrap();
+        let result = UciProxy::new(mock).run_with(input, io::sink()).await;
+        assert_eq!(result, Err(UziErr::EngineExited));
+    }
+}
@@ -0,0 +1,295 @@
+// This module contains Recording and ReplayTransport, which play back the part
+// of an engine in a recorded session, so that a session can be reproduced
+// without the engine, e.g. in tests or to debug a report. A recording is the
+// log of a TapLog, see TapTransport:
+//
+// 0 >Stockfish(0): uci
+// 12 <Stockfish(0): id name Stockfish 16
+// 12 <Stockfish(0): uciok
+//
+// The replayed engine sends the lines it received after the client sent the
+// lines it was sent before them, with the same delays as in the recording.
+
+use crate::err::UziErr;
+use crate::trace::Direction;
+use crate::transport::{BoxFuture, Transport};
+use std::fs;
+use std::future;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+
+// The lines of a recorded session, of one or more engines.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Recording {
+    entries: Vec<Entry>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Entry {
+    // The milliseconds since the start of the recording.
+    millis: u64,
+    direction: Direction,
+    label: String,
+    line: String,
+}
+
+// An engine played back from a recording. The lines the client sends are
+// matched against the ones in the recording by their command, e.g. go, since
+// the times in go and the moves usually differ between runs, or exactly in
+// strict mode. Once the recording ends, the engine closes the connection.
+#[derive(Debug)]
+pub struct ReplayTransport {
+    entries: Vec<Entry>,
+    // The next line to send to the client.
+    next_recv: usize,
+    // Where to look for the next line from the client.
+    next_sent: usize,
+    // When the last line was exchanged, and its time in the recording, which
+    // the delays of the next lines are counted from.
+    anchor: (Instant, u64),
+    speed: f64,
+    is_strict: bool,
+}
+
+impl Recording {
+    // Parses a recording. Blank lines are skipped, and other lines that are not
+    // in the format of a TapLog fail with BadRecording.
+    pub fn parse(text: &str) -> Result<Self, UziErr> {
+        let mut entries = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(parse_entry(line).ok_or(UziErr::BadRecording(i + 1))?);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, UziErr> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    // Returns the labels of the engines, in the order they first appear.
+    pub fn labels(&self) -> Vec<&str> {
+        let mut labels: Vec<&str> = Vec::new();
+        for entry in &self.entries {
+            if !labels.contains(&entry.label.as_str()) {
+                labels.push(&entry.label);
+            }
+        }
+        labels
+    }
+
+    // Returns a transport that plays back the engine with the label. To
+    // replay an engine that was restarted, create the transports in the
+    // function of a Spawner.
+    pub fn replay(&self, label: &str) -> ReplayTransport {
+        let entries = self
+            .entries
+            .iter()
+            .filter(|entry| entry.label == label)
+            .cloned()
+            .collect();
+        ReplayTransport::new(entries)
+    }
+}
+
+// Parses a line such as "12 <Stockfish(0): uciok".
+fn parse_entry(line: &str) -> Option<Entry> {
+    let (millis, rest) = line.split_once(' ')?;
+    let direction = match rest.chars().next()? {
+        '>' => Direction::Sent,
+        '<' => Direction::Received,
+        _ => return None,
+    };
+    let (label, line) = rest[1..].split_once(": ")?;
+    Some(Entry {
+        millis: millis.parse().ok()?,
+        direction,
+        label: label.to_string(),
+        line: line.to_string(),
+    })
+}
+
+impl ReplayTransport {
+    fn new(entries: Vec<Entry>) -> Self {
+        Self {
+            entries,
+            next_recv: 0,
+            next_sent: 0,
+            anchor: (Instant::now(), 0),
+            speed: 1.0,
+            is_strict: false,
+        }
+    }
+
+    // Plays the recording faster, or slower, by the factor, e.g. 2.0 for
+    // half the delays, or f64::INFINITY for no delays at all.
+    pub fn set_speed(&mut self, speed: f64) -> &mut Self {
+        self.speed = speed;
+        self
+    }
+
+    // Makes the lines the client sends match the recording exactly, which
+    // fails with ReplayMismatch otherwise.
+    pub fn set_strict(&mut self, is_strict: bool) -> &mut Self {
+        self.is_strict = is_strict;
+        self
+    }
+
+    fn matches(&self, recorded: &str, line: &str) -> bool {
+        if self.is_strict {
+            recorded == line
+        } else {
+            recorded.split_whitespace().next() == line.split_whitespace().next()
+        }
+    }
+
+    // Returns when the entry is due, counted from the last line exchanged.
+    fn due(&self, entry: &Entry) -> Instant {
+        let (instant, millis) = self.anchor;
+        let millis = entry.millis.saturating_sub(millis) as f64;
+        let delay = Duration::try_from_secs_f64(millis / 1000.0 / self.speed);
+        instant + delay.unwrap_or_default()
+    }
+}
+
+impl Transport for ReplayTransport {
+    // Matches the line against the next line the engine was sent. Lines the
+    // engine sent before it, e.g. after the client sent stop earlier than in
+    // the recording, are still delivered.
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        let next = self.entries[self.next_sent..]
+            .iter()
+            .position(|entry| entry.direction == Direction::Sent)
+            .map(|i| self.next_sent + i);
+        let result = match next {
+            Some(i) if self.matches(&self.entries[i].line, line) => {
+                self.next_sent = i + 1;
+                self.anchor = (Instant::now(), self.entries[i].millis);
+                Ok(())
+            }
+            _ => Err(UziErr::ReplayMismatch(line.to_string())),
+        };
+        Box::pin(async move { result })
+    }
+
+    // Returns the next line the engine sent, when it is due, or waits forever
+    // if the engine was waiting for a line from the client.
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        Box::pin(async move {
+            loop {
+                let Some(entry) = self.entries.get(self.next_recv) else {
+                    return Ok(None);
+                };
+                match entry.direction {
+                    Direction::Sent if self.next_recv < self.next_sent => self.next_recv += 1,
+                    Direction::Sent => future::pending::<()>().await,
+                    Direction::Received => {
+                        let due = self.due(entry);
+                        // The line is only taken once it is due, so that
+                        // this is cancel safe.
+                        time::sleep_until(due).await;
+                        let entry = &self.entries[self.next_recv];
+                        self.next_recv += 1;
+                        self.anchor = (due, entry.millis);
+                        return Ok(Some(entry.line.clone()));
+                    }
+                }
+            }
+        })
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        self.next_recv = self.entries.len();
+        Box::pin(async { Ok(()) })
+    }
+
+    fn wait_exit(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        self.kill()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Engine;
+    use crate::guicmd::{Go, Pos};
+    use crate::pm::Pm;
+    use crate::tap::{TapLog, TapTransport};
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+    use std::str::FromStr;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    const RECORDING: &str = "\
+0 >Fake(0): uci
+5 <Fake(0): id name Fake
+6 <Fake(0): uciok
+10 >Fake(1): uci
+18 >Fake(0): ucinewgame
+19 >Fake(0): isready
+19 <Fake(0): readyok
+20 >Fake(0): position startpos
+21 >Fake(0): go wtime 1000 btime 1000
+80 <Fake(0): info depth 1 score cp 5 pv e2e4
+131 <Fake(0): bestmove e2e4
+";
+
+    #[tokio::test]
+    async fn replay_with_timing() {
+        let recording = Recording::parse(RECORDING).unwrap();
+        assert_eq!(recording.labels(), vec!["Fake(0)", "Fake(1)"]);
+        let mut eng = Engine::new(recording.replay("Fake(0)"));
+        eng.uci(TIMEOUT).await.unwrap();
+        assert_eq!(eng.name(), Some("Fake"));
+
+        eng.position(&Pos::new()).await.unwrap();
+        let mut go = Go::new();
+        go.set_wtime(Duration::from_secs(5));
+        let started = Instant::now();
+        let best = eng.go(&go).await.unwrap().wait().await;
+        assert_eq!(best, Ok((Pm::from_str("e2e4").unwrap(), None)));
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        // The recording ends like an engine that exited.
+        assert!(matches!(
+            eng.recv().await.map_err(UziErr::into_root),
+            Err(UziErr::Crashed(_))
+        ));
+
+        let mut transport = recording.replay("Fake(0)");
+        transport.set_strict(true);
+        assert_eq!(transport.send_line("uci").await, Ok(()));
+        assert_eq!(
+            transport
+                .send_line("position fen 8/8/8/8/8/8/8/k1K5 w - - 0 1")
+                .await,
+            Err(UziErr::ReplayMismatch(
+                "position fen 8/8/8/8/8/8/8/k1K5 w - - 0 1".to_string()
+            ))
+        );
+        assert_eq!(
+            Recording::parse("0 >A: uci\n\n12 A: uciok"),
+            Err(UziErr::BadRecording(3))
+        );
+    }
+
+    #[tokio::test]
+    async fn replay_tapped_session() {
+        let path = std::env::temp_dir().join(format!("uzi-replay-{}.log", std::process::id()));
+        let log = TapLog::create(&path).unwrap();
+        let mut eng = Engine::new(TapTransport::new(fake_engine(FAKE_ENGINE), "Fake", log));
+        eng.uci(TIMEOUT).await.unwrap();
+        let pos = Pos::new();
+        let recorded = eng.best_move(&pos, &Go::new()).await;
+
+        let mut transport = Recording::open(&path).unwrap().replay("Fake");
+        transport.set_speed(f64::INFINITY);
+        let mut eng = Engine::new(transport);
+        eng.uci(TIMEOUT).await.unwrap();
+        assert_eq!(eng.options().len(), 1);
+        assert_eq!(eng.best_move(&pos, &Go::new()).await, recorded);
+        let _ = fs::remove_file(&path);
+    }
+}
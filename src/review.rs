@@ -0,0 +1,216 @@
+// This module contains GameReview, which analyzes every position of a game and
+// tags the moves that lost the most, i.e. blunders, mistakes and inaccuracies.
+
+use crate::analysis::AnalysisSession;
+use crate::cache::AnalysisCache;
+use crate::client::Engine;
+use crate::engcmd::Score;
+use crate::err::UziErr;
+use crate::guicmd::{Go, Pos};
+use crate::pm::Pm;
+
+// Analyzes the positions of a game with the same limits, and compares the
+// evaluation before and after every move.
+#[derive(Clone, Debug)]
+pub struct GameReview {
+    limits: Go,
+    thresholds: Thresholds,
+    cache: Option<AnalysisCache>,
+}
+
+// The centipawns a move has to lose, from the point of view of the side that
+// played it, to be tagged.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Thresholds {
+    pub inaccuracy: i32,
+    pub mistake: i32,
+    pub blunder: i32,
+}
+
+// How bad a move is.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum MoveTag {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+// The analysis of one move of the game.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MoveReport {
+    // The index of the move, starting at 0.
+    pub ply: usize,
+    pub pm: Pm,
+    // The move the engine prefers in the position before the move.
+    pub best: Pm,
+    // The evaluations before and after the move, both from the point of view
+    // of the side that played it.
+    pub before: Option<Score>,
+    pub after: Option<Score>,
+    // The centipawns lost by the move, or None if either evaluation is missing.
+    pub loss: Option<i32>,
+    pub tag: Option<MoveTag>,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            inaccuracy: 50,
+            mistake: 100,
+            blunder: 300,
+        }
+    }
+}
+
+impl Thresholds {
+    // Returns the tag of a move that lost the given centipawns.
+    pub fn tag(&self, loss: i32) -> Option<MoveTag> {
+        match loss {
+            loss if loss >= self.blunder => Some(MoveTag::Blunder),
+            loss if loss >= self.mistake => Some(MoveTag::Mistake),
+            loss if loss >= self.inaccuracy => Some(MoveTag::Inaccuracy),
+            _ => None,
+        }
+    }
+}
+
+impl GameReview {
+    pub fn new(limits: Go) -> Self {
+        Self {
+            limits,
+            thresholds: Thresholds::default(),
+            cache: None,
+        }
+    }
+
+    pub fn set_thresholds(&mut self, thresholds: Thresholds) -> &mut Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    // Sets a cache for the evaluations, e.g. to review games that share their
+    // opening moves without searching those positions again.
+    pub fn set_cache(&mut self, cache: AnalysisCache) -> &mut Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    // Analyzes the game, which starts at the position and continues with the
+    // moves, and returns a report for every move. The position may already
+    // have moves, which are not reviewed.
+    pub async fn review(
+        &self,
+        eng: &mut Engine,
+        start: &Pos,
+        moves: &[Pm],
+    ) -> Result<Vec<MoveReport>, UziErr> {
+        // The best move and the evaluation of every position, from the point of
+        // view of the side to move.
+        let mut evals = Vec::with_capacity(moves.len() + 1);
+        let mut session = AnalysisSession::new(eng);
+        if let Some(ref cache) = self.cache {
+            session.set_cache(cache.clone());
+        }
+        let mut pos = start.clone();
+        for i in 0..=moves.len() {
+            if i > 0 {
+                pos.add_move(moves[i - 1]);
+            }
+            session.set_position(pos.clone());
+            let result = session.analyze_cached(&self.limits).await?;
+            let score = result.lines.first().and_then(|line| line.score);
+            evals.push((result.best, score));
+        }
+
+        let reports = moves
+            .iter()
+            .enumerate()
+            .map(|(ply, pm)| {
+                let (best, before) = evals[ply];
+                // The side to move after the move is the opponent.
+                let after = evals[ply + 1].1.map(|score| score.negate());
+                let loss = before
+                    .and_then(|score| score.cp_value())
+                    .zip(after.and_then(|score| score.cp_value()))
+                    .map(|(before, after)| (before - after).max(0));
+                MoveReport {
+                    ply,
+                    pm: *pm,
+                    best,
+                    before,
+                    after,
+                    loss,
+                    tag: loss.and_then(|loss| self.thresholds.tag(loss)),
+                }
+            })
+            .collect();
+        Ok(reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::fake_engine;
+    use std::str::FromStr;
+
+    // Evaluates the position by its last move, always preferring a2a3.
+    const REVIEW_ENGINE: &str = r#"
+while read -r line; do
+    case "$line" in
+        isready) echo "readyok";;
+        position*) last=${line##* };;
+        go*)
+            case "$last" in
+                startpos) score="cp 20";;
+                e2e4) score="cp -25";;
+                e7e5) score="cp 150";;
+                d1h5) score="cp 300";;
+                *) score="mate -2";;
+            esac
+            echo "info depth 1 score $score pv a2a3"
+            echo "bestmove a2a3";;
+    esac
+done
+"#;
+
+    fn pm(s: &str) -> Pm {
+        Pm::from_str(s).unwrap()
+    }
+
+    #[tokio::test]
+    async fn review_tags_moves() {
+        let mut eng = Engine::new(fake_engine(REVIEW_ENGINE));
+        let mut go = Go::new();
+        go.set_depth(1);
+        let moves = [pm("e2e4"), pm("e7e5"), pm("d1h5"), pm("b8c6")];
+        let reports = GameReview::new(go)
+            .review(&mut eng, &Pos::new(), &moves)
+            .await
+            .unwrap();
+
+        let summary: Vec<(Pm, Option<i32>, Option<MoveTag>)> = reports
+            .iter()
+            .map(|report| (report.pm, report.loss, report.tag))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (pm("e2e4"), Some(0), None),
+                (pm("e7e5"), Some(125), Some(MoveTag::Mistake)),
+                (pm("d1h5"), Some(450), Some(MoveTag::Blunder)),
+                (pm("b8c6"), Some(0), None),
+            ]
+        );
+        assert_eq!(reports[0].best, pm("a2a3"));
+        assert_eq!(reports[3].after.and_then(|score| score.mate()), Some(2));
+    }
+
+    #[test]
+    fn review_thresholds() {
+        let thresholds = Thresholds::default();
+        assert_eq!(thresholds.tag(49), None);
+        assert_eq!(thresholds.tag(50), Some(MoveTag::Inaccuracy));
+        assert_eq!(thresholds.tag(299), Some(MoveTag::Mistake));
+    }
+}
@@ -0,0 +1,112 @@
+// This module sets the scheduling parameters of engine processes, i.e. the
+// niceness and the CPUs they can run on. These are set in the child process
+// right before the engine binary is executed.
+
+use crate::err::UziErr;
+use std::io;
+use std::process::Command;
+
+// Sets up the command so that the process runs with the given niceness and
+// only on the given CPUs. Niceness is supported on unix, and CPU affinity on
+// Linux. On other platforms this fails with an unsupported error.
+pub(crate) fn set_sched(
+    cmd: &mut Command,
+    nice: Option<i32>,
+    cpus: Option<&[usize]>,
+) -> Result<(), UziErr> {
+    if nice.is_none() && cpus.is_none() {
+        return Ok(());
+    }
+    imp::set_sched(cmd, nice, cpus)
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+
+    pub fn set_sched(
+        cmd: &mut Command,
+        nice: Option<i32>,
+        cpus: Option<&[usize]>,
+    ) -> Result<(), UziErr> {
+        // The CPU set is built here, since only async-signal-safe functions can
+        // be called in the child before exec.
+        let cpu_set = cpus.map(cpu_set);
+        let set_sched = move || {
+            if let Some(nice) = nice {
+                set_nice(nice)?;
+            }
+            if let Some(ref cpu_set) = cpu_set {
+                set_affinity(cpu_set)?;
+            }
+            Ok(())
+        };
+        // SAFETY: the closure only makes system calls, which are safe to call
+        // between fork and exec.
+        unsafe {
+            cmd.pre_exec(set_sched);
+        }
+        Ok(())
+    }
+
+    fn set_nice(nice: i32) -> io::Result<()> {
+        // SAFETY: setpriority only reads its arguments. The cast is needed
+        // because the type of the first argument differs between platforms.
+        #[allow(clippy::unnecessary_cast)]
+        let ret = unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, nice) };
+        match ret {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    type CpuSet = libc::cpu_set_t;
+
+    #[cfg(not(target_os = "linux"))]
+    type CpuSet = ();
+
+    #[cfg(target_os = "linux")]
+    fn cpu_set(cpus: &[usize]) -> CpuSet {
+        // SAFETY: cpu_set_t is a plain bit mask, for which zero is valid.
+        let mut cpu_set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        let max_cpus = 8 * std::mem::size_of::<libc::cpu_set_t>();
+        for &cpu in cpus.iter().filter(|&&cpu| cpu < max_cpus) {
+            // SAFETY: the CPU is within the set.
+            unsafe { libc::CPU_SET(cpu, &mut cpu_set) };
+        }
+        cpu_set
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn cpu_set(_cpus: &[usize]) -> CpuSet {}
+
+    #[cfg(target_os = "linux")]
+    fn set_affinity(cpu_set: &CpuSet) -> io::Result<()> {
+        // SAFETY: the set is valid for the size that is passed.
+        let ret = unsafe { libc::sched_setaffinity(0, std::mem::size_of::<CpuSet>(), cpu_set) };
+        match ret {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_affinity(_cpu_set: &CpuSet) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use super::*;
+
+    pub fn set_sched(
+        _cmd: &mut Command,
+        _nice: Option<i32>,
+        _cpus: Option<&[usize]>,
+    ) -> Result<(), UziErr> {
+        Err(io::Error::from(io::ErrorKind::Unsupported).into())
+    }
+}
@@ -0,0 +1,509 @@
+// This module contains SearchHandle, which is returned by Engine::go to follow a
+// running search.
+
+use crate::client::Engine;
+use crate::engcmd::{EngCmd, Info};
+use crate::err::UziErr;
+use crate::guicmd::{Go, GuiCmd, Pos};
+use crate::pm::Pm;
+use crate::throttle::InfoThrottle;
+use crate::watchdog::Awaited;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+// How long an engine has to send bestmove after it is stopped because the
+// search reached its hard deadline.
+const STOP_GRACE: Duration = Duration::from_secs(1);
+
+// The search state of an engine, as seen by the client.
+// - Idle - no search is running, every bestmove has been consumed.
+// - Searching - go was sent and the engine is searching normally.
+// - Pondering - go ponder was sent and ponderhit has not been sent yet.
+// - Stopping - stop was sent and the bestmove has not been consumed yet.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub(crate) enum SearchState {
+    #[default]
+    Idle,
+    Searching,
+    Pondering,
+    Stopping,
+}
+
+impl SearchState {
+    pub fn is_idle(&self) -> bool {
+        matches!(self, SearchState::Idle)
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        matches!(self, SearchState::Stopping)
+    }
+
+    // Returns the state after sending the command, or UziErr::BadSearchState if
+    // the command cannot be sent in the current state. A new search can only be
+    // started once the bestmove of the previous one has been consumed, and the
+    // position cannot change while the engine is searching. A stop while idle
+    // is allowed because the search may have just finished; the engine ignores
+    // it.
+    pub fn on_send(self, cmd: &GuiCmd) -> Result<Self, UziErr> {
+        match (self, cmd) {
+            (SearchState::Idle, GuiCmd::Go(go)) if go.is_ponder() => Ok(SearchState::Pondering),
+            (SearchState::Idle, GuiCmd::Go(_)) => Ok(SearchState::Searching),
+            (_, GuiCmd::Go(_)) => Err(UziErr::BadSearchState),
+            (SearchState::Searching | SearchState::Pondering, GuiCmd::Stop) => {
+                Ok(SearchState::Stopping)
+            }
+            (SearchState::Pondering, GuiCmd::Ponderhit) => Ok(SearchState::Searching),
+            (_, GuiCmd::Ponderhit) => Err(UziErr::BadSearchState),
+            (SearchState::Searching | SearchState::Pondering, GuiCmd::Pos(_) | GuiCmd::NewGame) => {
+                Err(UziErr::BadSearchState)
+            }
+            (state, _) => Ok(state),
+        }
+    }
+
+    // Returns the state after receiving the command, and whether the command
+    // belongs to the current search. A bestmove received while idle is a stray
+    // reply, e.g. to a stop sent after the search had already finished.
+    pub fn on_recv(self, cmd: &EngCmd) -> (Self, bool) {
+        match (self, cmd) {
+            (SearchState::Idle, EngCmd::BestMove { .. }) => (SearchState::Idle, false),
+            (_, EngCmd::BestMove { .. }) => (SearchState::Idle, true),
+            (state, _) => (state, true),
+        }
+    }
+}
+
+// A handle to a running search. The info updates are read with next_info, and
+// the result with wait, which can be called at any time.
+#[derive(Debug)]
+pub struct SearchHandle<'a> {
+    eng: &'a mut Engine,
+    started: Instant,
+
+    // The best move and the ponder move, once bestmove is received.
+    best: Option<(Pm, Option<Pm>)>,
+
+    throttle: Option<InfoThrottle>,
+
+    // The hard deadline of the search, if any.
+    deadline: Option<Deadline>,
+}
+
+// The hard deadline of a search.
+// - Stop - stop is sent at this time.
+// - Grace - stop was sent, and the engine is given up on at this time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Deadline {
+    Stop(Instant),
+    Grace(Instant),
+}
+
+impl Deadline {
+    fn at(self) -> Instant {
+        match self {
+            Deadline::Stop(at) | Deadline::Grace(at) => at,
+        }
+    }
+}
+
+impl<'a> SearchHandle<'a> {
+    pub(crate) fn new(eng: &'a mut Engine) -> Self {
+        let throttle = eng.info_interval().map(InfoThrottle::new);
+        Self {
+            eng,
+            started: Instant::now(),
+            best: None,
+            throttle,
+            deadline: None,
+        }
+    }
+
+    // Sets the hard deadline of the search, relative to its start.
+    pub(crate) fn set_deadline(&mut self, deadline: Duration) {
+        self.deadline = Some(Deadline::Stop(self.started + deadline));
+    }
+
+    // Throttles the info updates of this search, whatever the engine setting.
+    pub(crate) fn set_throttle(&mut self, interval: Duration) {
+        self.throttle = Some(InfoThrottle::new(interval));
+    }
+
+    // Stops the search, reads its bestmove, and starts searching another
+    // position with the handle, e.g. when the user moves on an analysis board.
+    pub(crate) async fn restart(&mut self, pos: &Pos, go: &Go) -> Result<(), UziErr> {
+        self.stop().await?;
+        while self.next_info().await?.is_some() {}
+        self.eng.position(pos).await?;
+        self.eng.go(go).await?;
+        self.started = Instant::now();
+        self.best = None;
+        self.throttle = self
+            .throttle
+            .as_ref()
+            .map(|throttle| InfoThrottle::new(throttle.interval()));
+        self.deadline = None;
+        Ok(())
+    }
+
+    // Returns true once the engine has sent bestmove.
+    pub fn is_done(&self) -> bool {
+        self.best.is_some()
+    }
+
+    // Returns the next info update of the search, or None once the engine has
+    // sent bestmove and the updates held back by the throttle, if any, have
+    // been delivered.
+    pub async fn next_info(&mut self) -> Result<Option<Info>, UziErr> {
+        loop {
+            if self.best.is_some() {
+                return Ok(self.throttle.as_mut().and_then(InfoThrottle::flush));
+            }
+
+            let deadline = self.deadline.map(Deadline::at);
+            let due = self.throttle.as_ref().and_then(InfoThrottle::next_due);
+            let cmd = match deadline.into_iter().chain(due).min() {
+                Some(wake) => {
+                    let wait = wake.saturating_duration_since(Instant::now());
+                    match time::timeout(wait, self.eng.recv_search(self.started)).await {
+                        Ok(cmd) => cmd?,
+                        Err(_) => match self.on_wake().await? {
+                            Some(info) => return Ok(Some(info)),
+                            None => continue,
+                        },
+                    }
+                }
+                None => self.eng.recv_search(self.started).await?,
+            };
+            if let Some(info) = self.on_cmd(cmd) {
+                return Ok(Some(info));
+            }
+        }
+    }
+
+    // Hands back an info the consumer is done with, see Engine::set_info_pool.
+    pub fn recycle(&mut self, info: Info) {
+        self.eng.recycle_info(info);
+    }
+
+    // Handles a command of the search, returning the info to deliver, if any.
+    fn on_cmd(&mut self, cmd: EngCmd) -> Option<Info> {
+        match cmd {
+            EngCmd::Info(info) => match self.throttle {
+                Some(ref mut throttle) => {
+                    let info = throttle.offer(info, Instant::now());
+                    self.eng.on_throttled(throttle.take_dropped());
+                    info
+                }
+                None => Some(info),
+            },
+            EngCmd::BestMove { best, ponder } => {
+                self.best = Some((best, ponder));
+                None
+            }
+            // TODO: log unexpected commands.
+            _ => None,
+        }
+    }
+
+    // Handles the deadlines that passed, returning a held info that is due, if
+    // any.
+    async fn on_wake(&mut self) -> Result<Option<Info>, UziErr> {
+        let now = Instant::now();
+        match self.deadline {
+            Some(Deadline::Stop(at)) if now >= at => {
+                self.eng.stop().await?;
+                self.deadline = Some(Deadline::Grace(now + STOP_GRACE));
+            }
+            Some(Deadline::Grace(at)) if now >= at => {
+                self.deadline = None;
+                let waited = now.duration_since(self.started);
+                return Err(self.eng.on_hang(Awaited::BestMove, waited).await);
+            }
+            _ => (),
+        }
+        Ok(self.take_due())
+    }
+
+    fn take_due(&mut self) -> Option<Info> {
+        self.throttle.as_mut()?.take_due(Instant::now())
+    }
+
+    // Tells the engine to stop searching. The result is still read with wait.
+    pub async fn stop(&mut self) -> Result<(), UziErr> {
+        if self.is_done() {
+            return Ok(());
+        }
+        self.eng.stop().await
+    }
+
+    // Tells the engine that the opponent played the move it was pondering on.
+    pub async fn ponderhit(&mut self) -> Result<(), UziErr> {
+        self.eng.ponderhit().await
+    }
+
+    // Waits for the search to finish, skipping the remaining info updates, and
+    // returns the best move and the optional ponder move.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "search", level = "debug", skip_all, fields(engine = self.eng.name()))
+    )]
+    pub async fn wait(mut self) -> Result<(Pm, Option<Pm>), UziErr> {
+        while self.next_info().await?.is_some() {}
+        Ok(self.best.expect("search is done"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+    use std::str::FromStr;
+
+    fn go_cmd(s: &str) -> GuiCmd {
+        GuiCmd::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn search_state_transitions() {
+        let state = SearchState::Idle;
+        assert_eq!(
+            state.on_send(&go_cmd("go depth 1")),
+            Ok(SearchState::Searching)
+        );
+        assert_eq!(
+            state.on_send(&go_cmd("go ponder")),
+            Ok(SearchState::Pondering)
+        );
+        assert_eq!(state.on_send(&GuiCmd::Stop), Ok(SearchState::Idle));
+        assert_eq!(
+            state.on_send(&GuiCmd::Ponderhit),
+            Err(UziErr::BadSearchState)
+        );
+
+        let state = SearchState::Pondering;
+        assert_eq!(
+            state.on_send(&GuiCmd::Ponderhit),
+            Ok(SearchState::Searching)
+        );
+        assert_eq!(state.on_send(&GuiCmd::Stop), Ok(SearchState::Stopping));
+
+        let state = SearchState::Searching;
+        assert_eq!(
+            state.on_send(&go_cmd("go infinite")),
+            Err(UziErr::BadSearchState)
+        );
+        assert_eq!(state.on_send(&GuiCmd::NewGame), Err(UziErr::BadSearchState));
+        assert_eq!(state.on_send(&GuiCmd::IsReady), Ok(SearchState::Searching));
+        assert_eq!(state.on_send(&GuiCmd::Stop), Ok(SearchState::Stopping));
+
+        let state = SearchState::Stopping;
+        assert_eq!(
+            state.on_send(&go_cmd("go infinite")),
+            Err(UziErr::BadSearchState)
+        );
+        assert_eq!(state.on_send(&GuiCmd::Stop), Ok(SearchState::Stopping));
+        assert_eq!(
+            state.on_send(&go_cmd("position startpos")),
+            Ok(SearchState::Stopping)
+        );
+    }
+
+    #[test]
+    fn search_state_consumes_one_best_move() {
+        let best = EngCmd::from_str("bestmove e2e4").unwrap();
+        let info = EngCmd::from_str("info depth 1").unwrap();
+        assert_eq!(
+            SearchState::Searching.on_recv(&info),
+            (SearchState::Searching, true)
+        );
+        assert_eq!(
+            SearchState::Stopping.on_recv(&best),
+            (SearchState::Idle, true)
+        );
+        assert_eq!(SearchState::Idle.on_recv(&best), (SearchState::Idle, false));
+    }
+
+    #[tokio::test]
+    async fn search_handle_infos_and_best_move() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        let mut go = Go::new();
+        go.set_depth(2);
+        let mut search = eng.go(&go).await.unwrap();
+
+        let info = search.next_info().await.unwrap().unwrap();
+        assert_eq!(info.depth(), Some(1));
+        assert_eq!(info.score().and_then(|s| s.cp()), Some(10));
+        let info = search.next_info().await.unwrap().unwrap();
+        assert_eq!(info.depth(), Some(2));
+        assert_eq!(info.pv().map(|pv| pv.len()), Some(2));
+        assert_eq!(search.next_info().await, Ok(None));
+        assert!(search.is_done());
+
+        assert_eq!(
+            search.wait().await,
+            Ok((
+                Pm::from_str("e2e4").unwrap(),
+                Some(Pm::from_str("e7e5").unwrap())
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn search_handle_recycles_infos() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        eng.set_info_pool(1);
+        let mut go = Go::new();
+        go.set_depth(2);
+        let mut search = eng.go(&go).await.unwrap();
+
+        let info = search.next_info().await.unwrap().unwrap();
+        assert_eq!(info.depth(), Some(1));
+        search.recycle(info);
+        let info = search.next_info().await.unwrap().unwrap();
+        assert_eq!(info.depth(), Some(2));
+        assert_eq!(info.score().and_then(|s| s.cp()), Some(20));
+        assert_eq!(info.pv().map(|pv| pv.len()), Some(2));
+        search.recycle(info);
+        assert_eq!(search.next_info().await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn search_handle_throttles_infos() {
+        let script = r#"
+            read -r line
+            i=1
+            while [ $i -le 200 ]; do
+                echo "info depth 5 nodes $i"
+                i=$((i + 1))
+            done
+            echo "bestmove e2e4"
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        eng.set_info_throttle(std::time::Duration::from_secs(60));
+        let mut search = eng.go(&Go::new().set_depth(5).clone()).await.unwrap();
+
+        let mut nodes = Vec::new();
+        while let Some(info) = search.next_info().await.unwrap() {
+            nodes.push(info.nodes().unwrap());
+        }
+        assert_eq!(nodes, vec![1, 200]);
+        assert_eq!(
+            search.wait().await,
+            Ok((Pm::from_str("e2e4").unwrap(), None))
+        );
+        assert_eq!(eng.traffic().throttled(), 198);
+    }
+
+    #[tokio::test]
+    async fn search_handle_deadline_stops_search() {
+        // Ignores the movetime and searches until stop.
+        let script = r#"
+            while read -r line; do
+                case "$line" in
+                    go*) echo "info depth 1 pv d2d4";;
+                    stop) echo "bestmove d2d4";;
+                esac
+            done
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let mut go = Go::new();
+        go.set_move_time(Duration::from_millis(10));
+        let search = eng
+            .go_with_deadline(&go, Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert_eq!(
+            search.wait().await,
+            Ok((Pm::from_str("d2d4").unwrap(), None))
+        );
+    }
+
+    #[tokio::test]
+    async fn search_handle_deadline_without_best_move() {
+        let mut eng = Engine::new(fake_engine("cat > /dev/null"));
+        let mut go = Go::new();
+        go.set_infinite();
+        let search = eng
+            .go_with_deadline(&go, Duration::from_millis(10))
+            .await
+            .unwrap();
+        assert_eq!(
+            search.wait().await.map_err(UziErr::into_root),
+            Err(UziErr::Timeout)
+        );
+    }
+
+    #[tokio::test]
+    async fn search_handle_stop() {
+        // Searches until stop, then reports the best move.
+        let script = r#"
+            while read -r line; do
+                case "$line" in
+                    go*) echo "info depth 1 score cp 5 pv d2d4";;
+                    stop) echo "bestmove d2d4";;
+                esac
+            done
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let mut go = Go::new();
+        go.set_infinite();
+        let mut search = eng.go(&go).await.unwrap();
+
+        assert!(search.next_info().await.unwrap().is_some());
+        search.stop().await.unwrap();
+        assert_eq!(
+            search.wait().await,
+            Ok((Pm::from_str("d2d4").unwrap(), None))
+        );
+    }
+
+    #[tokio::test]
+    async fn go_while_searching_is_rejected() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        let go = Go::new();
+        eng.go(&go).await.unwrap();
+        assert!(eng.is_searching());
+        assert!(matches!(
+            eng.go(&go).await.map_err(UziErr::into_root),
+            Err(UziErr::BadSearchState)
+        ));
+        assert_eq!(
+            eng.ponderhit().await.map_err(UziErr::into_root),
+            Err(UziErr::BadSearchState)
+        );
+        assert!(eng.wait_best_move().await.is_ok());
+        assert!(!eng.is_searching());
+        assert_eq!(
+            eng.wait_best_move().await.map_err(UziErr::into_root),
+            Err(UziErr::BadSearchState)
+        );
+    }
+
+    #[tokio::test]
+    async fn go_after_stop_reads_previous_best_move() {
+        // Searches until stop, numbering the searches in the best move.
+        let script = r#"
+            n=2
+            while read -r line; do
+                case "$line" in
+                    go*) n=$((n + 1)); echo "info depth 1";;
+                    stop) echo "bestmove a2a$n";;
+                esac
+            done
+        "#;
+        let mut go = Go::new();
+        go.set_infinite();
+        let mut eng = Engine::new(fake_engine(script));
+        eng.go(&go).await.unwrap();
+        eng.stop().await.unwrap();
+
+        // The bestmove of the first search is not mistaken for the second one.
+        let mut search = eng.go(&go).await.unwrap();
+        search.stop().await.unwrap();
+        assert_eq!(
+            search.wait().await,
+            Ok((Pm::from_str("a2a4").unwrap(), None))
+        );
+        assert!(!eng.is_searching());
+    }
+}
@@ -0,0 +1,1714 @@
+// This module contains the UciEngine trait and the run loop that drives it, so
+// that engine authors only have to implement the chess logic. The run loop
+// reads the commands from the GUI, answers the protocol commands itself, e.g.
+// uci and isready, and forwards the rest to the engine.
+
+use crate::board::Board;
+use crate::cmdwriter::CmdWriter;
+use crate::conv::to_number;
+use crate::diag::{CompilerInfo, DiagCmd, EvalReport};
+use crate::engcmd::{CheckStatus, EngCmd, Info};
+use crate::engtx::EngTx;
+use crate::err::UziErr;
+use crate::framing::LineReader;
+use crate::game::{GamePos, GameTracker};
+use crate::guicmd::{Go, GuiCmd, Pos, Register};
+use crate::optreg::{OptValue, OptionRegistry};
+use crate::pm::Pm;
+use crate::signals::forward_signals;
+use crate::trace::{self, Direction};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncRead;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc as tokio_mpsc;
+
+// A chess engine driven by run. Only the search is mandatory; the other
+// callbacks do nothing by default. Apart from the search, the callbacks are not
+// called while the engine searches: the commands that need the engine wait for
+// the search to end.
+pub trait UciEngine {
+    // Called with a value set by the GUI for one of the options declared with
+    // the runner. The name is spelled as declared, and the value has been
+    // checked against the declaration.
+    fn set_option(&mut self, _name: &str, _value: OptValue) {}
+
+    // Called when the GUI switches debug mode. If this arrives during a search,
+    // the engine gets it after the search, while the search itself sees the
+    // change through InfoSender::is_debug.
+    fn debug(&mut self, _is_enabled: bool) {}
+
+    // Called on ucinewgame, and before the first search of a game the GUI did
+    // not announce with it.
+    fn new_game(&mut self) {}
+
+    // Searches the position and returns the best move, with the move to ponder
+    // on if any. The runner keeps track of the position commands, and pos tells
+    // what changed since the previous search. This runs on a worker thread, so
+    // that the run loop keeps answering isready. The search should check stop
+    // regularly, and return as soon as it is stopped. Info updates are sent
+    // through info.
+    fn go(
+        &mut self,
+        pos: &GamePos,
+        go: &Go,
+        stop: &StopFlag,
+        info: &InfoSender,
+    ) -> (Pm, Option<Pm>);
+
+    // Called before run returns, after the search has stopped.
+    fn quit(&mut self) {}
+
+    // Called after uciok. Copyprotected engines check their copy here and
+    // return whether it is valid, which the runner reports to the GUI. Other
+    // engines return None.
+    fn check_copy_protection(&mut self) -> Option<bool> {
+        None
+    }
+
+    // Called after uciok. Engines that need registration return whether they
+    // are registered, which the runner reports to the GUI. Other engines
+    // return None. An engine that is not registered keeps working, and
+    // decides itself which features it leaves out.
+    fn check_registration(&mut self) -> Option<bool> {
+        None
+    }
+
+    // Called on register, with the name and code entered by the user or with
+    // Register::Later. Returns whether the engine is registered now, which the
+    // runner reports to the GUI unless the user registers later.
+    fn register(&mut self, _register: &Register) -> bool {
+        false
+    }
+
+    // Runs the bench, i.e. a fixed set of searches used to check that a change
+    // does not alter the search, and to measure the speed of the engine. The
+    // depth is set if the GUI passed one. Engines without a bench return None.
+    fn on_bench(&mut self, _depth: Option<u16>) -> Option<Bench> {
+        None
+    }
+
+    // Counts the leaf nodes of the move tree of the position to the given
+    // depth, for each legal move. Engines without perft return None.
+    fn on_perft(&mut self, _pos: &Pos, _depth: u16) -> Option<Vec<(Pm, u64)>> {
+        None
+    }
+
+    // Evaluates the position statically, for the eval command of Stockfish.
+    // Engines without it return None.
+    fn on_eval(&mut self, _pos: &Pos) -> Option<EvalReport> {
+        None
+    }
+
+    // Tells how the engine was built, for the compiler command of Stockfish.
+    // Engines without it return None.
+    fn on_compiler(&mut self) -> Option<CompilerInfo> {
+        None
+    }
+}
+
+// The result of a bench.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Bench {
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+impl Bench {
+    // Returns the nodes per second, or 0 if no time elapsed.
+    pub fn nps(&self) -> u64 {
+        let millis = self.time.as_millis();
+        if millis == 0 {
+            return 0;
+        }
+        (u128::from(self.nodes) * 1000 / millis) as u64
+    }
+}
+
+// Tells a running search that the GUI sent stop or quit. The search also learns
+// about ponderhit through it, since the engine is busy while it searches.
+#[derive(Clone, Debug, Default)]
+pub struct StopFlag {
+    state: Arc<FlagState>,
+}
+
+#[derive(Debug, Default)]
+struct FlagState {
+    stopped: AtomicBool,
+    pondering: AtomicBool,
+    // Used to wait for stop or ponderhit.
+    lock: Mutex<()>,
+    changed: Condvar,
+}
+
+impl StopFlag {
+    // Creates the flag for a search, which starts pondering if is_pondering is
+    // true.
+    pub fn new(is_pondering: bool) -> Self {
+        let flag = Self::default();
+        flag.state.pondering.store(is_pondering, Ordering::Release);
+        flag
+    }
+
+    // Returns true if the search has to stop.
+    pub fn is_stopped(&self) -> bool {
+        self.state.stopped.load(Ordering::Acquire)
+    }
+
+    // Returns true while the search ponders, i.e. until the opponent plays the
+    // move the engine was pondering on. The search should not use its clock
+    // until then, and it continues as a normal search afterwards.
+    pub fn is_pondering(&self) -> bool {
+        self.state.pondering.load(Ordering::Acquire)
+    }
+
+    pub fn stop(&self) {
+        self.update(|state| state.stopped.store(true, Ordering::Release));
+    }
+
+    pub fn ponderhit(&self) {
+        self.update(|state| state.pondering.store(false, Ordering::Release));
+    }
+
+    // Waits until the search is stopped or no longer ponders.
+    pub(crate) fn wait_for_ponder_end(&self) {
+        let mut guard = self.state.lock.lock().unwrap();
+        while self.is_pondering() && !self.is_stopped() {
+            guard = self.state.changed.wait(guard).unwrap();
+        }
+    }
+
+    // Changes the state under the lock, so that a waiter cannot miss it.
+    fn update(&self, change: impl FnOnce(&FlagState)) {
+        let _guard = self.state.lock.lock().unwrap();
+        change(&self.state);
+        self.state.changed.notify_all();
+    }
+}
+
+// Writes the engine output to the GUI. This can be cloned and sent to the
+// search thread. Lines are queued and written on a thread of their own, so a
+// GUI that is slow to read never blocks the search. Lines are written whole, so
+// output from several threads is not interleaved.
+#[derive(Clone)]
+pub struct UciOut {
+    tx: mpsc::Sender<Outgoing>,
+    // The first write error, which fails every send after it.
+    err: Arc<Mutex<Option<UziErr>>>,
+}
+
+// What the writer thread is asked to do.
+enum Outgoing {
+    Lines(Vec<String>),
+    // Signals once the lines queued before it have been written.
+    Flush(mpsc::Sender<()>),
+}
+
+impl UciOut {
+    pub fn new<W: Write + Send + 'static>(out: W) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let err = Arc::new(Mutex::new(None));
+        let writer_err = Arc::clone(&err);
+        thread::spawn(move || write_lines(out, rx, writer_err));
+        Self { tx, err }
+    }
+
+    // Queues a command for the GUI.
+    pub fn send(&self, cmd: &EngCmd) -> Result<(), UziErr> {
+        self.send_lines(&[cmd.to_string()])
+    }
+
+    // Queues lines that are not UCI commands, e.g. the output of bench, to be
+    // written at once.
+    pub(crate) fn send_lines(&self, lines: &[String]) -> Result<(), UziErr> {
+        self.check()?;
+        for line in lines {
+            trace::server_line(Direction::Sent, line);
+        }
+        self.tx
+            .send(Outgoing::Lines(lines.to_vec()))
+            .map_err(|_| UziErr::IoErr("output writer is gone".into()))
+    }
+
+    // Waits until the queued lines have been written.
+    pub fn flush(&self) -> Result<(), UziErr> {
+        let (done_tx, done_rx) = mpsc::channel();
+        if self.tx.send(Outgoing::Flush(done_tx)).is_ok() {
+            let _ = done_rx.recv();
+        }
+        self.check()
+    }
+
+    fn check(&self) -> Result<(), UziErr> {
+        match &*self.err.lock().unwrap() {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+// Writes and flushes the lines queued by UciOut until every UciOut is dropped.
+// The lines of a batch are written at once, through a buffer that is reused
+// for every batch. After a write error, the remaining lines are dropped.
+fn write_lines<W: Write>(out: W, rx: mpsc::Receiver<Outgoing>, err: Arc<Mutex<Option<UziErr>>>) {
+    let mut out = CmdWriter::new(out);
+    let mut is_broken = false;
+    for outgoing in rx {
+        match outgoing {
+            Outgoing::Lines(_) if is_broken => (),
+            Outgoing::Lines(lines) => {
+                for line in &lines {
+                    out.push(line);
+                }
+                if let Err(write_err) = out.flush() {
+                    is_broken = true;
+                    *err.lock().unwrap() = Some(write_err);
+                }
+            }
+            Outgoing::Flush(done_tx) => {
+                let _ = done_tx.send(());
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for UciOut {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("UciOut")
+    }
+}
+
+// The search cannot do much if the GUI is gone, so write errors are ignored
+// here. They end the run loop when it writes next.
+impl EngTx for UciOut {
+    fn send_best(&self, best: Pm) {
+        let _ = self.send(&EngCmd::BestMove { best, ponder: None });
+    }
+
+    fn send_ponder(&self, best: Pm, ponder: Pm) {
+        let _ = self.send(&EngCmd::BestMove {
+            best,
+            ponder: Some(ponder),
+        });
+    }
+
+    fn send_info(&self, info: Info) {
+        let _ = self.send(&EngCmd::Info(info));
+    }
+}
+
+// Identifies the engine to the GUI in reply to uci.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EngineMeta {
+    pub name: String,
+    // Appended to the name, unless it is empty.
+    pub version: String,
+    pub authors: Vec<String>,
+}
+
+impl EngineMeta {
+    pub fn new(name: &str, version: &str, authors: &[&str]) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+            authors: authors.iter().map(|author| author.to_string()).collect(),
+        }
+    }
+
+    // The value of "id name".
+    pub fn id_name(&self) -> String {
+        if self.version.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} {}", self.name, self.version)
+        }
+    }
+
+    // The value of "id author".
+    pub fn id_author(&self) -> String {
+        self.authors.join(", ")
+    }
+}
+
+// The UCI spec recommends sending periodic info updates, e.g. nps, about once a
+// second.
+const INFO_INTERVAL: Duration = Duration::from_secs(1);
+
+// Sends the info updates of a search to the GUI. This can be cloned and used
+// from any thread. Updates with a pv, score, string or refutation are sent
+// right away. Other updates only carry periodic fields, e.g. nps, hashfull or
+// currmove, and are sent at most once per interval, unless they have a depth
+// other than the one last sent, e.g. at the start of an iteration. An update
+// that arrives too early is held, replacing an older held one. The next update
+// that is sent replaces it as well, since it is newer, so the held one is only
+// sent before the best move.
+#[derive(Clone, Debug)]
+pub struct InfoSender {
+    out: UciOut,
+    state: Arc<Mutex<InfoState>>,
+    // The debug mode set by the GUI, shared with the runner.
+    debug: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+struct InfoState {
+    interval: Duration,
+    last_periodic: Option<Instant>,
+    held: Option<Info>,
+    // The depth of the last update sent that had one.
+    depth: Option<u16>,
+}
+
+impl InfoSender {
+    pub(crate) fn new(out: UciOut, interval: Duration, debug: Arc<AtomicBool>) -> Self {
+        Self {
+            out,
+            state: Arc::new(Mutex::new(InfoState {
+                interval,
+                last_periodic: None,
+                held: None,
+                depth: None,
+            })),
+            debug,
+        }
+    }
+
+    // Returns true if the GUI enabled debug mode.
+    pub fn is_debug(&self) -> bool {
+        self.debug.load(Ordering::Acquire)
+    }
+
+    // Sends the message as info string, but only in debug mode.
+    pub fn debug(&self, msg: &str) {
+        if self.is_debug() {
+            self.send(Info::from_string(msg));
+        }
+    }
+
+    pub fn send(&self, info: Info) {
+        let now = Instant::now();
+        // The lock is held while writing, so that the updates are written in
+        // the order they were accepted.
+        let mut state = self.state.lock().unwrap();
+        let is_new_depth = info.depth().is_some_and(|depth| Some(depth) != state.depth);
+        state.depth = info.depth().or(state.depth);
+        if !is_periodic(&info) {
+            // TODO: log the error.
+            let _ = self.out.send(&EngCmd::Info(info));
+            return;
+        }
+        let is_due = is_new_depth
+            || state
+                .last_periodic
+                .is_none_or(|last| now.duration_since(last) >= state.interval);
+        if is_due {
+            state.held = None;
+            state.last_periodic = Some(now);
+            let _ = self.out.send(&EngCmd::Info(info));
+        } else {
+            state.held = Some(info);
+        }
+    }
+
+    // Sends the held update, if any. This is called before the best move, so
+    // that the GUI gets the final numbers of the search.
+    pub(crate) fn flush(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(info) = state.held.take() {
+            let _ = self.out.send(&EngCmd::Info(info));
+        }
+    }
+}
+
+// Returns true if the update only has fields that change all the time, e.g.
+// nodes or the current move, rather than a result of the search.
+fn is_periodic(info: &Info) -> bool {
+    info.pv().is_none()
+        && info.score().is_none()
+        && info.string().is_none()
+        && info.refutation().is_none()
+}
+
+// Runs the engine with the GUI on stdin and stdout, until the GUI sends quit or
+// closes stdin. This is a shortcut for an engine without options.
+pub fn run<E: UciEngine + Send + 'static>(meta: EngineMeta, engine: E) -> Result<(), UziErr> {
+    Runner::new(meta, engine).run()
+}
+
+// Runs an engine, together with its metadata and the options it declares. The
+// runner answers uci and isready itself, so that isready is answered while the
+// engine searches.
+#[derive(Debug)]
+pub struct Runner<E> {
+    meta: EngineMeta,
+    engine: E,
+    options: OptionRegistry,
+    strict: bool,
+}
+
+impl<E: UciEngine + Send + 'static> Runner<E> {
+    pub fn new(meta: EngineMeta, engine: E) -> Self {
+        Self {
+            meta,
+            engine,
+            options: OptionRegistry::new(),
+            strict: false,
+        }
+    }
+
+    // Sets the options declared to the GUI after uci. Values set by the GUI
+    // for other options, or values that do not fit the declaration, are not
+    // passed to the engine.
+    pub fn set_options(&mut self, options: OptionRegistry) -> &mut Self {
+        self.options = options;
+        self
+    }
+
+    // Enables strict mode, which checks the order of the commands from the GUI,
+    // e.g. to test an engine with a sloppy GUI. Commands that only make sense
+    // later, e.g. setoption during a search, are queued until the search ends.
+    // Commands that make no sense, e.g. go before any position, are dropped.
+    // Either way, the GUI gets an info string explaining why.
+    pub fn set_strict(&mut self, is_strict: bool) -> &mut Self {
+        self.strict = is_strict;
+        self
+    }
+
+    // Runs the engine with the GUI on stdin and stdout, until the GUI sends
+    // quit or closes stdin. SIGINT and SIGTERM, or Ctrl-C on Windows, stop
+    // the search and quit, so that the engine shuts down cleanly.
+    // Tools like OpenBench run the engine with bench as argument, in which case
+    // this runs the bench and returns.
+    pub fn run(self) -> Result<(), UziErr> {
+        let mut args = std::env::args().skip(1);
+        if args.next().as_deref() == Some("bench") {
+            let cmd = std::iter::once("bench".to_string()).chain(args);
+            let lines = std::iter::once(Ok(cmd.collect::<Vec<_>>().join(" ")));
+            return self.run_lines(lines, UciOut::new(io::stdout()));
+        }
+        let mut rx = stdin_lines()?;
+        let lines = std::iter::from_fn(move || rx.blocking_recv());
+        self.run_lines(lines, UciOut::new(io::stdout()))
+    }
+
+    // Like run, with the GUI commands read from input and the output written
+    // to out.
+    pub fn run_with<R: BufRead>(self, input: R, out: UciOut) -> Result<(), UziErr> {
+        self.run_lines(input.lines().map(|line| line.map_err(UziErr::from)), out)
+    }
+
+    // Like run, for an engine that lives in a tokio runtime. The run loop and
+    // the searches run on blocking threads of the runtime, so the search can
+    // use the runtime, e.g. with Handle::current().block_on.
+    pub async fn run_async(self) -> Result<(), UziErr> {
+        self.run_channel(stdin_lines()?, UciOut::new(io::stdout()))
+            .await
+    }
+
+    // Like run_async, with the GUI commands read from input and the output
+    // written to out.
+    pub async fn run_with_async<R>(self, input: R, out: UciOut) -> Result<(), UziErr>
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        let (tx, rx) = tokio_mpsc::unbounded_channel();
+        let reader = tokio::spawn(async move {
+            let mut lines = LineReader::new(input);
+            loop {
+                let line = match lines.next_line().await {
+                    Ok(Some(line)) => Ok(line),
+                    Ok(None) => break,
+                    // A GUI command is never that long, so the line is junk.
+                    Err(UziErr::LineTooLong(_)) => continue,
+                    Err(err) => Err(err),
+                };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let result = self.run_channel(rx, out).await;
+        reader.abort();
+        result
+    }
+
+    // Runs the loop on a blocking thread, with the lines received from rx.
+    async fn run_channel(
+        self,
+        mut rx: tokio_mpsc::UnboundedReceiver<Result<String, UziErr>>,
+        out: UciOut,
+    ) -> Result<(), UziErr> {
+        let lines = std::iter::from_fn(move || rx.blocking_recv());
+        tokio::task::spawn_blocking(move || self.run_lines(lines, out))
+            .await
+            .map_err(|err| UziErr::IoErr(err.to_string()))?
+    }
+
+    fn run_lines<I>(self, lines: I, out: UciOut) -> Result<(), UziErr>
+    where
+        I: Iterator<Item = Result<String, UziErr>>,
+    {
+        let mut session = Session::new(self, out.clone());
+        for line in lines {
+            if !session.on_line(&line?)? {
+                break;
+            }
+        }
+        session.quit();
+        out.flush()
+    }
+}
+
+// What strict mode does with a command from the GUI.
+enum Verdict {
+    Accept,
+    // The command waits for the search to end, for the given reason.
+    Queue(&'static str),
+    // The command is dropped, for the given reason.
+    Reject(&'static str),
+}
+
+// The state of the run loop.
+struct Session<E> {
+    meta: EngineMeta,
+    options: OptionRegistry,
+    engine: Arc<Mutex<E>>,
+    out: UciOut,
+    search: Option<Search>,
+    debug: Arc<AtomicBool>,
+    pending_debug: Option<bool>,
+    game: GameTracker,
+    strict: bool,
+    got_uci: bool,
+    // True if a position was sent since the start or the last ucinewgame.
+    got_pos: bool,
+    // The commands held back until the search ends.
+    queued: VecDeque<String>,
+}
+
+impl<E: UciEngine + Send + 'static> Session<E> {
+    fn new(runner: Runner<E>, out: UciOut) -> Self {
+        Self {
+            meta: runner.meta,
+            options: runner.options,
+            engine: Arc::new(Mutex::new(runner.engine)),
+            out,
+            search: None,
+            debug: Arc::new(AtomicBool::new(false)),
+            pending_debug: None,
+            game: GameTracker::default(),
+            strict: runner.strict,
+            got_uci: false,
+            got_pos: false,
+            queued: VecDeque::new(),
+        }
+    }
+
+    // Handles a line from the GUI. Returns false after quit.
+    fn on_line(&mut self, line: &str) -> Result<bool, UziErr> {
+        trace::server_line(Direction::Received, line);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let name = match words.as_slice() {
+            [] => return Ok(true),
+            // Some tools send perft as an option of go.
+            ["go", "perft", ..] => "perft",
+            [name, ..] => *name,
+        };
+        // A search that was stopped ends soon, so go and isready wait for it
+        // rather than being rejected or answered before the queued commands.
+        let is_sync = name == "go" || name == "isready";
+        self.settle(is_sync && (self.strict || !self.queued.is_empty()))?;
+
+        if self.strict {
+            match self.check(name) {
+                Verdict::Accept => (),
+                Verdict::Queue(reason) => {
+                    self.diagnose(&format!("queued \"{}\": {}", line, reason))?;
+                    self.queued.push_back(line.into());
+                    return Ok(true);
+                }
+                Verdict::Reject(reason) => {
+                    self.diagnose(&format!("rejected \"{}\": {}", line, reason))?;
+                    return Ok(true);
+                }
+            }
+        } else if self.search.is_some()
+            && (waits_for_search(name) || name == "debug" && !self.queued.is_empty())
+        {
+            // The search holds the engine, and the run loop must not block on
+            // it, or it would never read stop. A debug change after a command
+            // that waits also waits, so that the engine gets them in order.
+            self.queued.push_back(line.into());
+            return Ok(true);
+        }
+        self.on_cmd(line)
+    }
+
+    // Checks the order of the commands in strict mode.
+    fn check(&self, name: &str) -> Verdict {
+        let search = self.search.as_ref();
+        let is_searching = search.is_some();
+        let is_stopping = search.is_some_and(|search| search.stop.is_stopped());
+        let is_pondering = search.is_some_and(|search| search.stop.is_pondering());
+        match name {
+            "uci" if is_searching => Verdict::Queue("the engine is searching"),
+            "uci" | "quit" => Verdict::Accept,
+            _ if !self.got_uci => Verdict::Reject("uci was not sent"),
+            "isready" | "debug" => Verdict::Accept,
+            "bench" | "perft" | "eval" | "compiler" if is_searching => {
+                Verdict::Reject("the engine is searching")
+            }
+            "setoption" | "position" | "ucinewgame" | "register" | "flip" if is_searching => {
+                Verdict::Queue("the engine is searching")
+            }
+            "go" if is_searching && !is_stopping => Verdict::Reject("the engine is searching"),
+            "go" if !self.got_pos => Verdict::Reject("no position was sent"),
+            "stop" if !is_searching => Verdict::Reject("the engine is not searching"),
+            "ponderhit" if !is_pondering => Verdict::Reject("the engine is not pondering"),
+            _ => Verdict::Accept,
+        }
+    }
+
+    // Forgets the search if it ended, or waits for it to end if wait is true
+    // and the search was stopped. The queued commands run afterwards.
+    fn settle(&mut self, wait: bool) -> Result<(), UziErr> {
+        let Some(ref search) = self.search else {
+            return Ok(());
+        };
+        let is_over = search.is_finished() || wait && search.stop.is_stopped();
+        if !is_over {
+            return Ok(());
+        }
+        if let Some(search) = self.search.take() {
+            search.join();
+        }
+        while let Some(line) = self.queued.pop_front() {
+            self.on_cmd(&line)?;
+        }
+        Ok(())
+    }
+
+    // Sends a diagnostic of strict mode to the GUI.
+    fn diagnose(&self, msg: &str) -> Result<(), UziErr> {
+        self.out.send(&EngCmd::Info(Info::from_string(msg)))
+    }
+
+    fn on_cmd(&mut self, line: &str) -> Result<bool, UziErr> {
+        // setoption is parsed with the declared options, since GuiCmd only
+        // knows the standard ones.
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.first() == Some(&"setoption") {
+            return match self.options.parse_set(&words) {
+                Ok((name, value)) => {
+                    lock_engine(&self.engine, &mut self.pending_debug).set_option(name, value);
+                    Ok(true)
+                }
+                Err(err) => self.ignore(line, err),
+            };
+        }
+        match words.as_slice() {
+            ["bench", args @ ..] => return self.on_bench(line, args),
+            ["perft", args @ ..] | ["go", "perft", args @ ..] => return self.on_perft(line, args),
+            _ => (),
+        }
+        if let Ok(cmd) = DiagCmd::from_str(line) {
+            return self.on_diag(line, cmd);
+        }
+
+        let cmd = match GuiCmd::from_str(line) {
+            Ok(cmd) => cmd,
+            Err(err) => return self.ignore(line, err),
+        };
+        match cmd {
+            GuiCmd::Uci => {
+                self.got_uci = true;
+                self.out.send(&EngCmd::IdName(self.meta.id_name()))?;
+                self.out.send(&EngCmd::IdAuthor(self.meta.id_author()))?;
+                for opt in self.options.iter() {
+                    self.out.send(&EngCmd::HasOpt(opt))?;
+                }
+                self.out.send(&EngCmd::UciOk)?;
+                self.check_license()?;
+            }
+            GuiCmd::IsReady => self.out.send(&EngCmd::ReadyOk)?,
+            GuiCmd::Debug(is_enabled) => {
+                self.debug.store(is_enabled, Ordering::Release);
+                // Do not wait for a running search.
+                match self.engine.try_lock() {
+                    Ok(mut engine) => engine.debug(is_enabled),
+                    Err(_) => self.pending_debug = Some(is_enabled),
+                }
+            }
+            // Parsed with the declared options above.
+            GuiCmd::SetOpt(_) => (),
+            GuiCmd::NewGame => {
+                self.got_pos = false;
+                self.game.on_new_game();
+                lock_engine(&self.engine, &mut self.pending_debug).new_game();
+            }
+            GuiCmd::Pos(pos) => {
+                self.got_pos = true;
+                self.game.on_position(pos);
+            }
+            GuiCmd::Go(mut go) => {
+                // A go without limits searches until stop, like go infinite.
+                if !go.has_any() {
+                    go.set_infinite();
+                }
+                // The previous search has sent its best move, unless the GUI
+                // did not wait for it. Stop it then, rather than running two
+                // searches, and run the commands that waited for it.
+                if let Some(ref search) = self.search {
+                    search.stop.stop();
+                }
+                self.settle(true)?;
+                // This also delivers a pending debug change before the next
+                // search.
+                let (pos, missed_new_game) = self.game.on_go();
+                let mut engine = lock_engine(&self.engine, &mut self.pending_debug);
+                if missed_new_game {
+                    engine.new_game();
+                }
+                drop(engine);
+                let info = InfoSender::new(self.out.clone(), INFO_INTERVAL, self.debug.clone());
+                self.search = Some(Search::start(self.engine.clone(), pos, go, info));
+            }
+            GuiCmd::Stop => {
+                if let Some(ref search) = self.search {
+                    search.stop.stop();
+                }
+            }
+            GuiCmd::Register(register) => {
+                let is_registered =
+                    lock_engine(&self.engine, &mut self.pending_debug).register(&register);
+                if register != Register::Later {
+                    self.out
+                        .send(&EngCmd::Registration(CheckStatus::Checking))?;
+                    self.out
+                        .send(&EngCmd::Registration(CheckStatus::from_result(
+                            is_registered,
+                        )))?;
+                }
+            }
+            GuiCmd::Ponderhit => {
+                if let Some(ref search) = self.search {
+                    search.stop.ponderhit();
+                }
+            }
+            GuiCmd::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // Runs the bench, and prints the result the way OpenBench expects it.
+    fn on_bench(&mut self, line: &str, args: &[&str]) -> Result<bool, UziErr> {
+        let depth = match args
+            .first()
+            .map(|depth| to_number::<u16>(depth))
+            .transpose()
+        {
+            Ok(depth) => depth,
+            Err(err) => return self.ignore(line, err),
+        };
+        let bench = lock_engine(&self.engine, &mut self.pending_debug).on_bench(depth);
+        let Some(bench) = bench else {
+            return self.ignore(line, UziErr::What);
+        };
+        let result = format!("{} nodes {} nps", bench.nodes, bench.nps());
+        self.out.send_lines(&[result])?;
+        Ok(true)
+    }
+
+    // Runs perft on the current position, and prints the count for each move
+    // and the total, like most engines do.
+    fn on_perft(&mut self, line: &str, args: &[&str]) -> Result<bool, UziErr> {
+        let depth = match args.first().map(|depth| to_number::<u16>(depth)) {
+            Some(Ok(depth)) => depth,
+            Some(Err(err)) => return self.ignore(line, err),
+            None => return self.ignore(line, UziErr::MissingCmd),
+        };
+        let counts =
+            lock_engine(&self.engine, &mut self.pending_debug).on_perft(self.game.pos(), depth);
+        let Some(counts) = counts else {
+            return self.ignore(line, UziErr::What);
+        };
+        let mut lines: Vec<String> = counts
+            .iter()
+            .map(|(pm, count)| format!("{}: {}", pm, count))
+            .collect();
+        let total: u64 = counts.iter().map(|(_, count)| count).sum();
+        lines.push(String::new());
+        lines.push(format!("Nodes searched: {}", total));
+        self.out.send_lines(&lines)?;
+        Ok(true)
+    }
+
+    // Answers the diagnostic commands of Stockfish. The runner flips the
+    // position itself, since it keeps track of it for the engine.
+    fn on_diag(&mut self, line: &str, cmd: DiagCmd) -> Result<bool, UziErr> {
+        let reply = match cmd {
+            DiagCmd::Eval => lock_engine(&self.engine, &mut self.pending_debug)
+                .on_eval(self.game.pos())
+                .map(|report| report.to_string()),
+            DiagCmd::Compiler => lock_engine(&self.engine, &mut self.pending_debug)
+                .on_compiler()
+                .map(|info| info.to_string()),
+            DiagCmd::Flip => {
+                let Some(board) = Board::from_pos(self.game.pos()) else {
+                    return self.ignore(line, UziErr::Position);
+                };
+                self.game.on_position(Pos::with_fen(&board.flip().to_fen()));
+                return Ok(true);
+            }
+        };
+        let Some(reply) = reply else {
+            return self.ignore(line, UziErr::What);
+        };
+        self.out
+            .send_lines(&reply.lines().map(str::to_string).collect::<Vec<_>>())?;
+        Ok(true)
+    }
+
+    // Drops a command that could not be handled, with a diagnostic in strict
+    // mode.
+    fn ignore(&self, line: &str, err: UziErr) -> Result<bool, UziErr> {
+        if self.strict {
+            self.diagnose(&format!("ignored \"{}\": {:?}", line, err))?;
+        }
+        trace::server_ignored(line, &err);
+        Ok(true)
+    }
+
+    // Reports the copyprotection and registration checks of the engine after
+    // uciok, for engines that have them.
+    fn check_license(&mut self) -> Result<(), UziErr> {
+        let mut engine = lock_engine(&self.engine, &mut self.pending_debug);
+        if let Some(is_ok) = engine.check_copy_protection() {
+            self.out
+                .send(&EngCmd::CopyProtection(CheckStatus::Checking))?;
+            self.out
+                .send(&EngCmd::CopyProtection(CheckStatus::from_result(is_ok)))?;
+        }
+        if let Some(is_ok) = engine.check_registration() {
+            self.out
+                .send(&EngCmd::Registration(CheckStatus::Checking))?;
+            self.out
+                .send(&EngCmd::Registration(CheckStatus::from_result(is_ok)))?;
+        }
+        Ok(())
+    }
+
+    // Stops the search, runs the commands that waited for it and lets the
+    // engine clean up, after quit or when the input ends.
+    fn quit(mut self) {
+        if let Some(ref search) = self.search {
+            search.stop.stop();
+        }
+        // TODO: log the error.
+        let _ = self.settle(true);
+        lock_engine(&self.engine, &mut self.pending_debug).quit();
+    }
+}
+
+// Returns the lines read from stdin, followed by stop and quit if the process
+// gets a signal to end. stdin is read on a thread of its own, rather than with
+// tokio::io::stdin, which keeps a runtime from shutting down until the GUI
+// closes stdin.
+fn stdin_lines() -> Result<tokio_mpsc::UnboundedReceiver<Result<String, UziErr>>, UziErr> {
+    let (tx, rx) = tokio_mpsc::unbounded_channel();
+    forward_signals(tx.clone())?;
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            if tx.send(line.map_err(UziErr::from)).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(rx)
+}
+
+// Returns true for the commands that need the engine, or that change the
+// position, which wait for a running search to end.
+fn waits_for_search(name: &str) -> bool {
+    matches!(
+        name,
+        "uci"
+            | "setoption"
+            | "ucinewgame"
+            | "position"
+            | "register"
+            | "bench"
+            | "perft"
+            | "eval"
+            | "compiler"
+            | "flip"
+    )
+}
+
+// Locks the engine, passing on a debug change that arrived while it searched.
+fn lock_engine<'a, E: UciEngine>(
+    engine: &'a Mutex<E>,
+    pending_debug: &mut Option<bool>,
+) -> MutexGuard<'a, E> {
+    let mut engine = engine.lock().unwrap();
+    if let Some(is_enabled) = pending_debug.take() {
+        engine.debug(is_enabled);
+    }
+    engine
+}
+
+// A search running on a worker thread.
+struct Search {
+    stop: StopFlag,
+    worker: Worker,
+}
+
+// The thread of a search. Inside a tokio runtime, the search runs on a blocking
+// thread of the runtime, so that it can use the runtime.
+enum Worker {
+    Thread(JoinHandle<()>),
+    Task(Handle, tokio::task::JoinHandle<()>),
+}
+
+impl Search {
+    fn start<E>(engine: Arc<Mutex<E>>, pos: GamePos, go: Go, info: InfoSender) -> Self
+    where
+        E: UciEngine + Send + 'static,
+    {
+        let stop = StopFlag::new(go.is_ponder());
+        let flag = stop.clone();
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let search = move || {
+            let mut engine = engine.lock().unwrap();
+            let _ = locked_tx.send(());
+            let (best, ponder) = engine.go(&pos, &go, &flag, &info);
+            drop(engine);
+            info.flush();
+            // The engine must not send the best move while it ponders, even if
+            // the search ended, e.g. at its depth limit. The GUI sends either
+            // ponderhit or stop.
+            flag.wait_for_ponder_end();
+            // TODO: log the error.
+            let _ = info.out.send(&EngCmd::BestMove { best, ponder });
+        };
+        let worker = match Handle::try_current() {
+            Ok(handle) => Worker::Task(handle.clone(), handle.spawn_blocking(search)),
+            Err(_) => Worker::Thread(thread::spawn(search)),
+        };
+        // The search has the engine before the run loop reads the next
+        // command, so that the command cannot get ahead of the search.
+        let _ = locked_rx.recv();
+        Self { stop, worker }
+    }
+
+    // Returns true if the search sent its best move.
+    fn is_finished(&self) -> bool {
+        match self.worker {
+            Worker::Thread(ref worker) => worker.is_finished(),
+            Worker::Task(_, ref worker) => worker.is_finished(),
+        }
+    }
+
+    // Waits for the search to send its best move.
+    fn join(self) {
+        // The engine panicked, and the lock is poisoned. The next command that
+        // needs the engine panics too.
+        match self.worker {
+            Worker::Thread(worker) => {
+                let _ = worker.join();
+            }
+            Worker::Task(handle, worker) => {
+                let _ = handle.block_on(worker);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // An engine that records the forwarded commands and always plays e2e4.
+    #[derive(Default)]
+    struct Recorder {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Recorder {
+        fn record(&self, call: String) {
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    impl UciEngine for Recorder {
+        fn set_option(&mut self, name: &str, value: OptValue) {
+            self.record(format!("{} {:?}", name, value));
+        }
+
+        fn debug(&mut self, is_enabled: bool) {
+            self.record(format!("debug {}", is_enabled));
+        }
+
+        fn new_game(&mut self) {
+            self.record("new_game".into());
+        }
+
+        fn go(
+            &mut self,
+            pos: &GamePos,
+            go: &Go,
+            stop: &StopFlag,
+            sender: &InfoSender,
+        ) -> (Pm, Option<Pm>) {
+            self.record(pos.pos().to_string());
+            sender.send(info("info depth 1 pv e2e4"));
+            sender.debug("searching");
+            // Ponder until ponderhit, then search until stopped.
+            if go.is_ponder() {
+                while stop.is_pondering() && !stop.is_stopped() {
+                    thread::sleep(std::time::Duration::from_millis(1));
+                }
+                let call = if stop.is_pondering() {
+                    "stopped"
+                } else {
+                    "ponderhit"
+                };
+                self.record(call.into());
+            }
+            // Search until stopped, like go infinite.
+            if go.is_infinite() {
+                while !stop.is_stopped() {
+                    thread::sleep(std::time::Duration::from_millis(1));
+                }
+                self.record("stopped".into());
+            }
+            (Pm::from_str("e2e4").unwrap(), None)
+        }
+
+        fn quit(&mut self) {
+            self.record("quit".into());
+        }
+
+        fn on_bench(&mut self, depth: Option<u16>) -> Option<Bench> {
+            self.record(format!("bench {:?}", depth));
+            Some(Bench {
+                nodes: 1000,
+                time: Duration::from_millis(500),
+            })
+        }
+
+        fn on_perft(&mut self, pos: &Pos, depth: u16) -> Option<Vec<(Pm, u64)>> {
+            self.record(format!("perft {} {}", depth, pos));
+            Some(vec![
+                (Pm::from_str("d2d4").unwrap(), 20),
+                (Pm::from_str("e2e4").unwrap(), 22),
+            ])
+        }
+
+        fn on_eval(&mut self, pos: &Pos) -> Option<EvalReport> {
+            self.record(format!("eval {}", pos));
+            Some(EvalReport {
+                details: vec!["Material 0".into()],
+                final_eval: Some(-25),
+            })
+        }
+
+        fn on_compiler(&mut self) -> Option<CompilerInfo> {
+            Some(CompilerInfo {
+                compiler: "rustc 1.80 on Linux".into(),
+                details: Vec::new(),
+            })
+        }
+    }
+
+    fn info(line: &str) -> Info {
+        match EngCmd::from_str(line) {
+            Ok(EngCmd::Info(info)) => info,
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+    }
+
+    fn meta() -> EngineMeta {
+        EngineMeta::new("Recorder", "1.0", &["uzi", "others"])
+    }
+
+    // A writer that can be inspected after the run loop took ownership of it.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn run_with_engine() {
+        let input = "uci\nisready\nsetoption name Hash value 8\nsetoption name Hash value 99\n\
+                     setoption name Threads value 2\nbogus\nucinewgame\n\
+                     position startpos moves e2e4\ngo depth 1\nquit\nisready\n";
+        let engine = Recorder::default();
+        let calls = engine.calls.clone();
+        let buf = SharedBuf::default();
+        let mut options = OptionRegistry::new();
+        options.add_spin("Hash", 1, 1, 16);
+        let mut runner = Runner::new(meta(), engine);
+        runner.set_options(options);
+        let result = runner.run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            output,
+            "id name Recorder 1.0\nid author uzi, others\n\
+             option name Hash type spin default 1 min 1 max 16\nuciok\nreadyok\n\
+             info depth 1 pv e2e4\nbestmove e2e4\n"
+        );
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "Hash Spin(8)",
+                "new_game",
+                "position startpos moves e2e4",
+                "quit",
+            ]
+        );
+    }
+
+    #[test]
+    fn run_with_infinite_search() {
+        let input = "go infinite\nisready\nstop\nisready\n";
+        let engine = Recorder::default();
+        let calls = engine.calls.clone();
+        let buf = SharedBuf::default();
+        let result =
+            Runner::new(meta(), engine).run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+
+        // The engine is still searching when the first isready arrives, and
+        // the best move can only come after stop.
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        let ready = lines.iter().position(|line| *line == "readyok");
+        let best = lines.iter().position(|line| *line == "bestmove e2e4");
+        assert!(ready < best);
+        let mut sorted = lines.clone();
+        sorted.sort();
+        assert_eq!(
+            sorted,
+            vec![
+                "bestmove e2e4",
+                "info depth 1 pv e2e4",
+                "readyok",
+                "readyok"
+            ]
+        );
+        // The GUI sent neither ucinewgame nor position.
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["new_game", "position startpos", "stopped", "quit"]
+        );
+    }
+
+    #[test]
+    fn run_with_bare_go() {
+        // The client sends go without limits for Go::new.
+        let input = "position startpos\ngo\nisready\nstop\nquit\n";
+        let engine = Recorder::default();
+        let calls = engine.calls.clone();
+        let buf = SharedBuf::default();
+        let result =
+            Runner::new(meta(), engine).run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("readyok\n"));
+        assert!(output.ends_with("bestmove e2e4\n"));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["new_game", "position startpos", "stopped", "quit"]
+        );
+    }
+
+    #[test]
+    fn run_with_new_game_during_search() {
+        // The run loop must not wait for the engine, which the search holds,
+        // or it would never read stop.
+        let input = "go infinite\nucinewgame\nstop\nquit\n";
+        let engine = Recorder::default();
+        let calls = engine.calls.clone();
+        let buf = SharedBuf::default();
+        let result =
+            Runner::new(meta(), engine).run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert!(output.ends_with("bestmove e2e4\n"));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "new_game",
+                "position startpos",
+                "stopped",
+                "new_game",
+                "quit"
+            ]
+        );
+
+        // The commands that need the engine run after the search, before
+        // isready is answered.
+        let input = "go infinite\nucinewgame\nsetoption name Hash value 8\nisready\nstop\n\
+                     isready\nquit\n";
+        let engine = Recorder::default();
+        let calls = engine.calls.clone();
+        let buf = SharedBuf::default();
+        let mut options = OptionRegistry::new();
+        options.add_spin("Hash", 1, 1, 16);
+        let mut runner = Runner::new(meta(), engine);
+        runner.set_options(options);
+        let result = runner.run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert!(output.ends_with("bestmove e2e4\nreadyok\n"));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "new_game",
+                "position startpos",
+                "stopped",
+                "new_game",
+                "Hash Spin(8)",
+                "quit",
+            ]
+        );
+    }
+
+    #[test]
+    fn run_with_ponder() {
+        let input = "go ponder infinite\nponderhit\nstop\ngo ponder\nstop\nquit\n";
+        let engine = Recorder::default();
+        let calls = engine.calls.clone();
+        let buf = SharedBuf::default();
+        let result =
+            Runner::new(meta(), engine).run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+
+        // Each search sends one best move, also when stopped while pondering.
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let best = output.lines().filter(|line| line.starts_with("bestmove"));
+        assert_eq!(best.count(), 2);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "new_game",
+                "position startpos",
+                "ponderhit",
+                "stopped",
+                "position startpos",
+                "stopped",
+                "quit",
+            ]
+        );
+    }
+
+    #[test]
+    fn stop_flag_holds_ponder_result() {
+        let flag = StopFlag::new(true);
+        let waiter = {
+            let flag = flag.clone();
+            thread::spawn(move || flag.wait_for_ponder_end())
+        };
+        thread::sleep(std::time::Duration::from_millis(20));
+        assert!(!waiter.is_finished());
+
+        flag.ponderhit();
+        waiter.join().unwrap();
+        assert!(!flag.is_pondering());
+        assert!(!flag.is_stopped());
+
+        // A search that does not ponder does not wait.
+        StopFlag::new(false).wait_for_ponder_end();
+    }
+
+    // A GUI pipe that does not take any output until it is released, and then
+    // fails if told to.
+    struct StalledPipe {
+        release: mpsc::Receiver<bool>,
+        buf: SharedBuf,
+    }
+
+    impl Write for StalledPipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if let Ok(is_broken) = self.release.recv() {
+                if is_broken {
+                    return Err(io::ErrorKind::BrokenPipe.into());
+                }
+            }
+            self.buf.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn uci_out_does_not_block_on_stalled_gui() {
+        let buf = SharedBuf::default();
+        let (release, rx) = mpsc::channel();
+        let out = UciOut::new(StalledPipe {
+            release: rx,
+            buf: buf.clone(),
+        });
+        for _ in 0..100 {
+            out.send(&EngCmd::ReadyOk).unwrap();
+        }
+        assert!(buf.0.lock().unwrap().is_empty());
+
+        drop(release);
+        out.flush().unwrap();
+        assert_eq!(buf.0.lock().unwrap().len(), 100 * "readyok\n".len());
+
+        // A write error fails the sends after it.
+        let (release, rx) = mpsc::channel();
+        let out = UciOut::new(StalledPipe {
+            release: rx,
+            buf: SharedBuf::default(),
+        });
+        out.send(&EngCmd::ReadyOk).unwrap();
+        release.send(true).unwrap();
+        assert!(matches!(out.flush(), Err(UziErr::IoErr(_))));
+        assert!(out.send(&EngCmd::ReadyOk).is_err());
+    }
+
+    #[test]
+    fn info_sender_throttles_periodic_updates() {
+        let buf = SharedBuf::default();
+        let out = UciOut::new(buf.clone());
+        let sender = InfoSender::new(out.clone(), Duration::from_millis(50), Arc::default());
+        sender.send(info("info nodes 10 nps 100"));
+        sender.send(info("info nodes 20 nps 200"));
+        sender.send(info("info depth 2 pv e2e4 score cp 5"));
+        sender.send(info("info nodes 30 nps 300 currmove d2d4"));
+        sender.send(info("info string hello"));
+        thread::sleep(Duration::from_millis(60));
+        sender.send(info("info nodes 40 nps 400"));
+        sender.send(info("info nodes 50 hashfull 10 nps 500"));
+        sender.flush();
+        sender.flush();
+        out.flush().unwrap();
+
+        // The update held at 30 nodes was replaced by the one at 40 nodes.
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            output,
+            "info nodes 10 nps 100\n\
+             info depth 2 pv e2e4 score cp 5\n\
+             info string hello\n\
+             info nodes 40 nps 400\n\
+             info nodes 50 hashfull 10 nps 500\n"
+        );
+    }
+
+    #[test]
+    fn info_sender_sends_new_depths() {
+        let buf = SharedBuf::default();
+        let out = UciOut::new(buf.clone());
+        let sender = InfoSender::new(out.clone(), Duration::from_secs(60), Arc::default());
+        sender.send(info("info depth 1 nodes 10"));
+        sender.send(info("info depth 2 nodes 20"));
+        sender.send(info("info depth 2 nodes 30"));
+        sender.send(info("info depth 2 nodes 40"));
+        sender.send(info("info depth 3 seldepth 5 nodes 50"));
+        sender.send(info("info depth 3 nodes 60"));
+        sender.flush();
+        out.flush().unwrap();
+
+        // The updates held at depth 2 were replaced by the one of depth 3, and
+        // the one held at depth 3 is sent before the best move.
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            output,
+            "info depth 1 nodes 10\n\
+             info depth 2 nodes 20\n\
+             info depth 3 seldepth 5 nodes 50\n\
+             info depth 3 nodes 60\n"
+        );
+    }
+
+    #[test]
+    fn run_with_debug() {
+        // ucinewgame, and the debug change after it, wait for the first search
+        // to finish.
+        let input = "debug on\ngo depth 1\nucinewgame\ndebug off\ngo depth 1\nquit\n";
+        let engine = Recorder::default();
+        let calls = engine.calls.clone();
+        let buf = SharedBuf::default();
+        let result =
+            Runner::new(meta(), engine).run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+
+        // Only the first search is in debug mode.
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let debug = output
+            .lines()
+            .filter(|line| *line == "info string searching");
+        assert_eq!(debug.count(), 1);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "debug true",
+                "new_game",
+                "position startpos",
+                "new_game",
+                "debug false",
+                "position startpos",
+                "quit",
+            ]
+        );
+    }
+
+    #[test]
+    fn run_with_debug_during_search() {
+        let input = "go infinite\ndebug on\nstop\nucinewgame\nquit\n";
+        let engine = Recorder::default();
+        let calls = engine.calls.clone();
+        let buf = SharedBuf::default();
+        let result =
+            Runner::new(meta(), engine).run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+
+        // The engine gets the change after the search, unless the search had
+        // not started yet.
+        let calls = calls.lock().unwrap();
+        let debug = calls.iter().position(|call| call == "debug true");
+        let new_game = calls.iter().rposition(|call| call == "new_game");
+        assert!(debug.is_some() && debug < new_game);
+        assert_eq!(calls.len(), 6);
+    }
+
+    // An engine whose search needs the runtime.
+    struct AsyncEval;
+
+    impl UciEngine for AsyncEval {
+        fn go(&mut self, _: &GamePos, _: &Go, _: &StopFlag, _: &InfoSender) -> (Pm, Option<Pm>) {
+            let eval = async {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                Pm::from_str("d2d4").unwrap()
+            };
+            (Handle::current().block_on(eval), None)
+        }
+    }
+
+    #[tokio::test]
+    async fn run_with_async_engine() {
+        let (mut gui, input) = tokio::io::duplex(1024);
+        let buf = SharedBuf::default();
+        let runner = Runner::new(meta(), AsyncEval);
+        let run = tokio::spawn(runner.run_with_async(input, UciOut::new(buf.clone())));
+
+        tokio::io::AsyncWriteExt::write_all(&mut gui, b"uci\nisready\ngo depth 3\nquit\n")
+            .await
+            .unwrap();
+        // The runner returns after quit, while the input is still open.
+        assert_eq!(run.await.unwrap(), Ok(()));
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            output,
+            "id name Recorder 1.0\nid author uzi, others\nuciok\nreadyok\nbestmove d2d4\n"
+        );
+    }
+
+    #[test]
+    fn run_with_strict_mode() {
+        let input = "isready\nuci\ngo depth 1\nposition startpos\ngo infinite\n\
+                     setoption name Hash value 8\ngo depth 1\nponderhit\nstop\nisready\n\
+                     go depth 1\nbogus\nquit\n";
+        let engine = Recorder::default();
+        let calls = engine.calls.clone();
+        let buf = SharedBuf::default();
+        let mut options = OptionRegistry::new();
+        options.add_spin("Hash", 1, 1, 16);
+        let mut runner = Runner::new(meta(), engine);
+        runner.set_options(options).set_strict(true);
+        let result = runner.run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let diagnostics: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("info string"))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                "info string rejected \"isready\": uci was not sent",
+                "info string rejected \"go depth 1\": no position was sent",
+                "info string queued \"setoption name Hash value 8\": the engine is searching",
+                "info string rejected \"go depth 1\": the engine is searching",
+                "info string rejected \"ponderhit\": the engine is not pondering",
+                "info string ignored \"bogus\": What",
+            ]
+        );
+        let best = output.lines().filter(|line| line.starts_with("bestmove"));
+        assert_eq!(best.count(), 2);
+        // The queued setoption runs before isready is answered.
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "new_game",
+                "position startpos",
+                "stopped",
+                "Hash Spin(8)",
+                "position startpos",
+                "quit",
+            ]
+        );
+    }
+
+    #[test]
+    fn run_with_bench_and_perft() {
+        let input = "bench\nbench 3\nposition startpos moves e7e5\nperft 2\ngo perft 1\nquit\n";
+        let engine = Recorder::default();
+        let calls = engine.calls.clone();
+        let buf = SharedBuf::default();
+        let result =
+            Runner::new(meta(), engine).run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let perft = "d2d4: 20\ne2e4: 22\n\nNodes searched: 42\n";
+        assert_eq!(
+            output,
+            format!(
+                "1000 nodes 2000 nps\n1000 nodes 2000 nps\n{}{}",
+                perft, perft
+            )
+        );
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "bench None",
+                "bench Some(3)",
+                "perft 2 position startpos moves e7e5",
+                "perft 1 position startpos moves e7e5",
+                "quit",
+            ]
+        );
+    }
+
+    #[test]
+    fn run_with_diagnostics() {
+        let input = "position startpos moves e2e4\neval\nflip\neval\ncompiler\nquit\n";
+        let engine = Recorder::default();
+        let calls = engine.calls.clone();
+        let buf = SharedBuf::default();
+        let result =
+            Runner::new(meta(), engine).run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let eval = "Material 0\nFinal evaluation -0.25 (white side)\n";
+        assert_eq!(
+            output,
+            format!("{}{}Compiled by rustc 1.80 on Linux\n", eval, eval)
+        );
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "eval position startpos moves e2e4",
+                "eval position fen rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1",
+                "quit",
+            ]
+        );
+    }
+
+    #[test]
+    fn run_with_engine_without_bench() {
+        let buf = SharedBuf::default();
+        let result = Runner::new(meta(), AsyncEval)
+            .run_with(Cursor::new("bench\nperft 1\n"), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+        assert!(buf.0.lock().unwrap().is_empty());
+    }
+
+    // An engine that is copyprotected and needs the code 1234 to register.
+    #[derive(Default)]
+    struct Licensed {
+        is_registered: bool,
+    }
+
+    impl UciEngine for Licensed {
+        fn go(&mut self, _: &GamePos, _: &Go, _: &StopFlag, _: &InfoSender) -> (Pm, Option<Pm>) {
+            (Pm::Null, None)
+        }
+
+        fn check_copy_protection(&mut self) -> Option<bool> {
+            Some(true)
+        }
+
+        fn check_registration(&mut self) -> Option<bool> {
+            Some(self.is_registered)
+        }
+
+        fn register(&mut self, register: &Register) -> bool {
+            if let Register::Now { code, .. } = register {
+                self.is_registered = code.as_deref() == Some("1234");
+            }
+            self.is_registered
+        }
+    }
+
+    #[test]
+    fn run_with_registration() {
+        let input =
+            "uci\nregister later\nregister name Omar code 1\nregister name Omar code 1234\nquit\n";
+        let buf = SharedBuf::default();
+        let result = Runner::new(meta(), Licensed::default())
+            .run_with(Cursor::new(input), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            output,
+            "id name Recorder 1.0\n\
+             id author uzi, others\n\
+             uciok\n\
+             copyprotection checking\n\
+             copyprotection ok\n\
+             registration checking\n\
+             registration error\n\
+             registration checking\n\
+             registration error\n\
+             registration checking\n\
+             registration ok\n"
+        );
+    }
+
+    #[test]
+    fn engine_meta_ids() {
+        let meta = EngineMeta::new("Recorder", "", &["uzi"]);
+        assert_eq!(meta.id_name(), "Recorder");
+        assert_eq!(meta.id_author(), "uzi");
+        assert_eq!(self::meta().id_name(), "Recorder 1.0");
+        assert_eq!(self::meta().id_author(), "uzi, others");
+    }
+
+    #[test]
+    fn run_with_closed_input() {
+        let buf = SharedBuf::default();
+        let result = Runner::new(meta(), Recorder::default())
+            .run_with(Cursor::new("uci\n"), UciOut::new(buf.clone()));
+        assert_eq!(result, Ok(()));
+        assert!(buf.0.lock().unwrap().ends_with(b"uciok\n"));
+    }
+}
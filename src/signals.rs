@@ -0,0 +1,91 @@
+// This module turns the signals that end the engine, i.e. SIGINT and SIGTERM,
+// or Ctrl-C on Windows, into GUI commands, so that the runner shuts the engine
+// down cleanly.
+
+use crate::err::UziErr;
+use std::future::poll_fn;
+use std::task::Poll;
+use std::thread;
+use tokio::runtime::Builder;
+use tokio::sync::mpsc::UnboundedSender;
+
+// Sends stop and quit to tx when the process gets one of the signals. The
+// handlers are installed when this returns, and the signals no longer end the
+// process.
+pub(crate) fn forward_signals(tx: UnboundedSender<Result<String, UziErr>>) -> Result<(), UziErr> {
+    let runtime = Builder::new_current_thread().enable_all().build()?;
+    let mut signals = {
+        let _guard = runtime.enter();
+        Signals::new()?
+    };
+    thread::spawn(move || {
+        runtime.block_on(poll_fn(|cx| signals.poll_recv(cx)));
+        let _ = tx.send(Ok("stop".into()));
+        let _ = tx.send(Ok("quit".into()));
+    });
+    Ok(())
+}
+
+#[cfg(unix)]
+struct Signals {
+    interrupt: tokio::signal::unix::Signal,
+    terminate: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl Signals {
+    fn new() -> Result<Self, UziErr> {
+        use tokio::signal::unix::{signal, SignalKind};
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+
+    fn poll_recv(&mut self, cx: &mut std::task::Context<'_>) -> Poll<()> {
+        if self.interrupt.poll_recv(cx).is_ready() || self.terminate.poll_recv(cx).is_ready() {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(windows)]
+struct Signals {
+    ctrl_c: tokio::signal::windows::CtrlC,
+    ctrl_break: tokio::signal::windows::CtrlBreak,
+}
+
+#[cfg(windows)]
+impl Signals {
+    fn new() -> Result<Self, UziErr> {
+        use tokio::signal::windows::{ctrl_break, ctrl_c};
+        Ok(Self {
+            ctrl_c: ctrl_c()?,
+            ctrl_break: ctrl_break()?,
+        })
+    }
+
+    fn poll_recv(&mut self, cx: &mut std::task::Context<'_>) -> Poll<()> {
+        if self.ctrl_c.poll_recv(cx).is_ready() || self.ctrl_break.poll_recv(cx).is_ready() {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn signal_becomes_stop_and_quit() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        assert_eq!(forward_signals(tx), Ok(()));
+        // The handler is installed, so this does not end the test process.
+        unsafe { libc::raise(libc::SIGTERM) };
+        assert_eq!(rx.blocking_recv(), Some(Ok("stop".into())));
+        assert_eq!(rx.blocking_recv(), Some(Ok("quit".into())));
+    }
+}
@@ -0,0 +1,386 @@
+// This module contains Snapshot, which runs a fixed set of positions and
+// limits on an engine and keeps the normalized result of each, i.e. the best
+// move with the final depth and score, so that later versions of the engine
+// can be checked against it, e.g. to catch a change in the search between two
+// commits. The limits should make the search deterministic, e.g. a depth or a
+// number of nodes with one thread rather than a time.
+//
+// A snapshot is saved as a text file with a paragraph per case:
+//
+// position startpos moves e2e4
+// go depth 12
+// result bestmove e7e5 depth 12 score cp -20
+//
+// A result is either a move or the kind of error of an engine that failed,
+// i.e. crashed, hung or failed. Lines starting with # are comments.
+
+use crate::analysis::{AnalysisResult, AnalysisSession};
+use crate::client::Engine;
+use crate::engcmd::{Info, Score};
+use crate::err::UziErr;
+use crate::guicmd::{Go, GuiCmd, Pos};
+use crate::pm::Pm;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+// How long to wait for readyok after ucinewgame before each case.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The results of an engine for a set of positions and limits.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Snapshot {
+    entries: Vec<SnapshotEntry>,
+}
+
+// A position and limits, and the result of the engine for them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SnapshotEntry {
+    pub pos: Pos,
+    pub limits: Go,
+    pub result: SnapshotResult,
+}
+
+// The part of a search result that is compared between runs.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SnapshotResult {
+    Move {
+        best: Pm,
+        depth: Option<u16>,
+        score: Option<Score>,
+    },
+    Crashed,
+    Hung,
+    Failed,
+}
+
+// A case whose result differs from the snapshot.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SnapshotDiff {
+    // The index of the case in the snapshot.
+    pub index: usize,
+    pub pos: Pos,
+    pub limits: Go,
+    pub expected: SnapshotResult,
+    // The result of the new run, or None if it doesn't have the case.
+    pub actual: Option<SnapshotResult>,
+}
+
+impl Snapshot {
+    // Runs the cases on the engine, one after the other. The engine gets
+    // ucinewgame before each case, so that the results don't depend on the
+    // cases before it. A case that fails is kept with the kind of the error.
+    pub async fn record<I>(eng: &mut Engine, cases: I) -> Self
+    where
+        I: IntoIterator<Item = (Pos, Go)>,
+    {
+        let mut entries = Vec::new();
+        for (pos, limits) in cases {
+            let result = match search(eng, &pos, &limits).await {
+                Ok(result) => SnapshotResult::from(&result),
+                Err(err) => SnapshotResult::from(&err),
+            };
+            entries.push(SnapshotEntry {
+                pos,
+                limits,
+                result,
+            });
+        }
+        Self { entries }
+    }
+
+    // Runs the cases of the snapshot on the engine again, and returns the
+    // cases whose result changed.
+    pub async fn check(&self, eng: &mut Engine) -> Vec<SnapshotDiff> {
+        let cases = self
+            .entries
+            .iter()
+            .map(|entry| (entry.pos.clone(), entry.limits.clone()));
+        self.diff(&Snapshot::record(eng, cases).await)
+    }
+
+    // Returns the cases whose result in the other snapshot differs from this
+    // one. The cases are matched by their index, and a case that the other
+    // snapshot doesn't have at the same index is reported without a result.
+    pub fn diff(&self, other: &Snapshot) -> Vec<SnapshotDiff> {
+        let mut diffs = Vec::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            let actual = other
+                .entries
+                .get(index)
+                .filter(|other| other.pos == entry.pos && other.limits == entry.limits)
+                .map(|other| other.result);
+            if actual != Some(entry.result) {
+                diffs.push(SnapshotDiff {
+                    index,
+                    pos: entry.pos.clone(),
+                    limits: entry.limits.clone(),
+                    expected: entry.result,
+                    actual,
+                });
+            }
+        }
+        diffs
+    }
+
+    pub fn entries(&self) -> &[SnapshotEntry] {
+        &self.entries
+    }
+
+    // Parses a snapshot. A line that doesn't fit the format fails with
+    // BadSnapshot.
+    pub fn parse(text: &str) -> Result<Self, UziErr> {
+        let mut entries = Vec::new();
+        let (mut pos, mut limits) = (None, None);
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = UziErr::BadSnapshot(i + 1);
+            match (pos.take(), limits.take()) {
+                (None, _) => match GuiCmd::from_str(line) {
+                    Ok(GuiCmd::Pos(new)) => pos = Some(new),
+                    _ => return Err(bad),
+                },
+                (Some(old), None) => match GuiCmd::from_str(line) {
+                    Ok(GuiCmd::Go(new)) => (pos, limits) = (Some(old), Some(new)),
+                    _ => return Err(bad),
+                },
+                (Some(pos), Some(limits)) => entries.push(SnapshotEntry {
+                    pos,
+                    limits,
+                    result: parse_result(line).ok_or(bad)?,
+                }),
+            }
+        }
+        // A case without a result.
+        if pos.is_some() {
+            return Err(UziErr::BadSnapshot(text.lines().count()));
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, UziErr> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), UziErr> {
+        Ok(fs::write(path, self.to_string())?)
+    }
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                writeln!(formatter)?;
+            }
+            writeln!(formatter, "{}", GuiCmd::Pos(entry.pos.clone()))?;
+            writeln!(formatter, "{}", GuiCmd::Go(entry.limits.clone()))?;
+            writeln!(formatter, "result {}", entry.result)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&AnalysisResult> for SnapshotResult {
+    fn from(result: &AnalysisResult) -> Self {
+        let best = result.lines.first();
+        SnapshotResult::Move {
+            best: result.best,
+            depth: best.and_then(|line| line.depth),
+            score: best.and_then(|line| line.score),
+        }
+    }
+}
+
+impl From<&UziErr> for SnapshotResult {
+    fn from(err: &UziErr) -> Self {
+        match err.root() {
+            UziErr::Crashed(_) | UziErr::EngineExited => SnapshotResult::Crashed,
+            UziErr::EngineHung | UziErr::Timeout => SnapshotResult::Hung,
+            _ => SnapshotResult::Failed,
+        }
+    }
+}
+
+impl Display for SnapshotResult {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotResult::Move { best, depth, score } => {
+                write!(formatter, "bestmove {}", best)?;
+                if let Some(depth) = depth {
+                    write!(formatter, " depth {}", depth)?;
+                }
+                if let Some(score) = score {
+                    write!(formatter, " {}", score)?;
+                }
+                Ok(())
+            }
+            SnapshotResult::Crashed => write!(formatter, "crashed"),
+            SnapshotResult::Hung => write!(formatter, "hung"),
+            SnapshotResult::Failed => write!(formatter, "failed"),
+        }
+    }
+}
+
+// E.g. "case 3 go depth 10: expected bestmove e2e4 depth 10 score cp 35, got
+// bestmove d2d4 depth 10 score cp 20".
+impl Display for SnapshotDiff {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "case {} {}: expected {}, got ",
+            self.index + 1,
+            GuiCmd::Go(self.limits.clone()),
+            self.expected
+        )?;
+        match self.actual {
+            Some(actual) => write!(formatter, "{}", actual),
+            None => write!(formatter, "nothing"),
+        }
+    }
+}
+
+async fn search(eng: &mut Engine, pos: &Pos, limits: &Go) -> Result<AnalysisResult, UziErr> {
+    eng.new_game().await?;
+    eng.sync(SYNC_TIMEOUT).await?;
+    let mut session = AnalysisSession::new(eng);
+    session.set_position(pos.clone());
+    session.analyze(limits).await?.wait().await
+}
+
+// Parses the words after "result".
+fn parse_result(line: &str) -> Option<SnapshotResult> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let result = match words[..] {
+        ["result", "crashed"] => SnapshotResult::Crashed,
+        ["result", "hung"] => SnapshotResult::Hung,
+        ["result", "failed"] => SnapshotResult::Failed,
+        ["result", "bestmove", best, ref rest @ ..] => {
+            let (depth, score) = match rest {
+                ["depth", depth, score @ ..] => (Some(depth.parse().ok()?), score),
+                score => (None, score),
+            };
+            let score = match score {
+                [] => None,
+                ["score", ..] => {
+                    let mut info = vec!["info"];
+                    info.extend(score);
+                    Some(Info::try_from(info.as_slice()).ok()?.score()?)
+                }
+                _ => return None,
+            };
+            SnapshotResult::Move {
+                best: Pm::from_str(best).ok()?,
+                depth,
+                score,
+            }
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+
+    fn cases() -> Vec<(Pos, Go)> {
+        let mut depth = Go::new();
+        depth.set_depth(2);
+        let mut nodes = Go::new();
+        nodes.set_nodes(1000);
+        let mut pos = Pos::new();
+        pos.add_move(Pm::from_str("d2d4").unwrap());
+        vec![(Pos::new(), depth), (pos, nodes)]
+    }
+
+    #[tokio::test]
+    async fn snapshot_record_and_check() {
+        let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+        let snapshot = Snapshot::record(&mut eng, cases()).await;
+        let text = "\
+position startpos
+go depth 2
+result bestmove e2e4 depth 2 score cp 20
+
+position startpos moves d2d4
+go nodes 1000
+result bestmove e2e4 depth 2 score cp 20
+";
+        assert_eq!(snapshot.to_string(), text);
+        assert_eq!(Snapshot::parse(text), Ok(snapshot.clone()));
+        assert!(snapshot.check(&mut eng).await.is_empty());
+
+        // An engine that plays another move at depth 2, and crashes on the
+        // second case.
+        let script = r#"
+            n=0
+            while read -r line; do
+                case "$line" in
+                    isready) echo "readyok";;
+                    go*)
+                        n=$((n + 1))
+                        [ $n = 2 ] && exit 1
+                        echo "info depth 2 score cp 15 pv d2d4"
+                        echo "bestmove d2d4";;
+                esac
+            done
+        "#;
+        let mut eng = Engine::new(fake_engine(script));
+        let diffs = snapshot.check(&mut eng).await;
+        let diffs: Vec<String> = diffs.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            diffs,
+            [
+                "case 1 go depth 2: expected bestmove e2e4 depth 2 score cp 20, \
+                 got bestmove d2d4 depth 2 score cp 15",
+                "case 2 go nodes 1000: expected bestmove e2e4 depth 2 score cp 20, got crashed",
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshot_parse() {
+        let text = "\
+# Before the change of the null move pruning.
+position fen 8/8/8/8/8/8/8/K6k w - - 0 1
+go depth 5
+result bestmove a1b1 score mate -3
+
+position startpos
+go depth 5
+result hung
+";
+        let snapshot = Snapshot::parse(text).unwrap();
+        let results: Vec<String> = snapshot
+            .entries()
+            .iter()
+            .map(|entry| entry.result.to_string())
+            .collect();
+        assert_eq!(results, ["bestmove a1b1 score mate -3", "hung"]);
+        // A case from another snapshot doesn't match.
+        let other = Snapshot::parse(&text.replace("depth 5\nresult hung", "depth 6\nresult hung"));
+        let diffs = snapshot.diff(&other.unwrap());
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].actual, None);
+
+        assert_eq!(
+            Snapshot::parse("position startpos\nresult hung"),
+            Err(UziErr::BadSnapshot(2))
+        );
+        assert_eq!(
+            Snapshot::parse("position startpos\ngo depth 1\nresult bestmove e2e4 depth"),
+            Err(UziErr::BadSnapshot(3))
+        );
+        assert_eq!(
+            Snapshot::parse("position startpos\ngo depth 1\n"),
+            Err(UziErr::BadSnapshot(2))
+        );
+    }
+}
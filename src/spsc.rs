@@ -0,0 +1,299 @@
+// This module contains a bounded lock-free queue for one producer and one
+// consumer, which the blocking client uses with the spsc feature between the
+// thread that reads the engine output and the caller. Unlike std::sync::mpsc,
+// sending and receiving don't take a lock or allocate, which evens out the
+// latency of relaying lines at bullet speeds. A side that has to wait, for a
+// line or for room, parks its thread, and only takes a lock to go to sleep.
+//
+// The queue is a ring of slots indexed by two counters: the number of values
+// pushed, which only the sender writes, and the number popped, which only the
+// receiver writes. Since neither side can be cloned or shared between threads,
+// each counter has a single writer.
+
+use std::cell::{Cell, UnsafeCell};
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+// Creates a queue that holds up to capacity values, which is at least 1.
+pub(crate) fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let slots = (0..capacity.max(1))
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+    let shared = Arc::new(Shared {
+        slots,
+        pushed: AtomicUsize::new(0),
+        popped: AtomicUsize::new(0),
+        is_closed: AtomicBool::new(false),
+        sender: Parker::default(),
+        receiver: Parker::default(),
+    });
+    let sender = Sender {
+        shared: Arc::clone(&shared),
+        _not_sync: PhantomData,
+    };
+    let receiver = Receiver {
+        shared,
+        _not_sync: PhantomData,
+    };
+    (sender, receiver)
+}
+
+struct Shared<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    pushed: AtomicUsize,
+    popped: AtomicUsize,
+    // Set when either side is dropped.
+    is_closed: AtomicBool,
+    // Where each side waits.
+    sender: Parker,
+    receiver: Parker,
+}
+
+// The slots between popped and pushed are only read by the receiver, and the
+// others only written by the sender, see the counters.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn slot(&self, count: usize) -> *mut MaybeUninit<T> {
+        self.slots[count % self.slots.len()].get()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.is_closed.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let (popped, pushed) = (*self.popped.get_mut(), *self.pushed.get_mut());
+        for count in popped..pushed {
+            // The values that were sent and not received are initialized.
+            unsafe { (*self.slot(count)).assume_init_drop() };
+        }
+    }
+}
+
+// A thread that waits for the other side of the queue.
+#[derive(Debug, Default)]
+struct Parker {
+    is_waiting: AtomicBool,
+    thread: Mutex<Option<Thread>>,
+}
+
+impl Parker {
+    // Parks the current thread until is_ready returns true, or the deadline
+    // passes. Returns whether it is ready.
+    fn wait(&self, is_ready: impl Fn() -> bool, deadline: Option<Instant>) -> bool {
+        loop {
+            if is_ready() {
+                return true;
+            }
+            *self.thread.lock().unwrap() = Some(thread::current());
+            self.is_waiting.store(true, Ordering::SeqCst);
+            // Pairs with the fence in wake, so that either this sees the change
+            // of the other side, or the other side sees that this waits.
+            fence(Ordering::SeqCst);
+            if is_ready() {
+                self.is_waiting.store(false, Ordering::SeqCst);
+                return true;
+            }
+            match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(timeout) if !timeout.is_zero() => thread::park_timeout(timeout),
+                    _ => {
+                        self.is_waiting.store(false, Ordering::SeqCst);
+                        return is_ready();
+                    }
+                },
+                None => thread::park(),
+            }
+            self.is_waiting.store(false, Ordering::SeqCst);
+        }
+    }
+
+    // Wakes the thread if it waits. An unpark before the thread parks makes
+    // the park return at once, so the wake is never lost.
+    fn wake(&self) {
+        fence(Ordering::SeqCst);
+        if self.is_waiting.load(Ordering::SeqCst) {
+            if let Some(thread) = &*self.thread.lock().unwrap() {
+                thread.unpark();
+            }
+        }
+    }
+}
+
+// The sending side of the queue.
+pub(crate) struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> Sender<T> {
+    // Sends a value, waiting for room if the queue is full. Fails if the
+    // receiver was dropped.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let shared = &*self.shared;
+        let pushed = shared.pushed.load(Ordering::Relaxed);
+        let has_room = || {
+            let popped = shared.popped.load(Ordering::Acquire);
+            shared.is_closed() || pushed.wrapping_sub(popped) < shared.slots.len()
+        };
+        shared.sender.wait(has_room, None);
+        if shared.is_closed() {
+            return Err(SendError(value));
+        }
+        // The slot is free, and the receiver doesn't read it before pushed
+        // moves past it.
+        unsafe { (*shared.slot(pushed)).write(value) };
+        shared
+            .pushed
+            .store(pushed.wrapping_add(1), Ordering::Release);
+        shared.receiver.wake();
+        Ok(())
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.is_closed.store(true, Ordering::Release);
+        self.shared.receiver.wake();
+    }
+}
+
+impl<T> Debug for Sender<T> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+// The receiving side of the queue. The values sent before the sender was
+// dropped are still received.
+pub(crate) struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> Receiver<T> {
+    // Returns the next value, if there is one already.
+    pub fn try_recv(&self) -> Option<T> {
+        let shared = &*self.shared;
+        let popped = shared.popped.load(Ordering::Relaxed);
+        if shared.pushed.load(Ordering::Acquire) == popped {
+            return None;
+        }
+        // The slot was written before pushed moved past it, and the sender
+        // doesn't write it again before popped moves past it.
+        let value = unsafe { (*shared.slot(popped)).assume_init_read() };
+        shared
+            .popped
+            .store(popped.wrapping_add(1), Ordering::Release);
+        shared.sender.wake();
+        Some(value)
+    }
+
+    // Waits for the next value. Fails once the sender was dropped and every
+    // value has been received.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.recv_until(None).map_err(|_| RecvError)
+    }
+
+    // Like recv, waiting at most timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(Instant::now().checked_add(timeout))
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let shared = &*self.shared;
+        loop {
+            if let Some(value) = self.try_recv() {
+                return Ok(value);
+            }
+            if shared.is_closed() {
+                // The sender may have sent a last value before it was dropped.
+                return self.try_recv().ok_or(RecvTimeoutError::Disconnected);
+            }
+            let popped = shared.popped.load(Ordering::Relaxed);
+            let is_ready = || shared.is_closed() || shared.pushed.load(Ordering::Acquire) != popped;
+            if !shared.receiver.wait(is_ready, deadline) {
+                return Err(RecvTimeoutError::Timeout);
+            }
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.is_closed.store(true, Ordering::Release);
+        self.shared.sender.wake();
+    }
+}
+
+impl<T> Debug for Receiver<T> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spsc_order_and_close() {
+        let (tx, rx) = channel(2);
+        tx.send("uciok".to_string()).unwrap();
+        tx.send("readyok".to_string()).unwrap();
+        assert_eq!(rx.try_recv().as_deref(), Some("uciok"));
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(1)).as_deref(),
+            Ok("readyok")
+        );
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(1)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        tx.send("bestmove e2e4".to_string()).unwrap();
+        drop(tx);
+        assert_eq!(rx.recv().as_deref(), Ok("bestmove e2e4"));
+        assert_eq!(rx.recv(), Err(RecvError));
+
+        let (tx, rx) = channel(1);
+        drop(rx);
+        assert_eq!(tx.send(1), Err(SendError(1)));
+    }
+
+    #[test]
+    fn spsc_between_threads() {
+        const COUNT: usize = 100_000;
+        let (tx, rx) = channel(16);
+        let sender = thread::spawn(move || {
+            for i in 0..COUNT {
+                tx.send(i).unwrap();
+            }
+        });
+        for i in 0..COUNT {
+            assert_eq!(rx.recv(), Ok(i));
+        }
+        assert_eq!(rx.recv(), Err(RecvError));
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn spsc_drops_unreceived_values() {
+        let value = Arc::new(());
+        let (tx, rx) = channel(4);
+        tx.send(Arc::clone(&value)).unwrap();
+        tx.send(Arc::clone(&value)).unwrap();
+        drop(rx.try_recv());
+        assert_eq!(Arc::strong_count(&value), 2);
+        drop((tx, rx));
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
@@ -0,0 +1,181 @@
+// This module contains TapTransport, which wraps a transport and writes every
+// line exchanged through it to a TapLog, in the format of the debug output of
+// cutechess-cli:
+//
+// 1250 >Stockfish(0): go wtime 60000 btime 60000
+// 2310 <Stockfish(0): bestmove e2e4 ponder e7e5
+//
+// The number is the time in milliseconds since the log was created, > marks the
+// lines sent to the engine and < the lines received from it, and the label
+// tells the engines apart. A log can be played back, see Recording.
+
+use crate::engproc::CrashReport;
+use crate::err::UziErr;
+use crate::transport::{BoxFuture, Transport};
+use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::broadcast;
+
+// Where taps write the lines. Clones write to the same writer, so the engines
+// of a match can share a log, with their lines in the order they were
+// exchanged and the times counted from the same start.
+#[derive(Clone)]
+pub struct TapLog {
+    out: Arc<Mutex<Box<dyn Write + Send>>>,
+    started: Instant,
+}
+
+impl TapLog {
+    pub fn new<W: Write + Send + 'static>(out: W) -> Self {
+        Self {
+            out: Arc::new(Mutex::new(Box::new(out))),
+            started: Instant::now(),
+        }
+    }
+
+    // Creates the file, or truncates it, and writes a line at a time, so that
+    // the log is complete up to a crash.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, UziErr> {
+        Ok(Self::new(LineWriter::new(File::create(path)?)))
+    }
+
+    // Writes the line with its time, its direction and the label. Errors are
+    // ignored, since the engine works without its log.
+    fn write(&self, marker: char, label: &str, line: &str) {
+        let millis = self.started.elapsed().as_millis();
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{} {}{}: {}", millis, marker, label, line);
+    }
+}
+
+impl Debug for TapLog {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("TapLog")
+            .field("started", &self.started)
+            .finish_non_exhaustive()
+    }
+}
+
+// A transport that logs the lines of another one. To tap the engines that
+// replace crashed ones as well, wrap the transports in the function of a
+// Spawner.
+#[derive(Debug)]
+pub struct TapTransport<T> {
+    inner: T,
+    label: String,
+    log: TapLog,
+}
+
+impl<T: Transport> TapTransport<T> {
+    // Returns the transport that logs the lines of inner with the label, e.g.
+    // the engine name and its number, as in "Stockfish(0)".
+    pub fn new(inner: T, label: &str, log: TapLog) -> Self {
+        Self {
+            inner,
+            label: label.to_string(),
+            log,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Transport> Transport for TapTransport<T> {
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        self.log.write('>', &self.label, line);
+        self.inner.send_line(line)
+    }
+
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        Box::pin(async move {
+            let line = self.inner.recv_line().await?;
+            if let Some(ref line) = line {
+                self.log.write('<', &self.label, line);
+            }
+            Ok(line)
+        })
+    }
+
+    fn subscribe_stderr(&self) -> broadcast::Receiver<String> {
+        self.inner.subscribe_stderr()
+    }
+
+    fn crash_report(&mut self) -> BoxFuture<'_, CrashReport> {
+        self.inner.crash_report()
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        self.inner.kill()
+    }
+
+    fn wait_exit(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        self.inner.wait_exit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Engine;
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+    use std::time::Duration;
+
+    // A writer the test can read back.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn tap_logs_both_engines() {
+        let buf = SharedBuf::default();
+        let log = TapLog::new(buf.clone());
+        let timeout = Duration::from_secs(5);
+        let tap = |label| TapTransport::new(fake_engine(FAKE_ENGINE), label, log.clone());
+        let mut first = Engine::new(tap("Fake(0)"));
+        let mut second = Engine::new(tap("Fake(1)"));
+        first.sync(timeout).await.unwrap();
+        second.uci(timeout).await.unwrap();
+        first.sync(timeout).await.unwrap();
+
+        let text = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let mut last = 0;
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let (millis, line) = line.split_once(' ').unwrap();
+            let millis: u128 = millis.parse().unwrap();
+            assert!(millis >= last);
+            last = millis;
+            lines.push(line);
+        }
+        assert_eq!(
+            lines,
+            [
+                ">Fake(0): isready",
+                "<Fake(0): readyok",
+                ">Fake(1): uci",
+                "<Fake(1): id name Fake",
+                "<Fake(1): id author uzi",
+                "<Fake(1): option name Hash type spin default 16 min 1 max 1024",
+                "<Fake(1): uciok",
+                ">Fake(0): isready",
+                "<Fake(0): readyok",
+            ]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+// This module contains the transport for engines on other machines, over TCP.
+// The lines are the same as over the pipes of a process, one command per line.
+// There are two ways to set up a session:
+// - Connect - the engine side listens with a TcpEngineServer, which runs an
+//   engine for every connection, and the client connects to it.
+// - Listen - the client listens and the engine side connects to it, e.g. when
+//   the machine of the engine is behind a firewall.
+//
+// Either way, the client gets a TcpTransport and uses it like any other, e.g.
+// with Engine::new, or TcpTransport::spawner to reconnect on a restart.
+
+use crate::engproc::Spawner;
+use crate::err::UziErr;
+use crate::listen::{self, Listener, DEFAULT_MAX_SESSIONS};
+use crate::transport::{BoxFuture, StreamTransport, Transport};
+use std::io;
+use std::net::{self, SocketAddr};
+use std::time::Duration;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+// How long the spawner of TcpTransport waits to connect. It blocks a thread of
+// the runtime meanwhile, so this is short.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// A connection to an engine over TCP. Killing the engine closes the
+// connection, which ends the session on the other side.
+#[derive(Debug)]
+pub struct TcpTransport {
+    stream: StreamTransport<OwnedReadHalf, OwnedWriteHalf>,
+    peer_addr: SocketAddr,
+}
+
+impl TcpTransport {
+    // Connects to an engine served at the address, e.g. "10.0.0.2:4000".
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, UziErr> {
+        Self::from_stream(TcpStream::connect(addr).await?)
+    }
+
+    // Waits for an engine to connect to the listener.
+    pub async fn accept(listener: &TcpListener) -> Result<Self, UziErr> {
+        let (stream, _) = listener.accept().await?;
+        Self::from_stream(stream)
+    }
+
+    pub fn from_stream(stream: TcpStream) -> Result<Self, UziErr> {
+        let peer_addr = stream.peer_addr()?;
+        // Commands are short and the engine should get them right away.
+        stream.set_nodelay(true)?;
+        let (reader, writer) = stream.into_split();
+        Ok(Self {
+            stream: StreamTransport::new(reader, writer),
+            peer_addr,
+        })
+    }
+
+    // Returns a spawner that connects to the address, for an Engine that
+    // reconnects when it is restarted, or for a pool of engines on a server.
+    // The spawner connects without waiting for the runtime, so it has to be
+    // used in a tokio runtime, and the address should be a resolved one. It
+    // gives up after CONNECT_TIMEOUT.
+    pub fn spawner(addr: SocketAddr) -> Spawner {
+        Spawner::new(move || {
+            let stream = net::TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+            stream.set_nonblocking(true)?;
+            Self::from_stream(TcpStream::from_std(stream)?)
+        })
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        self.stream.send_line(line)
+    }
+
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        self.stream.recv_line()
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        self.stream.kill()
+    }
+}
+
+// Serves an engine to clients on other machines. Every connection gets an
+// engine of its own from the spawner, which lives until the client sends quit
+// or disconnects.
+#[derive(Debug)]
+pub struct TcpEngineServer {
+    listener: TcpListener,
+    spawner: Spawner,
+    max_sessions: usize,
+}
+
+impl TcpEngineServer {
+    // Listens at the address, e.g. "0.0.0.0:4000", or port 0 for any free
+    // port, see local_addr.
+    pub async fn bind<A: ToSocketAddrs>(addr: A, spawner: Spawner) -> Result<Self, UziErr> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            spawner,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, UziErr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    // Sets how many sessions run at once, 64 by default. The connections over
+    // the limit wait until a session ends.
+    pub fn set_max_sessions(&mut self, max_sessions: usize) -> &mut Self {
+        self.max_sessions = max_sessions;
+        self
+    }
+
+    // Accepts connections until the task is dropped, also after accepting
+    // fails. Each session runs on a task of its own. A connection whose engine
+    // fails to start is closed, and the server goes on.
+    pub async fn serve(mut self) {
+        listen::serve(
+            &mut self.listener,
+            &self.spawner,
+            self.max_sessions,
+            |proxy, stream: TcpStream| async move {
+                let (reader, writer) = stream.into_split();
+                let _ = proxy.run_with(reader, writer).await;
+            },
+        )
+        .await
+    }
+}
+
+impl Listener for TcpListener {
+    type Conn = TcpStream;
+
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<TcpStream>> {
+        Box::pin(async move {
+            let (stream, _) = TcpListener::accept(self).await?;
+            // Commands are short and the engine should get them right away.
+            let _ = stream.set_nodelay(true);
+            Ok(stream)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Engine;
+    use crate::guicmd::{Go, Pos};
+    use crate::pm::Pm;
+    use crate::proxy::UciProxy;
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn spawner() -> Spawner {
+        Spawner::new(|| Ok(fake_engine(FAKE_ENGINE)))
+    }
+
+    #[tokio::test]
+    async fn tcp_connect_to_server() {
+        let server = TcpEngineServer::bind("127.0.0.1:0", spawner())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.serve());
+
+        let mut eng = Engine::new(TcpTransport::connect(addr).await.unwrap());
+        eng.uci(TIMEOUT).await.unwrap();
+        assert_eq!(eng.name(), Some("Fake"));
+        let best = eng.best_move(&Pos::new(), &Go::new()).await;
+        assert_eq!(best, Ok(Pm::from_str("e2e4").unwrap()));
+
+        // A restart connects again, and gets a new engine.
+        let mut eng = Engine::from_spawner(TcpTransport::spawner(addr)).unwrap();
+        eng.uci(TIMEOUT).await.unwrap();
+        eng.restart().await.unwrap();
+        eng.sync(TIMEOUT).await.unwrap();
+        assert_eq!(eng.options().len(), 1);
+
+        // A server that is down fails the spawner.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        assert!(TcpTransport::spawner(addr).spawn().is_err());
+    }
+
+    #[tokio::test]
+    async fn tcp_engine_connects_to_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // The engine side dials in.
+        tokio::spawn(async move {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let (reader, writer) = stream.into_split();
+            let proxy = UciProxy::new(fake_engine(FAKE_ENGINE));
+            proxy.run_with(reader, writer).await
+        });
+
+        let transport = TcpTransport::accept(&listener).await.unwrap();
+        assert!(transport.peer_addr().ip().is_loopback());
+        let mut eng = Engine::new(transport);
+        eng.uci(TIMEOUT).await.unwrap();
+        assert_eq!(eng.shutdown(TIMEOUT).await.map(|_| ()), Ok(()));
+    }
+}
@@ -0,0 +1,31 @@
+// This module contains helpers shared by the tests of the other modules.
+
+use crate::engproc::EngineProcess;
+use tokio::process::Command;
+
+// A shell script that behaves like a minimal UCI engine. It answers uci and
+// isready, sends a couple of info lines for every go and replies with e2e4.
+pub const FAKE_ENGINE: &str = r#"
+while read -r line; do
+    case "$line" in
+        uci)
+            echo "id name Fake"
+            echo "id author uzi"
+            echo "option name Hash type spin default 16 min 1 max 1024"
+            echo "uciok";;
+        isready) echo "readyok";;
+        go*)
+            echo "info depth 1 score cp 10 pv e2e4"
+            echo "info depth 2 score cp 20 pv e2e4 e7e5"
+            echo "bestmove e2e4 ponder e7e5";;
+        quit) exit 0;;
+    esac
+done
+"#;
+
+// Spawns a fake engine that runs the given shell script.
+pub fn fake_engine(script: &str) -> EngineProcess {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(script);
+    EngineProcess::from_cmd(cmd).unwrap()
+}
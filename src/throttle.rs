@@ -0,0 +1,221 @@
+// This module contains InfoThrottle, which limits how often the info updates of
+// a search are delivered to the consumer.
+
+use crate::engcmd::Info;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+// Limits the info updates to at most one per interval for each multipv rank.
+// Updates that arrive too early are held, and only the latest one is kept. A
+// held update is delivered when the interval has passed, or when the search
+// ends. Some updates are never held back:
+// - an update with a pv at a depth deeper than the last pv, i.e. the engine
+//   completed a depth.
+// - an update with a string, since it is a message rather than an update.
+#[derive(Clone, Debug)]
+pub(crate) struct InfoThrottle {
+    interval: Duration,
+    ranks: BTreeMap<u64, RankState>,
+    // The updates that were replaced by newer ones before they were
+    // delivered, since take_dropped was last called.
+    dropped: u64,
+}
+
+// The throttling state of a multipv rank.
+#[derive(Clone, Debug, Default)]
+struct RankState {
+    last_sent: Option<Instant>,
+    pv_depth: Option<u16>,
+    held: Option<Info>,
+}
+
+impl InfoThrottle {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            ranks: BTreeMap::new(),
+            dropped: 0,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    // Returns the info if it can be delivered now, otherwise it is held.
+    pub fn offer(&mut self, info: Info, now: Instant) -> Option<Info> {
+        let rank = self.ranks.entry(rank_of(&info)).or_default();
+        let has_pv = info.pv().is_some();
+        let completes_depth = has_pv && info.depth() > rank.pv_depth;
+        let is_due = rank
+            .last_sent
+            .is_none_or(|last_sent| now.duration_since(last_sent) >= self.interval);
+
+        if completes_depth || is_due || info.string().is_some() {
+            if has_pv {
+                rank.pv_depth = rank.pv_depth.max(info.depth());
+            }
+            // A held update is older than this one.
+            if info.string().is_none() && rank.held.take().is_some() {
+                self.dropped += 1;
+            }
+            rank.last_sent = Some(now);
+            return Some(info);
+        }
+
+        // Keep the last pv rather than replacing it with an update that only
+        // has, e.g., the current move.
+        let keeps_held = !has_pv && rank.held.as_ref().is_some_and(|held| held.pv().is_some());
+        if keeps_held || rank.held.replace(info).is_some() {
+            self.dropped += 1;
+        }
+        None
+    }
+
+    // Returns when the next held update is due, if any is held.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.ranks
+            .values()
+            .filter(|rank| rank.held.is_some())
+            .filter_map(|rank| rank.last_sent.map(|last_sent| last_sent + self.interval))
+            .min()
+    }
+
+    // Returns a held update that is due.
+    pub fn take_due(&mut self, now: Instant) -> Option<Info> {
+        let interval = self.interval;
+        let rank = self.ranks.values_mut().find(|rank| {
+            rank.held.is_some()
+                && rank
+                    .last_sent
+                    .is_none_or(|last_sent| now.duration_since(last_sent) >= interval)
+        })?;
+        rank.last_sent = Some(now);
+        rank.held.take()
+    }
+
+    // Returns the number of updates dropped since the last call.
+    pub fn take_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped)
+    }
+
+    // Returns a held update regardless of the interval, lowest rank first. This
+    // is used to deliver the final updates when the search ends.
+    pub fn flush(&mut self) -> Option<Info> {
+        self.ranks.values_mut().find_map(|rank| rank.held.take())
+    }
+}
+
+// The multipv rank of the info, which is 1 if the engine does not use multipv.
+fn rank_of(info: &Info) -> u64 {
+    info.multi_pv().unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engcmd::EngCmd;
+    use std::str::FromStr;
+
+    fn info(line: &str) -> Info {
+        match EngCmd::from_str(line) {
+            Ok(EngCmd::Info(info)) => info,
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn throttle_holds_early_updates() {
+        let start = Instant::now();
+        let mut throttle = InfoThrottle::new(ms(50));
+
+        assert!(throttle
+            .offer(info("info depth 1 nodes 10"), start)
+            .is_some());
+        assert!(throttle
+            .offer(info("info depth 1 nodes 20"), start + ms(10))
+            .is_none());
+        assert!(throttle
+            .offer(info("info depth 1 nodes 30"), start + ms(20))
+            .is_none());
+        assert_eq!(throttle.next_due(), Some(start + ms(50)));
+        assert!(throttle.take_due(start + ms(40)).is_none());
+        // The update with 20 nodes was replaced by the one with 30.
+        assert_eq!(throttle.take_dropped(), 1);
+        assert_eq!(throttle.take_dropped(), 0);
+
+        let due = throttle.take_due(start + ms(50)).unwrap();
+        assert_eq!(due.nodes(), Some(30));
+        assert_eq!(throttle.next_due(), None);
+    }
+
+    #[test]
+    fn throttle_delivers_depth_completion_and_strings() {
+        let start = Instant::now();
+        let mut throttle = InfoThrottle::new(ms(50));
+
+        assert!(throttle
+            .offer(info("info depth 1 pv e2e4"), start)
+            .is_some());
+        assert!(throttle
+            .offer(info("info depth 2 currmove e2e4"), start + ms(1))
+            .is_none());
+        assert!(throttle
+            .offer(info("info depth 2 pv d2d4"), start + ms(2))
+            .is_some());
+        assert!(throttle
+            .offer(info("info string hello"), start + ms(3))
+            .is_some());
+        assert_eq!(throttle.flush(), None);
+        assert_eq!(throttle.take_dropped(), 1);
+    }
+
+    #[test]
+    fn throttle_by_multipv_rank() {
+        let start = Instant::now();
+        let mut throttle = InfoThrottle::new(ms(50));
+
+        assert!(throttle
+            .offer(info("info depth 1 multipv 1 score cp 5"), start)
+            .is_some());
+        assert!(throttle
+            .offer(info("info depth 1 multipv 2 score cp 1"), start)
+            .is_some());
+        assert!(throttle
+            .offer(info("info depth 1 multipv 2 score cp 2"), start + ms(1))
+            .is_none());
+        assert!(throttle
+            .offer(info("info depth 1 multipv 1 score cp 6"), start + ms(1))
+            .is_none());
+
+        // The final updates are delivered lowest rank first.
+        assert_eq!(throttle.flush().unwrap().multi_pv(), Some(1));
+        assert_eq!(throttle.flush().unwrap().multi_pv(), Some(2));
+        assert_eq!(throttle.flush(), None);
+    }
+
+    #[test]
+    fn throttle_keeps_held_pv() {
+        let start = Instant::now();
+        let mut throttle = InfoThrottle::new(ms(50));
+
+        assert!(throttle
+            .offer(info("info depth 3 pv e2e4"), start)
+            .is_some());
+        assert!(throttle
+            .offer(info("info depth 3 score cp 9 pv d2d4"), start + ms(1))
+            .is_none());
+        assert!(throttle
+            .offer(info("info depth 3 currmove e2e4"), start + ms(2))
+            .is_none());
+        assert_eq!(
+            throttle.flush().unwrap().score().and_then(|s| s.cp()),
+            Some(9)
+        );
+        assert_eq!(throttle.take_dropped(), 1);
+    }
+}
@@ -0,0 +1,911 @@
+// This module contains Tournament, which plays EngineMatch games between
+// several engines, as a round robin or a gauntlet, and keeps a crosstable.
+
+use crate::batch::join_all;
+use crate::checkpoint::Checkpoint;
+use crate::client::Engine;
+use crate::engmatch::{EngineMatch, GameRecord, GameResult, MatchEvent, Side, WinReason};
+use crate::err::UziErr;
+use crate::opening::OpeningSuite;
+use crate::pool::EnginePool;
+use crate::profile::EngineProfile;
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time;
+
+// Called with every game of a tournament, with the crosstable so far.
+type Progress = Box<dyn FnMut(&TournamentGame, &Crosstable, TournamentProgress) + Send>;
+
+// How long an engine that lost on time or failed has to get ready again before
+// it is restarted.
+const RECOVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Who plays whom.
+// - RoundRobin - every engine plays every other engine.
+// - Gauntlet - the first engine plays every other engine.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Format {
+    RoundRobin,
+    Gauntlet,
+}
+
+// Plays the games of a format, each pairing once per round. Colors alternate
+// between rounds and, within a round, between the opponents of an engine, so
+// that every engine gets about as many games with white as with black.
+//
+// With an opening suite, the games are played in pairs: rounds 0 and 1 are a
+// pair, then rounds 2 and 3, and so on. Both games of a pair start from the
+// same opening, with the colors swapped, and every pair of games takes the
+// next opening of the suite.
+pub struct Tournament {
+    format: Format,
+    rounds: usize,
+    game_match: EngineMatch,
+    openings: Option<OpeningSuite>,
+    // The time odds of every engine, by index.
+    time_odds: Vec<f64>,
+    // The number of games played at once on engine pools.
+    concurrency: usize,
+    progress: Option<Mutex<Progress>>,
+    checkpoint: Option<PathBuf>,
+}
+
+// A game of the tournament, with the players as indexes into the engines.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Pairing {
+    // The round, starting at 0.
+    pub round: usize,
+    pub white: usize,
+    pub black: usize,
+    // The index of the opening in the suite, or None without a suite.
+    pub opening: Option<usize>,
+}
+
+// A game that was played, with its pairing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TournamentGame {
+    pub pairing: Pairing,
+    pub record: GameRecord,
+}
+
+// How far a tournament is, passed to the progress callback.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TournamentProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+// The results of the games between two engines, from the point of view of one
+// of them.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Tally {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+// The results of every engine against every other engine.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Crosstable {
+    // The tally of row against column.
+    tallies: Vec<Vec<Tally>>,
+}
+
+impl Tally {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // Returns the points, with 1 for a win and 1/2 for a draw.
+    pub fn points(&self) -> f64 {
+        f64::from(self.wins) + f64::from(self.draws) / 2.0
+    }
+
+    fn add(&mut self, other: &Tally) {
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.losses += other.losses;
+    }
+}
+
+impl Crosstable {
+    pub fn new(players: usize) -> Self {
+        Self {
+            tallies: vec![vec![Tally::default(); players]; players],
+        }
+    }
+
+    pub fn players(&self) -> usize {
+        self.tallies.len()
+    }
+
+    // Returns the tally of the player against the opponent.
+    pub fn tally(&self, player: usize, opponent: usize) -> Tally {
+        self.tallies[player][opponent]
+    }
+
+    // Returns the tally of the player against all opponents.
+    pub fn total(&self, player: usize) -> Tally {
+        let mut total = Tally::default();
+        for tally in &self.tallies[player] {
+            total.add(tally);
+        }
+        total
+    }
+
+    // Returns the players and their points, best first. Players with the same
+    // points keep their order.
+    pub fn standings(&self) -> Vec<(usize, f64)> {
+        let mut standings: Vec<(usize, f64)> = (0..self.players())
+            .map(|player| (player, self.total(player).points()))
+            .collect();
+        standings.sort_by(|a, b| b.1.total_cmp(&a.1));
+        standings
+    }
+
+    // Adds the result of a game.
+    pub fn add(&mut self, pairing: &Pairing, result: &GameResult) {
+        let (white, black) = (pairing.white, pairing.black);
+        match result {
+            GameResult::Win(Side::White, _) => {
+                self.tallies[white][black].wins += 1;
+                self.tallies[black][white].losses += 1;
+            }
+            GameResult::Win(Side::Black, _) => {
+                self.tallies[black][white].wins += 1;
+                self.tallies[white][black].losses += 1;
+            }
+            GameResult::Draw(_) => {
+                self.tallies[white][black].draws += 1;
+                self.tallies[black][white].draws += 1;
+            }
+        }
+    }
+}
+
+impl Tournament {
+    pub fn new(format: Format, game_match: EngineMatch) -> Self {
+        Self {
+            format,
+            rounds: 1,
+            game_match,
+            openings: None,
+            time_odds: Vec::new(),
+            concurrency: 1,
+            progress: None,
+            checkpoint: None,
+        }
+    }
+
+    // Sets the number of times every pairing is played. Use an even number
+    // for every pair of engines to play both colors equally often.
+    pub fn set_rounds(&mut self, rounds: usize) -> &mut Self {
+        self.rounds = rounds;
+        self
+    }
+
+    // Sets the suite the games start from. Without one, or with an empty one,
+    // every game starts from the start position of the match.
+    pub fn set_openings(&mut self, openings: OpeningSuite) -> &mut Self {
+        self.openings = Some(openings).filter(|openings| !openings.is_empty());
+        self
+    }
+
+    // Sets the time odds of the engines, by index, see
+    // EngineMatch::set_time_odds. Engines without a factor get 1.0. These
+    // replace the time odds of the match.
+    pub fn set_time_odds(&mut self, time_odds: Vec<f64>) -> &mut Self {
+        self.time_odds = time_odds;
+        self
+    }
+
+    // Returns a receiver for the events of the games played from now on, with
+    // the games numbered by their index in the schedule.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<MatchEvent> {
+        self.game_match.subscribe_events()
+    }
+
+    // Sets the number of games run_pools plays at once. Only the engine to
+    // move searches, since the engines don't ponder, so this is about the
+    // number of engines using the CPU at a time, each with its own threads.
+    pub fn set_concurrency(&mut self, concurrency: usize) -> &mut Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    // Sets the callback that gets every game, with the crosstable so far and
+    // the progress of the tournament. Games come in the order they finish,
+    // which is not the order of the schedule when games are played at once.
+    pub fn set_progress<F>(&mut self, progress: F) -> &mut Self
+    where
+        F: FnMut(&TournamentGame, &Crosstable, TournamentProgress) + Send + 'static,
+    {
+        self.progress = Some(Mutex::new(Box::new(progress)));
+        self
+    }
+
+    // Sets the file every finished game is written to. If the file already has
+    // games of the same tournament, e.g. because an earlier run was
+    // interrupted, they are not played again. Resuming fails with
+    // BadCheckpoint if the format, the number of rounds, the number of
+    // engines or the number of openings changed.
+    pub fn set_checkpoint<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.checkpoint = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    // Resumes the tournament from the checkpoint, i.e. plays the games that
+    // are not in it, and writes them to it. See set_checkpoint and run.
+    pub async fn resume<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        engines: &mut [Engine],
+    ) -> Result<(Vec<TournamentGame>, Crosstable), UziErr> {
+        self.set_checkpoint(path).run(engines).await
+    }
+
+    // Returns the games of the tournament between the given number of
+    // engines, in the order they are played.
+    pub fn schedule(&self, players: usize) -> Vec<Pairing> {
+        let pairs: Vec<(usize, usize)> = match self.format {
+            Format::RoundRobin => (0..players)
+                .flat_map(|a| (a + 1..players).map(move |b| (a, b)))
+                .collect(),
+            Format::Gauntlet => (1..players).map(|b| (0, b)).collect(),
+        };
+        let suite_len = self.openings.as_ref().map(OpeningSuite::len);
+        (0..self.rounds)
+            .flat_map(|round| {
+                let pairs = &pairs;
+                pairs.iter().enumerate().map(move |(i, &(a, b))| {
+                    let (white, black) = if (a + b + round) % 2 == 0 {
+                        (a, b)
+                    } else {
+                        (b, a)
+                    };
+                    let game_pair = round / 2 * pairs.len() + i;
+                    Pairing {
+                        round,
+                        white,
+                        black,
+                        opening: suite_len.map(|len| game_pair % len),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    // Plays the games one after the other, and returns them with the
+    // crosstable. An engine that loses on time or fails is made ready for its
+    // next game, and restarted if needed and if it was created from a
+    // spawner, otherwise it forfeits its next games. This fails if the start
+    // position of the match is not valid, or if the checkpoint can't be read
+    // or written.
+    pub async fn run(
+        &self,
+        engines: &mut [Engine],
+    ) -> Result<(Vec<TournamentGame>, Crosstable), UziErr> {
+        let schedule = self.schedule(engines.len());
+        let state = self.start(engines.len(), &schedule)?;
+        let done = state.done();
+        let state = Mutex::new(state);
+        for (index, pairing) in schedule.into_iter().enumerate() {
+            if done.contains(&index) {
+                continue;
+            }
+            let (white, black) = two_mut(engines, pairing.white, pairing.black);
+            let record = self.play_game(index, &pairing, white, black).await?;
+            match failed(&record.result) {
+                Some(Side::White) => recover(white).await,
+                Some(Side::Black) => recover(black).await,
+                None => (),
+            }
+            self.on_game(&state, index, TournamentGame { pairing, record })?;
+        }
+        Ok(state.into_inner().unwrap().into_results())
+    }
+
+    // Spawns an engine from every profile, and runs the tournament with them
+    // and the time odds of the profiles. An engine that is restarted during
+    // the tournament gets its profile again. To play games at once, use
+    // run_pools with the pools of the profiles, see EngineProfile::pool, after
+    // setting the time odds.
+    pub async fn run_profiles(
+        &mut self,
+        profiles: &[EngineProfile],
+    ) -> Result<(Vec<TournamentGame>, Crosstable), UziErr> {
+        let mut engines = Vec::with_capacity(profiles.len());
+        for profile in profiles {
+            engines.push(profile.spawn().await?);
+        }
+        self.set_time_odds(profiles.iter().map(EngineProfile::time_odds).collect());
+        self.run(&mut engines).await
+    }
+
+    // Like run, but plays as many games at once as the concurrency, with the
+    // engines of the pools, one pool per engine. Both games of a game pair,
+    // i.e. the games of the same engines in rounds 0 and 1, 2 and 3, and so
+    // on, are played by the same engine processes. An engine that loses on
+    // time or fails is discarded, and the pool spawns a new one. Returns the
+    // games in the order of the schedule. This fails if the start position of
+    // the match is not valid, if an engine can't be spawned, or if the
+    // checkpoint can't be read or written.
+    pub async fn run_pools(
+        &self,
+        pools: &[EnginePool],
+    ) -> Result<(Vec<TournamentGame>, Crosstable), UziErr> {
+        let schedule = self.schedule(pools.len());
+        let state = self.start(pools.len(), &schedule)?;
+        let done = state.done();
+        let state = Mutex::new(state);
+        let game_pairs: Vec<Vec<(usize, Pairing)>> = game_pairs(schedule)
+            .into_iter()
+            .map(|mut games| {
+                games.retain(|(index, _)| !done.contains(index));
+                games
+            })
+            .filter(|games| !games.is_empty())
+            .collect();
+        let game_pairs = Mutex::new(game_pairs.into_iter());
+        let worker = async || loop {
+            if state.lock().unwrap().error.is_some() {
+                break;
+            }
+            let Some(games) = game_pairs.lock().unwrap().next() else {
+                break;
+            };
+            if let Err(err) = self.play_game_pair(pools, games, &state).await {
+                state.lock().unwrap().error.get_or_insert(err);
+            }
+        };
+        let workers =
+            (0..self.concurrency).map(|_| Box::pin(worker()) as Pin<Box<dyn Future<Output = ()>>>);
+        join_all(workers.collect()).await;
+        let mut state = state.into_inner().unwrap();
+        match state.error.take() {
+            Some(err) => Err(err),
+            None => Ok(state.into_results()),
+        }
+    }
+
+    // Plays the games of a game pair, which have the same engines, with the
+    // index of every game in the schedule.
+    async fn play_game_pair(
+        &self,
+        pools: &[EnginePool],
+        games: Vec<(usize, Pairing)>,
+        state: &Mutex<State>,
+    ) -> Result<(), UziErr> {
+        let mut engines = None;
+        for (index, pairing) in games {
+            let (low, high) = if pairing.white < pairing.black {
+                (pairing.white, pairing.black)
+            } else {
+                (pairing.black, pairing.white)
+            };
+            // The engines are checked out in the order of the pools, so that
+            // workers waiting for each other's engines can't deadlock.
+            let (mut low_eng, mut high_eng) = match engines.take() {
+                Some(engines) => engines,
+                None => (pools[low].checkout().await?, pools[high].checkout().await?),
+            };
+            let (white, black) = if pairing.white == low {
+                (&mut *low_eng, &mut *high_eng)
+            } else {
+                (&mut *high_eng, &mut *low_eng)
+            };
+            let record = self.play_game(index, &pairing, white, black).await?;
+            match failed(&record.result) {
+                // The engine may still be searching, or be broken.
+                Some(side) => {
+                    let failed = match side {
+                        Side::White => pairing.white,
+                        Side::Black => pairing.black,
+                    };
+                    if failed == low {
+                        low_eng.discard();
+                    } else {
+                        high_eng.discard();
+                    }
+                }
+                None => engines = Some((low_eng, high_eng)),
+            }
+            self.on_game(state, index, TournamentGame { pairing, record })?;
+        }
+        Ok(())
+    }
+
+    async fn play_game(
+        &self,
+        game: usize,
+        pairing: &Pairing,
+        white: &mut Engine,
+        black: &mut Engine,
+    ) -> Result<GameRecord, UziErr> {
+        let opening = self.openings.as_ref().zip(pairing.opening);
+        let start = opening.and_then(|(suite, i)| suite.get(i));
+        let odds = |player: usize| self.time_odds.get(player).copied().unwrap_or(1.0);
+        self.game_match
+            .play_game(
+                game,
+                start.unwrap_or(self.game_match.start()),
+                white,
+                black,
+                [odds(pairing.white), odds(pairing.black)],
+            )
+            .await
+    }
+
+    // Returns the state of a new run, with the games of the checkpoint.
+    fn start(&self, players: usize, schedule: &[Pairing]) -> Result<State, UziErr> {
+        let mut state = State::new(players, schedule.len());
+        let Some(ref path) = self.checkpoint else {
+            return Ok(state);
+        };
+        let format = match self.format {
+            Format::RoundRobin => "roundrobin",
+            Format::Gauntlet => "gauntlet",
+        };
+        let openings = self.openings.as_ref().map_or(0, OpeningSuite::len);
+        let header = format!(
+            "tournament {} players {} rounds {} openings {}",
+            format, players, self.rounds, openings
+        );
+        let checkpoint = Checkpoint::new(path.clone(), header);
+        let mut done = HashSet::new();
+        for (index, game) in checkpoint.load()? {
+            if schedule.get(index) != Some(&game.pairing) {
+                return Err(UziErr::BadCheckpoint);
+            }
+            if done.insert(index) {
+                state.crosstable.add(&game.pairing, &game.record.result);
+                state.games.push((index, game));
+            }
+        }
+        state.checkpoint = Some(checkpoint);
+        Ok(state)
+    }
+
+    // Adds the game with the index in the schedule, writes it to the
+    // checkpoint, and reports it.
+    fn on_game(
+        &self,
+        state: &Mutex<State>,
+        index: usize,
+        game: TournamentGame,
+    ) -> Result<(), UziErr> {
+        let mut state = state.lock().unwrap();
+        if let Some(ref checkpoint) = state.checkpoint {
+            checkpoint.append(index, &game)?;
+        }
+        state.crosstable.add(&game.pairing, &game.record.result);
+        self.game_match
+            .send_event(MatchEvent::CrosstableUpdated(state.crosstable.clone()));
+        if let Some(ref progress) = self.progress {
+            let progress_of = TournamentProgress {
+                done: state.games.len() + 1,
+                total: state.total,
+            };
+            (progress.lock().unwrap())(&game, &state.crosstable, progress_of);
+        }
+        state.games.push((index, game));
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Tournament {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter
+            .debug_struct("Tournament")
+            .field("format", &self.format)
+            .field("rounds", &self.rounds)
+            .field("game_match", &self.game_match)
+            .field("openings", &self.openings)
+            .field("time_odds", &self.time_odds)
+            .field("concurrency", &self.concurrency)
+            .field("checkpoint", &self.checkpoint)
+            .finish_non_exhaustive()
+    }
+}
+
+// The games played so far.
+struct State {
+    // The games with their index in the schedule.
+    games: Vec<(usize, TournamentGame)>,
+    total: usize,
+    crosstable: Crosstable,
+    // The first error, after which no more games are started.
+    error: Option<UziErr>,
+    checkpoint: Option<Checkpoint>,
+}
+
+impl State {
+    fn new(players: usize, total: usize) -> Self {
+        Self {
+            games: Vec::new(),
+            total,
+            crosstable: Crosstable::new(players),
+            error: None,
+            checkpoint: None,
+        }
+    }
+
+    // Returns the indexes of the games played so far.
+    fn done(&self) -> HashSet<usize> {
+        self.games.iter().map(|(index, _)| *index).collect()
+    }
+
+    fn into_results(self) -> (Vec<TournamentGame>, Crosstable) {
+        let mut games = self.games;
+        games.sort_by_key(|(index, _)| *index);
+        let games = games.into_iter().map(|(_, game)| game).collect();
+        (games, self.crosstable)
+    }
+}
+
+// Groups the schedule into game pairs, with the index of every game in the
+// schedule. Odd rounds are paired with the round before.
+fn game_pairs(schedule: Vec<Pairing>) -> Vec<Vec<(usize, Pairing)>> {
+    let mut game_pairs: Vec<Vec<(usize, Pairing)>> = Vec::new();
+    for (index, pairing) in schedule.into_iter().enumerate() {
+        let is_pair = |first: &Pairing| {
+            first.round / 2 == pairing.round / 2
+                && (first.white, first.black) == (pairing.black, pairing.white)
+        };
+        match game_pairs
+            .iter_mut()
+            .find(|games| games.len() == 1 && is_pair(&games[0].1))
+        {
+            Some(games) => games.push((index, pairing)),
+            None => game_pairs.push(vec![(index, pairing)]),
+        }
+    }
+    game_pairs
+}
+
+// Returns the side whose engine lost on time or failed.
+fn failed(result: &GameResult) -> Option<Side> {
+    match result {
+        GameResult::Win(side, WinReason::Timeout | WinReason::EngineFailed(_)) => {
+            Some(side.other())
+        }
+        _ => None,
+    }
+}
+
+// Gets an engine that lost on time or failed ready for its next game. A search
+// that is still running is stopped, and an engine that doesn't respond is
+// restarted.
+async fn recover(eng: &mut Engine) {
+    let ready = async {
+        if eng.is_searching() {
+            eng.stop().await?;
+            eng.wait_best_move().await?;
+        }
+        eng.sync(RECOVER_TIMEOUT).await
+    };
+    if !matches!(time::timeout(RECOVER_TIMEOUT, ready).await, Ok(Ok(()))) {
+        // An engine that can't be restarted fails its next games.
+        let _ = eng.restart().await;
+    }
+}
+
+// Returns two different engines of the slice.
+fn two_mut(engines: &mut [Engine], a: usize, b: usize) -> (&mut Engine, &mut Engine) {
+    if a < b {
+        let (left, right) = engines.split_at_mut(b);
+        (&mut left[a], &mut right[0])
+    } else {
+        let (left, right) = engines.split_at_mut(a);
+        (&mut right[0], &mut left[b])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engmatch::TimeControl;
+    use crate::engproc::{Launcher, Spawner};
+    use crate::guicmd::Pos;
+    use crate::testutil::fake_engine;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    // Mates with the rook on its first move, so white wins every game.
+    const MATING_ENGINE: &str = r#"
+while read -r line; do
+    case "$line" in
+        uci) echo "uciok";;
+        isready) echo "readyok";;
+        go*) echo "bestmove h1h8";;
+    esac
+done
+"#;
+
+    fn tournament(format: Format, rounds: usize) -> Tournament {
+        let tc = TimeControl::new(Duration::from_secs(5), Duration::ZERO);
+        let mut game_match = EngineMatch::new(tc);
+        game_match.set_start(Pos::with_fen("k7/8/K7/8/8/8/8/7R w - - 0 1"));
+        let mut tournament = Tournament::new(format, game_match);
+        tournament.set_rounds(rounds);
+        tournament
+    }
+
+    fn engines(n: usize) -> Vec<Engine> {
+        (0..n)
+            .map(|_| Engine::new(fake_engine(MATING_ENGINE)))
+            .collect()
+    }
+
+    #[test]
+    fn tournament_schedule_balances_colors() {
+        let schedule = tournament(Format::RoundRobin, 2).schedule(4);
+        assert_eq!(schedule.len(), 12);
+        for player in 0..4 {
+            let whites = schedule.iter().filter(|p| p.white == player).count();
+            let blacks = schedule.iter().filter(|p| p.black == player).count();
+            assert_eq!((whites, blacks), (3, 3));
+        }
+
+        let schedule = tournament(Format::Gauntlet, 1).schedule(4);
+        let pairs: Vec<(usize, usize)> = schedule.iter().map(|p| (p.white, p.black)).collect();
+        assert_eq!(pairs, vec![(1, 0), (0, 2), (3, 0)]);
+    }
+
+    #[test]
+    fn tournament_schedule_pairs_openings() {
+        let suite = OpeningSuite::from_lines(["e2e4", "d2d4"]).unwrap();
+        let mut tournament = tournament(Format::RoundRobin, 4);
+        tournament.set_openings(suite);
+        let schedule = tournament.schedule(3);
+        let openings: Vec<Option<usize>> = schedule.iter().map(|p| p.opening).collect();
+        let (a, b) = (Some(0), Some(1));
+        assert_eq!(openings, vec![a, b, a, a, b, a, b, a, b, b, a, b]);
+        // Both games of a pair have the same opening and swapped colors.
+        for (first, second) in schedule[..3].iter().zip(&schedule[3..6]) {
+            assert_eq!(first.opening, second.opening);
+            assert_eq!((first.white, first.black), (second.black, second.white));
+        }
+        assert!(tournament
+            .set_openings(OpeningSuite::default())
+            .schedule(3)
+            .iter()
+            .all(|p| p.opening.is_none()));
+    }
+
+    #[tokio::test]
+    async fn tournament_plays_openings() {
+        let fens = [
+            "k7/8/K7/8/8/8/8/7R w - - 0 1",
+            "k7/8/1K6/8/8/8/8/7R w - - 0 1",
+        ];
+        let mut tournament = tournament(Format::Gauntlet, 2);
+        tournament.set_openings(OpeningSuite::from_lines(fens).unwrap());
+        let mut engines = engines(3);
+        let (games, crosstable) = tournament.run(&mut engines).await.unwrap();
+        let starts: Vec<Option<&str>> = games.iter().map(|game| game.record.start.fen()).collect();
+        assert_eq!(
+            starts,
+            vec![Some(fens[0]), Some(fens[1]), Some(fens[0]), Some(fens[1])]
+        );
+        // Each pair of games is split, as white wins every game.
+        assert_eq!(crosstable.total(0).points(), 2.0);
+    }
+
+    // Returns a pool per engine, with engines that take the given time to
+    // mate.
+    fn pools(n: usize, size: usize, sleep: &str) -> Vec<EnginePool> {
+        let script = MATING_ENGINE.replace("go*)", &format!("go*) sleep {};", sleep));
+        (0..n)
+            .map(|_| {
+                let script = script.clone();
+                EnginePool::new(Spawner::new(move || Ok(fake_engine(&script))), size)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tournament_game_pairs() {
+        let schedule = tournament(Format::RoundRobin, 3).schedule(3);
+        let pairs: Vec<Vec<usize>> = game_pairs(schedule)
+            .into_iter()
+            .map(|games| games.into_iter().map(|(index, _)| index).collect())
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                vec![0, 3],
+                vec![1, 4],
+                vec![2, 5],
+                vec![6],
+                vec![7],
+                vec![8]
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn tournament_concurrent_games_on_pools() {
+        let pools = pools(3, 2, "0.4");
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let mut tournament = tournament(Format::RoundRobin, 2);
+        let reported = progress.clone();
+        tournament
+            .set_concurrency(3)
+            .set_progress(move |_, crosstable, progress| {
+                let games = crosstable.total(0).games() + crosstable.total(1).games();
+                reported.lock().unwrap().push((progress, games));
+            });
+        let started = Instant::now();
+        let (games, crosstable) = tournament.run_pools(&pools).await.unwrap();
+        // The three game pairs are played at once, each in about 0.8s.
+        assert!(started.elapsed() < Duration::from_millis(1800));
+
+        let schedule: Vec<Pairing> = games.iter().map(|game| game.pairing).collect();
+        assert_eq!(schedule, tournament.schedule(3));
+        assert_eq!(crosstable.total(1).points(), 2.0);
+        let progress = progress.lock().unwrap();
+        assert_eq!(progress.len(), 6);
+        assert_eq!(progress[5], (TournamentProgress { done: 6, total: 6 }, 8));
+        // The engines are back in their pools.
+        assert!(pools.iter().all(|pool| pool.available() == 2));
+    }
+
+    #[tokio::test]
+    async fn tournament_restarts_crashed_engines() {
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let spawns = spawned.clone();
+        let crashing = Spawner::new(move || {
+            spawns.fetch_add(1, Ordering::SeqCst);
+            Ok(fake_engine(
+                &MATING_ENGINE.replace("echo \"bestmove h1h8\"", "exit 1"),
+            ))
+        });
+        let mut engines = vec![
+            Engine::new(fake_engine(MATING_ENGINE)),
+            Engine::from_spawner(crashing).unwrap(),
+        ];
+        let (games, crosstable) = tournament(Format::RoundRobin, 2)
+            .run(&mut engines)
+            .await
+            .unwrap();
+        // Engine 1 crashes when it has white, and loses as black anyway.
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].record.result.reason(), "White's engine crashed");
+        assert_eq!(crosstable.total(0).points(), 2.0);
+        assert_eq!(spawned.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn tournament_resumes_from_checkpoint() {
+        let path = std::env::temp_dir().join(format!("uzi-tournament-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut engines = engines(3);
+        let mut tournament = tournament(Format::RoundRobin, 2);
+        tournament.set_checkpoint(&path);
+        let (games, crosstable) = tournament.run(&mut engines).await.unwrap();
+        assert_eq!(games.len(), 6);
+
+        // Keep the first two games, as if the tournament was interrupted.
+        let text = std::fs::read_to_string(&path).unwrap();
+        let kept: Vec<&str> = text.split("\n\n").take(3).collect();
+        std::fs::write(&path, kept.join("\n\n") + "\n\n").unwrap();
+
+        let played = Arc::new(AtomicUsize::new(0));
+        let counter = played.clone();
+        tournament.set_progress(move |_, _, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let (resumed, resumed_crosstable) = tournament.resume(&path, &mut engines).await.unwrap();
+        assert_eq!(played.load(Ordering::SeqCst), 4);
+        assert_eq!(resumed_crosstable, crosstable);
+        // The times in the checkpoint are in whole milliseconds.
+        let results = |games: &[TournamentGame]| -> Vec<(Pairing, GameResult)> {
+            games
+                .iter()
+                .map(|game| (game.pairing, game.record.result.clone()))
+                .collect()
+        };
+        assert_eq!(results(&resumed), results(&games));
+
+        // The checkpoint is from a tournament with other rounds.
+        tournament.set_rounds(4);
+        let resumed = tournament.run(&mut engines).await;
+        assert_eq!(resumed, Err(UziErr::BadCheckpoint));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tournament_with_profiles() {
+        let profiles: Vec<EngineProfile> = [1.0, 0.5]
+            .into_iter()
+            .map(|time_odds| {
+                let mut launcher = Launcher::new("sh");
+                launcher.add_arg("-c").add_arg(MATING_ENGINE);
+                let mut profile = EngineProfile::new(launcher);
+                profile.set_time_odds(time_odds);
+                profile
+            })
+            .collect();
+        let (games, crosstable) = tournament(Format::RoundRobin, 2)
+            .run_profiles(&profiles)
+            .await
+            .unwrap();
+        assert_eq!(crosstable.total(1).games(), 2);
+        let short = TimeControl::new(Duration::from_millis(2500), Duration::ZERO);
+        for game in games {
+            let (white, black) = (
+                game.record.white_time_control,
+                game.record.black_time_control,
+            );
+            let odds = if game.pairing.white == 1 {
+                white
+            } else {
+                black
+            };
+            assert_eq!(odds, short);
+        }
+    }
+
+    #[tokio::test]
+    async fn tournament_round_robin_crosstable() {
+        let mut engines = engines(3);
+        let (games, crosstable) = tournament(Format::RoundRobin, 2)
+            .run(&mut engines)
+            .await
+            .unwrap();
+        assert_eq!(games.len(), 6);
+        // White wins every game, and every pair played both colors.
+        let one_each = Tally {
+            wins: 1,
+            draws: 0,
+            losses: 1,
+        };
+        assert_eq!(crosstable.tally(0, 1), one_each);
+        assert_eq!(crosstable.tally(2, 1), one_each);
+        assert_eq!(crosstable.total(1).points(), 2.0);
+        assert_eq!(crosstable.total(1).games(), 4);
+    }
+
+    #[tokio::test]
+    async fn tournament_gauntlet_standings() {
+        let mut engines = engines(3);
+        let tournament = tournament(Format::Gauntlet, 1);
+        let mut events = tournament.subscribe_events();
+        let (games, crosstable) = tournament.run(&mut engines).await.unwrap();
+        assert_eq!(games.len(), 2);
+        let events: Vec<String> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|event| match event {
+                MatchEvent::GameStarted { game, .. } => format!("started {}", game),
+                MatchEvent::MovePlayed { game, .. } => format!("move {}", game),
+                MatchEvent::Adjudicated { game, .. } => format!("adjudicated {}", game),
+                MatchEvent::GameFinished { game, .. } => format!("finished {}", game),
+                MatchEvent::CrosstableUpdated(crosstable) => {
+                    format!("crosstable {}", crosstable.total(0).games())
+                }
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                "started 0",
+                "move 0",
+                "finished 0",
+                "crosstable 1",
+                "started 1",
+                "move 1",
+                "finished 1",
+                "crosstable 2"
+            ]
+        );
+        // Engine 1 has white against engine 0, which has white against 2.
+        assert_eq!(crosstable.standings(), vec![(0, 1.0), (1, 1.0), (2, 0.0)]);
+        assert_eq!(crosstable.tally(1, 2), Tally::default());
+    }
+}
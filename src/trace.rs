@@ -0,0 +1,190 @@
+// This module records the protocol with the tracing crate if the tracing
+// feature is enabled, and does nothing otherwise. Every line exchanged is an
+// event at trace level, with the fields
+//
+// - direction - "sent" or "received".
+// - engine - the name of the engine, once it is known from its id. Only the
+//   client has it.
+// - line - the line.
+//
+// The target is uzi::client for the lines the client exchanges with an engine,
+// and uzi::server for the lines the runner exchanges with the GUI. Commands
+// that are ignored, e.g. a bestmove that doesn't belong to any search, are
+// events at debug level, with a reason or an error instead of a direction. The
+// client also has debug spans for the handshake, go and waiting for the
+// bestmove of a search. The engine servers record the connections they fail to
+// accept as events at warn level, with the target uzi::listen and the error.
+
+use crate::engcmd::EngCmd;
+use crate::err::UziErr;
+use std::io;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum Direction {
+    Sent,
+    Received,
+}
+
+impl Direction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Sent => "sent",
+            Self::Received => "received",
+        }
+    }
+}
+
+// Records a line the client exchanged with an engine.
+#[cfg(feature = "tracing")]
+pub(crate) fn client_line(direction: Direction, engine: Option<&str>, line: &str) {
+    tracing::trace!(
+        target: "uzi::client",
+        direction = direction.as_str(),
+        engine,
+        line
+    );
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn client_line(_direction: Direction, _engine: Option<&str>, _line: &str) {}
+
+// Records a command from an engine that the client ignored.
+#[cfg(feature = "tracing")]
+pub(crate) fn client_ignored(engine: Option<&str>, cmd: &EngCmd, reason: &str) {
+    tracing::debug!(target: "uzi::client", engine, line = %cmd, reason, "ignored");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn client_ignored(_engine: Option<&str>, _cmd: &EngCmd, _reason: &str) {}
+
+// Records a line the runner exchanged with the GUI.
+#[cfg(feature = "tracing")]
+pub(crate) fn server_line(direction: Direction, line: &str) {
+    tracing::trace!(target: "uzi::server", direction = direction.as_str(), line);
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn server_line(_direction: Direction, _line: &str) {}
+
+// Records a command from the GUI that the runner ignored.
+#[cfg(feature = "tracing")]
+pub(crate) fn server_ignored(line: &str, err: &UziErr) {
+    tracing::debug!(target: "uzi::server", line, error = ?err, "ignored");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn server_ignored(_line: &str, _err: &UziErr) {}
+
+// Records a connection an engine server failed to accept.
+#[cfg(feature = "tracing")]
+pub(crate) fn accept_failed(err: &io::Error) {
+    tracing::warn!(target: "uzi::listen", error = %err, "accept failed");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn accept_failed(_err: &io::Error) {}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use crate::client::Engine;
+    use crate::engcmd::EngCmd;
+    use crate::guicmd::Go;
+    use crate::server::UciOut;
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+    use std::fmt::{Debug, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    // Records the spans and events as lines, e.g. "span go engine=Fake" or
+    // "uzi::client direction=sent line=uci".
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    #[derive(Default)]
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            let _ = write!(self.0, " {}={}", field.name(), value);
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Fields::default();
+            span.record(&mut fields);
+            let line = format!("span {}{}", span.metadata().name(), fields.0);
+            self.0.lock().unwrap().push(line);
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            let line = format!("{}{}", event.metadata().target(), fields.0);
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[test]
+    fn trace_protocol() {
+        let recorder = Recorder::default();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            runtime.block_on(async {
+                let mut eng = Engine::new(fake_engine(FAKE_ENGINE));
+                eng.uci(Duration::from_secs(5)).await.unwrap();
+                let mut go = Go::new();
+                go.set_depth(2);
+                eng.go(&go).await.unwrap().wait().await.unwrap();
+            });
+            UciOut::new(Vec::new()).send(&EngCmd::ReadyOk).unwrap();
+        });
+
+        let lines = recorder.0.lock().unwrap().clone();
+        assert_eq!(
+            lines,
+            [
+                "span handshake",
+                "uzi::client direction=sent line=uci",
+                "uzi::client direction=received line=id name Fake",
+                "uzi::client direction=received engine=Fake line=id author uzi",
+                "uzi::client direction=received engine=Fake \
+                 line=option name Hash type spin default 16 min 1 max 1024",
+                "uzi::client direction=received engine=Fake line=uciok",
+                "span go engine=Fake go=go depth 2",
+                "uzi::client direction=sent engine=Fake line=go depth 2",
+                "span search engine=Fake",
+                "uzi::client direction=received engine=Fake \
+                 line=info depth 1 score cp 10 pv e2e4",
+                "uzi::client direction=received engine=Fake \
+                 line=info depth 2 score cp 20 pv e2e4 e7e5",
+                "uzi::client direction=received engine=Fake \
+                 line=bestmove e2e4 ponder e7e5",
+                "uzi::server direction=sent line=readyok",
+            ]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+// This module contains TrafficStats, which counts the lines exchanged with an
+// engine, so that a long running service can watch how chatty its engines
+// are, e.g. an engine that floods the client with info lines.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+// The kind under which lines from the engine that are not UCI commands are
+// counted, e.g. banners.
+pub const RAW: &str = "raw";
+
+// The lines exchanged with an engine since the client was created, or since
+// the stats were reset. Commands are counted by their first word, e.g. "info"
+// or "go", and bytes include the line ends.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TrafficStats {
+    since: Instant,
+    sent: BTreeMap<String, u64>,
+    received: BTreeMap<String, u64>,
+    bytes_sent: u64,
+    bytes_received: u64,
+    // The info updates the throttle of a search didn't deliver, since newer
+    // ones replaced them.
+    throttled: u64,
+}
+
+impl TrafficStats {
+    pub fn new() -> Self {
+        Self {
+            since: Instant::now(),
+            sent: BTreeMap::new(),
+            received: BTreeMap::new(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            throttled: 0,
+        }
+    }
+
+    // The time the stats have been collected for.
+    pub fn elapsed(&self) -> Duration {
+        self.since.elapsed()
+    }
+
+    // The number of commands of the kind sent to the engine, e.g. "go".
+    pub fn sent(&self, kind: &str) -> u64 {
+        self.sent.get(kind).copied().unwrap_or(0)
+    }
+
+    // The number of commands of the kind received from the engine, e.g.
+    // "info", or RAW for the lines that are not UCI commands.
+    pub fn received(&self, kind: &str) -> u64 {
+        self.received.get(kind).copied().unwrap_or(0)
+    }
+
+    // The number of commands of every kind sent, by kind.
+    pub fn sent_by_kind(&self) -> &BTreeMap<String, u64> {
+        &self.sent
+    }
+
+    pub fn received_by_kind(&self) -> &BTreeMap<String, u64> {
+        &self.received
+    }
+
+    // The average number of commands of the kind received per second.
+    pub fn received_rate(&self, kind: &str) -> f64 {
+        rate(self.received(kind), self.elapsed())
+    }
+
+    pub fn sent_rate(&self, kind: &str) -> f64 {
+        rate(self.sent(kind), self.elapsed())
+    }
+
+    // The average number of info lines received per second.
+    pub fn info_rate(&self) -> f64 {
+        self.received_rate("info")
+    }
+
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    // The number of info updates the info throttle dropped, see
+    // Engine::set_info_throttle.
+    pub fn throttled(&self) -> u64 {
+        self.throttled
+    }
+
+    pub(crate) fn on_sent(&mut self, line: &str) {
+        count(&mut self.sent, kind(line));
+        self.bytes_sent += line.len() as u64 + 1;
+    }
+
+    // Counts a line from the engine, which is a command if it could be
+    // parsed.
+    pub(crate) fn on_received(&mut self, line: &str, is_cmd: bool) {
+        count(&mut self.received, if is_cmd { kind(line) } else { RAW });
+        self.bytes_received += line.len() as u64 + 1;
+    }
+
+    pub(crate) fn on_throttled(&mut self, updates: u64) {
+        self.throttled += updates;
+    }
+}
+
+impl Default for TrafficStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn kind(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or_default()
+}
+
+fn count(counts: &mut BTreeMap<String, u64>, kind: &str) {
+    match counts.get_mut(kind) {
+        Some(count) => *count += 1,
+        None => {
+            counts.insert(kind.to_string(), 1);
+        }
+    }
+}
+
+fn rate(count: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    count as f64 / elapsed.as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traffic_counts_lines() {
+        let mut stats = TrafficStats::new();
+        stats.on_sent("go depth 5");
+        stats.on_received("info depth 1 pv e2e4", true);
+        stats.on_received("info depth 2 pv e2e4", true);
+        stats.on_received("Fake by uzi", false);
+        stats.on_received("bestmove e2e4", true);
+        stats.on_throttled(3);
+
+        assert_eq!(stats.sent("go"), 1);
+        assert_eq!(stats.received("info"), 2);
+        assert_eq!(stats.received(RAW), 1);
+        assert_eq!(stats.received("readyok"), 0);
+        assert_eq!(
+            stats.received_by_kind().keys().collect::<Vec<_>>(),
+            ["bestmove", "info", "raw"]
+        );
+        assert_eq!(stats.bytes_sent(), 11);
+        assert_eq!(stats.bytes_received(), 21 + 21 + 12 + 14);
+        assert_eq!(stats.throttled(), 3);
+        assert_eq!(rate(10, Duration::ZERO), 0.0);
+        assert_eq!(rate(10, Duration::from_secs(2)), 5.0);
+    }
+}
@@ -0,0 +1,78 @@
+// This module contains Transcript, which keeps the last lines exchanged with an
+// engine for debugging.
+
+use std::collections::VecDeque;
+
+// A ring buffer of the last lines exchanged with an engine, in the order they
+// were sent or received. Lines sent to the engine start with "> " and lines
+// received from it with "< ".
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Transcript {
+    len: usize,
+    lines: VecDeque<String>,
+}
+
+impl Transcript {
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            lines: VecDeque::with_capacity(len),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.len > 0
+    }
+
+    // Changes the number of lines kept, dropping the oldest lines if needed.
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len;
+        while self.lines.len() > len {
+            self.lines.pop_front();
+        }
+    }
+
+    pub fn sent(&mut self, line: &str) {
+        self.push(format!("> {}", line));
+    }
+
+    pub fn received(&mut self, line: &str) {
+        self.push(format!("< {}", line));
+    }
+
+    // Returns the lines, oldest first.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.iter().cloned().collect()
+    }
+
+    fn push(&mut self, line: String) {
+        if self.len == 0 {
+            return;
+        }
+        if self.lines.len() == self.len {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcript_keeps_last_lines() {
+        let mut transcript = Transcript::new(2);
+        transcript.sent("uci");
+        transcript.received("id name Fake");
+        transcript.received("uciok");
+        assert_eq!(transcript.lines(), vec!["< id name Fake", "< uciok"]);
+
+        transcript.set_len(1);
+        assert_eq!(transcript.lines(), vec!["< uciok"]);
+
+        transcript.set_len(0);
+        transcript.sent("isready");
+        assert!(transcript.lines().is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+// This module contains the Transport trait, which is how the client exchanges
+// lines with an engine, and the transports provided by the library:
+// - EngineProcess, for engines running as child processes.
+// - StreamTransport, for any byte stream, e.g. a socket or an in-memory pipe.
+// - BlockingTransport, for backends with blocking I/O, e.g. a serial port.
+//
+// Transport is the extension point for other backends, e.g. an engine in a
+// container. A backend with async I/O implements Transport, and one with
+// blocking I/O implements LineSource and LineSink, the blocking flavor, and is
+// wrapped in a BlockingTransport.
+
+use crate::engproc::{CrashReport, EngineProcess};
+use crate::err::UziErr;
+use crate::framing::LineReader;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::io::{BufRead, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task;
+
+// How many lines a BlockingTransport reads ahead of the client.
+const READ_AHEAD: usize = 256;
+
+// The future returned by the Transport methods. These are boxed so that the
+// client can hold any transport as a trait object.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+// A line oriented connection to an engine.
+pub trait Transport: Debug + Send {
+    // Sends a line to the engine. The newline is added by the transport.
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>>;
+
+    // Reads the next line from the engine, without the newline. Returns None
+    // if the engine closed the connection. This has to be cancel safe, since
+    // the client reads with timeouts.
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>>;
+
+    // Returns a receiver for diagnostic output of the engine, e.g. stderr.
+    // Transports without such output return a closed receiver.
+    fn subscribe_stderr(&self) -> broadcast::Receiver<String> {
+        broadcast::channel(1).1
+    }
+
+    // Describes why the engine closed the connection unexpectedly.
+    fn crash_report(&mut self) -> BoxFuture<'_, CrashReport> {
+        Box::pin(async { CrashReport::default() })
+    }
+
+    // Terminates the engine, or closes the connection to it.
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    // Waits for the engine to exit, e.g. after quit. By default this waits for
+    // the engine to close the connection, skipping anything it still sends.
+    fn wait_exit(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        Box::pin(async move {
+            while self.recv_line().await?.is_some() {}
+            Ok(())
+        })
+    }
+}
+
+impl Transport for EngineProcess {
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        Box::pin(EngineProcess::send_line(self, line))
+    }
+
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        Box::pin(EngineProcess::recv_line(self))
+    }
+
+    fn subscribe_stderr(&self) -> broadcast::Receiver<String> {
+        EngineProcess::subscribe_stderr(self)
+    }
+
+    fn crash_report(&mut self) -> BoxFuture<'_, CrashReport> {
+        Box::pin(EngineProcess::crash_report(self))
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        Box::pin(EngineProcess::kill(self))
+    }
+
+    fn wait_exit(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        Box::pin(EngineProcess::wait_exit(self))
+    }
+}
+
+// A transport over a pair of byte streams, e.g. the halves of a TCP
+// connection or of tokio::io::duplex.
+#[derive(Debug)]
+pub struct StreamTransport<R, W> {
+    reader: LineReader<R>,
+    writer: W,
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> StreamTransport<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader: LineReader::new(reader),
+            writer,
+        }
+    }
+}
+
+impl<R, W> Transport for StreamTransport<R, W>
+where
+    R: AsyncRead + Debug + Send + Unpin,
+    W: AsyncWrite + Debug + Send + Unpin,
+{
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        Box::pin(async move {
+            self.writer.write_all(line.as_bytes()).await?;
+            self.writer.write_all(b"\n").await?;
+            self.writer.flush().await?;
+            Ok(())
+        })
+    }
+
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        Box::pin(self.reader.next_line())
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        Box::pin(async move { Ok(self.writer.shutdown().await?) })
+    }
+}
+
+// The reading half of a blocking transport, see BlockingTransport.
+pub trait LineSource: Send + 'static {
+    // Blocks until the next line from the engine, and returns it without the
+    // line end. Returns None if the engine closed the connection.
+    fn recv_line(&mut self) -> Result<Option<String>, UziErr>;
+}
+
+// The writing half of a blocking transport, see BlockingTransport.
+pub trait LineSink: Send + 'static {
+    // Sends a line to the engine. The line end is added by the sink.
+    fn send_line(&mut self, line: &str) -> Result<(), UziErr>;
+
+    // Terminates the engine, or closes the connection to it.
+    fn kill(&mut self) -> Result<(), UziErr> {
+        Ok(())
+    }
+}
+
+// Any buffered reader is a source of lines that end with LF or CRLF. Bytes
+// that are not valid UTF-8 are replaced.
+impl<R: BufRead + Send + 'static> LineSource for R {
+    fn recv_line(&mut self) -> Result<Option<String>, UziErr> {
+        let mut line = Vec::new();
+        if self.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        while line.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+            line.pop();
+        }
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+}
+
+// Any writer is a sink of lines. It is closed when the transport is dropped.
+impl<W: Write + Send + 'static> LineSink for W {
+    fn send_line(&mut self, line: &str) -> Result<(), UziErr> {
+        self.write_all(line.as_bytes())?;
+        self.write_all(b"\n")?;
+        self.flush()?;
+        Ok(())
+    }
+}
+
+// A transport over a blocking source and sink, e.g. the halves of a serial
+// port:
+//
+// let port = serialport::new("/dev/ttyUSB0", 115_200).open()?;
+// let transport = BlockingTransport::new(BufReader::new(port.try_clone()?), port);
+//
+// The source is read on a thread of its own, which ends when the source
+// returns None or fails, or, after the transport is dropped, when the next
+// line arrives. The sink is written on the blocking threads of the runtime.
+pub struct BlockingTransport {
+    lines: mpsc::Receiver<Result<Option<String>, UziErr>>,
+    sink: Arc<Mutex<dyn LineSink>>,
+}
+
+impl BlockingTransport {
+    pub fn new<S: LineSource, K: LineSink>(mut source: S, sink: K) -> Self {
+        let (tx, rx) = mpsc::channel(READ_AHEAD);
+        thread::spawn(move || loop {
+            let line = source.recv_line();
+            let is_end = !matches!(line, Ok(Some(_)));
+            if tx.blocking_send(line).is_err() || is_end {
+                break;
+            }
+        });
+        Self {
+            lines: rx,
+            sink: Arc::new(Mutex::new(sink)),
+        }
+    }
+
+    // Runs the function with the sink on a blocking thread.
+    async fn with_sink<F>(&self, f: F) -> Result<(), UziErr>
+    where
+        F: FnOnce(&mut dyn LineSink) -> Result<(), UziErr> + Send + 'static,
+    {
+        let sink = Arc::clone(&self.sink);
+        task::spawn_blocking(move || f(&mut *sink.lock().unwrap()))
+            .await
+            .map_err(|err| UziErr::IoErr(err.to_string()))?
+    }
+}
+
+impl Debug for BlockingTransport {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("BlockingTransport")
+            .finish_non_exhaustive()
+    }
+}
+
+impl Transport for BlockingTransport {
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        let line = line.to_string();
+        Box::pin(self.with_sink(move |sink| sink.send_line(&line)))
+    }
+
+    // The lines are read ahead by the thread of the source, so this is cancel
+    // safe.
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        Box::pin(async move { self.lines.recv().await.unwrap_or(Ok(None)) })
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        Box::pin(self.with_sink(|sink| sink.kill()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Engine;
+    use crate::guicmd::GuiCmd;
+    use std::time::Duration;
+    use tokio::io;
+
+    #[tokio::test]
+    async fn stream_transport_engine() {
+        let (client, server) = io::duplex(1024);
+        let (client_read, client_write) = io::split(client);
+        let (server_read, mut server_write) = io::split(server);
+
+        // A minimal engine on the other end of the pipe.
+        tokio::spawn(async move {
+            let mut lines = LineReader::new(server_read);
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply = match line.as_str() {
+                    "uci" => "id name Piped\nuciok\n",
+                    "isready" => "readyok\n",
+                    _ => continue,
+                };
+                server_write.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        let mut eng = Engine::new(StreamTransport::new(client_read, client_write));
+        let timeout = Duration::from_secs(5);
+        assert_eq!(eng.uci(timeout).await, Ok(()));
+        assert_eq!(eng.name(), Some("Piped"));
+        assert_eq!(eng.sync(timeout).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn blocking_transport_engine() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // A minimal engine with blocking I/O, which ends its lines with CRLF.
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            while let Ok(Some(line)) = reader.recv_line() {
+                let reply = match line.as_str() {
+                    "uci" => "id name Blocking\r\nuciok\r\n",
+                    "isready" => "readyok\r\n",
+                    _ => break,
+                };
+                writer.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+
+        let stream = std::net::TcpStream::connect(addr).unwrap();
+        let reader = std::io::BufReader::new(stream.try_clone().unwrap());
+        let mut eng = Engine::new(BlockingTransport::new(reader, stream));
+        let timeout = Duration::from_secs(5);
+        assert_eq!(eng.uci(timeout).await, Ok(()));
+        assert_eq!(eng.name(), Some("Blocking"));
+        assert_eq!(eng.sync(timeout).await, Ok(()));
+        // The engine closes the connection when it gets a command it doesn't
+        // know.
+        eng.send(&GuiCmd::Stop).await.unwrap();
+        assert!(matches!(
+            eng.sync(timeout).await.map_err(UziErr::into_root),
+            Err(UziErr::Crashed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stream_transport_closed() {
+        let (client, server) = io::duplex(64);
+        drop(server);
+        let (read, write) = io::split(client);
+        let mut transport = StreamTransport::new(read, write);
+        assert_eq!(transport.recv_line().await, Ok(None));
+        assert_eq!(transport.crash_report().await, CrashReport::default());
+    }
+}
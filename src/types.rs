@@ -1,6 +1,8 @@
 // This module contains types representing the option types, e.g. spin, check,
 // etc.
 
+use crate::conv::{to_bool, to_number};
+use crate::err::UziErr;
 use std::fmt::{self, Display, Formatter};
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -8,7 +10,7 @@ pub struct CheckType(pub bool);
 
 impl Display for CheckType {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
-        write!(formatter, "type bool default {}", self.0)
+        write!(formatter, "type check default {}", self.0)
     }
 }
 
@@ -57,6 +59,12 @@ impl Display for ComboType {
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct ButtonType;
 
+impl Display for ButtonType {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("type button")
+    }
+}
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct StrType(pub String);
 
@@ -65,3 +73,155 @@ impl Display for StrType {
         write!(formatter, "type string default {}", self.0)
     }
 }
+
+// Represents the type of an option declared by the engine, together with its
+// default value and limits. This is used for options that are not part of the
+// UCI standard, e.g. "Threads" or "Skill Level", where the type is only known
+// once the engine declares it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum OptKind {
+    Check(CheckType),
+    Spin(SpinType<i64>),
+    Combo(ComboType),
+    Button(ButtonType),
+    Str(StrType),
+}
+
+impl Display for OptKind {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            OptKind::Check(t) => t.fmt(formatter),
+            OptKind::Spin(t) => t.fmt(formatter),
+            OptKind::Combo(t) => t.fmt(formatter),
+            OptKind::Button(t) => t.fmt(formatter),
+            OptKind::Str(t) => t.fmt(formatter),
+        }
+    }
+}
+
+// Parses the type part of an option declaration, i.e. everything from the
+// "type" keyword onwards, e.g. "type spin default 1 min 1 max 128".
+impl TryFrom<&[&str]> for OptKind {
+    type Error = UziErr;
+
+    fn try_from(words: &[&str]) -> Result<Self, Self::Error> {
+        if words.len() < 2 || words[0] != "type" {
+            return Err(UziErr::BadOptDecl);
+        }
+
+        // The value of each field can be made of multiple words, e.g. a string
+        // default with spaces, so we collect everything up to the next keyword.
+        let mut default: Option<String> = None;
+        let mut min: Option<String> = None;
+        let mut max: Option<String> = None;
+        let mut vars: Vec<String> = Vec::new();
+        let mut field: Option<&mut String> = None;
+
+        for word in &words[2..] {
+            match *word {
+                "default" => field = Some(default.insert(String::new())),
+                "min" => field = Some(min.insert(String::new())),
+                "max" => field = Some(max.insert(String::new())),
+                "var" => {
+                    vars.push(String::new());
+                    field = vars.last_mut();
+                }
+                _ => match field {
+                    Some(ref mut buf) => {
+                        if !buf.is_empty() {
+                            buf.push(' ');
+                        }
+                        buf.push_str(word);
+                    }
+                    None => return Err(UziErr::BadOptDecl),
+                },
+            }
+        }
+
+        match words[1] {
+            "check" => {
+                let default = default.ok_or(UziErr::BadOptDecl)?;
+                Ok(OptKind::Check(CheckType(to_bool(&default)?)))
+            }
+            "spin" => {
+                let (Some(default), Some(min), Some(max)) = (default, min, max) else {
+                    return Err(UziErr::BadOptDecl);
+                };
+                Ok(OptKind::Spin(SpinType {
+                    default: to_number::<i64>(&default)?,
+                    min: to_number::<i64>(&min)?,
+                    max: to_number::<i64>(&max)?,
+                }))
+            }
+            "combo" => Ok(OptKind::Combo(ComboType {
+                default: default.unwrap_or_default(),
+                var: vars,
+            })),
+            "button" => Ok(OptKind::Button(ButtonType)),
+            "string" => Ok(OptKind::Str(StrType(default.unwrap_or_default()))),
+            _ => Err(UziErr::BadOptDecl),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opt_kind_try_from_check() {
+        let words = ["type", "check", "default", "false"];
+        assert_eq!(
+            OptKind::try_from(&words[..]),
+            Ok(OptKind::Check(CheckType(false)))
+        );
+    }
+
+    #[test]
+    fn opt_kind_try_from_spin() {
+        let words = ["type", "spin", "default", "1", "min", "-5", "max", "1024"];
+        assert_eq!(
+            OptKind::try_from(&words[..]),
+            Ok(OptKind::Spin(SpinType {
+                default: 1,
+                min: -5,
+                max: 1024
+            }))
+        );
+        let words = ["type", "spin", "default", "1", "min", "1"];
+        assert_eq!(OptKind::try_from(&words[..]), Err(UziErr::BadOptDecl));
+    }
+
+    #[test]
+    fn opt_kind_try_from_combo() {
+        let words = [
+            "type", "combo", "default", "Normal", "var", "Solid", "var", "Normal", "var", "Risky",
+            "Play",
+        ];
+        let kind = OptKind::Combo(ComboType {
+            default: "Normal".into(),
+            var: vec!["Solid".into(), "Normal".into(), "Risky Play".into()],
+        });
+        assert_eq!(OptKind::try_from(&words[..]), Ok(kind.clone()));
+        assert_eq!(
+            kind.to_string().as_str(),
+            "type combo default Normal var Solid var Normal var Risky Play"
+        );
+    }
+
+    #[test]
+    fn opt_kind_try_from_button_and_string() {
+        let words = ["type", "button"];
+        assert_eq!(
+            OptKind::try_from(&words[..]),
+            Ok(OptKind::Button(ButtonType))
+        );
+        let words = ["type", "string", "default", "<empty>"];
+        assert_eq!(
+            OptKind::try_from(&words[..]),
+            Ok(OptKind::Str(StrType("<empty>".into())))
+        );
+        let words = ["type", "matrix"];
+        assert_eq!(OptKind::try_from(&words[..]), Err(UziErr::BadOptDecl));
+    }
+}
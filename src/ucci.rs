@@ -0,0 +1,868 @@
+// This module contains the commands of UCCI (Universal Chinese Chess
+// Protocol), the protocol of xiangqi engines, which is modeled on UCI with a
+// few commands of its own, e.g. banmoves, probe and go time ... opptime. The
+// commands are lines like those of UCI, so the transports work the same:
+//
+// let mut transport = EngineProcess::spawn("eleeye")?;
+// transport.send_line(&UcciGuiCmd::Ucci.to_string()).await?;
+// let cmd = UcciEngCmd::from_str(&transport.recv_line().await?.unwrap())?;
+//
+// Times are in milliseconds, as sent by GUIs to engines that declare the
+// option usemillisec.
+
+use crate::conv::{to_millis, to_number};
+use crate::engcmd::next_word;
+use crate::err::UziErr;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+// A square, e.g. h2, with the file from a to i and the rank from 0 to 9, red
+// at the bottom.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct UcciSq {
+    file: u8,
+    rank: u8,
+}
+
+impl UcciSq {
+    // Returns the square with the file from 0 to 8 and the rank from 0 to 9,
+    // e.g. (7, 2) for h2, or None if either is out of range.
+    pub fn new(file: u8, rank: u8) -> Option<Self> {
+        (file < 9 && rank < 10).then_some(Self { file, rank })
+    }
+
+    pub fn file(&self) -> u8 {
+        self.file
+    }
+
+    pub fn rank(&self) -> u8 {
+        self.rank
+    }
+}
+
+impl FromStr for UcciSq {
+    type Err = UziErr;
+
+    fn from_str(sq: &str) -> Result<UcciSq, Self::Err> {
+        match sq.as_bytes() {
+            [file @ b'a'..=b'i', rank @ b'0'..=b'9'] => Ok(UcciSq {
+                file: file - b'a',
+                rank: rank - b'0',
+            }),
+            _ => Err(UziErr::ParseSqErr),
+        }
+    }
+}
+
+impl Display for UcciSq {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{}{}", char::from(b'a' + self.file), self.rank)
+    }
+}
+
+// A move, e.g. h2e2.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct UcciMove {
+    from: UcciSq,
+    to: UcciSq,
+}
+
+impl UcciMove {
+    pub fn new(from: UcciSq, to: UcciSq) -> Self {
+        Self { from, to }
+    }
+
+    pub fn from(&self) -> UcciSq {
+        self.from
+    }
+
+    pub fn to(&self) -> UcciSq {
+        self.to
+    }
+}
+
+impl FromStr for UcciMove {
+    type Err = UziErr;
+
+    fn from_str(word: &str) -> Result<UcciMove, Self::Err> {
+        if word.len() != 4 || !word.is_ascii() {
+            return Err(UziErr::ParseMoveErr);
+        }
+        let to_sq = |sq: &str| UcciSq::from_str(sq).map_err(|_| UziErr::ParseMoveErr);
+        Ok(UcciMove {
+            from: to_sq(&word[..2])?,
+            to: to_sq(&word[2..])?,
+        })
+    }
+}
+
+impl Display for UcciMove {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{}{}", self.from, self.to)
+    }
+}
+
+// Represents a command from the GUI to the engine.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum UcciGuiCmd {
+    // ucci: Tells the engine to switch to UCCI mode.
+    Ucci,
+
+    // isready: Answered with readyok once the engine is ready.
+    IsReady,
+
+    // setoption <id> [<x>]: Sets an option of the engine, e.g. "setoption
+    // hashsize 64". Unlike UCI, there are no name and value keywords.
+    SetOpt { name: String, value: Option<String> },
+
+    // position [fen <fen> | startpos] moves <move1> ... <movei>: Sets up the
+    // position to search.
+    Pos(UcciPos),
+
+    // banmoves <move1> ... <movei>: Moves the engine must not play in the
+    // position, e.g. ones that repeat a perpetual check.
+    BanMoves(Vec<UcciMove>),
+
+    // go [ponder | draw] [opts]: Starts searching. With draw, the opponent
+    // offers a draw.
+    Go(UcciGo),
+
+    // ponderhit [draw]: The opponent played the move the engine ponders on,
+    // and offers a draw with draw.
+    Ponderhit { is_draw_offer: bool },
+
+    // probe <position>: Asks for what the hash table has on the position. The
+    // engine replies with pophash.
+    Probe(UcciPos),
+
+    // stop: Stops the search as soon as possible.
+    Stop,
+
+    // quit: Quit the program. The engine replies with bye.
+    Quit,
+}
+
+impl FromStr for UcciGuiCmd {
+    type Err = UziErr;
+
+    fn from_str(cmd: &str) -> Result<UcciGuiCmd, Self::Err> {
+        let words = cmd.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] => Err(UziErr::MissingCmd),
+            ["ucci"] => Ok(UcciGuiCmd::Ucci),
+            ["isready"] => Ok(UcciGuiCmd::IsReady),
+            ["setoption", name, value @ ..] => Ok(UcciGuiCmd::SetOpt {
+                name: name.to_string(),
+                value: (!value.is_empty()).then(|| value.join(" ")),
+            }),
+            ["setoption"] => Err(UziErr::SetOptErr),
+            ["position", ..] => Ok(UcciGuiCmd::Pos(UcciPos::try_from(&words[1..])?)),
+            ["banmoves", moves @ ..] => Ok(UcciGuiCmd::BanMoves(to_moves(moves)?)),
+            ["go", ..] => Ok(UcciGuiCmd::Go(UcciGo::try_from(&words[1..])?)),
+            ["ponderhit"] => Ok(UcciGuiCmd::Ponderhit {
+                is_draw_offer: false,
+            }),
+            ["ponderhit", "draw"] => Ok(UcciGuiCmd::Ponderhit {
+                is_draw_offer: true,
+            }),
+            ["probe", ..] => Ok(UcciGuiCmd::Probe(UcciPos::try_from(&words[1..])?)),
+            ["stop"] => Ok(UcciGuiCmd::Stop),
+            ["quit"] => Ok(UcciGuiCmd::Quit),
+            _ => Err(UziErr::What),
+        }
+    }
+}
+
+impl Display for UcciGuiCmd {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            UcciGuiCmd::Ucci => formatter.write_str("ucci"),
+            UcciGuiCmd::IsReady => formatter.write_str("isready"),
+            UcciGuiCmd::SetOpt { name, value: None } => write!(formatter, "setoption {}", name),
+            UcciGuiCmd::SetOpt {
+                name,
+                value: Some(value),
+            } => write!(formatter, "setoption {} {}", name, value),
+            UcciGuiCmd::Pos(pos) => write!(formatter, "position {}", pos),
+            UcciGuiCmd::BanMoves(moves) => {
+                formatter.write_str("banmoves")?;
+                for ucci_move in moves {
+                    write!(formatter, " {}", ucci_move)?;
+                }
+                Ok(())
+            }
+            UcciGuiCmd::Go(go) => go.fmt(formatter),
+            UcciGuiCmd::Ponderhit { is_draw_offer } => {
+                formatter.write_str("ponderhit")?;
+                if *is_draw_offer {
+                    formatter.write_str(" draw")?;
+                }
+                Ok(())
+            }
+            UcciGuiCmd::Probe(pos) => write!(formatter, "probe {}", pos),
+            UcciGuiCmd::Stop => formatter.write_str("stop"),
+            UcciGuiCmd::Quit => formatter.write_str("quit"),
+        }
+    }
+}
+
+fn to_moves(words: &[&str]) -> Result<Vec<UcciMove>, UziErr> {
+    words.iter().map(|word| UcciMove::from_str(word)).collect()
+}
+
+// A position, i.e. the start position or a FEN, e.g.
+// "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1", and
+// the moves played from it. It is displayed without the position or probe in
+// front, since both commands take it.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct UcciPos {
+    // None for the start position.
+    fen: Option<String>,
+    moves: Vec<UcciMove>,
+}
+
+impl UcciPos {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_fen(fen: &str) -> Self {
+        Self {
+            fen: Some(fen.to_string()),
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn add_move(&mut self, ucci_move: UcciMove) -> &mut Self {
+        self.moves.push(ucci_move);
+        self
+    }
+
+    // Returns the FEN of the initial position, or None for the start
+    // position.
+    pub fn fen(&self) -> Option<&str> {
+        self.fen.as_deref()
+    }
+
+    pub fn moves(&self) -> &[UcciMove] {
+        &self.moves
+    }
+}
+
+impl Display for UcciPos {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self.fen {
+            Some(ref fen) => write!(formatter, "fen {}", fen)?,
+            None => formatter.write_str("startpos")?,
+        }
+        if !self.moves.is_empty() {
+            formatter.write_str(" moves")?;
+            for ucci_move in &self.moves {
+                write!(formatter, " {}", ucci_move)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Parses the words after position or probe.
+impl TryFrom<&[&str]> for UcciPos {
+    type Error = UziErr;
+
+    fn try_from(words: &[&str]) -> Result<UcciPos, Self::Error> {
+        let moves_index = words.iter().position(|word| *word == "moves");
+        let (setup, moves) = words.split_at(moves_index.unwrap_or(words.len()));
+        let fen = match setup {
+            ["startpos"] => None,
+            ["fen", fen @ ..] if !fen.is_empty() => Some(fen.join(" ")),
+            _ => return Err(UziErr::Position),
+        };
+        let moves = to_moves(moves.get(1..).unwrap_or_default())?;
+        Ok(UcciPos { fen, moves })
+    }
+}
+
+// The limits of a search. UCCI allows one kind of limit per go, i.e. a depth,
+// a number of nodes, or the clocks.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct UcciGo {
+    is_ponder: bool,
+    // draw: The opponent offers a draw.
+    is_draw_offer: bool,
+    limit: Option<UcciLimit>,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum UcciLimit {
+    // depth <x> | depth infinite: Search x plies, or None until stop.
+    Depth(Option<u16>),
+    Nodes(u64),
+    // time <x> [movestogo <x> | increment <x>] [opptime <x> [oppmovestogo <x>
+    // | oppincrement <x>]]: The clocks of the engine and of its opponent.
+    Time {
+        clock: UcciClock,
+        opp_clock: Option<UcciClock>,
+    },
+}
+
+// A clock, with the moves to the next time control, or the increment.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct UcciClock {
+    pub time: Duration,
+    pub moves_to_go: Option<u16>,
+    pub increment: Option<Duration>,
+}
+
+impl UcciGo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_ponder(&mut self) -> &mut Self {
+        self.is_ponder = true;
+        self
+    }
+
+    pub fn set_draw_offer(&mut self) -> &mut Self {
+        self.is_draw_offer = true;
+        self
+    }
+
+    pub fn set_limit(&mut self, limit: UcciLimit) -> &mut Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn limit(&self) -> Option<&UcciLimit> {
+        self.limit.as_ref()
+    }
+}
+
+impl Display for UcciGo {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("go")?;
+        if self.is_ponder {
+            formatter.write_str(" ponder")?;
+        }
+        if self.is_draw_offer {
+            formatter.write_str(" draw")?;
+        }
+        match self.limit {
+            Some(UcciLimit::Depth(Some(depth))) => write!(formatter, " depth {}", depth),
+            Some(UcciLimit::Depth(None)) => formatter.write_str(" depth infinite"),
+            Some(UcciLimit::Nodes(nodes)) => write!(formatter, " nodes {}", nodes),
+            Some(UcciLimit::Time { clock, opp_clock }) => {
+                write_clock(formatter, "", &clock)?;
+                match opp_clock {
+                    Some(ref opp_clock) => write_clock(formatter, "opp", opp_clock),
+                    None => Ok(()),
+                }
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_clock(formatter: &mut Formatter<'_>, prefix: &str, clock: &UcciClock) -> fmt::Result {
+    write!(formatter, " {}time {}", prefix, clock.time.as_millis())?;
+    if let Some(moves_to_go) = clock.moves_to_go {
+        write!(formatter, " {}movestogo {}", prefix, moves_to_go)?;
+    }
+    if let Some(increment) = clock.increment {
+        write!(formatter, " {}increment {}", prefix, increment.as_millis())?;
+    }
+    Ok(())
+}
+
+// Parses the words after go.
+impl TryFrom<&[&str]> for UcciGo {
+    type Error = UziErr;
+
+    fn try_from(words: &[&str]) -> Result<UcciGo, Self::Error> {
+        let mut go = UcciGo::new();
+        let mut i = 0;
+        while let Some(word) = words.get(i) {
+            i += 1;
+            let mut value = || next_word(words, &mut i).map_err(|_| UziErr::GoErr);
+            match *word {
+                "ponder" => go.is_ponder = true,
+                "draw" => go.is_draw_offer = true,
+                "depth" => {
+                    go.limit = Some(UcciLimit::Depth(match value()? {
+                        "infinite" => None,
+                        depth => Some(to_number(depth)?),
+                    }))
+                }
+                "nodes" => go.limit = Some(UcciLimit::Nodes(to_number(value()?)?)),
+                "time" | "opptime" => {
+                    let clock = UcciClock {
+                        time: to_millis(value()?, word)?,
+                        moves_to_go: None,
+                        increment: None,
+                    };
+                    match (*word, &mut go.limit) {
+                        ("time", _) => {
+                            go.limit = Some(UcciLimit::Time {
+                                clock,
+                                opp_clock: None,
+                            })
+                        }
+                        (_, Some(UcciLimit::Time { opp_clock, .. })) => *opp_clock = Some(clock),
+                        _ => return Err(UziErr::GoErr),
+                    }
+                }
+                "movestogo" | "increment" | "oppmovestogo" | "oppincrement" => {
+                    let value = value()?;
+                    let clock = match (word.starts_with("opp"), &mut go.limit) {
+                        (false, Some(UcciLimit::Time { clock, .. })) => clock,
+                        (
+                            true,
+                            Some(UcciLimit::Time {
+                                opp_clock: Some(clock),
+                                ..
+                            }),
+                        ) => clock,
+                        _ => return Err(UziErr::GoErr),
+                    };
+                    match word.trim_start_matches("opp") {
+                        "movestogo" => clock.moves_to_go = Some(to_number(value)?),
+                        _ => clock.increment = Some(to_millis(value, word)?),
+                    }
+                }
+                _ => return Err(UziErr::GoErr),
+            }
+        }
+        Ok(go)
+    }
+}
+
+// Represents a command from the engine to the GUI.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum UcciEngCmd {
+    // id name <x>: The name and version of the engine.
+    IdName(String),
+
+    // id author <x>: The author of the engine.
+    IdAuthor(String),
+
+    // id copyright <x>: The copyright of the engine.
+    IdCopyright(String),
+
+    // id user <x>: The user the engine is licensed to.
+    IdUser(String),
+
+    // option <id> type <t> ...: An option of the engine, kept as sent after
+    // option, since the types and defaults differ from UCI, e.g. option
+    // usemillisec type check default true.
+    Opt(String),
+
+    // ucciok: The engine has sent its id and options.
+    UcciOk,
+
+    // readyok: The reply to isready.
+    ReadyOk,
+
+    // bestmove <move> [ponder <move>] [draw | resign]: The result of a search,
+    // with an offer or acceptance of a draw, or a resignation.
+    BestMove {
+        best: UcciMove,
+        ponder: Option<UcciMove>,
+        claim: Option<Claim>,
+    },
+
+    // nobestmove: The engine has no legal move, or was told to stop before it
+    // found one.
+    NoBestMove,
+
+    // pophash [bestmove <move>] [lowerbound <x> depth <d>] [upperbound <x>
+    // depth <d>]: The reply to probe.
+    PopHash(PopHash),
+
+    // info [opts]: Information about the search.
+    Info(UcciInfo),
+
+    // bye: The reply to quit.
+    Bye,
+}
+
+// What the engine says with its bestmove, see UcciEngCmd::BestMove.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Claim {
+    Draw,
+    Resign,
+}
+
+impl Claim {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Claim::Draw => "draw",
+            Claim::Resign => "resign",
+        }
+    }
+}
+
+// What the hash table has on a probed position, with the bounds of its score
+// and the depth they were searched to.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct PopHash {
+    pub best: Option<UcciMove>,
+    pub lower_bound: Option<(i32, u16)>,
+    pub upper_bound: Option<(i32, u16)>,
+}
+
+impl FromStr for UcciEngCmd {
+    type Err = UziErr;
+
+    fn from_str(cmd: &str) -> Result<UcciEngCmd, Self::Err> {
+        let words = cmd.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] => Err(UziErr::MissingCmd),
+            ["id", kind, value @ ..] if !value.is_empty() => {
+                let value = value.join(" ");
+                match *kind {
+                    "name" => Ok(UcciEngCmd::IdName(value)),
+                    "author" => Ok(UcciEngCmd::IdAuthor(value)),
+                    "copyright" => Ok(UcciEngCmd::IdCopyright(value)),
+                    "user" => Ok(UcciEngCmd::IdUser(value)),
+                    _ => Err(UziErr::IdErr),
+                }
+            }
+            ["id", ..] => Err(UziErr::IdErr),
+            ["option", _, ..] => Ok(UcciEngCmd::Opt(words[1..].join(" "))),
+            ["ucciok"] => Ok(UcciEngCmd::UcciOk),
+            ["readyok"] => Ok(UcciEngCmd::ReadyOk),
+            ["bestmove", best, rest @ ..] => {
+                let best = UcciMove::from_str(best)?;
+                let (ponder, rest) = match rest {
+                    ["ponder", ponder, rest @ ..] => (Some(UcciMove::from_str(ponder)?), rest),
+                    rest => (None, rest),
+                };
+                let claim = match rest {
+                    [] => None,
+                    ["draw"] => Some(Claim::Draw),
+                    ["resign"] => Some(Claim::Resign),
+                    _ => return Err(UziErr::BestMoveErr),
+                };
+                Ok(UcciEngCmd::BestMove {
+                    best,
+                    ponder,
+                    claim,
+                })
+            }
+            ["bestmove", ..] => Err(UziErr::BestMoveErr),
+            ["nobestmove"] => Ok(UcciEngCmd::NoBestMove),
+            ["pophash", ..] => Ok(UcciEngCmd::PopHash(PopHash::try_from(&words[1..])?)),
+            ["info", ..] => Ok(UcciEngCmd::Info(UcciInfo::try_from(words.as_slice())?)),
+            ["bye"] => Ok(UcciEngCmd::Bye),
+            _ => Err(UziErr::What),
+        }
+    }
+}
+
+impl Display for UcciEngCmd {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            UcciEngCmd::IdName(name) => write!(formatter, "id name {}", name),
+            UcciEngCmd::IdAuthor(author) => write!(formatter, "id author {}", author),
+            UcciEngCmd::IdCopyright(copyright) => write!(formatter, "id copyright {}", copyright),
+            UcciEngCmd::IdUser(user) => write!(formatter, "id user {}", user),
+            UcciEngCmd::Opt(opt) => write!(formatter, "option {}", opt),
+            UcciEngCmd::UcciOk => formatter.write_str("ucciok"),
+            UcciEngCmd::ReadyOk => formatter.write_str("readyok"),
+            UcciEngCmd::BestMove {
+                best,
+                ponder,
+                claim,
+            } => {
+                write!(formatter, "bestmove {}", best)?;
+                if let Some(ponder) = ponder {
+                    write!(formatter, " ponder {}", ponder)?;
+                }
+                if let Some(claim) = claim {
+                    write!(formatter, " {}", claim.as_str())?;
+                }
+                Ok(())
+            }
+            UcciEngCmd::NoBestMove => formatter.write_str("nobestmove"),
+            UcciEngCmd::PopHash(pophash) => pophash.fmt(formatter),
+            UcciEngCmd::Info(info) => info.fmt(formatter),
+            UcciEngCmd::Bye => formatter.write_str("bye"),
+        }
+    }
+}
+
+impl Display for PopHash {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("pophash")?;
+        if let Some(best) = self.best {
+            write!(formatter, " bestmove {}", best)?;
+        }
+        if let Some((score, depth)) = self.lower_bound {
+            write!(formatter, " lowerbound {} depth {}", score, depth)?;
+        }
+        if let Some((score, depth)) = self.upper_bound {
+            write!(formatter, " upperbound {} depth {}", score, depth)?;
+        }
+        Ok(())
+    }
+}
+
+// Parses the words after pophash.
+impl TryFrom<&[&str]> for PopHash {
+    type Error = UziErr;
+
+    fn try_from(mut words: &[&str]) -> Result<PopHash, Self::Error> {
+        let mut pophash = PopHash::default();
+        loop {
+            match words {
+                [] => return Ok(pophash),
+                ["bestmove", best, rest @ ..] => {
+                    pophash.best = Some(UcciMove::from_str(best)?);
+                    words = rest;
+                }
+                [bound @ ("lowerbound" | "upperbound"), score, "depth", depth, rest @ ..] => {
+                    let value = Some((to_number(score)?, to_number(depth)?));
+                    match *bound {
+                        "lowerbound" => pophash.lower_bound = value,
+                        _ => pophash.upper_bound = value,
+                    }
+                    words = rest;
+                }
+                _ => return Err(UziErr::What),
+            }
+        }
+    }
+}
+
+// The fields of an info that xiangqi GUIs show. Other fields are skipped.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct UcciInfo {
+    depth: Option<u16>,
+    // score <x>: The score in centipawns, as UCCI has no cp or mate keywords.
+    score: Option<i32>,
+    time: Option<Duration>,
+    nodes: Option<u64>,
+    curr_move: Option<UcciMove>,
+    pv: Option<Vec<UcciMove>>,
+    // message <str>: The rest of the line.
+    message: Option<String>,
+}
+
+impl UcciInfo {
+    pub fn depth(&self) -> Option<u16> {
+        self.depth
+    }
+
+    pub fn score(&self) -> Option<i32> {
+        self.score
+    }
+
+    pub fn time(&self) -> Option<Duration> {
+        self.time
+    }
+
+    pub fn nodes(&self) -> Option<u64> {
+        self.nodes
+    }
+
+    pub fn curr_move(&self) -> Option<UcciMove> {
+        self.curr_move
+    }
+
+    pub fn pv(&self) -> Option<&[UcciMove]> {
+        self.pv.as_deref()
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+}
+
+impl TryFrom<&[&str]> for UcciInfo {
+    type Error = UziErr;
+
+    fn try_from(cmd: &[&str]) -> Result<UcciInfo, Self::Error> {
+        if cmd.first() != Some(&"info") {
+            return Err(UziErr::InfoErr);
+        }
+        let mut info = UcciInfo::default();
+        let mut i = 1;
+        while i < cmd.len() {
+            let word = cmd[i];
+            i += 1;
+            match word {
+                "depth" => info.depth = Some(to_number(next_word(cmd, &mut i)?)?),
+                "score" => info.score = Some(to_number(next_word(cmd, &mut i)?)?),
+                "time" => info.time = Some(to_millis(next_word(cmd, &mut i)?, "time")?),
+                "nodes" => info.nodes = Some(to_number(next_word(cmd, &mut i)?)?),
+                "currmove" => info.curr_move = Some(UcciMove::from_str(next_word(cmd, &mut i)?)?),
+                "pv" => {
+                    let mut pv = Vec::new();
+                    while let Some(Ok(ucci_move)) = cmd.get(i).map(|word| UcciMove::from_str(word))
+                    {
+                        pv.push(ucci_move);
+                        i += 1;
+                    }
+                    info.pv = Some(pv);
+                }
+                "message" => {
+                    info.message = Some(cmd[i..].join(" "));
+                    i = cmd.len();
+                }
+                // Skip anything we don't understand, as for UCI.
+                _ => continue,
+            }
+        }
+        Ok(info)
+    }
+}
+
+impl Display for UcciInfo {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("info")?;
+        if let Some(depth) = self.depth {
+            write!(formatter, " depth {}", depth)?;
+        }
+        if let Some(score) = self.score {
+            write!(formatter, " score {}", score)?;
+        }
+        if let Some(time) = self.time {
+            write!(formatter, " time {}", time.as_millis())?;
+        }
+        if let Some(nodes) = self.nodes {
+            write!(formatter, " nodes {}", nodes)?;
+        }
+        if let Some(curr_move) = self.curr_move {
+            write!(formatter, " currmove {}", curr_move)?;
+        }
+        if let Some(ref pv) = self.pv {
+            formatter.write_str(" pv")?;
+            for ucci_move in pv {
+                write!(formatter, " {}", ucci_move)?;
+            }
+        }
+        if let Some(ref message) = self.message {
+            write!(formatter, " message {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::fake_engine;
+
+    #[test]
+    fn ucci_moves() {
+        assert_eq!(
+            UcciMove::from_str("h2e2"),
+            Ok(UcciMove::new(
+                UcciSq::new(7, 2).unwrap(),
+                UcciSq::new(4, 2).unwrap()
+            ))
+        );
+        assert_eq!(UcciMove::from_str("a0a9").unwrap().to_string(), "a0a9");
+        for word in ["j2e2", "h2e", "h2e2q", "7g7f", "h2é2"] {
+            assert!(UcciMove::from_str(word).is_err(), "{}", word);
+        }
+        assert_eq!(UcciSq::new(9, 0), None);
+    }
+
+    #[test]
+    fn ucci_cmds() {
+        for cmd in [
+            "ucci",
+            "setoption usemillisec true",
+            "setoption newgame",
+            "setoption bookfiles book one.dat",
+            "position startpos moves h2e2 h9g7",
+            "position fen rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1",
+            "banmoves b2b9 b9b2",
+            "go depth 12",
+            "go ponder draw depth infinite",
+            "go nodes 500000",
+            "go time 60000 increment 1000 opptime 55000 oppincrement 1000",
+            "go time 30000 movestogo 20 opptime 25000 oppmovestogo 20",
+            "ponderhit draw",
+            "probe startpos moves h2e2",
+        ] {
+            assert_eq!(UcciGuiCmd::from_str(cmd).unwrap().to_string(), cmd);
+        }
+        for cmd in [
+            "go movestogo 20",
+            "go opptime 1000",
+            "go time 1000 oppincrement 5",
+            "probe",
+        ] {
+            assert!(UcciGuiCmd::from_str(cmd).is_err(), "{}", cmd);
+        }
+        for cmd in [
+            "id name ElephantEye 3.31",
+            "id copyright 2004-2016 www.xqbase.com",
+            "option usemillisec type check default true",
+            "bestmove h2e2 ponder h9g7 draw",
+            "bestmove b0c2 resign",
+            "nobestmove",
+            "pophash bestmove h2e2 lowerbound 35 depth 9",
+            "pophash",
+            "info depth 9 score 35 time 1200 nodes 456789 pv h2e2 h9g7 h0g2",
+            "info currmove b0c2 message book move",
+            "bye",
+        ] {
+            assert_eq!(UcciEngCmd::from_str(cmd).unwrap().to_string(), cmd);
+        }
+        assert_eq!(
+            UcciEngCmd::from_str("pophash upperbound -20 depth 4"),
+            Ok(UcciEngCmd::PopHash(PopHash {
+                best: None,
+                lower_bound: None,
+                upper_bound: Some((-20, 4)),
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn ucci_over_transport() {
+        let mut transport = fake_engine(
+            r#"
+            while read -r line; do
+                case "$line" in
+                    ucci) echo "id name Fake"; echo "ucciok";;
+                    isready) echo "readyok";;
+                    go*) echo "info depth 1 score 20 pv h2e2"; echo "bestmove h2e2 draw";;
+                    quit) echo "bye"; exit;;
+                esac
+            done
+            "#,
+        );
+        let mut go = UcciGo::new();
+        go.set_draw_offer().set_limit(UcciLimit::Time {
+            clock: UcciClock {
+                time: Duration::from_secs(60),
+                moves_to_go: None,
+                increment: Some(Duration::from_secs(1)),
+            },
+            opp_clock: None,
+        });
+        for cmd in [
+            UcciGuiCmd::Ucci,
+            UcciGuiCmd::IsReady,
+            UcciGuiCmd::Pos(UcciPos::new()),
+            UcciGuiCmd::BanMoves(vec![UcciMove::from_str("b2b9").unwrap()]),
+            UcciGuiCmd::Go(go),
+            UcciGuiCmd::Quit,
+        ] {
+            transport.send_line(&cmd.to_string()).await.unwrap();
+        }
+        let mut replies = Vec::new();
+        while let Some(line) = transport.recv_line().await.unwrap() {
+            replies.push(UcciEngCmd::from_str(&line).unwrap());
+        }
+        assert_eq!(replies.len(), 6);
+        assert_eq!(replies[0], UcciEngCmd::IdName("Fake".to_string()));
+        assert_eq!(replies[4].to_string(), "bestmove h2e2 draw");
+        assert_eq!(replies[5], UcciEngCmd::Bye);
+    }
+}
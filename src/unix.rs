@@ -0,0 +1,213 @@
+// This module contains the transport for engines on the same machine over Unix
+// domain sockets, e.g. an analysis daemon that keeps its engines running, or an
+// engine in a sandbox that can't share pipes with the client. As with TCP, see
+// TcpTransport, the engine side either listens with a UnixEngineServer and the
+// client connects, or the client listens and the engine side connects.
+
+use crate::engproc::Spawner;
+use crate::err::UziErr;
+use crate::listen::{self, Listener, DEFAULT_MAX_SESSIONS};
+use crate::transport::{BoxFuture, StreamTransport, Transport};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net;
+use std::path::{Path, PathBuf};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+
+// A connection to an engine over a Unix socket. Killing the engine closes the
+// connection, which ends the session on the other side.
+#[derive(Debug)]
+pub struct UnixTransport {
+    stream: StreamTransport<OwnedReadHalf, OwnedWriteHalf>,
+}
+
+impl UnixTransport {
+    // Connects to an engine served at the path of the socket.
+    pub async fn connect<P: AsRef<Path>>(path: P) -> Result<Self, UziErr> {
+        Ok(Self::from_stream(UnixStream::connect(path).await?))
+    }
+
+    // Waits for an engine to connect to the listener.
+    pub async fn accept(listener: &UnixListener) -> Result<Self, UziErr> {
+        let (stream, _) = listener.accept().await?;
+        Ok(Self::from_stream(stream))
+    }
+
+    pub fn from_stream(stream: UnixStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        Self {
+            stream: StreamTransport::new(reader, writer),
+        }
+    }
+
+    // Returns a spawner that connects to the socket, for an Engine that
+    // reconnects when it is restarted, or for a pool of engines on a server.
+    // The spawner has to be used in a tokio runtime.
+    pub fn spawner<P: AsRef<Path>>(path: P) -> Spawner {
+        let path = path.as_ref().to_path_buf();
+        Spawner::new(move || {
+            let stream = net::UnixStream::connect(&path)?;
+            stream.set_nonblocking(true)?;
+            Ok(Self::from_stream(UnixStream::from_std(stream)?))
+        })
+    }
+}
+
+impl Transport for UnixTransport {
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        self.stream.send_line(line)
+    }
+
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        self.stream.recv_line()
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        self.stream.kill()
+    }
+}
+
+// Serves an engine to clients on the same machine. Every connection gets an
+// engine of its own from the spawner, see TcpEngineServer. The socket file is
+// removed when the server is dropped.
+#[derive(Debug)]
+pub struct UnixEngineServer {
+    listener: UnixListener,
+    spawner: Spawner,
+    max_sessions: usize,
+    path: PathBuf,
+}
+
+impl UnixEngineServer {
+    // Listens at the path. A socket left behind by a server that didn't shut
+    // down is replaced, but any other file at the path is an error.
+    pub fn bind<P: AsRef<Path>>(path: P, spawner: Spawner) -> Result<Self, UziErr> {
+        let path = path.as_ref().to_path_buf();
+        if is_stale_socket(&path) {
+            fs::remove_file(&path)?;
+        }
+        Ok(Self {
+            listener: UnixListener::bind(&path)?,
+            spawner,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+            path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Sets how many sessions run at once, see TcpEngineServer.
+    pub fn set_max_sessions(&mut self, max_sessions: usize) -> &mut Self {
+        self.max_sessions = max_sessions;
+        self
+    }
+
+    // Accepts connections until the task is dropped, see TcpEngineServer.
+    pub async fn serve(mut self) {
+        listen::serve(
+            &mut self.listener,
+            &self.spawner,
+            self.max_sessions,
+            |proxy, stream: UnixStream| async move {
+                let (reader, writer) = stream.into_split();
+                let _ = proxy.run_with(reader, writer).await;
+            },
+        )
+        .await
+    }
+}
+
+impl Listener for UnixListener {
+    type Conn = UnixStream;
+
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<UnixStream>> {
+        Box::pin(async move {
+            let (stream, _) = UnixListener::accept(self).await?;
+            Ok(stream)
+        })
+    }
+}
+
+impl Drop for UnixEngineServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Whether the path is a socket that nothing listens on.
+fn is_stale_socket(path: &Path) -> bool {
+    let is_socket = fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket());
+    is_socket
+        && matches!(
+            net::UnixStream::connect(path),
+            Err(err) if err.kind() == ErrorKind::ConnectionRefused
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Engine;
+    use crate::guicmd::{Go, Pos};
+    use crate::pm::Pm;
+    use crate::proxy::UciProxy;
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("uzi-{}-{}.sock", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn unix_connect_to_server() {
+        let path = socket_path("server");
+        // A socket left behind by a previous run.
+        drop(net::UnixListener::bind(&path).unwrap());
+        let spawner = Spawner::new(|| Ok(fake_engine(FAKE_ENGINE)));
+        let server = UnixEngineServer::bind(&path, spawner).unwrap();
+        assert_eq!(server.path(), path);
+        let serve = tokio::spawn(server.serve());
+
+        let mut eng = Engine::new(UnixTransport::connect(&path).await.unwrap());
+        eng.uci(TIMEOUT).await.unwrap();
+        let best = eng.best_move(&Pos::new(), &Go::new()).await;
+        assert_eq!(best, Ok(Pm::from_str("e2e4").unwrap()));
+
+        let mut eng = Engine::from_spawner(UnixTransport::spawner(&path)).unwrap();
+        eng.uci(TIMEOUT).await.unwrap();
+        eng.restart().await.unwrap();
+        eng.sync(TIMEOUT).await.unwrap();
+
+        // The socket is removed with the server.
+        serve.abort();
+        let _ = serve.await;
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn unix_engine_connects_to_client() {
+        let path = socket_path("client");
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let engine_path = path.clone();
+        tokio::spawn(async move {
+            let stream = UnixStream::connect(engine_path).await.unwrap();
+            let (reader, writer) = stream.into_split();
+            let proxy = UciProxy::new(fake_engine(FAKE_ENGINE));
+            proxy.run_with(reader, writer).await
+        });
+
+        let mut eng = Engine::new(UnixTransport::accept(&listener).await.unwrap());
+        eng.uci(TIMEOUT).await.unwrap();
+        assert_eq!(eng.name(), Some("Fake"));
+        assert_eq!(eng.shutdown(TIMEOUT).await.map(|_| ()), Ok(()));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,904 @@
+// This module contains the commands of USI (Universal Shogi Interface), the
+// protocol of shogi engines, which is UCI with shogi moves and positions and a
+// few commands of its own, e.g. usinewgame, position sfen and go byoyomi. The
+// commands are lines like those of UCI, so the transports work the same:
+//
+// let mut transport = EngineProcess::spawn("YaneuraOu")?;
+// transport.send_line(&UsiGuiCmd::Usi.to_string()).await?;
+// let cmd = UsiEngCmd::from_str(&transport.recv_line().await?.unwrap())?;
+//
+// In USI, black (sente) moves first, which is why btime comes before wtime.
+
+use crate::conv::{to_millis, to_number};
+use crate::engcmd::{next_word, ScoreBound};
+use crate::err::UziErr;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+// A square, e.g. 7g, with the file from 1 to 9 and the rank from a to i.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct UsiSq {
+    file: u8,
+    rank: u8,
+}
+
+impl UsiSq {
+    // Returns the square with the file and rank from 1 to 9, e.g. (7, 7) for
+    // 7g, or None if either is out of range.
+    pub fn new(file: u8, rank: u8) -> Option<Self> {
+        ((1..=9).contains(&file) && (1..=9).contains(&rank)).then_some(Self { file, rank })
+    }
+
+    pub fn file(&self) -> u8 {
+        self.file
+    }
+
+    pub fn rank(&self) -> u8 {
+        self.rank
+    }
+}
+
+impl FromStr for UsiSq {
+    type Err = UziErr;
+
+    fn from_str(sq: &str) -> Result<UsiSq, Self::Err> {
+        match sq.as_bytes() {
+            [file @ b'1'..=b'9', rank @ b'a'..=b'i'] => Ok(UsiSq {
+                file: file - b'0',
+                rank: rank - b'a' + 1,
+            }),
+            _ => Err(UziErr::ParseSqErr),
+        }
+    }
+}
+
+impl Display for UsiSq {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{}{}",
+            self.file,
+            char::from(b'a' + self.rank - 1)
+        )
+    }
+}
+
+// A piece that can be dropped from the hand.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DropPiece {
+    Pawn,
+    Lance,
+    Knight,
+    Silver,
+    Gold,
+    Bishop,
+    Rook,
+}
+
+impl DropPiece {
+    pub fn as_char(&self) -> char {
+        match self {
+            DropPiece::Pawn => 'P',
+            DropPiece::Lance => 'L',
+            DropPiece::Knight => 'N',
+            DropPiece::Silver => 'S',
+            DropPiece::Gold => 'G',
+            DropPiece::Bishop => 'B',
+            DropPiece::Rook => 'R',
+        }
+    }
+}
+
+impl TryFrom<u8> for DropPiece {
+    type Error = UziErr;
+
+    fn try_from(piece: u8) -> Result<DropPiece, Self::Error> {
+        match piece {
+            b'P' => Ok(DropPiece::Pawn),
+            b'L' => Ok(DropPiece::Lance),
+            b'N' => Ok(DropPiece::Knight),
+            b'S' => Ok(DropPiece::Silver),
+            b'G' => Ok(DropPiece::Gold),
+            b'B' => Ok(DropPiece::Bishop),
+            b'R' => Ok(DropPiece::Rook),
+            _ => Err(UziErr::ParsePieceErr(char::from(piece).to_string())),
+        }
+    }
+}
+
+// A move, e.g. 7g7f, 8h2b+ for a move that promotes, or P*5e for a drop.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UsiMove {
+    Normal {
+        from: UsiSq,
+        to: UsiSq,
+        is_promotion: bool,
+    },
+    Drop {
+        piece: DropPiece,
+        to: UsiSq,
+    },
+}
+
+impl FromStr for UsiMove {
+    type Err = UziErr;
+
+    fn from_str(word: &str) -> Result<UsiMove, Self::Err> {
+        let to_sq = |sq: &str| UsiSq::from_str(sq).map_err(|_| UziErr::ParseMoveErr);
+        match word.as_bytes() {
+            [piece, b'*', ..] if word.len() == 4 => Ok(UsiMove::Drop {
+                piece: DropPiece::try_from(*piece)?,
+                to: to_sq(&word[2..])?,
+            }),
+            [.., b'+'] if word.len() == 5 => Ok(UsiMove::Normal {
+                from: to_sq(&word[..2])?,
+                to: to_sq(&word[2..4])?,
+                is_promotion: true,
+            }),
+            _ if word.len() == 4 => Ok(UsiMove::Normal {
+                from: to_sq(&word[..2])?,
+                to: to_sq(&word[2..])?,
+                is_promotion: false,
+            }),
+            _ => Err(UziErr::ParseMoveErr),
+        }
+    }
+}
+
+impl Display for UsiMove {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            UsiMove::Normal {
+                from,
+                to,
+                is_promotion,
+            } => {
+                write!(formatter, "{}{}", from, to)?;
+                if *is_promotion {
+                    formatter.write_str("+")?;
+                }
+                Ok(())
+            }
+            UsiMove::Drop { piece, to } => write!(formatter, "{}*{}", piece.as_char(), to),
+        }
+    }
+}
+
+// Represents a command from the GUI to the engine.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum UsiGuiCmd {
+    // usi: Tells the engine to switch to USI mode.
+    Usi,
+
+    // isready: Sent once before the first game, and answered with readyok
+    // once the engine is ready, e.g. after it loaded its evaluation.
+    IsReady,
+
+    // setoption name <id> [value <x>]: Sets an option of the engine, e.g.
+    // USI_Hash.
+    SetOpt { name: String, value: Option<String> },
+
+    // usinewgame: A new game starts.
+    NewGame,
+
+    // position [sfen <sfen> | startpos] moves <move1> ... <movei>: Sets up the
+    // position to search.
+    Pos(UsiPos),
+
+    // go [opts]: Starts searching.
+    Go(UsiGo),
+
+    // stop: Stops the search as soon as possible.
+    Stop,
+
+    // ponderhit: The opponent played the move the engine ponders on.
+    Ponderhit,
+
+    // gameover <win | lose | draw>: The game ended, with the result for the
+    // engine.
+    GameOver(GameOver),
+
+    // quit: Quit the program as soon as possible.
+    Quit,
+}
+
+impl FromStr for UsiGuiCmd {
+    type Err = UziErr;
+
+    fn from_str(cmd: &str) -> Result<UsiGuiCmd, Self::Err> {
+        let words = cmd.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] => Err(UziErr::MissingCmd),
+            ["usi"] => Ok(UsiGuiCmd::Usi),
+            ["isready"] => Ok(UsiGuiCmd::IsReady),
+            ["setoption", "name", name] => Ok(UsiGuiCmd::SetOpt {
+                name: name.to_string(),
+                value: None,
+            }),
+            ["setoption", "name", name, "value", value @ ..] => Ok(UsiGuiCmd::SetOpt {
+                name: name.to_string(),
+                value: Some(value.join(" ")),
+            }),
+            ["setoption", ..] => Err(UziErr::SetOptErr),
+            ["usinewgame"] => Ok(UsiGuiCmd::NewGame),
+            ["position", ..] => Ok(UsiGuiCmd::Pos(UsiPos::try_from(&words[1..])?)),
+            ["go", ..] => Ok(UsiGuiCmd::Go(UsiGo::try_from(&words[1..])?)),
+            ["stop"] => Ok(UsiGuiCmd::Stop),
+            ["ponderhit"] => Ok(UsiGuiCmd::Ponderhit),
+            ["gameover", "win"] => Ok(UsiGuiCmd::GameOver(GameOver::Win)),
+            ["gameover", "lose"] => Ok(UsiGuiCmd::GameOver(GameOver::Lose)),
+            ["gameover", "draw"] => Ok(UsiGuiCmd::GameOver(GameOver::Draw)),
+            ["quit"] => Ok(UsiGuiCmd::Quit),
+            _ => Err(UziErr::What),
+        }
+    }
+}
+
+impl Display for UsiGuiCmd {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            UsiGuiCmd::Usi => formatter.write_str("usi"),
+            UsiGuiCmd::IsReady => formatter.write_str("isready"),
+            UsiGuiCmd::SetOpt { name, value: None } => write!(formatter, "setoption name {}", name),
+            UsiGuiCmd::SetOpt {
+                name,
+                value: Some(value),
+            } => write!(formatter, "setoption name {} value {}", name, value),
+            UsiGuiCmd::NewGame => formatter.write_str("usinewgame"),
+            UsiGuiCmd::Pos(pos) => pos.fmt(formatter),
+            UsiGuiCmd::Go(go) => go.fmt(formatter),
+            UsiGuiCmd::Stop => formatter.write_str("stop"),
+            UsiGuiCmd::Ponderhit => formatter.write_str("ponderhit"),
+            UsiGuiCmd::GameOver(result) => write!(formatter, "gameover {}", result.as_str()),
+            UsiGuiCmd::Quit => formatter.write_str("quit"),
+        }
+    }
+}
+
+// The result of a game for the engine, see UsiGuiCmd::GameOver.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GameOver {
+    Win,
+    Lose,
+    Draw,
+}
+
+impl GameOver {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GameOver::Win => "win",
+            GameOver::Lose => "lose",
+            GameOver::Draw => "draw",
+        }
+    }
+}
+
+// A position, i.e. the start position or an SFEN, e.g.
+// "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1", and the
+// moves played from it.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct UsiPos {
+    // None for the start position.
+    sfen: Option<String>,
+    moves: Vec<UsiMove>,
+}
+
+impl UsiPos {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_sfen(sfen: &str) -> Self {
+        Self {
+            sfen: Some(sfen.to_string()),
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn add_move(&mut self, usi_move: UsiMove) -> &mut Self {
+        self.moves.push(usi_move);
+        self
+    }
+
+    // Returns the SFEN of the initial position, or None for the start
+    // position.
+    pub fn sfen(&self) -> Option<&str> {
+        self.sfen.as_deref()
+    }
+
+    pub fn moves(&self) -> &[UsiMove] {
+        &self.moves
+    }
+}
+
+impl Display for UsiPos {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self.sfen {
+            Some(ref sfen) => write!(formatter, "position sfen {}", sfen)?,
+            None => formatter.write_str("position startpos")?,
+        }
+        if !self.moves.is_empty() {
+            formatter.write_str(" moves")?;
+            for usi_move in &self.moves {
+                write!(formatter, " {}", usi_move)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Parses the words after position.
+impl TryFrom<&[&str]> for UsiPos {
+    type Error = UziErr;
+
+    fn try_from(words: &[&str]) -> Result<UsiPos, Self::Error> {
+        let moves_index = words.iter().position(|word| *word == "moves");
+        let (setup, moves) = words.split_at(moves_index.unwrap_or(words.len()));
+        let sfen = match setup {
+            ["startpos"] => None,
+            ["sfen", sfen @ ..] if !sfen.is_empty() => Some(sfen.join(" ")),
+            _ => return Err(UziErr::Position),
+        };
+        let moves = moves
+            .iter()
+            .skip(1)
+            .map(|word| UsiMove::from_str(word))
+            .collect::<Result<_, _>>()?;
+        Ok(UsiPos { sfen, moves })
+    }
+}
+
+// The limits of a search, e.g. go btime 60000 wtime 60000 byoyomi 10000.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct UsiGo {
+    is_ponder: bool,
+    btime: Option<Duration>,
+    wtime: Option<Duration>,
+    binc: Option<Duration>,
+    winc: Option<Duration>,
+    // byoyomi <x>: The time per move once the main time is used up.
+    byoyomi: Option<Duration>,
+    depth: Option<u16>,
+    nodes: Option<u64>,
+    is_infinite: bool,
+    // mate <x | infinite>: Searches for a mate, within the time if any. The
+    // engine replies with checkmate rather than bestmove.
+    mate: Option<MateLimit>,
+}
+
+// The time of a go mate search.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MateLimit {
+    Time(Duration),
+    Infinite,
+}
+
+impl UsiGo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_ponder(&mut self) -> &mut Self {
+        self.is_ponder = true;
+        self
+    }
+
+    pub fn set_btime(&mut self, btime: Duration) -> &mut Self {
+        self.btime = Some(btime);
+        self
+    }
+
+    pub fn set_wtime(&mut self, wtime: Duration) -> &mut Self {
+        self.wtime = Some(wtime);
+        self
+    }
+
+    pub fn set_binc(&mut self, binc: Duration) -> &mut Self {
+        self.binc = Some(binc);
+        self
+    }
+
+    pub fn set_winc(&mut self, winc: Duration) -> &mut Self {
+        self.winc = Some(winc);
+        self
+    }
+
+    pub fn set_byoyomi(&mut self, byoyomi: Duration) -> &mut Self {
+        self.byoyomi = Some(byoyomi);
+        self
+    }
+
+    pub fn set_depth(&mut self, depth: u16) -> &mut Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    pub fn set_nodes(&mut self, nodes: u64) -> &mut Self {
+        self.nodes = Some(nodes);
+        self
+    }
+
+    pub fn set_infinite(&mut self) -> &mut Self {
+        self.is_infinite = true;
+        self
+    }
+
+    pub fn set_mate(&mut self, mate: MateLimit) -> &mut Self {
+        self.mate = Some(mate);
+        self
+    }
+
+    pub fn byoyomi(&self) -> Option<Duration> {
+        self.byoyomi
+    }
+
+    pub fn mate(&self) -> Option<MateLimit> {
+        self.mate
+    }
+}
+
+impl Display for UsiGo {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("go")?;
+        if self.is_ponder {
+            formatter.write_str(" ponder")?;
+        }
+        for (name, time) in [
+            ("btime", self.btime),
+            ("wtime", self.wtime),
+            ("binc", self.binc),
+            ("winc", self.winc),
+            ("byoyomi", self.byoyomi),
+        ] {
+            if let Some(time) = time {
+                write!(formatter, " {} {}", name, time.as_millis())?;
+            }
+        }
+        if let Some(depth) = self.depth {
+            write!(formatter, " depth {}", depth)?;
+        }
+        if let Some(nodes) = self.nodes {
+            write!(formatter, " nodes {}", nodes)?;
+        }
+        if self.is_infinite {
+            formatter.write_str(" infinite")?;
+        }
+        match self.mate {
+            Some(MateLimit::Time(time)) => write!(formatter, " mate {}", time.as_millis()),
+            Some(MateLimit::Infinite) => formatter.write_str(" mate infinite"),
+            None => Ok(()),
+        }
+    }
+}
+
+// Parses the words after go.
+impl TryFrom<&[&str]> for UsiGo {
+    type Error = UziErr;
+
+    fn try_from(words: &[&str]) -> Result<UsiGo, Self::Error> {
+        let mut go = UsiGo::new();
+        let mut words = words.iter();
+        while let Some(word) = words.next() {
+            let mut value = || words.next().copied().ok_or(UziErr::GoErr);
+            match *word {
+                "ponder" => go.is_ponder = true,
+                "btime" => go.btime = Some(to_millis(value()?, word)?),
+                "wtime" => go.wtime = Some(to_millis(value()?, word)?),
+                "binc" => go.binc = Some(to_millis(value()?, word)?),
+                "winc" => go.winc = Some(to_millis(value()?, word)?),
+                "byoyomi" => go.byoyomi = Some(to_millis(value()?, word)?),
+                "depth" => go.depth = Some(to_number(value()?)?),
+                "nodes" => go.nodes = Some(to_number(value()?)?),
+                "infinite" => go.is_infinite = true,
+                "mate" => {
+                    go.mate = Some(match value()? {
+                        "infinite" => MateLimit::Infinite,
+                        time => MateLimit::Time(to_millis(time, word)?),
+                    })
+                }
+                _ => return Err(UziErr::GoErr),
+            }
+        }
+        Ok(go)
+    }
+}
+
+// Represents a command from the engine to the GUI.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum UsiEngCmd {
+    // id name <x>: The name and version of the engine.
+    IdName(String),
+
+    // id author <x>: The author of the engine.
+    IdAuthor(String),
+
+    // option name <id> type <t> ...: An option of the engine, kept as sent
+    // after option, since USI has option types UCI doesn't, e.g. filename.
+    Opt(String),
+
+    // usiok: The engine has sent its id and options.
+    UsiOk,
+
+    // readyok: The reply to isready.
+    ReadyOk,
+
+    // bestmove <move> [ponder <move>] | bestmove resign | bestmove win: The
+    // result of a search.
+    BestMove(UsiBest),
+
+    // checkmate <move1> ... <movei> | checkmate nomate | checkmate timeout |
+    // checkmate notimplemented: The result of go mate, i.e. the mating moves,
+    // or none if there is no mate, the time ran out, or the engine doesn't
+    // search for mates.
+    Checkmate(Checkmate),
+
+    // info [opts]: Information about the search.
+    Info(UsiInfo),
+}
+
+// The result of a search.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UsiBest {
+    Move {
+        best: UsiMove,
+        ponder: Option<UsiMove>,
+    },
+    // The engine resigns.
+    Resign,
+    // The engine declares a win by the entering king rule.
+    Win,
+}
+
+// The result of go mate, see UsiEngCmd::Checkmate.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Checkmate {
+    Mate(Vec<UsiMove>),
+    NoMate,
+    Timeout,
+    NotImplemented,
+}
+
+impl FromStr for UsiEngCmd {
+    type Err = UziErr;
+
+    fn from_str(cmd: &str) -> Result<UsiEngCmd, Self::Err> {
+        let words = cmd.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] => Err(UziErr::MissingCmd),
+            ["id", "name", name @ ..] if !name.is_empty() => Ok(UsiEngCmd::IdName(name.join(" "))),
+            ["id", "author", author @ ..] if !author.is_empty() => {
+                Ok(UsiEngCmd::IdAuthor(author.join(" ")))
+            }
+            ["id", ..] => Err(UziErr::IdErr),
+            ["option", "name", ..] => Ok(UsiEngCmd::Opt(words[1..].join(" "))),
+            ["usiok"] => Ok(UsiEngCmd::UsiOk),
+            ["readyok"] => Ok(UsiEngCmd::ReadyOk),
+            ["bestmove", "resign"] => Ok(UsiEngCmd::BestMove(UsiBest::Resign)),
+            ["bestmove", "win"] => Ok(UsiEngCmd::BestMove(UsiBest::Win)),
+            ["bestmove", best] => Ok(UsiEngCmd::BestMove(UsiBest::Move {
+                best: UsiMove::from_str(best)?,
+                ponder: None,
+            })),
+            ["bestmove", best, "ponder", ponder] => Ok(UsiEngCmd::BestMove(UsiBest::Move {
+                best: UsiMove::from_str(best)?,
+                ponder: Some(UsiMove::from_str(ponder)?),
+            })),
+            ["bestmove", ..] => Err(UziErr::BestMoveErr),
+            ["checkmate", "nomate"] => Ok(UsiEngCmd::Checkmate(Checkmate::NoMate)),
+            ["checkmate", "timeout"] => Ok(UsiEngCmd::Checkmate(Checkmate::Timeout)),
+            ["checkmate", "notimplemented"] => Ok(UsiEngCmd::Checkmate(Checkmate::NotImplemented)),
+            ["checkmate", moves @ ..] => Ok(UsiEngCmd::Checkmate(Checkmate::Mate(
+                moves
+                    .iter()
+                    .map(|word| UsiMove::from_str(word))
+                    .collect::<Result<_, _>>()?,
+            ))),
+            ["info", ..] => Ok(UsiEngCmd::Info(UsiInfo::try_from(words.as_slice())?)),
+            _ => Err(UziErr::What),
+        }
+    }
+}
+
+impl Display for UsiEngCmd {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            UsiEngCmd::IdName(name) => write!(formatter, "id name {}", name),
+            UsiEngCmd::IdAuthor(author) => write!(formatter, "id author {}", author),
+            UsiEngCmd::Opt(opt) => write!(formatter, "option {}", opt),
+            UsiEngCmd::UsiOk => formatter.write_str("usiok"),
+            UsiEngCmd::ReadyOk => formatter.write_str("readyok"),
+            UsiEngCmd::BestMove(UsiBest::Move { best, ponder }) => {
+                write!(formatter, "bestmove {}", best)?;
+                if let Some(ponder) = ponder {
+                    write!(formatter, " ponder {}", ponder)?;
+                }
+                Ok(())
+            }
+            UsiEngCmd::BestMove(UsiBest::Resign) => formatter.write_str("bestmove resign"),
+            UsiEngCmd::BestMove(UsiBest::Win) => formatter.write_str("bestmove win"),
+            UsiEngCmd::Checkmate(Checkmate::Mate(moves)) => {
+                formatter.write_str("checkmate")?;
+                for usi_move in moves {
+                    write!(formatter, " {}", usi_move)?;
+                }
+                Ok(())
+            }
+            UsiEngCmd::Checkmate(Checkmate::NoMate) => formatter.write_str("checkmate nomate"),
+            UsiEngCmd::Checkmate(Checkmate::Timeout) => formatter.write_str("checkmate timeout"),
+            UsiEngCmd::Checkmate(Checkmate::NotImplemented) => {
+                formatter.write_str("checkmate notimplemented")
+            }
+            UsiEngCmd::Info(info) => info.fmt(formatter),
+        }
+    }
+}
+
+// A score, from the point of view of the engine.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UsiScore {
+    Cp(i32),
+    // Mate in the plies, negative if the engine gets mated.
+    Mate(i32),
+    // mate + or mate -: A mate for the engine, or against it, in an unknown
+    // number of plies.
+    MateSign(bool),
+}
+
+// The fields of an info that shogi GUIs show. Other fields, e.g. currmove,
+// are skipped.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct UsiInfo {
+    depth: Option<u16>,
+    sel_depth: Option<u16>,
+    time: Option<Duration>,
+    nodes: Option<u64>,
+    nps: Option<u64>,
+    hash_full: Option<u16>,
+    multi_pv: Option<u16>,
+    score: Option<UsiScore>,
+    bound: Option<ScoreBound>,
+    pv: Option<Vec<UsiMove>>,
+    // string <str>: The rest of the line.
+    string: Option<String>,
+}
+
+impl UsiInfo {
+    pub fn depth(&self) -> Option<u16> {
+        self.depth
+    }
+
+    pub fn sel_depth(&self) -> Option<u16> {
+        self.sel_depth
+    }
+
+    pub fn time(&self) -> Option<Duration> {
+        self.time
+    }
+
+    pub fn nodes(&self) -> Option<u64> {
+        self.nodes
+    }
+
+    pub fn nps(&self) -> Option<u64> {
+        self.nps
+    }
+
+    pub fn hash_full(&self) -> Option<u16> {
+        self.hash_full
+    }
+
+    pub fn multi_pv(&self) -> Option<u16> {
+        self.multi_pv
+    }
+
+    pub fn score(&self) -> Option<UsiScore> {
+        self.score
+    }
+
+    pub fn bound(&self) -> Option<ScoreBound> {
+        self.bound
+    }
+
+    pub fn pv(&self) -> Option<&[UsiMove]> {
+        self.pv.as_deref()
+    }
+
+    pub fn string(&self) -> Option<&str> {
+        self.string.as_deref()
+    }
+}
+
+impl TryFrom<&[&str]> for UsiInfo {
+    type Error = UziErr;
+
+    fn try_from(cmd: &[&str]) -> Result<UsiInfo, Self::Error> {
+        if cmd.first() != Some(&"info") {
+            return Err(UziErr::InfoErr);
+        }
+        let mut info = UsiInfo::default();
+        let mut i = 1;
+        while i < cmd.len() {
+            let word = cmd[i];
+            i += 1;
+            match word {
+                "depth" => info.depth = Some(to_number(next_word(cmd, &mut i)?)?),
+                "seldepth" => info.sel_depth = Some(to_number(next_word(cmd, &mut i)?)?),
+                "time" => info.time = Some(to_millis(next_word(cmd, &mut i)?, "time")?),
+                "nodes" => info.nodes = Some(to_number(next_word(cmd, &mut i)?)?),
+                "nps" => info.nps = Some(to_number(next_word(cmd, &mut i)?)?),
+                "hashfull" => info.hash_full = Some(to_number(next_word(cmd, &mut i)?)?),
+                "multipv" => info.multi_pv = Some(to_number(next_word(cmd, &mut i)?)?),
+                "score" => {
+                    info.score = Some(match (next_word(cmd, &mut i)?, next_word(cmd, &mut i)?) {
+                        ("cp", cp) => UsiScore::Cp(to_number(cp)?),
+                        ("mate", "+") => UsiScore::MateSign(true),
+                        ("mate", "-") => UsiScore::MateSign(false),
+                        ("mate", plies) => UsiScore::Mate(to_number(plies)?),
+                        _ => return Err(UziErr::InfoErr),
+                    });
+                    info.bound = match cmd.get(i) {
+                        Some(&"lowerbound") => Some(ScoreBound::Lower),
+                        Some(&"upperbound") => Some(ScoreBound::Upper),
+                        _ => None,
+                    };
+                    i += usize::from(info.bound.is_some());
+                }
+                "pv" => {
+                    let mut pv = Vec::new();
+                    while let Some(Ok(usi_move)) = cmd.get(i).map(|word| UsiMove::from_str(word)) {
+                        pv.push(usi_move);
+                        i += 1;
+                    }
+                    info.pv = Some(pv);
+                }
+                "string" => {
+                    info.string = Some(cmd[i..].join(" "));
+                    i = cmd.len();
+                }
+                // Skip anything we don't understand, as for UCI.
+                _ => continue,
+            }
+        }
+        Ok(info)
+    }
+}
+
+impl Display for UsiInfo {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("info")?;
+        for (name, value) in [
+            ("depth", self.depth.map(u64::from)),
+            ("seldepth", self.sel_depth.map(u64::from)),
+            ("time", self.time.map(|time| time.as_millis() as u64)),
+            ("nodes", self.nodes),
+            ("nps", self.nps),
+            ("hashfull", self.hash_full.map(u64::from)),
+            ("multipv", self.multi_pv.map(u64::from)),
+        ] {
+            if let Some(value) = value {
+                write!(formatter, " {} {}", name, value)?;
+            }
+        }
+        match self.score {
+            Some(UsiScore::Cp(cp)) => write!(formatter, " score cp {}", cp)?,
+            Some(UsiScore::Mate(plies)) => write!(formatter, " score mate {}", plies)?,
+            Some(UsiScore::MateSign(true)) => formatter.write_str(" score mate +")?,
+            Some(UsiScore::MateSign(false)) => formatter.write_str(" score mate -")?,
+            None => (),
+        }
+        if let Some(bound) = self.bound {
+            write!(formatter, " {}", bound)?;
+        }
+        if let Some(ref pv) = self.pv {
+            formatter.write_str(" pv")?;
+            for usi_move in pv {
+                write!(formatter, " {}", usi_move)?;
+            }
+        }
+        if let Some(ref string) = self.string {
+            write!(formatter, " string {}", string)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::fake_engine;
+
+    #[test]
+    fn usi_moves() {
+        assert_eq!(UsiMove::from_str("7g7f").unwrap().to_string(), "7g7f");
+        assert_eq!(
+            UsiMove::from_str("8h2b+"),
+            Ok(UsiMove::Normal {
+                from: UsiSq::new(8, 8).unwrap(),
+                to: UsiSq::new(2, 2).unwrap(),
+                is_promotion: true,
+            })
+        );
+        assert_eq!(
+            UsiMove::from_str("P*5e"),
+            Ok(UsiMove::Drop {
+                piece: DropPiece::Pawn,
+                to: UsiSq::new(5, 5).unwrap(),
+            })
+        );
+        for word in ["7j7f", "K*5e", "0g7f", "7g7f=", "e2e4"] {
+            assert!(UsiMove::from_str(word).is_err(), "{}", word);
+        }
+    }
+
+    #[test]
+    fn usi_cmds() {
+        for cmd in [
+            "usi",
+            "setoption name USI_Hash value 256",
+            "usinewgame",
+            "position startpos moves 7g7f 3c3d 8h2b+ 3a2b B*4e",
+            "position sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            "go btime 60000 wtime 50000 byoyomi 10000",
+            "go ponder btime 0 wtime 0 binc 5000 winc 5000",
+            "go mate infinite",
+            "gameover lose",
+        ] {
+            assert_eq!(UsiGuiCmd::from_str(cmd).unwrap().to_string(), cmd);
+        }
+        for cmd in [
+            "id name Lesserkai 1.5",
+            "option name USI_Hash type spin default 256 min 1 max 4096",
+            "bestmove 7g7f ponder 3c3d",
+            "bestmove resign",
+            "bestmove win",
+            "checkmate nomate",
+            "checkmate S*2b 2a2b G*3b",
+            "info depth 10 seldepth 14 time 500 nodes 123456 score cp -42 pv 7g7f 3c3d",
+            "info multipv 2 score mate + lowerbound pv 2b3c+",
+            "info string book move",
+        ] {
+            assert_eq!(UsiEngCmd::from_str(cmd).unwrap().to_string(), cmd);
+        }
+        assert_eq!(UsiGuiCmd::from_str("position sfen"), Err(UziErr::Position));
+        assert_eq!(UsiGuiCmd::from_str("go movetime 1000"), Err(UziErr::GoErr));
+        assert_eq!(UsiEngCmd::from_str("bestmove"), Err(UziErr::BestMoveErr));
+    }
+
+    // An engine process is a transport for USI engines too.
+    #[tokio::test]
+    async fn usi_over_transport() {
+        let mut transport = fake_engine(
+            r#"
+            while read -r line; do
+                case "$line" in
+                    usi) echo "id name Fake"; echo "usiok";;
+                    isready) echo "readyok";;
+                    go*) echo "info depth 1 score cp 30 pv 2g2f"; echo "bestmove 2g2f";;
+                esac
+            done
+            "#,
+        );
+        let mut go = UsiGo::new();
+        go.set_btime(Duration::ZERO)
+            .set_wtime(Duration::ZERO)
+            .set_byoyomi(Duration::from_secs(1));
+        for cmd in [
+            UsiGuiCmd::Usi,
+            UsiGuiCmd::IsReady,
+            UsiGuiCmd::NewGame,
+            UsiGuiCmd::Pos(UsiPos::new()),
+            UsiGuiCmd::Go(go),
+        ] {
+            transport.send_line(&cmd.to_string()).await.unwrap();
+        }
+        let mut replies = Vec::new();
+        while !matches!(replies.last(), Some(UsiEngCmd::BestMove(_))) {
+            let line = transport.recv_line().await.unwrap().unwrap();
+            replies.push(UsiEngCmd::from_str(&line).unwrap());
+        }
+        assert_eq!(replies.len(), 5);
+        assert_eq!(replies[0], UsiEngCmd::IdName("Fake".to_string()));
+        assert_eq!(replies[4].to_string(), "bestmove 2g2f");
+    }
+}
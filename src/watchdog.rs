@@ -0,0 +1,163 @@
+// This module contains the configuration and events of the watchdog used by the
+// Engine client to detect engines that hang, i.e. that stop answering isready or
+// never send bestmove.
+
+use crate::err::UziErr;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+// The watchdog configuration. When an engine misses one of the deadlines, the
+// client kills the process, optionally spawns a new one, and reports what
+// happened to the subscribers.
+#[derive(Clone, Debug)]
+pub struct Watchdog {
+    // The maximum time to wait for readyok after isready.
+    pub(crate) ready_timeout: Duration,
+
+    // The maximum time to wait for bestmove after a search was started. The
+    // default is None, because searches like "go infinite" have no deadline.
+    pub(crate) best_move_timeout: Option<Duration>,
+
+    // If true, a new engine process is spawned after killing a hung one.
+    pub(crate) respawn: bool,
+
+    events: Option<UnboundedSender<WatchdogEvent>>,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_ready_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.ready_timeout = timeout;
+        self
+    }
+
+    pub fn set_best_move_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.best_move_timeout.replace(timeout);
+        self
+    }
+
+    pub fn set_respawn(&mut self, respawn: bool) -> &mut Self {
+        self.respawn = respawn;
+        self
+    }
+
+    // Returns a receiver for the watchdog events. Only the last subscriber
+    // receives events.
+    pub fn subscribe(&mut self) -> UnboundedReceiver<WatchdogEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.events = Some(tx);
+        rx
+    }
+
+    pub(crate) fn report(&self, event: WatchdogEvent) {
+        if let Some(ref events) = self.events {
+            // Nobody is listening anymore if this fails, which is fine.
+            let _ = events.send(event);
+        }
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self {
+            ready_timeout: Duration::from_secs(10),
+            best_move_timeout: None,
+            respawn: false,
+            events: None,
+        }
+    }
+}
+
+// Limits how often an engine is respawned, after it hung or crashed:
+// - max_restarts - the number of respawns allowed within the reset window.
+// - backoff - the delay before the first respawn, which doubles for each respawn
+//   within the reset window, up to max_backoff.
+// - reset_window - after running this long since the last respawn, the engine
+//   is considered healthy and the count starts again.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct RespawnPolicy {
+    pub(crate) max_restarts: u32,
+    pub(crate) backoff: Duration,
+    pub(crate) max_backoff: Duration,
+    pub(crate) reset_window: Duration,
+}
+
+impl RespawnPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_max_restarts(&mut self, max_restarts: u32) -> &mut Self {
+        self.max_restarts = max_restarts;
+        self
+    }
+
+    pub fn set_backoff(&mut self, backoff: Duration, max_backoff: Duration) -> &mut Self {
+        self.backoff = backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn set_reset_window(&mut self, reset_window: Duration) -> &mut Self {
+        self.reset_window = reset_window;
+        self
+    }
+
+    // The delay before the respawn that follows the given number of respawns.
+    pub(crate) fn delay(&self, restarts: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(restarts))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RespawnPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            reset_window: Duration::from_secs(60),
+        }
+    }
+}
+
+// The reply the client was waiting for when the engine hung.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Awaited {
+    ReadyOk,
+    BestMove,
+}
+
+// The incidents reported by the watchdog.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WatchdogEvent {
+    // The engine missed the deadline for a reply and was killed.
+    Killed {
+        awaited: Awaited,
+        deadline: Duration,
+    },
+    // A new engine process was spawned to replace the killed one.
+    Respawned,
+    // The replacement engine process could not be spawned.
+    RespawnFailed(UziErr),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn respawn_policy_delay() {
+        let mut policy = RespawnPolicy::new();
+        policy.set_backoff(Duration::from_millis(100), Duration::from_secs(1));
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(800));
+        assert_eq!(policy.delay(4), Duration::from_secs(1));
+        assert_eq!(policy.delay(40), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,202 @@
+// This module contains the WebSocket transport, for GUIs that run in a browser
+// and can't start processes or open plain sockets. Every UCI line is a text
+// frame of its own. A frame with several lines, as some GUIs send, is split
+// into its lines. There are two sides:
+// - WsTransport - a connection to an engine, for a client that connects to an
+//   engine server, or that accepts engines connecting to it.
+// - WsEngineServer - serves an engine to WebSocket clients, e.g. a browser
+//   GUI, with an engine process for every connection.
+
+use crate::engproc::Spawner;
+use crate::err::UziErr;
+use crate::listen::{self, DEFAULT_MAX_SESSIONS};
+use crate::transport::{BoxFuture, Transport};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio_tungstenite::tungstenite::error::ProtocolError;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+// A line oriented connection over a WebSocket.
+#[derive(Debug)]
+pub struct WsTransport<S> {
+    ws: WebSocketStream<S>,
+    // The lines of the last frame that have not been read yet.
+    pending: VecDeque<String>,
+}
+
+impl WsTransport<MaybeTlsStream<TcpStream>> {
+    // Connects to an engine served at the URL, e.g. "ws://10.0.0.2:4000".
+    pub async fn connect(url: &str) -> Result<Self, UziErr> {
+        let (ws, _) = tokio_tungstenite::connect_async(url).await?;
+        Ok(Self::new(ws))
+    }
+}
+
+impl WsTransport<TcpStream> {
+    // Goes through the WebSocket handshake with a peer that connected to a
+    // listener, e.g. an engine that connects to the client.
+    pub async fn accept(stream: TcpStream) -> Result<Self, UziErr> {
+        Ok(Self::new(tokio_tungstenite::accept_async(stream).await?))
+    }
+}
+
+impl<S> WsTransport<S> {
+    pub fn new(ws: WebSocketStream<S>) -> Self {
+        Self {
+            ws,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<S> Transport for WsTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Debug + Send + Unpin,
+{
+    fn send_line<'a>(&'a mut self, line: &'a str) -> BoxFuture<'a, Result<(), UziErr>> {
+        Box::pin(async move { Ok(self.ws.send(Message::Text(line.to_string())).await?) })
+    }
+
+    // Pings and pongs are answered by the WebSocket, and binary frames are
+    // skipped, since UCI is text.
+    fn recv_line(&mut self) -> BoxFuture<'_, Result<Option<String>, UziErr>> {
+        Box::pin(async move {
+            loop {
+                if let Some(line) = self.pending.pop_front() {
+                    return Ok(Some(line));
+                }
+                match self.ws.next().await {
+                    Some(Ok(Message::Text(text))) => {
+                        self.pending.extend(text.lines().map(str::to_string));
+                    }
+                    Some(Ok(Message::Close(_))) | None => return Ok(None),
+                    Some(Ok(_)) => (),
+                    // A peer that exited without closing, like a process.
+                    Some(Err(tungstenite::Error::Protocol(
+                        ProtocolError::ResetWithoutClosingHandshake,
+                    ))) => return Ok(None),
+                    Some(Err(err)) => return Err(err.into()),
+                }
+            }
+        })
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<(), UziErr>> {
+        Box::pin(async move { Ok(self.ws.close(None).await?) })
+    }
+}
+
+// Serves an engine to WebSocket clients. Every connection gets an engine of
+// its own from the spawner, see TcpEngineServer.
+#[derive(Debug)]
+pub struct WsEngineServer {
+    listener: TcpListener,
+    spawner: Spawner,
+    max_sessions: usize,
+}
+
+impl WsEngineServer {
+    // Listens at the address, e.g. "127.0.0.1:4000", or port 0 for any free
+    // port, see local_addr.
+    pub async fn bind<A: ToSocketAddrs>(addr: A, spawner: Spawner) -> Result<Self, UziErr> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            spawner,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, UziErr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    // Sets how many sessions run at once, see TcpEngineServer.
+    pub fn set_max_sessions(&mut self, max_sessions: usize) -> &mut Self {
+        self.max_sessions = max_sessions;
+        self
+    }
+
+    // Accepts connections until the task is dropped, see TcpEngineServer. Each
+    // session, including its handshake, runs on a task of its own. A
+    // connection whose handshake fails is closed.
+    pub async fn serve(mut self) {
+        listen::serve(
+            &mut self.listener,
+            &self.spawner,
+            self.max_sessions,
+            |proxy, stream: TcpStream| async move {
+                if let Ok(gui) = WsTransport::accept(stream).await {
+                    let _ = proxy.run_with_transport(gui).await;
+                }
+            },
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Engine;
+    use crate::guicmd::{Go, Pos};
+    use crate::pm::Pm;
+    use crate::testutil::{fake_engine, FAKE_ENGINE};
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn server() -> SocketAddr {
+        let spawner = Spawner::new(|| Ok(fake_engine(FAKE_ENGINE)));
+        let server = WsEngineServer::bind("127.0.0.1:0", spawner).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.serve());
+        addr
+    }
+
+    #[tokio::test]
+    async fn websocket_engine_session() {
+        let addr = server().await;
+        let url = format!("ws://{}", addr);
+        let mut eng = Engine::new(WsTransport::connect(&url).await.unwrap());
+        eng.uci(TIMEOUT).await.unwrap();
+        assert_eq!(eng.name(), Some("Fake"));
+        let best = eng.best_move(&Pos::new(), &Go::new()).await;
+        assert_eq!(best, Ok(Pm::from_str("e2e4").unwrap()));
+        assert_eq!(eng.shutdown(TIMEOUT).await.map(|_| ()), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn websocket_frames() {
+        let addr = server().await;
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+            .await
+            .unwrap();
+        // Two lines in a frame, as a browser GUI may send them.
+        ws.send(Message::Text("uci\nisready\n".into()))
+            .await
+            .unwrap();
+        let mut frames = Vec::new();
+        while frames.last().map(String::as_str) != Some("readyok") {
+            match ws.next().await.unwrap().unwrap() {
+                Message::Text(text) => frames.push(text),
+                message => panic!("unexpected {:?}", message),
+            }
+        }
+        assert_eq!(
+            frames,
+            [
+                "id name Fake",
+                "id author uzi",
+                "option name Hash type spin default 16 min 1 max 1024",
+                "uciok",
+                "readyok",
+            ]
+        );
+    }
+}
@@ -0,0 +1,76 @@
+// This module contains the Windows specifics of running engine processes:
+// - engines are started without a console window, which would otherwise pop up
+//   when the GUI is not a console program.
+// - engines are put in a job object that kills them, and any process they
+//   started, when the job is closed, i.e. when the EngineProcess is dropped or
+//   the GUI exits.
+
+use std::ffi::c_void;
+use std::io;
+use std::mem;
+use std::ptr;
+use tokio::process::{Child, Command};
+use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+use windows_sys::Win32::System::JobObjects::{
+    AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+    SetInformationJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+    JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+};
+use windows_sys::Win32::System::Threading::CREATE_NO_WINDOW;
+
+// Sets up the command so that the engine does not get a console window.
+pub(crate) fn set_no_window(cmd: &mut Command) {
+    cmd.creation_flags(CREATE_NO_WINDOW);
+}
+
+// A job object that kills its processes when it is closed.
+#[derive(Debug)]
+pub(crate) struct Job(HANDLE);
+
+// SAFETY: the handle of a job object can be used and closed from any thread.
+unsafe impl Send for Job {}
+unsafe impl Sync for Job {}
+
+impl Job {
+    // Creates a job and assigns the process to it. Processes started by the
+    // engine after this are also in the job.
+    pub fn assign(child: &Child) -> io::Result<Self> {
+        let process = child
+            .raw_handle()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+
+        // SAFETY: null arguments create an unnamed job with default security.
+        let job = Job(unsafe { CreateJobObjectW(ptr::null(), ptr::null()) });
+        if job.0.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: the limits are a plain structure, for which zero is valid.
+        let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { mem::zeroed() };
+        limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        // SAFETY: the pointer and size describe the limits, which outlive the
+        // call, and the handles are valid.
+        let ok = unsafe {
+            SetInformationJobObject(
+                job.0,
+                JobObjectExtendedLimitInformation,
+                &limits as *const JOBOBJECT_EXTENDED_LIMIT_INFORMATION as *const c_void,
+                mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            ) != 0
+                && AssignProcessToJobObject(job.0, process as HANDLE) != 0
+        };
+        match ok {
+            true => Ok(job),
+            false => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        // SAFETY: the handle is valid and only closed here.
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
@@ -0,0 +1,1073 @@
+// This module contains the adapters between UCI and the xboard protocol, so
+// that an engine can be used with a GUI that speaks the other protocol:
+// - XboardAdapter runs a UCI engine for an xboard GUI, e.g. Stockfish in
+//   XBoard or on a chess server that only speaks xboard.
+// - UciAdapter runs an xboard engine for a UCI GUI, e.g. an older engine in a
+//   GUI that only speaks UCI.
+//
+// The adapters translate the moves, the time controls and the thinking output,
+// and keep the state that one protocol has and the other doesn't, e.g. the side
+// the engine plays in xboard, or the whole position of every search in UCI.
+// Like UciProxy, they run with the GUI on stdin and stdout, so that a program
+// that runs the adapter can be installed in the GUI as the engine:
+//
+// XboardAdapter::spawn("stockfish")?.run().await?;
+
+use crate::board::Board;
+use crate::engcmd::{EngCmd, Info};
+use crate::engmatch::Side;
+use crate::engproc::EngineProcess;
+use crate::err::UziErr;
+use crate::guicmd::{Go, GuiCmd, Pos};
+use crate::opt::SetOpt;
+use crate::pm::Pm;
+use crate::proxy::{next_event, Event};
+use crate::transport::{StreamTransport, Transport};
+use crate::xboard::{FeatureValue, Thinking, XbEngCmd, XbGuiCmd};
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::fmt::{self, Debug, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{self, AsyncRead, AsyncWrite};
+use tokio::time::{self, Instant};
+
+// Thinking output has mate scores as this plus the moves to mate, e.g. 100005
+// for mate in 5, and minus this minus the moves for getting mated.
+const XB_MATE: i32 = 100_000;
+
+// The time control of xboard engines until the GUI sends level, i.e. 40 moves
+// in 5 minutes.
+const DEFAULT_LEVEL: (u32, Duration, Duration) = (40, Duration::from_secs(300), Duration::ZERO);
+
+// Runs a UCI engine for an xboard GUI.
+pub struct XboardAdapter {
+    engine: Box<dyn Transport>,
+    name: Option<String>,
+    // The features are sent once the GUI has sent protover and the engine has
+    // sent uciok, in either order.
+    got_protover: bool,
+    got_uciok: bool,
+    // The position of the game, from new or setboard, and the moves since.
+    pos: Pos,
+    // The side the engine plays, or None in force mode.
+    side: Option<Side>,
+    // The moves per time control, the base time and the increment, from level.
+    level: (u32, Duration, Duration),
+    move_time: Option<Duration>,
+    depth: Option<u16>,
+    // The clocks of the engine and of its opponent, from time and otim.
+    time: Option<Duration>,
+    otim: Option<Duration>,
+    is_posting: bool,
+    // The pings of the GUI, which are answered once the engine answers the
+    // isready sent for each one.
+    pings: VecDeque<u32>,
+    // When the search for the move of the engine started, if it is searching.
+    search: Option<Instant>,
+    // The searches that were stopped, e.g. by force, whose bestmove is ignored.
+    stale: usize,
+    // How long the engine may take to exit after quit before it is killed.
+    grace_period: Duration,
+}
+
+impl XboardAdapter {
+    // Returns an adapter for the UCI engine at the other end of the transport.
+    pub fn new<T: Transport + 'static>(engine: T) -> Self {
+        Self {
+            engine: Box::new(engine),
+            name: None,
+            got_protover: false,
+            got_uciok: false,
+            pos: Pos::new(),
+            side: Some(Side::Black),
+            level: DEFAULT_LEVEL,
+            move_time: None,
+            depth: None,
+            time: None,
+            otim: None,
+            is_posting: false,
+            pings: VecDeque::new(),
+            search: None,
+            stale: 0,
+            grace_period: Duration::from_secs(5),
+        }
+    }
+
+    pub fn spawn<S: AsRef<OsStr>>(program: S) -> Result<Self, UziErr> {
+        Ok(Self::new(EngineProcess::spawn(program)?))
+    }
+
+    pub fn set_grace_period(&mut self, grace_period: Duration) -> &mut Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    // Runs the adapter with the GUI on stdin and stdout, see run_with.
+    pub async fn run(self) -> Result<(), UziErr> {
+        self.run_with(io::stdin(), io::stdout()).await
+    }
+
+    // Runs the adapter for the GUI, which writes to input and reads from
+    // output, see run_with_transport.
+    pub async fn run_with<R, W>(self, input: R, output: W) -> Result<(), UziErr>
+    where
+        R: AsyncRead + Debug + Send + Unpin,
+        W: AsyncWrite + Debug + Send + Unpin,
+    {
+        self.run_with_transport(StreamTransport::new(input, output))
+            .await
+    }
+
+    // Runs the adapter for the xboard GUI at the other end of the transport.
+    // Returns once the engine has exited after the GUI sent quit or closed the
+    // connection, or fails with EngineExited if the engine exits before that.
+    pub async fn run_with_transport<G: Transport>(mut self, mut gui: G) -> Result<(), UziErr> {
+        self.engine.send_line("uci").await?;
+        loop {
+            match next_event(&mut gui, &mut self.engine).await {
+                Event::Gui(line) => {
+                    let Some(line) = line? else {
+                        let _ = self.engine.send_line("quit").await;
+                        break;
+                    };
+                    if !self.on_gui_line(&mut gui, &line).await? {
+                        break;
+                    }
+                }
+                Event::Engine(line) => {
+                    let Some(line) = line? else {
+                        return Err(UziErr::EngineExited);
+                    };
+                    self.on_engine_line(&mut gui, &line).await?;
+                }
+            }
+        }
+        let result = wait_exit(&mut self.engine, self.grace_period).await;
+        let _ = gui.kill().await;
+        result
+    }
+
+    // Handles a line of the GUI. Returns false after quit.
+    async fn on_gui_line<G: Transport>(&mut self, gui: &mut G, line: &str) -> Result<bool, UziErr> {
+        let cmd = match XbGuiCmd::from_str(line) {
+            Ok(cmd) => cmd,
+            Err(UziErr::MissingCmd) => return Ok(true),
+            Err(_) => {
+                let error = XbEngCmd::Error {
+                    kind: "unknown command".to_string(),
+                    cmd: line.trim().to_string(),
+                };
+                gui.send_line(&error.to_string()).await?;
+                return Ok(true);
+            }
+        };
+        match cmd {
+            XbGuiCmd::Xboard
+            | XbGuiCmd::Accepted(_)
+            | XbGuiCmd::Rejected(_)
+            | XbGuiCmd::Memory(_)
+            | XbGuiCmd::Cores(_)
+            | XbGuiCmd::Hard
+            | XbGuiCmd::Easy => (),
+            XbGuiCmd::Protover(_) => {
+                self.got_protover = true;
+                self.send_features(gui).await?;
+            }
+            XbGuiCmd::New => {
+                self.cancel().await?;
+                self.engine.send_line("ucinewgame").await?;
+                self.pos = Pos::new();
+                self.side = Some(Side::Black);
+                self.depth = None;
+                self.time = None;
+                self.otim = None;
+            }
+            XbGuiCmd::SetBoard(fen) => {
+                self.cancel().await?;
+                self.pos = Pos::with_fen(&fen);
+            }
+            XbGuiCmd::Force | XbGuiCmd::Result(..) => {
+                self.cancel().await?;
+                self.side = None;
+            }
+            XbGuiCmd::Go => {
+                self.side = Some(self.side_to_move());
+                self.think().await?;
+            }
+            XbGuiCmd::PlayOther => self.side = Some(self.side_to_move().other()),
+            XbGuiCmd::UserMove(pm) | XbGuiCmd::Move(pm) => {
+                self.pos.add_move(pm);
+                if self.side == Some(self.side_to_move()) {
+                    self.think().await?;
+                }
+            }
+            XbGuiCmd::MoveNow => {
+                if self.search.is_some() {
+                    self.engine.send_line("stop").await?;
+                }
+            }
+            XbGuiCmd::Undo | XbGuiCmd::Remove => {
+                self.cancel().await?;
+                let plies = if cmd == XbGuiCmd::Undo { 1 } else { 2 };
+                self.pos = take_back(&self.pos, plies);
+            }
+            XbGuiCmd::Level { moves, base, inc } => {
+                self.level = (moves, base, inc);
+                self.move_time = None;
+            }
+            XbGuiCmd::St(move_time) => self.move_time = Some(move_time),
+            XbGuiCmd::Sd(depth) => self.depth = Some(depth),
+            XbGuiCmd::Time(time) => self.time = Some(time),
+            XbGuiCmd::Otim(otim) => self.otim = Some(otim),
+            XbGuiCmd::Ping(n) => {
+                self.pings.push_back(n);
+                self.engine.send_line("isready").await?;
+            }
+            XbGuiCmd::Post => self.is_posting = true,
+            XbGuiCmd::NoPost => self.is_posting = false,
+            XbGuiCmd::Quit => {
+                self.engine.send_line("quit").await?;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn on_engine_line<G: Transport>(
+        &mut self,
+        gui: &mut G,
+        line: &str,
+    ) -> Result<(), UziErr> {
+        match EngCmd::from_str(line) {
+            Ok(EngCmd::IdName(name)) => self.name = Some(name),
+            Ok(EngCmd::UciOk) => {
+                self.got_uciok = true;
+                self.send_features(gui).await?;
+            }
+            Ok(EngCmd::ReadyOk) => {
+                if let Some(n) = self.pings.pop_front() {
+                    gui.send_line(&XbEngCmd::Pong(n).to_string()).await?;
+                }
+            }
+            Ok(EngCmd::Info(info)) if self.is_posting && self.stale == 0 => {
+                let thinking = self
+                    .search
+                    .and_then(|started| to_thinking(&info, started.elapsed()));
+                if let Some(thinking) = thinking {
+                    gui.send_line(&XbEngCmd::Thinking(thinking).to_string())
+                        .await?;
+                }
+            }
+            Ok(EngCmd::BestMove { best, .. }) => {
+                if self.stale > 0 {
+                    self.stale -= 1;
+                    return Ok(());
+                }
+                if self.search.take().is_none() {
+                    return Ok(());
+                }
+                // Engines send the null move when they have no legal moves.
+                let reply = match best {
+                    Pm::Null => XbEngCmd::Resign,
+                    best => {
+                        self.pos.add_move(best);
+                        XbEngCmd::Move(best)
+                    }
+                };
+                gui.send_line(&reply.to_string()).await?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    // Sends the features of the engine once the GUI has sent protover and the
+    // engine has sent uciok.
+    async fn send_features<G: Transport>(&mut self, gui: &mut G) -> Result<(), UziErr> {
+        if !self.got_protover || !self.got_uciok {
+            return Ok(());
+        }
+        let name = self
+            .name
+            .clone()
+            .unwrap_or_else(|| "UCI engine".to_string());
+        let mut features = vec![("myname".to_string(), FeatureValue::Str(name))];
+        for (feature, value) in [
+            ("setboard", 1),
+            ("usermove", 1),
+            ("ping", 1),
+            ("reuse", 1),
+            ("san", 0),
+            ("colors", 0),
+            ("sigint", 0),
+            ("sigterm", 0),
+            ("done", 1),
+        ] {
+            features.push((feature.to_string(), FeatureValue::Int(value)));
+        }
+        gui.send_line(&XbEngCmd::Feature(features).to_string())
+            .await
+    }
+
+    fn side_to_move(&self) -> Side {
+        match self.pos.is_white_to_move() {
+            true => Side::White,
+            false => Side::Black,
+        }
+    }
+
+    // Starts the search for the move of the engine, which plays the side to
+    // move.
+    async fn think(&mut self) -> Result<(), UziErr> {
+        self.cancel().await?;
+        let go = self.limits();
+        self.engine
+            .send_line(&GuiCmd::Pos(self.pos.clone()).to_string())
+            .await?;
+        self.engine.send_line(&GuiCmd::Go(go).to_string()).await?;
+        self.search = Some(Instant::now());
+        Ok(())
+    }
+
+    // Returns the limits of the search from the time control, with the clocks
+    // of the GUI, or the base time of level if the GUI hasn't sent them.
+    fn limits(&self) -> Go {
+        let mut go = Go::new();
+        if let Some(depth) = self.depth {
+            go.set_depth(depth);
+        }
+        if let Some(move_time) = self.move_time {
+            go.set_move_time(move_time);
+            return go;
+        }
+        let (moves, base, inc) = self.level;
+        let time = self.time.unwrap_or(base);
+        let otim = self.otim.unwrap_or(base);
+        match self.side_to_move() {
+            Side::White => go.set_wtime(time).set_btime(otim),
+            Side::Black => go.set_wtime(otim).set_btime(time),
+        };
+        if !inc.is_zero() {
+            go.set_winc(inc).set_binc(inc);
+        }
+        if moves > 0 {
+            // The moves the engine has made since new or setboard.
+            let played = self.pos.moves().len() as u32 / 2;
+            go.set_moves_to_go((moves - played % moves) as u16);
+        }
+        go
+    }
+
+    // Stops the search of the engine, if any, and ignores its move.
+    async fn cancel(&mut self) -> Result<(), UziErr> {
+        if self.search.take().is_some() {
+            self.stale += 1;
+            self.engine.send_line("stop").await?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for XboardAdapter {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("XboardAdapter")
+            .field("engine", &self.engine)
+            .field("pos", &self.pos)
+            .field("side", &self.side)
+            .finish_non_exhaustive()
+    }
+}
+
+// The features of an xboard engine the adapter uses, see UciAdapter.
+#[derive(Clone, Debug, Default)]
+struct Features {
+    name: Option<String>,
+    usermove: bool,
+    setboard: bool,
+    ping: bool,
+    memory: bool,
+    smp: bool,
+}
+
+impl Features {
+    // Records the feature, and returns true if the adapter accepts it. SAN
+    // moves are rejected, so that the engine sends coordinates.
+    fn set(&mut self, name: &str, value: &FeatureValue) -> bool {
+        let is_on = *value == FeatureValue::Int(1);
+        match (name, value) {
+            ("myname", FeatureValue::Str(value)) => self.name = Some(value.clone()),
+            ("usermove", _) => self.usermove = is_on,
+            ("setboard", _) => self.setboard = is_on,
+            ("ping", _) => self.ping = is_on,
+            ("memory", _) => self.memory = is_on,
+            ("smp", _) => self.smp = is_on,
+            ("san", _) => return !is_on,
+            _ => (),
+        }
+        true
+    }
+}
+
+// A search of the xboard engine for a go of the GUI.
+#[derive(Debug)]
+struct XbSearch {
+    // The searched position, to read the pv of the engine if it is in SAN.
+    board: Option<Board>,
+    // Whether the move of the engine is held until stop or ponderhit, as UCI
+    // requires for go infinite and go ponder.
+    is_held: bool,
+    held: Option<Pm>,
+}
+
+// Runs an xboard engine for a UCI GUI. Searches without a time control, e.g.
+// go infinite, are played with the time control of the engine, and the move is
+// held until stop. The depth of a search is sent with sd, which xboard engines
+// keep for the later searches of the game.
+pub struct UciAdapter {
+    engine: Box<dyn Transport>,
+    features: Features,
+    // How long the engine may take to send its features after protover, for
+    // engines that don't send done=1.
+    feature_timeout: Duration,
+    // Whether the GUI is waiting for uciok, and when the features are due,
+    // unless the engine asked for more time with done=0.
+    is_handshaking: bool,
+    features_due: Option<Instant>,
+    // The position of the next search, from the GUI.
+    pos: Pos,
+    // The position the engine has, or None if it has to be set up with new.
+    synced: Option<Pos>,
+    // The pings sent for the isready commands of the GUI.
+    pings: VecDeque<u32>,
+    last_ping: u32,
+    search: Option<XbSearch>,
+    grace_period: Duration,
+}
+
+impl UciAdapter {
+    // Returns an adapter for the xboard engine at the other end of the
+    // transport.
+    pub fn new<T: Transport + 'static>(engine: T) -> Self {
+        Self {
+            engine: Box::new(engine),
+            features: Features::default(),
+            feature_timeout: Duration::from_secs(2),
+            is_handshaking: false,
+            features_due: None,
+            pos: Pos::new(),
+            synced: None,
+            pings: VecDeque::new(),
+            last_ping: 0,
+            search: None,
+            grace_period: Duration::from_secs(5),
+        }
+    }
+
+    pub fn spawn<S: AsRef<OsStr>>(program: S) -> Result<Self, UziErr> {
+        Ok(Self::new(EngineProcess::spawn(program)?))
+    }
+
+    // Sets how long the engine may take to send its features, 2 seconds by
+    // default, as in xboard.
+    pub fn set_feature_timeout(&mut self, feature_timeout: Duration) -> &mut Self {
+        self.feature_timeout = feature_timeout;
+        self
+    }
+
+    pub fn set_grace_period(&mut self, grace_period: Duration) -> &mut Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    // Runs the adapter with the GUI on stdin and stdout, see run_with.
+    pub async fn run(self) -> Result<(), UziErr> {
+        self.run_with(io::stdin(), io::stdout()).await
+    }
+
+    // Runs the adapter for the GUI, which writes to input and reads from
+    // output, see run_with_transport.
+    pub async fn run_with<R, W>(self, input: R, output: W) -> Result<(), UziErr>
+    where
+        R: AsyncRead + Debug + Send + Unpin,
+        W: AsyncWrite + Debug + Send + Unpin,
+    {
+        self.run_with_transport(StreamTransport::new(input, output))
+            .await
+    }
+
+    // Runs the adapter for the UCI GUI at the other end of the transport, see
+    // XboardAdapter::run_with_transport.
+    pub async fn run_with_transport<G: Transport>(mut self, mut gui: G) -> Result<(), UziErr> {
+        loop {
+            let event = next_event(&mut gui, &mut self.engine);
+            let event = match self.features_due {
+                Some(due) => match time::timeout_at(due, event).await {
+                    Ok(event) => event,
+                    Err(_) => {
+                        self.finish_handshake(&mut gui).await?;
+                        continue;
+                    }
+                },
+                None => event.await,
+            };
+            match event {
+                Event::Gui(line) => {
+                    let Some(line) = line? else {
+                        let _ = self.engine.send_line("quit").await;
+                        break;
+                    };
+                    if !self.on_gui_line(&mut gui, &line).await? {
+                        break;
+                    }
+                }
+                Event::Engine(line) => {
+                    let Some(line) = line? else {
+                        return Err(UziErr::EngineExited);
+                    };
+                    self.on_engine_line(&mut gui, &line).await?;
+                }
+            }
+        }
+        let result = wait_exit(&mut self.engine, self.grace_period).await;
+        let _ = gui.kill().await;
+        result
+    }
+
+    // Handles a line of the GUI. Returns false after quit.
+    async fn on_gui_line<G: Transport>(&mut self, gui: &mut G, line: &str) -> Result<bool, UziErr> {
+        // Threads is not a standard option, so it is not parsed as one.
+        if let ["setoption", "name", "Threads", "value", threads] =
+            line.split_whitespace().collect::<Vec<_>>()[..]
+        {
+            if let (true, Ok(threads)) = (self.features.smp, threads.parse()) {
+                self.send(XbGuiCmd::Cores(threads)).await?;
+            }
+            return Ok(true);
+        }
+        // Like engines, the adapter ignores the commands it doesn't know.
+        let Ok(cmd) = GuiCmd::from_str(line) else {
+            return Ok(true);
+        };
+        match cmd {
+            GuiCmd::Uci => {
+                self.send(XbGuiCmd::Xboard).await?;
+                self.send(XbGuiCmd::Protover(2)).await?;
+                self.is_handshaking = true;
+                self.features_due = Some(Instant::now() + self.feature_timeout);
+            }
+            GuiCmd::IsReady => {
+                if self.features.ping {
+                    self.last_ping += 1;
+                    self.pings.push_back(self.last_ping);
+                    self.send(XbGuiCmd::Ping(self.last_ping)).await?;
+                } else {
+                    gui.send_line(&EngCmd::ReadyOk.to_string()).await?;
+                }
+            }
+            GuiCmd::SetOpt(SetOpt::Hash(mb)) if self.features.memory => {
+                self.send(XbGuiCmd::Memory(mb)).await?;
+            }
+            GuiCmd::NewGame => self.synced = None,
+            GuiCmd::Pos(pos) => self.pos = pos,
+            GuiCmd::Go(go) => self.go(&go).await?,
+            GuiCmd::Stop => self.release(gui, true).await?,
+            GuiCmd::Ponderhit => self.release(gui, false).await?,
+            GuiCmd::Quit => {
+                self.send(XbGuiCmd::Quit).await?;
+                return Ok(false);
+            }
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    async fn on_engine_line<G: Transport>(
+        &mut self,
+        gui: &mut G,
+        line: &str,
+    ) -> Result<(), UziErr> {
+        let Ok(cmd) = XbEngCmd::from_str(line) else {
+            return Ok(());
+        };
+        match cmd {
+            XbEngCmd::Feature(features) => {
+                for (name, value) in features {
+                    let reply = match self.features.set(&name, &value) {
+                        true => XbGuiCmd::Accepted(name.clone()),
+                        false => XbGuiCmd::Rejected(name.clone()),
+                    };
+                    self.send(reply).await?;
+                    match (name.as_str(), value) {
+                        ("done", FeatureValue::Int(1)) => self.finish_handshake(gui).await?,
+                        ("done", _) => self.features_due = None,
+                        _ => (),
+                    }
+                }
+            }
+            XbEngCmd::Move(pm) => {
+                // Keeps the engine from thinking on, or pondering, until the
+                // next go.
+                self.send(XbGuiCmd::Force).await?;
+                if let Some(ref mut synced) = self.synced {
+                    synced.add_move(pm);
+                }
+                match self.search {
+                    Some(ref mut search) if search.is_held => search.held = Some(pm),
+                    Some(_) => {
+                        self.search = None;
+                        send_best(gui, pm).await?;
+                    }
+                    None => (),
+                }
+            }
+            XbEngCmd::Resign => {
+                if self.search.take().is_some() {
+                    send_best(gui, Pm::Null).await?;
+                }
+            }
+            XbEngCmd::Thinking(thinking) => {
+                if let Some(ref search) = self.search {
+                    gui.send_line(&to_info(&thinking, search.board.as_ref()))
+                        .await?;
+                }
+            }
+            XbEngCmd::Pong(_) => {
+                if self.pings.pop_front().is_some() {
+                    gui.send_line(&EngCmd::ReadyOk.to_string()).await?;
+                }
+            }
+            XbEngCmd::Result(..)
+            | XbEngCmd::OfferDraw
+            | XbEngCmd::IllegalMove { .. }
+            | XbEngCmd::Error { .. } => {
+                let info = Info::from_string(line.trim());
+                gui.send_line(&EngCmd::Info(info).to_string()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, cmd: XbGuiCmd) -> Result<(), UziErr> {
+        self.engine.send_line(&cmd.to_string()).await
+    }
+
+    // Sends the id and the options of the engine, and uciok, once the engine
+    // has sent its features or the time for them is up.
+    async fn finish_handshake<G: Transport>(&mut self, gui: &mut G) -> Result<(), UziErr> {
+        self.features_due = None;
+        if !std::mem::take(&mut self.is_handshaking) {
+            return Ok(());
+        }
+        let name = self.features.name.as_deref().unwrap_or("xboard engine");
+        gui.send_line(&EngCmd::IdName(name.to_string()).to_string())
+            .await?;
+        if self.features.memory {
+            gui.send_line("option name Hash type spin default 16 min 1 max 65536")
+                .await?;
+        }
+        if self.features.smp {
+            gui.send_line("option name Threads type spin default 1 min 1 max 512")
+                .await?;
+        }
+        gui.send_line(&EngCmd::UciOk.to_string()).await
+    }
+
+    // Sets up the position of the GUI on the engine, by playing the new moves
+    // if it continues the position the engine has, or from new otherwise.
+    async fn sync_pos(&mut self) -> Result<(), UziErr> {
+        let played = match self.synced {
+            Some(ref synced)
+                if synced.fen() == self.pos.fen()
+                    && self.pos.moves().starts_with(synced.moves()) =>
+            {
+                synced.moves().len()
+            }
+            _ => {
+                self.send(XbGuiCmd::New).await?;
+                self.send(XbGuiCmd::Force).await?;
+                if let Some(fen) = self.pos.fen() {
+                    self.send(XbGuiCmd::SetBoard(fen.to_string())).await?;
+                }
+                0
+            }
+        };
+        let moves = self.pos.moves()[played..].to_vec();
+        for pm in moves {
+            match self.features.usermove {
+                true => self.send(XbGuiCmd::UserMove(pm)).await?,
+                false => self.send(XbGuiCmd::Move(pm)).await?,
+            }
+        }
+        self.synced = Some(self.pos.clone());
+        Ok(())
+    }
+
+    // Starts a search of the engine on the position of the GUI.
+    async fn go(&mut self, go: &Go) -> Result<(), UziErr> {
+        self.sync_pos().await?;
+        if let Some(depth) = go.depth() {
+            self.send(XbGuiCmd::Sd(depth)).await?;
+        }
+        let (time, otim, inc) = match self.pos.is_white_to_move() {
+            true => (go.wtime(), go.btime(), go.winc()),
+            false => (go.btime(), go.wtime(), go.binc()),
+        };
+        if let Some(move_time) = go.move_time() {
+            // st only takes whole seconds.
+            let secs = move_time.as_secs().max(1);
+            self.send(XbGuiCmd::St(Duration::from_secs(secs))).await?;
+        } else if let Some(time) = time {
+            self.send(XbGuiCmd::Level {
+                moves: go.moves_to_go().map_or(0, u32::from),
+                base: time,
+                inc: inc.unwrap_or_default(),
+            })
+            .await?;
+            self.send(XbGuiCmd::Time(time)).await?;
+            self.send(XbGuiCmd::Otim(otim.unwrap_or(time))).await?;
+        }
+        self.send(XbGuiCmd::Post).await?;
+        self.send(XbGuiCmd::Go).await?;
+        self.search = Some(XbSearch {
+            board: Board::from_pos(&self.pos),
+            is_held: go.is_infinite() || go.is_ponder(),
+            held: None,
+        });
+        Ok(())
+    }
+
+    // Sends the move of a held search on stop or ponderhit, or, on stop,
+    // makes the engine move now if it hasn't moved yet.
+    async fn release<G: Transport>(&mut self, gui: &mut G, is_stop: bool) -> Result<(), UziErr> {
+        let Some(ref mut search) = self.search else {
+            return Ok(());
+        };
+        search.is_held = false;
+        match search.held {
+            Some(pm) => {
+                self.search = None;
+                send_best(gui, pm).await
+            }
+            None if is_stop => self.send(XbGuiCmd::MoveNow).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Debug for UciAdapter {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("UciAdapter")
+            .field("engine", &self.engine)
+            .field("features", &self.features)
+            .field("pos", &self.pos)
+            .finish_non_exhaustive()
+    }
+}
+
+// Waits for the engine to exit, or kills it after the grace period. The lines
+// it still sends are dropped, since they are in the wrong protocol for the GUI.
+async fn wait_exit(engine: &mut Box<dyn Transport>, grace_period: Duration) -> Result<(), UziErr> {
+    let drain = async {
+        while engine.recv_line().await?.is_some() {}
+        Ok(())
+    };
+    match time::timeout(grace_period, drain).await {
+        Ok(result) => result,
+        Err(_) => engine.kill().await,
+    }
+}
+
+async fn send_best<G: Transport>(gui: &mut G, best: Pm) -> Result<(), UziErr> {
+    let best = EngCmd::BestMove { best, ponder: None };
+    gui.send_line(&best.to_string()).await
+}
+
+// Returns the position without its last moves.
+fn take_back(pos: &Pos, plies: usize) -> Pos {
+    let mut taken = match pos.fen() {
+        Some(fen) => Pos::with_fen(fen),
+        None => Pos::new(),
+    };
+    let moves = pos.moves();
+    for pm in &moves[..moves.len().saturating_sub(plies)] {
+        taken.add_move(*pm);
+    }
+    taken
+}
+
+// Returns the thinking output for an info of the best line, if it has a pv
+// and a score.
+fn to_thinking(info: &Info, elapsed: Duration) -> Option<Thinking> {
+    if info.multi_pv().is_some_and(|rank| rank > 1) {
+        return None;
+    }
+    let pv = info.pv()?;
+    let score = info.score()?;
+    let score = match score.mate() {
+        Some(mate) if mate > 0 => XB_MATE + i32::from(mate),
+        Some(mate) => -XB_MATE + i32::from(mate),
+        None => score.cp()?,
+    };
+    let pv = pv.iter().map(Pm::to_string).collect::<Vec<_>>().join(" ");
+    Some(Thinking::new(
+        info.depth().unwrap_or(0),
+        score,
+        info.time().unwrap_or(elapsed),
+        info.nodes().unwrap_or(0),
+        &pv,
+    ))
+}
+
+// Returns the info line for thinking output. The pv is kept up to the first
+// word that is neither a move in coordinates nor in SAN, and move numbers are
+// skipped.
+fn to_info(thinking: &Thinking, board: Option<&Board>) -> String {
+    let score = match thinking.score() {
+        score if score >= XB_MATE => format!("mate {}", score - XB_MATE),
+        score if score <= -XB_MATE => format!("mate {}", score + XB_MATE),
+        score => format!("cp {}", score),
+    };
+    let mut info = format!(
+        "info depth {} score {} time {} nodes {}",
+        thinking.depth(),
+        score,
+        thinking.time().as_millis(),
+        thinking.nodes()
+    );
+    let mut board = board.cloned();
+    let mut pv = Vec::new();
+    for word in thinking.pv().split_whitespace() {
+        if word.starts_with(|c: char| c.is_ascii_digit()) {
+            continue;
+        }
+        let pm = match board {
+            Some(ref board) => Pm::from_str(word)
+                .ok()
+                .filter(|pm| board.legal_moves().contains(pm))
+                .or_else(|| board.parse_san(word)),
+            None => Pm::from_str(word).ok(),
+        };
+        let Some(pm) = pm else {
+            break;
+        };
+        if let Some(ref mut board) = board {
+            board.play(pm);
+        }
+        pv.push(pm.to_string());
+    }
+    if !pv.is_empty() {
+        info.push_str(" pv ");
+        info.push_str(&pv.join(" "));
+    }
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEngine, MockSearch};
+    use crate::tap::{TapLog, TapTransport};
+    use crate::testutil::fake_engine;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    // A writer the test can read back.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Sends the lines, and returns the replies up to the one that starts with
+    // last.
+    async fn exchange<G: Transport>(gui: &mut G, lines: &[&str], last: &str) -> Vec<String> {
+        for line in lines {
+            gui.send_line(line).await.unwrap();
+        }
+        let mut replies = Vec::new();
+        while !replies
+            .last()
+            .is_some_and(|line: &String| line.starts_with(last))
+        {
+            replies.push(gui.recv_line().await.unwrap().unwrap());
+        }
+        replies
+    }
+
+    #[tokio::test]
+    async fn xboard_gui_plays_uci_engine() {
+        let mut mock = MockEngine::new("Mock");
+        let mut search = MockSearch::new(Pm::from_str("e7e5").unwrap());
+        search.add_info("depth 3 score cp 15 time 120 nodes 5000 pv e7e5 g1f3");
+        mock.add_search(search);
+        let log = mock.log();
+        let (gui_input, input) = tokio::io::duplex(1024);
+        let (output, gui_output) = tokio::io::duplex(1024);
+        let run = tokio::spawn(XboardAdapter::new(mock).run_with(input, output));
+        let mut gui = StreamTransport::new(gui_output, gui_input);
+
+        assert_eq!(
+            exchange(&mut gui, &["xboard", "protover 2"], "feature").await,
+            [
+                "feature myname=\"Mock\" setboard=1 usermove=1 ping=1 reuse=1 san=0 colors=0 \
+                 sigint=0 sigterm=0 done=1"
+            ]
+        );
+        let lines = [
+            "new",
+            "random",
+            "post",
+            "level 40 5 0",
+            "time 30000",
+            "otim 29000",
+            "usermove e2e4",
+        ];
+        assert_eq!(
+            exchange(&mut gui, &lines, "move").await,
+            [
+                "Error (unknown command): random",
+                "3 15 12 5000 e7e5 g1f3",
+                "move e7e5",
+            ]
+        );
+        // After force, the engine only gets the moves, and undo takes back the
+        // last one.
+        let lines = ["force", "usermove g1f3", "undo", "go", "ping 7"];
+        assert_eq!(
+            exchange(&mut gui, &lines, "pong").await.last().unwrap(),
+            "pong 7"
+        );
+        gui.send_line("quit").await.unwrap();
+        assert_eq!(run.await.unwrap(), Ok(()));
+        assert_eq!(
+            log.lines(),
+            [
+                "uci",
+                "ucinewgame",
+                "position startpos moves e2e4",
+                "go wtime 290000 btime 300000 movestogo 40",
+                "position startpos moves e2e4 e7e5",
+                "go wtime 300000 btime 290000 movestogo 39",
+                "isready",
+                "quit",
+            ]
+        );
+    }
+
+    // An xboard engine that replies to go with thinking output in SAN, with
+    // move numbers and an annotation, and its move.
+    const XBOARD_ENGINE: &str = r#"
+        while read -r line; do
+            case "$line" in
+                "protover 2") echo 'feature myname="Old 1.0" usermove=1 ping=1 san=1 done=1';;
+                "ping "*) echo "pong ${line#ping }";;
+                go)
+                    echo "2 35 10 1200 1... Nf6 2. Nc3 ?!"
+                    echo "move g8f6";;
+            esac
+        done
+    "#;
+
+    #[tokio::test]
+    async fn uci_gui_plays_xboard_engine() {
+        let buf = SharedBuf::default();
+        let engine = TapTransport::new(fake_engine(XBOARD_ENGINE), "Old", TapLog::new(buf.clone()));
+        let (gui_input, input) = tokio::io::duplex(1024);
+        let (output, gui_output) = tokio::io::duplex(1024);
+        let run = tokio::spawn(UciAdapter::new(engine).run_with(input, output));
+        let mut gui = StreamTransport::new(gui_output, gui_input);
+
+        assert_eq!(
+            exchange(&mut gui, &["uci"], "uciok").await,
+            ["id name Old 1.0", "uciok"]
+        );
+        assert_eq!(
+            exchange(&mut gui, &["isready"], "readyok").await,
+            ["readyok"]
+        );
+        let lines = [
+            "ucinewgame",
+            "position startpos moves e2e4",
+            "go wtime 60000 btime 50000 winc 1000 binc 1000",
+        ];
+        assert_eq!(
+            exchange(&mut gui, &lines, "bestmove").await,
+            [
+                "info depth 2 score cp 35 time 100 nodes 1200 pv g8f6 b1c3",
+                "bestmove g8f6",
+            ]
+        );
+        // The next search only plays the new moves.
+        let lines = ["position startpos moves e2e4 g8f6 d2d4", "go movetime 500"];
+        assert_eq!(
+            exchange(&mut gui, &lines, "bestmove").await.last().unwrap(),
+            "bestmove g8f6"
+        );
+        gui.send_line("quit").await.unwrap();
+        assert_eq!(run.await.unwrap(), Ok(()));
+
+        let log = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let sent = log
+            .lines()
+            .filter_map(|line| line.split_once(">Old: ").map(|(_, line)| line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sent,
+            [
+                "xboard",
+                "protover 2",
+                "accepted myname",
+                "accepted usermove",
+                "accepted ping",
+                "rejected san",
+                "accepted done",
+                "ping 1",
+                "new",
+                "force",
+                "usermove e2e4",
+                "level 0 0:50 1",
+                "time 5000",
+                "otim 6000",
+                "post",
+                "go",
+                "force",
+                "usermove d2d4",
+                "st 1",
+                "post",
+                "go",
+                "force",
+                "quit",
+            ]
+        );
+    }
+
+    #[test]
+    fn thinking_conversions() {
+        let info = match EngCmd::from_str("info depth 9 score mate -3 nodes 10 pv e2e4") {
+            Ok(EngCmd::Info(info)) => info,
+            cmd => panic!("unexpected {:?}", cmd),
+        };
+        let thinking = to_thinking(&info, Duration::from_millis(250)).unwrap();
+        assert_eq!(thinking.to_string(), "9 -100003 25 10 e2e4");
+        assert_eq!(
+            to_info(&thinking, None),
+            "info depth 9 score mate -3 time 250 nodes 10 pv e2e4"
+        );
+        let thinking = Thinking::new(4, 100002, Duration::ZERO, 0, "e2e4 e7e5 Nf3");
+        assert_eq!(
+            to_info(&thinking, None),
+            "info depth 4 score mate 2 time 0 nodes 0 pv e2e4 e7e5"
+        );
+        assert_eq!(take_back(&Pos::new(), 2), Pos::new());
+    }
+}
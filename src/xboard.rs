@@ -0,0 +1,669 @@
+// This module contains the commands of the Chess Engine Communication Protocol
+// (CECP), also known as the xboard protocol, which many GUIs and older engines
+// speak instead of UCI. Like UCI commands, they are lines of text, so the
+// transports work the same for both protocols:
+//
+// let mut transport = EngineProcess::spawn("crafty")?;
+// transport.send_line(&XbGuiCmd::Xboard.to_string()).await?;
+// transport.send_line(&XbGuiCmd::Protover(2).to_string()).await?;
+// let cmd = XbEngCmd::from_str(&transport.recv_line().await?.unwrap())?;
+//
+// Only the commands needed to set up and play a game are typed. Moves are in
+// coordinate notation, as sent by engines with the feature san=0, the default.
+
+use crate::conv::to_number;
+use crate::engmatch::Side;
+use crate::err::UziErr;
+use crate::pm::Pm;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+// Represents a command from the GUI to the engine.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum XbGuiCmd {
+    // xboard: Tells the engine to switch to xboard mode.
+    Xboard,
+
+    // protover <n>: The version of the protocol of the GUI, 2 or higher. The
+    // engine replies with its features.
+    Protover(u32),
+
+    // accepted <feature>: The GUI supports a feature the engine sent.
+    Accepted(String),
+
+    // rejected <feature>: The GUI doesn't support a feature the engine sent.
+    Rejected(String),
+
+    // new: Resets the board to the start position with white to move. The
+    // engine plays black.
+    New,
+
+    // setboard <fen>: Sets up the position, with the feature setboard=1.
+    SetBoard(String),
+
+    // force: The engine plays neither side, and only checks the moves it
+    // gets.
+    Force,
+
+    // go: The engine plays the side to move, and starts thinking.
+    Go,
+
+    // playother: The engine plays the side not to move, and ponders if
+    // pondering is on.
+    PlayOther,
+
+    // usermove <move>: A move of the opponent, with the feature usermove=1.
+    UserMove(Pm),
+
+    // <move>: A move of the opponent, without the feature usermove=1.
+    Move(Pm),
+
+    // ?: The engine moves now.
+    MoveNow,
+
+    // undo: Takes back the last move, in force mode.
+    Undo,
+
+    // remove: Takes back the last two moves, and the engine keeps playing the
+    // same side.
+    Remove,
+
+    // level <mps> <base> <inc>: A time control of mps moves in base time, or
+    // the whole game if mps is 0, with an increment of inc per move. The base
+    // is sent in minutes, or minutes:seconds, and the increment in seconds.
+    Level {
+        moves: u32,
+        base: Duration,
+        inc: Duration,
+    },
+
+    // st <secs>: Exactly secs seconds per move.
+    St(Duration),
+
+    // sd <depth>: Search depth plies only.
+    Sd(u16),
+
+    // time <cs>: The clock of the engine, in centiseconds.
+    Time(Duration),
+
+    // otim <cs>: The clock of the opponent, in centiseconds.
+    Otim(Duration),
+
+    // ping <n>: The engine replies with pong n once it has handled all the
+    // commands before the ping.
+    Ping(u32),
+
+    // result <result> {<comment>}: The game ended.
+    Result(XbResult, String),
+
+    // post: The engine sends thinking output.
+    Post,
+
+    // nopost: The engine doesn't send thinking output.
+    NoPost,
+
+    // memory <mb>: The size of the hash tables, with the feature memory=1.
+    Memory(u64),
+
+    // cores <n>: The number of threads, with the feature smp=1.
+    Cores(u32),
+
+    // hard: Pondering is on.
+    Hard,
+
+    // easy: Pondering is off.
+    Easy,
+
+    // quit: Quit the program as soon as possible.
+    Quit,
+}
+
+impl FromStr for XbGuiCmd {
+    type Err = UziErr;
+
+    fn from_str(cmd: &str) -> Result<XbGuiCmd, Self::Err> {
+        let words = cmd.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] => Err(UziErr::MissingCmd),
+            ["xboard"] => Ok(XbGuiCmd::Xboard),
+            ["protover", version] => Ok(XbGuiCmd::Protover(to_number(version)?)),
+            ["accepted", feature] => Ok(XbGuiCmd::Accepted(feature.to_string())),
+            ["rejected", feature] => Ok(XbGuiCmd::Rejected(feature.to_string())),
+            ["new"] => Ok(XbGuiCmd::New),
+            ["setboard", ..] if words.len() > 1 => Ok(XbGuiCmd::SetBoard(words[1..].join(" "))),
+            ["force"] => Ok(XbGuiCmd::Force),
+            ["go"] => Ok(XbGuiCmd::Go),
+            ["playother"] => Ok(XbGuiCmd::PlayOther),
+            ["usermove", pm] => Ok(XbGuiCmd::UserMove(Pm::from_str(pm)?)),
+            ["?"] => Ok(XbGuiCmd::MoveNow),
+            ["undo"] => Ok(XbGuiCmd::Undo),
+            ["remove"] => Ok(XbGuiCmd::Remove),
+            ["level", moves, base, inc] => Ok(XbGuiCmd::Level {
+                moves: to_number(moves)?,
+                base: to_base(base)?,
+                inc: to_secs(inc)?,
+            }),
+            ["st", secs] => Ok(XbGuiCmd::St(to_secs(secs)?)),
+            ["sd", depth] => Ok(XbGuiCmd::Sd(to_number(depth)?)),
+            ["time", cs] => Ok(XbGuiCmd::Time(to_centis(cs)?)),
+            ["otim", cs] => Ok(XbGuiCmd::Otim(to_centis(cs)?)),
+            ["ping", n] => Ok(XbGuiCmd::Ping(to_number(n)?)),
+            ["result", ..] => {
+                let (result, comment) = to_result(cmd.trim_start()["result".len()..].trim())?;
+                Ok(XbGuiCmd::Result(result, comment))
+            }
+            ["post"] => Ok(XbGuiCmd::Post),
+            ["nopost"] => Ok(XbGuiCmd::NoPost),
+            ["memory", mb] => Ok(XbGuiCmd::Memory(to_number(mb)?)),
+            ["cores", n] => Ok(XbGuiCmd::Cores(to_number(n)?)),
+            ["hard"] => Ok(XbGuiCmd::Hard),
+            ["easy"] => Ok(XbGuiCmd::Easy),
+            ["quit"] => Ok(XbGuiCmd::Quit),
+            [pm] => Pm::from_str(pm)
+                .map(XbGuiCmd::Move)
+                .map_err(|_| UziErr::What),
+            _ => Err(UziErr::What),
+        }
+    }
+}
+
+impl Display for XbGuiCmd {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            XbGuiCmd::Xboard => formatter.write_str("xboard"),
+            XbGuiCmd::Protover(version) => write!(formatter, "protover {}", version),
+            XbGuiCmd::Accepted(feature) => write!(formatter, "accepted {}", feature),
+            XbGuiCmd::Rejected(feature) => write!(formatter, "rejected {}", feature),
+            XbGuiCmd::New => formatter.write_str("new"),
+            XbGuiCmd::SetBoard(fen) => write!(formatter, "setboard {}", fen),
+            XbGuiCmd::Force => formatter.write_str("force"),
+            XbGuiCmd::Go => formatter.write_str("go"),
+            XbGuiCmd::PlayOther => formatter.write_str("playother"),
+            XbGuiCmd::UserMove(pm) => write!(formatter, "usermove {}", pm),
+            XbGuiCmd::Move(pm) => pm.fmt(formatter),
+            XbGuiCmd::MoveNow => formatter.write_str("?"),
+            XbGuiCmd::Undo => formatter.write_str("undo"),
+            XbGuiCmd::Remove => formatter.write_str("remove"),
+            XbGuiCmd::Level { moves, base, inc } => {
+                let secs = base.as_secs();
+                match secs % 60 {
+                    0 => write!(formatter, "level {} {}", moves, secs / 60)?,
+                    rem => write!(formatter, "level {} {}:{:02}", moves, secs / 60, rem)?,
+                }
+                write!(formatter, " {}", inc.as_secs_f64())
+            }
+            XbGuiCmd::St(secs) => write!(formatter, "st {}", secs.as_secs()),
+            XbGuiCmd::Sd(depth) => write!(formatter, "sd {}", depth),
+            XbGuiCmd::Time(time) => write!(formatter, "time {}", time.as_millis() / 10),
+            XbGuiCmd::Otim(time) => write!(formatter, "otim {}", time.as_millis() / 10),
+            XbGuiCmd::Ping(n) => write!(formatter, "ping {}", n),
+            XbGuiCmd::Result(result, comment) => {
+                write!(formatter, "result {} {{{}}}", result, comment)
+            }
+            XbGuiCmd::Post => formatter.write_str("post"),
+            XbGuiCmd::NoPost => formatter.write_str("nopost"),
+            XbGuiCmd::Memory(mb) => write!(formatter, "memory {}", mb),
+            XbGuiCmd::Cores(n) => write!(formatter, "cores {}", n),
+            XbGuiCmd::Hard => formatter.write_str("hard"),
+            XbGuiCmd::Easy => formatter.write_str("easy"),
+            XbGuiCmd::Quit => formatter.write_str("quit"),
+        }
+    }
+}
+
+// Represents a command from the engine to the GUI.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum XbEngCmd {
+    // feature <name>=<value> ...: The features of the engine, as a reply to
+    // protover, in the order they were sent. The GUI replies to each one with
+    // accepted or rejected.
+    Feature(Vec<(String, FeatureValue)>),
+
+    // move <move>: The move of the engine.
+    Move(Pm),
+
+    // pong <n>: The reply to ping n.
+    Pong(u32),
+
+    // <result> {<comment>}: The engine claims the game ended.
+    Result(XbResult, String),
+
+    // resign: The engine resigns.
+    Resign,
+
+    // offer draw: The engine offers a draw, or accepts the draw offer of its
+    // opponent.
+    OfferDraw,
+
+    // Illegal move [(<reason>)]: <move>: The engine doesn't accept a move.
+    IllegalMove { pm: String, reason: Option<String> },
+
+    // Error (<kind>): <command>: The engine doesn't accept a command.
+    Error { kind: String, cmd: String },
+
+    // <ply> <score> <time> <nodes> <pv>: Thinking output, with post.
+    Thinking(Thinking),
+}
+
+impl FromStr for XbEngCmd {
+    type Err = UziErr;
+
+    fn from_str(cmd: &str) -> Result<XbEngCmd, Self::Err> {
+        let cmd = cmd.trim();
+        let words = cmd.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] => Err(UziErr::MissingCmd),
+            ["feature", ..] => Ok(XbEngCmd::Feature(to_features(&cmd["feature".len()..])?)),
+            ["move", pm] => Ok(XbEngCmd::Move(Pm::from_str(pm)?)),
+            ["pong", n] => Ok(XbEngCmd::Pong(to_number(n)?)),
+            ["resign"] => Ok(XbEngCmd::Resign),
+            ["offer", "draw"] => Ok(XbEngCmd::OfferDraw),
+            ["Illegal", "move" | "move:", ..] => {
+                let (reason, pm) = to_complaint(&cmd["Illegal move".len()..])?;
+                Ok(XbEngCmd::IllegalMove { pm, reason })
+            }
+            ["Error", ..] => match to_complaint(&cmd["Error".len()..])? {
+                (Some(kind), cmd) => Ok(XbEngCmd::Error { kind, cmd }),
+                (None, _) => Err(UziErr::XboardErr),
+            },
+            [word, ..] if XbResult::from_str(word).is_ok() => {
+                let (result, comment) = to_result(cmd)?;
+                Ok(XbEngCmd::Result(result, comment))
+            }
+            [word, ..] if word.starts_with(|c: char| c.is_ascii_digit()) => {
+                Ok(XbEngCmd::Thinking(Thinking::from_str(cmd)?))
+            }
+            _ => Err(UziErr::What),
+        }
+    }
+}
+
+impl Display for XbEngCmd {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            XbEngCmd::Feature(features) => {
+                formatter.write_str("feature")?;
+                for (name, value) in features {
+                    write!(formatter, " {}={}", name, value)?;
+                }
+                Ok(())
+            }
+            XbEngCmd::Move(pm) => write!(formatter, "move {}", pm),
+            XbEngCmd::Pong(n) => write!(formatter, "pong {}", n),
+            XbEngCmd::Result(result, comment) => write!(formatter, "{} {{{}}}", result, comment),
+            XbEngCmd::Resign => formatter.write_str("resign"),
+            XbEngCmd::OfferDraw => formatter.write_str("offer draw"),
+            XbEngCmd::IllegalMove { pm, reason: None } => {
+                write!(formatter, "Illegal move: {}", pm)
+            }
+            XbEngCmd::IllegalMove {
+                pm,
+                reason: Some(reason),
+            } => write!(formatter, "Illegal move ({}): {}", reason, pm),
+            XbEngCmd::Error { kind, cmd } => write!(formatter, "Error ({}): {}", kind, cmd),
+            XbEngCmd::Thinking(thinking) => thinking.fmt(formatter),
+        }
+    }
+}
+
+// The value of a feature, either a number, e.g. ping=1, or a quoted string,
+// e.g. myname="Crafty 25.2".
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum FeatureValue {
+    Int(i64),
+    Str(String),
+}
+
+impl Display for FeatureValue {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FeatureValue::Int(value) => write!(formatter, "{}", value),
+            FeatureValue::Str(value) => write!(formatter, "\"{}\"", value),
+        }
+    }
+}
+
+// The result of a game, as in PGN.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum XbResult {
+    Win(Side),
+    Draw,
+    // *: The game ended without a result, e.g. it was aborted.
+    Unknown,
+}
+
+impl FromStr for XbResult {
+    type Err = UziErr;
+
+    fn from_str(result: &str) -> Result<XbResult, Self::Err> {
+        match result {
+            "1-0" => Ok(XbResult::Win(Side::White)),
+            "0-1" => Ok(XbResult::Win(Side::Black)),
+            "1/2-1/2" => Ok(XbResult::Draw),
+            "*" => Ok(XbResult::Unknown),
+            _ => Err(UziErr::XboardErr),
+        }
+    }
+}
+
+impl Display for XbResult {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            XbResult::Win(Side::White) => "1-0",
+            XbResult::Win(Side::Black) => "0-1",
+            XbResult::Draw => "1/2-1/2",
+            XbResult::Unknown => "*",
+        })
+    }
+}
+
+// The thinking output of an engine, i.e.
+//
+// <ply> <score> <time> <nodes> <pv>
+//
+// The pv is kept as sent, since engines usually send it in SAN.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Thinking {
+    depth: u16,
+    // The score in centipawns, from the point of view of the engine.
+    score: i32,
+    time: Duration,
+    nodes: u64,
+    pv: String,
+}
+
+impl Thinking {
+    pub fn new(depth: u16, score: i32, time: Duration, nodes: u64, pv: &str) -> Self {
+        Self {
+            depth,
+            score,
+            time,
+            nodes,
+            pv: pv.to_string(),
+        }
+    }
+
+    pub fn depth(&self) -> u16 {
+        self.depth
+    }
+
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    pub fn pv(&self) -> &str {
+        &self.pv
+    }
+}
+
+impl FromStr for Thinking {
+    type Err = UziErr;
+
+    fn from_str(line: &str) -> Result<Thinking, Self::Err> {
+        let mut words = line.split_whitespace();
+        let mut next = || words.next().ok_or(UziErr::XboardErr);
+        // Some engines mark the depth of a fail high or low, e.g. 12++.
+        let depth = to_number(next()?.trim_end_matches(['.', '+', '-', '&']))?;
+        let score = to_number(next()?)?;
+        let time = to_centis(next()?)?;
+        let nodes = to_number(next()?)?;
+        let pv = words.collect::<Vec<_>>().join(" ");
+        Ok(Thinking {
+            depth,
+            score,
+            time,
+            nodes,
+            pv,
+        })
+    }
+}
+
+impl Display for Thinking {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{} {} {} {} {}",
+            self.depth,
+            self.score,
+            self.time.as_millis() / 10,
+            self.nodes,
+            self.pv
+        )
+    }
+}
+
+fn to_centis(word: &str) -> Result<Duration, UziErr> {
+    to_number::<u64>(word).map(|cs| Duration::from_millis(cs * 10))
+}
+
+fn to_secs(word: &str) -> Result<Duration, UziErr> {
+    match word.parse::<f64>() {
+        Ok(secs) if secs >= 0.0 && secs.is_finite() => Ok(Duration::from_secs_f64(secs)),
+        _ => Err(UziErr::BadNumber(word.into())),
+    }
+}
+
+// Parses the base time of level, in minutes or minutes:seconds.
+fn to_base(word: &str) -> Result<Duration, UziErr> {
+    let (mins, secs) = word.split_once(':').unwrap_or((word, "0"));
+    let mins = to_number::<u64>(mins)?;
+    let secs = to_number::<u64>(secs)?;
+    Ok(Duration::from_secs(mins * 60 + secs))
+}
+
+// Parses a result with an optional comment in braces, e.g. 1-0 {White mates}.
+fn to_result(text: &str) -> Result<(XbResult, String), UziErr> {
+    let (result, comment) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let comment = comment.trim();
+    let comment = match comment.strip_prefix('{') {
+        Some(comment) => comment.strip_suffix('}').ok_or(UziErr::XboardErr)?,
+        None if comment.is_empty() => comment,
+        None => return Err(UziErr::XboardErr),
+    };
+    Ok((XbResult::from_str(result)?, comment.to_string()))
+}
+
+// Parses the rest of an Error or an Illegal move line, i.e. an optional text
+// in parentheses, a colon, and the command or move.
+fn to_complaint(text: &str) -> Result<(Option<String>, String), UziErr> {
+    let text = text.trim_start();
+    let (reason, rest) = match text.strip_prefix('(') {
+        Some(text) => {
+            let (reason, rest) = text.split_once(')').ok_or(UziErr::XboardErr)?;
+            (Some(reason.to_string()), rest)
+        }
+        None => (None, text),
+    };
+    let rest = rest
+        .trim_start()
+        .strip_prefix(':')
+        .ok_or(UziErr::XboardErr)?;
+    Ok((reason, rest.trim().to_string()))
+}
+
+// Parses the name=value pairs of a feature command. String values are quoted
+// and may contain spaces.
+fn to_features(mut text: &str) -> Result<Vec<(String, FeatureValue)>, UziErr> {
+    let mut features = Vec::new();
+    loop {
+        text = text.trim_start();
+        if text.is_empty() {
+            return Ok(features);
+        }
+        let (name, rest) = text.split_once('=').ok_or(UziErr::XboardErr)?;
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(UziErr::XboardErr);
+        }
+        let value = match rest.strip_prefix('"') {
+            Some(rest) => {
+                let (value, rest) = rest.split_once('"').ok_or(UziErr::XboardErr)?;
+                text = rest;
+                FeatureValue::Str(value.to_string())
+            }
+            None => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                text = &rest[end..];
+                FeatureValue::Int(to_number(&rest[..end])?)
+            }
+        };
+        features.push((name.to_string(), value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::fake_engine;
+
+    #[test]
+    fn xboard_gui_cmds() {
+        let cmds = [
+            "xboard",
+            "protover 2",
+            "accepted usermove",
+            "new",
+            "setboard 8/5k2/8/8/8/8/8/4K3 w - - 0 1",
+            "usermove e7e8q",
+            "e2e4",
+            "?",
+            "level 40 5 0",
+            "level 0 2:30 1.5",
+            "st 10",
+            "memory 256",
+            "undo",
+            "time 30000",
+            "otim 29950",
+            "ping 7",
+            "result 1/2-1/2 {Draw by repetition}",
+            "quit",
+        ];
+        for cmd in cmds {
+            assert_eq!(XbGuiCmd::from_str(cmd).unwrap().to_string(), cmd);
+        }
+        assert_eq!(
+            XbGuiCmd::from_str("level 0 2:30 1.5"),
+            Ok(XbGuiCmd::Level {
+                moves: 0,
+                base: Duration::from_secs(150),
+                inc: Duration::from_millis(1500),
+            })
+        );
+        assert_eq!(
+            XbGuiCmd::from_str("time 30000"),
+            Ok(XbGuiCmd::Time(Duration::from_secs(300)))
+        );
+        assert_eq!(XbGuiCmd::from_str("usermove"), Err(UziErr::What));
+        assert_eq!(XbGuiCmd::from_str("e2"), Err(UziErr::What));
+    }
+
+    #[test]
+    fn xboard_eng_cmds() {
+        assert_eq!(
+            XbEngCmd::from_str("feature ping=1 myname=\"Crafty 25.2\" setboard=1 done=1"),
+            Ok(XbEngCmd::Feature(vec![
+                ("ping".to_string(), FeatureValue::Int(1)),
+                (
+                    "myname".to_string(),
+                    FeatureValue::Str("Crafty 25.2".to_string())
+                ),
+                ("setboard".to_string(), FeatureValue::Int(1)),
+                ("done".to_string(), FeatureValue::Int(1)),
+            ]))
+        );
+        assert_eq!(
+            XbEngCmd::from_str("Illegal move (in check): e2e4"),
+            Ok(XbEngCmd::IllegalMove {
+                pm: "e2e4".to_string(),
+                reason: Some("in check".to_string()),
+            })
+        );
+        assert_eq!(
+            XbEngCmd::from_str("Error (unknown command): foo bar"),
+            Ok(XbEngCmd::Error {
+                kind: "unknown command".to_string(),
+                cmd: "foo bar".to_string(),
+            })
+        );
+        assert_eq!(
+            XbEngCmd::from_str("0-1 {White resigns}"),
+            Ok(XbEngCmd::Result(
+                XbResult::Win(Side::Black),
+                "White resigns".to_string()
+            ))
+        );
+        let Ok(XbEngCmd::Thinking(thinking)) = XbEngCmd::from_str("9 -156 1084 48000 Nf3 Nc6")
+        else {
+            panic!("not thinking output");
+        };
+        assert_eq!(thinking.depth(), 9);
+        assert_eq!(thinking.score(), -156);
+        assert_eq!(thinking.time(), Duration::from_millis(10840));
+        assert_eq!(thinking.pv(), "Nf3 Nc6");
+        for cmd in [
+            "move e7e5",
+            "pong 7",
+            "resign",
+            "offer draw",
+            "Illegal move: e2e5",
+            "* {Aborted}",
+        ] {
+            assert_eq!(XbEngCmd::from_str(cmd).unwrap().to_string(), cmd);
+        }
+        assert_eq!(
+            XbEngCmd::from_str("feature myname=\"Crafty"),
+            Err(UziErr::XboardErr)
+        );
+        assert_eq!(XbEngCmd::from_str("Error foo"), Err(UziErr::XboardErr));
+    }
+
+    // An engine process is a transport for xboard engines too.
+    #[tokio::test]
+    async fn xboard_over_transport() {
+        let mut transport = fake_engine(
+            r#"
+            while read -r line; do
+                case "$line" in
+                    "protover 2") echo 'feature myname="Fake" done=1';;
+                    "ping "*) echo "pong ${line#ping }";;
+                    "usermove e2e4") echo "move e7e5";;
+                esac
+            done
+            "#,
+        );
+        let cmds = [
+            XbGuiCmd::Xboard,
+            XbGuiCmd::Protover(2),
+            XbGuiCmd::New,
+            XbGuiCmd::UserMove(Pm::from_str("e2e4").unwrap()),
+            XbGuiCmd::Ping(3),
+        ];
+        for cmd in cmds {
+            transport.send_line(&cmd.to_string()).await.unwrap();
+        }
+        let mut replies = Vec::new();
+        for _ in 0..3 {
+            let line = transport.recv_line().await.unwrap().unwrap();
+            replies.push(XbEngCmd::from_str(&line).unwrap());
+        }
+        assert_eq!(
+            replies,
+            [
+                XbEngCmd::Feature(vec![
+                    ("myname".to_string(), FeatureValue::Str("Fake".to_string())),
+                    ("done".to_string(), FeatureValue::Int(1)),
+                ]),
+                XbEngCmd::Move(Pm::from_str("e7e5").unwrap()),
+                XbEngCmd::Pong(3),
+            ]
+        );
+    }
+}
@@ -0,0 +1,100 @@
+// Drives engines through the client API from outside of the crate, with
+// nothing but the public API, against scripted engines.
+
+use std::str::FromStr;
+use std::time::Duration;
+use uzi::{
+    AnalysisSession, CheckVerdict, ConformanceSuite, Engine, EngineMatch, EnginePool,
+    EngineProcess, Format, Go, Launcher, MockEngine, MockGui, MockSearch, Pm, Pos, RespawnPolicy,
+    SearchHandle, Spawner, TcpEngineServer, TcpTransport, TimeControl, Tournament, Watchdog,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn pm(pm: &str) -> Pm {
+    Pm::from_str(pm).unwrap()
+}
+
+fn mock() -> MockEngine {
+    let mut mock = MockEngine::new("Mock");
+    let mut search = MockSearch::new(pm("e2e4"));
+    search.add_info("depth 1 score cp 20 pv e2e4");
+    mock.add_search(search);
+    mock
+}
+
+#[tokio::test]
+async fn client_drives_mock_engine() {
+    let mut eng = Engine::new(mock());
+    eng.uci(TIMEOUT).await.unwrap();
+    assert_eq!(eng.name(), Some("Mock"));
+    eng.position(&Pos::new()).await.unwrap();
+    let mut search: SearchHandle<'_> = eng.go(&Go::new()).await.unwrap();
+    let info = search.next_info().await.unwrap().unwrap();
+    assert_eq!(info.pv(), Some(&[pm("e2e4")][..]));
+    assert_eq!(search.wait().await, Ok((pm("e2e4"), None)));
+
+    let mut session = AnalysisSession::new(&mut eng);
+    session.set_position(Pos::new());
+    assert_eq!(session.position(), &Pos::new());
+}
+
+#[tokio::test]
+async fn pool_and_tcp_server_spawn_mocks() {
+    let pool = EnginePool::new(Spawner::new(|| Ok(mock())), 2);
+    assert_eq!(
+        pool.best_move(&Pos::new(), &Go::new()).await,
+        Ok((pm("e2e4"), None))
+    );
+
+    let mut server = TcpEngineServer::bind("127.0.0.1:0", Spawner::new(|| Ok(mock())))
+        .await
+        .unwrap();
+    server.set_max_sessions(4);
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.serve());
+    let mut eng = Engine::new(TcpTransport::connect(addr).await.unwrap());
+    eng.uci(TIMEOUT).await.unwrap();
+    assert_eq!(eng.best_move(&Pos::new(), &Go::new()).await, Ok(pm("e2e4")));
+}
+
+#[tokio::test]
+async fn client_types_are_public() {
+    let mut watchdog = Watchdog::new();
+    watchdog.set_ready_timeout(TIMEOUT);
+    let mut policy = RespawnPolicy::new();
+    policy.set_max_restarts(3);
+    let mut suite = ConformanceSuite::new();
+    suite.set_reply_timeout(TIMEOUT);
+    let game_match = EngineMatch::new(TimeControl::new(TIMEOUT, Duration::ZERO));
+    let tournament = Tournament::new(Format::RoundRobin, game_match);
+    let launcher = Launcher::new("stockfish");
+    let verdict: Option<&CheckVerdict> = None;
+    assert!(format!("{:?}", (tournament, launcher, verdict)).contains("stockfish"));
+    assert!(EngineProcess::spawn("/nonexistent/engine").is_err());
+    assert!(MockGui::spawn("/nonexistent/engine").is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_server_spawns_mocks() {
+    use uzi::{UnixEngineServer, UnixTransport};
+
+    let path = std::env::temp_dir().join(format!("uzi-client-api-{}.sock", std::process::id()));
+    let server = UnixEngineServer::bind(&path, Spawner::new(|| Ok(mock()))).unwrap();
+    assert_eq!(server.path(), path);
+    tokio::spawn(server.serve());
+    let mut eng = Engine::new(UnixTransport::connect(&path).await.unwrap());
+    assert_eq!(eng.best_move(&Pos::new(), &Go::new()).await, Ok(pm("e2e4")));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[cfg(feature = "sync-client")]
+#[test]
+fn blocking_client_is_public() {
+    use uzi::{BlockingEngine, BlockingSearchHandle};
+
+    assert!(BlockingEngine::spawn("/nonexistent/engine").is_err());
+    let search: Option<BlockingSearchHandle<'_>> = None;
+    assert!(search.is_none());
+}
@@ -0,0 +1,123 @@
+// Implements UciEngine outside of the crate, with nothing but the public API,
+// and drives it through the runner like a GUI would.
+
+use std::io::{self, Cursor, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uzi::{
+    Bench, CompilerInfo, EngineMeta, EvalReport, GamePos, Go, Info, InfoSender, OptValue,
+    OptionRegistry, Piece, Pm, Pos, Register, Runner, Sq, StopFlag, UciEngine, UciOut,
+};
+
+// Plays the first of the search moves, or a queen promotion on a8 otherwise.
+#[derive(Default)]
+struct FirstMove {
+    calls: Arc<Mutex<Vec<String>>>,
+}
+
+impl FirstMove {
+    fn record(&self, call: String) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+impl UciEngine for FirstMove {
+    fn set_option(&mut self, name: &str, value: OptValue) {
+        self.record(format!("{} {:?}", name, value));
+    }
+
+    fn go(
+        &mut self,
+        pos: &GamePos,
+        go: &Go,
+        _stop: &StopFlag,
+        info: &InfoSender,
+    ) -> (Pm, Option<Pm>) {
+        self.record(format!("{} {:?}", pos.pos(), go.depth()));
+        info.send(Info::from_string("searching"));
+        let promo = Pm::Promo {
+            from: Sq::from((6, 0)),
+            to: Sq::from((7, 0)),
+            promo: Piece::Queen,
+        };
+        let best = go
+            .search_moves()
+            .and_then(|moves| moves.first().copied())
+            .unwrap_or(promo);
+        (best, None)
+    }
+
+    fn check_registration(&mut self) -> Option<bool> {
+        Some(false)
+    }
+
+    fn register(&mut self, register: &Register) -> bool {
+        matches!(register, Register::Now { .. })
+    }
+
+    fn on_bench(&mut self, _depth: Option<u16>) -> Option<Bench> {
+        Some(Bench {
+            nodes: 10,
+            time: Duration::from_millis(1),
+        })
+    }
+
+    fn on_perft(&mut self, _pos: &Pos, depth: u16) -> Option<Vec<(Pm, u64)>> {
+        Some(vec![(Pm::from_str("e2e4").ok()?, u64::from(depth))])
+    }
+
+    fn on_eval(&mut self, _pos: &Pos) -> Option<EvalReport> {
+        None
+    }
+
+    fn on_compiler(&mut self) -> Option<CompilerInfo> {
+        None
+    }
+}
+
+// A writer that can be read after the runner took ownership of it.
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn external_engine_runs() {
+    let input = "uci\nsetoption name Skill value 3\nregister name Omar code 1\n\
+                 position startpos moves e2e4\ngo depth 1 searchmoves e7e5\ngo depth 1\nquit\n";
+    let engine = FirstMove::default();
+    let calls = engine.calls.clone();
+    let buf = SharedBuf::default();
+    let mut options = OptionRegistry::new();
+    options.add_spin("Skill", 20, 0, 20);
+    let mut runner = Runner::new(EngineMeta::new("FirstMove", "1.0", &["uzi"]), engine);
+    runner.set_options(options);
+    let result = runner.run_with(Cursor::new(input), UciOut::new(buf.clone()));
+    assert_eq!(result, Ok(()));
+
+    let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(
+        output,
+        "id name FirstMove 1.0\nid author uzi\n\
+         option name Skill type spin default 20 min 0 max 20\nuciok\n\
+         registration checking\nregistration error\nregistration checking\nregistration ok\n\
+         info string searching\nbestmove e7e5\ninfo string searching\nbestmove a7a8q\n"
+    );
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec![
+            "Skill Spin(3)",
+            "position startpos moves e2e4 Some(1)",
+            "position startpos moves e2e4 Some(1)",
+        ]
+    );
+}